@@ -0,0 +1,89 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// The error of a participant
+pub type BoxError = Box<dyn error::Error + Send + Sync>;
+
+/// An error of the two-phase commit
+#[derive(Debug)]
+pub enum Error {
+    /// A participant failed to prepare, and all of them were rolled back
+    Prepare {
+        /// The id of the transaction
+        xid: String,
+        /// The error of the participant
+        source: BoxError,
+    },
+    /// The decision to commit couldn't be recorded, and all the participants
+    /// were rolled back
+    Log(io::Error),
+    /// The decision to commit was recorded, but a participant failed to
+    /// commit. The transaction is committed by `Coordinator::recover`
+    /// later.
+    Commit {
+        /// The id of the transaction
+        xid: String,
+        /// The error of the participant
+        source: BoxError,
+    },
+    /// A participant failed to roll back a transaction left prepared, during
+    /// `Coordinator::recover`
+    Rollback {
+        /// The id of the transaction
+        xid: String,
+        /// The error of the participant
+        source: BoxError,
+    },
+    /// A participant failed to list its prepared transactions, during
+    /// `Coordinator::recover`
+    Recover(BoxError),
+}
+
+impl Error {
+    /// The id of the transaction the error is about, if any
+    pub fn xid(&self) -> Option<&str> {
+        match *self {
+            Error::Prepare { ref xid, .. } | Error::Commit { ref xid, .. } | Error::Rollback { ref xid, .. } => {
+                Some(xid)
+            }
+            Error::Log(_) | Error::Recover(_) => None,
+        }
+    }
+
+    /// Whether the transaction is decided to commit, so that its effects
+    /// become visible sooner or later despite the error
+    pub fn is_committed(&self) -> bool {
+        matches!(*self, Error::Commit { .. })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Prepare { ref xid, ref source } => write!(f, "failed to prepare {}: {}", xid, source),
+            Error::Log(ref e) => write!(f, "failed to record the decision: {}", e),
+            Error::Commit { ref xid, ref source } => write!(f, "failed to commit prepared {}: {}", xid, source),
+            Error::Rollback { ref xid, ref source } => write!(f, "failed to roll back prepared {}: {}", xid, source),
+            Error::Recover(ref e) => write!(f, "failed to list the prepared transactions: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Prepare { ref source, .. } | Error::Commit { ref source, .. } | Error::Rollback { ref source, .. } => {
+                Some(&**source)
+            }
+            Error::Log(ref e) => Some(e),
+            Error::Recover(ref e) => Some(&**e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Log(e)
+    }
+}
@@ -0,0 +1,364 @@
+//! Two-phase commit of a transaction across several contexts
+//!
+//! A transaction runs on a list of contexts which are `Participant`s, e.g.
+//! two databases, built by `hlist!` and reached by `lift`. When it succeeds,
+//! the `Coordinator` prepares all the participants, records its decision to
+//! commit in a `DecisionLog`, and commits them. When the transaction or any
+//! preparation fails, all of them are rolled back instead. If the process
+//! crashes in between, `Coordinator::recover` commits the prepared
+//! transactions which were decided to commit and rolls back the others.
+//!
+//! # Examples
+//!
+//! ```
+//! #[macro_use]
+//! extern crate transaction;
+//!
+//! use std::collections::BTreeMap;
+//! use std::convert::Infallible;
+//! use transaction::prelude::*;
+//! use transaction_2pc::{Coordinator, MemoryLog, Participant};
+//!
+//! // a store whose pending writes can be prepared
+//! #[derive(Default)]
+//! struct Store {
+//!     data: BTreeMap<&'static str, i32>,
+//!     pending: Vec<(&'static str, i32)>,
+//!     prepared: BTreeMap<String, Vec<(&'static str, i32)>>,
+//! }
+//!
+//! impl Participant for Store {
+//!     type Err = Infallible;
+//!
+//!     fn prepare(&mut self, xid: &str) -> Result<(), Infallible> {
+//!         let pending = std::mem::take(&mut self.pending);
+//!         self.prepared.insert(xid.to_string(), pending);
+//!         Ok(())
+//!     }
+//!
+//!     fn commit(&mut self, xid: &str) -> Result<(), Infallible> {
+//!         let writes = self.prepared.remove(xid).unwrap_or_default();
+//!         self.data.extend(writes);
+//!         Ok(())
+//!     }
+//!
+//!     fn rollback(&mut self, xid: &str) -> Result<(), Infallible> {
+//!         self.pending.clear();
+//!         self.prepared.remove(xid);
+//!         Ok(())
+//!     }
+//!
+//!     fn prepared(&mut self) -> Result<Vec<String>, Infallible> {
+//!         Ok(self.prepared.keys().cloned().collect())
+//!     }
+//! }
+//!
+//! struct Accounts(Store);
+//! struct Audit(Store);
+//! # impl Participant for Accounts {
+//! #     type Err = Infallible;
+//! #     fn prepare(&mut self, xid: &str) -> Result<(), Infallible> { self.0.prepare(xid) }
+//! #     fn commit(&mut self, xid: &str) -> Result<(), Infallible> { self.0.commit(xid) }
+//! #     fn rollback(&mut self, xid: &str) -> Result<(), Infallible> { self.0.rollback(xid) }
+//! #     fn prepared(&mut self) -> Result<Vec<String>, Infallible> { self.0.prepared() }
+//! # }
+//! # impl Participant for Audit {
+//! #     type Err = Infallible;
+//! #     fn prepare(&mut self, xid: &str) -> Result<(), Infallible> { self.0.prepare(xid) }
+//! #     fn commit(&mut self, xid: &str) -> Result<(), Infallible> { self.0.commit(xid) }
+//! #     fn rollback(&mut self, xid: &str) -> Result<(), Infallible> { self.0.rollback(xid) }
+//! #     fn prepared(&mut self) -> Result<Vec<String>, Infallible> { self.0.prepared() }
+//! # }
+//!
+//! # fn main() {
+//! let deposit = with_ctx(|a: &mut Accounts| -> Result<(), transaction_2pc::Error> {
+//!     a.0.pending.push(("alice", 100));
+//!     Ok(())
+//! });
+//! let audit = with_ctx(|a: &mut Audit| -> Result<(), transaction_2pc::Error> {
+//!     a.0.pending.push(("deposits", 1));
+//!     Ok(())
+//! });
+//! let tx = deposit.lift().join(audit.lift());
+//!
+//! let mut participants = hlist![Accounts(Store::default()), Audit(Store::default())];
+//! let mut coordinator = Coordinator::new(MemoryLog::new());
+//! coordinator.run(&mut participants, tx).unwrap();
+//! assert_eq!(participants.head.0.data["alice"], 100);
+//! assert_eq!(participants.tail.head.0.data["deposits"], 1);
+//! # }
+//! ```
+
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use transaction::hooks::{self, Outcome};
+use transaction::Transaction;
+
+mod error;
+mod log;
+mod participant;
+pub mod xa;
+
+pub use crate::error::{BoxError, Error};
+pub use crate::log::{DecisionLog, FileLog, MemoryLog};
+pub use crate::participant::{Decision, Participant, Participants};
+
+/// The coordinator of two-phase commits, recording its decisions in the log
+/// `L`.
+///
+/// The ids of the transactions start with the name of the coordinator, so
+/// that the recovery of a coordinator leaves those of the others alone when
+/// they share participants.
+#[derive(Debug)]
+pub struct Coordinator<L> {
+    log: L,
+    name: String,
+    // distinguishes the runs of the coordinator from those before a restart
+    epoch: u128,
+    count: u64,
+}
+
+impl<L> Coordinator<L>
+where
+    L: DecisionLog,
+{
+    /// A coordinator named `2pc`
+    pub fn new(log: L) -> Self {
+        Coordinator {
+            log,
+            name: "2pc".to_string(),
+            epoch: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0),
+            count: 0,
+        }
+    }
+
+    /// Set the name of the coordinator. It must be unique among the
+    /// coordinators sharing participants and kept across restarts, and must
+    /// not contain a newline.
+    pub fn name(self, name: impl Into<String>) -> Self {
+        Coordinator {
+            name: name.into(),
+            ..self
+        }
+    }
+
+    /// The log of the decisions
+    pub fn log(&self) -> &L {
+        &self.log
+    }
+
+    /// Run the transaction on the participants, and commit all of them by
+    /// the two-phase commit if it succeeds, or roll back all of them
+    /// otherwise.
+    ///
+    /// When the commit of a participant fails after the decision, the others
+    /// still commit and `Error::Commit` is returned. The transaction is
+    /// committed but not finished, and `recover` finishes it.
+    ///
+    /// If the transaction panics, all the participants are rolled back and
+    /// the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::Infallible;
+    /// use std::panic::{self, AssertUnwindSafe};
+    /// use transaction::prelude::*;
+    /// use transaction_2pc::{Coordinator, MemoryLog, Participant};
+    ///
+    /// #[derive(Default)]
+    /// struct Store {
+    ///     pending: Vec<i32>,
+    /// }
+    ///
+    /// impl Participant for Store {
+    ///     type Err = Infallible;
+    ///     fn prepare(&mut self, _: &str) -> Result<(), Infallible> {
+    ///         Ok(())
+    ///     }
+    ///     fn commit(&mut self, _: &str) -> Result<(), Infallible> {
+    ///         Ok(())
+    ///     }
+    ///     fn rollback(&mut self, _: &str) -> Result<(), Infallible> {
+    ///         self.pending.clear();
+    ///         Ok(())
+    ///     }
+    ///     fn prepared(&mut self) -> Result<Vec<String>, Infallible> {
+    ///         Ok(Vec::new())
+    ///     }
+    /// }
+    ///
+    /// let mut participants = (Store::default(), Store::default());
+    /// let mut coordinator = Coordinator::new(MemoryLog::new());
+    /// let boom = with_ctx(|stores: &mut (Store, Store)| -> Result<(), transaction_2pc::Error> {
+    ///     stores.0.pending.push(1);
+    ///     stores.1.pending.push(2);
+    ///     panic!("boom")
+    /// });
+    /// let ret = panic::catch_unwind(AssertUnwindSafe(|| coordinator.run(&mut participants, boom)));
+    /// assert!(ret.is_err());
+    /// assert!(participants.0.pending.is_empty() && participants.1.pending.is_empty());
+    /// ```
+    pub fn run<P, T, E, Tx>(&mut self, participants: &mut P, tx: Tx) -> Result<T, E>
+    where
+        P: Participants,
+        E: From<Error>,
+        Tx: Transaction<Ctx = P, Item = T, Err = E>,
+    {
+        let xid = self.next_xid();
+        let (ret, _) = hooks::instrument("2pc", tx.label(), |&(_, outcome)| outcome, || {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(xid = xid.as_str(), "run");
+            #[cfg(feature = "log")]
+            ::log::debug!("run transaction {:?} as {}", tx.label(), xid);
+            let ret = {
+                let guard = RollbackOnPanic { participants: &mut *participants, xid: &xid };
+                tx.run(guard.participants)
+            };
+            match ret {
+                Ok(t) => match self.commit(participants, &xid) {
+                    Ok(()) => (Ok(t), Outcome::Committed),
+                    Err(e) if e.is_committed() => (Err(E::from(e)), Outcome::Committed),
+                    Err(e) => (Err(E::from(e)), Outcome::RolledBack),
+                },
+                Err(e) => {
+                    // the recovery rolls back the prepared ones
+                    let _ = participants.rollback(&xid);
+                    (Err(e), Outcome::RolledBack)
+                }
+            }
+        });
+        ret
+    }
+
+    /// Finish the transactions of this coordinator which the participants
+    /// have left prepared, e.g. by a crash: commit those decided to commit
+    /// and roll back the others. Run it at startup with the same log and
+    /// name, and after `Error::Commit`.
+    ///
+    /// The decisions are forgotten once all the participants are recovered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::BTreeSet;
+    /// use std::io;
+    /// use transaction::prelude::*;
+    /// use transaction_2pc::{Coordinator, MemoryLog, Participant};
+    ///
+    /// // a participant failing to commit while it is offline
+    /// #[derive(Default)]
+    /// struct Flaky {
+    ///     offline: bool,
+    ///     prepared: BTreeSet<String>,
+    ///     committed: u32,
+    /// }
+    ///
+    /// impl Participant for Flaky {
+    ///     type Err = io::Error;
+    ///
+    ///     fn prepare(&mut self, xid: &str) -> io::Result<()> {
+    ///         self.prepared.insert(xid.to_string());
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn commit(&mut self, xid: &str) -> io::Result<()> {
+    ///         if self.offline {
+    ///             return Err(io::ErrorKind::NotConnected.into());
+    ///         }
+    ///         self.prepared.remove(xid);
+    ///         self.committed += 1;
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn rollback(&mut self, xid: &str) -> io::Result<()> {
+    ///         self.prepared.remove(xid);
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn prepared(&mut self) -> io::Result<Vec<String>> {
+    ///         Ok(self.prepared.iter().cloned().collect())
+    ///     }
+    /// }
+    ///
+    /// let mut participants = (Flaky::default(), Flaky { offline: true, ..Flaky::default() });
+    /// let mut coordinator = Coordinator::new(MemoryLog::new());
+    /// let tx = with_ctx(|_: &mut (Flaky, Flaky)| -> Result<(), transaction_2pc::Error> { Ok(()) });
+    /// let e = coordinator.run(&mut participants, tx).unwrap_err();
+    /// assert!(e.is_committed());
+    ///
+    /// participants.1.offline = false;
+    /// coordinator.recover(&mut participants).unwrap();
+    /// assert_eq!((participants.0.committed, participants.1.committed), (1, 1));
+    /// ```
+    pub fn recover<P>(&mut self, participants: &mut P) -> Result<(), Error>
+    where
+        P: Participants,
+    {
+        let committed = self.log.committed()?;
+        let prefix = format!("{}-", self.name);
+        participants.recover(&|xid| {
+            if !xid.starts_with(&prefix) {
+                None
+            } else if committed.iter().any(|c| c == xid) {
+                Some(Decision::Commit)
+            } else {
+                Some(Decision::Rollback)
+            }
+        })?;
+        for xid in committed {
+            self.log.forget(&xid)?;
+        }
+        Ok(())
+    }
+
+    fn next_xid(&mut self) -> String {
+        self.count += 1;
+        format!("{}-{}-{}", self.name, self.epoch, self.count)
+    }
+
+    // the two phases after the transaction succeeded
+    fn commit<P>(&mut self, participants: &mut P, xid: &str) -> Result<(), Error>
+    where
+        P: Participants,
+    {
+        if let Err(source) = participants.prepare(xid) {
+            let _ = participants.rollback(xid);
+            return Err(Error::Prepare {
+                xid: xid.to_string(),
+                source,
+            });
+        }
+        if let Err(e) = self.log.record(xid) {
+            let _ = participants.rollback(xid);
+            return Err(Error::Log(e));
+        }
+        participants.commit(xid).map_err(|source| Error::Commit {
+            xid: xid.to_string(),
+            source,
+        })?;
+        // a decision left behind is forgotten by the next recovery
+        let _ = self.log.forget(xid);
+        Ok(())
+    }
+}
+
+// rolls back the participants of a panicking transaction
+struct RollbackOnPanic<'a, P: Participants> {
+    participants: &'a mut P,
+    xid: &'a str,
+}
+
+impl<P: Participants> Drop for RollbackOnPanic<'_, P> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            #[cfg(feature = "log")]
+            ::log::warn!("rolling back the participants of the panicking transaction {}", self.xid);
+            let _ = self.participants.rollback(self.xid);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The durable record of the decisions of a `Coordinator`.
+///
+/// Only the decisions to commit are recorded: a prepared transaction without
+/// a decision is rolled back by the recovery. A decision is recorded before
+/// any participant commits, and forgotten after all of them do.
+pub trait DecisionLog {
+    /// Record the decision to commit `xid`. It must survive a crash once
+    /// this returns.
+    fn record(&mut self, xid: &str) -> io::Result<()>;
+
+    /// Forget the decision on `xid`, which all the participants committed
+    fn forget(&mut self, xid: &str) -> io::Result<()>;
+
+    /// The transactions decided to commit but not forgotten yet
+    fn committed(&mut self) -> io::Result<Vec<String>>;
+}
+
+impl<L> DecisionLog for &mut L
+where
+    L: ?Sized + DecisionLog,
+{
+    fn record(&mut self, xid: &str) -> io::Result<()> {
+        (**self).record(xid)
+    }
+
+    fn forget(&mut self, xid: &str) -> io::Result<()> {
+        (**self).forget(xid)
+    }
+
+    fn committed(&mut self) -> io::Result<Vec<String>> {
+        (**self).committed()
+    }
+}
+
+/// A `DecisionLog` in memory, which doesn't survive a crash, for tests
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLog {
+    committed: BTreeSet<String>,
+}
+
+impl MemoryLog {
+    /// An empty log
+    pub fn new() -> Self {
+        MemoryLog::default()
+    }
+}
+
+impl DecisionLog for MemoryLog {
+    fn record(&mut self, xid: &str) -> io::Result<()> {
+        self.committed.insert(xid.to_string());
+        Ok(())
+    }
+
+    fn forget(&mut self, xid: &str) -> io::Result<()> {
+        self.committed.remove(xid);
+        Ok(())
+    }
+
+    fn committed(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.committed.iter().cloned().collect())
+    }
+}
+
+/// A `DecisionLog` appending the decisions to a file, which is synced
+/// before each decision is used.
+///
+/// The file is compacted to the decisions not forgotten when it is opened.
+///
+/// # Examples
+///
+/// ```
+/// use transaction_2pc::{DecisionLog, FileLog};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join("transaction-2pc-doctest.log");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut log = FileLog::open(&path)?;
+/// log.record("2pc-1")?;
+/// log.record("2pc-2")?;
+/// log.forget("2pc-1")?;
+/// drop(log);
+///
+/// // after a crash
+/// let mut log = FileLog::open(&path)?;
+/// assert_eq!(log.committed()?, vec!["2pc-2".to_string()]);
+/// # std::fs::remove_file(&path)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FileLog {
+    file: File,
+    committed: BTreeSet<String>,
+}
+
+impl FileLog {
+    /// Open the log at `path`, creating it if missing
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let log = match fs::read_to_string(path) {
+            Ok(log) => log,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        // a torn last line is a decision which was never used
+        let complete = log.rfind('\n').map_or("", |end| &log[..=end]);
+        let mut committed = BTreeSet::new();
+        for line in complete.lines() {
+            match line.split_once(' ') {
+                Some(("commit", xid)) => {
+                    committed.insert(xid.to_string());
+                }
+                Some(("forget", xid)) => {
+                    committed.remove(xid);
+                }
+                _ => {}
+            }
+        }
+        // compacting also drops the torn line, so that the next decision
+        // isn't appended to it
+        if complete.len() != log.len() || complete.lines().count() != committed.len() {
+            compact(path, &committed)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileLog { file, committed })
+    }
+
+    fn append(&mut self, op: &str, xid: &str) -> io::Result<()> {
+        debug_assert!(!xid.contains('\n'));
+        writeln!(self.file, "{} {}", op, xid)
+    }
+}
+
+// replace the log by the decisions not forgotten. The new log is written
+// aside and renamed over, so that a crash leaves either of them.
+fn compact(path: &Path, committed: &BTreeSet<String>) -> io::Result<()> {
+    let mut tmp = PathBuf::from(path);
+    tmp.set_extension("compact");
+    let mut file = File::create(&tmp)?;
+    for xid in committed {
+        writeln!(file, "commit {}", xid)?;
+    }
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+impl DecisionLog for FileLog {
+    fn record(&mut self, xid: &str) -> io::Result<()> {
+        self.append("commit", xid)?;
+        self.file.sync_data()?;
+        self.committed.insert(xid.to_string());
+        Ok(())
+    }
+
+    fn forget(&mut self, xid: &str) -> io::Result<()> {
+        // a lost forget only makes the recovery look for the transaction
+        // again, so it isn't synced
+        self.append("forget", xid)?;
+        self.committed.remove(xid);
+        Ok(())
+    }
+
+    fn committed(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.committed.iter().cloned().collect())
+    }
+}
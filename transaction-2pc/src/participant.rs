@@ -0,0 +1,156 @@
+use transaction::{HCons, HNil};
+
+use crate::error::{BoxError, Error};
+
+/// A context taking part in a two-phase commit, implemented by the backends
+/// whose transactions can be prepared, e.g. by `PREPARE TRANSACTION` of
+/// PostgreSQL or `XA PREPARE` of MySQL.
+///
+/// The transaction running in the context is identified by the id given to
+/// `prepare`. `commit` and `rollback` must work with the id alone, also from
+/// a new context after a crash, since `Coordinator::recover` finishes the
+/// transactions left prepared.
+pub trait Participant {
+    /// The error of the participant
+    type Err: Into<BoxError>;
+
+    /// Make the effects of the transaction in the context durable without
+    /// committing them, and name the transaction `xid`. Once it succeeds,
+    /// `commit` must be able to succeed even after a crash.
+    fn prepare(&mut self, xid: &str) -> Result<(), Self::Err>;
+
+    /// Commit the prepared transaction `xid`
+    fn commit(&mut self, xid: &str) -> Result<(), Self::Err>;
+
+    /// Roll back the transaction `xid`, whether it is prepared or still
+    /// running in the context
+    fn rollback(&mut self, xid: &str) -> Result<(), Self::Err>;
+
+    /// The ids of the transactions which are prepared but neither committed
+    /// nor rolled back
+    fn prepared(&mut self) -> Result<Vec<String>, Self::Err>;
+}
+
+/// The participants of a two-phase commit: a pair of `Participant`s, or a
+/// context list of them built by `hlist!`.
+pub trait Participants {
+    /// Prepare all the participants in order, stopping at the first failure
+    fn prepare(&mut self, xid: &str) -> Result<(), BoxError>;
+
+    /// Commit all the participants, and return the first failure
+    fn commit(&mut self, xid: &str) -> Result<(), BoxError>;
+
+    /// Roll back all the participants, and return the first failure
+    fn rollback(&mut self, xid: &str) -> Result<(), BoxError>;
+
+    /// Commit or roll back the transactions the participants have prepared
+    /// as `decide` says, skipping those it returns `None` for
+    fn recover(&mut self, decide: &dyn Fn(&str) -> Option<Decision>) -> Result<(), Error>;
+}
+
+/// The decision on a prepared transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Decision {
+    /// Commit the transaction
+    Commit,
+    /// Roll back the transaction
+    Rollback,
+}
+
+// finish the transactions the participant has prepared
+fn recover<P>(participant: &mut P, decide: &dyn Fn(&str) -> Option<Decision>) -> Result<(), Error>
+where
+    P: Participant,
+{
+    let prepared = participant.prepared().map_err(|e| Error::Recover(e.into()))?;
+    for xid in prepared {
+        match decide(&xid) {
+            Some(Decision::Commit) => participant.commit(&xid).map_err(|e| Error::Commit {
+                source: e.into(),
+                xid,
+            })?,
+            Some(Decision::Rollback) => participant.rollback(&xid).map_err(|e| Error::Rollback {
+                source: e.into(),
+                xid,
+            })?,
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+impl Participants for HNil {
+    fn prepare(&mut self, _xid: &str) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn commit(&mut self, _xid: &str) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn rollback(&mut self, _xid: &str) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn recover(&mut self, _decide: &dyn Fn(&str) -> Option<Decision>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<H, T> Participants for HCons<H, T>
+where
+    H: Participant,
+    T: Participants,
+{
+    fn prepare(&mut self, xid: &str) -> Result<(), BoxError> {
+        self.head.prepare(xid).map_err(Into::into)?;
+        self.tail.prepare(xid)
+    }
+
+    fn commit(&mut self, xid: &str) -> Result<(), BoxError> {
+        // the others commit even if one fails, so that fewer are left for
+        // the recovery
+        let head = self.head.commit(xid).map_err(Into::into);
+        let tail = self.tail.commit(xid);
+        head.and(tail)
+    }
+
+    fn rollback(&mut self, xid: &str) -> Result<(), BoxError> {
+        let head = self.head.rollback(xid).map_err(Into::into);
+        let tail = self.tail.rollback(xid);
+        head.and(tail)
+    }
+
+    fn recover(&mut self, decide: &dyn Fn(&str) -> Option<Decision>) -> Result<(), Error> {
+        recover(&mut self.head, decide)?;
+        self.tail.recover(decide)
+    }
+}
+
+impl<A, B> Participants for (A, B)
+where
+    A: Participant,
+    B: Participant,
+{
+    fn prepare(&mut self, xid: &str) -> Result<(), BoxError> {
+        self.0.prepare(xid).map_err(Into::into)?;
+        self.1.prepare(xid).map_err(Into::into)
+    }
+
+    fn commit(&mut self, xid: &str) -> Result<(), BoxError> {
+        let a = self.0.commit(xid).map_err(Into::into);
+        let b = self.1.commit(xid).map_err(Into::into);
+        a.and(b)
+    }
+
+    fn rollback(&mut self, xid: &str) -> Result<(), BoxError> {
+        let a = self.0.rollback(xid).map_err(Into::into);
+        let b = self.1.rollback(xid).map_err(Into::into);
+        a.and(b)
+    }
+
+    fn recover(&mut self, decide: &dyn Fn(&str) -> Option<Decision>) -> Result<(), Error> {
+        recover(&mut self.0, decide)?;
+        recover(&mut self.1, decide)
+    }
+}
@@ -0,0 +1,138 @@
+//! The interface of XA resource managers, by which a backend takes part in
+//! a distributed transaction of an external transaction manager.
+//!
+//! The transaction manager identifies a branch of the global transaction by
+//! an `Xid`, and drives a `ResourceManager` through `start`, the work of the
+//! branch, `end`, `prepare` and `commit` or `rollback`. After a crash it
+//! finds the prepared branches by `recover`. The methods map to the `XA`
+//! statements of MySQL one to one, and to `BEGIN` and the prepared
+//! transactions of PostgreSQL.
+//!
+//! # Examples
+//!
+//! ```
+//! use transaction_2pc::xa::Xid;
+//!
+//! let xid = Xid::new(0x1234, b"order-42".to_vec(), b"db1".to_vec());
+//! let gid = xid.encode();
+//! assert_eq!(gid, "4660_6f726465722d3432_646231");
+//! assert_eq!(Xid::decode(&gid), Some(xid));
+//! assert_eq!(Xid::decode("not an xid"), None);
+//! ```
+
+use std::fmt::Write;
+
+/// The maximum length of the global transaction id and the branch qualifier
+/// of an `Xid`
+pub const MAX_LEN: usize = 64;
+
+/// The id of a branch of a global transaction
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Xid {
+    /// The format of the ids, chosen by the transaction manager
+    pub format_id: i32,
+    /// The id of the global transaction, at most 64 bytes
+    pub gtrid: Vec<u8>,
+    /// The qualifier of the branch, at most 64 bytes
+    pub bqual: Vec<u8>,
+}
+
+impl Xid {
+    /// The id of the branch `bqual` of the global transaction `gtrid`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `gtrid` or `bqual` is longer than 64 bytes.
+    pub fn new(format_id: i32, gtrid: Vec<u8>, bqual: Vec<u8>) -> Self {
+        assert!(gtrid.len() <= MAX_LEN, "gtrid longer than {} bytes", MAX_LEN);
+        assert!(bqual.len() <= MAX_LEN, "bqual longer than {} bytes", MAX_LEN);
+        Xid {
+            format_id,
+            gtrid,
+            bqual,
+        }
+    }
+
+    /// Encode the id into a string of digits, hexadecimal digits and
+    /// underscores, e.g. for the names of the prepared transactions of
+    /// PostgreSQL
+    pub fn encode(&self) -> String {
+        let mut s = format!("{}_", self.format_id);
+        hex(&mut s, &self.gtrid);
+        s.push('_');
+        hex(&mut s, &self.bqual);
+        s
+    }
+
+    /// Decode an id encoded by `encode`, or `None` if `s` is not one
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut parts = s.splitn(3, '_');
+        let format_id = parts.next()?.parse().ok()?;
+        let gtrid = unhex(parts.next()?)?;
+        let bqual = unhex(parts.next()?)?;
+        if gtrid.len() > MAX_LEN || bqual.len() > MAX_LEN {
+            return None;
+        }
+        Some(Xid {
+            format_id,
+            gtrid,
+            bqual,
+        })
+    }
+}
+
+fn hex(s: &mut String, bytes: &[u8]) {
+    for b in bytes {
+        let _ = write!(s, "{:02x}", b);
+    }
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// The vote of a resource manager on preparing a branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Vote {
+    /// The branch is prepared and waits for `commit` or `rollback`
+    Commit,
+    /// The branch made no changes and is already finished, so it must not
+    /// be committed
+    ReadOnly,
+}
+
+/// A resource manager taking part in distributed transactions of an
+/// external transaction manager, e.g. a database connection
+pub trait ResourceManager {
+    /// The error of the resource manager
+    type Err;
+
+    /// Start the branch `xid` on the resource. The work done on it until
+    /// `end` belongs to the branch.
+    fn start(&mut self, xid: &Xid) -> Result<(), Self::Err>;
+
+    /// End the work of the branch `xid`, which is prepared or rolled back
+    /// next
+    fn end(&mut self, xid: &Xid) -> Result<(), Self::Err>;
+
+    /// Prepare the ended branch `xid` to commit
+    fn prepare(&mut self, xid: &Xid) -> Result<Vote, Self::Err>;
+
+    /// Commit the branch `xid`, which is prepared, or only ended if
+    /// `one_phase` is set because the transaction manager skips the
+    /// preparation for a single resource
+    fn commit(&mut self, xid: &Xid, one_phase: bool) -> Result<(), Self::Err>;
+
+    /// Roll back the branch `xid`, whether it is ended or prepared
+    fn rollback(&mut self, xid: &Xid) -> Result<(), Self::Err>;
+
+    /// The branches prepared on the resource and neither committed nor
+    /// rolled back, e.g. by a crash of the transaction manager
+    fn recover(&mut self) -> Result<Vec<Xid>, Self::Err>;
+}
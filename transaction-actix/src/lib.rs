@@ -0,0 +1,230 @@
+//! Per-request transactions for actix-web on r2d2 connection pools
+//!
+//! `Transactional` is an extractor giving a handler the `PooledRunner` of
+//! the app. The handler builds a transaction whose item is the response and
+//! hands it to `Transactional::run`, which runs it on a connection of the
+//! pool in the blocking thread pool. The backend transaction is committed
+//! when the response is successful, and rolled back when the transaction
+//! fails or the response is a server error (5xx).
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fmt;
+//! use std::sync::{Arc, Mutex};
+//!
+//! use actix_web::http::StatusCode;
+//! use actix_web::{test, web, App, ResponseError};
+//! use transaction::prelude::*;
+//! use transaction_actix::Transactional;
+//! use transaction_r2d2::{Connection, PooledRunner};
+//!
+//! // a connection logging the statements to the log shared by the pool
+//! struct Conn(Arc<Mutex<Vec<String>>>);
+//!
+//! impl Connection for Conn {
+//!     type Error = std::io::Error;
+//!     fn begin(&mut self) -> std::io::Result<()> {
+//!         Ok(self.0.lock().unwrap().push("BEGIN".to_string()))
+//!     }
+//!     fn commit(&mut self) -> std::io::Result<()> {
+//!         Ok(self.0.lock().unwrap().push("COMMIT".to_string()))
+//!     }
+//!     fn rollback(&mut self) -> std::io::Result<()> {
+//!         Ok(self.0.lock().unwrap().push("ROLLBACK".to_string()))
+//!     }
+//! }
+//!
+//! struct Manager(Arc<Mutex<Vec<String>>>);
+//! # impl r2d2::ManageConnection for Manager {
+//! #     type Connection = Conn;
+//! #     type Error = std::io::Error;
+//! #     fn connect(&self) -> std::io::Result<Conn> { Ok(Conn(self.0.clone())) }
+//! #     fn is_valid(&self, _: &mut Conn) -> std::io::Result<()> { Ok(()) }
+//! #     fn has_broken(&self, _: &mut Conn) -> bool { false }
+//! # }
+//!
+//! #[derive(Debug)]
+//! struct NotFound;
+//!
+//! impl fmt::Display for NotFound {
+//!     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+//!         write!(f, "not found")
+//!     }
+//! }
+//!
+//! impl ResponseError for NotFound {
+//!     fn status_code(&self) -> StatusCode {
+//!         StatusCode::NOT_FOUND
+//!     }
+//! }
+//!
+//! // the handler only builds the transaction
+//! async fn order(tx: Transactional<Manager>, id: web::Path<u32>) -> actix_web::Result<actix_web::HttpResponse> {
+//!     let id = id.into_inner();
+//!     tx.run(with_ctx(move |conn: &mut Conn| {
+//!         conn.0.lock().unwrap().push(format!("INSERT {}", id));
+//!         match id {
+//!             0 => Err(NotFound),
+//!             1 => Ok(("out of stock".to_string(), StatusCode::SERVICE_UNAVAILABLE)),
+//!             _ => Ok(("ordered".to_string(), StatusCode::CREATED)),
+//!         }
+//!     }))
+//!     .await
+//! }
+//!
+//! # fn main() -> Result<(), r2d2::Error> {
+//! let log = Arc::new(Mutex::new(Vec::new()));
+//! let runner = PooledRunner::new(r2d2::Pool::builder().max_size(1).build(Manager(log.clone()))?);
+//!
+//! actix_web::rt::System::new().block_on(async {
+//!     let app = test::init_service(
+//!         App::new()
+//!             .app_data(web::Data::new(runner))
+//!             .route("/orders/{id}", web::post().to(order)),
+//!     )
+//!     .await;
+//!     for (id, status) in [(2, 201), (0, 404), (1, 503)] {
+//!         let req = test::TestRequest::post().uri(&format!("/orders/{}", id)).to_request();
+//!         assert_eq!(test::call_service(&app, req).await.status(), status);
+//!     }
+//! });
+//! assert_eq!(
+//!     *log.lock().unwrap(),
+//!     ["BEGIN", "INSERT 2", "COMMIT", "BEGIN", "INSERT 0", "ROLLBACK", "BEGIN", "INSERT 1", "ROLLBACK"]
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, Responder, ResponseError};
+use r2d2::ManageConnection;
+use transaction::hooks::{self, Outcome};
+use transaction::Transaction;
+use transaction_r2d2::{Connection, PooledRunner};
+
+type ConnError<M> = <<M as ManageConnection>::Connection as Connection>::Error;
+
+/// An extractor of the `PooledRunner<M>` registered to the app as
+/// `web::Data`, running the transactions of the request.
+///
+/// The extraction fails with 500 Internal Server Error if no runner is
+/// registered.
+pub struct Transactional<M>
+where
+    M: ManageConnection,
+{
+    runner: PooledRunner<M>,
+    req: HttpRequest,
+}
+
+impl<M> Transactional<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+    ConnError<M>: fmt::Debug + fmt::Display + Send + 'static,
+{
+    /// The runner
+    pub fn runner(&self) -> &PooledRunner<M> {
+        &self.runner
+    }
+
+    /// run the given transaction on a connection of the pool and turn its
+    /// item into the response, committing the backend transaction if the
+    /// response is not a server error and rolling it back otherwise.
+    ///
+    /// The error of the transaction is returned as the error response. A
+    /// failure to check out a connection, begin or commit is 500 Internal
+    /// Server Error.
+    pub async fn run<T, E, Tx>(self, tx: Tx) -> actix_web::Result<HttpResponse>
+    where
+        T: Responder + Send + 'static,
+        E: ResponseError + Send + 'static,
+        Tx: Transaction<Ctx = M::Connection, Item = T, Err = E> + Send + 'static,
+    {
+        let Transactional { runner, req } = self;
+        let label = tx.label().map(str::to_string);
+        let label = label.as_deref();
+        hooks::instrument_async(
+            "actix",
+            label,
+            |ret| match *ret {
+                Ok(ref res) if res.status().is_server_error() => Outcome::RolledBack,
+                Ok(_) => Outcome::Committed,
+                Err(_) => Outcome::RolledBack,
+            },
+            finish(runner, req, tx),
+        )
+        .await
+    }
+}
+
+// run the transaction, build the response and commit or roll back by it
+async fn finish<M, T, E, Tx>(runner: PooledRunner<M>, req: HttpRequest, tx: Tx) -> actix_web::Result<HttpResponse>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+    ConnError<M>: fmt::Debug + fmt::Display + Send + 'static,
+    T: Responder + Send + 'static,
+    E: ResponseError + Send + 'static,
+    Tx: Transaction<Ctx = M::Connection, Item = T, Err = E> + Send + 'static,
+{
+    let (conn, ret) = web::block(move || {
+        let mut conn = runner.begin()?;
+        let ret = conn.run(&tx);
+        Ok::<_, transaction_r2d2::PooledError<_>>((conn, ret))
+    })
+    .await?
+    .map_err(ErrorInternalServerError)?;
+    let res = match ret {
+        Ok(t) => t.respond_to(&req).map_into_boxed_body(),
+        Err(e) => {
+            let _ = web::block(move || conn.rollback()).await;
+            return Err(e.into());
+        }
+    };
+    if res.status().is_server_error() {
+        let _ = web::block(move || conn.rollback()).await;
+    } else {
+        web::block(move || conn.commit())
+            .await?
+            .map_err(ErrorInternalServerError)?;
+    }
+    Ok(res)
+}
+
+impl<M> FromRequest for Transactional<M>
+where
+    M: ManageConnection,
+{
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let runner = match req.app_data::<web::Data<PooledRunner<M>>>() {
+            Some(runner) => PooledRunner::clone(&**runner),
+            None => return ready(Err(ErrorInternalServerError("no PooledRunner is registered to the app"))),
+        };
+        ready(Ok(Transactional {
+            runner,
+            req: req.clone(),
+        }))
+    }
+}
+
+impl<M> fmt::Debug for Transactional<M>
+where
+    M: ManageConnection + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transactional")
+            .field("runner", &self.runner)
+            .field("req", &self.req)
+            .finish()
+    }
+}
@@ -0,0 +1,70 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of AMQP transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The broker did not confirm a message published with publisher
+    /// confirms. Publishing again may duplicate the confirmed messages.
+    Nacked,
+    /// Any other error
+    Other,
+}
+
+/// An error of lapin, or a message not confirmed
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: Option<lapin::Error>,
+}
+
+impl Error {
+    pub(crate) fn nacked() -> Self {
+        Error {
+            kind: ErrorKind::Nacked,
+            inner: None,
+        }
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of lapin, if the messages were not nacked
+    pub fn get_ref(&self) -> Option<&lapin::Error> {
+        self.inner.as_ref()
+    }
+}
+
+impl From<lapin::Error> for Error {
+    fn from(inner: lapin::Error) -> Self {
+        Error {
+            kind: ErrorKind::Other,
+            inner: Some(inner),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner {
+            Some(ref e) => fmt::Display::fmt(e, f),
+            None => f.write_str("the broker did not confirm a message"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.as_ref().and_then(|e| e.source())
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind == ErrorKind::Nacked
+    }
+}
@@ -0,0 +1,233 @@
+//! Transaction backend for AMQP
+//!
+//! Asynchronous transactions run in an `AmqpContext`, a channel of lapin
+//! opened for each transaction and put in the transactional mode by
+//! `tx.select`. The messages published by the `publish` leaves are delivered
+//! all together when the [tokio runner](transaction_tokio::Runner) commits
+//! the channel by `tx.commit`, and discarded when it rolls back by
+//! `tx.rollback`.
+//!
+//! Some brokers disable the transactions. `AmqpClient::fallback_to_confirms`
+//! falls back to publisher confirms on them: the messages are published at
+//! once, and the commit waits for the broker to confirm all of them, failing
+//! with `ErrorKind::Nacked` otherwise. The messages published can't be
+//! discarded then, so a rolled back transaction may have delivered some.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use lapin::{Connection, ConnectionProperties};
+//! use transaction::async_tx::{AsyncTransaction, AsyncTransactionExt};
+//! use transaction_amqp::{publish, AmqpClient, AmqpRunner};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), transaction_amqp::Error> {
+//!     let connection = Connection::connect("amqp://localhost:5672", ConnectionProperties::default()).await?;
+//!     let runner = AmqpRunner::new(AmqpClient::new(connection).fallback_to_confirms(true));
+//!
+//!     let order = publish("orders", "created", "book").and_then(|()| publish("invoices", "created", "book: 10"));
+//!     runner.run_async(order).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+use lapin::options::ConfirmSelectOptions;
+use lapin::publisher_confirm::PublisherConfirm;
+use lapin::{Channel, Connection};
+use transaction::hooks::Outcome;
+use transaction_tokio::{AsyncPool, Runner, TestRunner};
+
+mod error;
+mod publish;
+
+pub use crate::error::*;
+pub use crate::publish::*;
+
+/// The context of the transactions: a channel in the transactional mode, or
+/// in the confirm mode when the transactions are disabled.
+pub struct AmqpContext {
+    channel: Channel,
+    // the confirmations to wait for at commit, in the confirm mode
+    confirms: Option<Vec<PublisherConfirm>>,
+}
+
+impl AmqpContext {
+    // never pub this function
+    fn new(channel: Channel, confirm: bool) -> Self {
+        AmqpContext {
+            channel,
+            confirms: if confirm { Some(Vec::new()) } else { None },
+        }
+    }
+
+    /// The channel
+    pub fn channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    /// Whether the channel falls back to publisher confirms
+    pub fn is_confirm_mode(&self) -> bool {
+        self.confirms.is_some()
+    }
+
+    async fn commit(&mut self) -> Result<(), Error> {
+        match self.confirms {
+            None => Ok(self.channel.tx_commit().await?),
+            Some(ref mut confirms) => {
+                for confirm in confirms.drain(..) {
+                    if confirm.await?.is_nack() {
+                        return Err(Error::nacked());
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn rollback(&mut self) -> Result<(), Error> {
+        match self.confirms {
+            None => Ok(self.channel.tx_rollback().await?),
+            // the messages published can't be discarded
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for AmqpContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AmqpContext")
+            .field("channel", &self.channel)
+            .field("confirm_mode", &self.is_confirm_mode())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An `AsyncPool` of the channels of a connection. Each transaction runs on
+/// a new channel, closed when it ends, so they run concurrently.
+#[derive(Clone)]
+pub struct AmqpClient {
+    connection: Arc<Connection>,
+    fallback_to_confirms: bool,
+    // set once the broker refuses the transactions
+    tx_disabled: Arc<AtomicBool>,
+}
+
+impl AmqpClient {
+    /// Run the transactions on the channels of the connection in the
+    /// transactional mode, failing if the broker disables it
+    pub fn new(connection: Connection) -> Self {
+        AmqpClient {
+            connection: Arc::new(connection),
+            fallback_to_confirms: false,
+            tx_disabled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Fall back to publisher confirms if the broker refuses `tx.select`.
+    /// The broker closes the channel refusing it, so the transactions run on
+    /// another channel, and the following ones use publisher confirms
+    /// directly.
+    pub fn fallback_to_confirms(self, fallback_to_confirms: bool) -> Self {
+        AmqpClient {
+            fallback_to_confirms,
+            ..self
+        }
+    }
+
+    /// The connection
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    async fn confirm_channel(&self) -> Result<AmqpContext, Error> {
+        let channel = self.connection.create_channel().await?;
+        channel.confirm_select(ConfirmSelectOptions::default()).await?;
+        Ok(AmqpContext::new(channel, true))
+    }
+}
+
+impl fmt::Debug for AmqpClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AmqpClient")
+            .field("fallback_to_confirms", &self.fallback_to_confirms)
+            .field("tx_disabled", &self.tx_disabled.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncPool for AmqpClient {
+    type Ctx = AmqpContext;
+    type Error = Error;
+
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        async move {
+            if self.fallback_to_confirms && self.tx_disabled.load(Ordering::Relaxed) {
+                return self.confirm_channel().await;
+            }
+            let channel = self.connection.create_channel().await?;
+            match channel.tx_select().await {
+                Ok(()) => Ok(AmqpContext::new(channel, false)),
+                Err(lapin::Error::ProtocolError(_)) if self.fallback_to_confirms => {
+                    self.tx_disabled.store(true, Ordering::Relaxed);
+                    self.confirm_channel().await
+                }
+                Err(e) => Err(e.into()),
+            }
+        }.boxed()
+    }
+
+    fn release(&self, mut ctx: Self::Ctx, outcome: Outcome) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move {
+            let ret = match outcome {
+                Outcome::Committed => ctx.commit().await,
+                Outcome::RolledBack => ctx.rollback().await,
+            };
+            // the channels are not reused
+            let closed = ctx.channel.close(200, "OK").await;
+            ret?;
+            Ok(closed?)
+        }.boxed()
+    }
+}
+
+/// A `Runner` of transactions on the channels of a connection of AMQP
+///
+/// If a transaction panics, its channel is rolled back, so the messages it
+/// published are discarded unless it fell back to publisher confirms, and
+/// the panic is resumed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::panic::AssertUnwindSafe;
+///
+/// use futures::FutureExt;
+/// use lapin::options::{BasicGetOptions, QueueDeclareOptions};
+/// use lapin::types::FieldTable;
+/// use lapin::{Connection, ConnectionProperties};
+/// use transaction::async_tx::AsyncTransactionExt;
+/// use transaction_amqp::{publish, AmqpClient, AmqpRunner};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), transaction_amqp::Error> {
+///     let connection = Connection::connect("amqp://localhost:5672", ConnectionProperties::default()).await?;
+///     let channel = connection.create_channel().await?;
+///     channel.queue_declare("orders", QueueDeclareOptions::default(), FieldTable::default()).await?;
+///     let runner = AmqpRunner::new(AmqpClient::new(connection));
+///
+///     let boom = publish("", "orders", "book").map(|()| -> () { panic!("boom") });
+///     assert!(AssertUnwindSafe(runner.run_async(boom)).catch_unwind().await.is_err());
+///     assert!(channel.basic_get("orders", BasicGetOptions::default()).await?.is_none());
+///     Ok(())
+/// }
+/// ```
+pub type AmqpRunner = Runner<AmqpClient>;
+
+/// A `TestRunner` of transactions on the channels of a connection of AMQP,
+/// rolling back every transaction
+pub type AmqpTestRunner = TestRunner<AmqpClient>;
@@ -0,0 +1,79 @@
+use lapin::options::BasicPublishOptions;
+use lapin::BasicProperties;
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::{AmqpContext, Error};
+
+/// Publish the message to the exchange with the routing key. The message is
+/// delivered when the transaction commits.
+pub fn publish<E, R, P>(exchange: E, routing_key: R, payload: P) -> Publish
+where
+    E: Into<String>,
+    R: Into<String>,
+    P: Into<Vec<u8>>,
+{
+    Publish {
+        exchange: exchange.into(),
+        routing_key: routing_key.into(),
+        payload: payload.into(),
+        options: BasicPublishOptions::default(),
+        properties: BasicProperties::default(),
+    }
+}
+
+/// The result of `publish`
+#[derive(Debug)]
+#[must_use]
+pub struct Publish {
+    exchange: String,
+    routing_key: String,
+    payload: Vec<u8>,
+    options: BasicPublishOptions,
+    properties: BasicProperties,
+}
+
+impl Publish {
+    /// Publish with the options, e.g. `mandatory`
+    pub fn options(self, options: BasicPublishOptions) -> Self {
+        Publish { options, ..self }
+    }
+
+    /// Publish with the properties, e.g. the content type or the delivery
+    /// mode
+    pub fn properties(self, properties: BasicProperties) -> Self {
+        Publish { properties, ..self }
+    }
+}
+
+impl AsyncTransaction for Publish {
+    type Ctx = AmqpContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let confirm = ctx
+                .channel
+                .basic_publish(
+                    &self.exchange,
+                    &self.routing_key,
+                    self.options,
+                    &self.payload,
+                    self.properties.clone(),
+                )
+                .await?;
+            // the confirmations are awaited at commit
+            if let Some(ref mut confirms) = ctx.confirms {
+                confirms.push(confirm);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Visit for Publish {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("publish"));
+    }
+}
@@ -0,0 +1,451 @@
+//! A transaction per request for axum on r2d2 connection pools
+//!
+//! `TxLayer` is a tower layer holding a `PooledRunner`. For each request, it
+//! hands the handler a `Transactional` extension, whose `run` runs
+//! transactions in a backend transaction begun on a connection of the pool
+//! at the first run and shared by all the runs of the request. When the
+//! response is produced, the layer commits the backend transaction if the
+//! response is successful and rolls it back otherwise, e.g. on 4xx and 5xx.
+//!
+//! The errors of the transactions are turned into responses by `TxError`,
+//! whose status is given by `ToStatus`. `run_tx!` runs a transaction and
+//! returns its error from the handler.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fmt;
+//! use std::sync::{Arc, Mutex};
+//!
+//! use axum::body::Body;
+//! use axum::http::{Request, StatusCode};
+//! use axum::routing::post;
+//! use axum::Router;
+//! use tower::ServiceExt;
+//! use transaction::prelude::*;
+//! use transaction_axum::{run_tx, ToStatus, Transactional, TxError, TxLayer};
+//! use transaction_r2d2::{Connection, PooledError, PooledRunner};
+//!
+//! // a connection logging the statements to the log shared by the pool
+//! struct Conn(Arc<Mutex<Vec<String>>>);
+//!
+//! impl Conn {
+//!     fn exec(&mut self, statement: String) {
+//!         self.0.lock().unwrap().push(statement)
+//!     }
+//! }
+//!
+//! impl Connection for Conn {
+//!     type Error = std::io::Error;
+//!     fn begin(&mut self) -> std::io::Result<()> {
+//!         Ok(self.exec("BEGIN".to_string()))
+//!     }
+//!     fn commit(&mut self) -> std::io::Result<()> {
+//!         Ok(self.exec("COMMIT".to_string()))
+//!     }
+//!     fn rollback(&mut self) -> std::io::Result<()> {
+//!         Ok(self.exec("ROLLBACK".to_string()))
+//!     }
+//! }
+//!
+//! struct Manager(Arc<Mutex<Vec<String>>>);
+//! # impl r2d2::ManageConnection for Manager {
+//! #     type Connection = Conn;
+//! #     type Error = std::io::Error;
+//! #     fn connect(&self) -> std::io::Result<Conn> { Ok(Conn(self.0.clone())) }
+//! #     fn is_valid(&self, _: &mut Conn) -> std::io::Result<()> { Ok(()) }
+//! #     fn has_broken(&self, _: &mut Conn) -> bool { false }
+//! # }
+//!
+//! #[derive(Debug)]
+//! enum AppError {
+//!     OutOfStock,
+//!     Pool(PooledError<std::io::Error>),
+//! }
+//! # impl From<PooledError<std::io::Error>> for AppError {
+//! #     fn from(e: PooledError<std::io::Error>) -> Self { AppError::Pool(e) }
+//! # }
+//! # impl fmt::Display for AppError {
+//! #     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{:?}", self) }
+//! # }
+//!
+//! impl ToStatus for AppError {
+//!     fn to_status(&self) -> StatusCode {
+//!         match *self {
+//!             AppError::OutOfStock => StatusCode::CONFLICT,
+//!             AppError::Pool(ref e) => e.to_status(),
+//!         }
+//!     }
+//! }
+//!
+//! fn insert(table: &'static str) -> impl Transaction<Ctx = Conn, Item = (), Err = AppError> {
+//!     with_ctx(move |conn: &mut Conn| Ok(conn.exec(format!("INSERT {}", table))))
+//! }
+//!
+//! fn reserve() -> impl Transaction<Ctx = Conn, Item = (), Err = AppError> {
+//!     insert("stock").and_then(|_| with_ctx(|_: &mut Conn| Err(AppError::OutOfStock)))
+//! }
+//!
+//! // both transactions run in the backend transaction of the request
+//! async fn order(tx: Transactional<Manager>) -> Result<StatusCode, TxError<AppError>> {
+//!     run_tx!(tx, insert("orders"));
+//!     run_tx!(tx, reserve());
+//!     Ok(StatusCode::CREATED)
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let log = Arc::new(Mutex::new(Vec::new()));
+//! let runner = PooledRunner::new(r2d2::Pool::builder().max_size(1).build(Manager(log.clone()))?);
+//! let app = Router::new().route("/orders", post(order)).layer(TxLayer::new(runner));
+//!
+//! let rt = tokio::runtime::Builder::new_current_thread().build()?;
+//! let res = rt.block_on(app.oneshot(Request::post("/orders").body(Body::empty())?))?;
+//! assert_eq!(res.status(), StatusCode::CONFLICT);
+//! assert_eq!(*log.lock().unwrap(), ["BEGIN", "INSERT orders", "INSERT stock", "ROLLBACK"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::panic;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{Request, Response, StatusCode};
+use axum::response::IntoResponse;
+use r2d2::ManageConnection;
+use tower::{Layer, Service};
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+use transaction::Transaction;
+use transaction_r2d2::{Connection, PooledError, PooledRunner, PooledTransaction};
+
+type ConnError<M> = <<M as ManageConnection>::Connection as Connection>::Error;
+
+/// A layer giving each request a `Transactional`, and committing or rolling
+/// back its backend transaction by the response.
+pub struct TxLayer<M>
+where
+    M: ManageConnection,
+{
+    runner: PooledRunner<M>,
+    commit_if: fn(StatusCode) -> bool,
+}
+
+impl<M> TxLayer<M>
+where
+    M: ManageConnection,
+{
+    /// Run the transactions of the requests by the runner, committing them
+    /// if the status of the response is 1xx, 2xx or 3xx.
+    pub fn new(runner: PooledRunner<M>) -> Self {
+        TxLayer {
+            runner,
+            commit_if: |status| !status.is_client_error() && !status.is_server_error(),
+        }
+    }
+
+    /// Commit the transactions of the responses of the statuses for which
+    /// `commit_if` returns true, and roll back the others
+    pub fn commit_if(self, commit_if: fn(StatusCode) -> bool) -> Self {
+        TxLayer { commit_if, ..self }
+    }
+}
+
+impl<M> Clone for TxLayer<M>
+where
+    M: ManageConnection,
+{
+    fn clone(&self) -> Self {
+        TxLayer {
+            runner: self.runner.clone(),
+            commit_if: self.commit_if,
+        }
+    }
+}
+
+impl<M> fmt::Debug for TxLayer<M>
+where
+    M: ManageConnection + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TxLayer").field("runner", &self.runner).finish_non_exhaustive()
+    }
+}
+
+impl<S, M> Layer<S> for TxLayer<M>
+where
+    M: ManageConnection,
+{
+    type Service = TxService<S, M>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TxService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service of `TxLayer`
+pub struct TxService<S, M>
+where
+    M: ManageConnection,
+{
+    inner: S,
+    layer: TxLayer<M>,
+}
+
+impl<S, M> Clone for TxService<S, M>
+where
+    S: Clone,
+    M: ManageConnection,
+{
+    fn clone(&self) -> Self {
+        TxService {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, M> fmt::Debug for TxService<S, M>
+where
+    S: fmt::Debug,
+    M: ManageConnection + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TxService")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<S, M, B, ResBody> Service<Request<B>> for TxService<S, M>
+where
+    S: Service<Request<B>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    M: ManageConnection,
+    M::Connection: Connection,
+    B: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        // the clone may not be ready, so call the one which is
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let state = Arc::new(Mutex::new(State { tx: None, start: None }));
+        req.extensions_mut().insert(Transactional {
+            runner: self.layer.runner.clone(),
+            state: state.clone(),
+        });
+        let commit_if = self.layer.commit_if;
+        Box::pin(async move {
+            let res = inner.call(req).await?;
+            let (tx, start) = {
+                let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+                (state.tx.take(), state.start.take())
+            };
+            if let (Some(tx), Some(start)) = (tx, start) {
+                let outcome = if commit_if(res.status()) {
+                    Outcome::Committed
+                } else {
+                    Outcome::RolledBack
+                };
+                let label = None;
+                // a failed commit leaves the response as is, since it is
+                // already produced; the backend rolls back
+                let _ = tokio::task::spawn_blocking(move || {
+                    let _ = match outcome {
+                        Outcome::Committed => tx.commit(),
+                        Outcome::RolledBack => tx.rollback(),
+                    };
+                })
+                .await;
+                metrics::record_run(label, outcome, start.elapsed());
+                #[cfg(feature = "tracing")]
+                match outcome {
+                    Outcome::Committed => tracing::debug!(backend = "axum", "commit"),
+                    Outcome::RolledBack => tracing::debug!(backend = "axum", "rollback"),
+                }
+                #[cfg(feature = "log")]
+                match outcome {
+                    Outcome::Committed => log::debug!("commit transaction of the request"),
+                    Outcome::RolledBack => log::debug!("rollback transaction of the request"),
+                }
+                hooks::after_run(label, outcome);
+            }
+            Ok(res)
+        })
+    }
+}
+
+// the backend transaction of a request, begun at the first run
+struct State<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+{
+    tx: Option<PooledTransaction<M>>,
+    start: Option<Instant>,
+}
+
+/// The extension running the transactions of a request in its backend
+/// transaction, given by `TxLayer`. Extracting it fails with 500 Internal
+/// Server Error without the layer.
+pub struct Transactional<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+{
+    runner: PooledRunner<M>,
+    state: Arc<Mutex<State<M>>>,
+}
+
+impl<M> Clone for Transactional<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+{
+    fn clone(&self) -> Self {
+        Transactional {
+            runner: self.runner.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<M> fmt::Debug for Transactional<M>
+where
+    M: ManageConnection + fmt::Debug,
+    M::Connection: Connection,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transactional").field("runner", &self.runner).finish_non_exhaustive()
+    }
+}
+
+impl<M> Transactional<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+{
+    /// run the given transaction in the backend transaction of the request
+    /// on the blocking threads, beginning it at the first run. A failed
+    /// transaction leaves its changes in the backend transaction, which is
+    /// rolled back if the error is returned as an error response.
+    pub async fn run<T, E, Tx>(&self, tx: Tx) -> Result<T, E>
+    where
+        T: Send + 'static,
+        E: From<PooledError<ConnError<M>>> + Send + 'static,
+        Tx: Transaction<Ctx = M::Connection, Item = T, Err = E> + Send + 'static,
+    {
+        let runner = self.runner.clone();
+        let state = self.state.clone();
+        let ret = tokio::task::spawn_blocking(move || {
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            if state.tx.is_none() {
+                hooks::before_run(None);
+                #[cfg(feature = "log")]
+                log::debug!("start transaction of the request");
+                state.start = Some(Instant::now());
+                state.tx = Some(runner.begin()?);
+            }
+            state.tx.as_mut().expect("transaction is begun").run(&tx)
+        })
+        .await;
+        match ret {
+            Ok(ret) => ret,
+            Err(e) => panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+impl<M, S> FromRequestParts<S> for Transactional<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Transactional<M>>()
+            .cloned()
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "TxLayer is missing"))
+    }
+}
+
+/// Errors mapped to the statuses of their responses
+pub trait ToStatus {
+    /// The status of the response of the error
+    fn to_status(&self) -> StatusCode;
+}
+
+/// 503 Service Unavailable when no connection is available in time, and 500
+/// Internal Server Error otherwise
+impl<C> ToStatus for PooledError<C> {
+    fn to_status(&self) -> StatusCode {
+        match *self {
+            PooledError::Pool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            PooledError::Connection(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The error of a transaction as a response of the status given by
+/// `ToStatus`, with the error message as the body of a client error
+#[derive(Debug)]
+pub struct TxError<E>(pub E);
+
+impl<E> From<E> for TxError<E> {
+    fn from(e: E) -> Self {
+        TxError(e)
+    }
+}
+
+impl<E> IntoResponse for TxError<E>
+where
+    E: ToStatus + fmt::Display,
+{
+    fn into_response(self) -> axum::response::Response {
+        let status = self.0.to_status();
+        #[cfg(feature = "log")]
+        log::debug!("transaction failed with {}: {}", status, self.0);
+        // the details of server errors are not for the clients
+        if status.is_server_error() {
+            status.into_response()
+        } else {
+            (status, self.0.to_string()).into_response()
+        }
+    }
+}
+
+/// Run the transaction by the `Transactional` of the request and evaluate
+/// to its item, or return its error from the handler as a `TxError`.
+#[macro_export]
+macro_rules! run_tx {
+    ($tx:expr, $transaction:expr) => {
+        match $tx.run($transaction).await {
+            Ok(item) => item,
+            Err(e) => return Err($crate::TxError(e).into()),
+        }
+    };
+}
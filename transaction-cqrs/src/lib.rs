@@ -0,0 +1,223 @@
+//! A command bus dispatching commands to transactions
+//!
+//! A command is a value naming an intent to change the state, e.g. a
+//! deposit. A `CommandHandler` turns the commands into transactions, and a
+//! `Bus` wraps them in its `Middleware`, e.g. `Validation`, `Authorization`
+//! and `Auditing`, which are transaction combinators applied to every
+//! command, before running them by a `Runner`.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::cell::RefCell;
+//! use std::collections::HashMap;
+//! use std::sync::Mutex;
+//!
+//! use transaction::prelude::*;
+//! use transaction::AuditEntry;
+//! use transaction_cqrs::*;
+//!
+//! #[derive(Clone, Default)]
+//! struct Bank {
+//!     user: &'static str,
+//!     balances: HashMap<&'static str, u32>,
+//! }
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Error {
+//!     Invalid(&'static str),
+//!     Forbidden(&'static str),
+//!     Insufficient,
+//! }
+//!
+//! struct Deposit(&'static str, u32);
+//! struct Withdraw(&'static str, u32);
+//!
+//! impl Command for Deposit {
+//!     const NAME: &'static str = "deposit";
+//! }
+//! impl Command for Withdraw {
+//!     const NAME: &'static str = "withdraw";
+//! }
+//!
+//! impl Validate for Deposit {
+//!     type Error = Error;
+//!     fn validate(&self) -> Result<(), Error> {
+//!         if self.1 == 0 { Err(Error::Invalid("zero amount")) } else { Ok(()) }
+//!     }
+//! }
+//! # impl Validate for Withdraw {
+//! #     type Error = Error;
+//! #     fn validate(&self) -> Result<(), Error> { Ok(()) }
+//! # }
+//!
+//! // the handlers of all the commands of the bank
+//! struct Accounts;
+//!
+//! impl CommandHandler<Deposit> for Accounts {
+//!     type Ctx = Bank;
+//!     type Item = u32;
+//!     type Err = Error;
+//!     fn handle<'a>(&'a self, &Deposit(account, amount): &Deposit) -> BoxTx<'a, Bank, u32, Error> {
+//!         with_ctx(move |bank: &mut Bank| {
+//!             let balance = bank.balances.entry(account).or_insert(0);
+//!             *balance += amount;
+//!             Ok(*balance)
+//!         })
+//!         .boxed()
+//!     }
+//! }
+//!
+//! impl CommandHandler<Withdraw> for Accounts {
+//!     type Ctx = Bank;
+//!     type Item = u32;
+//!     type Err = Error;
+//!     fn handle<'a>(&'a self, &Withdraw(account, amount): &Withdraw) -> BoxTx<'a, Bank, u32, Error> {
+//!         with_ctx(move |bank: &mut Bank| {
+//!             let balance = bank.balances.entry(account).or_insert(0);
+//!             *balance = balance.checked_sub(amount).ok_or(Error::Insufficient)?;
+//!             Ok(*balance)
+//!         })
+//!         .boxed()
+//!     }
+//! }
+//!
+//! // runs the transactions on a copy of the bank, keeping it if they succeed
+//! struct BankRunner(RefCell<Bank>);
+//!
+//! impl Runner<Bank> for BankRunner {
+//!     type Error = Error;
+//!     fn run<T, E>(&self, tx: BoxTx<'_, Bank, T, E>) -> Result<T, E> {
+//!         let mut bank = self.0.borrow().clone();
+//!         let t = tx.run(&mut bank)?;
+//!         *self.0.borrow_mut() = bank;
+//!         Ok(t)
+//!     }
+//! }
+//!
+//! let audit = Mutex::new(Vec::<AuditEntry>::new());
+//! let bus = Bus::new(Accounts)
+//!     .layer(Validation)
+//!     .layer(Authorization(|bank: &mut Bank, command| match (bank.user, command) {
+//!         ("teller", _) | (_, "deposit") => Ok(()),
+//!         _ => Err(Error::Forbidden(command)),
+//!     }))
+//!     .layer(Auditing(&audit));
+//!
+//! let runner = BankRunner(RefCell::new(Bank { user: "alice", ..Bank::default() }));
+//! assert_eq!(bus.dispatch(&runner, Deposit("alice", 100)), Ok(100));
+//! assert_eq!(bus.dispatch(&runner, Deposit("alice", 0)), Err(Error::Invalid("zero amount")));
+//! assert_eq!(bus.dispatch(&runner, Withdraw("alice", 30)), Err(Error::Forbidden("withdraw")));
+//!
+//! runner.0.borrow_mut().user = "teller";
+//! assert_eq!(bus.dispatch(&runner, Withdraw("alice", 30)), Ok(70));
+//! assert_eq!(bus.dispatch(&runner, Withdraw("alice", 80)), Err(Error::Insufficient));
+//!
+//! let labels = audit.lock().unwrap().iter().map(|e| e.label.clone().unwrap()).collect::<Vec<_>>();
+//! assert_eq!(labels, ["deposit", "deposit", "withdraw", "withdraw", "withdraw"]);
+//! ```
+
+use transaction::Transaction;
+
+mod middleware;
+
+pub use crate::middleware::*;
+
+/// A boxed transaction, which the handlers return and the middleware wraps
+pub type BoxTx<'a, Ctx, T, E> = Box<dyn Transaction<Ctx = Ctx, Item = T, Err = E> + 'a>;
+
+/// A command, naming an intent to change the state
+pub trait Command {
+    /// The name of the command, e.g. to label its transactions
+    const NAME: &'static str;
+}
+
+/// Handlers turning the commands of type `C` into transactions. A type may
+/// handle several commands, implementing this trait for each.
+pub trait CommandHandler<C> {
+    /// The context of the transactions
+    type Ctx;
+    /// The result of the transactions
+    type Item;
+    /// The error of the transactions
+    type Err;
+
+    /// The transaction handling the command
+    fn handle<'a>(&'a self, command: &C) -> BoxTx<'a, Self::Ctx, Self::Item, Self::Err>;
+}
+
+/// Runners of the transactions in the context `Ctx`, e.g. a backend runner
+/// together with the connection it runs on.
+pub trait Runner<Ctx> {
+    /// The error of the runner, e.g. of a commit
+    type Error;
+
+    /// Run the transaction
+    fn run<T, E>(&self, tx: BoxTx<'_, Ctx, T, E>) -> Result<T, E>
+    where
+        E: From<Self::Error>;
+}
+
+/// A bus dispatching the commands to the handlers `H` through the
+/// middleware `M`
+#[derive(Debug, Clone, Default)]
+pub struct Bus<H, M = ()> {
+    handlers: H,
+    middleware: M,
+}
+
+impl<H> Bus<H> {
+    /// A bus dispatching to the handlers without middleware
+    pub fn new(handlers: H) -> Self {
+        Bus {
+            handlers,
+            middleware: (),
+        }
+    }
+}
+
+impl<H, M> Bus<H, M> {
+    /// Add the middleware, wrapping the transactions wrapped by the
+    /// middleware added so far
+    pub fn layer<N>(self, middleware: N) -> Bus<H, Stack<M, N>> {
+        Bus {
+            handlers: self.handlers,
+            middleware: Stack::new(self.middleware, middleware),
+        }
+    }
+
+    /// The handlers
+    pub fn handlers(&self) -> &H {
+        &self.handlers
+    }
+
+    /// The transaction of the command, wrapped by the middleware, e.g. to
+    /// run it by hand
+    pub fn transaction<'a, C>(&'a self, command: &C) -> BoxTx<'a, H::Ctx, H::Item, H::Err>
+    where
+        C: Command,
+        H: CommandHandler<C>,
+        H::Ctx: 'a,
+        H::Item: 'a,
+        H::Err: 'a,
+        M: Middleware<C, H::Ctx, H::Err>,
+    {
+        self.middleware.wrap(command, self.handlers.handle(command))
+    }
+
+    /// Run the transaction of the command by the runner
+    pub fn dispatch<C, R>(&self, runner: &R, command: C) -> Result<H::Item, H::Err>
+    where
+        C: Command,
+        H: CommandHandler<C>,
+        H::Err: From<R::Error>,
+        M: Middleware<C, H::Ctx, H::Err>,
+        R: Runner<H::Ctx>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("command", name = C::NAME).entered();
+        #[cfg(feature = "log")]
+        log::debug!("dispatch command {}", C::NAME);
+        runner.run(self.transaction(&command))
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+
+use transaction::prelude::*;
+use transaction::{AuditSink, NoPayload};
+
+use crate::{BoxTx, Command};
+
+/// Middleware of a `Bus`, wrapping the transactions of the commands in
+/// combinators, e.g. to validate, authorize or audit them.
+pub trait Middleware<C, Ctx, E> {
+    /// Wrap the transaction handling the command
+    fn wrap<'a, T>(&'a self, command: &C, tx: BoxTx<'a, Ctx, T, E>) -> BoxTx<'a, Ctx, T, E>
+    where
+        Ctx: 'a,
+        T: 'a,
+        E: 'a;
+}
+
+/// No middleware
+impl<C, Ctx, E> Middleware<C, Ctx, E> for () {
+    fn wrap<'a, T>(&'a self, _command: &C, tx: BoxTx<'a, Ctx, T, E>) -> BoxTx<'a, Ctx, T, E>
+    where
+        Ctx: 'a,
+        T: 'a,
+        E: 'a,
+    {
+        tx
+    }
+}
+
+/// The middleware `Outer` wrapping the transactions wrapped by `Inner`,
+/// given by `Bus::layer`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+impl<Inner, Outer> Stack<Inner, Outer> {
+    pub(crate) fn new(inner: Inner, outer: Outer) -> Self {
+        Stack { inner, outer }
+    }
+}
+
+impl<C, Ctx, E, Inner, Outer> Middleware<C, Ctx, E> for Stack<Inner, Outer>
+where
+    Inner: Middleware<C, Ctx, E>,
+    Outer: Middleware<C, Ctx, E>,
+{
+    fn wrap<'a, T>(&'a self, command: &C, tx: BoxTx<'a, Ctx, T, E>) -> BoxTx<'a, Ctx, T, E>
+    where
+        Ctx: 'a,
+        T: 'a,
+        E: 'a,
+    {
+        let tx = self.inner.wrap(command, tx);
+        self.outer.wrap(command, tx)
+    }
+}
+
+/// Commands which can be checked before they are handled
+pub trait Validate {
+    /// The error of an invalid command
+    type Error;
+
+    /// Check the command
+    fn validate(&self) -> Result<(), Self::Error>;
+}
+
+/// Middleware failing the transactions of invalid commands without running
+/// them. All the commands dispatched through it must be `Validate`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Validation;
+
+impl<C, Ctx, E> Middleware<C, Ctx, E> for Validation
+where
+    C: Validate,
+    C::Error: Clone + 'static,
+    E: From<C::Error>,
+{
+    fn wrap<'a, T>(&'a self, command: &C, tx: BoxTx<'a, Ctx, T, E>) -> BoxTx<'a, Ctx, T, E>
+    where
+        Ctx: 'a,
+        T: 'a,
+        E: 'a,
+    {
+        match command.validate() {
+            Ok(()) => tx,
+            Err(e) => with_ctx(move |_: &mut Ctx| Err(E::from(e.clone()))).boxed(),
+        }
+    }
+}
+
+/// Middleware running the check, given the context and the name of the
+/// command, before the transactions, failing them if it fails, e.g. when
+/// the user in the context may not run the command.
+#[derive(Clone, Copy, Default)]
+pub struct Authorization<F>(pub F);
+
+impl<F> fmt::Debug for Authorization<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Authorization").finish_non_exhaustive()
+    }
+}
+
+impl<C, Ctx, E, F> Middleware<C, Ctx, E> for Authorization<F>
+where
+    C: Command,
+    F: Fn(&mut Ctx, &'static str) -> Result<(), E>,
+{
+    fn wrap<'a, T>(&'a self, _command: &C, tx: BoxTx<'a, Ctx, T, E>) -> BoxTx<'a, Ctx, T, E>
+    where
+        Ctx: 'a,
+        T: 'a,
+        E: 'a,
+    {
+        Box::new(Guarded {
+            check: move |ctx: &mut Ctx| (self.0)(ctx, C::NAME),
+            tx,
+        })
+    }
+}
+
+/// Middleware recording an entry of each run of the transactions to the
+/// sink, labeled by the name of the command. See `transaction::audited`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Auditing<S>(pub S);
+
+impl<C, Ctx, E, S> Middleware<C, Ctx, E> for Auditing<S>
+where
+    C: Command,
+    S: AuditSink,
+{
+    fn wrap<'a, T>(&'a self, _command: &C, tx: BoxTx<'a, Ctx, T, E>) -> BoxTx<'a, Ctx, T, E>
+    where
+        Ctx: 'a,
+        T: 'a,
+        E: 'a,
+    {
+        tx.named(C::NAME).audited(&self.0, NoPayload).boxed()
+    }
+}
+
+// run the check before the transaction
+struct Guarded<F, Tx> {
+    check: F,
+    tx: Tx,
+}
+
+impl<F, Tx> Transaction for Guarded<F, Tx>
+where
+    Tx: Transaction,
+    F: Fn(&mut Tx::Ctx) -> Result<(), Tx::Err>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        (self.check)(ctx)?;
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
@@ -2,19 +2,399 @@
 
 extern crate diesel;
 extern crate transaction;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "log")]
+extern crate log;
 use transaction::*;
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
 use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
 
 /// run the given function insed a transaction using the given connection.
+/// If the function panics, the transaction is rolled back before the panic
+/// is resumed, like in all the runners of this crate.
 pub fn run<'a, Cn, T, E, Tx>(cn: &'a Cn, tx: Tx) -> Result<T, E>
 where
     Cn: diesel::Connection,
     E: From<diesel::result::Error>,
     Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
 {
-    cn.clone().transaction(
-        || tx.run(&mut DieselContext::new(cn)),
-    )
+    hooks::instrument("diesel", tx.label(), Outcome::of, || {
+        transaction(cn, || tx.run(&mut DieselContext::new(cn)))
+    })
+}
+
+/// run the given function insed a transaction using the given connection.
+/// The `scoped` sub-transactions are run in savepoints, so an error in them
+/// only rolls back their own changes.
+pub fn run_scoped<'a, Cn, T, E, Tx>(cn: &'a Cn, tx: Tx) -> Result<T, E>
+where
+    Cn: diesel::Connection,
+    E: From<diesel::result::Error>,
+    Tx: Transaction<Ctx = ScopedCtx<DieselContext<'a, Cn>>, Item = T, Err = E>,
+{
+    hooks::instrument("diesel", tx.label(), Outcome::of, || {
+        transaction(cn, || tx.run(&mut ScopedCtx::new(DieselContext::new(cn))))
+    })
+}
+
+/// run the given function insed a transaction using the given connection.
+/// The entries told by the transaction are returned only if it is committed.
+pub fn run_logged<'a, Cn, L, T, E, Tx>(cn: &'a Cn, tx: Tx) -> Result<(T, Vec<L>), E>
+where
+    Cn: diesel::Connection,
+    E: From<diesel::result::Error>,
+    Tx: Transaction<Ctx = WithLog<DieselContext<'a, Cn>, L>, Item = T, Err = E>,
+{
+    hooks::instrument("diesel", tx.label(), Outcome::of, || {
+        transaction(cn, || {
+            let mut ctx = WithLog::new(DieselContext::new(cn));
+            let item = tx.run(&mut ctx)?;
+            Ok((item, ctx.take_log()))
+        })
+    })
+}
+
+/// The backends whose transactions can be configured by `RunnerBuilder`.
+/// Enable the `postgres` or `mysql` feature to implement it for the backend.
+pub trait SetTransaction: diesel::backend::Backend {
+    /// Whether `SET TRANSACTION` is issued just before beginning the
+    /// transaction (MySQL) rather than as its first statement (PostgreSQL)
+    const BEFORE_BEGIN: bool;
+
+    /// The statement lifting the limit of `limit_statements` once the
+    /// transaction is done, if the limit outlives it
+    const LIFT_LIMIT: Option<&'static str>;
+
+    /// The statement limiting the statements of the transaction to `millis`
+    /// milliseconds, issued with `SET TRANSACTION`
+    fn limit_statements(millis: u128) -> String;
+}
+
+#[cfg(feature = "postgres")]
+impl SetTransaction for diesel::pg::Pg {
+    const BEFORE_BEGIN: bool = false;
+    const LIFT_LIMIT: Option<&'static str> = None;
+
+    fn limit_statements(millis: u128) -> String {
+        format!("SET LOCAL statement_timeout = {}", millis)
+    }
+}
+
+// `max_execution_time` only limits the `SELECT`s, and is set for the session
+#[cfg(feature = "mysql")]
+impl SetTransaction for diesel::mysql::Mysql {
+    const BEFORE_BEGIN: bool = true;
+    const LIFT_LIMIT: Option<&'static str> = Some("SET SESSION max_execution_time = DEFAULT");
+
+    fn limit_statements(millis: u128) -> String {
+        format!("SET SESSION max_execution_time = {}", millis)
+    }
+}
+
+/// Builder of a `Runner` issuing the isolation level and the access mode of
+/// the transactions, e.g.
+/// `RunnerBuilder::new().isolation(IsolationLevel::Serializable).build()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunnerBuilder {
+    mode: TransactionMode,
+    timeout: Option<Duration>,
+}
+
+impl RunnerBuilder {
+    /// Use the database defaults
+    pub fn new() -> Self {
+        RunnerBuilder::default()
+    }
+
+    /// Set the isolation level
+    pub fn isolation(self, level: IsolationLevel) -> Self {
+        RunnerBuilder {
+            mode: self.mode.isolation(level),
+            ..self
+        }
+    }
+
+    /// Make the transactions read-only
+    pub fn read_only(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.read_only(),
+            ..self
+        }
+    }
+
+    /// Make the transactions deferrable (PostgreSQL only)
+    pub fn deferrable(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.deferrable(),
+            ..self
+        }
+    }
+
+    /// Replace all the characteristics
+    pub fn mode(self, mode: TransactionMode) -> Self {
+        RunnerBuilder { mode, ..self }
+    }
+
+    /// Give each transaction `timeout` to be done, see `Runner::run`
+    pub fn timeout(self, timeout: Duration) -> Self {
+        RunnerBuilder {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> Runner {
+        Runner {
+            mode: self.mode,
+            timeout: self.timeout,
+        }
+    }
+
+    /// Build the runner rolling back every transaction, for tests
+    pub fn build_test(self) -> TestRunner {
+        TestRunner { runner: self.build() }
+    }
+}
+
+/// Runner of transactions with the characteristics given by `RunnerBuilder`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Runner {
+    mode: TransactionMode,
+    timeout: Option<Duration>,
+}
+
+impl Runner {
+    /// The characteristics of the transactions
+    pub fn mode(&self) -> &TransactionMode {
+        &self.mode
+    }
+
+    /// run the given function insed a transaction with the characteristics
+    /// using the given connection. Pass a reference to run the same
+    /// transaction with other runners.
+    ///
+    /// The transaction is to be done by the timeout of the runner, or by the
+    /// `transaction::RunDeadline` entered around if earlier, e.g. by a
+    /// `transaction::runner::RunnerBuilder::deadline`. Its statements are
+    /// limited to the time remaining when it begins, by
+    /// `SET LOCAL statement_timeout` on PostgreSQL and by
+    /// `SET SESSION max_execution_time` on MySQL, which only limits the
+    /// `SELECT`s and is lifted once the transaction is done. The deadline is
+    /// entered for `transaction::remaining_time`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate diesel;
+    /// extern crate transaction_diesel;
+    ///
+    /// # #[cfg(feature = "postgres")]
+    /// # fn main() {
+    /// use std::time::Duration;
+    /// use diesel::expression::dsl::sql;
+    /// use diesel::pg::PgConnection;
+    /// use diesel::prelude::*;
+    /// use diesel::types::Text;
+    /// use transaction_diesel::{with_conn, RunnerBuilder};
+    ///
+    /// let cn = PgConnection::establish("postgres://localhost/postgres").unwrap();
+    /// let runner = RunnerBuilder::new().timeout(Duration::from_secs(5)).build();
+    /// let timeout = with_conn(|cn: &PgConnection| {
+    ///     diesel::select(sql::<Text>("(SELECT setting FROM pg_settings WHERE name = 'statement_timeout')"))
+    ///         .get_result::<String>(cn)
+    /// });
+    /// let timeout: u64 = runner.run(&cn, timeout).unwrap().parse().unwrap();
+    /// assert!(timeout > 0 && timeout <= 5000);
+    /// # }
+    /// # #[cfg(not(feature = "postgres"))]
+    /// # fn main() {}
+    /// ```
+    pub fn run<'a, Cn, T, E, Tx>(&self, cn: &'a Cn, tx: Tx) -> Result<T, E>
+    where
+        Cn: diesel::Connection,
+        Cn::Backend: SetTransaction,
+        E: From<diesel::result::Error>,
+        Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+    {
+        hooks::instrument("diesel", tx.label(), Outcome::of, || {
+            self.transaction(cn, || tx.run(&mut DieselContext::new(cn)))
+        })
+    }
+
+    // begin a transaction with the characteristics and run `f` in it
+    fn transaction<Cn, T, E, F>(&self, cn: &Cn, f: F) -> Result<T, E>
+    where
+        Cn: diesel::Connection,
+        Cn::Backend: SetTransaction,
+        E: From<diesel::result::Error>,
+        F: FnOnce() -> Result<T, E>,
+    {
+        let _deadline = RunDeadline::enter(self.timeout.map(|timeout| clock::Instant::now() + timeout));
+        // a timeout of 0 disables it, so at least 1ms is given
+        let limit = RunDeadline::current().map(|deadline| {
+            let timeout = deadline.saturating_duration_since(clock::Instant::now()).as_millis().max(1);
+            Cn::Backend::limit_statements(timeout)
+        });
+        let _lift = match (&limit, Cn::Backend::LIFT_LIMIT) {
+            (Some(_), Some(lift)) => Some(LiftLimit { cn, lift }),
+            _ => None,
+        };
+        let set: Vec<_> = self.mode.characteristics().map(|c| format!("SET TRANSACTION {}", c)).into_iter().chain(limit).collect();
+        let set_all = || -> Result<(), diesel::result::Error> {
+            for statement in &set {
+                cn.batch_execute(statement)?;
+            }
+            Ok(())
+        };
+        if Cn::Backend::BEFORE_BEGIN {
+            set_all()?;
+        }
+        transaction(cn, || {
+            if !Cn::Backend::BEFORE_BEGIN {
+                set_all()?;
+            }
+            f()
+        })
+    }
+
+    /// run the given function like `run`, running the whole transaction
+    /// again while it fails with a retryable error (e.g. a serialization
+    /// failure or a deadlock) and the policy allows. Each retry is recorded
+    /// by `metrics::record_retry`. See `is_retryable` to classify the errors
+    /// of diesel.
+    pub fn run_retry<'a, Cn, T, E, Tx, R>(&self, cn: &'a Cn, tx: Tx, policy: R) -> Result<T, E>
+    where
+        Cn: diesel::Connection,
+        Cn::Backend: SetTransaction,
+        E: From<diesel::result::Error> + Retryable,
+        Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+        R: RetryPolicy,
+    {
+        let mut retries = 0;
+        loop {
+            let e = match self.run(cn, &tx) {
+                Err(e) => if e.is_retryable() { e } else { return Err(e) },
+                Ok(t) => return Ok(t),
+            };
+            let delay = match policy.next_delay(retries) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            #[cfg(feature = "log")]
+            log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+            metrics::record_retry(tx.label());
+            thread::sleep(delay);
+            retries += 1;
+        }
+    }
+}
+
+// lifts the limit of the statements of a transaction outliving it, even if
+// the transaction panics
+struct LiftLimit<'a, Cn: diesel::Connection + 'a> {
+    cn: &'a Cn,
+    lift: &'static str,
+}
+
+impl<'a, Cn: diesel::Connection> Drop for LiftLimit<'a, Cn> {
+    fn drop(&mut self) {
+        let _ = self.cn.batch_execute(self.lift);
+    }
+}
+
+/// Runner of transactions which are always rolled back, even when they
+/// succeed, for hermetic tests against a real database. Unlike `test_run`,
+/// the `Item` or the error is returned instead of panicking, and the nested
+/// savepoints behave as they do in `Runner`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestRunner {
+    runner: Runner,
+}
+
+impl TestRunner {
+    /// The characteristics of the transactions
+    pub fn mode(&self) -> &TransactionMode {
+        self.runner.mode()
+    }
+
+    /// run the given function insed a transaction with the characteristics
+    /// using the given connection, and roll it back.
+    pub fn run<'a, Cn, T, E, Tx>(&self, cn: &'a Cn, tx: Tx) -> Result<T, E>
+    where
+        Cn: diesel::Connection,
+        Cn::Backend: SetTransaction,
+        E: From<diesel::result::Error>,
+        Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+    {
+        hooks::instrument("diesel", tx.label(), |_| Outcome::RolledBack, || {
+            let mut ret = None;
+            let rolled_back = self.runner.transaction(cn, || {
+                ret = Some(tx.run(&mut DieselContext::new(cn)));
+                Err(diesel::result::Error::RollbackTransaction)
+            });
+            match rolled_back {
+                Err(diesel::result::Error::RollbackTransaction) | Ok(()) => {
+                    ret.expect("the transaction has been run")
+                }
+                // a failure to roll back is reported since the test is no
+                // longer hermetic
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+}
+
+/// Whether the error of diesel may go away by running the transaction
+/// again: serialization failures, deadlocks and lock timeouts of PostgreSQL
+/// and MySQL.
+///
+/// This version of diesel doesn't expose the SQLSTATE, so the messages of
+/// the errors are matched.
+pub fn is_retryable(e: &diesel::result::Error) -> bool {
+    const MESSAGES: &[&str] = &[
+        // PostgreSQL: serialization_failure, deadlock_detected, lock_not_available
+        "could not serialize access",
+        "deadlock detected",
+        "could not obtain lock",
+        // MySQL: ER_LOCK_DEADLOCK, ER_LOCK_WAIT_TIMEOUT
+        "Deadlock found",
+        "Lock wait timeout exceeded",
+    ];
+    match *e {
+        diesel::result::Error::DatabaseError(_, ref info) => {
+            MESSAGES.iter().any(|m| info.message().contains(m))
+        }
+        _ => false,
+    }
+}
+
+// run `f` in a transaction of the connection. diesel leaves the
+// transaction open if `f` panics, so it is rolled back before the panic is
+// resumed.
+fn transaction<Cn, T, E, F>(cn: &Cn, f: F) -> Result<T, E>
+where
+    Cn: diesel::Connection,
+    E: From<diesel::result::Error>,
+    F: FnOnce() -> Result<T, E>,
+{
+    let mut panicked = None;
+    let ret = cn.transaction(|| match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(ret) => ret,
+        Err(e) => {
+            panicked = Some(e);
+            Err(diesel::result::Error::RollbackTransaction.into())
+        }
+    });
+    if let Some(e) = panicked {
+        panic::resume_unwind(e);
+    }
+    ret
 }
 
 /// run the given function insed a transaction using the given connection but do not commit it.
@@ -26,9 +406,9 @@ where
     E: From<diesel::result::Error>,
     Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
 {
-    cn.clone().test_transaction(
-        || tx.run(&mut DieselContext::new(cn)),
-    )
+    hooks::instrument("diesel", tx.label(), |_| Outcome::RolledBack, || {
+        cn.test_transaction(|| tx.run(&mut DieselContext::new(cn)))
+    })
 }
 
 /// diesel transaction object.
@@ -51,6 +431,74 @@ impl<'a, Cn> DieselContext<'a, Cn> {
     }
 }
 
+impl<'a, Cn> Savepoints for DieselContext<'a, Cn>
+where
+    Cn: diesel::Connection,
+{
+    type Error = diesel::result::Error;
+
+    fn savepoint(&mut self, name: &str) -> Result<(), Self::Error> {
+        self.conn.batch_execute(&format!("SAVEPOINT {}", name))
+    }
+
+    fn release(&mut self, name: &str) -> Result<(), Self::Error> {
+        self.conn.batch_execute(&format!("RELEASE SAVEPOINT {}", name))
+    }
+
+    fn rollback_to(&mut self, name: &str) -> Result<(), Self::Error> {
+        self.conn.batch_execute(&format!("ROLLBACK TO SAVEPOINT {}", name))
+    }
+}
+
+/// Run the transaction in a nested diesel transaction, i.e. a savepoint. When
+/// it fails, the changes it made are rolled back to the savepoint and the
+/// error is returned, so the enclosing transaction can recover with e.g.
+/// `or_else` and still commit.
+pub fn nested<'a, Cn, A>(a: A) -> Nested<A::Tx>
+where
+    Cn: diesel::Connection,
+    A: IntoTransaction<DieselContext<'a, Cn>>,
+{
+    Nested { tx: a.into_transaction() }
+}
+
+/// The result of `nested`
+#[derive(Debug)]
+#[must_use]
+pub struct Nested<Tx> {
+    tx: Tx,
+}
+
+impl<'a, Cn, Tx> Transaction for Nested<Tx>
+where
+    Cn: diesel::Connection + 'a,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>>,
+    Tx::Err: From<diesel::result::Error>,
+{
+    type Ctx = DieselContext<'a, Cn>;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut DieselContext<'a, Cn>) -> Result<Self::Item, Self::Err> {
+        // diesel issues savepoints for the transactions in a transaction
+        let conn = ctx.conn();
+        transaction(conn, || self.tx.run(ctx))
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Nested<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("nested"), |v| self.tx.accept(v));
+    }
+}
+
 /// Receive the connection from the executing transaction and perform computation.
 pub fn with_conn<'a, Conn, F, T, E>(f: F) -> WithConn<'a, Conn, F>
 where
@@ -80,3 +528,50 @@ where
         (self.f)(ctx.conn())
     }
 }
+
+impl<'a, Conn, F> Visit for WithConn<'a, Conn, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_conn"));
+    }
+}
+
+/// Issue `SET TRANSACTION` with the characteristics of the mode in the
+/// transaction of `run_scoped`, e.g. to make it serializable. It is illegal
+/// in a savepoint, so it doesn't compile in a `scoped`. PostgreSQL accepts
+/// it only before the first query of the transaction.
+pub fn set_transaction_mode<'a, Cn>(mode: TransactionMode) -> SetTransactionMode<'a, Cn> {
+    SetTransactionMode {
+        mode,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `set_transaction_mode`
+#[derive(Debug)]
+#[must_use]
+pub struct SetTransactionMode<'a, Cn: 'a> {
+    mode: TransactionMode,
+    _phantom: PhantomData<&'a Cn>,
+}
+
+impl<'a, Cn> Transaction for SetTransactionMode<'a, Cn>
+where
+    Cn: diesel::Connection,
+{
+    type Ctx = ScopedCtx<DieselContext<'a, Cn>, Top>;
+    type Item = ();
+    type Err = diesel::result::Error;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match self.mode.characteristics() {
+            Some(c) => ctx.inner().conn().batch_execute(&format!("SET TRANSACTION {}", c)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, Cn> Visit for SetTransactionMode<'a, Cn> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("set_transaction_mode"));
+    }
+}
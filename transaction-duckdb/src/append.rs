@@ -0,0 +1,169 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use duckdb::types::ToSql;
+use duckdb::{Appender, Connection, DatabaseName};
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{DuckDbContext, Error, Params};
+
+// the table an appender appends to
+#[derive(Debug)]
+struct Target {
+    table: String,
+    schema: Option<String>,
+    columns: Vec<String>,
+}
+
+impl Target {
+    fn new(table: impl Into<String>) -> Self {
+        Target {
+            table: table.into(),
+            schema: None,
+            columns: Vec::new(),
+        }
+    }
+
+    fn appender<'c>(&self, conn: &'c Connection) -> duckdb::Result<Appender<'c>> {
+        let main = DatabaseName::Main.to_string();
+        let schema = self.schema.as_deref().unwrap_or(&main);
+        if self.columns.is_empty() {
+            conn.appender_to_db(&self.table, schema)
+        } else {
+            let columns = self.columns.iter().map(String::as_str).collect::<Vec<_>>();
+            conn.appender_with_columns_to_db(&self.table, schema, &columns)
+        }
+    }
+}
+
+/// Append the rows to the table through an appender, and return the number
+/// of the rows. The rows are flushed at the end of the leaf, so the later
+/// leaves see them, and committed with the transaction.
+pub fn append<'a>(table: impl Into<String>, rows: Vec<Params>) -> Append<'a> {
+    Append {
+        target: Target::new(table),
+        rows,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `append`
+#[must_use]
+pub struct Append<'a> {
+    target: Target,
+    rows: Vec<Params>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Append<'a> {
+    /// Append to the table of the schema instead of `main`
+    pub fn schema(mut self, schema: impl Into<String>) -> Self {
+        self.target.schema = Some(schema.into());
+        self
+    }
+
+    /// Append only to the columns, in the order given, filling the others
+    /// with their defaults
+    pub fn columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.target.columns = columns.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+impl<'a> fmt::Debug for Append<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Append")
+            .field("target", &self.target)
+            .field("rows", &self.rows.len())
+            .finish()
+    }
+}
+
+impl<'a> Transaction for Append<'a> {
+    type Ctx = DuckDbContext<'a>;
+    type Item = usize;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut DuckDbContext<'a>) -> Result<Self::Item, Self::Err> {
+        let mut appender = self.target.appender(ctx.conn())?;
+        for row in &self.rows {
+            let row = row.iter().map(|p| p as &dyn ToSql).collect::<Vec<_>>();
+            appender.append_row(&row[..])?;
+        }
+        appender.flush()?;
+        Ok(self.rows.len())
+    }
+}
+
+impl<'a> Visit for Append<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("append"));
+    }
+}
+
+/// Receive an appender to the table and perform computation, e.g. append the
+/// rows generated on the fly. The appender is flushed after `f` succeeds.
+pub fn with_appender<'a, F, T>(table: impl Into<String>, f: F) -> WithAppender<'a, F>
+where
+    F: Fn(&mut Appender) -> duckdb::Result<T>,
+{
+    WithAppender {
+        target: Target::new(table),
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_appender`
+#[derive(Debug)]
+#[must_use]
+pub struct WithAppender<'a, F> {
+    target: Target,
+    f: F,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, F> WithAppender<'a, F> {
+    /// Append to the table of the schema instead of `main`
+    pub fn schema(mut self, schema: impl Into<String>) -> Self {
+        self.target.schema = Some(schema.into());
+        self
+    }
+
+    /// Append only to the columns, in the order given, filling the others
+    /// with their defaults
+    pub fn columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.target.columns = columns.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+impl<'a, F, T> Transaction for WithAppender<'a, F>
+where
+    F: Fn(&mut Appender) -> duckdb::Result<T>,
+{
+    type Ctx = DuckDbContext<'a>;
+    type Item = T;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut DuckDbContext<'a>) -> Result<Self::Item, Self::Err> {
+        let mut appender = self.target.appender(ctx.conn())?;
+        let t = (self.f)(&mut appender)?;
+        appender.flush()?;
+        Ok(t)
+    }
+}
+
+impl<'a, F> Visit for WithAppender<'a, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_appender"));
+    }
+}
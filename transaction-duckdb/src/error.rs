@@ -0,0 +1,77 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of duckdb-rs by their messages, as DuckDB
+/// reports no error codes through its C API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The transaction conflicted with a concurrent one writing the same rows
+    /// or catalog entries, and was aborted
+    Conflict,
+    /// A constraint like `PRIMARY KEY`, `UNIQUE` or `NOT NULL` is violated
+    Constraint,
+    /// Any other error
+    Other,
+}
+
+/// An error of duckdb-rs together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: duckdb::Error,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of duckdb-rs
+    pub fn get_ref(&self) -> &duckdb::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of duckdb-rs
+    pub fn into_inner(self) -> duckdb::Error {
+        self.inner
+    }
+}
+
+impl From<duckdb::Error> for Error {
+    fn from(inner: duckdb::Error) -> Self {
+        let kind = match inner {
+            duckdb::Error::DuckDBFailure(_, Some(ref msg)) => {
+                if msg.starts_with("Constraint Error") {
+                    ErrorKind::Constraint
+                } else if msg.to_lowercase().contains("conflict") {
+                    ErrorKind::Conflict
+                } else {
+                    ErrorKind::Other
+                }
+            }
+            _ => ErrorKind::Other,
+        };
+        Error { kind, inner }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind == ErrorKind::Conflict
+    }
+}
@@ -0,0 +1,251 @@
+//! A transaction runner for duckdb-rs
+//!
+//! Transactions run in a `DuckDbContext` wrapping a transaction of
+//! duckdb-rs, which `run` commits when the transaction succeeds and rolls
+//! back otherwise. `execute`, `query_row`, `query_map` and friends expose the
+//! statements of duckdb-rs as leaves, and `append` and `with_appender` bulk
+//! insert rows through the appenders of DuckDB, which are much faster than
+//! `INSERT` statements for loads.
+//!
+//! A batch job loading several tables in one transaction publishes all of
+//! them at once or none. DuckDB aborts the transaction writing the rows or
+//! the catalog entries a concurrent one has written with a retryable
+//! `ErrorKind::Conflict`, which `run_retry` runs again. The runner built with
+//! `RunnerBuilder::checkpoint` also checkpoints the database after each
+//! commit, moving the loaded data from the write-ahead log to the database
+//! file.
+//!
+//! # Examples
+//!
+//! ```
+//! use duckdb::Connection;
+//! use transaction::prelude::*;
+//! use transaction_duckdb::{append, execute_batch, query_row, with_appender, RunnerBuilder};
+//!
+//! # fn main() -> Result<(), transaction_duckdb::Error> {
+//! let conn = Connection::open_in_memory()?;
+//! let runner = RunnerBuilder::new().build();
+//! runner.run(&conn, execute_batch(
+//!     "CREATE TABLE days (day INTEGER PRIMARY KEY); CREATE TABLE sales (day INTEGER, amount DOUBLE)",
+//! ))?;
+//!
+//! let load = with_appender("days", |app| app.append_rows((1..=3).map(|day| [day])))
+//!     .and_then(|()| append("sales", vec![vec![Box::new(1), Box::new(9.5)], vec![Box::new(3), Box::new(2.0)]]))
+//!     .and_then(|_| query_row("SELECT SUM(amount) FROM sales", vec![], |row| row.get::<_, f64>(0)));
+//! assert_eq!(runner.run(&conn, &load)?, 11.5);
+//!
+//! // the days are loaded again, so the whole load fails and is rolled back
+//! assert!(runner.run(&conn, &load).is_err());
+//! let count = runner.run(&conn, query_row("SELECT COUNT(*) FROM sales", vec![], |row| row.get::<_, i64>(0)))?;
+//! assert_eq!(count, 2);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::thread;
+
+use duckdb::Connection;
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+use transaction::{RetryPolicy, Retryable, Transaction};
+
+mod append;
+mod error;
+mod statement;
+
+pub use crate::append::*;
+pub use crate::error::*;
+pub use crate::statement::*;
+
+/// The context of the transactions: a transaction of duckdb-rs.
+pub struct DuckDbContext<'a> {
+    tx: duckdb::Transaction<'a>,
+}
+
+impl<'a> DuckDbContext<'a> {
+    // never pub this function
+    fn new(tx: duckdb::Transaction<'a>) -> Self {
+        DuckDbContext { tx }
+    }
+
+    /// The connection in the transaction
+    pub fn conn(&self) -> &Connection {
+        &self.tx
+    }
+}
+
+impl<'a> fmt::Debug for DuckDbContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DuckDbContext").finish_non_exhaustive()
+    }
+}
+
+/// run the given function inside a transaction using the given connection.
+pub fn run<'a, T, E, Tx>(conn: &'a Connection, tx: Tx) -> Result<T, E>
+where
+    E: From<Error>,
+    Tx: Transaction<Ctx = DuckDbContext<'a>, Item = T, Err = E>,
+{
+    Runner::default().run(conn, tx)
+}
+
+/// Builder of a `Runner`, e.g. `RunnerBuilder::new().checkpoint(true).build()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunnerBuilder {
+    checkpoint: bool,
+}
+
+impl RunnerBuilder {
+    /// Leave the checkpoints to DuckDB
+    pub fn new() -> Self {
+        RunnerBuilder::default()
+    }
+
+    /// Run `CHECKPOINT` after each commit, so that the committed data is
+    /// written to the database file rather than left in the write-ahead log.
+    /// It pays off after large loads. A failed checkpoint is not reported as
+    /// the data is committed anyway.
+    pub fn checkpoint(self, checkpoint: bool) -> Self {
+        RunnerBuilder { checkpoint }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> Runner {
+        Runner {
+            checkpoint: self.checkpoint,
+        }
+    }
+
+    /// Build the runner rolling back every transaction, for tests
+    pub fn build_test(self) -> TestRunner {
+        TestRunner { runner: self.build() }
+    }
+}
+
+/// Runner of transactions configured by `RunnerBuilder`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Runner {
+    checkpoint: bool,
+}
+
+impl Runner {
+    /// Whether the database is checkpointed after each commit
+    pub fn checkpoints(&self) -> bool {
+        self.checkpoint
+    }
+
+    /// run the given function inside a transaction using the given
+    /// connection. Pass a reference to run the same transaction again.
+    ///
+    /// If the transaction panics, the transaction of duckdb-rs is rolled
+    /// back when dropped and the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// use duckdb::Connection;
+    /// use transaction::prelude::*;
+    /// use transaction_duckdb::{execute, execute_batch, query_row, Runner};
+    ///
+    /// # fn main() -> Result<(), transaction_duckdb::Error> {
+    /// let conn = Connection::open_in_memory()?;
+    /// let runner = Runner::default();
+    /// runner.run(&conn, execute_batch("CREATE TABLE sales (amount DOUBLE)"))?;
+    ///
+    /// let boom = execute("INSERT INTO sales VALUES (9.5)", vec![]).map(|_| -> () { panic!("boom") });
+    /// assert!(panic::catch_unwind(AssertUnwindSafe(|| runner.run(&conn, boom))).is_err());
+    /// let count = runner.run(&conn, query_row("SELECT COUNT(*) FROM sales", vec![], |row| row.get::<_, i64>(0)))?;
+    /// assert_eq!(count, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run<'a, T, E, Tx>(&self, conn: &'a Connection, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = DuckDbContext<'a>, Item = T, Err = E>,
+    {
+        hooks::instrument("duckdb", tx.label(), Outcome::of, || {
+            let mut ctx = self.begin(conn)?;
+            match tx.run(&mut ctx) {
+                Ok(t) => {
+                    ctx.tx.commit().map_err(Error::from)?;
+                    if self.checkpoint {
+                        if let Err(_e) = conn.execute_batch("CHECKPOINT") {
+                            #[cfg(feature = "log")]
+                            log::warn!("failed to checkpoint: {}", _e);
+                        }
+                    }
+                    Ok(t)
+                }
+                Err(e) => {
+                    let _ = ctx.tx.rollback();
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    /// run the given function like `run`, running the whole transaction
+    /// again while it fails with a retryable error and the policy allows.
+    /// Each retry is recorded by `metrics::record_retry`.
+    pub fn run_retry<'a, T, E, Tx, R>(&self, conn: &'a Connection, tx: Tx, policy: R) -> Result<T, E>
+    where
+        E: From<Error> + Retryable,
+        Tx: Transaction<Ctx = DuckDbContext<'a>, Item = T, Err = E>,
+        R: RetryPolicy,
+    {
+        let mut retries = 0;
+        loop {
+            let e = match self.run(conn, &tx) {
+                Err(e) => if e.is_retryable() { e } else { return Err(e) },
+                Ok(t) => return Ok(t),
+            };
+            let delay = match policy.next_delay(retries) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            #[cfg(feature = "log")]
+            log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+            metrics::record_retry(tx.label());
+            thread::sleep(delay);
+            retries += 1;
+        }
+    }
+
+    // begin a transaction on the connection
+    fn begin<'a>(&self, conn: &'a Connection) -> Result<DuckDbContext<'a>, Error> {
+        Ok(DuckDbContext::new(duckdb::Transaction::new_unchecked(conn)?))
+    }
+}
+
+/// Runner of transactions which are always rolled back, even when they
+/// succeed, for hermetic tests. The `Item` or the error of the transaction
+/// is returned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestRunner {
+    runner: Runner,
+}
+
+impl TestRunner {
+    /// run the given function inside a transaction using the given
+    /// connection, and roll it back.
+    pub fn run<'a, T, E, Tx>(&self, conn: &'a Connection, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = DuckDbContext<'a>, Item = T, Err = E>,
+    {
+        hooks::instrument("duckdb", tx.label(), |_| Outcome::RolledBack, || {
+            let mut ctx = self.runner.begin(conn)?;
+            let ret = tx.run(&mut ctx);
+            match ctx.tx.rollback() {
+                // a failure to roll back is reported since the test is no
+                // longer hermetic
+                Err(e) if ret.is_ok() => Err(Error::from(e).into()),
+                _ => ret,
+            }
+        })
+    }
+}
@@ -0,0 +1,222 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use duckdb::types::ToSql;
+use duckdb::{Connection, Row};
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{Error, DuckDbContext};
+
+/// The parameters of a statement, owned by the transaction
+pub type Params = Vec<Box<dyn ToSql + Send + Sync>>;
+
+struct Sql {
+    statement: String,
+    params: Params,
+}
+
+impl fmt::Debug for Sql {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sql")
+            .field("statement", &self.statement)
+            .field("params", &self.params.len())
+            .finish()
+    }
+}
+
+impl Sql {
+    fn new(statement: impl Into<String>, params: Params) -> Self {
+        Sql {
+            statement: statement.into(),
+            params,
+        }
+    }
+
+    fn params(&self) -> impl duckdb::Params + '_ {
+        duckdb::params_from_iter(self.params.iter())
+    }
+}
+
+/// Run the statement and return the number of the rows modified.
+pub fn execute<'a>(statement: impl Into<String>, params: Params) -> Execute<'a> {
+    Execute {
+        sql: Sql::new(statement, params),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `execute`
+#[derive(Debug)]
+#[must_use]
+pub struct Execute<'a> {
+    sql: Sql,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for Execute<'a> {
+    type Ctx = DuckDbContext<'a>;
+    type Item = usize;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut DuckDbContext<'a>) -> Result<Self::Item, Self::Err> {
+        let mut stmt = ctx.conn().prepare_cached(&self.sql.statement)?;
+        Ok(stmt.execute(self.sql.params())?)
+    }
+}
+
+impl<'a> Visit for Execute<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("execute"));
+    }
+}
+
+/// Run the statements separated by semicolons, without parameters.
+pub fn execute_batch<'a>(statements: impl Into<String>) -> ExecuteBatch<'a> {
+    ExecuteBatch {
+        statements: statements.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `execute_batch`
+#[derive(Debug)]
+#[must_use]
+pub struct ExecuteBatch<'a> {
+    statements: String,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for ExecuteBatch<'a> {
+    type Ctx = DuckDbContext<'a>;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut DuckDbContext<'a>) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.conn().execute_batch(&self.statements)?)
+    }
+}
+
+impl<'a> Visit for ExecuteBatch<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("execute_batch"));
+    }
+}
+
+/// Run the statement which returns at least one row and convert the first
+/// one by `f`. It fails when the statement returns no rows.
+pub fn query_row<'a, F, T>(statement: impl Into<String>, params: Params, f: F) -> QueryRow<'a, F>
+where
+    F: Fn(&Row) -> duckdb::Result<T>,
+{
+    QueryRow {
+        sql: Sql::new(statement, params),
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `query_row`
+#[derive(Debug)]
+#[must_use]
+pub struct QueryRow<'a, F> {
+    sql: Sql,
+    f: F,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, F, T> Transaction for QueryRow<'a, F>
+where
+    F: Fn(&Row) -> duckdb::Result<T>,
+{
+    type Ctx = DuckDbContext<'a>;
+    type Item = T;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut DuckDbContext<'a>) -> Result<Self::Item, Self::Err> {
+        let mut stmt = ctx.conn().prepare_cached(&self.sql.statement)?;
+        Ok(stmt.query_row(self.sql.params(), &self.f)?)
+    }
+}
+
+impl<'a, F> Visit for QueryRow<'a, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("query_row"));
+    }
+}
+
+/// Run the statement and convert each of the resulting rows by `f`.
+pub fn query_map<'a, F, T>(statement: impl Into<String>, params: Params, f: F) -> QueryMap<'a, F>
+where
+    F: Fn(&Row) -> duckdb::Result<T>,
+{
+    QueryMap {
+        sql: Sql::new(statement, params),
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `query_map`
+#[derive(Debug)]
+#[must_use]
+pub struct QueryMap<'a, F> {
+    sql: Sql,
+    f: F,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, F, T> Transaction for QueryMap<'a, F>
+where
+    F: Fn(&Row) -> duckdb::Result<T>,
+{
+    type Ctx = DuckDbContext<'a>;
+    type Item = Vec<T>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut DuckDbContext<'a>) -> Result<Self::Item, Self::Err> {
+        let mut stmt = ctx.conn().prepare_cached(&self.sql.statement)?;
+        let rows = stmt.query_map(self.sql.params(), &self.f)?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+}
+
+impl<'a, F> Visit for QueryMap<'a, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("query_map"));
+    }
+}
+
+/// Receive the connection from the executing transaction and perform computation.
+pub fn with_conn<'a, F, T, E>(f: F) -> WithConn<'a, F>
+where
+    F: Fn(&Connection) -> Result<T, E>,
+{
+    WithConn { f, _phantom: PhantomData }
+}
+
+/// The result of `with_conn`
+#[derive(Debug)]
+#[must_use]
+pub struct WithConn<'a, F> {
+    f: F,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, F, T, E> Transaction for WithConn<'a, F>
+where
+    F: Fn(&Connection) -> Result<T, E>,
+{
+    type Ctx = DuckDbContext<'a>;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut DuckDbContext<'a>) -> Result<Self::Item, Self::Err> {
+        (self.f)(ctx.conn())
+    }
+}
+
+impl<'a, F> Visit for WithConn<'a, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_conn"));
+    }
+}
@@ -0,0 +1,70 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of etcd transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The comparisons of the mini-transaction failed, i.e. a key read by the
+    /// transaction was written by another one
+    CompareFailed,
+    /// Any other error
+    Other,
+}
+
+/// An error of etcd, or a failed comparison
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: Option<etcd_client::Error>,
+}
+
+impl Error {
+    pub(crate) fn compare_failed() -> Self {
+        Error {
+            kind: ErrorKind::CompareFailed,
+            inner: None,
+        }
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of etcd, if the comparisons did not fail
+    pub fn get_ref(&self) -> Option<&etcd_client::Error> {
+        self.inner.as_ref()
+    }
+}
+
+impl From<etcd_client::Error> for Error {
+    fn from(inner: etcd_client::Error) -> Self {
+        Error {
+            kind: ErrorKind::Other,
+            inner: Some(inner),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner {
+            Some(ref e) => fmt::Display::fmt(e, f),
+            None => f.write_str("the comparisons of the transaction failed"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.as_ref().and_then(|e| e.source())
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind != ErrorKind::Other
+    }
+}
@@ -0,0 +1,256 @@
+//! Transaction backend for etcd
+//!
+//! A transaction of etcd is a single mini-transaction: a list of comparisons,
+//! the operations applied if they all hold and those applied otherwise.
+//! Asynchronous transactions running in an `EtcdContext` build one from the
+//! composed leaves:
+//!
+//! * `get` reads the key at once and adds the comparison of its modification
+//!   revision to the one read.
+//! * `put` and `delete` add the writes applied if the comparisons hold.
+//! * `compare_version` adds a comparison of the version of the key.
+//!
+//! The `Runner` sends the mini-transaction at the end. When the comparisons
+//! fail, the keys read are read again in the same request, and the
+//! transaction is run again on them, as the software transactional memory of
+//! the etcd client does. The transactions are thus serializable
+//! read-modify-write transactions.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use etcd_client::Client;
+//! use transaction::async_tx::AsyncTransactionExt;
+//! use transaction_etcd::{get, put, Runner};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), transaction_etcd::Error> {
+//!     let client = Client::connect(["localhost:2379"], None).await?;
+//!     let runner = Runner::new(client.kv_client()).max_retries(10);
+//!
+//!     let incr = get("hits")
+//!         .map(|v| v.map_or(0, |v| String::from_utf8(v).unwrap().parse::<u64>().unwrap()))
+//!         .and_then(|n| put("hits", (n + 1).to_string()).map(move |()| n + 1));
+//!     let hits = runner.run(&incr).await?;
+//!     println!("{} hits", hits);
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use etcd_client::{Compare, CompareOp, KvClient, TxnOp, TxnOpResponse};
+use transaction::async_tx::AsyncTransaction;
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+
+mod error;
+mod ops;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+// a key read by a transaction; an absent key has the modification
+// revision 0
+#[derive(Debug, Clone)]
+struct Read {
+    mod_revision: i64,
+    value: Option<Vec<u8>>,
+}
+
+/// The context of the transactions: the mini-transaction being built.
+pub struct EtcdContext {
+    kv: KvClient,
+    // the keys read again by the last failed attempt
+    prefetched: HashMap<Vec<u8>, Read>,
+    reads: BTreeMap<Vec<u8>, Read>,
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    compares: Vec<Compare>,
+}
+
+impl EtcdContext {
+    // never pub this function
+    fn new(kv: KvClient, prefetched: HashMap<Vec<u8>, Read>) -> Self {
+        EtcdContext {
+            kv,
+            prefetched,
+            reads: BTreeMap::new(),
+            writes: BTreeMap::new(),
+            compares: Vec::new(),
+        }
+    }
+
+    async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+        if let Some(read) = self.reads.get(key) {
+            return Ok(read.value.clone());
+        }
+        let read = match self.prefetched.remove(key) {
+            Some(read) => read,
+            None => {
+                let mut resp = self.kv.get(key, None).await?;
+                read_of(resp.take_kvs().into_iter().next())
+            }
+        };
+        let value = read.value.clone();
+        self.reads.insert(key.to_vec(), read);
+        Ok(value)
+    }
+
+    // send the mini-transaction, returning the keys read again if the
+    // comparisons failed
+    async fn commit(mut self) -> Result<Option<HashMap<Vec<u8>, Read>>, Error> {
+        if self.reads.is_empty() && self.writes.is_empty() && self.compares.is_empty() {
+            return Ok(None);
+        }
+        let mut compares = self.compares;
+        let mut reread = Vec::with_capacity(self.reads.len());
+        for (key, read) in self.reads {
+            compares.push(Compare::mod_revision(key.clone(), CompareOp::Equal, read.mod_revision));
+            reread.push(key);
+        }
+        let writes = self
+            .writes
+            .into_iter()
+            .map(|(key, value)| match value {
+                Some(value) => TxnOp::put(key, value, None),
+                None => TxnOp::delete(key, None),
+            })
+            .collect::<Vec<_>>();
+        let gets = reread.iter().map(|key| TxnOp::get(key.clone(), None)).collect::<Vec<_>>();
+        let txn = etcd_client::Txn::new().when(compares).and_then(writes).or_else(gets);
+        let resp = self.kv.txn(txn).await?;
+        if resp.succeeded() {
+            return Ok(None);
+        }
+        let prefetched = reread
+            .into_iter()
+            .zip(resp.op_responses())
+            .filter_map(|(key, resp)| match resp {
+                TxnOpResponse::Get(mut resp) => Some((key, read_of(resp.take_kvs().into_iter().next()))),
+                _ => None,
+            })
+            .collect();
+        Ok(Some(prefetched))
+    }
+}
+
+fn read_of(kv: Option<etcd_client::KeyValue>) -> Read {
+    match kv {
+        Some(kv) => Read {
+            mod_revision: kv.mod_revision(),
+            value: Some(kv.into_key_value().1),
+        },
+        None => Read {
+            mod_revision: 0,
+            value: None,
+        },
+    }
+}
+
+impl fmt::Debug for EtcdContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EtcdContext")
+            .field("reads", &self.reads.len())
+            .field("writes", &self.writes.len())
+            .field("compares", &self.compares.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runner of optimistic read-modify-write transactions on etcd
+#[derive(Clone)]
+pub struct Runner {
+    kv: KvClient,
+    max_retries: Option<u32>,
+}
+
+impl Runner {
+    /// Run the transactions on the client, retrying them as long as the
+    /// comparisons fail
+    pub fn new(kv: KvClient) -> Self {
+        Runner { kv, max_retries: None }
+    }
+
+    /// Limit the number of the retries of a transaction. The transactions
+    /// comparing the keys they don't read, e.g. by `compare_version`, may
+    /// fail every time and should be limited.
+    pub fn max_retries(self, max_retries: u32) -> Self {
+        Runner {
+            max_retries: Some(max_retries),
+            ..self
+        }
+    }
+
+    /// The client
+    pub fn kv_client(&self) -> &KvClient {
+        &self.kv
+    }
+
+    /// run the given transaction and send the mini-transaction built by it.
+    /// When the comparisons fail, it is run again on the keys read again,
+    /// and fails with `ErrorKind::CompareFailed` once the retries run out.
+    /// Each retry is recorded by `metrics::record_retry`.
+    ///
+    /// If the transaction panics, the writes buffered are dropped without
+    /// being sent, and the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::panic::AssertUnwindSafe;
+    ///
+    /// use etcd_client::Client;
+    /// use futures::FutureExt;
+    /// use transaction::async_tx::AsyncTransactionExt;
+    /// use transaction_etcd::{get, put, Runner};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() -> Result<(), transaction_etcd::Error> {
+    ///     let client = Client::connect(["localhost:2379"], None).await?;
+    ///     let runner = Runner::new(client.kv_client());
+    ///
+    ///     let before = runner.run(get("hits")).await?;
+    ///     let boom = put("hits", "0").map(|()| -> () { panic!("boom") });
+    ///     assert!(AssertUnwindSafe(runner.run(boom)).catch_unwind().await.is_err());
+    ///     assert_eq!(runner.run(get("hits")).await?, before);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn run<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = EtcdContext>,
+        Tx::Err: From<Error>,
+    {
+        hooks::instrument_async("etcd", tx.label(), Outcome::of, async {
+            let mut prefetched = HashMap::new();
+            let mut retries = 0;
+            loop {
+                let mut ctx = EtcdContext::new(self.kv.clone(), prefetched);
+                let item = tx.run_async(&mut ctx).await?;
+                prefetched = match ctx.commit().await? {
+                    None => return Ok(item),
+                    Some(prefetched) => prefetched,
+                };
+                if self.max_retries.is_some_and(|max| retries >= max) {
+                    return Err(Error::compare_failed().into());
+                }
+                retries += 1;
+                #[cfg(feature = "log")]
+                log::debug!("comparisons failed, retrying");
+                metrics::record_retry(tx.label());
+            }
+        }).await
+    }
+}
+
+impl fmt::Debug for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Runner")
+            .field("max_retries", &self.max_retries)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,148 @@
+use etcd_client::{Compare, CompareOp};
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::{EtcdContext, Error};
+
+/// Get the value of the key. The key is compared at commit to have not
+/// been modified since the read, and the writes of the transaction to it
+/// are visible.
+pub fn get<K>(key: K) -> Get
+where
+    K: Into<Vec<u8>>,
+{
+    Get { key: key.into() }
+}
+
+/// The result of `get`
+#[derive(Debug)]
+#[must_use]
+pub struct Get {
+    key: Vec<u8>,
+}
+
+impl AsyncTransaction for Get {
+    type Ctx = EtcdContext;
+    type Item = Option<Vec<u8>>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(ctx.get(&self.key))
+    }
+}
+
+impl Visit for Get {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get"));
+    }
+}
+
+/// Set the value of the key. The write is buffered until the commit.
+pub fn put<K, V>(key: K, value: V) -> Put
+where
+    K: Into<Vec<u8>>,
+    V: Into<Vec<u8>>,
+{
+    Put {
+        key: key.into(),
+        value: value.into(),
+    }
+}
+
+/// The result of `put`
+#[derive(Debug)]
+#[must_use]
+pub struct Put {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl AsyncTransaction for Put {
+    type Ctx = EtcdContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.writes.insert(self.key.clone(), Some(self.value.clone()));
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for Put {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("put"));
+    }
+}
+
+/// Delete the key. The write is buffered until the commit.
+pub fn delete<K>(key: K) -> Delete
+where
+    K: Into<Vec<u8>>,
+{
+    Delete { key: key.into() }
+}
+
+/// The result of `delete`
+#[derive(Debug)]
+#[must_use]
+pub struct Delete {
+    key: Vec<u8>,
+}
+
+impl AsyncTransaction for Delete {
+    type Ctx = EtcdContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.writes.insert(self.key.clone(), None);
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for Delete {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete"));
+    }
+}
+
+/// Compare the version of the key at commit, e.g.
+/// `compare_version("lock", CompareOp::Equal, 0)` to commit only if the key
+/// does not exist. The version of a key is the number of its modifications
+/// since its creation, and 0 if it does not exist.
+pub fn compare_version<K>(key: K, op: CompareOp, version: i64) -> CompareVersion
+where
+    K: Into<Vec<u8>>,
+{
+    CompareVersion {
+        key: key.into(),
+        op,
+        version,
+    }
+}
+
+/// The result of `compare_version`
+#[derive(Debug)]
+#[must_use]
+pub struct CompareVersion {
+    key: Vec<u8>,
+    op: CompareOp,
+    version: i64,
+}
+
+impl AsyncTransaction for CompareVersion {
+    type Ctx = EtcdContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.compares.push(Compare::version(self.key.clone(), self.op, self.version));
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for CompareVersion {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("compare_version"));
+    }
+}
@@ -0,0 +1,62 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+use crate::ExpectedVersion;
+
+/// An error of the event store `E`
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The stream was not at the expected version, since another writer
+    /// appended to it meanwhile
+    WrongExpectedVersion {
+        /// The stream
+        stream: String,
+        /// The version the append expected
+        expected: ExpectedVersion,
+        /// The version of the stream
+        actual: u64,
+    },
+    /// The store failed
+    Store(E),
+}
+
+impl<E> fmt::Display for Error<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WrongExpectedVersion {
+                ref stream,
+                ref expected,
+                actual,
+            } => write!(f, "stream `{}` expected at {:?} is at version {}", stream, expected, actual),
+            Error::Store(ref e) => write!(f, "event store failed: {}", e),
+        }
+    }
+}
+
+impl<E> error::Error for Error<E>
+where
+    E: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::WrongExpectedVersion { .. } => None,
+            Error::Store(ref e) => Some(e),
+        }
+    }
+}
+
+/// `WrongExpectedVersion` is retryable: run again, the transaction reads the
+/// streams at their current versions.
+impl<E> Retryable for Error<E> {
+    fn is_retryable(&self) -> bool {
+        match *self {
+            Error::WrongExpectedVersion { .. } => true,
+            Error::Store(_) => false,
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Event sourcing transactions on streams of events
+//!
+//! A transaction runs in an `EventContext`: it reads the streams of its
+//! aggregates by `read_stream`, decides, and appends the new events by
+//! `append`, expecting the streams at the versions it read. The appends are
+//! queued and applied all together when the transaction succeeds. If another
+//! writer has appended to a stream meanwhile, they are rejected with
+//! `Error::WrongExpectedVersion`, and `run_retry` runs the transaction again
+//! on the current streams.
+//!
+//! Stores are plugged in by implementing `EventStore`. `MemoryStore` keeps
+//! the streams in memory.
+//!
+//! # Examples
+//!
+//! ```
+//! use transaction::prelude::*;
+//! use transaction::Backoff;
+//! use transaction_eventstore::{append, read_stream, run_retry, Event, ExpectedVersion, MemoryStore};
+//!
+//! // replay the events of an account into its balance
+//! fn balance(events: &[transaction_eventstore::RecordedEvent]) -> i64 {
+//!     events
+//!         .iter()
+//!         .map(|e| {
+//!             let amount = String::from_utf8_lossy(&e.event.data).parse::<i64>().unwrap();
+//!             match e.event.event_type.as_str() {
+//!                 "Deposited" => amount,
+//!                 "Withdrawn" => -amount,
+//!                 _ => 0,
+//!             }
+//!         })
+//!         .sum()
+//! }
+//!
+//! let store = MemoryStore::new();
+//! let withdraw = |amount: i64| {
+//!     read_stream("account-1").and_then(move |events| {
+//!         let version = ExpectedVersion::Exact(events.len() as u64);
+//!         let event = if balance(&events) < amount {
+//!             Event::new("Rejected", amount.to_string())
+//!         } else {
+//!             Event::new("Withdrawn", amount.to_string())
+//!         };
+//!         append("account-1", version, vec![event])
+//!     })
+//! };
+//! let deposit = append("account-1", ExpectedVersion::Exact(0), vec![Event::new("Deposited", "100")]);
+//!
+//! run_retry(&store, deposit, Backoff::immediate()).unwrap();
+//! run_retry(&store, withdraw(30), Backoff::immediate()).unwrap();
+//! run_retry(&store, withdraw(80), Backoff::immediate()).unwrap();
+//! let events = run_retry(&store, read_stream("account-1"), Backoff::immediate()).unwrap();
+//! assert_eq!(balance(&events), 70);
+//! assert_eq!(events[2].event.event_type, "Rejected");
+//! ```
+
+use std::fmt;
+use std::thread;
+
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+use transaction::{RetryPolicy, Retryable, Transaction};
+
+mod error;
+mod store;
+mod stream;
+
+pub use crate::error::*;
+pub use crate::store::*;
+pub use crate::stream::*;
+
+/// The context of the transactions: the store and the appends queued for
+/// the commit.
+pub struct EventContext<'a, S> {
+    store: &'a S,
+    appends: Vec<Append>,
+}
+
+impl<'a, S> EventContext<'a, S> {
+    // never pub this function
+    fn new(store: &'a S) -> Self {
+        EventContext {
+            store,
+            appends: Vec::new(),
+        }
+    }
+
+    /// The store
+    pub fn store(&self) -> &'a S {
+        self.store
+    }
+}
+
+impl<'a, S> fmt::Debug for EventContext<'a, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventContext")
+            .field("appends", &self.appends)
+            .finish_non_exhaustive()
+    }
+}
+
+/// run the given transaction on the store, appending the queued events if it
+/// succeeds and discarding them otherwise. Pass a reference to run the same
+/// transaction again.
+///
+/// If the transaction panics, the queued events are discarded and the panic
+/// is resumed.
+///
+/// # Examples
+///
+/// ```
+/// use std::panic::{self, AssertUnwindSafe};
+/// use transaction::prelude::*;
+/// use transaction_eventstore::{append, run, Event, EventStore, ExpectedVersion, MemoryStore};
+///
+/// let store = MemoryStore::new();
+/// let boom = append("account-1", ExpectedVersion::Any, vec![Event::new("Deposited", "100")])
+///     .map(|_| -> () { panic!("boom") });
+/// assert!(panic::catch_unwind(AssertUnwindSafe(|| run(&store, boom))).is_err());
+/// assert!(store.read("account-1").unwrap().is_empty());
+/// ```
+pub fn run<'a, S, T, E, Tx>(store: &'a S, tx: Tx) -> Result<T, E>
+where
+    S: EventStore,
+    E: From<Error<S::Error>>,
+    Tx: Transaction<Ctx = EventContext<'a, S>, Item = T, Err = E>,
+{
+    hooks::instrument("eventstore", tx.label(), Outcome::of, || {
+        let mut ctx = EventContext::new(store);
+        let t = tx.run(&mut ctx)?;
+        if !ctx.appends.is_empty() {
+            store.append(&ctx.appends)?;
+        }
+        Ok(t)
+    })
+}
+
+/// run the given transaction like `run`, running it again while it fails
+/// with a retryable error, e.g. `Error::WrongExpectedVersion`, and the
+/// policy allows. Each retry is recorded by `metrics::record_retry`.
+///
+/// # Examples
+///
+/// ```
+/// use std::cell::Cell;
+/// use transaction::prelude::*;
+/// use transaction::Backoff;
+/// use transaction_eventstore::*;
+///
+/// let store = MemoryStore::new();
+/// let racing = Cell::new(true);
+/// let tx = read_stream("order-1").and_then(|events| {
+///     // another writer appends after the first read
+///     let race = with_ctx(|ctx: &mut EventContext<MemoryStore>| {
+///         if racing.replace(false) {
+///             let placed = Append {
+///                 stream: "order-1".to_string(),
+///                 expected: ExpectedVersion::Any,
+///                 events: vec![Event::new("Placed", "")],
+///             };
+///             ctx.store().append(&[placed])?;
+///         }
+///         Ok(())
+///     });
+///     let version = ExpectedVersion::Exact(events.len() as u64);
+///     race.and_then(move |_| append("order-1", version, vec![Event::new("Shipped", "")]))
+/// });
+///
+/// run_retry(&store, tx, Backoff::immediate()).unwrap();
+/// let events = store.read("order-1").unwrap();
+/// assert_eq!(events[0].event.event_type, "Placed");
+/// assert_eq!(events[1].event.event_type, "Shipped");
+/// ```
+pub fn run_retry<'a, S, T, E, Tx, R>(store: &'a S, tx: Tx, policy: R) -> Result<T, E>
+where
+    S: EventStore,
+    E: From<Error<S::Error>> + Retryable,
+    Tx: Transaction<Ctx = EventContext<'a, S>, Item = T, Err = E>,
+    R: RetryPolicy,
+{
+    let mut retries = 0;
+    loop {
+        let e = match run(store, &tx) {
+            Err(e) => if e.is_retryable() { e } else { return Err(e) },
+            Ok(t) => return Ok(t),
+        };
+        let delay = match policy.next_delay(retries) {
+            Some(delay) => delay,
+            None => return Err(e),
+        };
+        #[cfg(feature = "log")]
+        log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+        metrics::record_retry(tx.label());
+        thread::sleep(delay);
+        retries += 1;
+    }
+}
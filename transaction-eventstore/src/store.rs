@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+use crate::Error;
+
+/// An event to append
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Event {
+    /// The type of the event, e.g. `Deposited`
+    pub event_type: String,
+    /// The serialized event
+    pub data: Vec<u8>,
+}
+
+impl Event {
+    /// The event of the type with the data
+    pub fn new(event_type: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        Event {
+            event_type: event_type.into(),
+            data: data.into(),
+        }
+    }
+}
+
+/// An event appended to a stream
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecordedEvent {
+    /// The stream
+    pub stream: String,
+    /// The position of the event in the stream, from 0
+    pub revision: u64,
+    /// The event
+    pub event: Event,
+}
+
+/// The version a stream is expected at by an append. The version of a
+/// stream is the number of its events, so that a new stream is at 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExpectedVersion {
+    /// Append whatever the version is
+    Any,
+    /// Append only if the stream is at the version
+    Exact(u64),
+}
+
+impl ExpectedVersion {
+    /// Whether a stream at the version is expected
+    pub fn matches(self, version: u64) -> bool {
+        match self {
+            ExpectedVersion::Any => true,
+            ExpectedVersion::Exact(expected) => expected == version,
+        }
+    }
+}
+
+/// The events to append to a stream expected at a version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Append {
+    /// The stream
+    pub stream: String,
+    /// The version the stream is expected at
+    pub expected: ExpectedVersion,
+    /// The events to append
+    pub events: Vec<Event>,
+}
+
+/// Stores of the streams of events, e.g. EventStoreDB or a table of events.
+pub trait EventStore {
+    /// The error of the store
+    type Error;
+
+    /// The events of the stream in order, which are none for a new stream
+    fn read(&self, stream: &str) -> Result<Vec<RecordedEvent>, Self::Error>;
+
+    /// Append all the events, checking the expected version of each append
+    /// against the versions including the earlier appends. Either all the
+    /// events are appended or, failing with `Error::WrongExpectedVersion` or
+    /// otherwise, none of them. Stores which can't append to several streams
+    /// atomically should fail for such appends.
+    fn append(&self, appends: &[Append]) -> Result<(), Error<Self::Error>>;
+}
+
+impl<S> EventStore for &S
+where
+    S: ?Sized + EventStore,
+{
+    type Error = S::Error;
+
+    fn read(&self, stream: &str) -> Result<Vec<RecordedEvent>, Self::Error> {
+        (**self).read(stream)
+    }
+
+    fn append(&self, appends: &[Append]) -> Result<(), Error<Self::Error>> {
+        (**self).append(appends)
+    }
+}
+
+/// An `EventStore` keeping the streams in memory, e.g. for tests
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    streams: Mutex<HashMap<String, Vec<RecordedEvent>>>,
+}
+
+impl MemoryStore {
+    /// An empty store
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl EventStore for MemoryStore {
+    type Error = Infallible;
+
+    fn read(&self, stream: &str) -> Result<Vec<RecordedEvent>, Infallible> {
+        let streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(streams.get(stream).cloned().unwrap_or_default())
+    }
+
+    fn append(&self, appends: &[Append]) -> Result<(), Error<Infallible>> {
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        // check all the appends before applying any
+        let mut versions = HashMap::new();
+        for append in appends {
+            let version = versions
+                .entry(append.stream.as_str())
+                .or_insert_with(|| streams.get(&append.stream).map_or(0, |s| s.len() as u64));
+            if !append.expected.matches(*version) {
+                return Err(Error::WrongExpectedVersion {
+                    stream: append.stream.clone(),
+                    expected: append.expected,
+                    actual: *version,
+                });
+            }
+            *version += append.events.len() as u64;
+        }
+        for append in appends {
+            let stream = streams.entry(append.stream.clone()).or_default();
+            for event in &append.events {
+                let revision = stream.len() as u64;
+                stream.push(RecordedEvent {
+                    stream: append.stream.clone(),
+                    revision,
+                    event: event.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
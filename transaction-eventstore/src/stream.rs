@@ -0,0 +1,98 @@
+use std::marker::PhantomData;
+
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{Append, Error, Event, EventContext, EventStore, ExpectedVersion, RecordedEvent};
+
+/// Read the events of the stream, including those appended earlier in the
+/// transaction. The number of the events read is the version to expect.
+pub fn read_stream<'a, S>(stream: impl Into<String>) -> ReadStream<'a, S>
+where
+    S: EventStore,
+{
+    ReadStream {
+        stream: stream.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `read_stream`
+#[derive(Debug)]
+#[must_use]
+pub struct ReadStream<'a, S> {
+    stream: String,
+    _phantom: PhantomData<fn(&'a S)>,
+}
+
+impl<'a, S> Transaction for ReadStream<'a, S>
+where
+    S: EventStore,
+{
+    type Ctx = EventContext<'a, S>;
+    type Item = Vec<RecordedEvent>;
+    type Err = Error<S::Error>;
+
+    fn run(&self, ctx: &mut EventContext<'a, S>) -> Result<Self::Item, Self::Err> {
+        let mut events = ctx.store.read(&self.stream).map_err(Error::Store)?;
+        let pending = ctx.appends.iter().filter(|a| a.stream == self.stream);
+        for event in pending.flat_map(|a| &a.events) {
+            events.push(RecordedEvent {
+                stream: self.stream.clone(),
+                revision: events.len() as u64,
+                event: event.clone(),
+            });
+        }
+        Ok(events)
+    }
+}
+
+impl<'a, S> Visit for ReadStream<'a, S> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("read_stream"));
+    }
+}
+
+/// Append the events to the stream expected at the version when the
+/// transaction succeeds. The transaction fails with
+/// `Error::WrongExpectedVersion` if the stream is at another version then.
+pub fn append<'a, S>(stream: impl Into<String>, expected: ExpectedVersion, events: Vec<Event>) -> AppendTo<'a, S>
+where
+    S: EventStore,
+{
+    AppendTo {
+        append: Append {
+            stream: stream.into(),
+            expected,
+            events,
+        },
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `append`
+#[derive(Debug)]
+#[must_use]
+pub struct AppendTo<'a, S> {
+    append: Append,
+    _phantom: PhantomData<fn(&'a S)>,
+}
+
+impl<'a, S> Transaction for AppendTo<'a, S>
+where
+    S: EventStore,
+{
+    type Ctx = EventContext<'a, S>;
+    type Item = ();
+    type Err = Error<S::Error>;
+
+    fn run(&self, ctx: &mut EventContext<'a, S>) -> Result<Self::Item, Self::Err> {
+        ctx.appends.push(self.append.clone());
+        Ok(())
+    }
+}
+
+impl<'a, S> Visit for AppendTo<'a, S> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("append"));
+    }
+}
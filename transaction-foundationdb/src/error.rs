@@ -0,0 +1,95 @@
+use std::error;
+use std::fmt;
+
+use foundationdb::FdbError;
+use transaction::Retryable;
+
+/// The classification of the errors of FoundationDB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The transaction conflicted with another one (`not_committed`, 1020)
+    Conflict,
+    /// The transaction may or may not have been committed, e.g. the
+    /// connection was lost during the commit
+    MaybeCommitted,
+    /// Any other error which can be retried without committing twice, e.g.
+    /// a too old read version
+    Transient,
+    /// Any other error
+    Other,
+}
+
+/// An error of FoundationDB together with its classification
+#[derive(Debug, Clone, Copy)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: FdbError,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of FoundationDB
+    pub fn get_ref(&self) -> &FdbError {
+        &self.inner
+    }
+
+    /// Unwrap the error of FoundationDB
+    pub fn into_inner(self) -> FdbError {
+        self.inner
+    }
+}
+
+impl From<FdbError> for Error {
+    fn from(inner: FdbError) -> Self {
+        let kind = if inner.code() == 1020 {
+            ErrorKind::Conflict
+        } else if inner.is_maybe_committed() {
+            ErrorKind::MaybeCommitted
+        } else if inner.is_retryable_not_committed() {
+            ErrorKind::Transient
+        } else {
+            ErrorKind::Other
+        };
+        Error { kind, inner }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        match self.kind {
+            ErrorKind::Conflict | ErrorKind::Transient => true,
+            ErrorKind::MaybeCommitted | ErrorKind::Other => false,
+        }
+    }
+}
+
+/// The errors of transactions which may come from FoundationDB. The runner
+/// hands them to `on_error` to decide whether to retry.
+pub trait AsFdbError {
+    /// The error of FoundationDB, if this is one
+    fn as_fdb_error(&self) -> Option<FdbError>;
+}
+
+impl AsFdbError for Error {
+    fn as_fdb_error(&self) -> Option<FdbError> {
+        Some(self.inner)
+    }
+}
+
+impl AsFdbError for FdbError {
+    fn as_fdb_error(&self) -> Option<FdbError> {
+        Some(*self)
+    }
+}
@@ -0,0 +1,240 @@
+//! Transaction backend for FoundationDB
+//!
+//! Asynchronous transactions run in an `FdbContext` wrapping a transaction of
+//! FoundationDB. They compose the reads and writes, and the `Runner` commits
+//! them following the standard retry loop: when the transaction or its commit
+//! fails with an error of FoundationDB, the error is handed to `on_error`,
+//! which waits with backoff and resets the transaction if the error is
+//! retryable, and the transaction is run again.
+//!
+//! The leaves `get`, `set`, `clear`, `clear_range`, `atomic_op` and `watch`
+//! expose the operations of FoundationDB.
+//!
+//! The network of FoundationDB must be started by `foundationdb::boot` before
+//! running transactions.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use foundationdb::Database;
+//! use transaction::async_tx::AsyncTransactionExt;
+//! use transaction_foundationdb::{get, set, RunnerBuilder};
+//!
+//! # fn main() -> Result<(), transaction_foundationdb::Error> {
+//! let _network = unsafe { foundationdb::boot() };
+//! let db = Database::default()?;
+//! let runner = RunnerBuilder::new().retry_limit(10).build();
+//!
+//! let incr = get("hits")
+//!     .map(|v| v.map_or(0, |v| String::from_utf8(v).unwrap().parse::<u64>().unwrap()))
+//!     .and_then(|n| set("hits", (n + 1).to_string()).map(move |()| n + 1));
+//! let hits = futures::executor::block_on(runner.run(&db, &incr))?;
+//! println!("{} hits", hits);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use foundationdb::options::TransactionOption;
+use foundationdb::{Database, FdbError, FdbResult, Transaction};
+use transaction::async_tx::AsyncTransaction;
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+
+mod error;
+mod ops;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+/// The context of the transactions: a transaction of FoundationDB.
+pub struct FdbContext {
+    tr: Transaction,
+}
+
+impl FdbContext {
+    // never pub this function
+    fn new(tr: Transaction) -> Self {
+        FdbContext { tr }
+    }
+
+    /// The transaction of FoundationDB
+    pub fn transaction(&self) -> &Transaction {
+        &self.tr
+    }
+}
+
+impl fmt::Debug for FdbContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FdbContext").finish_non_exhaustive()
+    }
+}
+
+/// Builder of a `Runner`, e.g. `RunnerBuilder::new().retry_limit(10).build()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunnerBuilder {
+    retry_limit: Option<u32>,
+    timeout: Option<Duration>,
+    idempotent: bool,
+}
+
+impl RunnerBuilder {
+    /// Retry the transactions as long as `on_error` allows, without a retry
+    /// limit and timeout, and don't retry the transactions which may have
+    /// been committed
+    pub fn new() -> Self {
+        RunnerBuilder::default()
+    }
+
+    /// Limit the number of the retries of a transaction
+    pub fn retry_limit(self, retry_limit: u32) -> Self {
+        RunnerBuilder {
+            retry_limit: Some(retry_limit),
+            ..self
+        }
+    }
+
+    /// Limit the time a transaction takes, including the retries
+    pub fn timeout(self, timeout: Duration) -> Self {
+        RunnerBuilder {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Declare that the transactions can be applied twice safely, so that
+    /// the commits with an unknown result are retried too. Otherwise they
+    /// fail with `ErrorKind::MaybeCommitted`.
+    pub fn idempotent(self, idempotent: bool) -> Self {
+        RunnerBuilder { idempotent, ..self }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> Runner {
+        Runner {
+            retry_limit: self.retry_limit,
+            timeout: self.timeout,
+            idempotent: self.idempotent,
+        }
+    }
+}
+
+/// Runner of transactions configured by `RunnerBuilder`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Runner {
+    retry_limit: Option<u32>,
+    timeout: Option<Duration>,
+    idempotent: bool,
+}
+
+impl Runner {
+    /// The number of the retries of a transaction, if limited
+    pub fn retry_limit(&self) -> Option<u32> {
+        self.retry_limit
+    }
+
+    /// The time a transaction takes, if limited
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Whether the commits with an unknown result are retried
+    pub fn is_idempotent(&self) -> bool {
+        self.idempotent
+    }
+
+    /// run the given transaction on the database, committing it and retrying
+    /// it on the errors of FoundationDB as `on_error` decides. The errors
+    /// which are not those of FoundationDB are returned as is. Each retry is
+    /// recorded by `metrics::record_retry`.
+    ///
+    /// If the transaction panics, the transaction of FoundationDB is
+    /// cancelled as it is dropped, so its writes are never sent, and the
+    /// panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::panic::AssertUnwindSafe;
+    ///
+    /// use foundationdb::Database;
+    /// use futures::FutureExt;
+    /// use transaction::async_tx::AsyncTransactionExt;
+    /// use transaction_foundationdb::{get, set, RunnerBuilder};
+    ///
+    /// # fn main() -> Result<(), transaction_foundationdb::Error> {
+    /// let _network = unsafe { foundationdb::boot() };
+    /// let db = Database::default()?;
+    /// let runner = RunnerBuilder::new().build();
+    ///
+    /// futures::executor::block_on(async {
+    ///     let before = runner.run(&db, get("hits")).await?;
+    ///     let boom = set("hits", "0").map(|()| -> () { panic!("boom") });
+    ///     assert!(AssertUnwindSafe(runner.run(&db, boom)).catch_unwind().await.is_err());
+    ///     assert_eq!(runner.run(&db, get("hits")).await?, before);
+    ///     Ok::<_, transaction_foundationdb::Error>(())
+    /// })
+    /// # }
+    /// ```
+    pub async fn run<Tx>(&self, db: &Database, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = FdbContext>,
+        Tx::Err: From<Error> + AsFdbError,
+    {
+        hooks::instrument_async("foundationdb", tx.label(), Outcome::of, async {
+            let mut tr = self.begin(db).map_err(Error::from)?;
+            loop {
+                let mut ctx = FdbContext::new(tr);
+                let ret = tx.run_async(&mut ctx).await;
+                let FdbContext { tr: done } = ctx;
+                // `on_error` waits and resets the transaction, or fails with
+                // the error if it is not retryable or the limits are exceeded
+                let (e, retried) = match ret {
+                    Ok(item) => match done.commit().await {
+                        Ok(_) => return Ok(item),
+                        Err(commit_error) => {
+                            let e = Tx::Err::from(Error::from(*commit_error));
+                            if !self.retries(*commit_error) {
+                                return Err(e);
+                            }
+                            (e, commit_error.on_error().await)
+                        }
+                    },
+                    Err(e) => match e.as_fdb_error() {
+                        Some(fdb_error) if self.retries(fdb_error) => {
+                            let retried = done.on_error(fdb_error).await;
+                            (e, retried)
+                        }
+                        _ => return Err(e),
+                    },
+                };
+                tr = match retried {
+                    Ok(tr) => tr,
+                    Err(_) => return Err(e),
+                };
+                #[cfg(feature = "log")]
+                log::debug!("transaction failed, retrying");
+                metrics::record_retry(tx.label());
+            }
+        }).await
+    }
+
+    // the transactions which may have been committed are retried only if
+    // they are idempotent
+    fn retries(&self, e: FdbError) -> bool {
+        self.idempotent || !e.is_maybe_committed()
+    }
+
+    fn begin(&self, db: &Database) -> FdbResult<Transaction> {
+        let tr = db.create_trx()?;
+        if let Some(retry_limit) = self.retry_limit {
+            tr.set_option(TransactionOption::RetryLimit(retry_limit as i32))?;
+        }
+        if let Some(timeout) = self.timeout {
+            tr.set_option(TransactionOption::Timeout(timeout.as_millis() as i32))?;
+        }
+        Ok(tr)
+    }
+}
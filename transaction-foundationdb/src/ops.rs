@@ -0,0 +1,241 @@
+use foundationdb::options::MutationType;
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::{Error, FdbContext};
+
+/// Get the value of the key. The read adds the key to the read conflict
+/// range, so the transaction conflicts with the writes to it made before the
+/// commit.
+pub fn get<K>(key: K) -> Get
+where
+    K: Into<Vec<u8>>,
+{
+    Get {
+        key: key.into(),
+        snapshot: false,
+    }
+}
+
+/// Get the value of the key without adding it to the read conflict range.
+pub fn snapshot_get<K>(key: K) -> Get
+where
+    K: Into<Vec<u8>>,
+{
+    Get {
+        key: key.into(),
+        snapshot: true,
+    }
+}
+
+/// The result of `get` and `snapshot_get`
+#[derive(Debug)]
+#[must_use]
+pub struct Get {
+    key: Vec<u8>,
+    snapshot: bool,
+}
+
+impl AsyncTransaction for Get {
+    type Ctx = FdbContext;
+    type Item = Option<Vec<u8>>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let value = ctx.transaction().get(&self.key, self.snapshot).await?;
+            Ok(value.map(|value| value.to_vec()))
+        })
+    }
+}
+
+impl Visit for Get {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new(if self.snapshot { "snapshot_get" } else { "get" }));
+    }
+}
+
+/// Set the value of the key.
+pub fn set<K, V>(key: K, value: V) -> Set
+where
+    K: Into<Vec<u8>>,
+    V: Into<Vec<u8>>,
+{
+    Set {
+        key: key.into(),
+        value: value.into(),
+    }
+}
+
+/// The result of `set`
+#[derive(Debug)]
+#[must_use]
+pub struct Set {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl AsyncTransaction for Set {
+    type Ctx = FdbContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.transaction().set(&self.key, &self.value);
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for Set {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("set"));
+    }
+}
+
+/// Clear the key.
+pub fn clear<K>(key: K) -> Clear
+where
+    K: Into<Vec<u8>>,
+{
+    Clear { key: key.into() }
+}
+
+/// The result of `clear`
+#[derive(Debug)]
+#[must_use]
+pub struct Clear {
+    key: Vec<u8>,
+}
+
+impl AsyncTransaction for Clear {
+    type Ctx = FdbContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.transaction().clear(&self.key);
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for Clear {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("clear"));
+    }
+}
+
+/// Clear the keys from `begin`, inclusive, to `end`, exclusive.
+pub fn clear_range<B, E>(begin: B, end: E) -> ClearRange
+where
+    B: Into<Vec<u8>>,
+    E: Into<Vec<u8>>,
+{
+    ClearRange {
+        begin: begin.into(),
+        end: end.into(),
+    }
+}
+
+/// The result of `clear_range`
+#[derive(Debug)]
+#[must_use]
+pub struct ClearRange {
+    begin: Vec<u8>,
+    end: Vec<u8>,
+}
+
+impl AsyncTransaction for ClearRange {
+    type Ctx = FdbContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.transaction().clear_range(&self.begin, &self.end);
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for ClearRange {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("clear_range"));
+    }
+}
+
+/// Apply the atomic operation with the parameter to the value of the key,
+/// e.g. `atomic_op("hits", 1u64.to_le_bytes(), MutationType::Add)`. It does
+/// not read the key, so it does not make the transaction conflict.
+pub fn atomic_op<K, P>(key: K, param: P, op: MutationType) -> AtomicOp
+where
+    K: Into<Vec<u8>>,
+    P: Into<Vec<u8>>,
+{
+    AtomicOp {
+        key: key.into(),
+        param: param.into(),
+        op,
+    }
+}
+
+/// The result of `atomic_op`
+#[derive(Debug)]
+#[must_use]
+pub struct AtomicOp {
+    key: Vec<u8>,
+    param: Vec<u8>,
+    op: MutationType,
+}
+
+impl AsyncTransaction for AtomicOp {
+    type Ctx = FdbContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.transaction().atomic_op(&self.key, &self.param, self.op);
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for AtomicOp {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("atomic_op"));
+    }
+}
+
+/// A future resolving when the watched key changes
+pub type Watch = BoxFuture<'static, Result<(), Error>>;
+
+/// Watch the key. The returned future starts watching when the transaction
+/// commits and resolves when the value of the key changes from the one
+/// committed. It fails if the transaction does not commit.
+pub fn watch<K>(key: K) -> WatchKey
+where
+    K: Into<Vec<u8>>,
+{
+    WatchKey { key: key.into() }
+}
+
+/// The result of `watch`
+#[derive(Debug)]
+#[must_use]
+pub struct WatchKey {
+    key: Vec<u8>,
+}
+
+impl AsyncTransaction for WatchKey {
+    type Ctx = FdbContext;
+    type Item = Watch;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        let watch = ctx.transaction().watch(&self.key).map_err(Error::from).boxed();
+        Box::pin(futures::future::ok(watch))
+    }
+}
+
+impl Visit for WatchKey {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("watch"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use transaction::Retryable;
+
+/// The classification of the errors of file system transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The path is absolute, goes out of the root by `..` or is not UTF-8
+    InvalidPath,
+    /// The commit stopped halfway. The journal keeps it, and
+    /// `Runner::recover` completes it.
+    Incomplete,
+    /// Any other error of the file system
+    Io,
+}
+
+/// An error of the file system together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: io::Error,
+}
+
+impl Error {
+    pub(crate) fn invalid_path(msg: &str) -> Self {
+        Error {
+            kind: ErrorKind::InvalidPath,
+            inner: io::Error::new(io::ErrorKind::InvalidInput, msg),
+        }
+    }
+
+    pub(crate) fn incomplete(inner: io::Error) -> Self {
+        Error {
+            kind: ErrorKind::Incomplete,
+            inner,
+        }
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of the file system
+    pub fn get_ref(&self) -> &io::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of the file system
+    pub fn into_inner(self) -> io::Error {
+        self.inner
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(inner: io::Error) -> Self {
+        Error {
+            kind: ErrorKind::Io,
+            inner,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    // the transactions run one at a time, so they never conflict
+    fn is_retryable(&self) -> bool {
+        false
+    }
+}
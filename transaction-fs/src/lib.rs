@@ -0,0 +1,499 @@
+//! A transaction runner for file systems
+//!
+//! Transactions run by the `Runner` in a `FsContext` rooted at a directory.
+//! The leaves `write`, `rename` and `remove_file` don't touch the root;
+//! they stage the new contents of the files in a journal directory, and
+//! `read` sees the staged changes. A failed transaction just discards the
+//! staging, so the root is left as it was.
+//!
+//! A succeeding transaction is committed by writing a manifest of the staged
+//! changes to the journal and renaming it into place, which is the point of
+//! no return, then renaming the staged files over the files of the root and
+//! removing the removed ones. Should the commit stop halfway, e.g. by a
+//! crash, the manifest is left in the journal and the next `Runner` on it
+//! (or the next run) completes the commit before anything else, so the
+//! others see either none or all of the changes of a transaction after the
+//! recovery.
+//!
+//! Renames are atomic only within a file system, so the journal must be on
+//! the same one as the root. The runner runs the transactions one at a time,
+//! but it does not lock the files against other processes; make it the only
+//! writer of the root.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fs;
+//! use transaction::prelude::*;
+//! use transaction_fs::{read, remove_file, rename, write, Runner};
+//!
+//! # fn main() -> Result<(), transaction_fs::Error> {
+//! let dir = tempfile::tempdir().unwrap();
+//! let root = dir.path().join("root");
+//! fs::create_dir(&root)?;
+//! let runner = Runner::new(&root, dir.path().join("journal"))?;
+//!
+//! let staged = runner.run(
+//!     write("config.new", "port = 8080")
+//!         .and_then(|()| rename("config.new", "conf/app.toml"))
+//!         .and_then(|()| read("conf/app.toml")),
+//! )?;
+//! assert_eq!(staged.as_deref(), Some(&b"port = 8080"[..]));
+//! assert_eq!(fs::read_to_string(root.join("conf/app.toml"))?, "port = 8080");
+//! assert!(!root.join("config.new").exists());
+//!
+//! // removing a missing file fails the transaction, so the write is rolled back
+//! let ret = runner.run(write("conf/app.toml", "port = 80").and_then(|()| remove_file("missing")));
+//! assert!(ret.is_err());
+//! assert_eq!(fs::read_to_string(root.join("conf/app.toml"))?, "port = 8080");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write as _};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+use transaction::hooks::{self, Outcome};
+use transaction::Transaction;
+
+mod error;
+mod ops;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+const STAGING: &str = "staging";
+const MANIFEST: &str = "manifest";
+const MANIFEST_TMP: &str = "manifest.tmp";
+
+// the change staged for a file of the root
+#[derive(Debug)]
+enum Staged {
+    // replace the file with the staged file of the name
+    Write(String),
+    Delete,
+}
+
+/// The context of transactions: the root directory and the changes staged
+/// for it.
+pub struct FsContext {
+    root: PathBuf,
+    staging: PathBuf,
+    staged: BTreeMap<PathBuf, Staged>,
+    // the name of the next staged file
+    next: u64,
+}
+
+impl FsContext {
+    // never pub this function
+    fn new(root: PathBuf, staging: PathBuf) -> Self {
+        FsContext {
+            root,
+            staging,
+            staged: BTreeMap::new(),
+            next: 0,
+        }
+    }
+
+    /// The root directory the paths are relative to
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // normalize the path relative to the root, which is also written in the
+    // manifest
+    fn resolve(path: &Path) -> Result<PathBuf, Error> {
+        let mut resolved = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::CurDir => (),
+                _ => {
+                    return Err(Error::invalid_path(
+                        "the path must be relative to the root without `..`",
+                    ))
+                }
+            }
+        }
+        if resolved.as_os_str().is_empty() {
+            return Err(Error::invalid_path("the path is empty"));
+        }
+        // the manifest separates the paths by NUL, which UTF-8 paths lack
+        if resolved.to_str().is_none() {
+            return Err(Error::invalid_path("the path is not UTF-8"));
+        }
+        Ok(resolved)
+    }
+
+    // fail if the path of the root is a directory, which the commit can't
+    // replace
+    fn check_file(&self, path: &Path) -> Result<(), Error> {
+        match fs::symlink_metadata(self.root.join(path)) {
+            Ok(meta) if meta.is_dir() => Err(Error::invalid_path("the path is a directory")),
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, Error> {
+        match self.staged.get(path) {
+            Some(Staged::Write(_)) => Ok(true),
+            Some(Staged::Delete) => Ok(false),
+            None => match fs::symlink_metadata(self.root.join(path)) {
+                Ok(meta) => Ok(!meta.is_dir()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    fn next_name(&mut self) -> String {
+        let name = self.next.to_string();
+        self.next += 1;
+        name
+    }
+
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, Error> {
+        let path = Self::resolve(path)?;
+        let file = match self.staged.get(&path) {
+            Some(Staged::Write(name)) => self.staging.join(name),
+            Some(Staged::Delete) => return Ok(None),
+            None => self.root.join(&path),
+        };
+        match fs::read(file) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), Error> {
+        let path = Self::resolve(path)?;
+        self.check_file(&path)?;
+        let name = self.next_name();
+        let mut file = File::create(self.staging.join(&name))?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        self.staged.insert(path, Staged::Write(name));
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<(), Error> {
+        let from = Self::resolve(from)?;
+        let to = Self::resolve(to)?;
+        if !self.exists(&from)? {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "the source is not found").into());
+        }
+        if from == to {
+            return Ok(());
+        }
+        self.check_file(&to)?;
+        let name = match self.staged.remove(&from) {
+            Some(Staged::Write(name)) => name,
+            _ => {
+                // stage the file of the root by a link, which costs no copy
+                let name = self.next_name();
+                let staged = self.staging.join(&name);
+                if fs::hard_link(self.root.join(&from), &staged).is_err() {
+                    fs::copy(self.root.join(&from), &staged)?;
+                }
+                name
+            }
+        };
+        self.staged.insert(to, Staged::Write(name));
+        self.staged.insert(from, Staged::Delete);
+        Ok(())
+    }
+
+    fn remove_file(&mut self, path: &Path) -> Result<(), Error> {
+        let path = Self::resolve(path)?;
+        if !self.exists(&path)? {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "the file is not found").into());
+        }
+        self.staged.insert(path, Staged::Delete);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for FsContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FsContext")
+            .field("root", &self.root)
+            .field("staged", &self.staged.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runner of the transactions of a root directory, running them one at a
+/// time.
+#[derive(Debug)]
+pub struct Runner {
+    root: PathBuf,
+    journal: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl Runner {
+    /// Run the transactions of the root, staging the changes in the journal
+    /// directory, which is created if it doesn't exist. A commit left
+    /// incomplete in the journal is completed first.
+    pub fn new<R, J>(root: R, journal: J) -> Result<Self, Error>
+    where
+        R: Into<PathBuf>,
+        J: Into<PathBuf>,
+    {
+        let runner = Runner {
+            root: root.into(),
+            journal: journal.into(),
+            lock: Mutex::new(()),
+        };
+        fs::create_dir_all(&runner.journal)?;
+        runner.recover()?;
+        Ok(runner)
+    }
+
+    /// The root directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The journal directory
+    pub fn journal(&self) -> &Path {
+        &self.journal
+    }
+
+    /// Complete the commit left incomplete in the journal, if any. Returns
+    /// whether there was one. `run` does this before running the
+    /// transaction, so call this only to recover early.
+    pub fn recover(&self) -> Result<bool, Error> {
+        // a panicking run has left the journal to be recovered, so the lock
+        // is sound
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.recover_locked()
+    }
+
+    /// run the given transaction, waiting for the running one if any. Pass
+    /// a reference to run the same transaction again.
+    ///
+    /// If the transaction panics, its staging is discarded, leaving the root
+    /// as it was, and the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::fs;
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// use transaction::prelude::*;
+    /// use transaction_fs::{write, Error, Runner};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let root = dir.path().join("root");
+    /// fs::create_dir(&root)?;
+    /// let runner = Runner::new(&root, dir.path().join("journal"))?;
+    ///
+    /// let boom = write("app.toml", "port = 80").map(|_| -> () { panic!("boom") });
+    /// assert!(panic::catch_unwind(AssertUnwindSafe(|| runner.run::<_, Error, _>(boom))).is_err());
+    /// assert!(!root.join("app.toml").exists());
+    /// assert_eq!(fs::read_dir(runner.journal())?.count(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run<T, E, Tx>(&self, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = FsContext, Item = T, Err = E>,
+    {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        hooks::instrument("fs", tx.label(), Outcome::of, || {
+            self.recover_locked()?;
+            let staging = self.journal.join(STAGING);
+            remove_dir_all(&staging)?;
+            fs::create_dir(&staging).map_err(Error::from)?;
+            let discard = DiscardOnPanic(staging.clone());
+            let mut ctx = FsContext::new(self.root.clone(), staging);
+            let ret = tx.run(&mut ctx);
+            drop(discard);
+            match ret {
+                Ok(t) => {
+                    self.commit(ctx)?;
+                    Ok(t)
+                }
+                Err(e) => {
+                    // the root is untouched, so the staging is just garbage
+                    let _ = remove_dir_all(&ctx.staging);
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    fn commit(&self, ctx: FsContext) -> Result<(), Error> {
+        let changes = ctx
+            .staged
+            .into_iter()
+            .map(|(path, staged)| match staged {
+                Staged::Write(name) => Change::Write(name, path),
+                Staged::Delete => Change::Delete(path),
+            })
+            .collect::<Vec<_>>();
+        if !changes.is_empty() {
+            if let Err(e) = self.write_manifest(&changes) {
+                // the staged files are needed to complete a decided commit
+                if e.kind() != ErrorKind::Incomplete {
+                    let _ = remove_dir_all(&ctx.staging);
+                }
+                return Err(e);
+            }
+            self.apply(&changes).map_err(Error::incomplete)?;
+        }
+        let _ = remove_dir_all(&ctx.staging);
+        Ok(())
+    }
+
+    fn write_manifest(&self, changes: &[Change]) -> Result<(), Error> {
+        let tmp = self.journal.join(MANIFEST_TMP);
+        let mut file = File::create(&tmp)?;
+        file.write_all(&encode(changes))?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.journal.join(MANIFEST))?;
+        // the commit is decided once the rename is durable
+        sync_dir(&self.journal).map_err(Error::incomplete)
+    }
+
+    fn recover_locked(&self) -> Result<bool, Error> {
+        let manifest = match fs::read(self.journal.join(MANIFEST)) {
+            Ok(manifest) => manifest,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(Error::incomplete(e)),
+        };
+        let changes = decode(&manifest).map_err(Error::incomplete)?;
+        self.apply(&changes).map_err(Error::incomplete)?;
+        let _ = remove_dir_all(&self.journal.join(STAGING));
+        #[cfg(feature = "log")]
+        log::info!("recovered the commit of {} changes", changes.len());
+        Ok(true)
+    }
+
+    // apply the changes and remove the manifest. Applying them again is
+    // harmless, so an incomplete apply is completed by starting over.
+    fn apply(&self, changes: &[Change]) -> io::Result<()> {
+        let staging = self.journal.join(STAGING);
+        let mut dirs = Vec::new();
+        for change in changes {
+            match change {
+                Change::Write(name, path) => {
+                    let target = self.root.join(path);
+                    let parent = target.parent().unwrap_or(&self.root).to_path_buf();
+                    fs::create_dir_all(&parent)?;
+                    match fs::rename(staging.join(name), &target) {
+                        Ok(()) => (),
+                        // renamed by the previous apply
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                        Err(e) => return Err(e),
+                    }
+                    dirs.push(parent);
+                }
+                Change::Delete(path) => {
+                    let target = self.root.join(path);
+                    match fs::remove_file(&target) {
+                        Ok(()) => (),
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                        Err(e) => return Err(e),
+                    }
+                    dirs.push(target.parent().unwrap_or(&self.root).to_path_buf());
+                }
+            }
+        }
+        dirs.sort();
+        dirs.dedup();
+        for dir in dirs {
+            sync_dir(&dir)?;
+        }
+        fs::remove_file(self.journal.join(MANIFEST))?;
+        sync_dir(&self.journal)
+    }
+}
+
+// a change recorded in the manifest
+#[derive(Debug)]
+enum Change {
+    // rename the staged file of the name to the path
+    Write(String, PathBuf),
+    Delete(PathBuf),
+}
+
+// the manifest is a sequence of `W\0name\0path\0` and `D\0path\0`
+fn encode(changes: &[Change]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for change in changes {
+        let fields = match change {
+            Change::Write(name, path) => ["W", name, path.to_str().unwrap()].to_vec(),
+            Change::Delete(path) => ["D", path.to_str().unwrap()].to_vec(),
+        };
+        for field in fields {
+            buf.extend_from_slice(field.as_bytes());
+            buf.push(0);
+        }
+    }
+    buf
+}
+
+fn decode(manifest: &[u8]) -> io::Result<Vec<Change>> {
+    let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "the manifest is corrupt");
+    let manifest = std::str::from_utf8(manifest).map_err(|_| corrupt())?;
+    let mut fields = manifest.split_terminator('\0');
+    let mut changes = Vec::new();
+    while let Some(tag) = fields.next() {
+        let change = match tag {
+            "W" => {
+                let name = fields.next().ok_or_else(corrupt)?;
+                let path = fields.next().ok_or_else(corrupt)?;
+                Change::Write(name.to_owned(), path.into())
+            }
+            "D" => Change::Delete(fields.next().ok_or_else(corrupt)?.into()),
+            _ => return Err(corrupt()),
+        };
+        changes.push(change);
+    }
+    Ok(changes)
+}
+
+// removes the staging of a transaction if it panics, before anything is
+// committed
+struct DiscardOnPanic(PathBuf);
+
+impl Drop for DiscardOnPanic {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            #[cfg(feature = "log")]
+            log::warn!("discarding the staging of a panicking transaction");
+            let _ = remove_dir_all(&self.0);
+        }
+    }
+}
+
+fn remove_dir_all(dir: &Path) -> Result<(), Error> {
+    match fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// make the renames and removals in the directory durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
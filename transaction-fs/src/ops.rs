@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{Error, FsContext};
+
+/// Read the file, or `None` if it does not exist. The writes of the
+/// transaction to it are visible.
+pub fn read<P>(path: P) -> Read
+where
+    P: Into<PathBuf>,
+{
+    Read { path: path.into() }
+}
+
+/// The result of `read`
+#[derive(Debug)]
+#[must_use]
+pub struct Read {
+    path: PathBuf,
+}
+
+impl Transaction for Read {
+    type Ctx = FsContext;
+    type Item = Option<Vec<u8>>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut FsContext) -> Result<Self::Item, Self::Err> {
+        ctx.read(&self.path)
+    }
+}
+
+impl Visit for Read {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("read"));
+    }
+}
+
+/// Write the contents to the file, creating it and its parent directories if
+/// they don't exist. The contents are staged in the journal until the
+/// commit.
+pub fn write<P, C>(path: P, contents: C) -> Write
+where
+    P: Into<PathBuf>,
+    C: Into<Vec<u8>>,
+{
+    Write {
+        path: path.into(),
+        contents: contents.into(),
+    }
+}
+
+/// The result of `write`
+#[derive(Debug)]
+#[must_use]
+pub struct Write {
+    path: PathBuf,
+    contents: Vec<u8>,
+}
+
+impl Transaction for Write {
+    type Ctx = FsContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut FsContext) -> Result<Self::Item, Self::Err> {
+        ctx.write(&self.path, &self.contents)
+    }
+}
+
+impl Visit for Write {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("write"));
+    }
+}
+
+/// Rename the file, replacing the destination if it exists. It fails with
+/// `NotFound` if the source does not exist.
+pub fn rename<F, T>(from: F, to: T) -> Rename
+where
+    F: Into<PathBuf>,
+    T: Into<PathBuf>,
+{
+    Rename {
+        from: from.into(),
+        to: to.into(),
+    }
+}
+
+/// The result of `rename`
+#[derive(Debug)]
+#[must_use]
+pub struct Rename {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+impl Transaction for Rename {
+    type Ctx = FsContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut FsContext) -> Result<Self::Item, Self::Err> {
+        ctx.rename(&self.from, &self.to)
+    }
+}
+
+impl Visit for Rename {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("rename"));
+    }
+}
+
+/// Remove the file. It fails with `NotFound` if the file does not exist.
+pub fn remove_file<P>(path: P) -> RemoveFile
+where
+    P: Into<PathBuf>,
+{
+    RemoveFile { path: path.into() }
+}
+
+/// The result of `remove_file`
+#[derive(Debug)]
+#[must_use]
+pub struct RemoveFile {
+    path: PathBuf,
+}
+
+impl Transaction for RemoveFile {
+    type Ctx = FsContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut FsContext) -> Result<Self::Item, Self::Err> {
+        ctx.remove_file(&self.path)
+    }
+}
+
+impl Visit for RemoveFile {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("remove_file"));
+    }
+}
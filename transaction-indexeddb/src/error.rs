@@ -0,0 +1,136 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::DomException;
+
+/// The classification of the errors of IndexedDB by the names of their
+/// `DOMException`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `TransactionInactiveError`: a request was made after the transaction
+    /// had committed on idle, because the transaction awaited something
+    /// other than the requests of its context
+    Inactive,
+    /// The transaction was rolled back after it had committed on idle, so
+    /// its writes are kept
+    AutoCommitted,
+    /// `ConstraintError`, e.g. a key added twice or a unique index violated
+    Constraint,
+    /// `QuotaExceededError`: the storage quota of the origin is used up
+    QuotaExceeded,
+    /// `AbortError`: the transaction was aborted, e.g. by the browser
+    Aborted,
+    /// A value or a key could not be converted from or into JavaScript
+    Conversion,
+    /// Any other error
+    Other,
+}
+
+/// An error of IndexedDB together with its classification. The JavaScript
+/// error is kept by its name and message, so that the error can be sent
+/// like the other errors of transactions.
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    name: String,
+    message: String,
+}
+
+impl Error {
+    pub(crate) fn auto_committed() -> Self {
+        Error {
+            kind: ErrorKind::AutoCommitted,
+            name: "AutoCommitted".to_string(),
+            message: "the transaction had committed on idle before it was rolled back".to_string(),
+        }
+    }
+
+    pub(crate) fn aborted(e: Option<DomException>) -> Self {
+        match e {
+            Some(e) => Error::from(e),
+            None => Error {
+                kind: ErrorKind::Aborted,
+                name: "AbortError".to_string(),
+                message: "the transaction was aborted".to_string(),
+            },
+        }
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The name of the JavaScript error, e.g. `ConstraintError`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The message of the JavaScript error
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<DomException> for Error {
+    fn from(e: DomException) -> Self {
+        let name = e.name();
+        let kind = match name.as_str() {
+            "TransactionInactiveError" => ErrorKind::Inactive,
+            "ConstraintError" => ErrorKind::Constraint,
+            "QuotaExceededError" => ErrorKind::QuotaExceeded,
+            "AbortError" => ErrorKind::Aborted,
+            _ => ErrorKind::Other,
+        };
+        Error {
+            kind,
+            name,
+            message: e.message(),
+        }
+    }
+}
+
+impl From<JsValue> for Error {
+    fn from(value: JsValue) -> Self {
+        let value = match value.dyn_into::<DomException>() {
+            Ok(e) => return Error::from(e),
+            Err(value) => value,
+        };
+        let (name, message) = match value.dyn_into::<js_sys::Error>() {
+            Ok(e) => (e.name().into(), e.message().into()),
+            Err(value) => ("Error".to_string(), value.as_string().unwrap_or_else(|| format!("{:?}", value))),
+        };
+        Error {
+            kind: ErrorKind::Other,
+            name,
+            message,
+        }
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for Error {
+    fn from(e: serde_wasm_bindgen::Error) -> Self {
+        Error {
+            kind: ErrorKind::Conversion,
+            ..Error::from(JsValue::from(e))
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        // IndexedDB runs the transactions with overlapping scopes one after
+        // another, so they never conflict
+        false
+    }
+}
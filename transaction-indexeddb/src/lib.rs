@@ -0,0 +1,395 @@
+//! Transaction backend for IndexedDB on wasm
+//!
+//! Asynchronous transactions run in an `IdbContext`, an `IDBTransaction` over
+//! the object stores of the database, and the leaves `get`, `put`, `delete`,
+//! `get_all` and the queries of the indexes issue its requests. Keys and
+//! values are converted from and into JavaScript with serde.
+//!
+//! IndexedDB commits a transaction by itself when no request is pending once
+//! the control returns to the event loop. The `Runner` keeps the explicit
+//! model of this crate on top of it instead:
+//!
+//! * a failed request doesn't abort the transaction, so that the transaction
+//!   can recover from it, e.g. by `or_else`.
+//! * a successful transaction is committed by `IDBTransaction.commit`, and
+//!   its result is returned once the commit is complete, so a failure to
+//!   commit, e.g. over quota, is reported.
+//! * a failed transaction is aborted, and so is a transaction whose run is
+//!   dropped.
+//!
+//! The transaction stays alive only while it awaits its own requests. If it
+//! awaits anything else, e.g. a `fetch` or a timer, IndexedDB commits it on
+//! idle and the next request fails with `ErrorKind::Inactive`, and a
+//! rollback after that fails with `ErrorKind::AutoCommitted`, as the writes
+//! are kept.
+//!
+//! The crate builds on any target, but the runner works only on
+//! `wasm32-unknown-unknown` in a browser or a worker, with an executor like
+//! `wasm_bindgen_futures::spawn_local`. There, `std::time` is not available,
+//! so the clock of the `transaction` crate is to be set to `DateClock` at
+//! start up for its deadlines, timings and caches.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use serde::{Deserialize, Serialize};
+//! use transaction::async_tx::{AsyncTransaction, AsyncTransactionExt};
+//! use transaction_indexeddb::{delete, get, index_get_all, open, put_with_key, Runner};
+//!
+//! #[derive(Debug, Clone, Serialize, Deserialize)]
+//! struct Todo {
+//!     owner: String,
+//!     title: String,
+//! }
+//!
+//! # async fn f() -> Result<(), transaction_indexeddb::Error> {
+//! let db = open("todos", 1, |db, _old_version| {
+//!     let todos = db.create_object_store("todos")?;
+//!     todos.create_index_with_str("owner", "owner")?;
+//!     Ok(())
+//! }).await?;
+//! let runner = Runner::new(db);
+//!
+//! let todo = |owner: &str, title: &str| Todo { owner: owner.into(), title: title.into() };
+//! let add = put_with_key("todos", 1, todo("alice", "write docs"))
+//!     .join(put_with_key("todos", 2, todo("alice", "review")));
+//! runner.run_async(add).await?;
+//!
+//! // hand the first todo over to bob and drop the second one, or neither
+//! let hand_over = get::<_, Todo>("todos", 1)
+//!     .and_then(|todo| put_with_key("todos", 1, Todo { owner: "bob".into(), ..todo.unwrap() }))
+//!     .and_then(|()| delete("todos", 2));
+//! runner.run_async(hand_over).await?;
+//!
+//! let alices = runner.run_async(index_get_all::<_, Todo>("todos", "owner", "alice")).await?;
+//! assert!(alices.is_empty());
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future;
+use send_wrapper::SendWrapper;
+use transaction::async_tx::AsyncTransaction;
+use transaction::clock::Clock;
+use transaction::hooks::{self, Outcome};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Event, IdbDatabase, IdbFactory, IdbObjectStore, IdbTransaction, IdbTransactionMode, IdbVersionChangeEvent};
+
+mod error;
+mod request;
+mod store;
+
+pub use crate::error::*;
+pub use crate::store::*;
+
+/// The context of the transactions: a transaction of IndexedDB.
+pub struct IdbContext {
+    inner: SendWrapper<Inner>,
+}
+
+struct Inner {
+    tx: IdbTransaction,
+    finished: Rc<RefCell<Finished>>,
+    _oncomplete: Closure<dyn FnMut(Event)>,
+    _onabort: Closure<dyn FnMut(Event)>,
+}
+
+// how the transaction of IndexedDB finished, set by its complete and abort
+// events
+#[derive(Default)]
+struct Finished {
+    outcome: Option<Outcome>,
+    waker: Option<Waker>,
+}
+
+impl IdbContext {
+    // never pub this function
+    fn new(tx: IdbTransaction) -> Self {
+        let finished = Rc::new(RefCell::new(Finished::default()));
+        let finish = |outcome| {
+            let finished = finished.clone();
+            Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+                let mut finished = finished.borrow_mut();
+                finished.outcome = Some(outcome);
+                if let Some(waker) = finished.waker.take() {
+                    waker.wake();
+                }
+            })
+        };
+        let oncomplete = finish(Outcome::Committed);
+        let onabort = finish(Outcome::RolledBack);
+        tx.set_oncomplete(Some(oncomplete.as_ref().unchecked_ref()));
+        tx.set_onabort(Some(onabort.as_ref().unchecked_ref()));
+        IdbContext {
+            inner: SendWrapper::new(Inner {
+                tx,
+                finished,
+                _oncomplete: oncomplete,
+                _onabort: onabort,
+            }),
+        }
+    }
+
+    /// The transaction of IndexedDB
+    pub fn transaction(&self) -> &IdbTransaction {
+        &self.inner.tx
+    }
+
+    /// The object store of the name in the scope of the transaction
+    pub fn store(&self, name: &str) -> Result<IdbObjectStore, Error> {
+        Ok(self.inner.tx.object_store(name)?)
+    }
+
+    // commit the transaction unless it has committed on idle
+    async fn commit(&self) -> Result<(), Error> {
+        if self.inner.outcome().is_none() {
+            // web-sys deprecates `commit` though the browsers support it. The
+            // transaction commits on idle anyway when it fails, and the
+            // complete or abort event tells the outcome.
+            #[allow(deprecated)]
+            let _ = self.inner.tx.commit();
+        }
+        match self.inner.finish().await {
+            Outcome::Committed => Ok(()),
+            Outcome::RolledBack => Err(Error::aborted(self.inner.tx.error())),
+        }
+    }
+
+    // abort the transaction, which fails if it has committed on idle
+    async fn rollback(&self) -> Result<(), Error> {
+        if self.inner.outcome().is_none() {
+            self.inner.tx.abort()?;
+        }
+        match self.inner.finish().await {
+            Outcome::Committed => Err(Error::auto_committed()),
+            Outcome::RolledBack => Ok(()),
+        }
+    }
+}
+
+impl Inner {
+    fn outcome(&self) -> Option<Outcome> {
+        self.finished.borrow().outcome
+    }
+
+    // wait for the complete or abort event
+    fn finish(&self) -> impl Future<Output = Outcome> + '_ {
+        future::poll_fn(move |cx| {
+            let mut finished = self.finished.borrow_mut();
+            match finished.outcome {
+                Some(outcome) => Poll::Ready(outcome),
+                None => {
+                    finished.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // the run was dropped halfway, e.g. interrupted. Leaving the
+        // transaction alone would commit it on idle.
+        if self.outcome().is_none() {
+            let _ = self.tx.abort();
+        }
+        self.tx.set_oncomplete(None);
+        self.tx.set_onabort(None);
+    }
+}
+
+impl fmt::Debug for IdbContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IdbContext").finish_non_exhaustive()
+    }
+}
+
+/// Open the database of the name at the version. When the database is
+/// older, or new, `upgrade` is called with the database and its previous
+/// version, 0 for a new database, to create or change the object stores and
+/// the indexes. The upgrade is aborted if `upgrade` fails.
+pub async fn open<F>(name: &str, version: u32, upgrade: F) -> Result<IdbDatabase, Error>
+where
+    F: FnOnce(&IdbDatabase, u32) -> Result<(), JsValue> + 'static,
+{
+    // `indexedDB` of the window or of the worker
+    let factory = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))?;
+    if factory.is_undefined() || factory.is_null() {
+        return Err(JsValue::from_str("IndexedDB is not available").into());
+    }
+    let open = factory.unchecked_into::<IdbFactory>().open_with_u32(name, version)?;
+    let failed = Rc::new(RefCell::new(None::<Error>));
+    let onupgradeneeded = {
+        let open = open.clone();
+        let failed = failed.clone();
+        let mut upgrade = Some(upgrade);
+        Closure::<dyn FnMut(IdbVersionChangeEvent)>::new(move |e: IdbVersionChangeEvent| {
+            let upgrade = match upgrade.take() {
+                Some(upgrade) => upgrade,
+                None => return,
+            };
+            let upgraded = open
+                .result()
+                .and_then(|db| upgrade(db.unchecked_ref(), e.old_version() as u32))
+                .map_err(Error::from);
+            if let Err(e) = upgraded {
+                if let Some(tx) = open.transaction() {
+                    let _ = tx.abort();
+                }
+                *failed.borrow_mut() = Some(e);
+            }
+        })
+    };
+    open.set_onupgradeneeded(Some(onupgradeneeded.as_ref().unchecked_ref()));
+    let opened = request::request(open.clone().into()).await;
+    open.set_onupgradeneeded(None);
+    // the error of the upgrade tells more than the abort of the request
+    if let Some(e) = failed.borrow_mut().take() {
+        return Err(e);
+    }
+    Ok(opened?.unchecked_into())
+}
+
+/// Runner of transactions on a database of IndexedDB. By default, the
+/// transactions read and write all the object stores of the database.
+#[derive(Debug, Clone)]
+pub struct Runner {
+    db: IdbDatabase,
+    stores: Option<Vec<String>>,
+    mode: IdbTransactionMode,
+}
+
+impl Runner {
+    /// make a runner reading and writing all the object stores of the
+    /// database
+    pub fn new(db: IdbDatabase) -> Self {
+        Runner {
+            db,
+            stores: None,
+            mode: IdbTransactionMode::Readwrite,
+        }
+    }
+
+    /// Limit the transactions to the object stores. IndexedDB runs the
+    /// transactions whose scopes don't overlap concurrently.
+    pub fn stores<I, S>(self, stores: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Runner {
+            stores: Some(stores.into_iter().map(Into::into).collect()),
+            ..self
+        }
+    }
+
+    /// Run the transactions read-only. Read-only transactions run
+    /// concurrently with each other, and writes fail in them.
+    pub fn read_only(self) -> Self {
+        Runner {
+            mode: IdbTransactionMode::Readonly,
+            ..self
+        }
+    }
+
+    /// The database
+    pub fn db(&self) -> &IdbDatabase {
+        &self.db
+    }
+
+    /// Run the asynchronous transaction, committing it if it succeeds and
+    /// aborting it otherwise.
+    ///
+    /// If the transaction panics, or the future is dropped before it
+    /// finishes, the transaction of IndexedDB is aborted rather than
+    /// committed on idle.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures::future::{self, Either};
+    /// use transaction_indexeddb::{get, put_with_key, Runner};
+    ///
+    /// # async fn f(runner: Runner) -> Result<(), transaction_indexeddb::Error> {
+    /// // the run is dropped while its write is pending
+    /// let run = Box::pin(runner.run_async(put_with_key("todos", 1, "write docs")));
+    /// if let Either::Right(((), run)) = future::select(run, future::ready(())).await {
+    ///     drop(run);
+    /// }
+    /// assert_eq!(runner.run_async(get::<_, String>("todos", 1)).await?, None);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub async fn run_async<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = IdbContext>,
+        Tx::Err: From<Error>,
+    {
+        hooks::instrument_async("indexeddb", tx.label(), Outcome::of, async {
+            let mut ctx = self.begin()?;
+            match tx.run_async(&mut ctx).await {
+                Ok(t) => {
+                    ctx.commit().await?;
+                    Ok(t)
+                }
+                Err(e) => match ctx.rollback().await {
+                    // the writes of the failed transaction are kept, which
+                    // matters more than its error
+                    Err(rollback) if rollback.kind() == ErrorKind::AutoCommitted => Err(rollback.into()),
+                    _ => Err(e),
+                },
+            }
+        })
+        .await
+    }
+
+    fn begin(&self) -> Result<IdbContext, Error> {
+        let stores = match self.stores {
+            Some(ref stores) => stores.iter().map(|s| JsValue::from_str(s)).collect::<js_sys::Array>(),
+            None => {
+                let names = self.db.object_store_names();
+                (0..names.length())
+                    .filter_map(|i| names.item(i))
+                    .map(|s| JsValue::from_str(&s))
+                    .collect()
+            }
+        };
+        let tx = self.db.transaction_with_str_sequence_and_mode(&stores, self.mode)?;
+        Ok(IdbContext::new(tx))
+    }
+}
+
+/// The clock of the browsers and the workers for `transaction::clock::set`,
+/// reading `Date.now()`. The time goes back if the time of the system is
+/// set back. The sleeps return at once since the event loop can't be
+/// blocked: the asynchronous transactions sleep on an
+/// `async_tx::Timer` instead.
+///
+/// ```no_run
+/// transaction::clock::set(Box::new(transaction_indexeddb::DateClock));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateClock;
+
+impl Clock for DateClock {
+    fn now(&self) -> Duration {
+        Duration::from_secs_f64(js_sys::Date::now().max(0.0) / 1000.0)
+    }
+
+    fn system_time(&self) -> SystemTime {
+        UNIX_EPOCH + self.now()
+    }
+
+    fn sleep(&self, _duration: Duration) {}
+}
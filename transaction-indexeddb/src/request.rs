@@ -0,0 +1,93 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+
+use futures::future;
+use send_wrapper::SendWrapper;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use transaction::async_tx::AsyncRun;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Event, IdbRequest, IdbRequestReadyState};
+
+use crate::Error;
+
+// JavaScript values can't leave the thread, which is the only one on wasm.
+// The wrapper panics if they ever did.
+pub(crate) fn boxed<'a, T, F>(f: F) -> AsyncRun<'a, T, Error>
+where
+    F: Future<Output = Result<T, Error>> + 'a,
+{
+    Box::pin(SendWrapper::new(f))
+}
+
+// keys and values are converted into plain objects, not `Map`s, so that the
+// key paths of the stores and the indexes reach their fields
+pub(crate) fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, Error> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+pub(crate) fn from_js<T: DeserializeOwned>(value: JsValue) -> Result<T, Error> {
+    Ok(serde_wasm_bindgen::from_value(value)?)
+}
+
+// wait for the request to succeed or fail, and take its result
+pub(crate) async fn request(request: IdbRequest) -> Result<JsValue, Error> {
+    let waker = Rc::new(RefCell::new(None::<Waker>));
+    let wake = {
+        let waker = waker.clone();
+        move || {
+            if let Some(waker) = waker.borrow_mut().take() {
+                waker.wake();
+            }
+        }
+    };
+    let onsuccess = Closure::<dyn FnMut(Event)>::new({
+        let wake = wake.clone();
+        move |_: Event| wake()
+    });
+    let onerror = Closure::<dyn FnMut(Event)>::new(move |e: Event| {
+        // a failed request aborts the transaction by default, but the
+        // transaction may recover from it, e.g. by `or_else`. The runner
+        // aborts it if the transaction fails after all.
+        e.prevent_default();
+        wake()
+    });
+    request.set_onsuccess(Some(onsuccess.as_ref().unchecked_ref()));
+    request.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+    let listening = Listening {
+        request,
+        _onsuccess: onsuccess,
+        _onerror: onerror,
+    };
+    future::poll_fn(|cx| {
+        if listening.request.ready_state() == IdbRequestReadyState::Done {
+            Poll::Ready(())
+        } else {
+            *waker.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    })
+    .await;
+    match listening.request.error()? {
+        Some(e) => Err(e.into()),
+        None => Ok(listening.request.result()?),
+    }
+}
+
+// the handlers of a request, which are unset before they are dropped, also
+// when the future waiting for the request is
+struct Listening {
+    request: IdbRequest,
+    _onsuccess: Closure<dyn FnMut(Event)>,
+    _onerror: Closure<dyn FnMut(Event)>,
+}
+
+impl Drop for Listening {
+    fn drop(&mut self) {
+        self.request.set_onsuccess(None);
+        self.request.set_onerror(None);
+    }
+}
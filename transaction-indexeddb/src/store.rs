@@ -0,0 +1,345 @@
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+use wasm_bindgen::JsValue;
+use web_sys::{IdbKeyRange, IdbRequest};
+
+use crate::request::{boxed, from_js, request, to_js};
+use crate::{Error, IdbContext};
+
+// the object store, or one of its indexes, a leaf reads
+#[derive(Debug)]
+struct Source {
+    store: String,
+    index: Option<String>,
+}
+
+impl Source {
+    fn new(store: String, index: Option<String>) -> Self {
+        Source { store, index }
+    }
+
+    fn get(&self, ctx: &IdbContext, query: &JsValue) -> Result<IdbRequest, Error> {
+        let store = ctx.store(&self.store)?;
+        Ok(match self.index {
+            Some(ref index) => store.index(index)?.get(query)?,
+            None => store.get(query)?,
+        })
+    }
+
+    fn get_all(&self, ctx: &IdbContext, query: &JsValue) -> Result<IdbRequest, Error> {
+        let store = ctx.store(&self.store)?;
+        Ok(match self.index {
+            Some(ref index) => store.index(index)?.get_all_with_key(query)?,
+            None => store.get_all_with_key(query)?,
+        })
+    }
+}
+
+// the keys `get_all` and friends read
+#[derive(Debug)]
+enum Query<K> {
+    All,
+    Only(K),
+    Range(Bound<K>, Bound<K>),
+}
+
+impl<K: Serialize> Query<K> {
+    fn range<R: RangeBounds<K>>(range: R) -> Self
+    where
+        K: Clone,
+    {
+        Query::Range(range.start_bound().cloned(), range.end_bound().cloned())
+    }
+
+    fn to_js(&self) -> Result<JsValue, Error> {
+        let bound = |bound: &Bound<K>| -> Result<Option<(JsValue, bool)>, Error> {
+            Ok(match *bound {
+                Bound::Included(ref k) => Some((to_js(k)?, false)),
+                Bound::Excluded(ref k) => Some((to_js(k)?, true)),
+                Bound::Unbounded => None,
+            })
+        };
+        let range = match *self {
+            Query::All => return Ok(JsValue::NULL),
+            Query::Only(ref k) => return to_js(k),
+            Query::Range(ref lower, ref upper) => match (bound(lower)?, bound(upper)?) {
+                (None, None) => return Ok(JsValue::NULL),
+                (Some((l, open)), None) => IdbKeyRange::lower_bound_with_open(&l, open)?,
+                (None, Some((u, open))) => IdbKeyRange::upper_bound_with_open(&u, open)?,
+                (Some((l, lo)), Some((u, uo))) => IdbKeyRange::bound_with_lower_open_and_upper_open(&l, &u, lo, uo)?,
+            },
+        };
+        Ok(range.into())
+    }
+}
+
+/// Get the value of the key in the object store, or `None` if there is no
+/// such record.
+pub fn get<K, T>(store: impl Into<String>, key: K) -> Get<K, T>
+where
+    K: Serialize,
+    T: DeserializeOwned,
+{
+    Get {
+        source: Source::new(store.into(), None),
+        key,
+        _phantom: PhantomData,
+    }
+}
+
+/// Get the value of the first record whose key in the index of the object
+/// store is `key`, or `None` if there is no such record.
+pub fn index_get<K, T>(store: impl Into<String>, index: impl Into<String>, key: K) -> Get<K, T>
+where
+    K: Serialize,
+    T: DeserializeOwned,
+{
+    Get {
+        source: Source::new(store.into(), Some(index.into())),
+        key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get` and `index_get`
+#[derive(Debug)]
+#[must_use]
+pub struct Get<K, T> {
+    source: Source,
+    key: K,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<K, T> AsyncTransaction for Get<K, T>
+where
+    K: Serialize + Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Ctx = IdbContext;
+    type Item = Option<T>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut IdbContext) -> AsyncRun<'a, Self::Item, Self::Err> {
+        boxed(async move {
+            let key = to_js(&self.key)?;
+            let value = request(self.source.get(ctx, &key)?).await?;
+            if value.is_undefined() {
+                Ok(None)
+            } else {
+                from_js(value).map(Some)
+            }
+        })
+    }
+}
+
+impl<K, T> Visit for Get<K, T> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        let name = if self.source.index.is_some() { "index_get" } else { "get" };
+        visit_leaf(visitor, Node::new(name));
+    }
+}
+
+/// Get the values of all the records of the object store, in the order of
+/// their keys.
+pub fn get_all<T>(store: impl Into<String>) -> GetAll<(), T>
+where
+    T: DeserializeOwned,
+{
+    GetAll {
+        source: Source::new(store.into(), None),
+        query: Query::All,
+        _phantom: PhantomData,
+    }
+}
+
+/// Get the values of the records of the object store whose keys are in the
+/// range, e.g. `10..20` or `"a".to_string()..`, in the order of their keys.
+pub fn get_range<K, R, T>(store: impl Into<String>, range: R) -> GetAll<K, T>
+where
+    K: Serialize + Clone,
+    R: RangeBounds<K>,
+    T: DeserializeOwned,
+{
+    GetAll {
+        source: Source::new(store.into(), None),
+        query: Query::range(range),
+        _phantom: PhantomData,
+    }
+}
+
+/// Get the values of all the records whose key in the index of the object
+/// store is `key`.
+pub fn index_get_all<K, T>(store: impl Into<String>, index: impl Into<String>, key: K) -> GetAll<K, T>
+where
+    K: Serialize,
+    T: DeserializeOwned,
+{
+    GetAll {
+        source: Source::new(store.into(), Some(index.into())),
+        query: Query::Only(key),
+        _phantom: PhantomData,
+    }
+}
+
+/// Get the values of the records whose keys in the index of the object
+/// store are in the range, in the order of the keys in the index.
+pub fn index_get_range<K, R, T>(store: impl Into<String>, index: impl Into<String>, range: R) -> GetAll<K, T>
+where
+    K: Serialize + Clone,
+    R: RangeBounds<K>,
+    T: DeserializeOwned,
+{
+    GetAll {
+        source: Source::new(store.into(), Some(index.into())),
+        query: Query::range(range),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get_all`, `get_range`, `index_get_all` and
+/// `index_get_range`
+#[derive(Debug)]
+#[must_use]
+pub struct GetAll<K, T> {
+    source: Source,
+    query: Query<K>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<K, T> AsyncTransaction for GetAll<K, T>
+where
+    K: Serialize + Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Ctx = IdbContext;
+    type Item = Vec<T>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut IdbContext) -> AsyncRun<'a, Self::Item, Self::Err> {
+        boxed(async move {
+            let query = self.query.to_js()?;
+            from_js(request(self.source.get_all(ctx, &query)?).await?)
+        })
+    }
+}
+
+impl<K, T> Visit for GetAll<K, T> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        let name = if self.source.index.is_some() { "index_get_all" } else { "get_all" };
+        visit_leaf(visitor, Node::new(name));
+    }
+}
+
+/// Put the value into the object store, replacing the record of the same
+/// key. The key is taken from the value by the key path of the store, or
+/// generated if the store has a key generator.
+pub fn put<V>(store: impl Into<String>, value: V) -> Put<(), V>
+where
+    V: Serialize,
+{
+    Put {
+        store: store.into(),
+        key: None,
+        value,
+    }
+}
+
+/// Put the value into the object store with the key, replacing the record of
+/// the same key. The store must have no key path.
+pub fn put_with_key<K, V>(store: impl Into<String>, key: K, value: V) -> Put<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    Put {
+        store: store.into(),
+        key: Some(key),
+        value,
+    }
+}
+
+/// The result of `put` and `put_with_key`
+#[derive(Debug)]
+#[must_use]
+pub struct Put<K, V> {
+    store: String,
+    key: Option<K>,
+    value: V,
+}
+
+impl<K, V> AsyncTransaction for Put<K, V>
+where
+    K: Serialize + Send + Sync,
+    V: Serialize + Send + Sync,
+{
+    type Ctx = IdbContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut IdbContext) -> AsyncRun<'a, Self::Item, Self::Err> {
+        boxed(async move {
+            let store = ctx.store(&self.store)?;
+            let value = to_js(&self.value)?;
+            let put = match self.key {
+                Some(ref key) => store.put_with_key(&value, &to_js(key)?)?,
+                None => store.put(&value)?,
+            };
+            request(put).await?;
+            Ok(())
+        })
+    }
+}
+
+impl<K, V> Visit for Put<K, V> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("put"));
+    }
+}
+
+/// Delete the record of the key from the object store. Deleting a missing
+/// key succeeds.
+pub fn delete<K>(store: impl Into<String>, key: K) -> Delete<K>
+where
+    K: Serialize,
+{
+    Delete {
+        store: store.into(),
+        key,
+    }
+}
+
+/// The result of `delete`
+#[derive(Debug)]
+#[must_use]
+pub struct Delete<K> {
+    store: String,
+    key: K,
+}
+
+impl<K> AsyncTransaction for Delete<K>
+where
+    K: Serialize + Send + Sync,
+{
+    type Ctx = IdbContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut IdbContext) -> AsyncRun<'a, Self::Item, Self::Err> {
+        boxed(async move {
+            let key = to_js(&self.key)?;
+            request(ctx.store(&self.store)?.delete(&key)?).await?;
+            Ok(())
+        })
+    }
+}
+
+impl<K> Visit for Delete<K> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete"));
+    }
+}
@@ -0,0 +1,80 @@
+use std::error;
+use std::fmt;
+
+use rdkafka::error::KafkaError;
+use transaction::Retryable;
+
+/// The classification of the errors of Kafka transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The transaction must be aborted, and can be run again afterwards,
+    /// e.g. a message failed to be produced
+    Abortable,
+    /// The operation failed temporarily and can be retried as is, e.g. a
+    /// commit timed out
+    Retriable,
+    /// The producer is no longer usable, e.g. it was fenced by another
+    /// producer with the same transactional id
+    Fatal,
+    /// Any other error
+    Other,
+}
+
+/// An error of rdkafka together with its classification
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: KafkaError,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of rdkafka
+    pub fn get_ref(&self) -> &KafkaError {
+        &self.inner
+    }
+
+    /// Unwrap the error of rdkafka
+    pub fn into_inner(self) -> KafkaError {
+        self.inner
+    }
+}
+
+impl From<KafkaError> for Error {
+    fn from(inner: KafkaError) -> Self {
+        let kind = match inner {
+            KafkaError::Transaction(ref e) if e.is_fatal() => ErrorKind::Fatal,
+            KafkaError::Transaction(ref e) if e.txn_requires_abort() => ErrorKind::Abortable,
+            KafkaError::Transaction(ref e) if e.is_retriable() => ErrorKind::Retriable,
+            // a failed delivery makes the transaction abortable
+            KafkaError::MessageProduction(_) => ErrorKind::Abortable,
+            _ => ErrorKind::Other,
+        };
+        Error { kind, inner }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        match self.kind {
+            ErrorKind::Abortable | ErrorKind::Retriable => true,
+            ErrorKind::Fatal | ErrorKind::Other => false,
+        }
+    }
+}
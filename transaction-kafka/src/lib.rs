@@ -0,0 +1,295 @@
+//! Transaction backend for Kafka
+//!
+//! Asynchronous transactions run in a `KafkaContext` wrapping a transactional
+//! producer of rdkafka. The leaves `produce` and `send_offsets` produce
+//! messages and commit the offsets of a consumer group in the transaction,
+//! so a consume-transform-produce pipeline composed of them processes each
+//! message exactly once.
+//!
+//! The `Runner` initializes the transactions of the producer once, and
+//! begins and commits a transaction of Kafka around each run. When the
+//! transaction or its commit fails, the transaction of Kafka is aborted, and
+//! the transaction is run again if the error is `Retryable` and the runner
+//! has retries left. A commit failing with a retriable error is committed
+//! again after a backoff, a bounded number of times. The producer can't be
+//! used anymore after a fatal error.
+//!
+//! The transactional operations of rdkafka block until they finish, so they
+//! run on the blocking threads of tokio.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use rdkafka::producer::FutureProducer;
+//! use rdkafka::ClientConfig;
+//! use transaction::async_tx::AsyncTransactionExt;
+//! use transaction_kafka::{produce, Runner};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), transaction_kafka::Error> {
+//!     let producer: FutureProducer = ClientConfig::new()
+//!         .set("bootstrap.servers", "localhost:9092")
+//!         .set("transactional.id", "orders")
+//!         .create()
+//!         .map_err(transaction_kafka::Error::from)?;
+//!     let runner = Runner::new(producer, Duration::from_secs(10)).await?.max_retries(3);
+//!
+//!     let order = produce("orders", Some("alice"), "book")
+//!         .and_then(|_| produce("invoices", Some("alice"), "book: 10"));
+//!     runner.run(&order).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rdkafka::error::{KafkaError, KafkaResult};
+use rdkafka::producer::{FutureProducer, Producer};
+use transaction::async_tx::AsyncTransaction;
+use transaction::hooks::{self, Outcome};
+use transaction::{metrics, Backoff, Retryable, RetryPolicy};
+
+mod error;
+mod ops;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+/// The context of the transactions: a transactional producer in a
+/// transaction.
+pub struct KafkaContext {
+    producer: FutureProducer,
+    timeout: Duration,
+}
+
+impl KafkaContext {
+    // never pub this function
+    fn new(producer: FutureProducer, timeout: Duration) -> Self {
+        KafkaContext { producer, timeout }
+    }
+
+    /// The producer
+    pub fn producer(&self) -> &FutureProducer {
+        &self.producer
+    }
+}
+
+impl fmt::Debug for KafkaContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KafkaContext")
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+// aborts the transaction of Kafka begun for a run if the transaction panics
+// or is dropped before it is committed, so the producer can begin the next
+// one. The abort blocks the thread, which is unwinding or cancelling the run
+// anyway.
+struct AbortOnDrop {
+    // taken once the transaction finishes
+    producer: Option<FutureProducer>,
+    timeout: Duration,
+}
+
+impl AbortOnDrop {
+    fn disarm(mut self) {
+        self.producer = None;
+    }
+}
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        if let Some(producer) = self.producer.take() {
+            #[cfg(feature = "log")]
+            log::warn!("aborting the transaction of Kafka of a panicking or dropped transaction");
+            // a commit dropped goes on on a blocking thread, and the abort
+            // conflicts with it until it is done
+            let started = Instant::now();
+            while let Err(KafkaError::Transaction(e)) = producer.abort_transaction(self.timeout) {
+                if !e.is_retriable() || started.elapsed() >= self.timeout {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+}
+
+// run a blocking operation of the producer on the blocking threads of tokio
+async fn blocking<T, F>(producer: &FutureProducer, f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&FutureProducer) -> KafkaResult<T> + Send + 'static,
+{
+    let producer = producer.clone();
+    match tokio::task::spawn_blocking(move || f(&producer)).await {
+        Ok(ret) => Ok(ret?),
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Runner of transactions on a transactional producer
+#[derive(Clone)]
+pub struct Runner {
+    producer: FutureProducer,
+    timeout: Duration,
+    max_retries: u32,
+    commit_backoff: Backoff,
+}
+
+impl Runner {
+    /// Initialize the transactions of the producer, which must be configured
+    /// with a `transactional.id`. The transactional operations time out
+    /// after `timeout`, and the transactions are not retried. The commits
+    /// failing with a retriable error are retried 3 times, 100ms after the
+    /// first failure and doubling the delay.
+    pub async fn new(producer: FutureProducer, timeout: Duration) -> Result<Self, Error> {
+        blocking(&producer, move |producer| producer.init_transactions(timeout)).await?;
+        Ok(Runner {
+            producer,
+            timeout,
+            max_retries: 0,
+            commit_backoff: Backoff::exponential(Duration::from_millis(100)),
+        })
+    }
+
+    /// Run the transactions failing with a `Retryable` error again, up to
+    /// `max_retries` times
+    pub fn max_retries(self, max_retries: u32) -> Self {
+        Runner { max_retries, ..self }
+    }
+
+    /// Commit again the commits failing with a retriable error, e.g. timing
+    /// out, after the delays of `commit_backoff`. The transaction of Kafka is
+    /// aborted once it gives up.
+    pub fn commit_backoff(self, commit_backoff: Backoff) -> Self {
+        Runner { commit_backoff, ..self }
+    }
+
+    /// The producer
+    pub fn producer(&self) -> &FutureProducer {
+        &self.producer
+    }
+
+    /// run the given transaction in a transaction of Kafka, committing it if
+    /// the transaction succeeds and aborting it otherwise. Each retry is
+    /// recorded by `metrics::record_retry`.
+    ///
+    /// If the transaction panics, or the future is dropped before the
+    /// transaction of Kafka is committed, it is aborted, blocking the thread
+    /// until it is, so the producer can begin the next one. A commit dropped
+    /// goes on in the background, and the abort waits for it up to the
+    /// timeout, taking effect only if the commit fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::panic::AssertUnwindSafe;
+    /// use std::time::Duration;
+    ///
+    /// use futures::FutureExt;
+    /// use rdkafka::producer::FutureProducer;
+    /// use rdkafka::ClientConfig;
+    /// use transaction::async_tx::AsyncTransactionExt;
+    /// use transaction_kafka::{produce, Error, Runner};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() -> Result<(), Error> {
+    ///     let producer: FutureProducer = ClientConfig::new()
+    ///         .set("bootstrap.servers", "localhost:9092")
+    ///         .set("transactional.id", "orders")
+    ///         .create()
+    ///         .map_err(Error::from)?;
+    ///     let runner = Runner::new(producer, Duration::from_secs(10)).await?;
+    ///
+    ///     let boom = produce("orders", Some("alice"), "book").map(|_| -> () { panic!("boom") });
+    ///     assert!(AssertUnwindSafe(runner.run(boom)).catch_unwind().await.is_err());
+    ///     // the producer is out of the transaction aborted
+    ///     runner.run(produce("orders", Some("bob"), "pen")).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn run<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = KafkaContext>,
+        Tx::Err: From<Error> + Retryable,
+    {
+        hooks::instrument_async("kafka", tx.label(), Outcome::of, async {
+            let mut retries = 0;
+            loop {
+                blocking(&self.producer, |producer| producer.begin_transaction()).await?;
+                let begun = AbortOnDrop {
+                    producer: Some(self.producer.clone()),
+                    timeout: self.timeout,
+                };
+                let mut ctx = KafkaContext::new(self.producer.clone(), self.timeout);
+                let ret = tx.run_async(&mut ctx).await;
+                let e = match ret {
+                    Ok(item) => match self.commit().await {
+                        Ok(()) => {
+                            begun.disarm();
+                            return Ok(item);
+                        }
+                        Err(e) if e.kind() == ErrorKind::Fatal => {
+                            begun.disarm();
+                            return Err(e.into());
+                        }
+                        Err(e) => Tx::Err::from(e),
+                    },
+                    Err(e) => e,
+                };
+                begun.disarm();
+                let timeout = self.timeout;
+                if blocking(&self.producer, move |producer| producer.abort_transaction(timeout))
+                    .await
+                    .is_err()
+                {
+                    return Err(e);
+                }
+                if retries >= self.max_retries || !e.is_retryable() {
+                    return Err(e);
+                }
+                retries += 1;
+                #[cfg(feature = "log")]
+                log::debug!("transaction aborted, retrying");
+                metrics::record_retry(tx.label());
+            }
+        }).await
+    }
+
+    // commit, again after the delays of the backoff while the commit fails
+    // with a retriable error
+    async fn commit(&self) -> Result<(), Error> {
+        let timeout = self.timeout;
+        let mut retries = 0;
+        loop {
+            match blocking(&self.producer, move |producer| producer.commit_transaction(timeout)).await {
+                Err(e) if e.kind() == ErrorKind::Retriable => match self.commit_backoff.next_delay(retries) {
+                    Some(delay) => {
+                        retries += 1;
+                        #[cfg(feature = "log")]
+                        log::debug!("commit failed with a retriable error, committing again");
+                        tokio::time::sleep(delay).await;
+                    }
+                    None => return Err(e),
+                },
+                ret => return ret,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Runner")
+            .field("timeout", &self.timeout)
+            .field("max_retries", &self.max_retries)
+            .field("commit_backoff", &self.commit_backoff)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use rdkafka::consumer::ConsumerGroupMetadata;
+use rdkafka::producer::{FutureRecord, Producer};
+use rdkafka::TopicPartitionList;
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::{blocking, Error, KafkaContext};
+
+/// Produce the message to the topic in the transaction. It resolves to the
+/// partition and the offset of the message once delivered, and the message
+/// is visible to the consumers reading committed messages once the
+/// transaction commits.
+pub fn produce<T, K, P>(topic: T, key: Option<K>, payload: P) -> Produce
+where
+    T: Into<String>,
+    K: Into<Vec<u8>>,
+    P: Into<Vec<u8>>,
+{
+    Produce {
+        topic: topic.into(),
+        key: key.map(Into::into),
+        payload: payload.into(),
+    }
+}
+
+/// The result of `produce`
+#[derive(Debug)]
+#[must_use]
+pub struct Produce {
+    topic: String,
+    key: Option<Vec<u8>>,
+    payload: Vec<u8>,
+}
+
+impl AsyncTransaction for Produce {
+    type Ctx = KafkaContext;
+    type Item = (i32, i64);
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let mut record = FutureRecord::<[u8], [u8]>::to(&self.topic).payload(&self.payload);
+            if let Some(ref key) = self.key {
+                record = record.key(key);
+            }
+            match ctx.producer.send(record, ctx.timeout).await {
+                Ok(delivery) => Ok(delivery),
+                Err((e, _)) => Err(e.into()),
+            }
+        })
+    }
+}
+
+impl Visit for Produce {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("produce"));
+    }
+}
+
+/// Commit the offsets of the consumer group with the transaction, so that
+/// the messages consumed are marked as processed exactly when the messages
+/// produced from them are committed.
+pub fn send_offsets(offsets: TopicPartitionList, group: ConsumerGroupMetadata) -> SendOffsets {
+    SendOffsets {
+        offsets,
+        group: Arc::new(group),
+    }
+}
+
+/// The result of `send_offsets`
+#[must_use]
+pub struct SendOffsets {
+    offsets: TopicPartitionList,
+    group: Arc<ConsumerGroupMetadata>,
+}
+
+impl std::fmt::Debug for SendOffsets {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SendOffsets")
+            .field("offsets", &self.offsets)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncTransaction for SendOffsets {
+    type Ctx = KafkaContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        let offsets = self.offsets.clone();
+        let group = self.group.clone();
+        let timeout = ctx.timeout;
+        Box::pin(blocking(&ctx.producer, move |producer| {
+            producer.send_offsets_to_transaction(&offsets, &group, timeout)
+        }))
+    }
+}
+
+impl Visit for SendOffsets {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("send_offsets"));
+    }
+}
@@ -0,0 +1,69 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of LMDB transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `Runner::try_write` found another read-write transaction running
+    WriterBusy,
+    /// Any other error
+    Other,
+}
+
+/// An error of heed, or a busy writer
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: Option<heed::Error>,
+}
+
+impl Error {
+    pub(crate) fn writer_busy() -> Self {
+        Error {
+            kind: ErrorKind::WriterBusy,
+            inner: None,
+        }
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of heed, if the writer was not busy
+    pub fn get_ref(&self) -> Option<&heed::Error> {
+        self.inner.as_ref()
+    }
+}
+
+impl From<heed::Error> for Error {
+    fn from(inner: heed::Error) -> Self {
+        Error {
+            kind: ErrorKind::Other,
+            inner: Some(inner),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner {
+            Some(ref e) => fmt::Display::fmt(e, f),
+            None => f.write_str("another read-write transaction is running"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.as_ref().and_then(|e| e.source())
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind != ErrorKind::Other
+    }
+}
@@ -0,0 +1,224 @@
+//! A transaction runner for LMDB
+//!
+//! LMDB runs any number of read-only transactions alongside at most one
+//! read-write transaction, so the two kinds run in different contexts.
+//!
+//! Read-only transactions run by `read` in a `ReadContext` borrowing a read
+//! transaction of heed owned by the caller. The values they read are borrowed
+//! from the memory map for as long as the read transaction lives, so `get`
+//! returns `&[u8]` without copying.
+//!
+//! Read-write transactions run by `Runner::write` in a `WriteContext`, one at
+//! a time. The runner holds its own lock of the writer, so `Runner::try_write`
+//! fails fast with a retryable `ErrorKind::WriterBusy` instead of waiting in
+//! LMDB.
+//!
+//! # Examples
+//!
+//! ```
+//! use heed::EnvOpenOptions;
+//! use transaction::prelude::*;
+//! use transaction_lmdb::{get, put, read, Db, Runner};
+//!
+//! # fn main() -> Result<(), transaction_lmdb::Error> {
+//! let dir = tempfile::tempdir().unwrap();
+//! let env = unsafe { EnvOpenOptions::new().open(dir.path())? };
+//! let mut wtxn = env.write_txn()?;
+//! let db: Db = env.create_database(&mut wtxn, None)?;
+//! wtxn.commit()?;
+//!
+//! let runner = Runner::new(env);
+//! runner.write(put(db, "greeting", "hello").and_then(move |()| put(db, "name", "lmdb")))?;
+//!
+//! let rtxn = runner.read_txn()?;
+//! let (greeting, name) = read(&rtxn, get(db, "greeting").join(get(db, "name")))?;
+//! assert_eq!(greeting, Some(&b"hello"[..]));
+//! assert_eq!(name, Some(&b"lmdb"[..]));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::{Mutex, TryLockError};
+
+use heed::types::Bytes;
+use heed::{Env, RoTxn, RwTxn};
+use transaction::hooks::{self, Outcome};
+use transaction::Transaction;
+
+mod error;
+mod read;
+mod write;
+
+pub use crate::error::*;
+pub use crate::read::*;
+pub use crate::write::*;
+
+/// The databases the leaves read and write: raw bytes to raw bytes.
+pub type Db = heed::Database<Bytes, Bytes>;
+
+/// The context of read-only transactions: a read transaction of heed.
+pub struct ReadContext<'a> {
+    txn: &'a RoTxn<'a>,
+}
+
+impl<'a> ReadContext<'a> {
+    // never pub this function
+    fn new(txn: &'a RoTxn<'a>) -> Self {
+        ReadContext { txn }
+    }
+
+    /// The read transaction of heed
+    pub fn txn(&self) -> &'a RoTxn<'a> {
+        self.txn
+    }
+}
+
+impl<'a> fmt::Debug for ReadContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReadContext").finish_non_exhaustive()
+    }
+}
+
+/// The context of read-write transactions: a write transaction of heed.
+pub struct WriteContext<'a> {
+    txn: RwTxn<'a>,
+}
+
+impl<'a> WriteContext<'a> {
+    // never pub this function
+    fn new(txn: RwTxn<'a>) -> Self {
+        WriteContext { txn }
+    }
+
+    /// The write transaction of heed, for reading
+    pub fn txn(&self) -> &RoTxn<'a> {
+        &self.txn
+    }
+
+    /// The write transaction of heed
+    pub fn txn_mut(&mut self) -> &mut RwTxn<'a> {
+        &mut self.txn
+    }
+}
+
+impl<'a> fmt::Debug for WriteContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriteContext").finish_non_exhaustive()
+    }
+}
+
+/// run the given read-only transaction on the read transaction. The result
+/// may borrow from it, so the read transaction is neither committed nor
+/// aborted; drop it when done with the result.
+pub fn read<'a, T, E, Tx>(txn: &'a RoTxn<'a>, tx: Tx) -> Result<T, E>
+where
+    Tx: Transaction<Ctx = ReadContext<'a>, Item = T, Err = E>,
+{
+    hooks::instrument("lmdb", tx.label(), Outcome::of, || tx.run(&mut ReadContext::new(txn)))
+}
+
+/// Runner of the transactions of an environment, running the read-write ones
+/// one at a time.
+#[derive(Debug)]
+pub struct Runner {
+    env: Env,
+    writer: Mutex<()>,
+}
+
+impl Runner {
+    /// Run the transactions of the environment. Write with this runner
+    /// only, or the other writers wait in LMDB for the lock.
+    pub fn new(env: Env) -> Self {
+        Runner {
+            env,
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// The environment
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Begin a read transaction to run read-only transactions by `read`
+    pub fn read_txn(&self) -> Result<RoTxn<'_>, Error> {
+        Ok(self.env.read_txn()?)
+    }
+
+    /// run the given read-write transaction, waiting for the running one if
+    /// any. Pass a reference to run the same transaction again.
+    ///
+    /// If the transaction panics, the write transaction of heed is aborted
+    /// as it is dropped, releasing the lock of the writer, and the panic is
+    /// resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// use heed::EnvOpenOptions;
+    /// use transaction::prelude::*;
+    /// use transaction_lmdb::{get_owned, put, Db, Error, Runner};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let env = unsafe { EnvOpenOptions::new().open(dir.path())? };
+    /// let mut wtxn = env.write_txn()?;
+    /// let db: Db = env.create_database(&mut wtxn, None)?;
+    /// wtxn.commit()?;
+    /// let runner = Runner::new(env);
+    ///
+    /// let boom = put(db, "greeting", "hello").map(|_| -> () { panic!("boom") });
+    /// assert!(panic::catch_unwind(AssertUnwindSafe(|| runner.write::<_, Error, _>(boom))).is_err());
+    /// assert_eq!(runner.try_write::<_, Error, _>(get_owned(db, "greeting"))?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write<'a, T, E, Tx>(&'a self, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = WriteContext<'a>, Item = T, Err = E>,
+    {
+        // a panicking writer has aborted its transaction, so the lock is sound
+        let _guard = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        self.write_locked(tx)
+    }
+
+    /// run the given read-write transaction like `write`, failing with
+    /// `ErrorKind::WriterBusy` instead of waiting if another one is running.
+    /// The error is retryable.
+    pub fn try_write<'a, T, E, Tx>(&'a self, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = WriteContext<'a>, Item = T, Err = E>,
+    {
+        let _guard = match self.writer.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(Error::writer_busy().into()),
+        };
+        self.write_locked(tx)
+    }
+
+    fn write_locked<'a, T, E, Tx>(&'a self, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = WriteContext<'a>, Item = T, Err = E>,
+    {
+        hooks::instrument("lmdb", tx.label(), Outcome::of, || {
+            let mut ctx = WriteContext::new(self.env.write_txn().map_err(Error::from)?);
+            match tx.run(&mut ctx) {
+                Ok(t) => {
+                    ctx.txn.commit().map_err(Error::from)?;
+                    Ok(t)
+                }
+                Err(e) => {
+                    ctx.txn.abort();
+                    Err(e)
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,80 @@
+use std::marker::PhantomData;
+
+use heed::RoTxn;
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{Db, Error, ReadContext};
+
+/// Get the value of the key, borrowed from the memory map for as long as the
+/// read transaction lives.
+pub fn get<'a, K>(db: Db, key: K) -> Get<'a>
+where
+    K: Into<Vec<u8>>,
+{
+    Get {
+        db,
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get`
+#[derive(Debug)]
+#[must_use]
+pub struct Get<'a> {
+    db: Db,
+    key: Vec<u8>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for Get<'a> {
+    type Ctx = ReadContext<'a>;
+    type Item = Option<&'a [u8]>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut ReadContext<'a>) -> Result<Self::Item, Self::Err> {
+        Ok(self.db.get(ctx.txn(), &self.key)?)
+    }
+}
+
+impl<'a> Visit for Get<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get"));
+    }
+}
+
+/// Receive the read transaction and perform computation. The result may
+/// borrow from it.
+pub fn with_read_txn<'a, F, T, E>(f: F) -> WithReadTxn<'a, F>
+where
+    F: Fn(&'a RoTxn<'a>) -> Result<T, E>,
+{
+    WithReadTxn { f, _phantom: PhantomData }
+}
+
+/// The result of `with_read_txn`
+#[derive(Debug)]
+#[must_use]
+pub struct WithReadTxn<'a, F> {
+    f: F,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, F, T, E> Transaction for WithReadTxn<'a, F>
+where
+    F: Fn(&'a RoTxn<'a>) -> Result<T, E>,
+{
+    type Ctx = ReadContext<'a>;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut ReadContext<'a>) -> Result<Self::Item, Self::Err> {
+        (self.f)(ctx.txn())
+    }
+}
+
+impl<'a, F> Visit for WithReadTxn<'a, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_read_txn"));
+    }
+}
@@ -0,0 +1,156 @@
+use std::marker::PhantomData;
+
+use heed::RwTxn;
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{Db, Error, WriteContext};
+
+/// Get a copy of the value of the key in a read-write transaction. The
+/// values cannot be borrowed since the transaction may modify them.
+pub fn get_owned<'a, K>(db: Db, key: K) -> GetOwned<'a>
+where
+    K: Into<Vec<u8>>,
+{
+    GetOwned {
+        db,
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get_owned`
+#[derive(Debug)]
+#[must_use]
+pub struct GetOwned<'a> {
+    db: Db,
+    key: Vec<u8>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for GetOwned<'a> {
+    type Ctx = WriteContext<'a>;
+    type Item = Option<Vec<u8>>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut WriteContext<'a>) -> Result<Self::Item, Self::Err> {
+        Ok(self.db.get(ctx.txn(), &self.key)?.map(<[u8]>::to_vec))
+    }
+}
+
+impl<'a> Visit for GetOwned<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get_owned"));
+    }
+}
+
+/// Set the value of the key.
+pub fn put<'a, K, V>(db: Db, key: K, value: V) -> Put<'a>
+where
+    K: Into<Vec<u8>>,
+    V: Into<Vec<u8>>,
+{
+    Put {
+        db,
+        key: key.into(),
+        value: value.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `put`
+#[derive(Debug)]
+#[must_use]
+pub struct Put<'a> {
+    db: Db,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for Put<'a> {
+    type Ctx = WriteContext<'a>;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut WriteContext<'a>) -> Result<Self::Item, Self::Err> {
+        Ok(self.db.put(ctx.txn_mut(), &self.key, &self.value)?)
+    }
+}
+
+impl<'a> Visit for Put<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("put"));
+    }
+}
+
+/// Delete the key, and return whether it existed.
+pub fn delete<'a, K>(db: Db, key: K) -> Delete<'a>
+where
+    K: Into<Vec<u8>>,
+{
+    Delete {
+        db,
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `delete`
+#[derive(Debug)]
+#[must_use]
+pub struct Delete<'a> {
+    db: Db,
+    key: Vec<u8>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for Delete<'a> {
+    type Ctx = WriteContext<'a>;
+    type Item = bool;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut WriteContext<'a>) -> Result<Self::Item, Self::Err> {
+        Ok(self.db.delete(ctx.txn_mut(), &self.key)?)
+    }
+}
+
+impl<'a> Visit for Delete<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete"));
+    }
+}
+
+/// Receive the read-write transaction and perform computation.
+pub fn with_write_txn<'a, F, T, E>(f: F) -> WithWriteTxn<'a, F>
+where
+    F: Fn(&mut RwTxn<'a>) -> Result<T, E>,
+{
+    WithWriteTxn { f, _phantom: PhantomData }
+}
+
+/// The result of `with_write_txn`
+#[derive(Debug)]
+#[must_use]
+pub struct WithWriteTxn<'a, F> {
+    f: F,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, F, T, E> Transaction for WithWriteTxn<'a, F>
+where
+    F: Fn(&mut RwTxn<'a>) -> Result<T, E>,
+{
+    type Ctx = WriteContext<'a>;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut WriteContext<'a>) -> Result<Self::Item, Self::Err> {
+        (self.f)(ctx.txn_mut())
+    }
+}
+
+impl<'a, F> Visit for WithWriteTxn<'a, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_write_txn"));
+    }
+}
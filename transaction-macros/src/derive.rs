@@ -0,0 +1,82 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{DeriveInput, Error, Ident, LitStr, Type};
+
+// the arguments of `#[transaction(..)]` on the command
+struct Args {
+    ctx: Option<Type>,
+    item: Option<Type>,
+    err: Option<Type>,
+    execute: Option<Ident>,
+    label: Option<LitStr>,
+}
+
+pub(crate) fn expand(input: DeriveInput) -> Result<TokenStream, Error> {
+    let args = args(&input)?;
+    let missing = |name: &str| {
+        Error::new(
+            Span::call_site(),
+            format!("#[derive(IntoTransaction)] needs `#[transaction({} = ..)]`", name),
+        )
+    };
+    let ctx = args.ctx.ok_or_else(|| missing("ctx"))?;
+    let item = args.item.ok_or_else(|| missing("item"))?;
+    let err = args.err.ok_or_else(|| missing("err"))?;
+    let execute = args.execute.unwrap_or_else(|| Ident::new("execute", Span::call_site()));
+    let name = &input.ident;
+    let label = args
+        .label
+        .unwrap_or_else(|| LitStr::new(&name.to_string(), name.span()));
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::transaction::Transaction for #name #ty_generics #where_clause {
+            type Ctx = #ctx;
+            type Item = #item;
+            type Err = #err;
+
+            fn run(&self, ctx: &mut Self::Ctx) -> ::std::result::Result<Self::Item, Self::Err> {
+                #name::#execute(self, ctx)
+            }
+
+            fn label(&self) -> ::std::option::Option<&str> {
+                ::std::option::Option::Some(#label)
+            }
+        }
+
+        impl #impl_generics ::transaction::Visit for #name #ty_generics #where_clause {
+            fn accept(&self, visitor: &mut dyn ::transaction::Visitor) {
+                ::transaction::visit_leaf(visitor, ::transaction::Node::new(#label));
+            }
+        }
+    })
+}
+
+fn args(input: &DeriveInput) -> Result<Args, Error> {
+    let mut args = Args {
+        ctx: None,
+        item: None,
+        err: None,
+        execute: None,
+        label: None,
+    };
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("transaction")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("ctx") {
+                args.ctx = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("item") {
+                args.item = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("err") {
+                args.err = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("execute") {
+                args.execute = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("label") {
+                args.label = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `ctx`, `item`, `err`, `execute` or `label`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(args)
+}
@@ -0,0 +1,299 @@
+//! Attribute macros for [transaction](https://docs.rs/transaction)
+//!
+//! `#[transaction]` turns a function written over a context with plain
+//! `let x = step?;` statements into a function returning `impl Transaction`,
+//! so transactions are composed without the closures of `and_then`.
+//!
+//! `#[derive(IntoTransaction)]` makes a struct holding the parameters of a
+//! command, e.g. `TransferMoney { from, to, amount }`, a transaction
+//! running its `execute` method.
+
+extern crate proc_macro;
+
+mod derive;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    parse_macro_input, parse_quote, DeriveInput, Error, Expr, ExprAsync, ExprClosure, FnArg, GenericArgument,
+    GenericParam, Ident, Item, ItemFn, Pat, PathArguments, ReturnType, Type,
+};
+
+/// Turn a function over a context into a function returning the
+/// transaction running its body.
+///
+/// The first parameter of the function is the context, `&mut Ctx`, and the
+/// function returns `Result<Item, Err>`. The parameter is removed and the
+/// function returns `impl Transaction<Ctx = Ctx, Item = Item, Err = Err>`
+/// instead, whose `run` runs the body with the context. In the body,
+/// `step?` runs the transaction `step` on the context, and is `?` as usual
+/// when `step` is a `Result`. So `let x = step1?; step2(x)?; Ok(x)` is the
+/// same transaction as `step1.and_then(|x| step2(x).map(move |_| x))`, also
+/// converting the errors by `From` like `?`. The `?`s in closures, async
+/// blocks and macros are left alone.
+///
+/// The body runs every time the transaction runs, so it uses the other
+/// parameters by reference or by copy. The lifetimes of the parameters
+/// which are references are to be named, e.g. `name: &'a str`.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::prelude::*;
+/// use transaction_macros::transaction;
+///
+/// type Accounts = Vec<i64>;
+///
+/// fn balance(account: usize) -> impl Transaction<Ctx = Accounts, Item = i64, Err = String> {
+///     with_ctx(move |accounts: &mut Accounts| {
+///         accounts.get(account).cloned().ok_or(format!("no account {}", account))
+///     })
+/// }
+///
+/// fn deposit(account: usize, amount: i64) -> impl Transaction<Ctx = Accounts, Item = (), Err = String> {
+///     balance(account).and_then(move |b| with_ctx(move |accounts: &mut Accounts| {
+///         accounts[account] = b + amount;
+///         Ok(())
+///     }))
+/// }
+///
+/// #[transaction]
+/// fn transfer(accounts: &mut Accounts, from: usize, to: usize, amount: i64) -> Result<i64, String> {
+///     let available = balance(from)?;
+///     if available < amount {
+///         return Err(format!("insufficient balance {}", available));
+///     }
+///     deposit(from, -amount)?;
+///     deposit(to, amount)?;
+///     Ok(available - amount)
+/// }
+///
+/// let mut accounts = vec![100, 0];
+/// assert_eq!(transfer(0, 1, 30).run(&mut accounts), Ok(70));
+/// assert_eq!(accounts, vec![70, 30]);
+///
+/// // it composes with the other transactions
+/// let tx = transfer(0, 1, 100).or_else(|_| transfer(0, 1, 70));
+/// assert_eq!(tx.run(&mut accounts), Ok(0));
+/// assert_eq!(transfer(0, 2, 0).run(&mut accounts), Err("no account 2".to_string()));
+/// ```
+///
+/// The function must take the context and return a `Result`:
+///
+/// ```compile_fail
+/// use transaction_macros::transaction;
+///
+/// #[transaction]
+/// fn count(ctx: &mut Vec<i64>) -> usize {
+///     ctx.len()
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn transaction(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let e = Error::new(Span::call_site(), "#[transaction] takes no arguments");
+        return e.to_compile_error().into();
+    }
+    let f = parse_macro_input!(item as ItemFn);
+    match expand(f) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Make a command struct a transaction running its method
+/// `fn execute(&self, ctx: &mut Ctx) -> Result<Item, Err>`.
+///
+/// The types of the transaction are given by
+/// `#[transaction(ctx = Ctx, item = Item, err = Err)]`, and
+/// `execute = method` runs another method instead. The transaction is
+/// labeled with the name of the struct, or `label = "name"`, so the runs of
+/// the commands are told apart by the hooks and the metrics. It implements
+/// `Visit` as a leaf of the same name as well.
+///
+/// As `Transaction`, and so `IntoTransaction`, is implemented for the struct
+/// itself, the commands are passed to the combinators and the runners as
+/// they are, and can be kept as `Box<dyn Transaction<..>>`, e.g. in a
+/// registry of the commands of an application.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use transaction::prelude::*;
+/// use transaction_macros::IntoTransaction;
+///
+/// type Accounts = Vec<i64>;
+///
+/// #[derive(IntoTransaction)]
+/// #[transaction(ctx = Accounts, item = i64, err = String)]
+/// struct TransferMoney {
+///     from: usize,
+///     to: usize,
+///     amount: i64,
+/// }
+///
+/// impl TransferMoney {
+///     fn execute(&self, accounts: &mut Accounts) -> Result<i64, String> {
+///         if accounts[self.from] < self.amount {
+///             return Err(format!("insufficient balance {}", accounts[self.from]));
+///         }
+///         accounts[self.from] -= self.amount;
+///         accounts[self.to] += self.amount;
+///         Ok(accounts[self.from])
+///     }
+/// }
+///
+/// #[derive(IntoTransaction)]
+/// #[transaction(ctx = Accounts, item = i64, err = String, execute = total, label = "audit")]
+/// struct Audit;
+///
+/// impl Audit {
+///     fn total(&self, accounts: &mut Accounts) -> Result<i64, String> {
+///         Ok(accounts.iter().sum())
+///     }
+/// }
+///
+/// let mut commands: HashMap<&str, Box<dyn Transaction<Ctx = Accounts, Item = i64, Err = String>>> =
+///     HashMap::new();
+/// commands.insert("pay", Box::new(TransferMoney { from: 0, to: 1, amount: 30 }));
+/// commands.insert("audit", Box::new(Audit));
+///
+/// let mut accounts = vec![100, 0];
+/// assert_eq!(commands["pay"].run(&mut accounts), Ok(70));
+/// assert_eq!(commands["audit"].run(&mut accounts), Ok(100));
+/// assert_eq!(commands["pay"].label(), Some("TransferMoney"));
+///
+/// // and they compose
+/// let tx = TransferMoney { from: 1, to: 0, amount: 10 }.and_then(|_| Audit);
+/// assert_eq!(tx.run(&mut accounts), Ok(100));
+/// assert_eq!(accounts, vec![80, 20]);
+/// ```
+#[proc_macro_derive(IntoTransaction, attributes(transaction))]
+pub fn derive_into_transaction(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match derive::expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(mut f: ItemFn) -> Result<proc_macro2::TokenStream, Error> {
+    if let Some(asyncness) = f.sig.asyncness {
+        return Err(Error::new(asyncness.span(), "#[transaction] functions can't be async"));
+    }
+    let (ctx, ctx_ty) = context(&mut f)?;
+    let (item_ty, err_ty) = result_types(&f.sig.output)?;
+    let lifetimes = f
+        .sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match *param {
+            GenericParam::Lifetime(ref def) => Some(def.lifetime.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    f.sig.output = parse_quote! {
+        -> impl ::transaction::Transaction<Ctx = #ctx_ty, Item = #item_ty, Err = #err_ty> #(+ #lifetimes)*
+    };
+
+    let ctx_ident = match *ctx.pat {
+        Pat::Ident(ref pat) => pat.ident.clone(),
+        _ => unreachable!("checked by `context`"),
+    };
+    let mut body = f.block;
+    Steps { ctx: &ctx_ident }.visit_block_mut(&mut body);
+    let pat = &ctx.pat;
+    f.block = parse_quote! {{
+        ::transaction::with_ctx(move |#pat: &mut #ctx_ty| -> ::std::result::Result<#item_ty, #err_ty> #body)
+    }};
+    Ok(quote!(#f))
+}
+
+// take the context parameter out of the function
+fn context(f: &mut ItemFn) -> Result<(syn::PatType, Type), Error> {
+    let missing = || {
+        Error::new(
+            f.sig.paren_token.span.join(),
+            "#[transaction] functions take the context `ctx: &mut Ctx` first",
+        )
+    };
+    let ctx = match f.sig.inputs.first() {
+        Some(FnArg::Typed(ctx)) => ctx.clone(),
+        Some(FnArg::Receiver(receiver)) => {
+            return Err(Error::new(receiver.span(), "#[transaction] methods are not supported"));
+        }
+        None => return Err(missing()),
+    };
+    let ctx_ty = match *ctx.ty {
+        Type::Reference(ref r) if r.mutability.is_some() => (*r.elem).clone(),
+        _ => return Err(missing()),
+    };
+    if let Pat::Ident(ref pat) = *ctx.pat {
+        if pat.by_ref.is_none() && pat.subpat.is_none() {
+            let inputs = f.sig.inputs.iter().skip(1).cloned().collect();
+            f.sig.inputs = inputs;
+            return Ok((ctx, ctx_ty));
+        }
+    }
+    Err(Error::new(ctx.pat.span(), "the context must be bound to a name"))
+}
+
+// the `T` and `E` of `-> Result<T, E>`
+fn result_types(output: &ReturnType) -> Result<(Type, Type), Error> {
+    let missing = || Error::new(output.span(), "#[transaction] functions return `Result<Item, Err>`");
+    let ty = match *output {
+        ReturnType::Type(_, ref ty) => ty,
+        ReturnType::Default => return Err(missing()),
+    };
+    let segment = match **ty {
+        Type::Path(ref path) if path.qself.is_none() => path.path.segments.last(),
+        _ => None,
+    };
+    let args = match segment {
+        Some(segment) if segment.ident == "Result" => match segment.arguments {
+            PathArguments::AngleBracketed(ref args) => &args.args,
+            _ => return Err(missing()),
+        },
+        _ => return Err(missing()),
+    };
+    let mut types = args.iter().filter_map(|arg| match *arg {
+        GenericArgument::Type(ref ty) => Some(ty.clone()),
+        _ => None,
+    });
+    match (types.next(), types.next(), types.next()) {
+        (Some(item), Some(err), None) => Ok((item, err)),
+        _ => Err(missing()),
+    }
+}
+
+// rewrites `step?` to run the step on the context
+struct Steps<'a> {
+    ctx: &'a Ident,
+}
+
+impl<'a> VisitMut for Steps<'a> {
+    fn visit_expr_mut(&mut self, e: &mut Expr) {
+        if let Expr::Try(ref mut t) = *e {
+            self.visit_expr_mut(&mut t.expr);
+            let step = &t.expr;
+            let ctx = self.ctx;
+            let run = quote_spanned!(step.span()=> ::transaction::__private::Step::step(#step, &mut *#ctx));
+            *t.expr = parse_quote!(#run);
+        } else {
+            visit_mut::visit_expr_mut(self, e);
+        }
+    }
+
+    // the `?`s of closures and async blocks return from them, not from the
+    // body
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+
+    fn visit_expr_async_mut(&mut self, _: &mut ExprAsync) {}
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
@@ -0,0 +1,49 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of in-memory transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A concurrent transaction committed a write to a key the transaction
+    /// wrote after the transaction began
+    Conflict,
+}
+
+/// An error of the in-memory store
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+}
+
+impl Error {
+    pub(crate) fn conflict() -> Self {
+        Error {
+            kind: ErrorKind::Conflict,
+        }
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ErrorKind::Conflict => {
+                f.write_str("the transaction conflicted with a concurrent commit")
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind == ErrorKind::Conflict
+    }
+}
@@ -0,0 +1,464 @@
+//! An in-memory transactional key-value store
+//!
+//! `MemStore` is a map shared by its clones. Transactions run in a
+//! `MemContext` begun on it, which reads a snapshot of the map as of the
+//! beginning and buffers the writes. Committing the context applies the
+//! writes atomically, failing with a retryable `ErrorKind::Conflict` if a
+//! concurrent transaction has committed a write to any of the keys written
+//! since the context began (snapshot isolation). Dropping or rolling back the
+//! context discards the writes. `MemStore::run` does all this for a
+//! transaction, running it again on conflicts.
+//!
+//! It is a reference backend, and a fast test double for transactions
+//! generic over the capabilities: the context provides a clock of the store,
+//! which stands still unless advanced, and random numbers of a seeded
+//! generator, so the runs are reproducible.
+//!
+//! `sim` runs transactions concurrently on a store under a scheduler driven
+//! by a seed, to test their retries on conflicts deterministically.
+//!
+//! # Examples
+//!
+//! ```
+//! use transaction::prelude::*;
+//! use transaction_mem::{delete, get, put, scan, ErrorKind, MemStore};
+//!
+//! # fn main() -> Result<(), transaction_mem::Error> {
+//! let store = MemStore::new();
+//! store.run(put("apple", 3).and_then(|_| put("banana", 5)))?;
+//!
+//! // the writes are discarded by rolling back
+//! let mut ctx = store.begin();
+//! assert_eq!(delete("apple").run(&mut ctx)?, Some(3));
+//! assert_eq!(scan(..).run(&mut ctx)?, vec![("banana", 5)]);
+//! ctx.rollback();
+//! assert_eq!(store.run(scan(..))?, vec![("apple", 3), ("banana", 5)]);
+//!
+//! // a transaction begun earlier doesn't see the later commits
+//! let mut ctx = store.begin();
+//! store.run(put("apple", 4))?;
+//! assert_eq!(get("apple").run(&mut ctx)?, Some(3));
+//! put("apple", 0).run(&mut ctx)?;
+//! assert_eq!(ctx.commit().unwrap_err().kind(), ErrorKind::Conflict);
+//! assert_eq!(store.run(get("apple"))?, Some(4));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeBounds;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use transaction::hooks::{self, Outcome};
+use transaction::runner::{Backend, RunOptions};
+use transaction::{HasClock, HasRng, Snapshots, Transaction};
+
+mod error;
+mod ops;
+pub mod sim;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+struct Shared<K, V> {
+    data: Arc<BTreeMap<K, V>>,
+    // the number of the commits, and the commit which wrote each key last
+    version: u64,
+    written: BTreeMap<K, u64>,
+    now: SystemTime,
+    seed: u64,
+}
+
+/// The in-memory key-value store. The clones share the map.
+pub struct MemStore<K, V> {
+    shared: Arc<Mutex<Shared<K, V>>>,
+}
+
+impl<K, V> MemStore<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Create an empty store. The clock starts at the UNIX epoch and the
+    /// seed of the random numbers is 0.
+    pub fn new() -> Self {
+        MemStore {
+            shared: Arc::new(Mutex::new(Shared {
+                data: Arc::new(BTreeMap::new()),
+                version: 0,
+                written: BTreeMap::new(),
+                now: SystemTime::UNIX_EPOCH,
+                seed: 0,
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared<K, V>> {
+        // a commit panicking in `Clone` leaves part of its writes applied,
+        // which is still a sound map
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Set the time the transactions begun after this get by `now`
+    pub fn set_now(&self, now: SystemTime) {
+        self.lock().now = now;
+    }
+
+    /// Advance the clock of the store
+    pub fn advance(&self, duration: Duration) {
+        self.lock().now += duration;
+    }
+
+    /// Seed the random numbers of the transactions begun after this. Each
+    /// transaction gets a different sequence, determined by the seed and the
+    /// number of the transactions begun before.
+    pub fn seed(&self, seed: u64) {
+        self.lock().seed = seed;
+    }
+
+    /// The committed entries
+    pub fn snapshot(&self) -> Arc<BTreeMap<K, V>> {
+        self.lock().data.clone()
+    }
+
+    /// Put the committed entries back to the snapshot. The keys of either
+    /// are written by it, so the transactions begun before and writing any
+    /// of them conflict.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use transaction::prelude::*;
+    /// use transaction::Snapshots;
+    /// use transaction_mem::{get, put, MemStore};
+    ///
+    /// # fn main() -> Result<(), transaction_mem::Error> {
+    /// let store = MemStore::new();
+    /// store.run(put("apple", 3))?;
+    /// let snapshot = store.snapshot();
+    /// store.run(put("apple", 4).and_then(|_| put("banana", 5)))?;
+    /// store.restore(snapshot);
+    /// assert_eq!(store.run(get("banana"))?, None);
+    ///
+    /// // the contexts undo their writes by their snapshots too
+    /// let mut ctx = store.begin();
+    /// let snapshot = ctx.snapshot();
+    /// put("apple", 0).run(&mut ctx)?;
+    /// ctx.restore(snapshot);
+    /// assert_eq!(get("apple").run(&mut ctx)?, Some(3));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn restore(&self, snapshot: Arc<BTreeMap<K, V>>) {
+        let mut guard = self.lock();
+        let shared = &mut *guard;
+        shared.version += 1;
+        for key in shared.data.keys().chain(snapshot.keys()) {
+            shared.written.insert(key.clone(), shared.version);
+        }
+        shared.data = snapshot;
+    }
+
+    /// Begin a transaction on the snapshot of the committed entries
+    pub fn begin(&self) -> MemContext<K, V> {
+        let mut shared = self.lock();
+        let rng = splitmix64(&mut shared.seed);
+        MemContext {
+            store: self.clone(),
+            snapshot: shared.data.clone(),
+            version: shared.version,
+            writes: BTreeMap::new(),
+            now: shared.now,
+            rng,
+        }
+    }
+
+    /// run the given transaction and commit it, running it again if it
+    /// conflicts with a concurrent one. Pass a reference to run the same
+    /// transaction again.
+    ///
+    /// If the transaction panics, its writes are dropped without being
+    /// committed and the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// use transaction::prelude::*;
+    /// use transaction_mem::{get, put, Error, MemStore};
+    ///
+    /// let store = MemStore::<&str, i32>::new();
+    /// let boom = put("apple", 3).map(|_| -> () { panic!("boom") });
+    /// assert!(panic::catch_unwind(AssertUnwindSafe(|| store.run::<_, Error, _>(boom))).is_err());
+    /// assert_eq!(store.run::<_, Error, _>(get("apple")).unwrap(), None);
+    /// ```
+    ///
+    /// The items of `cached` are cached once the transaction commits, and
+    /// not by the attempts which conflict.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use transaction::prelude::*;
+    /// use transaction::LruCache;
+    /// use transaction_mem::{get, put, Error, MemContext, MemStore};
+    ///
+    /// let store = MemStore::<&str, i32>::new();
+    /// let cache = LruCache::new(100);
+    /// let apple = || get("apple").cached(&cache, |_: &MemContext<&str, i32>| "apple", Duration::from_secs(60));
+    ///
+    /// store.run::<_, Error, _>(put("apple", 3)).unwrap();
+    /// assert_eq!(store.run::<_, Error, _>(apple()).unwrap(), Some(3));
+    /// store.run::<_, Error, _>(put("apple", 4)).unwrap();
+    /// // answered by the cache, without reading the store
+    /// assert_eq!(store.run::<_, Error, _>(apple()).unwrap(), Some(3));
+    /// ```
+    pub fn run<T, E, Tx>(&self, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = MemContext<K, V>, Item = T, Err = E>,
+    {
+        hooks::instrument("mem", tx.label(), Outcome::of, || loop {
+            let mut ctx = self.begin();
+            let t = tx.run(&mut ctx)?;
+            match ctx.commit() {
+                Ok(()) => return Ok(t),
+                Err(e) if e.kind() == ErrorKind::Conflict => {
+                    hooks::retry(tx.label());
+                    #[cfg(feature = "log")]
+                    log::debug!("retry transaction {:?} on conflict", tx.label());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        })
+    }
+}
+
+impl<K, V> Default for MemStore<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn default() -> Self {
+        MemStore::new()
+    }
+}
+
+/// The snapshot is the committed entries
+impl<K, V> Snapshots for MemStore<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Snapshot = Arc<BTreeMap<K, V>>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        MemStore::snapshot(self)
+    }
+
+    fn restore(&mut self, snapshot: Self::Snapshot) {
+        MemStore::restore(self, snapshot)
+    }
+}
+
+/// The base of the runners built by `RunnerBuilder`, running the
+/// transactions like `MemStore::run`
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use transaction::runner::RunnerBuilder;
+/// use transaction::Backoff;
+/// use transaction_mem::{get, put, Error, MemStore};
+///
+/// # fn main() -> Result<(), Error> {
+/// let store = MemStore::new();
+/// let runner = RunnerBuilder::new(store.clone())
+///     .retry(Backoff::exponential(Duration::from_millis(1)).max_retries(3))
+///     .build();
+/// runner.run(put("apple", 3))?;
+/// assert_eq!(runner.run(get("apple"))?, Some(3));
+/// # Ok(())
+/// # }
+/// ```
+impl<K, V, T, E> Backend<T, E> for MemStore<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+    E: From<Error>,
+{
+    type Ctx = MemContext<K, V>;
+
+    fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        MemStore::run(self, tx)
+    }
+}
+
+impl<K, V> Clone for MemStore<K, V> {
+    fn clone(&self) -> Self {
+        MemStore {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for MemStore<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemStore").finish_non_exhaustive()
+    }
+}
+
+/// The context of transactions: a snapshot of the store and the writes
+/// buffered on it.
+pub struct MemContext<K, V> {
+    store: MemStore<K, V>,
+    snapshot: Arc<BTreeMap<K, V>>,
+    version: u64,
+    // `None` deletes the key
+    writes: BTreeMap<K, Option<V>>,
+    now: SystemTime,
+    rng: u64,
+}
+
+impl<K, V> MemContext<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Apply the writes to the store atomically. Fails with
+    /// `ErrorKind::Conflict` without applying any if a transaction committed
+    /// after this one began has written any of the keys.
+    pub fn commit(self) -> Result<(), Error> {
+        let MemContext {
+            store,
+            snapshot,
+            version,
+            writes,
+            ..
+        } = self;
+        // read-only transactions never conflict
+        if writes.is_empty() {
+            return Ok(());
+        }
+        // so that the map is copied only if other transactions still read it
+        drop(snapshot);
+        let mut guard = store.lock();
+        let shared = &mut *guard;
+        let conflicted = writes
+            .keys()
+            .any(|key| shared.written.get(key).is_some_and(|&v| v > version));
+        if conflicted {
+            return Err(Error::conflict());
+        }
+        shared.version += 1;
+        let data = Arc::make_mut(&mut shared.data);
+        for (key, value) in writes {
+            match value {
+                Some(value) => data.insert(key.clone(), value),
+                None => data.remove(&key),
+            };
+            shared.written.insert(key, shared.version);
+        }
+        Ok(())
+    }
+
+    /// Discard the writes. Dropping the context does the same.
+    pub fn rollback(self) {}
+
+    fn get(&self, key: &K) -> Option<V> {
+        match self.writes.get(key) {
+            Some(value) => value.clone(),
+            None => self.snapshot.get(key).cloned(),
+        }
+    }
+
+    // buffer the write and return the previous value
+    fn write(&mut self, key: K, value: Option<V>) -> Option<V> {
+        let prev = self.get(&key);
+        self.writes.insert(key, value);
+        prev
+    }
+
+    fn scan<R>(&self, range: R) -> Vec<(K, V)>
+    where
+        R: RangeBounds<K> + Clone,
+    {
+        let mut entries = self
+            .snapshot
+            .range(range.clone())
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<BTreeMap<_, _>>();
+        for (key, value) in self.writes.range(range) {
+            match value {
+                Some(value) => entries.insert(key.clone(), value.clone()),
+                None => entries.remove(key),
+            };
+        }
+        entries.into_iter().collect()
+    }
+}
+
+/// The snapshot is the writes buffered, so restoring it undoes the writes
+/// made after it
+impl<K, V> Snapshots for MemContext<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    type Snapshot = Writes<K, V>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        Writes {
+            writes: self.writes.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Self::Snapshot) {
+        self.writes = snapshot.writes;
+    }
+}
+
+/// The writes buffered by a `MemContext`, taken by `Snapshots::snapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Writes<K, V> {
+    writes: BTreeMap<K, Option<V>>,
+}
+
+impl<K, V> HasClock for MemContext<K, V> {
+    // the clock of the store when the transaction began
+    fn now(&self) -> SystemTime {
+        self.now
+    }
+}
+
+impl<K, V> HasRng for MemContext<K, V> {
+    fn next_u64(&mut self) -> u64 {
+        splitmix64(&mut self.rng)
+    }
+}
+
+impl<K, V> fmt::Debug for MemContext<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemContext")
+            .field("version", &self.version)
+            .field("writes", &self.writes.len())
+            .finish_non_exhaustive()
+    }
+}
+
+// SplitMix64, which is enough for tests and needs no dependency
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,155 @@
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{Error, MemContext};
+
+/// Get the value of the key. The writes of the transaction are visible.
+pub fn get<K, V>(key: K) -> Get<K, V> {
+    Get {
+        key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get`
+#[derive(Debug)]
+#[must_use]
+pub struct Get<K, V> {
+    key: K,
+    _phantom: PhantomData<fn() -> V>,
+}
+
+impl<K, V> Transaction for Get<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Ctx = MemContext<K, V>;
+    type Item = Option<V>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.get(&self.key))
+    }
+}
+
+impl<K, V> Visit for Get<K, V> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get"));
+    }
+}
+
+/// Set the value of the key, and return the previous one.
+pub fn put<K, V>(key: K, value: V) -> Put<K, V> {
+    Put { key, value }
+}
+
+/// The result of `put`
+#[derive(Debug)]
+#[must_use]
+pub struct Put<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K, V> Transaction for Put<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Ctx = MemContext<K, V>;
+    type Item = Option<V>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.write(self.key.clone(), Some(self.value.clone())))
+    }
+}
+
+impl<K, V> Visit for Put<K, V> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("put"));
+    }
+}
+
+/// Delete the key, and return its value.
+pub fn delete<K, V>(key: K) -> Delete<K, V> {
+    Delete {
+        key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `delete`
+#[derive(Debug)]
+#[must_use]
+pub struct Delete<K, V> {
+    key: K,
+    _phantom: PhantomData<fn() -> V>,
+}
+
+impl<K, V> Transaction for Delete<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Ctx = MemContext<K, V>;
+    type Item = Option<V>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.write(self.key.clone(), None))
+    }
+}
+
+impl<K, V> Visit for Delete<K, V> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete"));
+    }
+}
+
+/// Get the entries in the range of keys, in the order of the keys. The writes
+/// of the transaction are visible. Like `BTreeMap::range`, it panics if the
+/// range starts after it ends.
+pub fn scan<K, V, R>(range: R) -> Scan<K, V>
+where
+    K: Clone,
+    R: RangeBounds<K>,
+{
+    Scan {
+        start: range.start_bound().cloned(),
+        end: range.end_bound().cloned(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `scan`
+#[derive(Debug)]
+#[must_use]
+pub struct Scan<K, V> {
+    start: Bound<K>,
+    end: Bound<K>,
+    _phantom: PhantomData<fn() -> V>,
+}
+
+impl<K, V> Transaction for Scan<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    type Ctx = MemContext<K, V>;
+    type Item = Vec<(K, V)>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.scan((self.start.as_ref(), self.end.as_ref())))
+    }
+}
+
+impl<K, V> Visit for Scan<K, V> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("scan"));
+    }
+}
@@ -0,0 +1,581 @@
+//! A deterministic simulation of concurrent transactions on a `MemStore`
+//!
+//! `Simulation` runs tasks, each running a transaction until it commits like
+//! `MemStore::run`, on a single thread under a scheduler driven by a seed.
+//! An attempt of a task is two steps: beginning, which takes the snapshot
+//! and runs the transaction on it, and committing. Since a transaction sees
+//! only its snapshot and its own writes, when the transaction runs between
+//! the two makes no difference, so the interleavings of the steps are all
+//! the interleavings of the tasks which matter to the store. The scheduler
+//! picks a random task to step each time, and can also advance the clock of
+//! the store between the steps and fail commits with spurious conflicts, to
+//! exercise the retries. The same seed gives the same schedule, so a failure
+//! is reproduced by its seed.
+//!
+//! `check` runs a scenario under many seeds and checks the store after
+//! each. The transactions should have no effects out of the store, which
+//! would happen in the order of the beginnings instead.
+//!
+//! When a scenario fails, `check` shrinks the `Schedule` of the failure:
+//! it leaves out tasks, drops steps and takes out the spurious conflicts
+//! and the clock advances as long as the scenario still fails with the same
+//! message, and prints the minimal schedule, which `replay` runs again.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use transaction::prelude::*;
+//! use transaction_mem::{get, put, sim, Error, MemContext};
+//!
+//! fn deposit(amount: i64) -> impl Transaction<Ctx = MemContext<&'static str, i64>, Item = i64, Err = Error> {
+//!     get("balance").and_then(move |balance| {
+//!         let balance = balance.unwrap_or(0) + amount;
+//!         put("balance", balance).map(move |_| balance)
+//!     })
+//! }
+//!
+//! sim::check(0..100, |sim| {
+//!     sim.spurious_conflicts(0.2).max_tick(Duration::from_millis(10));
+//!     let tasks = vec![sim.spawn(deposit(10)), sim.spawn(deposit(20)), sim.spawn(deposit(30))];
+//!     move |store| {
+//!         // no deposit is lost whatever the interleaving
+//!         assert_eq!(*store.snapshot().get("balance").unwrap(), 60);
+//!         let mut balances = tasks.iter().map(|t| t.take().unwrap().unwrap()).collect::<Vec<_>>();
+//!         balances.sort();
+//!         assert_eq!(balances.last(), Some(&60));
+//!     }
+//! });
+//! ```
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use transaction::Transaction;
+
+use crate::{splitmix64, Error, ErrorKind, MemContext, MemStore};
+
+// the steps after which a simulation is considered stuck
+const MAX_STEPS: usize = 100_000;
+
+// the result of a task, shared with its `Task`
+type Slot<T, E> = Rc<RefCell<Option<Result<T, E>>>>;
+
+/// What happened in a step of a `Simulation`, naming the tasks by the order
+/// they are spawned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// The task began an attempt and ran its transaction
+    Begin(usize),
+    /// The transaction of the task failed, which ends the task
+    Abort(usize),
+    /// The task committed, which ends the task
+    Commit(usize),
+    /// The commit of the task conflicted, so the task tries again
+    Conflict(usize),
+    /// The commit of the task was failed with a spurious conflict, so the
+    /// task tries again
+    Spurious(usize),
+    /// The clock of the store advanced
+    Tick(Duration),
+}
+
+/// A step of a `Schedule`: the clock advances by `tick`, then the task
+/// begins or commits, failing the commit with a spurious conflict if
+/// `spurious`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Step {
+    /// The task, by the order it is spawned
+    pub task: usize,
+    /// Whether a commit fails with a spurious conflict
+    pub spurious: bool,
+    /// The advance of the clock before the step
+    pub tick: Duration,
+}
+
+/// The choices of the scheduler of a `Simulation`, to run it again by
+/// `replay`.
+///
+/// The tasks `skipped` are left out. The steps of the tasks which have
+/// already ended are skipped, and once the steps run out the tasks left run
+/// one after another in the order they are spawned.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Schedule {
+    /// The seed of the random numbers of the store
+    pub seed: u64,
+    /// The tasks left out
+    pub skipped: Vec<usize>,
+    /// The steps
+    pub steps: Vec<Step>,
+}
+
+// where the scheduler takes its choices from
+enum Source {
+    Random(u64),
+    Replay(Vec<Step>, usize),
+}
+
+/// The simulation of tasks running transactions concurrently on a store,
+/// scheduled by a seed
+pub struct Simulation<K, V> {
+    store: MemStore<K, V>,
+    seed: u64,
+    source: Source,
+    skipped: Vec<usize>,
+    // the tasks not ended, with their numbers
+    tasks: Vec<(usize, Box<dyn Process<K, V>>)>,
+    spawned: usize,
+    spurious: f64,
+    max_tick: Duration,
+    steps: Vec<Step>,
+    trace: Vec<Event>,
+}
+
+impl<K, V> Simulation<K, V>
+where
+    K: Ord + Clone + 'static,
+    V: Clone + 'static,
+{
+    /// A simulation on an empty store, whose random numbers are also
+    /// seeded by the seed
+    pub fn new(seed: u64) -> Self {
+        let mut rng = seed;
+        let store_seed = splitmix64(&mut rng);
+        Simulation::with_source(store_seed, Source::Random(rng), Vec::new())
+    }
+
+    /// A simulation following the schedule
+    pub fn replay(schedule: &Schedule) -> Self {
+        Simulation::with_source(
+            schedule.seed,
+            Source::Replay(schedule.steps.clone(), 0),
+            schedule.skipped.clone(),
+        )
+    }
+
+    fn with_source(seed: u64, source: Source, skipped: Vec<usize>) -> Self {
+        let store = MemStore::new();
+        store.seed(seed);
+        Simulation {
+            store,
+            seed,
+            source,
+            skipped,
+            tasks: Vec::new(),
+            spawned: 0,
+            spurious: 0.0,
+            max_tick: Duration::from_secs(0),
+            steps: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    /// The store, e.g. to fill it before running
+    pub fn store(&self) -> &MemStore<K, V> {
+        &self.store
+    }
+
+    /// Fail each commit with a spurious conflict with the probability.
+    /// Replayed simulations follow their schedule instead.
+    pub fn spurious_conflicts(&mut self, probability: f64) -> &mut Self {
+        self.spurious = probability;
+        self
+    }
+
+    /// Advance the clock of the store by a random duration up to `max`
+    /// before each step. Replayed simulations follow their schedule instead.
+    pub fn max_tick(&mut self, max: Duration) -> &mut Self {
+        self.max_tick = max;
+        self
+    }
+
+    /// Add a task running the transaction until it commits or fails. The
+    /// result is taken from the returned `Task` after the run.
+    pub fn spawn<Tx>(&mut self, tx: Tx) -> Task<Tx::Item, Tx::Err>
+    where
+        Tx: Transaction<Ctx = MemContext<K, V>> + 'static,
+        Tx::Err: From<Error>,
+    {
+        let result = Rc::new(RefCell::new(None));
+        let attempts = Attempts {
+            tx,
+            pending: None,
+            result: result.clone(),
+        };
+        if !self.skipped.contains(&self.spawned) {
+            self.tasks.push((self.spawned, Box::new(attempts)));
+        }
+        self.spawned += 1;
+        Task { result }
+    }
+
+    /// Run the tasks until all of them end, and return what happened
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tasks don't end in 100000 steps, e.g. since every
+    /// commit fails with a spurious conflict
+    pub fn run(&mut self) -> &[Event] {
+        while !self.tasks.is_empty() {
+            if self.steps.len() >= MAX_STEPS {
+                panic!("the tasks didn't end in {} steps", MAX_STEPS);
+            }
+            let step = self.next_step();
+            if step.tick > Duration::from_secs(0) {
+                self.store.advance(step.tick);
+                self.trace.push(Event::Tick(step.tick));
+            }
+            let i = self
+                .tasks
+                .iter()
+                .position(|&(task, _)| task == step.task)
+                .expect("the step of a task not ended");
+            let (task, ref mut process) = self.tasks[i];
+            let event = process.step(task, &self.store, step.spurious);
+            self.steps.push(step);
+            self.trace.push(event);
+            if let Event::Abort(_) | Event::Commit(_) = event {
+                self.tasks.remove(i);
+            }
+        }
+        &self.trace
+    }
+
+    // the next step of a task not ended
+    fn next_step(&mut self) -> Step {
+        match self.source {
+            Source::Random(ref mut rng) => {
+                let tick = if self.max_tick > Duration::from_secs(0) {
+                    self.max_tick.mul_f64(unit(splitmix64(rng)))
+                } else {
+                    Duration::from_secs(0)
+                };
+                let i = (splitmix64(rng) % self.tasks.len() as u64) as usize;
+                let spurious = unit(splitmix64(rng)) < self.spurious;
+                Step {
+                    task: self.tasks[i].0,
+                    spurious,
+                    tick,
+                }
+            }
+            Source::Replay(ref steps, ref mut next) => {
+                let tasks = &self.tasks;
+                let found = steps[*next..]
+                    .iter()
+                    .position(|step| tasks.iter().any(|&(task, _)| task == step.task));
+                match found {
+                    Some(found) => {
+                        let step = steps[*next + found];
+                        *next += found + 1;
+                        step
+                    }
+                    None => {
+                        *next = steps.len();
+                        Step {
+                            task: self.tasks[0].0,
+                            spurious: false,
+                            tick: Duration::from_secs(0),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// What happened so far
+    pub fn trace(&self) -> &[Event] {
+        &self.trace
+    }
+
+    /// The schedule followed so far
+    pub fn schedule(&self) -> Schedule {
+        Schedule {
+            seed: self.seed,
+            skipped: self.skipped.clone(),
+            steps: self.steps.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Simulation<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Simulation")
+            .field("seed", &self.seed)
+            .field("tasks", &self.tasks.len())
+            .field("spurious", &self.spurious)
+            .field("max_tick", &self.max_tick)
+            .field("trace", &self.trace)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Run the scenario under each of the seeds. `scenario` fills the store
+/// and spawns the tasks of the simulation, and returns a function checking
+/// the store after all the tasks end.
+///
+/// A panic of a transaction or of the check is propagated after printing
+/// the seed and the minimal schedule failing with the same message.
+pub fn check<K, V, F, C>(seeds: Range<u64>, scenario: F)
+where
+    K: Ord + Clone + 'static,
+    V: Clone + 'static,
+    F: Fn(&mut Simulation<K, V>) -> C,
+    C: FnOnce(&MemStore<K, V>),
+{
+    for seed in seeds {
+        let (e, schedule, tasks) = match run_scenario(&scenario, Simulation::new(seed)) {
+            Ok(()) => continue,
+            Err(failure) => failure,
+        };
+        let message = panic_message(&*e);
+        let minimal = quietly(|| shrink(&scenario, schedule, tasks, &message));
+        eprintln!(
+            "the simulation failed with seed {}: {}\nthe minimal schedule: {:?}",
+            seed, message, minimal
+        );
+        panic::resume_unwind(e);
+    }
+}
+
+/// Run the scenario following the schedule, e.g. the minimal one printed by
+/// `check`, and check the store like `check`
+///
+/// # Examples
+///
+/// Snapshot isolation lets two withdrawals checking the total of two
+/// accounts overdraw them, as `check` finds and shrinks to the schedule
+/// where the one withdrawing from `b` begins first.
+///
+/// ```should_panic
+/// use transaction::prelude::*;
+/// use transaction_mem::sim::{self, Schedule, Step};
+/// use transaction_mem::{get, put, Error, MemContext};
+/// # use std::time::Duration;
+///
+/// fn withdraw(from: &'static str, other: &'static str) -> impl Transaction<Ctx = MemContext<&'static str, i64>, Item = (), Err = Error> {
+///     get(from).join(get(other)).and_then(move |(a, b)| {
+///         let (a, b) = (a.unwrap_or(0), b.unwrap_or(0));
+///         put(from, if a + b >= 10 { a - 10 } else { a }).map(|_| ())
+///     })
+/// }
+///
+/// let schedule = Schedule {
+///     seed: 0,
+///     skipped: vec![],
+///     steps: vec![Step { task: 1, spurious: false, tick: Duration::from_secs(0) }],
+/// };
+/// sim::replay(&schedule, |sim| {
+///     sim.store().run(put("a", 5).and_then(|_| put("b", 5))).unwrap();
+///     sim.spawn(withdraw("a", "b"));
+///     sim.spawn(withdraw("b", "a"));
+///     |store| {
+///         let accounts = store.snapshot();
+///         assert!(accounts["a"] + accounts["b"] >= 0, "overdrawn");
+///     }
+/// });
+/// ```
+pub fn replay<K, V, F, C>(schedule: &Schedule, scenario: F)
+where
+    K: Ord + Clone + 'static,
+    V: Clone + 'static,
+    F: FnOnce(&mut Simulation<K, V>) -> C,
+    C: FnOnce(&MemStore<K, V>),
+{
+    let mut sim = Simulation::replay(schedule);
+    let verify = scenario(&mut sim);
+    sim.run();
+    verify(sim.store());
+}
+
+// the panic of a failed run, with the schedule and the number of the tasks
+type Failure = (Box<dyn Any + Send>, Schedule, usize);
+
+fn run_scenario<K, V, F, C>(scenario: &F, mut sim: Simulation<K, V>) -> Result<(), Failure>
+where
+    K: Ord + Clone + 'static,
+    V: Clone + 'static,
+    F: Fn(&mut Simulation<K, V>) -> C,
+    C: FnOnce(&MemStore<K, V>),
+{
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+        let verify = scenario(&mut sim);
+        sim.run();
+        verify(sim.store());
+    }));
+    ret.map_err(|e| (e, sim.schedule(), sim.spawned))
+}
+
+// shrink the schedule while the scenario still fails with the message
+fn shrink<K, V, F, C>(scenario: &F, mut schedule: Schedule, tasks: usize, message: &str) -> Schedule
+where
+    K: Ord + Clone + 'static,
+    V: Clone + 'static,
+    F: Fn(&mut Simulation<K, V>) -> C,
+    C: FnOnce(&MemStore<K, V>),
+{
+    let fails = |candidate: &Schedule| match run_scenario(scenario, Simulation::replay(candidate)) {
+        Ok(()) => false,
+        Err((e, _, _)) => panic_message(&*e) == message,
+    };
+    loop {
+        let mut shrunk = false;
+        // leave out the tasks
+        for task in 0..tasks {
+            if schedule.skipped.contains(&task) {
+                continue;
+            }
+            let mut candidate = schedule.clone();
+            candidate.skipped.push(task);
+            candidate.steps.retain(|step| step.task != task);
+            if fails(&candidate) {
+                schedule = candidate;
+                shrunk = true;
+            }
+        }
+        // drop the steps, halving the chunks dropped at once
+        let mut chunk = (schedule.steps.len() / 2).max(1);
+        while chunk > 0 {
+            let mut start = 0;
+            while start + chunk <= schedule.steps.len() {
+                let mut candidate = schedule.clone();
+                candidate.steps.drain(start..start + chunk);
+                if fails(&candidate) {
+                    schedule = candidate;
+                    shrunk = true;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+        // take out the spurious conflicts and the clock advances
+        for i in 0..schedule.steps.len() {
+            let step = schedule.steps[i];
+            if !step.spurious && step.tick == Duration::from_secs(0) {
+                continue;
+            }
+            let mut candidate = schedule.clone();
+            candidate.steps[i] = Step {
+                spurious: false,
+                tick: Duration::from_secs(0),
+                ..step
+            };
+            if fails(&candidate) {
+                schedule = candidate;
+                shrunk = true;
+            }
+        }
+        if !shrunk {
+            return schedule;
+        }
+    }
+}
+
+fn panic_message(e: &(dyn Any + Send)) -> String {
+    match e.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => e.downcast_ref::<String>().cloned().unwrap_or_default(),
+    }
+}
+
+// run `f` without printing the panics of this thread
+fn quietly<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let hook: Arc<dyn Fn(&PanicHookInfo) + Send + Sync> = panic::take_hook().into();
+    let id = thread::current().id();
+    let others = hook.clone();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().id() != id {
+            others(info)
+        }
+    }));
+    let ret = f();
+    drop(panic::take_hook());
+    panic::set_hook(Box::new(move |info| hook(info)));
+    ret
+}
+
+// a number in [0, 1) from the 53 high bits
+fn unit(n: u64) -> f64 {
+    (n >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The result of a task of a `Simulation`
+#[derive(Debug)]
+pub struct Task<T, E> {
+    result: Slot<T, E>,
+}
+
+impl<T, E> Task<T, E> {
+    /// Take the result of the transaction, or `None` if the task has not
+    /// ended or the result is already taken
+    pub fn take(&self) -> Option<Result<T, E>> {
+        self.result.borrow_mut().take()
+    }
+}
+
+// a task as the steps of its attempts
+trait Process<K, V> {
+    fn step(&mut self, task: usize, store: &MemStore<K, V>, spurious: bool) -> Event;
+}
+
+struct Attempts<Tx>
+where
+    Tx: Transaction,
+{
+    tx: Tx,
+    // the attempt begun, to be committed
+    pending: Option<(Tx::Ctx, Tx::Item)>,
+    result: Slot<Tx::Item, Tx::Err>,
+}
+
+impl<K, V, Tx> Process<K, V> for Attempts<Tx>
+where
+    K: Ord + Clone,
+    V: Clone,
+    Tx: Transaction<Ctx = MemContext<K, V>>,
+    Tx::Err: From<Error>,
+{
+    fn step(&mut self, task: usize, store: &MemStore<K, V>, spurious: bool) -> Event {
+        let (ctx, t) = match self.pending.take() {
+            None => {
+                let mut ctx = store.begin();
+                return match self.tx.run(&mut ctx) {
+                    Ok(t) => {
+                        self.pending = Some((ctx, t));
+                        Event::Begin(task)
+                    }
+                    Err(e) => {
+                        *self.result.borrow_mut() = Some(Err(e));
+                        Event::Abort(task)
+                    }
+                };
+            }
+            Some(pending) => pending,
+        };
+        if spurious {
+            return Event::Spurious(task);
+        }
+        match ctx.commit() {
+            Ok(()) => {
+                *self.result.borrow_mut() = Some(Ok(t));
+                Event::Commit(task)
+            }
+            Err(ref e) if e.kind() == ErrorKind::Conflict => Event::Conflict(task),
+            Err(e) => {
+                *self.result.borrow_mut() = Some(Err(e.into()));
+                Event::Abort(task)
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use mongodb::bson::Document;
+use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
+use mongodb::Collection;
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::{Error, MongoContext};
+
+/// Find the first document matching the filter.
+pub fn find_one(collection: Collection<Document>, filter: Document) -> FindOne {
+    FindOne { collection, filter }
+}
+
+/// The result of `find_one`
+#[derive(Debug)]
+#[must_use]
+pub struct FindOne {
+    collection: Collection<Document>,
+    filter: Document,
+}
+
+impl AsyncTransaction for FindOne {
+    type Ctx = MongoContext;
+    type Item = Option<Document>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let find = self.collection.find_one(self.filter.clone());
+            Ok(find.session(ctx.session_mut()).await?)
+        })
+    }
+}
+
+impl Visit for FindOne {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("find_one"));
+    }
+}
+
+/// Insert the document.
+pub fn insert_one(collection: Collection<Document>, document: Document) -> InsertOne {
+    InsertOne { collection, document }
+}
+
+/// The result of `insert_one`
+#[derive(Debug)]
+#[must_use]
+pub struct InsertOne {
+    collection: Collection<Document>,
+    document: Document,
+}
+
+impl AsyncTransaction for InsertOne {
+    type Ctx = MongoContext;
+    type Item = InsertOneResult;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let insert = self.collection.insert_one(&self.document);
+            Ok(insert.session(ctx.session_mut()).await?)
+        })
+    }
+}
+
+impl Visit for InsertOne {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("insert_one"));
+    }
+}
+
+/// Update the first document matching the filter with the update document,
+/// e.g. `doc! { "$inc": { "balance": 10 } }`.
+pub fn update_one(collection: Collection<Document>, filter: Document, update: Document) -> UpdateOne {
+    UpdateOne {
+        collection,
+        filter,
+        update,
+    }
+}
+
+/// The result of `update_one`
+#[derive(Debug)]
+#[must_use]
+pub struct UpdateOne {
+    collection: Collection<Document>,
+    filter: Document,
+    update: Document,
+}
+
+impl AsyncTransaction for UpdateOne {
+    type Ctx = MongoContext;
+    type Item = UpdateResult;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let update = self.collection.update_one(self.filter.clone(), self.update.clone());
+            Ok(update.session(ctx.session_mut()).await?)
+        })
+    }
+}
+
+impl Visit for UpdateOne {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("update_one"));
+    }
+}
+
+/// Delete the first document matching the filter.
+pub fn delete_one(collection: Collection<Document>, filter: Document) -> DeleteOne {
+    DeleteOne { collection, filter }
+}
+
+/// The result of `delete_one`
+#[derive(Debug)]
+#[must_use]
+pub struct DeleteOne {
+    collection: Collection<Document>,
+    filter: Document,
+}
+
+impl AsyncTransaction for DeleteOne {
+    type Ctx = MongoContext;
+    type Item = DeleteResult;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let delete = self.collection.delete_one(self.filter.clone());
+            Ok(delete.session(ctx.session_mut()).await?)
+        })
+    }
+}
+
+impl Visit for DeleteOne {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete_one"));
+    }
+}
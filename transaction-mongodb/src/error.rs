@@ -0,0 +1,76 @@
+use std::error;
+use std::fmt;
+
+use mongodb::error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use transaction::Retryable;
+
+/// The classification of the errors of mongodb by their labels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Labeled `TransientTransactionError`: the whole transaction can be run
+    /// again
+    TransientTransaction,
+    /// Labeled `UnknownTransactionCommitResult`: the commit may or may not
+    /// have been applied. The commit is retried by the runner but the
+    /// transaction is not.
+    UnknownCommitResult,
+    /// Any other error
+    Other,
+}
+
+/// An error of mongodb together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: mongodb::error::Error,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of mongodb
+    pub fn get_ref(&self) -> &mongodb::error::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of mongodb
+    pub fn into_inner(self) -> mongodb::error::Error {
+        self.inner
+    }
+}
+
+impl From<mongodb::error::Error> for Error {
+    fn from(inner: mongodb::error::Error) -> Self {
+        let kind = if inner.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+            ErrorKind::TransientTransaction
+        } else if inner.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+            ErrorKind::UnknownCommitResult
+        } else {
+            ErrorKind::Other
+        };
+        Error { kind, inner }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        // running the transaction again after an unknown commit result may
+        // apply it twice
+        self.kind == ErrorKind::TransientTransaction
+    }
+}
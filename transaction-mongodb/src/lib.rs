@@ -0,0 +1,238 @@
+//! Transaction backend for mongodb
+//!
+//! Asynchronous transactions run in a `MongoContext`, a `ClientSession` on
+//! which a multi-document transaction is started when acquired. The [tokio
+//! runner](transaction_tokio::Runner) commits the transaction when it
+//! succeeds and aborts it otherwise.
+//!
+//! The retries follow the transactions specification of MongoDB:
+//!
+//! * a commit failing with the `UnknownTransactionCommitResult` label is
+//!   retried until it succeeds, fails otherwise or the commit timeout of
+//!   `MongoClient` passes.
+//! * a transaction failing with the `TransientTransactionError` label, at
+//!   commit or before, is `Retryable`, so `Runner::run_async_retry` runs the
+//!   whole transaction again on a new session.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use mongodb::bson::{doc, Document};
+//! use transaction::async_tx::{AsyncTransaction, AsyncTransactionExt};
+//! use transaction::Backoff;
+//! use transaction_mongodb::{update_one, MongoClient, MongoRunner};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), transaction_mongodb::Error> {
+//!     let client = mongodb::Client::with_uri_str("mongodb://localhost/?replicaSet=rs0").await?;
+//!     let accounts = client.database("bank").collection::<Document>("accounts");
+//!     let runner = MongoRunner::new(MongoClient::new(client));
+//!
+//!     let transfer = update_one(accounts.clone(), doc! { "_id": "alice" }, doc! { "$inc": { "balance": -10 } })
+//!         .and_then(move |_| update_one(accounts.clone(), doc! { "_id": "bob" }, doc! { "$inc": { "balance": 10 } }));
+//!     let policy = Backoff::exponential(Duration::from_millis(10)).max_retries(5);
+//!     runner.run_async_retry(transfer, policy).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use futures::future::{BoxFuture, FutureExt};
+use mongodb::error::UNKNOWN_TRANSACTION_COMMIT_RESULT;
+use mongodb::options::TransactionOptions;
+use mongodb::{Client, ClientSession};
+use transaction::hooks::Outcome;
+use transaction_tokio::{AsyncConnection, AsyncPool, Runner, TestRunner};
+
+mod collection;
+mod error;
+
+pub use crate::collection::*;
+pub use crate::error::*;
+
+/// How long a commit with an unknown result is retried by default, after the
+/// convenient transaction API of the drivers
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The context of the transactions: a session of mongodb in a transaction.
+///
+/// It implements `AsyncConnection`, so pools of sessions can be run by the
+/// `PooledRunner` of transaction-tokio.
+pub struct MongoContext {
+    session: ClientSession,
+}
+
+impl MongoContext {
+    /// Wrap a session. No transaction is started yet.
+    pub fn new(session: ClientSession) -> Self {
+        MongoContext { session }
+    }
+
+    /// The session
+    pub fn session(&self) -> &ClientSession {
+        &self.session
+    }
+
+    /// The session, to pass to the operations of mongodb
+    pub fn session_mut(&mut self) -> &mut ClientSession {
+        &mut self.session
+    }
+}
+
+impl fmt::Debug for MongoContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MongoContext").finish_non_exhaustive()
+    }
+}
+
+impl AsyncConnection for MongoContext {
+    type Error = Error;
+
+    fn begin(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move { Ok(self.session.start_transaction().await?) }.boxed()
+    }
+
+    fn commit(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+        commit(&mut self.session, COMMIT_TIMEOUT).boxed()
+    }
+
+    fn rollback(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move { Ok(self.session.abort_transaction().await?) }.boxed()
+    }
+}
+
+// commit, retrying while the result is unknown and the timeout allows
+async fn commit(session: &mut ClientSession, timeout: Duration) -> Result<(), Error> {
+    let start = Instant::now();
+    loop {
+        match session.commit_transaction().await {
+            // committing again is safe, and the server reports the result of
+            // the first commit if it was applied
+            Err(e) if e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) && start.elapsed() < timeout => continue,
+            ret => return Ok(ret?),
+        }
+    }
+}
+
+/// An `AsyncPool` of the sessions of a client. Each transaction runs on a
+/// new session, so they run concurrently.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use mongodb::options::{Acknowledgment, ReadConcern, TransactionOptions, WriteConcern};
+/// use transaction_mongodb::MongoClient;
+///
+/// # async fn f() -> mongodb::error::Result<()> {
+/// let client = mongodb::Client::with_uri_str("mongodb://localhost/?replicaSet=rs0").await?;
+/// let options = TransactionOptions::builder()
+///     .read_concern(ReadConcern::snapshot())
+///     .write_concern(WriteConcern::builder().w(Acknowledgment::Majority).build())
+///     .build();
+/// let client = MongoClient::new(client)
+///     .options(options)
+///     .commit_timeout(Duration::from_secs(10));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MongoClient {
+    client: Client,
+    options: Option<TransactionOptions>,
+    commit_timeout: Duration,
+}
+
+impl MongoClient {
+    /// Run the transactions on the sessions of the client with the default
+    /// options of the sessions, retrying the commits with an unknown result
+    /// for 120 seconds
+    pub fn new(client: Client) -> Self {
+        MongoClient {
+            client,
+            options: None,
+            commit_timeout: COMMIT_TIMEOUT,
+        }
+    }
+
+    /// Start the transactions with the options, e.g. the read and write
+    /// concerns
+    pub fn options(self, options: TransactionOptions) -> Self {
+        MongoClient {
+            options: Some(options),
+            ..self
+        }
+    }
+
+    /// Set how long a commit with an unknown result is retried
+    pub fn commit_timeout(self, commit_timeout: Duration) -> Self {
+        MongoClient { commit_timeout, ..self }
+    }
+
+    /// The client
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl AsyncPool for MongoClient {
+    type Ctx = MongoContext;
+    type Error = Error;
+
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        async move {
+            let mut session = self.client.start_session().await?;
+            session.start_transaction().with_options(self.options.clone()).await?;
+            Ok(MongoContext::new(session))
+        }.boxed()
+    }
+
+    fn release(&self, mut ctx: Self::Ctx, outcome: Outcome) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move {
+            match outcome {
+                Outcome::Committed => commit(&mut ctx.session, self.commit_timeout).await,
+                Outcome::RolledBack => ctx.rollback().await,
+            }
+        }.boxed()
+    }
+}
+
+/// A `Runner` of transactions on the sessions of a client of mongodb
+///
+/// If a transaction panics, its session is released as `RolledBack`, which
+/// aborts the transaction on the server, and the panic is resumed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::panic::AssertUnwindSafe;
+///
+/// use futures::FutureExt;
+/// use mongodb::bson::{doc, Document};
+/// use transaction::async_tx::AsyncTransactionExt;
+/// use transaction_mongodb::{find_one, update_one, MongoClient, MongoRunner};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), transaction_mongodb::Error> {
+///     let client = mongodb::Client::with_uri_str("mongodb://localhost/?replicaSet=rs0").await?;
+///     let accounts = client.database("bank").collection::<Document>("accounts");
+///     let runner = MongoRunner::new(MongoClient::new(client));
+///
+///     let boom = update_one(accounts.clone(), doc! { "_id": "alice" }, doc! { "$set": { "balance": 0 } })
+///         .map(|_| -> () { panic!("boom") });
+///     assert!(AssertUnwindSafe(runner.run_async(boom)).catch_unwind().await.is_err());
+///     let alice = runner.run_async(find_one(accounts, doc! { "_id": "alice" })).await?;
+///     assert_ne!(alice.and_then(|alice| alice.get_i64("balance").ok()), Some(0));
+///     Ok(())
+/// }
+/// ```
+pub type MongoRunner = Runner<MongoClient>;
+
+/// A `TestRunner` of transactions on the sessions of a client of mongodb,
+/// aborting every transaction
+pub type MongoTestRunner = TestRunner<MongoClient>;
@@ -0,0 +1,76 @@
+use std::error;
+use std::fmt;
+
+use async_nats::jetstream::context::{PublishError, PublishErrorKind};
+use transaction::Retryable;
+
+/// The classification of the errors of JetStream transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The acknowledgment of a publish timed out or the connection broke.
+    /// Publishing again is deduplicated by the message id.
+    TimedOut,
+    /// The stream refused a publish, e.g. no stream matched the subject
+    Publish,
+    /// The source message could not be acknowledged, so it will be
+    /// redelivered
+    Ack,
+    /// Any other error
+    Other,
+}
+
+/// An error of NATS together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: async_nats::Error,
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind, inner: async_nats::Error) -> Self {
+        Error { kind, inner }
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of NATS
+    pub fn get_ref(&self) -> &(dyn error::Error + Send + Sync + 'static) {
+        &*self.inner
+    }
+
+    /// Unwrap the error of NATS
+    pub fn into_inner(self) -> async_nats::Error {
+        self.inner
+    }
+}
+
+impl From<PublishError> for Error {
+    fn from(inner: PublishError) -> Self {
+        let kind = match inner.kind() {
+            PublishErrorKind::TimedOut | PublishErrorKind::BrokenPipe => ErrorKind::TimedOut,
+            _ => ErrorKind::Publish,
+        };
+        Error::new(kind, Box::new(inner))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind == ErrorKind::TimedOut
+    }
+}
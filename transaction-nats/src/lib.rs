@@ -0,0 +1,187 @@
+//! Transaction backend for NATS JetStream
+//!
+//! JetStream has no transactions, but a consumer processing a message and
+//! publishing the results can be made effectively exactly-once: the
+//! publishes are deduplicated by their message ids, and the source message is
+//! acknowledged only after all of them succeed, so that a failure before the
+//! acknowledgment redelivers the message and the publishes are repeated
+//! harmlessly.
+//!
+//! Asynchronous transactions run in a `NatsContext` processing a source
+//! message, read by the `source` leaf. The `publish` leaf publishes a
+//! message with an id derived from the source message. The `Runner` double
+//! acknowledges the source message, waiting for the server to confirm the
+//! acknowledgment, when the transaction succeeds, and negatively
+//! acknowledges it to redeliver it otherwise. The guarantee is thus at least
+//! once, made exactly once by the deduplication window of the streams.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use async_nats::jetstream::{self, consumer::pull};
+//! use futures::StreamExt;
+//! use transaction::async_tx::AsyncTransactionExt;
+//! use transaction_nats::{publish, source, Runner};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), async_nats::Error> {
+//!     let client = async_nats::connect("localhost:4222").await?;
+//!     let js = jetstream::new(client);
+//!     let consumer = js.get_stream("ORDERS").await?.get_consumer::<pull::Config>("billing").await?;
+//!     let runner = Runner::new(js);
+//!
+//!     let bill = source().and_then(|order| publish("invoices.created", order.payload));
+//!     let mut messages = consumer.messages().await?;
+//!     while let Some(message) = messages.next().await {
+//!         runner.run(message?, &bill).await?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use async_nats::jetstream::{self, AckKind};
+use futures::FutureExt;
+use transaction::async_tx::AsyncTransaction;
+use transaction::hooks::{self, Outcome};
+
+mod error;
+mod ops;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+/// The context of the transactions: the source message being processed and
+/// the context of JetStream to publish on.
+pub struct NatsContext {
+    js: jetstream::Context,
+    source: async_nats::Message,
+    // the prefix of the derived message ids and the number of the messages
+    // published with them
+    msg_id_prefix: String,
+    published: u64,
+}
+
+impl NatsContext {
+    // never pub this function
+    fn new(js: jetstream::Context, source: async_nats::Message, msg_id_prefix: String) -> Self {
+        NatsContext {
+            js,
+            source,
+            msg_id_prefix,
+            published: 0,
+        }
+    }
+
+    /// The context of JetStream
+    pub fn jetstream(&self) -> &jetstream::Context {
+        &self.js
+    }
+
+    fn next_msg_id(&mut self) -> String {
+        let msg_id = format!("{}:{}", self.msg_id_prefix, self.published);
+        self.published += 1;
+        msg_id
+    }
+}
+
+impl fmt::Debug for NatsContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NatsContext")
+            .field("source", &self.source.subject)
+            .field("published", &self.published)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runner of transactions processing the messages of JetStream consumers
+#[derive(Debug, Clone)]
+pub struct Runner {
+    js: jetstream::Context,
+}
+
+impl Runner {
+    /// Publish the messages on the context of JetStream
+    pub fn new(js: jetstream::Context) -> Self {
+        Runner { js }
+    }
+
+    /// The context of JetStream
+    pub fn jetstream(&self) -> &jetstream::Context {
+        &self.js
+    }
+
+    /// run the given transaction on the message, double acknowledging it if
+    /// the transaction succeeds and negatively acknowledging it otherwise.
+    /// The message must be delivered by a consumer with explicit
+    /// acknowledgments.
+    ///
+    /// If the transaction panics, the message is negatively acknowledged to
+    /// redeliver it, and the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::panic::AssertUnwindSafe;
+    ///
+    /// use async_nats::jetstream::{self, consumer::pull};
+    /// use futures::{FutureExt, StreamExt};
+    /// use transaction::async_tx::AsyncTransactionExt;
+    /// use transaction_nats::{source, Runner};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() -> Result<(), async_nats::Error> {
+    ///     let client = async_nats::connect("localhost:4222").await?;
+    ///     let js = jetstream::new(client);
+    ///     let consumer = js.get_stream("ORDERS").await?.get_consumer::<pull::Config>("billing").await?;
+    ///     let runner = Runner::new(js);
+    ///
+    ///     let boom = source().map(|_| -> () { panic!("boom") });
+    ///     let message = consumer.messages().await?.next().await.ok_or("no message")??;
+    ///     // the message is redelivered
+    ///     assert!(AssertUnwindSafe(runner.run(message, &boom)).catch_unwind().await.is_err());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn run<Tx>(&self, message: jetstream::Message, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = NatsContext>,
+        Tx::Err: From<Error>,
+    {
+        hooks::instrument_async("nats", tx.label(), Outcome::of, async {
+            // the stream and the sequence identify the message across the
+            // redeliveries
+            let msg_id_prefix = match message.info() {
+                Ok(info) => format!("{}:{}", info.stream, info.stream_sequence),
+                Err(e) => return Err(Error::new(ErrorKind::Other, e).into()),
+            };
+            let mut ctx = NatsContext::new(self.js.clone(), message.message.clone(), msg_id_prefix);
+            let ret = match AssertUnwindSafe(async { tx.run_async(&mut ctx).await }).catch_unwind().await {
+                Ok(ret) => ret,
+                Err(panic) => {
+                    #[cfg(feature = "log")]
+                    log::warn!("redelivering the message of a panicking transaction");
+                    let _ = message.ack_with(AckKind::Nak(None)).await;
+                    panic::resume_unwind(panic);
+                }
+            };
+            match ret {
+                Ok(item) => {
+                    message
+                        .double_ack()
+                        .await
+                        .map_err(|e| Error::new(ErrorKind::Ack, e))?;
+                    Ok(item)
+                }
+                Err(e) => {
+                    // the message is redelivered anyway once the ack wait
+                    // passes, so the failure of the nak is not reported
+                    let _ = message.ack_with(AckKind::Nak(None)).await;
+                    Err(e)
+                }
+            }
+        }).await
+    }
+}
@@ -0,0 +1,95 @@
+use async_nats::jetstream::context::Publish as PublishMessage;
+use async_nats::jetstream::publish::PublishAck;
+use bytes::Bytes;
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::{Error, NatsContext};
+
+/// The source message the transaction processes.
+pub fn source() -> Source {
+    Source { _priv: () }
+}
+
+/// The result of `source`
+#[derive(Debug)]
+#[must_use]
+pub struct Source {
+    _priv: (),
+}
+
+impl AsyncTransaction for Source {
+    type Ctx = NatsContext;
+    type Item = async_nats::Message;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(futures::future::ok(ctx.source.clone()))
+    }
+}
+
+impl Visit for Source {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("source"));
+    }
+}
+
+/// Publish the message to the subject of a stream, waiting for the stream to
+/// acknowledge it. The message id deduplicating it is derived from the
+/// source message and the number of the messages published before, so the
+/// publishes of a redelivered message are deduplicated as long as the
+/// transaction publishes the same messages in the same order.
+pub fn publish<S, P>(subject: S, payload: P) -> Publish
+where
+    S: Into<String>,
+    P: Into<Bytes>,
+{
+    Publish {
+        subject: subject.into(),
+        payload: payload.into(),
+        msg_id: None,
+    }
+}
+
+/// The result of `publish`
+#[derive(Debug)]
+#[must_use]
+pub struct Publish {
+    subject: String,
+    payload: Bytes,
+    msg_id: Option<String>,
+}
+
+impl Publish {
+    /// Deduplicate the message by the given id instead of the derived one
+    pub fn msg_id<I: Into<String>>(self, msg_id: I) -> Self {
+        Publish {
+            msg_id: Some(msg_id.into()),
+            ..self
+        }
+    }
+}
+
+impl AsyncTransaction for Publish {
+    type Ctx = NatsContext;
+    type Item = PublishAck;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let msg_id = match self.msg_id {
+                Some(ref msg_id) => msg_id.clone(),
+                None => ctx.next_msg_id(),
+            };
+            let message = PublishMessage::build().payload(self.payload.clone()).message_id(msg_id);
+            let ack = ctx.js.send_publish(self.subject.clone(), message).await?;
+            Ok(ack.await?)
+        })
+    }
+}
+
+impl Visit for Publish {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("publish"));
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+use transaction::level::Level;
+use transaction::{visit_leaf, DryRun, IsolatedAt, Node, ReadOnly, Transaction, Visit, Visitor};
+
+use crate::{Error, PgContext};
+
+/// Run the `COPY ... FROM STDIN` statement, sending the data, and return the
+/// number of the rows copied. The data is in the format given in the
+/// statement, e.g. the text format by default. In preview mode, the
+/// statement is recorded and 0 is returned instead.
+pub fn copy_in<'a>(statement: impl Into<String>, data: impl Into<Vec<u8>>) -> CopyIn<'a> {
+    CopyIn {
+        statement: statement.into(),
+        data: data.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `copy_in`
+#[derive(Debug)]
+#[must_use]
+pub struct CopyIn<'a> {
+    statement: String,
+    data: Vec<u8>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for CopyIn<'a> {
+    type Ctx = PgContext<'a>;
+    type Item = u64;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut PgContext<'a>) -> Result<Self::Item, Self::Err> {
+        if let Some(effects) = ctx.dry_run() {
+            effects.record(format!("{} ({} bytes)", self.statement, self.data.len()));
+            return Ok(0);
+        }
+        let mut writer = ctx.transaction().copy_in(self.statement.as_str())?;
+        writer.write_all(&self.data)?;
+        Ok(writer.finish()?)
+    }
+}
+
+impl<'a> Visit for CopyIn<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("copy_in"));
+    }
+}
+
+impl<'a, L: Level> IsolatedAt<L> for CopyIn<'a> {}
+
+/// Run the `COPY ... TO STDOUT` statement and return the data received.
+pub fn copy_out<'a>(statement: impl Into<String>) -> CopyOut<'a> {
+    CopyOut {
+        statement: statement.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `copy_out`
+#[derive(Debug)]
+#[must_use]
+pub struct CopyOut<'a> {
+    statement: String,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for CopyOut<'a> {
+    type Ctx = PgContext<'a>;
+    type Item = Vec<u8>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut PgContext<'a>) -> Result<Self::Item, Self::Err> {
+        let mut data = Vec::new();
+        ctx.transaction().copy_out(self.statement.as_str())?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl<'a> Visit for CopyOut<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("copy_out"));
+    }
+}
+
+impl<'a> ReadOnly for CopyOut<'a> {}
+
+impl<'a, L: Level> IsolatedAt<L> for CopyOut<'a> {}
@@ -0,0 +1,121 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use postgres::error::SqlState;
+use transaction::{IsolationTooWeak, Retryable};
+
+/// The classification of the errors of postgres by their SQLSTATE
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `serialization_failure` (40001)
+    SerializationFailure,
+    /// `deadlock_detected` (40P01)
+    Deadlock,
+    /// `lock_not_available` (55P03)
+    LockTimeout,
+    /// `query_canceled` (57014), e.g. by the `statement_timeout` of the
+    /// deadline of the run
+    StatementTimeout,
+    /// Any other error
+    Other,
+}
+
+/// An error of postgres, of the I/O of `COPY`, or of a
+/// `transaction::requires_isolation` run at a weaker level, together with
+/// its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: Inner,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Postgres(postgres::Error),
+    Io(io::Error),
+    Isolation(IsolationTooWeak),
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of postgres, if it is not an I/O error of `COPY`
+    pub fn as_postgres(&self) -> Option<&postgres::Error> {
+        match self.inner {
+            Inner::Postgres(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<postgres::Error> for Error {
+    fn from(inner: postgres::Error) -> Self {
+        let kind = match inner.code() {
+            Some(code) if *code == SqlState::T_R_SERIALIZATION_FAILURE => ErrorKind::SerializationFailure,
+            Some(code) if *code == SqlState::T_R_DEADLOCK_DETECTED => ErrorKind::Deadlock,
+            Some(code) if *code == SqlState::LOCK_NOT_AVAILABLE => ErrorKind::LockTimeout,
+            Some(code) if *code == SqlState::QUERY_CANCELED => ErrorKind::StatementTimeout,
+            _ => ErrorKind::Other,
+        };
+        Error {
+            kind,
+            inner: Inner::Postgres(inner),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(inner: io::Error) -> Self {
+        // the readers and writers of `COPY` wrap the errors of postgres
+        let is_postgres = inner.get_ref().is_some_and(|e| e.is::<postgres::Error>());
+        if is_postgres {
+            let e = inner.into_inner().unwrap().downcast::<postgres::Error>().unwrap();
+            return Error::from(*e);
+        }
+        Error {
+            kind: ErrorKind::Other,
+            inner: Inner::Io(inner),
+        }
+    }
+}
+
+impl From<IsolationTooWeak> for Error {
+    fn from(inner: IsolationTooWeak) -> Self {
+        Error {
+            kind: ErrorKind::Other,
+            inner: Inner::Isolation(inner),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner {
+            Inner::Postgres(ref e) => fmt::Display::fmt(e, f),
+            Inner::Io(ref e) => fmt::Display::fmt(e, f),
+            Inner::Isolation(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.inner {
+            Inner::Postgres(ref e) => e.source(),
+            Inner::Io(ref e) => e.source(),
+            Inner::Isolation(ref e) => e.source(),
+        }
+    }
+}
+
+// the deadline of a transaction timed out is not any further when it runs
+// again
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        !matches!(self.kind, ErrorKind::StatementTimeout | ErrorKind::Other)
+    }
+}
@@ -0,0 +1,404 @@
+//! A transaction runner for the synchronous postgres crate
+//!
+//! Transactions run in a `PgContext` wrapping a `postgres::Transaction`,
+//! which `run` commits when the transaction succeeds and rolls back
+//! otherwise. `query`, `query_one`, `execute`, `copy_in` and `copy_out`
+//! expose the statements of postgres as leaves. `PgContext` locks rows and
+//! advisory locks for `transaction::with_row_lock`, and is scoped to the
+//! tenant of `transaction::scoped_to_tenant` for row-level security.
+//!
+//! The statements take a round trip each, even when joined, as postgres
+//! waits for the response of each statement. transaction-tokio-postgres
+//! pipelines the joined statements by `pipelined`.
+//!
+//! `Runner::preview` runs a transaction in the preview mode of
+//! `transaction::DryRun`, reporting the statements it would have run.
+//! `Runner::run_read_only` runs the `transaction::ReadOnly` transactions,
+//! made of `query`, `query_one` and `copy_out`, in read-only transactions,
+//! e.g. on a hot standby.
+//!
+//! The runners check the `transaction::requires_isolation` of the
+//! transactions against their isolation level when they run, and
+//! `Runner::run_at` at compile time.
+//!
+//! The statements of the transactions are limited by `statement_timeout` to
+//! the time remaining until the deadline of the run, given by
+//! `RunnerBuilder::timeout` or entered by `transaction::RunDeadline`, so
+//! that the server stops working on them too.
+//!
+//! The errors are classified by their SQLSTATE, and serialization failures,
+//! deadlocks and lock timeouts are `Retryable`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use postgres::{Client, NoTls};
+//! use transaction::prelude::*;
+//! use transaction_postgres::{execute, query_one, run};
+//!
+//! fn main() -> Result<(), transaction_postgres::Error> {
+//!     let mut client = Client::connect("host=localhost user=postgres", NoTls)?;
+//!
+//!     let tx = execute("UPDATE users SET name = $1 WHERE id = $2", vec![Box::new("alice"), Box::new(1)])
+//!         .and_then(|_| query_one("SELECT name FROM users WHERE id = $1", vec![Box::new(1)]))
+//!         .map(|row| row.get::<_, String>(0));
+//!     let name = run(&mut client, tx)?;
+//!     println!("{}", name);
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use postgres::Client;
+use transaction::clock;
+use transaction::hooks::{self, Outcome};
+use transaction::level::Level;
+use transaction::{
+    DryRun, Effects, IsolatedAt, IsolationLevel, ReadOnly, RunDeadline, RunIsolation, Savepoints, TenantScope,
+    Transaction, TransactionMode,
+};
+
+mod copy;
+mod error;
+mod lock;
+mod statement;
+#[cfg(feature = "xa")]
+mod xa;
+
+pub use crate::copy::*;
+pub use crate::error::*;
+pub use crate::lock::*;
+pub use crate::statement::*;
+#[cfg(feature = "xa")]
+pub use crate::xa::*;
+
+/// The context of the transactions: a transaction of postgres.
+pub struct PgContext<'a> {
+    tx: postgres::Transaction<'a>,
+    dry_run: Option<Effects>,
+}
+
+impl<'a> PgContext<'a> {
+    // never pub this function
+    fn new(tx: postgres::Transaction<'a>) -> Self {
+        PgContext { tx, dry_run: None }
+    }
+
+    /// The transaction of postgres
+    pub fn transaction(&mut self) -> &mut postgres::Transaction<'a> {
+        &mut self.tx
+    }
+}
+
+impl<'a> fmt::Debug for PgContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PgContext").finish_non_exhaustive()
+    }
+}
+
+impl<'a> Savepoints for PgContext<'a> {
+    type Error = Error;
+
+    fn savepoint(&mut self, name: &str) -> Result<(), Self::Error> {
+        Ok(self.tx.batch_execute(&format!("SAVEPOINT {}", name))?)
+    }
+
+    fn release(&mut self, name: &str) -> Result<(), Self::Error> {
+        Ok(self.tx.batch_execute(&format!("RELEASE SAVEPOINT {}", name))?)
+    }
+
+    fn rollback_to(&mut self, name: &str) -> Result<(), Self::Error> {
+        Ok(self.tx.batch_execute(&format!("ROLLBACK TO SAVEPOINT {}", name))?)
+    }
+}
+
+/// In preview mode, `execute` and `copy_in` record their statements instead
+/// of running them, see `Runner::preview`.
+impl<'a> DryRun for PgContext<'a> {
+    fn dry_run(&mut self) -> Option<&mut Effects> {
+        self.dry_run.as_mut()
+    }
+}
+
+/// Scoping to a tenant sets the setting `app.tenant` for the rest of the
+/// transaction, to be read by the row-level security policies, e.g.
+/// `USING (tenant = current_setting('app.tenant'))`.
+impl<'a> TenantScope for PgContext<'a> {
+    type Err = Error;
+
+    fn scope_to(&mut self, tenant: &str) -> Result<(), Self::Err> {
+        self.tx.execute("SELECT set_config('app.tenant', $1, true)", &[&tenant])?;
+        Ok(())
+    }
+}
+
+/// run the given function inside a transaction using the given client.
+pub fn run<'a, T, E, Tx>(client: &'a mut Client, tx: Tx) -> Result<T, E>
+where
+    E: From<Error>,
+    Tx: Transaction<Ctx = PgContext<'a>, Item = T, Err = E>,
+{
+    Runner::default().run(client, tx)
+}
+
+/// Builder of a `Runner` issuing the isolation level and the access mode of
+/// the transactions, e.g.
+/// `RunnerBuilder::new().isolation(IsolationLevel::Serializable).build()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunnerBuilder {
+    mode: TransactionMode,
+    timeout: Option<Duration>,
+}
+
+impl RunnerBuilder {
+    /// Use the database defaults
+    pub fn new() -> Self {
+        RunnerBuilder::default()
+    }
+
+    /// Set the isolation level
+    pub fn isolation(self, level: IsolationLevel) -> Self {
+        RunnerBuilder {
+            mode: self.mode.isolation(level),
+            ..self
+        }
+    }
+
+    /// Make the transactions read-only
+    pub fn read_only(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.read_only(),
+            ..self
+        }
+    }
+
+    /// Make the transactions deferrable
+    pub fn deferrable(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.deferrable(),
+            ..self
+        }
+    }
+
+    /// Replace all the characteristics
+    pub fn mode(self, mode: TransactionMode) -> Self {
+        RunnerBuilder { mode, ..self }
+    }
+
+    /// Give the transactions `timeout` to be done, see `Runner::run`
+    pub fn timeout(self, timeout: Duration) -> Self {
+        RunnerBuilder {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> Runner {
+        Runner {
+            mode: self.mode,
+            timeout: self.timeout,
+        }
+    }
+
+    /// Build the runner rolling back every transaction, for tests
+    pub fn build_test(self) -> TestRunner {
+        TestRunner { runner: self.build() }
+    }
+}
+
+/// Runner of transactions with the characteristics given by `RunnerBuilder`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Runner {
+    mode: TransactionMode,
+    timeout: Option<Duration>,
+}
+
+impl Runner {
+    /// The characteristics of the transactions
+    pub fn mode(&self) -> &TransactionMode {
+        &self.mode
+    }
+
+    /// run the given function inside a transaction with the characteristics
+    /// using the given client.
+    /// If the function panics, the transaction of postgres is rolled back as
+    /// it is dropped, leaving the client out of any transaction, and the
+    /// panic is resumed.
+    ///
+    /// The transaction is to be done by the timeout of the runner, or by the
+    /// `transaction::RunDeadline` entered around if earlier, e.g. by a
+    /// `transaction::runner::RunnerBuilder::deadline`. Its statements are
+    /// limited to the time remaining by `SET LOCAL statement_timeout` when
+    /// it begins, failing with `ErrorKind::StatementTimeout`, and the
+    /// deadline is entered for `transaction::remaining_time`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// use postgres::{Client, NoTls};
+    /// use transaction::prelude::*;
+    /// use transaction_postgres::{execute, RunnerBuilder};
+    ///
+    /// fn main() -> Result<(), transaction_postgres::Error> {
+    ///     let mut client = Client::connect("host=localhost user=postgres", NoTls)?;
+    ///     client.batch_execute("CREATE TEMPORARY TABLE users (name TEXT)")?;
+    ///     let runner = RunnerBuilder::new().build();
+    ///
+    ///     let boom = execute("INSERT INTO users VALUES ($1)", vec![Box::new("alice")]).map(|_| -> () { panic!("boom") });
+    ///     assert!(panic::catch_unwind(AssertUnwindSafe(|| runner.run(&mut client, boom))).is_err());
+    ///     // the client is out of the transaction rolled back
+    ///     let users: i64 = client.query_one("SELECT count(*) FROM users", &[])?.get(0);
+    ///     assert_eq!(users, 0);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn run<'a, T, E, Tx>(&self, client: &'a mut Client, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = PgContext<'a>, Item = T, Err = E>,
+    {
+        let tx = &tx;
+        hooks::instrument("postgres", tx.label(), Outcome::of, move || {
+            let _isolation = RunIsolation::enter(self.mode.isolation_level());
+            let _deadline = self.enter_deadline();
+            let mut ctx = self.begin(client)?;
+            match tx.run(&mut ctx) {
+                Ok(t) => {
+                    ctx.tx.commit().map_err(Error::from)?;
+                    Ok(t)
+                }
+                Err(e) => {
+                    let _ = ctx.tx.rollback();
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    /// run the given function like `run` at the isolation level `L`, whatever
+    /// the level of the runner. The functions requiring a stronger level
+    /// by `transaction::requires_isolation` don't compile.
+    pub fn run_at<'a, L, T, E, Tx>(&self, client: &'a mut Client, tx: Tx) -> Result<T, E>
+    where
+        L: Level,
+        E: From<Error>,
+        Tx: IsolatedAt<L, Ctx = PgContext<'a>, Item = T, Err = E>,
+    {
+        Runner {
+            mode: self.mode.isolation(L::LEVEL),
+            ..*self
+        }
+        .run(client, tx)
+    }
+
+    /// run the given read-only function like `run` inside a read-only
+    /// transaction, whatever the access mode of the runner, e.g. on a hot
+    /// standby. The statements of `query` writing are rejected by postgres.
+    pub fn run_read_only<'a, T, E, Tx>(&self, client: &'a mut Client, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: ReadOnly<Ctx = PgContext<'a>, Item = T, Err = E>,
+    {
+        Runner {
+            mode: self.mode.read_only(),
+            ..*self
+        }
+        .run(client, tx)
+    }
+
+    /// run the given function like `run` in preview mode, where `execute`
+    /// and `copy_in` record their statements instead of running them, and
+    /// roll it back. The `Item` is returned together with the statements
+    /// which would have been run.
+    pub fn preview<'a, T, E, Tx>(&self, client: &'a mut Client, tx: Tx) -> Result<(T, Effects), E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = PgContext<'a>, Item = T, Err = E>,
+    {
+        let tx = &tx;
+        hooks::instrument("postgres", tx.label(), |_| Outcome::RolledBack, move || {
+            let _isolation = RunIsolation::enter(self.mode.isolation_level());
+            let _deadline = self.enter_deadline();
+            let mut ctx = self.begin(client)?;
+            ctx.dry_run = Some(Effects::new());
+            let ret = tx.run(&mut ctx);
+            let effects = ctx.dry_run.take().unwrap_or_default();
+            let _ = ctx.tx.rollback();
+            ret.map(|t| (t, effects))
+        })
+    }
+
+    // enter the deadline of the transaction, given the timeout
+    fn enter_deadline(&self) -> RunDeadline {
+        RunDeadline::enter(self.timeout.map(|timeout| clock::Instant::now() + timeout))
+    }
+
+    // begin a transaction with the characteristics, limiting its statements
+    // to the deadline entered. A `statement_timeout` of 0 disables it, so at
+    // least 1ms is given.
+    fn begin<'a>(&self, client: &'a mut Client) -> Result<PgContext<'a>, Error> {
+        let mut builder = client.build_transaction();
+        if let Some(level) = self.mode.isolation_level() {
+            builder = builder.isolation_level(match level {
+                IsolationLevel::ReadUncommitted => postgres::IsolationLevel::ReadUncommitted,
+                IsolationLevel::ReadCommitted => postgres::IsolationLevel::ReadCommitted,
+                IsolationLevel::RepeatableRead => postgres::IsolationLevel::RepeatableRead,
+                IsolationLevel::Serializable => postgres::IsolationLevel::Serializable,
+            });
+        }
+        if let Some(read_only) = self.mode.is_read_only() {
+            builder = builder.read_only(read_only);
+        }
+        if let Some(deferrable) = self.mode.is_deferrable() {
+            builder = builder.deferrable(deferrable);
+        }
+        let mut tx = builder.start()?;
+        if let Some(deadline) = RunDeadline::current() {
+            let timeout = deadline.saturating_duration_since(clock::Instant::now()).as_millis().max(1);
+            tx.batch_execute(&format!("SET LOCAL statement_timeout = {}", timeout))?;
+        }
+        Ok(PgContext::new(tx))
+    }
+}
+
+/// Runner of transactions which are always rolled back, even when they
+/// succeed, for hermetic tests against a real database. The `Item` or the
+/// error of the transaction is returned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestRunner {
+    runner: Runner,
+}
+
+impl TestRunner {
+    /// The characteristics of the transactions
+    pub fn mode(&self) -> &TransactionMode {
+        self.runner.mode()
+    }
+
+    /// run the given function inside a transaction with the characteristics
+    /// using the given client, and roll it back.
+    pub fn run<'a, T, E, Tx>(&self, client: &'a mut Client, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = PgContext<'a>, Item = T, Err = E>,
+    {
+        let tx = &tx;
+        hooks::instrument("postgres", tx.label(), |_| Outcome::RolledBack, move || {
+            let _isolation = RunIsolation::enter(self.runner.mode.isolation_level());
+            let _deadline = self.runner.enter_deadline();
+            let mut ctx = self.runner.begin(client)?;
+            let ret = tx.run(&mut ctx);
+            match ctx.tx.rollback() {
+                // a failure to roll back is reported since the test is no
+                // longer hermetic
+                Err(e) if ret.is_ok() => Err(Error::from(e).into()),
+                _ => ret,
+            }
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use transaction::HasLocking;
+
+use crate::{Error, ErrorKind, PgContext};
+
+/// A row to lock by `SELECT ... FOR UPDATE`: that of the table whose column,
+/// `id` by default, has the key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RowKey {
+    table: String,
+    column: String,
+    key: i64,
+}
+
+impl RowKey {
+    /// The row of the table whose `id` is the key
+    pub fn new(table: impl Into<String>, key: i64) -> Self {
+        RowKey {
+            table: table.into(),
+            column: "id".to_string(),
+            key,
+        }
+    }
+
+    /// Find the row by the column instead of `id`
+    pub fn column(self, column: impl Into<String>) -> Self {
+        RowKey {
+            column: column.into(),
+            ..self
+        }
+    }
+}
+
+// quote the name of a table or a column, which can't be a parameter
+fn quote(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Locks the rows by `SELECT ... FOR UPDATE`. The waits are bounded by
+/// `lock_timeout`, e.g. set by `SET LOCAL lock_timeout = '1s'`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use postgres::{Client, NoTls};
+/// use transaction::prelude::*;
+/// use transaction::{with_row_lock, Backoff, Retryable, RowLockError};
+/// use transaction_postgres::{execute, Error, RowKey, RunnerBuilder};
+///
+/// #[derive(Debug)]
+/// enum AppError {
+///     Pg(Error),
+///     Lock(RowLockError<Error>),
+/// }
+/// # impl From<Error> for AppError {
+/// #     fn from(e: Error) -> Self { AppError::Pg(e) }
+/// # }
+/// # impl From<RowLockError<Error>> for AppError {
+/// #     fn from(e: RowLockError<Error>) -> Self { AppError::Lock(e) }
+/// # }
+/// impl Retryable for AppError {
+///     fn is_retryable(&self) -> bool {
+///         match *self {
+///             AppError::Pg(ref e) => e.is_retryable(),
+///             AppError::Lock(ref e) => e.is_retryable(),
+///         }
+///     }
+/// }
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = Client::connect("host=localhost user=postgres", NoTls)?;
+/// let debit = execute("UPDATE accounts SET balance = balance - 10 WHERE id = 1", vec![]);
+/// let credit = execute("UPDATE accounts SET balance = balance + 10 WHERE id = 2", vec![]);
+/// let transfer = with_row_lock(
+///     vec![RowKey::new("accounts", 1), RowKey::new("accounts", 2)],
+///     debit.join(credit).map_err(AppError::from),
+/// )
+/// .retry_with(Backoff::exponential(std::time::Duration::from_millis(10)));
+/// RunnerBuilder::new().build().run(&mut client, transfer).unwrap();
+/// # Ok(())
+/// # }
+/// ```
+impl<'a> HasLocking<RowKey> for PgContext<'a> {
+    type Error = Error;
+
+    fn lock(&mut self, keys: &[RowKey]) -> Result<(), Error> {
+        for key in keys {
+            let statement = format!(
+                "SELECT 1 FROM {} WHERE {} = $1 FOR UPDATE",
+                quote(&key.table),
+                quote(&key.column)
+            );
+            self.transaction().query(statement.as_str(), &[&key.key])?;
+        }
+        Ok(())
+    }
+
+    fn is_contention(error: &Error) -> bool {
+        error.kind() == ErrorKind::LockTimeout || error.kind() == ErrorKind::Deadlock
+    }
+}
+
+/// Takes the advisory locks of the keys by `pg_advisory_xact_lock`, which
+/// are released when the transaction ends.
+impl<'a> HasLocking<i64> for PgContext<'a> {
+    type Error = Error;
+
+    fn lock(&mut self, keys: &[i64]) -> Result<(), Error> {
+        for key in keys {
+            self.transaction().execute("SELECT pg_advisory_xact_lock($1)", &[key])?;
+        }
+        Ok(())
+    }
+
+    fn is_contention(error: &Error) -> bool {
+        error.kind() == ErrorKind::LockTimeout || error.kind() == ErrorKind::Deadlock
+    }
+}
@@ -0,0 +1,143 @@
+use std::marker::PhantomData;
+
+use postgres::types::ToSql;
+use postgres::Row;
+use transaction::level::Level;
+use transaction::{visit_leaf, DryRun, IsolatedAt, Node, ReadOnly, Transaction, Visit, Visitor};
+
+use crate::{Error, PgContext};
+
+/// The parameters of a statement, owned by the transaction
+pub type Params = Vec<Box<dyn ToSql + Send + Sync>>;
+
+#[derive(Debug)]
+struct Sql {
+    statement: String,
+    params: Params,
+}
+
+impl Sql {
+    fn new(statement: impl Into<String>, params: Params) -> Self {
+        Sql {
+            statement: statement.into(),
+            params,
+        }
+    }
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params.iter().map(|p| &**p as &(dyn ToSql + Sync)).collect()
+    }
+}
+
+/// Run the statement and return the resulting rows. It is `ReadOnly`, the
+/// statements writing are rejected by `Runner::run_read_only`.
+pub fn query<'a>(statement: impl Into<String>, params: Params) -> Query<'a> {
+    Query {
+        sql: Sql::new(statement, params),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `query`
+#[derive(Debug)]
+#[must_use]
+pub struct Query<'a> {
+    sql: Sql,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for Query<'a> {
+    type Ctx = PgContext<'a>;
+    type Item = Vec<Row>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut PgContext<'a>) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.transaction().query(self.sql.statement.as_str(), &self.sql.params())?)
+    }
+}
+
+impl<'a> Visit for Query<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("query"));
+    }
+}
+
+impl<'a> ReadOnly for Query<'a> {}
+
+impl<'a, L: Level> IsolatedAt<L> for Query<'a> {}
+
+/// Run the statement which returns exactly one row and return it. It fails
+/// when the statement returns no or more rows.
+pub fn query_one<'a>(statement: impl Into<String>, params: Params) -> QueryOne<'a> {
+    QueryOne {
+        sql: Sql::new(statement, params),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `query_one`
+#[derive(Debug)]
+#[must_use]
+pub struct QueryOne<'a> {
+    sql: Sql,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for QueryOne<'a> {
+    type Ctx = PgContext<'a>;
+    type Item = Row;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut PgContext<'a>) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.transaction().query_one(self.sql.statement.as_str(), &self.sql.params())?)
+    }
+}
+
+impl<'a> Visit for QueryOne<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("query_one"));
+    }
+}
+
+impl<'a> ReadOnly for QueryOne<'a> {}
+
+impl<'a, L: Level> IsolatedAt<L> for QueryOne<'a> {}
+
+/// Run the statement and return the number of the rows modified. In
+/// preview mode, the statement is recorded and 0 is returned instead.
+pub fn execute<'a>(statement: impl Into<String>, params: Params) -> Execute<'a> {
+    Execute {
+        sql: Sql::new(statement, params),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `execute`
+#[derive(Debug)]
+#[must_use]
+pub struct Execute<'a> {
+    sql: Sql,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Transaction for Execute<'a> {
+    type Ctx = PgContext<'a>;
+    type Item = u64;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut PgContext<'a>) -> Result<Self::Item, Self::Err> {
+        if let Some(effects) = ctx.dry_run() {
+            effects.record(format!("{} {:?}", self.sql.statement, self.sql.params));
+            return Ok(0);
+        }
+        Ok(ctx.transaction().execute(self.sql.statement.as_str(), &self.sql.params())?)
+    }
+}
+
+impl<'a> Visit for Execute<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("execute"));
+    }
+}
+
+impl<'a, L: Level> IsolatedAt<L> for Execute<'a> {}
@@ -0,0 +1,188 @@
+use std::fmt;
+
+use postgres::Client;
+use transaction_2pc::xa::{ResourceManager, Vote, Xid};
+use transaction_2pc::Participant;
+
+use crate::Error;
+
+/// A client of postgres taking part in distributed transactions by the
+/// prepared transactions of PostgreSQL, as an XA `ResourceManager` of an
+/// external transaction manager or as a `Participant` of a `Coordinator`.
+/// The server must allow them by `max_prepared_transactions`.
+///
+/// The work of the transaction is done on `client` between `begin` (or
+/// `start` for XA) and `prepare`. The XA ids are named by `Xid::encode`.
+///
+/// # Examples
+///
+/// ```no_run
+/// #[macro_use]
+/// extern crate transaction;
+///
+/// use postgres::{Client, NoTls};
+/// use transaction::prelude::*;
+/// use transaction_2pc::{Coordinator, FileLog};
+/// use transaction_postgres::PgResource;
+///
+/// struct Orders<'a>(PgResource<'a>);
+/// struct Billing<'a>(PgResource<'a>);
+/// # impl<'a> transaction_2pc::Participant for Orders<'a> {
+/// #     type Err = transaction_postgres::Error;
+/// #     fn prepare(&mut self, xid: &str) -> Result<(), Self::Err> { self.0.prepare(xid) }
+/// #     fn commit(&mut self, xid: &str) -> Result<(), Self::Err> { self.0.commit(xid) }
+/// #     fn rollback(&mut self, xid: &str) -> Result<(), Self::Err> { self.0.rollback(xid) }
+/// #     fn prepared(&mut self) -> Result<Vec<String>, Self::Err> { self.0.prepared() }
+/// # }
+/// # impl<'a> transaction_2pc::Participant for Billing<'a> {
+/// #     type Err = transaction_postgres::Error;
+/// #     fn prepare(&mut self, xid: &str) -> Result<(), Self::Err> { self.0.prepare(xid) }
+/// #     fn commit(&mut self, xid: &str) -> Result<(), Self::Err> { self.0.commit(xid) }
+/// #     fn rollback(&mut self, xid: &str) -> Result<(), Self::Err> { self.0.rollback(xid) }
+/// #     fn prepared(&mut self) -> Result<Vec<String>, Self::Err> { self.0.prepared() }
+/// # }
+///
+/// type BoxError = Box<dyn std::error::Error + Send + Sync>;
+///
+/// # fn main() -> Result<(), BoxError> {
+/// let mut orders = Client::connect("host=orders user=postgres", NoTls)?;
+/// let mut billing = Client::connect("host=billing user=postgres", NoTls)?;
+/// let mut coordinator = Coordinator::new(FileLog::open("decisions.log")?).name("shop");
+///
+/// let place = with_ctx(|o: &mut Orders| -> Result<u64, BoxError> {
+///     Ok(o.0.client().execute("INSERT INTO orders VALUES (42)", &[])?)
+/// });
+/// let charge = with_ctx(|b: &mut Billing| -> Result<u64, BoxError> {
+///     Ok(b.0.client().execute("INSERT INTO charges VALUES (42, 100)", &[])?)
+/// });
+///
+/// let mut participants = hlist![Orders(PgResource::new(&mut orders)), Billing(PgResource::new(&mut billing))];
+/// coordinator.recover(&mut participants)?;
+/// participants.head.0.begin()?;
+/// participants.tail.head.0.begin()?;
+/// coordinator.run(&mut participants, place.lift().join(charge.lift()))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct PgResource<'a> {
+    client: &'a mut Client,
+    // whether a transaction is running in the session, i.e. begun and not
+    // prepared, committed or rolled back yet
+    active: bool,
+}
+
+impl<'a> PgResource<'a> {
+    /// The client as a resource manager, without a transaction
+    pub fn new(client: &'a mut Client) -> Self {
+        PgResource { client, active: false }
+    }
+
+    /// Begin a transaction on the client to prepare later. Recover the
+    /// prepared transactions before, since they can't be committed in a
+    /// transaction.
+    pub fn begin(&mut self) -> Result<(), Error> {
+        self.client.batch_execute("BEGIN")?;
+        self.active = true;
+        Ok(())
+    }
+
+    /// The client, to do the work of the transaction on
+    pub fn client(&mut self) -> &mut Client {
+        self.client
+    }
+
+    fn prepare_as(&mut self, gid: &str) -> Result<(), Error> {
+        self.client
+            .batch_execute(&format!("PREPARE TRANSACTION {}", quote(gid)))?;
+        self.active = false;
+        Ok(())
+    }
+
+    fn rollback_as(&mut self, gid: &str) -> Result<(), Error> {
+        if self.active {
+            self.client.batch_execute("ROLLBACK")?;
+            self.active = false;
+        } else {
+            self.client.batch_execute(&format!("ROLLBACK PREPARED {}", quote(gid)))?;
+        }
+        Ok(())
+    }
+
+    fn gids(&mut self) -> Result<Vec<String>, Error> {
+        let rows = self.client.query(
+            "SELECT gid FROM pg_prepared_xacts WHERE database = current_database() ORDER BY prepared",
+            &[],
+        )?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
+
+impl<'a> fmt::Debug for PgResource<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PgResource").field("active", &self.active).finish_non_exhaustive()
+    }
+}
+
+// the names of the prepared transactions are string literals
+fn quote(gid: &str) -> String {
+    format!("'{}'", gid.replace('\'', "''"))
+}
+
+impl<'a> ResourceManager for PgResource<'a> {
+    type Err = Error;
+
+    fn start(&mut self, _xid: &Xid) -> Result<(), Error> {
+        // the transaction is named when it is prepared
+        self.begin()
+    }
+
+    fn end(&mut self, _xid: &Xid) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn prepare(&mut self, xid: &Xid) -> Result<Vote, Error> {
+        self.prepare_as(&xid.encode())?;
+        Ok(Vote::Commit)
+    }
+
+    fn commit(&mut self, xid: &Xid, one_phase: bool) -> Result<(), Error> {
+        if one_phase {
+            self.client.batch_execute("COMMIT")?;
+            self.active = false;
+        } else {
+            self.client
+                .batch_execute(&format!("COMMIT PREPARED {}", quote(&xid.encode())))?;
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self, xid: &Xid) -> Result<(), Error> {
+        self.rollback_as(&xid.encode())
+    }
+
+    fn recover(&mut self) -> Result<Vec<Xid>, Error> {
+        // the others are not prepared by XA
+        Ok(self.gids()?.iter().filter_map(|gid| Xid::decode(gid)).collect())
+    }
+}
+
+impl<'a> Participant for PgResource<'a> {
+    type Err = Error;
+
+    fn prepare(&mut self, xid: &str) -> Result<(), Error> {
+        self.prepare_as(xid)
+    }
+
+    fn commit(&mut self, xid: &str) -> Result<(), Error> {
+        self.client.batch_execute(&format!("COMMIT PREPARED {}", quote(xid)))?;
+        Ok(())
+    }
+
+    fn rollback(&mut self, xid: &str) -> Result<(), Error> {
+        self.rollback_as(xid)
+    }
+
+    fn prepared(&mut self) -> Result<Vec<String>, Error> {
+        self.gids()
+    }
+}
@@ -0,0 +1,37 @@
+use std::error;
+use std::fmt;
+
+/// An error of `Runner`
+#[derive(Debug)]
+pub enum PooledError<C, P = r2d2::Error> {
+    /// The pool failed to give a connection within the connection timeout
+    Pool(P),
+    /// Beginning, committing or rolling back the transaction failed
+    Connection(C),
+}
+
+impl<C, P> fmt::Display for PooledError<C, P>
+where
+    C: fmt::Display,
+    P: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PooledError::Pool(ref e) => write!(f, "failed to acquire a connection: {}", e),
+            PooledError::Connection(ref e) => write!(f, "transaction control failed: {}", e),
+        }
+    }
+}
+
+impl<C, P> error::Error for PooledError<C, P>
+where
+    C: error::Error + 'static,
+    P: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            PooledError::Pool(ref e) => Some(e),
+            PooledError::Connection(ref e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! A transaction runner on r2d2 connection pools
+//!
+//! `PooledRunner` checks a connection out of an `r2d2::Pool` for each run,
+//! begins a transaction on it, runs the transaction with the connection as
+//! its context, and commits or rolls it back. The drivers are plugged in by
+//! implementing `Connection` for their connections.
+//!
+//! `PooledRunner` is the `Runner` on r2d2 pools; `Runner` works on any
+//! `transaction::pool::ConnectionProvider`, e.g. the other pools of sync
+//! drivers or middleware wrapping a pool.
+//!
+//! `ShardedRunner` routes the transactions to the pools of several shards by
+//! their keys, or runs them on every shard. `Runner::run_pipelined` runs a
+//! batch of transactions on a single connection.
+//!
+//! If the transaction panics, the connection is rolled back while unwinding,
+//! so it gets back to the pool without a dangling transaction.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::panic::{self, AssertUnwindSafe};
+//!
+//! use transaction::prelude::*;
+//! use transaction_r2d2::{Connection, PooledError, PooledRunner};
+//!
+//! #[derive(Debug, PartialEq)]
+//! struct Error;
+//!
+//! impl From<PooledError<()>> for Error {
+//!     fn from(_: PooledError<()>) -> Self {
+//!         Error
+//!     }
+//! }
+//!
+//! #[derive(Default)]
+//! struct Conn {
+//!     log: Vec<&'static str>,
+//! }
+//!
+//! impl Connection for Conn {
+//!     type Error = ();
+//!     fn begin(&mut self) -> Result<(), ()> {
+//!         self.log.push("BEGIN");
+//!         Ok(())
+//!     }
+//!     fn commit(&mut self) -> Result<(), ()> {
+//!         self.log.push("COMMIT");
+//!         Ok(())
+//!     }
+//!     fn rollback(&mut self) -> Result<(), ()> {
+//!         self.log.push("ROLLBACK");
+//!         Ok(())
+//!     }
+//! }
+//!
+//! struct Manager;
+//!
+//! impl r2d2::ManageConnection for Manager {
+//!     type Connection = Conn;
+//!     type Error = std::io::Error;
+//!     fn connect(&self) -> Result<Conn, std::io::Error> {
+//!         Ok(Conn::default())
+//!     }
+//!     fn is_valid(&self, _: &mut Conn) -> Result<(), std::io::Error> {
+//!         Ok(())
+//!     }
+//!     fn has_broken(&self, _: &mut Conn) -> bool {
+//!         false
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), r2d2::Error> {
+//! let runner = PooledRunner::new(r2d2::Pool::builder().max_size(1).build(Manager)?);
+//! let log = with_ctx(|conn: &mut Conn| Ok::<_, Error>(conn.log.clone()));
+//! assert_eq!(runner.run(&log), Ok(vec!["BEGIN"]));
+//!
+//! let boom = with_ctx(|_: &mut Conn| -> Result<(), Error> { panic!("boom") });
+//! assert!(panic::catch_unwind(AssertUnwindSafe(|| runner.run(boom))).is_err());
+//! assert_eq!(
+//!     runner.run(&log),
+//!     Ok(vec!["BEGIN", "COMMIT", "BEGIN", "ROLLBACK", "BEGIN"])
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+use r2d2::{ManageConnection, Pool};
+use transaction::hooks::{self, Outcome};
+use transaction::pool::ConnectionProvider;
+use transaction::Transaction;
+
+mod error;
+mod pipelined;
+mod sharded;
+
+pub use crate::error::*;
+pub use crate::sharded::*;
+
+/// A connection of a sync driver on which a transaction can be begun,
+/// committed and rolled back.
+pub trait Connection {
+    /// The error of the driver
+    type Error;
+
+    /// Begin a transaction
+    fn begin(&mut self) -> Result<(), Self::Error>;
+
+    /// Commit the transaction
+    fn commit(&mut self) -> Result<(), Self::Error>;
+
+    /// Roll back the transaction
+    fn rollback(&mut self) -> Result<(), Self::Error>;
+}
+
+type ConnError<M> = <<M as ManageConnection>::Connection as Connection>::Error;
+// the connection lent by the provider and its error
+type ProvidedConn<P> = <<P as ConnectionProvider>::Connection as Deref>::Target;
+type ProvidedError<P> = PooledError<<ProvidedConn<P> as Connection>::Error, <P as ConnectionProvider>::Error>;
+
+/// Runner of transactions on the connections of a `ConnectionProvider`,
+/// e.g. an r2d2 pool. The transactions run with the connection as their
+/// context.
+#[derive(Debug, Clone)]
+pub struct Runner<P> {
+    provider: P,
+    acquire_timeout: Option<Duration>,
+}
+
+/// A `Runner` on the connections of an r2d2 pool
+pub type PooledRunner<M> = Runner<Pool<M>>;
+
+impl<P> Runner<P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    /// Run the transactions on the connections of the provider. The
+    /// connection timeout is that of the provider, and the unhealthy
+    /// connections are given back as broken.
+    pub fn new(provider: P) -> Self {
+        Runner {
+            provider,
+            acquire_timeout: None,
+        }
+    }
+
+    /// Fail with `PooledError::Pool` if no connection is checked out within
+    /// `timeout`, instead of the timeout of the provider
+    pub fn acquire_timeout(self, timeout: Duration) -> Self {
+        Runner {
+            acquire_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// The provider
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// run the given transaction on a connection checked out of the pool,
+    /// committing it if the transaction succeeds and rolling it back
+    /// otherwise. Pass a reference to run the same transaction again.
+    pub fn run<T, E, Tx>(&self, tx: Tx) -> Result<T, E>
+    where
+        E: From<ProvidedError<P>>,
+        Tx: Transaction<Ctx = ProvidedConn<P>, Item = T, Err = E>,
+    {
+        hooks::instrument("r2d2", tx.label(), Outcome::of, || {
+            let mut conn = ProvidedTransaction::begin(&self.provider, self.acquire_timeout)?;
+            match conn.run(&tx) {
+                Ok(t) => {
+                    conn.commit().map_err(PooledError::Connection)?;
+                    Ok(t)
+                }
+                Err(e) => {
+                    let _ = conn.rollback();
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    /// check a connection out of the pool and begin a transaction on it, to
+    /// be committed or rolled back later, e.g. after an HTTP response is
+    /// built. Unlike `run`, nothing is reported to the hooks and metrics.
+    pub fn begin(&self) -> Result<ProvidedTransaction<P>, ProvidedError<P>>
+    where
+        P: Clone,
+    {
+        ProvidedTransaction::begin(self.provider.clone(), self.acquire_timeout)
+    }
+}
+
+impl<M> Runner<Pool<M>>
+where
+    M: ManageConnection,
+{
+    /// The pool
+    pub fn pool(&self) -> &Pool<M> {
+        &self.provider
+    }
+}
+
+/// A transaction begun on a connection checked out of the provider by
+/// `Runner::begin`. It is rolled back when dropped without being committed
+/// or rolled back, e.g. on panics, so the connection gets back to the pool
+/// without a dangling transaction. A connection on which the transaction
+/// failed to begin, commit or roll back is given back as broken.
+pub struct ProvidedTransaction<P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    provider: P,
+    // taken when the transaction is finished
+    conn: Option<P::Connection>,
+}
+
+/// A `ProvidedTransaction` on a connection of an r2d2 pool
+pub type PooledTransaction<M> = ProvidedTransaction<Pool<M>>;
+
+impl<P> ProvidedTransaction<P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    fn begin(provider: P, timeout: Option<Duration>) -> Result<Self, ProvidedError<P>> {
+        let mut conn = acquire(&provider, timeout).map_err(PooledError::Pool)?;
+        if let Err(e) = conn.begin() {
+            provider.release(conn, true);
+            return Err(PooledError::Connection(e));
+        }
+        Ok(ProvidedTransaction {
+            provider,
+            conn: Some(conn),
+        })
+    }
+
+    /// run the given transaction with the connection as its context, leaving
+    /// the backend transaction open
+    pub fn run<T, E, Tx>(&mut self, tx: Tx) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = ProvidedConn<P>, Item = T, Err = E>,
+    {
+        tx.run(self)
+    }
+
+    /// Commit the transaction
+    pub fn commit(self) -> Result<(), <ProvidedConn<P> as Connection>::Error> {
+        self.finish(Outcome::Committed)
+    }
+
+    /// Roll back the transaction
+    pub fn rollback(self) -> Result<(), <ProvidedConn<P> as Connection>::Error> {
+        self.finish(Outcome::RolledBack)
+    }
+
+    fn finish(mut self, outcome: Outcome) -> Result<(), <ProvidedConn<P> as Connection>::Error> {
+        let mut conn = self.conn.take().expect("the transaction is not finished");
+        let ret = match outcome {
+            Outcome::Committed => conn.commit(),
+            Outcome::RolledBack => conn.rollback(),
+        };
+        self.provider.release(conn, ret.is_err());
+        ret
+    }
+}
+
+impl<P> fmt::Debug for ProvidedTransaction<P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProvidedTransaction")
+            .field("conn", &self.conn.as_deref())
+            .finish()
+    }
+}
+
+impl<P> Deref for ProvidedTransaction<P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    type Target = ProvidedConn<P>;
+
+    fn deref(&self) -> &ProvidedConn<P> {
+        self.conn.as_deref().expect("the transaction is not finished")
+    }
+}
+
+impl<P> DerefMut for ProvidedTransaction<P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    fn deref_mut(&mut self) -> &mut ProvidedConn<P> {
+        self.conn.as_deref_mut().expect("the transaction is not finished")
+    }
+}
+
+impl<P> Drop for ProvidedTransaction<P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    fn drop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            #[cfg(feature = "log")]
+            log::warn!("rolling back the connection of a panicking transaction");
+            let broken = conn.rollback().is_err();
+            self.provider.release(conn, broken);
+        }
+    }
+}
+
+// check a connection out of the provider, acquiring another once if it is
+// unhealthy
+fn acquire<P>(provider: &P, timeout: Option<Duration>) -> Result<P::Connection, P::Error>
+where
+    P: ConnectionProvider,
+{
+    let mut conn = provider.acquire(timeout)?;
+    if !provider.check_health(&mut conn) {
+        provider.release(conn, true);
+        conn = provider.acquire(timeout)?;
+    }
+    Ok(conn)
+}
@@ -0,0 +1,296 @@
+use std::ops::DerefMut;
+
+use transaction::hooks::{self, Outcome};
+use transaction::pool::{ConnectionProvider, Pipelining};
+use transaction::{Cancelled, Transaction};
+
+use crate::{acquire, Connection, PooledError, ProvidedConn, ProvidedError, Runner};
+
+impl<P> Runner<P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    /// Run a batch of independent transactions back to back on a single
+    /// connection checked out of the provider, e.g. the jobs of a worker,
+    /// returning the result of each in order. The transactions are committed
+    /// together or one by one as told by `pipelining`.
+    ///
+    /// `setup` runs on the connection before the first transaction begins,
+    /// e.g. to set the search path of the session, and `teardown` after the
+    /// last one is finished, to reset it. The batch fails as a whole, with
+    /// nothing committed, if no connection is checked out, if `setup` fails,
+    /// or, with `Pipelining::Single`, if the transaction fails to begin or
+    /// commit. A failed `teardown` only gives the connection back as broken.
+    ///
+    /// With `Pipelining::PerItem`, once the connection fails to begin, commit
+    /// or roll back a transaction, the transactions left fail with
+    /// `Cancelled`. The hooks and metrics are told of each transaction, or of
+    /// the batch as a single unlabeled one with `Pipelining::Single`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use transaction::pool::Pipelining;
+    /// use transaction::prelude::*;
+    /// use transaction::Cancelled;
+    /// use transaction_r2d2::{Connection, PooledError, PooledRunner};
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum Error {
+    ///     Pool,
+    ///     Failed,
+    ///     Cancelled,
+    /// }
+    ///
+    /// impl From<PooledError<()>> for Error {
+    ///     fn from(_: PooledError<()>) -> Self {
+    ///         Error::Pool
+    ///     }
+    /// }
+    ///
+    /// impl From<Cancelled> for Error {
+    ///     fn from(_: Cancelled) -> Self {
+    ///         Error::Cancelled
+    ///     }
+    /// }
+    ///
+    /// #[derive(Default)]
+    /// struct Conn {
+    ///     log: Vec<&'static str>,
+    /// }
+    ///
+    /// impl Connection for Conn {
+    ///     type Error = ();
+    ///     fn begin(&mut self) -> Result<(), ()> {
+    ///         self.log.push("BEGIN");
+    ///         Ok(())
+    ///     }
+    ///     fn commit(&mut self) -> Result<(), ()> {
+    ///         self.log.push("COMMIT");
+    ///         Ok(())
+    ///     }
+    ///     fn rollback(&mut self) -> Result<(), ()> {
+    ///         self.log.push("ROLLBACK");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// struct Manager;
+    ///
+    /// impl r2d2::ManageConnection for Manager {
+    ///     type Connection = Conn;
+    ///     type Error = std::io::Error;
+    ///     fn connect(&self) -> Result<Conn, std::io::Error> {
+    ///         Ok(Conn::default())
+    ///     }
+    ///     fn is_valid(&self, _: &mut Conn) -> Result<(), std::io::Error> {
+    ///         Ok(())
+    ///     }
+    ///     fn has_broken(&self, _: &mut Conn) -> bool {
+    ///         false
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> Result<(), r2d2::Error> {
+    /// let runner = PooledRunner::new(r2d2::Pool::builder().max_size(1).build(Manager)?);
+    /// let insert = |row: &'static str| {
+    ///     with_ctx(move |conn: &mut Conn| {
+    ///         if row == "bad" {
+    ///             return Err(Error::Failed);
+    ///         }
+    ///         conn.log.push(row);
+    ///         Ok(row)
+    ///     })
+    /// };
+    /// let setup = with_ctx(|conn: &mut Conn| {
+    ///     conn.log.clear();
+    ///     conn.log.push("SET");
+    ///     Ok(())
+    /// });
+    /// let teardown = with_ctx(|conn: &mut Conn| {
+    ///     conn.log.push("RESET");
+    ///     Ok::<_, Error>(())
+    /// });
+    /// let log = with_ctx(|conn: &mut Conn| Ok::<_, Error>(conn.log.clone()));
+    /// let batch = || vec![insert("a"), insert("bad"), insert("b")];
+    ///
+    /// let results = runner.run_pipelined(Pipelining::PerItem, &setup, batch(), &teardown);
+    /// assert_eq!(results, Ok(vec![Ok("a"), Err(Error::Failed), Ok("b")]));
+    /// assert_eq!(
+    ///     runner.run(&log),
+    ///     Ok(vec!["SET", "BEGIN", "a", "COMMIT", "BEGIN", "ROLLBACK", "BEGIN", "b", "COMMIT", "RESET", "BEGIN"])
+    /// );
+    ///
+    /// let results = runner.run_pipelined(Pipelining::Single, &setup, batch(), &teardown);
+    /// assert_eq!(results, Ok(vec![Err(Error::Cancelled), Err(Error::Failed), Err(Error::Cancelled)]));
+    /// assert_eq!(runner.run(&log), Ok(vec!["SET", "BEGIN", "a", "ROLLBACK", "RESET", "BEGIN"]));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_pipelined<I, T, E, S, D>(
+        &self,
+        pipelining: Pipelining,
+        setup: S,
+        txs: I,
+        teardown: D,
+    ) -> Result<Vec<Result<T, E>>, E>
+    where
+        E: From<ProvidedError<P>> + From<Cancelled>,
+        I: IntoIterator,
+        I::Item: Transaction<Ctx = ProvidedConn<P>, Item = T, Err = E>,
+        S: Transaction<Ctx = ProvidedConn<P>, Item = (), Err = E>,
+        D: Transaction<Ctx = ProvidedConn<P>, Item = ()>,
+    {
+        let conn = acquire(&self.provider, self.acquire_timeout).map_err(PooledError::Pool)?;
+        let mut batch = Batch {
+            provider: &self.provider,
+            conn: Some(conn),
+            in_transaction: false,
+            // the session may be left half set up if the setup fails or
+            // panics
+            broken: true,
+        };
+        setup.run(batch.conn())?;
+        batch.broken = false;
+        let results = match pipelining {
+            Pipelining::Single => batch.run_single(txs)?,
+            Pipelining::PerItem => batch.run_each(txs),
+        };
+        if !batch.broken {
+            batch.broken = true;
+            if teardown.run(batch.conn()).is_ok() {
+                batch.broken = false;
+            } else {
+                #[cfg(feature = "log")]
+                log::warn!("discarding the connection of a batch which failed to tear down");
+            }
+        }
+        Ok(results)
+    }
+}
+
+// the connection checked out for a batch, rolled back if a transaction is
+// left open and given back to the provider when dropped, also on panics
+struct Batch<'p, P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    provider: &'p P,
+    // taken when given back
+    conn: Option<P::Connection>,
+    in_transaction: bool,
+    broken: bool,
+}
+
+impl<P> Batch<'_, P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    fn conn(&mut self) -> &mut ProvidedConn<P> {
+        self.conn.as_deref_mut().expect("the connection is not given back")
+    }
+
+    fn begin(&mut self) -> Result<(), ProvidedError<P>> {
+        let ret = self.conn().begin();
+        self.in_transaction = ret.is_ok();
+        self.broken = ret.is_err();
+        ret.map_err(PooledError::Connection)
+    }
+
+    fn finish(&mut self, outcome: Outcome) -> Result<(), ProvidedError<P>> {
+        self.in_transaction = false;
+        let ret = match outcome {
+            Outcome::Committed => self.conn().commit(),
+            Outcome::RolledBack => self.conn().rollback(),
+        };
+        self.broken = ret.is_err();
+        ret.map_err(PooledError::Connection)
+    }
+
+    fn run_single<I, T, E>(&mut self, txs: I) -> Result<Vec<Result<T, E>>, E>
+    where
+        E: From<ProvidedError<P>> + From<Cancelled>,
+        I: IntoIterator,
+        I::Item: Transaction<Ctx = ProvidedConn<P>, Item = T, Err = E>,
+    {
+        let txs: Vec<_> = txs.into_iter().collect();
+        hooks::instrument("r2d2", None, batch_outcome, || {
+            self.begin()?;
+            let mut items = Vec::with_capacity(txs.len());
+            for (i, tx) in txs.iter().enumerate() {
+                match tx.run(self.conn()) {
+                    Ok(t) => items.push(t),
+                    Err(e) => {
+                        let _ = self.finish(Outcome::RolledBack);
+                        let mut results: Vec<Result<T, E>> = txs.iter().map(|_| Err(Cancelled.into())).collect();
+                        results[i] = Err(e);
+                        return Ok(results);
+                    }
+                }
+            }
+            self.finish(Outcome::Committed)?;
+            Ok(items.into_iter().map(Ok).collect())
+        })
+    }
+
+    fn run_each<I, T, E>(&mut self, txs: I) -> Vec<Result<T, E>>
+    where
+        E: From<ProvidedError<P>> + From<Cancelled>,
+        I: IntoIterator,
+        I::Item: Transaction<Ctx = ProvidedConn<P>, Item = T, Err = E>,
+    {
+        txs.into_iter()
+            .map(|tx| {
+                if self.broken {
+                    return Err(Cancelled.into());
+                }
+                hooks::instrument("r2d2", tx.label(), Outcome::of, || {
+                    self.begin()?;
+                    match tx.run(self.conn()) {
+                        Ok(t) => {
+                            self.finish(Outcome::Committed)?;
+                            Ok(t)
+                        }
+                        Err(e) => {
+                            let _ = self.finish(Outcome::RolledBack);
+                            Err(e)
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+impl<P> Drop for Batch<'_, P>
+where
+    P: ConnectionProvider,
+    P::Connection: DerefMut,
+    ProvidedConn<P>: Connection,
+{
+    fn drop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            if self.in_transaction {
+                #[cfg(feature = "log")]
+                log::warn!("rolling back the connection of a panicking transaction");
+                self.broken |= conn.rollback().is_err();
+            }
+            self.provider.release(conn, self.broken);
+        }
+    }
+}
+
+// the batch of `Pipelining::Single` commits only if all its transactions
+// succeed
+fn batch_outcome<T, E>(ret: &Result<Vec<Result<T, E>>, E>) -> Outcome {
+    match *ret {
+        Ok(ref results) if results.iter().all(Result::is_ok) => Outcome::Committed,
+        _ => Outcome::RolledBack,
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::panic;
+use std::thread;
+
+use r2d2::{ManageConnection, Pool};
+use transaction::Transaction;
+
+use crate::{ConnError, Connection, PooledError, PooledRunner};
+
+/// Runner of transactions on the pools of several shards, e.g. databases of
+/// tenants. Each transaction runs on the shard of its key, given by the
+/// routing function `F` from the keys of type `K` to the indices of the
+/// shards, or on every shard by `scatter`.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::prelude::*;
+/// use transaction_r2d2::{Connection, PooledError, ShardedRunner};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Error;
+/// # impl From<PooledError<()>> for Error {
+/// #     fn from(_: PooledError<()>) -> Self { Error }
+/// # }
+///
+/// // a connection to the database of a shard
+/// struct Conn {
+///     shard: u32,
+/// }
+/// # impl Connection for Conn {
+/// #     type Error = ();
+/// #     fn begin(&mut self) -> Result<(), ()> { Ok(()) }
+/// #     fn commit(&mut self) -> Result<(), ()> { Ok(()) }
+/// #     fn rollback(&mut self) -> Result<(), ()> { Ok(()) }
+/// # }
+///
+/// struct Manager(u32);
+/// # impl r2d2::ManageConnection for Manager {
+/// #     type Connection = Conn;
+/// #     type Error = std::io::Error;
+/// #     fn connect(&self) -> Result<Conn, std::io::Error> { Ok(Conn { shard: self.0 }) }
+/// #     fn is_valid(&self, _: &mut Conn) -> Result<(), std::io::Error> { Ok(()) }
+/// #     fn has_broken(&self, _: &mut Conn) -> bool { false }
+/// # }
+///
+/// # fn main() -> Result<(), r2d2::Error> {
+/// let pools = (0..3)
+///     .map(|shard| r2d2::Pool::builder().max_size(1).build(Manager(shard)))
+///     .collect::<Result<Vec<_>, _>>()?;
+/// let runner = ShardedRunner::new(pools, |tenant: &u32| *tenant as usize);
+///
+/// let shard = with_ctx(|conn: &mut Conn| Ok::<_, Error>(conn.shard));
+/// assert_eq!(runner.run(&4, &shard), Ok(1));
+///
+/// let shards = runner.scatter(&shard).into_iter().collect::<Result<Vec<_>, _>>();
+/// assert_eq!(shards, Ok(vec![0, 1, 2]));
+/// # Ok(())
+/// # }
+/// ```
+pub struct ShardedRunner<M, K, F>
+where
+    M: ManageConnection,
+    K: ?Sized,
+{
+    shards: Vec<PooledRunner<M>>,
+    route: F,
+    _phantom: PhantomData<fn(&K)>,
+}
+
+impl<M, K, F> ShardedRunner<M, K, F>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+    K: ?Sized,
+    F: Fn(&K) -> usize,
+{
+    /// Run the transactions on the pools of the shards, routing a key to the
+    /// shard of the index `route` returns modulo the number of the shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no pool.
+    pub fn new(pools: Vec<Pool<M>>, route: F) -> Self {
+        assert!(!pools.is_empty(), "ShardedRunner needs a shard");
+        ShardedRunner {
+            shards: pools.into_iter().map(PooledRunner::new).collect(),
+            route,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The runners of the shards
+    pub fn shards(&self) -> &[PooledRunner<M>] {
+        &self.shards
+    }
+
+    /// The runner of the shard of the key
+    pub fn shard(&self, key: &K) -> &PooledRunner<M> {
+        &self.shards[(self.route)(key) % self.shards.len()]
+    }
+
+    /// run the given transaction on the shard of the key, like
+    /// `PooledRunner::run`
+    pub fn run<T, E, Tx>(&self, key: &K, tx: Tx) -> Result<T, E>
+    where
+        E: From<PooledError<ConnError<M>>>,
+        Tx: Transaction<Ctx = M::Connection, Item = T, Err = E>,
+    {
+        self.shard(key).run(tx)
+    }
+
+    /// run the given transaction on every shard concurrently, each in a
+    /// thread, and return the results in the order of the shards.
+    ///
+    /// The transactions of the shards commit or roll back independently of
+    /// each other, so some of them may have committed when another fails.
+    pub fn scatter<T, E, Tx>(&self, tx: Tx) -> Vec<Result<T, E>>
+    where
+        E: From<PooledError<ConnError<M>>> + Send,
+        T: Send,
+        Tx: Transaction<Ctx = M::Connection, Item = T, Err = E> + Sync,
+    {
+        let tx = &tx;
+        thread::scope(|s| {
+            let handles = self
+                .shards
+                .iter()
+                .map(|shard| s.spawn(move || shard.run(tx)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    }
+}
+
+impl<M, K, F> fmt::Debug for ShardedRunner<M, K, F>
+where
+    M: ManageConnection + fmt::Debug,
+    K: ?Sized,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShardedRunner").field("shards", &self.shards).finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use redis::{Cmd, ConnectionLike, FromRedisValue, ToRedisArgs};
+use transaction::Cache;
+
+use crate::lock::lock;
+
+/// A `Cache` of redis for `transaction::cached`, storing the values by
+/// `SET PX` so that redis expires them. Cloning it shares the connection.
+///
+/// The failures of redis count as misses, and the writes failing are
+/// ignored, so that the transactions run as if nothing was cached.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use transaction::prelude::*;
+/// use transaction::runner::RunnerBuilder;
+/// # use transaction::runner::{Backend, RunOptions};
+/// # struct Db;
+/// # impl<T, E> Backend<T, E> for Db {
+/// #     type Ctx = ();
+/// #     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+/// #     where
+/// #         Tx: Transaction<Ctx = (), Item = T, Err = E> + ?Sized,
+/// #     {
+/// #         tx.run(&mut ())
+/// #     }
+/// # }
+/// use transaction_redis::RedisCache;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let cache = RedisCache::new(redis::Client::open("redis://127.0.0.1/")?.get_connection()?);
+/// let runner = RunnerBuilder::new(Db).after_commit().build();
+///
+/// let report = with_ctx(|_: &mut ()| Ok::<_, String>("expensive".to_string()))
+///     .cached(&cache, |_: &()| "report", Duration::from_secs(300));
+/// println!("{}", runner.run(report)?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RedisCache<C> {
+    conn: Arc<Mutex<C>>,
+}
+
+impl<C> Clone for RedisCache<C> {
+    fn clone(&self) -> Self {
+        RedisCache { conn: self.conn.clone() }
+    }
+}
+
+impl<C> RedisCache<C>
+where
+    C: ConnectionLike,
+{
+    /// Cache the values on the connection
+    pub fn new(conn: C) -> Self {
+        RedisCache {
+            conn: Arc::new(Mutex::new(conn)),
+        }
+    }
+}
+
+impl<C, K, V> Cache<K, V> for RedisCache<C>
+where
+    C: ConnectionLike,
+    K: ToRedisArgs,
+    V: ToRedisArgs + FromRedisValue,
+{
+    fn get(&self, key: &K) -> Option<V> {
+        let mut cmd = Cmd::new();
+        cmd.arg("GET").arg(key);
+        let value = lock(&self.conn).req_command(&cmd).ok()?;
+        Option::<V>::from_redis_value(&value).ok().flatten()
+    }
+
+    fn insert(&self, key: K, value: V, ttl: Duration) {
+        // a TTL below a millisecond would be refused by redis
+        let millis = ttl.as_millis().clamp(1, u64::MAX as u128) as u64;
+        let mut cmd = Cmd::new();
+        cmd.arg("SET").arg(key).arg(value).arg("PX").arg(millis);
+        let _ = lock(&self.conn).req_command(&cmd);
+    }
+
+    fn remove(&self, key: &K) {
+        let mut cmd = Cmd::new();
+        cmd.arg("DEL").arg(key);
+        let _ = lock(&self.conn).req_command(&cmd);
+    }
+}
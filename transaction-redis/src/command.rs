@@ -0,0 +1,126 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use redis::{Cmd, FromRedisValue, ToRedisArgs};
+use transaction::{visit_leaf, Node, ReadOnly, Transaction, Visit, Visitor};
+
+use crate::{Error, RedisContext};
+
+/// Run the read command at once, watching the keys it reads, and return its
+/// reply. If another client modifies the keys before `EXEC`, the transaction
+/// is aborted.
+pub fn read<'a, K, V>(keys: K, cmd: Cmd) -> Read<'a, V>
+where
+    K: ToRedisArgs,
+    V: FromRedisValue,
+{
+    Read {
+        keys: keys.to_redis_args(),
+        cmd,
+        _phantom: PhantomData,
+    }
+}
+
+/// Get the value of the key, watching it. See `read`.
+pub fn get<'a, K, V>(key: K) -> Read<'a, V>
+where
+    K: ToRedisArgs,
+    V: FromRedisValue,
+{
+    let mut cmd = redis::cmd("GET");
+    cmd.arg(&key);
+    read(key, cmd)
+}
+
+/// The result of `read` and `get`
+#[must_use]
+pub struct Read<'a, V> {
+    keys: Vec<Vec<u8>>,
+    cmd: Cmd,
+    _phantom: PhantomData<fn(&'a ()) -> V>,
+}
+
+impl<'a, V> fmt::Debug for Read<'a, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Read").field("keys", &self.keys).finish_non_exhaustive()
+    }
+}
+
+impl<'a, V> Transaction for Read<'a, V>
+where
+    V: FromRedisValue,
+{
+    type Ctx = RedisContext<'a>;
+    type Item = V;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut RedisContext<'a>) -> Result<Self::Item, Self::Err> {
+        ctx.watch(&self.keys)?;
+        Ok(ctx.query(&self.cmd)?)
+    }
+}
+
+impl<'a, V> Visit for Read<'a, V> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("read"));
+    }
+}
+
+impl<'a, V> ReadOnly for Read<'a, V> where V: FromRedisValue {}
+
+/// Queue the write command to be run between `MULTI` and `EXEC` when the
+/// transaction commits. Its reply is discarded.
+pub fn queue<'a>(cmd: Cmd) -> Queue<'a> {
+    Queue { cmd, _phantom: PhantomData }
+}
+
+/// Queue `SET key value`. See `queue`.
+pub fn set<'a, K, V>(key: K, value: V) -> Queue<'a>
+where
+    K: ToRedisArgs,
+    V: ToRedisArgs,
+{
+    let mut cmd = redis::cmd("SET");
+    cmd.arg(key).arg(value);
+    queue(cmd)
+}
+
+/// Queue `DEL key`. See `queue`.
+pub fn del<'a, K>(key: K) -> Queue<'a>
+where
+    K: ToRedisArgs,
+{
+    let mut cmd = redis::cmd("DEL");
+    cmd.arg(key);
+    queue(cmd)
+}
+
+/// The result of `queue`, `set` and `del`
+#[must_use]
+pub struct Queue<'a> {
+    cmd: Cmd,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> fmt::Debug for Queue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Queue").finish_non_exhaustive()
+    }
+}
+
+impl<'a> Transaction for Queue<'a> {
+    type Ctx = RedisContext<'a>;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut RedisContext<'a>) -> Result<Self::Item, Self::Err> {
+        ctx.queue(self.cmd.clone());
+        Ok(())
+    }
+}
+
+impl<'a> Visit for Queue<'a> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("queue"));
+    }
+}
@@ -0,0 +1,70 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of the redis transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `EXEC` was aborted because a watched key was modified after it was
+    /// read
+    Aborted,
+    /// Any other error
+    Other,
+}
+
+/// An error of redis, or an aborted `EXEC`
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: Option<redis::RedisError>,
+}
+
+impl Error {
+    pub(crate) fn aborted() -> Self {
+        Error {
+            kind: ErrorKind::Aborted,
+            inner: None,
+        }
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of redis, if it is not an aborted `EXEC`
+    pub fn get_ref(&self) -> Option<&redis::RedisError> {
+        self.inner.as_ref()
+    }
+}
+
+impl From<redis::RedisError> for Error {
+    fn from(inner: redis::RedisError) -> Self {
+        Error {
+            kind: ErrorKind::Other,
+            inner: Some(inner),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner {
+            Some(ref e) => fmt::Display::fmt(e, f),
+            None => f.write_str("transaction aborted: a watched key was modified"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.as_ref().and_then(|e| e.source())
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind != ErrorKind::Other
+    }
+}
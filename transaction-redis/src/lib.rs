@@ -0,0 +1,350 @@
+//! A transaction runner for redis
+//!
+//! The writes of a transaction are queued and sent between `MULTI` and
+//! `EXEC` when it commits, so they are applied all together or not at all.
+//! The reads needed for the decisions run at once, before `MULTI`, and watch
+//! their keys: if another client modifies them before `EXEC`, the writes are
+//! discarded and the transaction fails with `ErrorKind::Aborted`.
+//! `run_retry` runs such optimistic transactions again until they succeed.
+//!
+//! `Runner::preview` runs a transaction in the preview mode of
+//! `transaction::DryRun`, reporting the commands it would have queued.
+//!
+//! As the writes are only queued, `get` doesn't see the `set`s of the same
+//! transaction. `transaction::overlay_get` does see those of
+//! `overlay_set` and `overlay_del`, which `RedisContext` records for the
+//! run.
+//!
+//! `Redlock` is a `LockManager` for `transaction::with_lock`, holding locks
+//! on one or more redis masters, and `RedisCache` is a `Cache` for
+//! `transaction::cached`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use transaction::prelude::*;
+//! use transaction::Backoff;
+//! use transaction_redis::{get, set, Runner};
+//!
+//! fn main() -> Result<(), transaction_redis::Error> {
+//!     let client = redis::Client::open("redis://127.0.0.1/")?;
+//!     let runner = Runner::new(client.get_connection()?);
+//!
+//!     // a transfer which is applied only if neither balance changed meanwhile
+//!     let tx = get("alice")
+//!         .join(get("bob"))
+//!         .and_then(|(alice, bob): (i64, i64)| {
+//!             if alice < 10 {
+//!                 return ok(false).boxed();
+//!             }
+//!             set("alice", alice - 10)
+//!                 .join(set("bob", bob + 10))
+//!                 .map(|_| true)
+//!                 .boxed()
+//!         });
+//!     let transferred = runner.run_retry(tx, Backoff::immediate().max_retries(10))?;
+//!     println!("{}", transferred);
+//!     Ok(())
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::fmt;
+use std::thread;
+
+use redis::{Arg, Cmd, ConnectionLike, FromRedisValue, Pipeline, RedisResult, Value};
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+use transaction::{DryRun, Effects, Overlay, OverlayStore, RetryPolicy, Retryable, Transaction};
+
+mod cache;
+mod command;
+mod error;
+mod lock;
+
+pub use crate::cache::*;
+pub use crate::command::*;
+pub use crate::error::*;
+pub use crate::lock::*;
+
+/// The context of the transactions: the connection and the commands queued
+/// for `EXEC`.
+pub struct RedisContext<'a> {
+    conn: &'a RefCell<dyn ConnectionLike + 'a>,
+    pipe: Pipeline,
+    watching: bool,
+    overlay: Overlay<Vec<u8>, Vec<u8>>,
+    dry_run: Option<Effects>,
+}
+
+impl<'a> RedisContext<'a> {
+    // never pub this function
+    fn new(conn: &'a RefCell<dyn ConnectionLike + 'a>) -> Self {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        RedisContext {
+            conn,
+            pipe,
+            watching: false,
+            overlay: Overlay::new(),
+            dry_run: None,
+        }
+    }
+
+    fn watch(&mut self, keys: &[Vec<u8>]) -> RedisResult<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        redis::cmd("WATCH").arg(keys).query::<()>(&mut *self.conn.borrow_mut())?;
+        self.watching = true;
+        Ok(())
+    }
+
+    fn query<V: FromRedisValue>(&mut self, cmd: &Cmd) -> RedisResult<V> {
+        cmd.query(&mut *self.conn.borrow_mut())
+    }
+
+    fn queue(&mut self, cmd: Cmd) {
+        if let Some(effects) = &mut self.dry_run {
+            effects.record(describe(&cmd));
+            return;
+        }
+        self.pipe.add_command(cmd).ignore();
+    }
+
+    // send the queued commands between MULTI and EXEC
+    fn exec(&mut self) -> Result<(), Error> {
+        if self.pipe.cmd_iter().next().is_none() && !self.watching {
+            return Ok(());
+        }
+        let reply: RedisResult<Value> = self.pipe.query(&mut *self.conn.borrow_mut());
+        // EXEC forgets the watched keys, whether it fails, is aborted or not
+        self.watching = false;
+        match reply? {
+            Value::Nil => Err(Error::aborted()),
+            _ => Ok(()),
+        }
+    }
+
+    // forget the queued commands, which are not sent yet
+    fn discard(&mut self) -> Result<(), Error> {
+        if self.watching {
+            redis::cmd("UNWATCH").query::<()>(&mut *self.conn.borrow_mut())?;
+            self.watching = false;
+        }
+        Ok(())
+    }
+}
+
+// the connection is reused by the next runs, so the keys are unwatched even
+// if the transaction panics
+impl<'a> Drop for RedisContext<'a> {
+    fn drop(&mut self) {
+        if !self.watching {
+            return;
+        }
+        #[cfg(feature = "log")]
+        log::warn!("unwatching the keys of a panicking transaction");
+        if let Ok(mut conn) = self.conn.try_borrow_mut() {
+            let _ = redis::cmd("UNWATCH").query::<()>(&mut *conn);
+        }
+    }
+}
+
+/// In preview mode, the commands queued are recorded instead, see
+/// `Runner::preview`.
+impl<'a> DryRun for RedisContext<'a> {
+    fn dry_run(&mut self) -> Option<&mut Effects> {
+        self.dry_run.as_mut()
+    }
+}
+
+/// The reads of `transaction::overlay_get` watch their keys like `get`, and
+/// the writes of `overlay_set` and `overlay_del` are queued like `set` and
+/// `del`, so the reads see the writes of the run before `EXEC`.
+impl<'a> OverlayStore<Vec<u8>, Vec<u8>> for RedisContext<'a> {
+    type Err = Error;
+
+    fn load(&mut self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, Error> {
+        self.watch(std::slice::from_ref(key))?;
+        Ok(self.query(redis::cmd("GET").arg(key))?)
+    }
+
+    fn store(&mut self, key: &Vec<u8>, value: Option<&Vec<u8>>) -> Result<(), Error> {
+        let cmd = match value {
+            Some(value) => {
+                let mut cmd = redis::cmd("SET");
+                cmd.arg(key).arg(value);
+                cmd
+            }
+            None => {
+                let mut cmd = redis::cmd("DEL");
+                cmd.arg(key);
+                cmd
+            }
+        };
+        self.queue(cmd);
+        Ok(())
+    }
+
+    fn overlay(&mut self) -> &mut Overlay<Vec<u8>, Vec<u8>> {
+        &mut self.overlay
+    }
+}
+
+impl<'a> fmt::Debug for RedisContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedisContext")
+            .field("queued", &self.pipe.cmd_iter().count())
+            .field("watching", &self.watching)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runner of transactions on a connection of redis
+#[derive(Debug)]
+pub struct Runner<C> {
+    conn: RefCell<C>,
+}
+
+impl<C> Runner<C>
+where
+    C: ConnectionLike,
+{
+    /// Run the transactions on the connection
+    pub fn new(conn: C) -> Self {
+        Runner { conn: RefCell::new(conn) }
+    }
+
+    /// Unwrap the connection
+    pub fn into_inner(self) -> C {
+        self.conn.into_inner()
+    }
+
+    /// run the given function, sending the queued commands between `MULTI`
+    /// and `EXEC` if it succeeds and discarding them otherwise. Pass a
+    /// reference to run the same transaction again.
+    ///
+    /// If the transaction panics, the queued commands are discarded and the
+    /// watched keys unwatched, so the connection is left clean for the next
+    /// runs, and the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// use redis::{ConnectionLike, RedisResult, Value};
+    /// use transaction::prelude::*;
+    /// use transaction_redis::{get, Error, Runner};
+    ///
+    /// // a connection logging the commands, to which every key is missing
+    /// #[derive(Default)]
+    /// struct Conn {
+    ///     log: Vec<String>,
+    /// }
+    ///
+    /// impl ConnectionLike for Conn {
+    ///     fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+    ///         let cmd = String::from_utf8_lossy(cmd).split("\r\n").nth(2).unwrap_or_default().to_string();
+    ///         let reply = if cmd == "GET" { Value::Nil } else { Value::Okay };
+    ///         self.log.push(cmd);
+    ///         Ok(reply)
+    ///     }
+    ///     fn req_packed_commands(&mut self, _: &[u8], _: usize, _: usize) -> RedisResult<Vec<Value>> {
+    ///         unimplemented!("no transaction is committed")
+    ///     }
+    ///     fn get_db(&self) -> i64 {
+    ///         0
+    ///     }
+    ///     fn check_connection(&mut self) -> bool {
+    ///         true
+    ///     }
+    ///     fn is_open(&self) -> bool {
+    ///         true
+    ///     }
+    /// }
+    ///
+    /// let runner = Runner::new(Conn::default());
+    /// let boom = get::<_, Option<i64>>("apple").map(|_| -> () { panic!("boom") });
+    /// assert!(panic::catch_unwind(AssertUnwindSafe(|| runner.run::<_, Error, _>(boom))).is_err());
+    /// assert_eq!(runner.into_inner().log, vec!["WATCH", "GET", "UNWATCH"]);
+    /// ```
+    pub fn run<'a, T, E, Tx>(&'a self, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = RedisContext<'a>, Item = T, Err = E>,
+    {
+        hooks::instrument("redis", tx.label(), Outcome::of, || {
+            let mut ctx = RedisContext::new(&self.conn);
+            match tx.run(&mut ctx) {
+                Ok(t) => {
+                    ctx.exec()?;
+                    Ok(t)
+                }
+                Err(e) => {
+                    let _ = ctx.discard();
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    /// run the given function like `run` in preview mode, where the commands
+    /// queued are recorded instead, and discard it. The `Item` is returned
+    /// together with the commands which would have been sent to `EXEC`.
+    pub fn preview<'a, T, E, Tx>(&'a self, tx: Tx) -> Result<(T, Effects), E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = RedisContext<'a>, Item = T, Err = E>,
+    {
+        // nothing is written, so the run is not reported to the hooks
+        let mut ctx = RedisContext::new(&self.conn);
+        ctx.dry_run = Some(Effects::new());
+        let ret = tx.run(&mut ctx);
+        let effects = ctx.dry_run.take().unwrap_or_default();
+        let discarded = ctx.discard();
+        let t = ret?;
+        discarded?;
+        Ok((t, effects))
+    }
+
+    /// run the given function like `run`, running the whole transaction
+    /// again while it fails with a retryable error, e.g. it is aborted by a
+    /// modified watched key, and the policy allows. Each retry is recorded
+    /// by `metrics::record_retry`.
+    pub fn run_retry<'a, T, E, Tx, R>(&'a self, tx: Tx, policy: R) -> Result<T, E>
+    where
+        E: From<Error> + Retryable,
+        Tx: Transaction<Ctx = RedisContext<'a>, Item = T, Err = E>,
+        R: RetryPolicy,
+    {
+        let mut retries = 0;
+        loop {
+            let e = match self.run(&tx) {
+                Err(e) => if e.is_retryable() { e } else { return Err(e) },
+                Ok(t) => return Ok(t),
+            };
+            let delay = match policy.next_delay(retries) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            #[cfg(feature = "log")]
+            log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+            metrics::record_retry(tx.label());
+            thread::sleep(delay);
+            retries += 1;
+        }
+    }
+}
+
+// the command as typed in redis-cli, for the previews
+fn describe(cmd: &Cmd) -> String {
+    cmd.args_iter()
+        .map(|arg| match arg {
+            Arg::Simple(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            Arg::Cursor => "0".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,185 @@
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use redis::{Cmd, ConnectionLike, Value};
+use transaction::LockManager;
+
+use crate::Error;
+
+// delete or extend the key only if it still holds the token, i.e. the lock
+// hasn't expired and been acquired by another holder
+const RELEASE: &str = r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("DEL", KEYS[1]) else return 0 end"#;
+const EXTEND: &str =
+    r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("PEXPIRE", KEYS[1], ARGV[2]) else return 0 end"#;
+
+/// A `LockManager` by the Redlock algorithm: a lock is acquired when it is
+/// set on a majority of independent redis masters within its TTL. With a
+/// single connection it is the plain lock by `SET NX PX`.
+///
+/// The masters which fail count as not locked, and the manager fails only
+/// when all of them do.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use transaction::prelude::*;
+/// use transaction::{with_lock, Backoff, LockError, Retryable};
+/// use transaction_redis::{get, set, Redlock, Runner};
+///
+/// #[derive(Debug)]
+/// enum AppError {
+///     Redis(transaction_redis::Error),
+///     Lock(LockError<transaction_redis::Error>),
+/// }
+/// # impl From<transaction_redis::Error> for AppError {
+/// #     fn from(e: transaction_redis::Error) -> Self { AppError::Redis(e) }
+/// # }
+/// # impl From<LockError<transaction_redis::Error>> for AppError {
+/// #     fn from(e: LockError<transaction_redis::Error>) -> Self { AppError::Lock(e) }
+/// # }
+///
+/// // both an aborted transaction and a contended lock are tried again
+/// impl Retryable for AppError {
+///     fn is_retryable(&self) -> bool {
+///         match *self {
+///             AppError::Redis(ref e) => e.is_retryable(),
+///             AppError::Lock(ref e) => e.is_retryable(),
+///         }
+///     }
+/// }
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let masters = ["redis://10.0.0.1/", "redis://10.0.0.2/", "redis://10.0.0.3/"]
+///     .iter()
+///     .map(|url| redis::Client::open(*url)?.get_connection())
+///     .collect::<Result<Vec<_>, _>>()?;
+/// let locks = Redlock::new(masters);
+/// let runner = Runner::new(redis::Client::open("redis://127.0.0.1/")?.get_connection()?);
+///
+/// let report = get("sales").and_then(|n: i64| set("report", n));
+/// let tx = with_lock(&locks, "report", Duration::from_secs(30), report.map_err(AppError::from));
+/// runner.run_retry(tx, Backoff::exponential(Duration::from_millis(50)).max_retries(5)).unwrap();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Redlock<C> {
+    conns: Vec<Mutex<C>>,
+}
+
+/// The proof of holding a lock of `Redlock`
+#[derive(Debug)]
+pub struct RedlockGuard {
+    key: String,
+    token: String,
+}
+
+impl RedlockGuard {
+    /// The key of the lock
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl<C> Redlock<C>
+where
+    C: ConnectionLike,
+{
+    /// The locks on the masters, which should be independent of each other
+    pub fn new(conns: Vec<C>) -> Self {
+        assert!(!conns.is_empty(), "Redlock needs a master");
+        Redlock {
+            conns: conns.into_iter().map(Mutex::new).collect(),
+        }
+    }
+
+    fn quorum(&self) -> usize {
+        self.conns.len() / 2 + 1
+    }
+
+    // run the command on all the masters, and return how many replied
+    // `true` by `ok`, or the error if all of them failed
+    fn on_all<F>(&self, cmd: &Cmd, ok: F) -> Result<usize, Error>
+    where
+        F: Fn(&Value) -> bool,
+    {
+        let mut count = 0;
+        let mut failed = 0;
+        let mut error = None;
+        for conn in &self.conns {
+            match lock(conn).req_command(cmd) {
+                Ok(ref value) if ok(value) => count += 1,
+                Ok(_) => {}
+                Err(e) => {
+                    failed += 1;
+                    error = Some(e);
+                }
+            }
+        }
+        match error {
+            Some(e) if failed == self.conns.len() => Err(e.into()),
+            _ => Ok(count),
+        }
+    }
+}
+
+pub(crate) fn lock<C>(conn: &Mutex<C>) -> MutexGuard<'_, C> {
+    conn.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// a token unique to the acquisition, identifying the holder
+fn token() -> String {
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("{:x}-{:x}-{:x}", nanos, process::id(), COUNT.fetch_add(1, Ordering::Relaxed))
+}
+
+fn millis(ttl: Duration) -> u64 {
+    ttl.as_millis().max(1) as u64
+}
+
+impl<C> LockManager for Redlock<C>
+where
+    C: ConnectionLike,
+{
+    type Guard = RedlockGuard;
+    type Error = Error;
+
+    fn acquire(&self, key: &str, ttl: Duration) -> Result<Option<RedlockGuard>, Error> {
+        let guard = RedlockGuard {
+            key: key.to_string(),
+            token: token(),
+        };
+        let start = Instant::now();
+        let mut set = redis::cmd("SET");
+        set.arg(key).arg(&guard.token).arg("NX").arg("PX").arg(millis(ttl));
+        let locked = self.on_all(&set, |v| *v == Value::Okay)?;
+        // the clocks of the masters may drift by 1% of the TTL and 2ms
+        let drift = ttl / 100 + Duration::from_millis(2);
+        if locked >= self.quorum() && start.elapsed() + drift < ttl {
+            Ok(Some(guard))
+        } else {
+            // some masters may have been locked
+            let _ = self.release(guard);
+            Ok(None)
+        }
+    }
+
+    fn extend(&self, guard: &RedlockGuard, ttl: Duration) -> Result<bool, Error> {
+        let mut eval = redis::cmd("EVAL");
+        eval.arg(EXTEND).arg(1).arg(&guard.key).arg(&guard.token).arg(millis(ttl));
+        Ok(self.on_all(&eval, |v| *v == Value::Int(1))? >= self.quorum())
+    }
+
+    fn release(&self, guard: RedlockGuard) -> Result<(), Error> {
+        let mut eval = redis::cmd("EVAL");
+        eval.arg(RELEASE).arg(1).arg(&guard.key).arg(&guard.token);
+        self.on_all(&eval, |_| true).map(|_| ())
+    }
+}
@@ -0,0 +1,447 @@
+//! Transactions for Rocket on r2d2 connection pools
+//!
+//! A `Database` names a database in the `databases` table of the Rocket
+//! configuration and builds the connection manager from its `Config`.
+//! `TxFairing` builds its pool when Rocket ignites and manages a `Pool` of
+//! it, and `Transactional` is a request guard running transactions in a
+//! backend transaction begun on a connection of the pool at the first run
+//! and shared by all the runs of the request. The fairing commits the
+//! backend transaction when the route responds successfully and rolls it
+//! back when it responds with an error status (4xx or 5xx).
+//!
+//! ```toml
+//! [default.databases.main]
+//! url = "postgres://localhost/app"
+//! max_size = 16
+//! ```
+//!
+//! # Examples
+//!
+//! ```
+//! use std::error::Error;
+//! use std::sync::Mutex;
+//!
+//! use rocket::http::Status;
+//! use rocket::local::blocking::Client;
+//! use rocket::{post, routes};
+//! use transaction::prelude::*;
+//! use transaction_r2d2::{Connection, PooledError};
+//! use transaction_rocket::{Config, Database, Transactional, TxFairing};
+//!
+//! // the statements run on the database
+//! static LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+//!
+//! struct Conn;
+//!
+//! impl Conn {
+//!     fn exec(&mut self, statement: String) {
+//!         LOG.lock().unwrap().push(statement)
+//!     }
+//! }
+//!
+//! impl Connection for Conn {
+//!     type Error = std::io::Error;
+//!     fn begin(&mut self) -> std::io::Result<()> {
+//!         Ok(self.exec("BEGIN".to_string()))
+//!     }
+//!     fn commit(&mut self) -> std::io::Result<()> {
+//!         Ok(self.exec("COMMIT".to_string()))
+//!     }
+//!     fn rollback(&mut self) -> std::io::Result<()> {
+//!         Ok(self.exec("ROLLBACK".to_string()))
+//!     }
+//! }
+//!
+//! struct Manager;
+//! # impl r2d2::ManageConnection for Manager {
+//! #     type Connection = Conn;
+//! #     type Error = std::io::Error;
+//! #     fn connect(&self) -> std::io::Result<Conn> { Ok(Conn) }
+//! #     fn is_valid(&self, _: &mut Conn) -> std::io::Result<()> { Ok(()) }
+//! #     fn has_broken(&self, _: &mut Conn) -> bool { false }
+//! # }
+//!
+//! struct Main;
+//!
+//! impl Database for Main {
+//!     const NAME: &'static str = "main";
+//!     type Manager = Manager;
+//!     fn manager(_config: &Config) -> Result<Manager, Box<dyn Error + Send + Sync>> {
+//!         Ok(Manager)
+//!     }
+//! }
+//!
+//! #[derive(Debug)]
+//! enum AppError {
+//!     OutOfStock,
+//!     Pool(PooledError<std::io::Error>),
+//! }
+//! # impl From<PooledError<std::io::Error>> for AppError {
+//! #     fn from(e: PooledError<std::io::Error>) -> Self { AppError::Pool(e) }
+//! # }
+//!
+//! impl From<AppError> for Status {
+//!     fn from(e: AppError) -> Status {
+//!         match e {
+//!             AppError::OutOfStock => Status::Conflict,
+//!             AppError::Pool(_) => Status::ServiceUnavailable,
+//!         }
+//!     }
+//! }
+//!
+//! fn insert(table: &'static str, id: u32) -> impl Transaction<Ctx = Conn, Item = (), Err = AppError> {
+//!     with_ctx(move |conn: &mut Conn| Ok(conn.exec(format!("INSERT {} {}", table, id))))
+//! }
+//!
+//! fn reserve(id: u32) -> impl Transaction<Ctx = Conn, Item = (), Err = AppError> {
+//!     insert("stock", id).and_then(move |_| {
+//!         with_ctx(move |_: &mut Conn| if id == 0 { Err(AppError::OutOfStock) } else { Ok(()) })
+//!     })
+//! }
+//!
+//! // both transactions run in the backend transaction of the request
+//! #[post("/orders/<id>")]
+//! async fn order(tx: Transactional<Main>, id: u32) -> Result<Status, Status> {
+//!     tx.run(insert("orders", id)).await?;
+//!     tx.run(reserve(id)).await?;
+//!     Ok(Status::Created)
+//! }
+//!
+//! let figment = rocket::Config::figment()
+//!     .merge(("log_level", "off"))
+//!     .merge(("databases.main.url", "memory"))
+//!     .merge(("databases.main.max_size", 1));
+//! let rocket = rocket::custom(figment)
+//!     .attach(TxFairing::<Main>::new())
+//!     .mount("/", routes![order]);
+//! let client = Client::untracked(rocket).unwrap();
+//!
+//! assert_eq!(client.post("/orders/1").dispatch().status(), Status::Created);
+//! assert_eq!(client.post("/orders/0").dispatch().status(), Status::Conflict);
+//! assert_eq!(
+//!     *LOG.lock().unwrap(),
+//!     [
+//!         "BEGIN", "INSERT orders 1", "INSERT stock 1", "COMMIT",
+//!         "BEGIN", "INSERT orders 0", "INSERT stock 0", "ROLLBACK",
+//!     ]
+//! );
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use r2d2::ManageConnection;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::Deserialize;
+use rocket::tokio::task;
+use rocket::{Build, Response, Rocket};
+use transaction::hooks::{self, Outcome as RunOutcome};
+use transaction::metrics;
+use transaction::Transaction;
+use transaction_r2d2::{Connection, PooledError, PooledRunner, PooledTransaction};
+
+type ConnError<M> = <<M as ManageConnection>::Connection as Connection>::Error;
+
+/// A database of the app, configured in the `databases` table of the Rocket
+/// configuration under its name.
+pub trait Database: Send + Sync + 'static {
+    /// The name of the database in the configuration
+    const NAME: &'static str;
+
+    /// The connection manager of the pool
+    type Manager: ManageConnection;
+
+    /// Build the connection manager from the configuration
+    fn manager(config: &Config) -> Result<Self::Manager, Box<dyn Error + Send + Sync>>;
+}
+
+/// The configuration of a database
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Config {
+    /// The URL of the database, interpreted by `Database::manager`
+    pub url: String,
+    /// The maximum number of connections of the pool, 10 by default
+    #[serde(default)]
+    pub max_size: Option<u32>,
+    /// The seconds to wait for a connection of the pool, 30 by default
+    #[serde(default)]
+    pub connect_timeout: Option<u64>,
+}
+
+/// The pool of the database `D`, managed by `TxFairing`
+pub struct Pool<D>
+where
+    D: Database,
+{
+    runner: PooledRunner<D::Manager>,
+}
+
+impl<D> Pool<D>
+where
+    D: Database,
+    <D::Manager as ManageConnection>::Connection: Connection,
+{
+    /// The runner on the pool, e.g. to run transactions outside of requests
+    pub fn runner(&self) -> &PooledRunner<D::Manager> {
+        &self.runner
+    }
+}
+
+impl<D> fmt::Debug for Pool<D>
+where
+    D: Database,
+    D::Manager: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pool").field("runner", &self.runner).finish()
+    }
+}
+
+/// A fairing building the pool of the database `D` at ignition, and
+/// committing or rolling back the backend transactions of the requests by
+/// the status of their responses.
+///
+/// The ignition fails if the database is not configured or its pool cannot
+/// be built.
+pub struct TxFairing<D>(PhantomData<fn() -> D>);
+
+impl<D> TxFairing<D>
+where
+    D: Database,
+{
+    /// The fairing of the database
+    pub fn new() -> Self {
+        TxFairing(PhantomData)
+    }
+}
+
+impl<D> Default for TxFairing<D>
+where
+    D: Database,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> fmt::Debug for TxFairing<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TxFairing").finish()
+    }
+}
+
+#[rocket::async_trait]
+impl<D> Fairing for TxFairing<D>
+where
+    D: Database,
+    <D::Manager as ManageConnection>::Connection: Connection,
+{
+    fn info(&self) -> Info {
+        Info {
+            name: D::NAME,
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = match rocket.figment().extract_inner::<Config>(&format!("databases.{}", D::NAME)) {
+            Ok(config) => config,
+            Err(e) => {
+                rocket::error!("database {} is not configured: {}", D::NAME, e);
+                return Err(rocket);
+            }
+        };
+        // building the pool connects to the database
+        let pool = task::spawn_blocking(move || {
+            let manager = D::manager(&config)?;
+            let mut builder = r2d2::Pool::builder();
+            if let Some(max_size) = config.max_size {
+                builder = builder.max_size(max_size);
+            }
+            if let Some(connect_timeout) = config.connect_timeout {
+                builder = builder.connection_timeout(Duration::from_secs(connect_timeout));
+            }
+            Ok::<_, Box<dyn Error + Send + Sync>>(builder.build(manager)?)
+        })
+        .await;
+        match pool {
+            Ok(Ok(pool)) => Ok(rocket.manage(Pool::<D> {
+                runner: PooledRunner::new(pool),
+            })),
+            Ok(Err(e)) => {
+                rocket::error!("failed to build the pool of database {}: {}", D::NAME, e);
+                Err(rocket)
+            }
+            Err(e) => panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let (tx, start) = {
+            let state = req.local_cache(TxState::<D>::default);
+            let mut state = state.0.lock().unwrap_or_else(|e| e.into_inner());
+            (state.tx.take(), state.start.take())
+        };
+        let (tx, start) = match (tx, start) {
+            (Some(tx), Some(start)) => (tx, start),
+            _ => return,
+        };
+        let status = res.status();
+        let label = None;
+        let outcome = if status.class().is_client_error() || status.class().is_server_error() {
+            RunOutcome::RolledBack
+        } else {
+            RunOutcome::Committed
+        };
+        let committed = task::spawn_blocking(move || match outcome {
+            RunOutcome::Committed => tx.commit().is_ok(),
+            RunOutcome::RolledBack => {
+                let _ = tx.rollback();
+                true
+            }
+        })
+        .await
+        .unwrap_or(false);
+        // the response of a failed commit would lie
+        let outcome = if committed {
+            outcome
+        } else {
+            res.set_status(Status::InternalServerError);
+            res.set_sized_body(None, io::Cursor::new(""));
+            RunOutcome::RolledBack
+        };
+        metrics::record_run(label, outcome, start.elapsed());
+        #[cfg(feature = "tracing")]
+        match outcome {
+            RunOutcome::Committed => tracing::debug!(backend = "rocket", database = D::NAME, "commit"),
+            RunOutcome::RolledBack => tracing::debug!(backend = "rocket", database = D::NAME, "rollback"),
+        }
+        #[cfg(feature = "log")]
+        match outcome {
+            RunOutcome::Committed => log::debug!("commit transaction of the request on {}", D::NAME),
+            RunOutcome::RolledBack => log::debug!("rollback transaction of the request on {}", D::NAME),
+        }
+        hooks::after_run(label, outcome);
+    }
+}
+
+// the backend transaction of a request on the database `D`, begun at the
+// first run, in the local cache of the request
+struct TxState<D>(Arc<Mutex<State<D::Manager>>>)
+where
+    D: Database,
+    <D::Manager as ManageConnection>::Connection: Connection;
+
+impl<D> Default for TxState<D>
+where
+    D: Database,
+    <D::Manager as ManageConnection>::Connection: Connection,
+{
+    fn default() -> Self {
+        TxState(Arc::new(Mutex::new(State { tx: None, start: None })))
+    }
+}
+
+struct State<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+{
+    tx: Option<PooledTransaction<M>>,
+    start: Option<Instant>,
+}
+
+/// A request guard running the transactions of the request in its backend
+/// transaction on the database `D`.
+///
+/// The guard fails with 500 Internal Server Error if the `TxFairing` of the
+/// database is not attached.
+pub struct Transactional<D>
+where
+    D: Database,
+    <D::Manager as ManageConnection>::Connection: Connection,
+{
+    runner: PooledRunner<D::Manager>,
+    state: Arc<Mutex<State<D::Manager>>>,
+}
+
+impl<D> Transactional<D>
+where
+    D: Database,
+    <D::Manager as ManageConnection>::Connection: Connection,
+{
+    /// The runner on the pool, e.g. to run transactions committed
+    /// regardless of the response
+    pub fn runner(&self) -> &PooledRunner<D::Manager> {
+        &self.runner
+    }
+
+    /// run the given transaction in the backend transaction of the request
+    /// on the blocking threads, beginning it at the first run. A failed
+    /// transaction leaves its changes in the backend transaction, which is
+    /// rolled back if the route responds with an error status.
+    pub async fn run<T, E, Tx>(&self, tx: Tx) -> Result<T, E>
+    where
+        T: Send + 'static,
+        E: From<PooledError<ConnError<D::Manager>>> + Send + 'static,
+        Tx: Transaction<Ctx = <D::Manager as ManageConnection>::Connection, Item = T, Err = E> + Send + 'static,
+    {
+        let runner = self.runner.clone();
+        let state = self.state.clone();
+        let ret = task::spawn_blocking(move || {
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            if state.tx.is_none() {
+                hooks::before_run(None);
+                #[cfg(feature = "log")]
+                log::debug!("start transaction of the request on {}", D::NAME);
+                state.start = Some(Instant::now());
+                state.tx = Some(runner.begin()?);
+            }
+            state.tx.as_mut().expect("transaction is begun").run(&tx)
+        })
+        .await;
+        match ret {
+            Ok(ret) => ret,
+            Err(e) => panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, D> FromRequest<'r> for Transactional<D>
+where
+    D: Database,
+    <D::Manager as ManageConnection>::Connection: Connection,
+{
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let pool = match req.rocket().state::<Pool<D>>() {
+            Some(pool) => pool,
+            None => return Outcome::Error((Status::InternalServerError, "TxFairing is not attached")),
+        };
+        Outcome::Success(Transactional {
+            runner: pool.runner.clone(),
+            state: req.local_cache(TxState::<D>::default).0.clone(),
+        })
+    }
+}
+
+impl<D> fmt::Debug for Transactional<D>
+where
+    D: Database,
+    D::Manager: fmt::Debug,
+    <D::Manager as ManageConnection>::Connection: Connection,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transactional").field("runner", &self.runner).finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,75 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of rocksdb transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `Busy`: the transaction conflicted with a concurrent one, at commit
+    /// for the optimistic transactions or as a deadlock for the pessimistic
+    /// ones
+    Conflict,
+    /// `TimedOut`: a lock of a pessimistic transaction was not acquired in
+    /// the lock timeout
+    LockTimeout,
+    /// `TryAgain`: the memtable history needed to check the conflicts is
+    /// gone, e.g. the transaction ran too long
+    TryAgain,
+    /// Any other error
+    Other,
+}
+
+/// An error of rocksdb together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: rocksdb::Error,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of rocksdb
+    pub fn get_ref(&self) -> &rocksdb::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of rocksdb
+    pub fn into_inner(self) -> rocksdb::Error {
+        self.inner
+    }
+}
+
+impl From<rocksdb::Error> for Error {
+    fn from(inner: rocksdb::Error) -> Self {
+        let kind = match inner.kind() {
+            rocksdb::ErrorKind::Busy => ErrorKind::Conflict,
+            rocksdb::ErrorKind::TimedOut => ErrorKind::LockTimeout,
+            rocksdb::ErrorKind::TryAgain => ErrorKind::TryAgain,
+            _ => ErrorKind::Other,
+        };
+        Error { kind, inner }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind != ErrorKind::Other
+    }
+}
@@ -0,0 +1,264 @@
+//! A transaction runner for rocksdb
+//!
+//! Transactions run in a `RocksContext` wrapping a transaction of either an
+//! `OptimisticTransactionDB`, which checks the conflicts at commit, or a
+//! `TransactionDB`, which locks the keys written and read by
+//! `get_for_update`. The conflicts, deadlocks and lock timeouts are
+//! `Retryable`, so `run_retry` runs the transaction again following a
+//! `RetryPolicy`.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use rocksdb::OptimisticTransactionDB;
+//! use transaction::prelude::*;
+//! use transaction::Backoff;
+//! use transaction_rocksdb::{get_for_update, put, RunnerBuilder};
+//!
+//! # fn main() -> Result<(), transaction_rocksdb::Error> {
+//! let dir = tempfile::tempdir().unwrap();
+//! let db: OptimisticTransactionDB = OptimisticTransactionDB::open_default(dir.path())?;
+//! let runner = RunnerBuilder::new().snapshot(true).build();
+//!
+//! let incr = get_for_update("hits")
+//!     .map(|v| v.map_or(0, |v| String::from_utf8(v).unwrap().parse::<u64>().unwrap()))
+//!     .and_then(|n| put("hits", (n + 1).to_string()).map(move |()| n + 1));
+//! let policy = Backoff::exponential(Duration::from_millis(1)).max_retries(5);
+//! assert_eq!(runner.run_retry(&db, &incr, &policy)?, 1);
+//! assert_eq!(runner.run_retry(&db, &incr, &policy)?, 2);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use rocksdb::{
+    OptimisticTransactionDB, OptimisticTransactionOptions, ReadOptions, ThreadMode, TransactionDB,
+    TransactionOptions, WriteOptions,
+};
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+use transaction::{RetryPolicy, Retryable, Transaction};
+
+mod error;
+mod ops;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+/// The context of the transactions: a transaction of rocksdb.
+pub struct RocksContext<'a, DB> {
+    tx: rocksdb::Transaction<'a, DB>,
+    snapshot: bool,
+}
+
+impl<'a, DB> RocksContext<'a, DB> {
+    // never pub this function
+    fn new(tx: rocksdb::Transaction<'a, DB>, snapshot: bool) -> Self {
+        RocksContext { tx, snapshot }
+    }
+
+    /// The transaction of rocksdb
+    pub fn transaction(&self) -> &rocksdb::Transaction<'a, DB> {
+        &self.tx
+    }
+
+    // read with the snapshot of the transaction, if taken
+    fn with_read_options<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&rocksdb::Transaction<'a, DB>, &ReadOptions) -> T,
+    {
+        let mut opts = ReadOptions::default();
+        if self.snapshot {
+            let snapshot = self.tx.snapshot();
+            opts.set_snapshot(&snapshot);
+            f(&self.tx, &opts)
+        } else {
+            f(&self.tx, &opts)
+        }
+    }
+}
+
+impl<'a, DB> fmt::Debug for RocksContext<'a, DB> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RocksContext")
+            .field("snapshot", &self.snapshot)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The databases of rocksdb supporting transactions
+pub trait TransactionDb: Sized {
+    /// Begin a transaction configured by the runner
+    fn begin(&self, runner: &Runner) -> rocksdb::Transaction<'_, Self>;
+}
+
+impl<T: ThreadMode> TransactionDb for OptimisticTransactionDB<T> {
+    fn begin(&self, runner: &Runner) -> rocksdb::Transaction<'_, Self> {
+        let mut opts = OptimisticTransactionOptions::new();
+        opts.set_snapshot(runner.snapshot);
+        self.transaction_opt(&WriteOptions::default(), &opts)
+    }
+}
+
+impl<T: ThreadMode> TransactionDb for TransactionDB<T> {
+    fn begin(&self, runner: &Runner) -> rocksdb::Transaction<'_, Self> {
+        let mut opts = TransactionOptions::new();
+        opts.set_snapshot(runner.snapshot);
+        if let Some(timeout) = runner.lock_timeout {
+            opts.set_lock_timeout(timeout.as_millis() as i64);
+        }
+        self.transaction_opt(&WriteOptions::default(), &opts)
+    }
+}
+
+/// run the given function inside a transaction of the database without a
+/// snapshot.
+pub fn run<'a, DB, T, E, Tx>(db: &'a DB, tx: Tx) -> Result<T, E>
+where
+    DB: TransactionDb,
+    E: From<Error>,
+    Tx: Transaction<Ctx = RocksContext<'a, DB>, Item = T, Err = E>,
+{
+    Runner::default().run(db, tx)
+}
+
+/// Builder of a `Runner`, e.g. `RunnerBuilder::new().snapshot(true).build()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunnerBuilder {
+    snapshot: bool,
+    lock_timeout: Option<Duration>,
+}
+
+impl RunnerBuilder {
+    /// Run the transactions without a snapshot and with the lock timeout of
+    /// the database
+    pub fn new() -> Self {
+        RunnerBuilder::default()
+    }
+
+    /// Take a snapshot at the beginning of the transactions. The reads see
+    /// the snapshot, and the transactions conflict with the writes made
+    /// after it to the keys they write or read by `get_for_update`, i.e.
+    /// they are serializable rather than read committed.
+    pub fn snapshot(self, snapshot: bool) -> Self {
+        RunnerBuilder { snapshot, ..self }
+    }
+
+    /// Set how long the pessimistic transactions wait for a lock. The
+    /// optimistic transactions don't lock.
+    pub fn lock_timeout(self, timeout: Duration) -> Self {
+        RunnerBuilder {
+            lock_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> Runner {
+        Runner {
+            snapshot: self.snapshot,
+            lock_timeout: self.lock_timeout,
+        }
+    }
+}
+
+/// Runner of transactions configured by `RunnerBuilder`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Runner {
+    snapshot: bool,
+    lock_timeout: Option<Duration>,
+}
+
+impl Runner {
+    /// Whether a snapshot is taken at the beginning of the transactions
+    pub fn takes_snapshot(&self) -> bool {
+        self.snapshot
+    }
+
+    /// How long the pessimistic transactions wait for a lock, if set
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+
+    /// run the given function inside a transaction of the database. Pass a
+    /// reference to run the same transaction again.
+    ///
+    /// If the transaction panics, the transaction of rocksdb is rolled back
+    /// as it is dropped, releasing its locks, and the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// use rocksdb::TransactionDB;
+    /// use transaction::prelude::*;
+    /// use transaction_rocksdb::{get_for_update, put, RunnerBuilder};
+    ///
+    /// # fn main() -> Result<(), transaction_rocksdb::Error> {
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let db: TransactionDB = TransactionDB::open_default(dir.path())?;
+    /// let runner = RunnerBuilder::new().build();
+    ///
+    /// let boom = put("hits", "1").map(|()| -> () { panic!("boom") });
+    /// assert!(panic::catch_unwind(AssertUnwindSafe(|| runner.run(&db, boom))).is_err());
+    /// // the key is not locked anymore
+    /// assert_eq!(runner.run(&db, get_for_update("hits"))?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run<'a, DB, T, E, Tx>(&self, db: &'a DB, tx: Tx) -> Result<T, E>
+    where
+        DB: TransactionDb,
+        E: From<Error>,
+        Tx: Transaction<Ctx = RocksContext<'a, DB>, Item = T, Err = E>,
+    {
+        hooks::instrument("rocksdb", tx.label(), Outcome::of, || {
+            let mut ctx = RocksContext::new(db.begin(self), self.snapshot);
+            match tx.run(&mut ctx) {
+                Ok(t) => {
+                    ctx.tx.commit().map_err(Error::from)?;
+                    Ok(t)
+                }
+                Err(e) => {
+                    let _ = ctx.tx.rollback();
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    /// run the given function like `run`, running the whole transaction
+    /// again while it fails with a retryable error, e.g. a conflict at
+    /// commit, and the policy allows. Each retry is recorded by
+    /// `metrics::record_retry`.
+    pub fn run_retry<'a, DB, T, E, Tx, R>(&self, db: &'a DB, tx: Tx, policy: R) -> Result<T, E>
+    where
+        DB: TransactionDb,
+        E: From<Error> + Retryable,
+        Tx: Transaction<Ctx = RocksContext<'a, DB>, Item = T, Err = E>,
+        R: RetryPolicy,
+    {
+        let mut retries = 0;
+        loop {
+            let e = match self.run(db, &tx) {
+                Err(e) => if e.is_retryable() { e } else { return Err(e) },
+                Ok(t) => return Ok(t),
+            };
+            let delay = match policy.next_delay(retries) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            #[cfg(feature = "log")]
+            log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+            metrics::record_retry(tx.label());
+            thread::sleep(delay);
+            retries += 1;
+        }
+    }
+}
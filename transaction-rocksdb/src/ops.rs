@@ -0,0 +1,190 @@
+use std::marker::PhantomData;
+
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{Error, RocksContext};
+
+/// Get the value of the key, reading from the snapshot if the runner takes
+/// one.
+pub fn get<'a, DB, K>(key: K) -> Get<'a, DB>
+where
+    K: Into<Vec<u8>>,
+{
+    Get {
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get`
+#[derive(Debug)]
+#[must_use]
+pub struct Get<'a, DB> {
+    key: Vec<u8>,
+    _phantom: PhantomData<&'a DB>,
+}
+
+impl<'a, DB> Transaction for Get<'a, DB> {
+    type Ctx = RocksContext<'a, DB>;
+    type Item = Option<Vec<u8>>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut RocksContext<'a, DB>) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.with_read_options(|tx, opts| tx.get_opt(&self.key, opts))?)
+    }
+}
+
+impl<'a, DB> Visit for Get<'a, DB> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get"));
+    }
+}
+
+/// Get the value of the key and track it for conflicts, so that the
+/// transaction fails if another one writes it before the commit. Pessimistic
+/// transactions lock the key exclusively until the end.
+pub fn get_for_update<'a, DB, K>(key: K) -> GetForUpdate<'a, DB>
+where
+    K: Into<Vec<u8>>,
+{
+    GetForUpdate {
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get_for_update`
+#[derive(Debug)]
+#[must_use]
+pub struct GetForUpdate<'a, DB> {
+    key: Vec<u8>,
+    _phantom: PhantomData<&'a DB>,
+}
+
+impl<'a, DB> Transaction for GetForUpdate<'a, DB> {
+    type Ctx = RocksContext<'a, DB>;
+    type Item = Option<Vec<u8>>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut RocksContext<'a, DB>) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.with_read_options(|tx, opts| tx.get_for_update_opt(&self.key, true, opts))?)
+    }
+}
+
+impl<'a, DB> Visit for GetForUpdate<'a, DB> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get_for_update"));
+    }
+}
+
+/// Set the value of the key.
+pub fn put<'a, DB, K, V>(key: K, value: V) -> Put<'a, DB>
+where
+    K: Into<Vec<u8>>,
+    V: Into<Vec<u8>>,
+{
+    Put {
+        key: key.into(),
+        value: value.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `put`
+#[derive(Debug)]
+#[must_use]
+pub struct Put<'a, DB> {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    _phantom: PhantomData<&'a DB>,
+}
+
+impl<'a, DB> Transaction for Put<'a, DB> {
+    type Ctx = RocksContext<'a, DB>;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut RocksContext<'a, DB>) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.transaction().put(&self.key, &self.value)?)
+    }
+}
+
+impl<'a, DB> Visit for Put<'a, DB> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("put"));
+    }
+}
+
+/// Delete the key.
+pub fn delete<'a, DB, K>(key: K) -> Delete<'a, DB>
+where
+    K: Into<Vec<u8>>,
+{
+    Delete {
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `delete`
+#[derive(Debug)]
+#[must_use]
+pub struct Delete<'a, DB> {
+    key: Vec<u8>,
+    _phantom: PhantomData<&'a DB>,
+}
+
+impl<'a, DB> Transaction for Delete<'a, DB> {
+    type Ctx = RocksContext<'a, DB>;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut RocksContext<'a, DB>) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.transaction().delete(&self.key)?)
+    }
+}
+
+impl<'a, DB> Visit for Delete<'a, DB> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete"));
+    }
+}
+
+/// Merge the operand into the value of the key with the merge operator of
+/// the database.
+pub fn merge<'a, DB, K, V>(key: K, operand: V) -> Merge<'a, DB>
+where
+    K: Into<Vec<u8>>,
+    V: Into<Vec<u8>>,
+{
+    Merge {
+        key: key.into(),
+        operand: operand.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `merge`
+#[derive(Debug)]
+#[must_use]
+pub struct Merge<'a, DB> {
+    key: Vec<u8>,
+    operand: Vec<u8>,
+    _phantom: PhantomData<&'a DB>,
+}
+
+impl<'a, DB> Transaction for Merge<'a, DB> {
+    type Ctx = RocksContext<'a, DB>;
+    type Item = ();
+    type Err = Error;
+
+    fn run(&self, ctx: &mut RocksContext<'a, DB>) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.transaction().merge(&self.key, &self.operand)?)
+    }
+}
+
+impl<'a, DB> Visit for Merge<'a, DB> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("merge"));
+    }
+}
@@ -0,0 +1,81 @@
+use std::error;
+use std::fmt;
+
+use rusqlite::ErrorCode;
+use transaction::Retryable;
+
+// SQLITE_BUSY_SNAPSHOT
+const BUSY_SNAPSHOT: i32 = 517;
+
+/// The classification of the errors of rusqlite by their result codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `SQLITE_BUSY_SNAPSHOT`: the snapshot of a WAL read transaction is
+    /// stale and cannot be upgraded to a write transaction
+    SerializationFailure,
+    /// `SQLITE_BUSY` or `SQLITE_LOCKED`: the database is locked by another
+    /// connection beyond the busy timeout, or immediately when waiting could
+    /// deadlock
+    LockTimeout,
+    /// Any other error
+    Other,
+}
+
+/// An error of rusqlite together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: rusqlite::Error,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of rusqlite
+    pub fn get_ref(&self) -> &rusqlite::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of rusqlite
+    pub fn into_inner(self) -> rusqlite::Error {
+        self.inner
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(inner: rusqlite::Error) -> Self {
+        let kind = match inner {
+            rusqlite::Error::SqliteFailure(ref e, _) if e.extended_code == BUSY_SNAPSHOT => {
+                ErrorKind::SerializationFailure
+            }
+            rusqlite::Error::SqliteFailure(ref e, _)
+                if e.code == ErrorCode::DatabaseBusy || e.code == ErrorCode::DatabaseLocked =>
+            {
+                ErrorKind::LockTimeout
+            }
+            _ => ErrorKind::Other,
+        };
+        Error { kind, inner }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind != ErrorKind::Other
+    }
+}
@@ -0,0 +1,94 @@
+use rusqlite::types::{FromSql, ToSql};
+use rusqlite::{params, Connection, OptionalExtension};
+use transaction::{idempotent, IdempotencyStore, Transaction};
+
+use crate::{execute_batch, Error, ExecuteBatch, Runner, SqliteContext};
+
+/// Create the table `idempotency_keys` the items of `IdempotentRunner` are
+/// recorded in, if it doesn't exist
+pub fn create_idempotency_keys<'a>() -> ExecuteBatch<'a> {
+    execute_batch(
+        "CREATE TABLE IF NOT EXISTS idempotency_keys (
+             key TEXT PRIMARY KEY,
+             item
+         )",
+    )
+}
+
+// the items are stored as they are, so any type rusqlite converts works,
+// e.g. a JSON string for structured ones
+impl<'a, T> IdempotencyStore<T> for SqliteContext<'a>
+where
+    T: ToSql + FromSql,
+{
+    type Error = Error;
+
+    fn lookup(&mut self, key: &str) -> Result<Option<T>, Error> {
+        lookup(&self.tx, key)
+    }
+
+    fn record(&mut self, key: &str, item: &T) -> Result<(), Error> {
+        self.tx
+            .execute("INSERT INTO idempotency_keys (key, item) VALUES (?1, ?2)", params![key, item])?;
+        Ok(())
+    }
+}
+
+fn lookup<T: FromSql>(conn: &Connection, key: &str) -> Result<Option<T>, Error> {
+    Ok(conn
+        .query_row("SELECT item FROM idempotency_keys WHERE key = ?1", params![key], |row| row.get(0))
+        .optional()?)
+}
+
+/// Runner of transactions run once per idempotency key, e.g. of a payment
+/// or a webhook. The item of the transaction is recorded in the table
+/// created by `create_idempotency_keys` when it commits, and returned
+/// instead of running the transaction again for the same key.
+///
+/// # Examples
+///
+/// ```
+/// use rusqlite::Connection;
+/// use transaction::prelude::*;
+/// use transaction_rusqlite::{create_idempotency_keys, execute, run, RunnerBuilder};
+///
+/// # fn main() -> Result<(), transaction_rusqlite::Error> {
+/// let conn = Connection::open_in_memory()?;
+/// run(&conn, create_idempotency_keys().and_then(|_| execute("CREATE TABLE charges (amount INTEGER)", vec![])))?;
+///
+/// let runner = RunnerBuilder::new().build_idempotent();
+/// let charge = || execute("INSERT INTO charges VALUES (100)", vec![]).map(|_| "charged".to_string());
+/// assert_eq!(runner.run(&conn, "payment-1", charge())?, "charged");
+/// assert_eq!(runner.run(&conn, "payment-1", charge())?, "charged");
+///
+/// let count: i64 = conn.query_row("SELECT COUNT(*) FROM charges", [], |row| row.get(0))?;
+/// assert_eq!(count, 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdempotentRunner {
+    pub(crate) runner: Runner,
+}
+
+impl IdempotentRunner {
+    /// run the given function inside a transaction using the given
+    /// connection, unless a transaction of the key committed before, and
+    /// return its item.
+    pub fn run<'a, T, E, Tx>(&self, conn: &'a Connection, key: &str, tx: Tx) -> Result<T, E>
+    where
+        T: ToSql + FromSql,
+        E: From<Error>,
+        Tx: Transaction<Ctx = SqliteContext<'a>, Item = T, Err = E>,
+    {
+        match self.runner.run(conn, idempotent(key, tx)) {
+            Ok(t) => Ok(t),
+            // another connection may have run the same key concurrently and
+            // committed first, failing the insertion of the key
+            Err(e) => match lookup(conn, key) {
+                Ok(Some(t)) => Ok(t),
+                _ => Err(e),
+            },
+        }
+    }
+}
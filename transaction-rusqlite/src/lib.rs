@@ -0,0 +1,321 @@
+//! A transaction runner for rusqlite
+//!
+//! Transactions run in a `SqliteContext` wrapping a transaction of rusqlite,
+//! which `run` commits when the transaction succeeds and rolls back
+//! otherwise. `execute`, `query_row`, `query_map` and friends expose the
+//! statements of rusqlite as leaves, and `nested` runs a part of the
+//! transaction in a savepoint. The context is an `Outbox` storing the events
+//! of `publish` in the table created by `create_outbox`, which a `Relay`
+//! drains from a `SqliteOutbox`.
+//!
+//! The transactions begin `DEFERRED` by default, i.e. take the write lock on
+//! the first write. Writers waiting for each other are better begun
+//! `IMMEDIATE` with `RunnerBuilder`, so that they wait for the busy timeout
+//! at `BEGIN` instead of failing on the upgrade.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use rusqlite::Connection;
+//! use transaction::prelude::*;
+//! use transaction::Backoff;
+//! use transaction_rusqlite::{execute, execute_batch, nested, query_row, BeginMode, RunnerBuilder};
+//!
+//! # fn main() -> Result<(), transaction_rusqlite::Error> {
+//! let conn = Connection::open_in_memory()?;
+//! let runner = RunnerBuilder::new()
+//!     .begin(BeginMode::Immediate)
+//!     .busy_timeout(Duration::from_secs(5))
+//!     .build();
+//!
+//! runner.run(&conn, execute_batch("CREATE TABLE users (name TEXT UNIQUE)"))?;
+//! let tx = execute("INSERT INTO users VALUES (?1)", vec![Box::new("alice")])
+//!     // the duplicate is rolled back to the savepoint
+//!     .and_then(|_| nested(execute("INSERT INTO users VALUES (?1)", vec![Box::new("alice")])))
+//!     .or_else(|_| ok(0))
+//!     .and_then(|_| query_row("SELECT COUNT(*) FROM users", vec![], |row| row.get::<_, i64>(0)));
+//! let count = runner.run_retry(&conn, tx, Backoff::exponential(Duration::from_millis(10)))?;
+//! assert_eq!(count, 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use rusqlite::{Connection, TransactionBehavior};
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+use transaction::{RetryPolicy, Retryable, Savepoints, Transaction};
+
+mod error;
+mod idempotent;
+mod nested;
+mod outbox;
+mod statement;
+
+pub use crate::error::*;
+pub use crate::idempotent::*;
+pub use crate::nested::*;
+pub use crate::outbox::*;
+pub use crate::statement::*;
+
+/// The context of the transactions: a transaction of rusqlite.
+pub struct SqliteContext<'a> {
+    tx: rusqlite::Transaction<'a>,
+    // the number of the enclosing `nested`
+    depth: usize,
+}
+
+impl<'a> SqliteContext<'a> {
+    // never pub this function
+    fn new(tx: rusqlite::Transaction<'a>) -> Self {
+        SqliteContext { tx, depth: 0 }
+    }
+
+    /// The connection in the transaction
+    pub fn conn(&self) -> &Connection {
+        &self.tx
+    }
+}
+
+impl<'a> fmt::Debug for SqliteContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SqliteContext").field("depth", &self.depth).finish_non_exhaustive()
+    }
+}
+
+impl<'a> Savepoints for SqliteContext<'a> {
+    type Error = Error;
+
+    fn savepoint(&mut self, name: &str) -> Result<(), Self::Error> {
+        Ok(self.tx.execute_batch(&format!("SAVEPOINT {}", name))?)
+    }
+
+    fn release(&mut self, name: &str) -> Result<(), Self::Error> {
+        Ok(self.tx.execute_batch(&format!("RELEASE SAVEPOINT {}", name))?)
+    }
+
+    fn rollback_to(&mut self, name: &str) -> Result<(), Self::Error> {
+        Ok(self.tx.execute_batch(&format!("ROLLBACK TO SAVEPOINT {}", name))?)
+    }
+}
+
+/// When the transactions take the locks of the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BeginMode {
+    /// `BEGIN DEFERRED`: take the locks on the first read and write
+    #[default]
+    Deferred,
+    /// `BEGIN IMMEDIATE`: take the write lock at once
+    Immediate,
+    /// `BEGIN EXCLUSIVE`: take the write lock at once, and keep the readers
+    /// out unless in WAL mode
+    Exclusive,
+}
+
+impl BeginMode {
+    fn behavior(self) -> TransactionBehavior {
+        match self {
+            BeginMode::Deferred => TransactionBehavior::Deferred,
+            BeginMode::Immediate => TransactionBehavior::Immediate,
+            BeginMode::Exclusive => TransactionBehavior::Exclusive,
+        }
+    }
+}
+
+/// run the given function inside a deferred transaction using the given
+/// connection.
+pub fn run<'a, T, E, Tx>(conn: &'a Connection, tx: Tx) -> Result<T, E>
+where
+    E: From<Error>,
+    Tx: Transaction<Ctx = SqliteContext<'a>, Item = T, Err = E>,
+{
+    Runner::default().run(conn, tx)
+}
+
+/// Builder of a `Runner`, e.g.
+/// `RunnerBuilder::new().begin(BeginMode::Immediate).build()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunnerBuilder {
+    begin: BeginMode,
+    busy_timeout: Option<Duration>,
+}
+
+impl RunnerBuilder {
+    /// Begin the transactions deferred, keeping the busy timeout of the
+    /// connections
+    pub fn new() -> Self {
+        RunnerBuilder::default()
+    }
+
+    /// Set when the transactions take the locks
+    pub fn begin(self, begin: BeginMode) -> Self {
+        RunnerBuilder { begin, ..self }
+    }
+
+    /// Set the busy timeout of the connections before beginning the
+    /// transactions: how long SQLite waits for the locks held by the other
+    /// connections before failing with `SQLITE_BUSY`
+    pub fn busy_timeout(self, timeout: Duration) -> Self {
+        RunnerBuilder {
+            busy_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> Runner {
+        Runner {
+            begin: self.begin,
+            busy_timeout: self.busy_timeout,
+        }
+    }
+
+    /// Build the runner rolling back every transaction, for tests
+    pub fn build_test(self) -> TestRunner {
+        TestRunner { runner: self.build() }
+    }
+
+    /// Build the runner running each transaction once per idempotency key
+    pub fn build_idempotent(self) -> IdempotentRunner {
+        IdempotentRunner { runner: self.build() }
+    }
+}
+
+/// Runner of transactions configured by `RunnerBuilder`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Runner {
+    begin: BeginMode,
+    busy_timeout: Option<Duration>,
+}
+
+impl Runner {
+    /// When the transactions take the locks
+    pub fn begin_mode(&self) -> BeginMode {
+        self.begin
+    }
+
+    /// run the given function inside a transaction using the given
+    /// connection. Pass a reference to run the same transaction again.
+    ///
+    /// If the transaction panics, the transaction of rusqlite is rolled back
+    /// as it is dropped, leaving the connection out of any transaction, and
+    /// the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// use rusqlite::Connection;
+    /// use transaction::prelude::*;
+    /// use transaction_rusqlite::{execute, execute_batch, query_row, Error, Runner};
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let conn = Connection::open_in_memory()?;
+    /// let runner = Runner::default();
+    /// runner.run(&conn, execute_batch("CREATE TABLE users (name TEXT)"))?;
+    ///
+    /// let boom = execute("INSERT INTO users VALUES ('alice')", vec![])
+    ///     .map(|_| -> () { panic!("boom") });
+    /// assert!(panic::catch_unwind(AssertUnwindSafe(|| runner.run(&conn, boom))).is_err());
+    /// assert!(conn.is_autocommit());
+    /// let count = query_row("SELECT COUNT(*) FROM users", vec![], |row| row.get::<_, i64>(0));
+    /// assert_eq!(runner.run(&conn, count)?, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run<'a, T, E, Tx>(&self, conn: &'a Connection, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = SqliteContext<'a>, Item = T, Err = E>,
+    {
+        hooks::instrument("rusqlite", tx.label(), Outcome::of, || {
+            let mut ctx = self.begin(conn)?;
+            match tx.run(&mut ctx) {
+                Ok(t) => {
+                    ctx.tx.commit().map_err(Error::from)?;
+                    Ok(t)
+                }
+                Err(e) => {
+                    let _ = ctx.tx.rollback();
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    /// run the given function like `run`, running the whole transaction
+    /// again while it fails with a retryable error and the policy allows.
+    /// Each retry is recorded by `metrics::record_retry`.
+    ///
+    /// SQLite already waits for the locks for the busy timeout, so the
+    /// retries mostly cover the `SQLITE_BUSY` returned without waiting: a
+    /// deferred transaction failing to upgrade to a write transaction, or a
+    /// stale snapshot in WAL mode.
+    pub fn run_retry<'a, T, E, Tx, R>(&self, conn: &'a Connection, tx: Tx, policy: R) -> Result<T, E>
+    where
+        E: From<Error> + Retryable,
+        Tx: Transaction<Ctx = SqliteContext<'a>, Item = T, Err = E>,
+        R: RetryPolicy,
+    {
+        let mut retries = 0;
+        loop {
+            let e = match self.run(conn, &tx) {
+                Err(e) => if e.is_retryable() { e } else { return Err(e) },
+                Ok(t) => return Ok(t),
+            };
+            let delay = match policy.next_delay(retries) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            #[cfg(feature = "log")]
+            log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+            metrics::record_retry(tx.label());
+            thread::sleep(delay);
+            retries += 1;
+        }
+    }
+
+    // begin a transaction in the mode
+    fn begin<'a>(&self, conn: &'a Connection) -> Result<SqliteContext<'a>, Error> {
+        if let Some(timeout) = self.busy_timeout {
+            conn.busy_timeout(timeout)?;
+        }
+        let tx = rusqlite::Transaction::new_unchecked(conn, self.begin.behavior())?;
+        Ok(SqliteContext::new(tx))
+    }
+}
+
+/// Runner of transactions which are always rolled back, even when they
+/// succeed, for hermetic tests. The `Item` or the error of the transaction
+/// is returned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestRunner {
+    runner: Runner,
+}
+
+impl TestRunner {
+    /// run the given function inside a transaction using the given
+    /// connection, and roll it back.
+    pub fn run<'a, T, E, Tx>(&self, conn: &'a Connection, tx: Tx) -> Result<T, E>
+    where
+        E: From<Error>,
+        Tx: Transaction<Ctx = SqliteContext<'a>, Item = T, Err = E>,
+    {
+        hooks::instrument("rusqlite", tx.label(), |_| Outcome::RolledBack, || {
+            let mut ctx = self.runner.begin(conn)?;
+            let ret = tx.run(&mut ctx);
+            match ctx.tx.rollback() {
+                // a failure to roll back is reported since the test is no
+                // longer hermetic
+                Err(e) if ret.is_ok() => Err(Error::from(e).into()),
+                _ => ret,
+            }
+        })
+    }
+}
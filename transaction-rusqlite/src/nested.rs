@@ -0,0 +1,73 @@
+use transaction::{visit_node, IntoTransaction, Node, Savepoints, Transaction, Visit, Visitor};
+
+use crate::{Error, SqliteContext};
+
+/// Run the transaction in a savepoint. When it fails, the changes it made are
+/// rolled back to the savepoint and the error is returned, so the enclosing
+/// transaction can recover with e.g. `or_else` and still commit.
+pub fn nested<'a, A>(a: A) -> Nested<A::Tx>
+where
+    A: IntoTransaction<SqliteContext<'a>>,
+{
+    Nested { tx: a.into_transaction() }
+}
+
+/// The result of `nested`
+#[derive(Debug)]
+#[must_use]
+pub struct Nested<Tx> {
+    tx: Tx,
+}
+
+impl<'a, Tx> Transaction for Nested<Tx>
+where
+    Tx: Transaction<Ctx = SqliteContext<'a>>,
+    Tx::Err: From<Error>,
+{
+    type Ctx = SqliteContext<'a>;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut SqliteContext<'a>) -> Result<Self::Item, Self::Err> {
+        // savepoints are named after their depth, so the nested ones don't
+        // shadow each other
+        ctx.depth += 1;
+        let name = format!("transaction_rusqlite_{}", ctx.depth);
+        let ret = run_in_savepoint(&self.tx, ctx, &name);
+        ctx.depth -= 1;
+        ret
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+fn run_in_savepoint<'a, Tx>(tx: &Tx, ctx: &mut SqliteContext<'a>, name: &str) -> Result<Tx::Item, Tx::Err>
+where
+    Tx: Transaction<Ctx = SqliteContext<'a>>,
+    Tx::Err: From<Error>,
+{
+    ctx.savepoint(name)?;
+    match tx.run(ctx) {
+        Ok(item) => {
+            ctx.release(name)?;
+            Ok(item)
+        }
+        Err(e) => {
+            // ROLLBACK TO keeps the savepoint on the stack
+            ctx.rollback_to(name)?;
+            ctx.release(name)?;
+            Err(e)
+        }
+    }
+}
+
+impl<Tx> Visit for Nested<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("nested"), |v| self.tx.accept(v));
+    }
+}
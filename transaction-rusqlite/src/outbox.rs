@@ -0,0 +1,109 @@
+use rusqlite::{params, Connection};
+use transaction::outbox::{Event, Outbox, Pending, Source};
+
+use crate::{execute_batch, Error, ExecuteBatch, SqliteContext};
+
+/// Create the table `outbox` the events of `publish` are inserted into, if
+/// it doesn't exist
+pub fn create_outbox<'a>() -> ExecuteBatch<'a> {
+    execute_batch(
+        "CREATE TABLE IF NOT EXISTS outbox (
+             id INTEGER PRIMARY KEY AUTOINCREMENT,
+             topic TEXT NOT NULL,
+             payload BLOB NOT NULL
+         )",
+    )
+}
+
+impl<'a> Outbox for SqliteContext<'a> {
+    type Error = Error;
+
+    fn insert(&mut self, event: &Event) -> Result<(), Self::Error> {
+        self.tx.execute(
+            "INSERT INTO outbox (topic, payload) VALUES (?1, ?2)",
+            params![event.topic, event.payload],
+        )?;
+        Ok(())
+    }
+}
+
+/// The table `outbox` of the connection, drained by a `Relay`
+///
+/// # Examples
+///
+/// ```
+/// use rusqlite::Connection;
+/// use transaction::prelude::*;
+/// use transaction::outbox::{Broker, Event, Relay};
+/// use transaction_rusqlite::{create_outbox, execute, run, SqliteOutbox};
+///
+/// struct Sent(Vec<Event>);
+///
+/// impl Broker for Sent {
+///     type Error = std::convert::Infallible;
+///     fn send(&mut self, event: &Event) -> Result<(), Self::Error> {
+///         self.0.push(event.clone());
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let conn = Connection::open_in_memory()?;
+/// run(&conn, create_outbox().and_then(|_| execute("CREATE TABLE users (name TEXT)", vec![])))?;
+///
+/// let tx = execute("INSERT INTO users VALUES (?1)", vec![Box::new("alice")])
+///     .publish(Event::new("users", "alice signed up"));
+/// run(&conn, tx)?;
+///
+/// let mut relay = Relay::new(SqliteOutbox::new(&conn), Sent(vec![]));
+/// assert_eq!(relay.drain()?, 1);
+/// assert_eq!(relay.broker().0, vec![Event::new("users", "alice signed up")]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SqliteOutbox<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> SqliteOutbox<'a> {
+    /// The outbox of the connection
+    pub fn new(conn: &'a Connection) -> Self {
+        SqliteOutbox { conn }
+    }
+}
+
+impl<'a> Source for SqliteOutbox<'a> {
+    type Id = i64;
+    type Error = Error;
+
+    fn fetch(&mut self, limit: usize) -> Result<Vec<Pending<i64>>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT id, topic, payload FROM outbox ORDER BY id LIMIT ?1")?;
+        let pending = stmt
+            .query_map(params![limit as i64], |row| {
+                Ok(Pending {
+                    id: row.get(0)?,
+                    event: Event {
+                        topic: row.get(1)?,
+                        payload: row.get(2)?,
+                    },
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(pending)
+    }
+
+    fn remove(&mut self, ids: &[i64]) -> Result<(), Error> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare_cached("DELETE FROM outbox WHERE id = ?1")?;
+            for id in ids {
+                stmt.execute(params![id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
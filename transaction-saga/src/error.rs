@@ -0,0 +1,62 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// The error of a saga which didn't complete
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The step `step` failed, and the steps before it were compensated.
+    /// The error is `None` when the step failed before the saga was resumed.
+    Compensated {
+        /// The index of the step which failed
+        step: usize,
+        /// The error of the step
+        error: Option<E>,
+    },
+    /// The compensation of the step `step` failed, so the steps up to it
+    /// are left committed. `Saga::resume` runs the compensations again.
+    Compensation {
+        /// The index of the step whose compensation failed
+        step: usize,
+        /// The error of the compensation
+        error: E,
+    },
+    /// The progress couldn't be recorded in the log. `Saga::resume`
+    /// continues from the progress last recorded.
+    Log(io::Error),
+}
+
+impl<E> fmt::Display for Error<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Compensated { step, error: Some(ref e) } => {
+                write!(f, "step {} failed and the saga was compensated: {}", step, e)
+            }
+            Error::Compensated { step, error: None } => write!(f, "step {} failed and the saga was compensated", step),
+            Error::Compensation { step, ref error } => write!(f, "failed to compensate step {}: {}", step, error),
+            Error::Log(ref e) => write!(f, "failed to record the progress of the saga: {}", e),
+        }
+    }
+}
+
+impl<E> error::Error for Error<E>
+where
+    E: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Compensated { ref error, .. } => error.as_ref().map(|e| e as &dyn error::Error),
+            Error::Compensation { ref error, .. } => Some(error),
+            Error::Log(ref e) => Some(e),
+        }
+    }
+}
+
+impl<E> From<io::Error> for Error<E> {
+    fn from(e: io::Error) -> Self {
+        Error::Log(e)
+    }
+}
@@ -0,0 +1,207 @@
+//! Sagas of transactions undone by compensating transactions
+//!
+//! A `Saga` is a sequence of steps, each of which pairs a forward
+//! transaction with a compensation undoing it. The steps run one by one,
+//! each committed on its own by a runner of the backend, so a saga may span
+//! several databases or services which can't share a transaction. When a
+//! step fails, the compensations of the steps before it run in reverse
+//! order.
+//!
+//! With a `SagaLog`, the progress is recorded after each step, and
+//! `Saga::resume` continues a saga interrupted by a crash. A step which
+//! committed right before the crash runs again, so the steps and the
+//! compensations should be idempotent.
+//!
+//! # Examples
+//!
+//! ```
+//! use transaction::prelude::*;
+//! use transaction_saga::{Error, Saga};
+//!
+//! #[derive(Default)]
+//! struct Bookings {
+//!     hotels: Vec<&'static str>,
+//!     flights: Vec<&'static str>,
+//! }
+//!
+//! # fn main() {
+//! let saga = Saga::new("trip-42")
+//!     .step(
+//!         with_ctx(|b: &mut Bookings| -> Result<(), String> {
+//!             b.hotels.push("grand hotel");
+//!             Ok(())
+//!         }),
+//!         with_ctx(|b: &mut Bookings| -> Result<(), String> {
+//!             b.hotels.retain(|h| *h != "grand hotel");
+//!             Ok(())
+//!         }),
+//!     )
+//!     .step(
+//!         with_ctx(|_: &mut Bookings| -> Result<(), String> { Err("sold out".to_string()) }),
+//!         with_ctx(|b: &mut Bookings| -> Result<(), String> {
+//!             b.flights.clear();
+//!             Ok(())
+//!         }),
+//!     );
+//!
+//! let mut bookings = Bookings::default();
+//! // each step is run by the runner of the backend, committing it
+//! let ret = saga.run(|tx| tx.run(&mut bookings));
+//! match ret {
+//!     Err(Error::Compensated { step: 1, error: Some(e) }) => assert_eq!(e, "sold out"),
+//!     _ => panic!("the saga should be compensated"),
+//! }
+//! assert!(bookings.hotels.is_empty());
+//! # }
+//! ```
+
+use std::fmt;
+
+use transaction::{IntoTransaction, Transaction, TransactionExt};
+
+mod error;
+mod log;
+
+pub use crate::error::Error;
+pub use crate::log::{MemoryLog, Progress, SagaLog};
+
+type BoxTx<'a, Ctx, E> = Box<dyn Transaction<Ctx = Ctx, Item = (), Err = E> + 'a>;
+
+/// A sequence of transactions, each paired with a compensation undoing it.
+/// Build it by `new` and `step`, and run it by `run` or `resume`.
+pub struct Saga<'a, Ctx, E> {
+    id: String,
+    steps: Vec<Step<'a, Ctx, E>>,
+}
+
+impl<'a, Ctx, E> fmt::Debug for Saga<'a, Ctx, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Saga")
+            .field("id", &self.id)
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
+
+struct Step<'a, Ctx, E> {
+    forward: BoxTx<'a, Ctx, E>,
+    compensation: BoxTx<'a, Ctx, E>,
+}
+
+impl<'a, Ctx, E> Saga<'a, Ctx, E> {
+    /// A saga without steps. `id` identifies the run of the saga in the
+    /// `SagaLog`, so it must be unique among the runs.
+    pub fn new(id: impl Into<String>) -> Self {
+        Saga {
+            id: id.into(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// The id of the saga
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Add a step running `forward`, which is undone by `compensation` when
+    /// a later step fails. The items of both are discarded.
+    pub fn step<F, C>(mut self, forward: F, compensation: C) -> Self
+    where
+        F: IntoTransaction<Ctx, Err = E>,
+        C: IntoTransaction<Ctx, Err = E>,
+        F::Tx: 'a,
+        C::Tx: 'a,
+        Ctx: 'a,
+        E: 'a,
+    {
+        self.steps.push(Step {
+            forward: Box::new(forward.into_transaction().map(|_| ())),
+            compensation: Box::new(compensation.into_transaction().map(|_| ())),
+        });
+        self
+    }
+
+    /// Run the saga from the start without recording the progress. `run`
+    /// commits a transaction, e.g. by the runner of the backend.
+    pub fn run<R>(&self, run: R) -> Result<(), Error<E>>
+    where
+        R: FnMut(&dyn Transaction<Ctx = Ctx, Item = (), Err = E>) -> Result<(), E>,
+    {
+        self.resume(MemoryLog::new(), run)
+    }
+
+    /// Run the saga from the progress recorded in the log, or from the start
+    /// if there is none, recording the progress after each step. A saga
+    /// already finished only returns its outcome again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use transaction::prelude::*;
+    /// use transaction_saga::{MemoryLog, Progress, Saga, SagaLog};
+    ///
+    /// # fn main() {
+    /// let saga = Saga::new("order-7")
+    ///     .step(
+    ///         with_ctx(|n: &mut i32| -> Result<(), ()> { *n += 1; Ok(()) }),
+    ///         with_ctx(|n: &mut i32| -> Result<(), ()> { *n -= 1; Ok(()) }),
+    ///     )
+    ///     .step(
+    ///         with_ctx(|n: &mut i32| -> Result<(), ()> { *n *= 10; Ok(()) }),
+    ///         with_ctx(|n: &mut i32| -> Result<(), ()> { *n /= 10; Ok(()) }),
+    ///     );
+    ///
+    /// // the process crashed after the first step
+    /// let mut log = MemoryLog::new();
+    /// log.save("order-7", Progress::Running { done: 1 }).unwrap();
+    /// let mut n = 1;
+    ///
+    /// saga.resume(&mut log, |tx| tx.run(&mut n)).unwrap();
+    /// assert_eq!(n, 10);
+    /// assert_eq!(log.load("order-7").unwrap(), Some(Progress::Completed));
+    /// # }
+    /// ```
+    pub fn resume<L, R>(&self, mut log: L, mut run: R) -> Result<(), Error<E>>
+    where
+        L: SagaLog,
+        R: FnMut(&dyn Transaction<Ctx = Ctx, Item = (), Err = E>) -> Result<(), E>,
+    {
+        match log.load(&self.id)?.unwrap_or(Progress::Running { done: 0 }) {
+            Progress::Running { done } => {
+                for (i, step) in self.steps.iter().enumerate().skip(done) {
+                    if let Err(e) = run(&*step.forward) {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(saga = self.id.as_str(), step = i, "step failed, compensating");
+                        #[cfg(feature = "log")]
+                        ::log::debug!("step {} of saga {} failed, compensating", i, self.id);
+                        log.save(&self.id, Progress::Compensating { failed: i, left: i })?;
+                        return self.compensate(&mut log, &mut run, i, i, Some(e));
+                    }
+                    log.save(&self.id, Progress::Running { done: i + 1 })?;
+                }
+                log.save(&self.id, Progress::Completed)?;
+                Ok(())
+            }
+            Progress::Compensating { failed, left } => self.compensate(&mut log, &mut run, failed, left, None),
+            Progress::Completed => Ok(()),
+            Progress::Compensated { failed } => Err(Error::Compensated {
+                step: failed,
+                error: None,
+            }),
+        }
+    }
+
+    // compensate the first `left` steps in reverse order
+    fn compensate<L, R>(&self, log: &mut L, run: &mut R, failed: usize, left: usize, error: Option<E>) -> Result<(), Error<E>>
+    where
+        L: SagaLog,
+        R: FnMut(&dyn Transaction<Ctx = Ctx, Item = (), Err = E>) -> Result<(), E>,
+    {
+        for i in (0..left).rev() {
+            run(&*self.steps[i].compensation).map_err(|error| Error::Compensation { step: i, error })?;
+            log.save(&self.id, Progress::Compensating { failed, left: i })?;
+        }
+        log.save(&self.id, Progress::Compensated { failed })?;
+        Err(Error::Compensated { step: failed, error })
+    }
+}
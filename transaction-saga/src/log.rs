@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::io;
+
+/// How far a saga has got, recorded in a `SagaLog`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Progress {
+    /// The first `done` steps are committed, and the others are to run
+    Running {
+        /// The number of the steps committed
+        done: usize,
+    },
+    /// The step `failed` failed, and the first `left` steps are still to be
+    /// compensated
+    Compensating {
+        /// The index of the step which failed
+        failed: usize,
+        /// The number of the steps still to be compensated
+        left: usize,
+    },
+    /// All the steps are committed
+    Completed,
+    /// The step `failed` failed, and all the steps before it are
+    /// compensated
+    Compensated {
+        /// The index of the step which failed
+        failed: usize,
+    },
+}
+
+/// The durable record of the progress of sagas, by which `Saga::resume`
+/// continues a saga after a crash. Implement it on the storage the steps
+/// commit to, or any other which survives the process.
+pub trait SagaLog {
+    /// Record the progress of the saga `id`
+    fn save(&mut self, id: &str, progress: Progress) -> io::Result<()>;
+
+    /// The progress of the saga `id` last saved, or `None` if it has never
+    /// run
+    fn load(&mut self, id: &str) -> io::Result<Option<Progress>>;
+}
+
+impl<L> SagaLog for &mut L
+where
+    L: ?Sized + SagaLog,
+{
+    fn save(&mut self, id: &str, progress: Progress) -> io::Result<()> {
+        (**self).save(id, progress)
+    }
+
+    fn load(&mut self, id: &str) -> io::Result<Option<Progress>> {
+        (**self).load(id)
+    }
+}
+
+/// A `SagaLog` in memory, which doesn't survive a crash, for tests
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLog {
+    sagas: HashMap<String, Progress>,
+}
+
+impl MemoryLog {
+    /// An empty log
+    pub fn new() -> Self {
+        MemoryLog::default()
+    }
+}
+
+impl SagaLog for MemoryLog {
+    fn save(&mut self, id: &str, progress: Progress) -> io::Result<()> {
+        self.sagas.insert(id.to_string(), progress);
+        Ok(())
+    }
+
+    fn load(&mut self, id: &str) -> io::Result<Option<Progress>> {
+        Ok(self.sagas.get(id).cloned())
+    }
+}
@@ -0,0 +1,211 @@
+use futures::future::BoxFuture;
+use sea_orm::{
+    ActiveModelBehavior, ActiveModelTrait, DatabaseTransaction, DbErr, DeleteResult, EntityTrait,
+    IntoActiveModel, Select,
+};
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::{Error, SeaOrmContext};
+
+type Model<A> = <<A as ActiveModelTrait>::Entity as EntityTrait>::Model;
+
+/// Lift an operation of SeaORM on the transaction into a leaf, e.g.
+/// `lift(|db| user::Entity::find_by_id(1).one(db).boxed())`.
+pub fn lift<F, T>(f: F) -> Lift<F>
+where
+    F: for<'c> Fn(&'c DatabaseTransaction) -> BoxFuture<'c, Result<T, DbErr>> + Send + Sync,
+    T: Send,
+{
+    Lift { f }
+}
+
+/// The result of `lift`
+#[derive(Debug)]
+#[must_use]
+pub struct Lift<F> {
+    f: F,
+}
+
+impl<F, T> AsyncTransaction for Lift<F>
+where
+    F: for<'c> Fn(&'c DatabaseTransaction) -> BoxFuture<'c, Result<T, DbErr>> + Send + Sync,
+    T: Send,
+{
+    type Ctx = SeaOrmContext;
+    type Item = T;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok((self.f)(ctx).await?) })
+    }
+}
+
+impl<F> Visit for Lift<F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("lift"));
+    }
+}
+
+/// Select the first model, e.g. `one(user::Entity::find_by_id(1))`.
+pub fn one<E: EntityTrait>(select: Select<E>) -> One<E> {
+    One { select }
+}
+
+/// The result of `one`
+#[derive(Debug)]
+#[must_use]
+pub struct One<E: EntityTrait> {
+    select: Select<E>,
+}
+
+impl<E: EntityTrait> AsyncTransaction for One<E> {
+    type Ctx = SeaOrmContext;
+    type Item = Option<E::Model>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(self.select.clone().one(&*ctx).await?) })
+    }
+}
+
+impl<E: EntityTrait> Visit for One<E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("one"));
+    }
+}
+
+/// Select all the models, e.g. `all(user::Entity::find())`.
+pub fn all<E: EntityTrait>(select: Select<E>) -> All<E> {
+    All { select }
+}
+
+/// The result of `all`
+#[derive(Debug)]
+#[must_use]
+pub struct All<E: EntityTrait> {
+    select: Select<E>,
+}
+
+impl<E: EntityTrait> AsyncTransaction for All<E> {
+    type Ctx = SeaOrmContext;
+    type Item = Vec<E::Model>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(self.select.clone().all(&*ctx).await?) })
+    }
+}
+
+impl<E: EntityTrait> Visit for All<E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("all"));
+    }
+}
+
+/// Insert the active model and return the inserted model.
+pub fn insert<A>(model: A) -> Insert<A>
+where
+    A: ActiveModelTrait + ActiveModelBehavior + Send + Sync,
+    Model<A>: IntoActiveModel<A>,
+{
+    Insert { model }
+}
+
+/// The result of `insert`
+#[derive(Debug)]
+#[must_use]
+pub struct Insert<A> {
+    model: A,
+}
+
+impl<A> AsyncTransaction for Insert<A>
+where
+    A: ActiveModelTrait + ActiveModelBehavior + Send + Sync,
+    Model<A>: IntoActiveModel<A>,
+{
+    type Ctx = SeaOrmContext;
+    type Item = Model<A>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(self.model.clone().insert(&*ctx).await?) })
+    }
+}
+
+impl<A> Visit for Insert<A> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("insert"));
+    }
+}
+
+/// Update the changed columns of the active model and return the updated
+/// model.
+pub fn update<A>(model: A) -> Update<A>
+where
+    A: ActiveModelTrait + ActiveModelBehavior + Send + Sync,
+    Model<A>: IntoActiveModel<A>,
+{
+    Update { model }
+}
+
+/// The result of `update`
+#[derive(Debug)]
+#[must_use]
+pub struct Update<A> {
+    model: A,
+}
+
+impl<A> AsyncTransaction for Update<A>
+where
+    A: ActiveModelTrait + ActiveModelBehavior + Send + Sync,
+    Model<A>: IntoActiveModel<A>,
+{
+    type Ctx = SeaOrmContext;
+    type Item = Model<A>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(self.model.clone().update(&*ctx).await?) })
+    }
+}
+
+impl<A> Visit for Update<A> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("update"));
+    }
+}
+
+/// Delete the row of the active model.
+pub fn delete<A>(model: A) -> Delete<A>
+where
+    A: ActiveModelTrait + ActiveModelBehavior + Send + Sync,
+{
+    Delete { model }
+}
+
+/// The result of `delete`
+#[derive(Debug)]
+#[must_use]
+pub struct Delete<A> {
+    model: A,
+}
+
+impl<A> AsyncTransaction for Delete<A>
+where
+    A: ActiveModelTrait + ActiveModelBehavior + Send + Sync,
+{
+    type Ctx = SeaOrmContext;
+    type Item = DeleteResult;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(self.model.clone().delete(&*ctx).await?) })
+    }
+}
+
+impl<A> Visit for Delete<A> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete"));
+    }
+}
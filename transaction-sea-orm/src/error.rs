@@ -0,0 +1,71 @@
+use std::error;
+use std::fmt;
+
+use sea_orm::{ConnAcquireErr, DbErr};
+use transaction::Retryable;
+
+pub use transaction_sqlx::ErrorKind;
+
+/// An error of SeaORM together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: DbErr,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of SeaORM
+    pub fn get_ref(&self) -> &DbErr {
+        &self.inner
+    }
+
+    /// Unwrap the error of SeaORM
+    pub fn into_inner(self) -> DbErr {
+        self.inner
+    }
+}
+
+// the errors of the database are those of sqlx, classified as
+// transaction-sqlx does
+fn classify(e: &DbErr) -> ErrorKind {
+    match *e {
+        DbErr::ConnectionAcquire(ConnAcquireErr::Timeout) => ErrorKind::PoolTimedOut,
+        #[cfg(any(feature = "postgres", feature = "mysql", feature = "sqlite"))]
+        DbErr::Conn(sea_orm::RuntimeErr::SqlxError(ref e))
+        | DbErr::Exec(sea_orm::RuntimeErr::SqlxError(ref e))
+        | DbErr::Query(sea_orm::RuntimeErr::SqlxError(ref e)) => transaction_sqlx::classify(e),
+        _ => ErrorKind::Other,
+    }
+}
+
+impl From<DbErr> for Error {
+    fn from(inner: DbErr) -> Self {
+        Error {
+            kind: classify(&inner),
+            inner,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        !matches!(self.kind, ErrorKind::StatementTimeout | ErrorKind::Other)
+    }
+}
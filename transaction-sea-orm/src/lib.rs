@@ -0,0 +1,409 @@
+//! Transaction backend for SeaORM
+//!
+//! Asynchronous transactions run in a `DatabaseTransaction` begun on the
+//! database connection. The [tokio runner](transaction_tokio::Runner)
+//! commits it when the transaction succeeds and rolls it back otherwise.
+//! `nested` runs a part of the transaction in a nested transaction of
+//! SeaORM, i.e. a savepoint, so that its failure can be recovered without
+//! poisoning the whole transaction.
+//!
+//! The entity operations of SeaORM are lifted into leaves by `one`, `all`,
+//! `insert`, `update` and `delete`, and any other operation by `lift`.
+//!
+//! The errors of the database are classified as transaction-sqlx does, so
+//! that serialization failures, deadlocks and lock timeouts are `Retryable`.
+//!
+//! # Examples
+//!
+//! ```
+//! mod user {
+//!     use std::convert::TryInto;
+//!
+//!     use sea_orm::entity::prelude::*;
+//!
+//!     #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+//!     #[sea_orm(table_name = "users")]
+//!     pub struct Model {
+//!         #[sea_orm(primary_key, auto_increment = false)]
+//!         pub name: String,
+//!     }
+//!
+//!     #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+//!     pub enum Relation {}
+//!
+//!     impl ActiveModelBehavior for ActiveModel {}
+//! }
+//!
+//! use sea_orm::{ActiveValue, ConnectionTrait, Database, EntityTrait};
+//! use transaction::async_tx::{self, AsyncTransaction, AsyncTransactionExt};
+//! use transaction_sea_orm::{all, insert, nested, Error};
+//!
+//! fn new_user(name: &str) -> user::ActiveModel {
+//!     user::ActiveModel { name: ActiveValue::Set(name.to_string()) }
+//! }
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), Error> {
+//!     let db = Database::connect("sqlite::memory:").await?;
+//!     db.execute_unprepared("CREATE TABLE users (name TEXT PRIMARY KEY)").await?;
+//!     let runner = transaction_sea_orm::runner(db);
+//!
+//!     // the duplicate is rolled back to the savepoint and the rest is committed
+//!     let tx = insert(new_user("alice"))
+//!         .and_then(|_| nested(insert(new_user("alice"))).map(|_| ()).or_else(|_| async_tx::ok(())))
+//!         .and_then(|()| insert(new_user("bob")))
+//!         .and_then(|_| all(user::Entity::find()));
+//!     let users = runner.run_async(tx).await?;
+//!     assert_eq!(users.len(), 2);
+//!     Ok(())
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+use sea_orm::{AccessMode, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbBackend, TransactionTrait};
+use transaction::async_tx::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use transaction::clock::Instant;
+use transaction::hooks::Outcome;
+use transaction::{visit_node, IsolationLevel, Node, TransactionMode, Visit, Visitor};
+use transaction_tokio::{AsyncPool, Runner, TestRunner};
+
+mod entity;
+mod error;
+
+pub use crate::entity::*;
+pub use crate::error::*;
+
+/// The context of the transactions: a transaction of SeaORM
+pub type SeaOrmContext = DatabaseTransaction;
+
+/// An `AsyncPool` beginning a `DatabaseTransaction` on the database
+/// connection, which is a pool of connections itself
+///
+/// The transactions run with a deadline, e.g. by
+/// `Runner::run_async_with`, have their statements limited to the time
+/// remaining when they begin: by `SET LOCAL statement_timeout` on
+/// PostgreSQL, and by `SET SESSION max_execution_time` on MySQL, which only
+/// limits the `SELECT`s. SQLite has no such limit, so the transactions can
+/// only give up by themselves, checking `transaction::remaining_time`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "postgres")]
+/// # mod example {
+/// use std::time::Duration;
+///
+/// use sea_orm::{ConnectionTrait, Database, DbBackend, Statement};
+/// use transaction::clock::Instant;
+/// use transaction::hooks::Outcome;
+/// use transaction_sea_orm::{Error, SeaOrmPool};
+/// use transaction_tokio::AsyncPool;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// pub async fn main() -> Result<(), Error> {
+///     let db = Database::connect("postgres://postgres@localhost/postgres").await?;
+///     let pool = SeaOrmPool::new(db);
+///
+///     let tx = pool.acquire_until(Some(Instant::now() + Duration::from_secs(5))).await?;
+///     let setting = Statement::from_string(
+///         DbBackend::Postgres,
+///         "SELECT setting FROM pg_settings WHERE name = 'statement_timeout'",
+///     );
+///     let row = tx.query_one(setting).await?.expect("the setting");
+///     pool.release(tx, Outcome::Committed).await?;
+///     let timeout: u64 = row.try_get::<String>("", "setting")?.parse().unwrap();
+///     assert!(timeout > 0 && timeout <= 5000);
+///     Ok(())
+/// }
+/// # }
+/// # #[cfg(feature = "postgres")]
+/// # fn main() -> Result<(), transaction_sea_orm::Error> { example::main() }
+/// # #[cfg(not(feature = "postgres"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct SeaOrmPool {
+    db: DatabaseConnection,
+    isolation: Option<sea_orm::IsolationLevel>,
+    access: Option<AccessMode>,
+    // whether the sessions of MySQL may have a timeout to reset
+    timed_out_sessions: Arc<AtomicBool>,
+}
+
+impl SeaOrmPool {
+    /// Use the database connection
+    pub fn new(db: DatabaseConnection) -> Self {
+        SeaOrmPool {
+            db,
+            isolation: None,
+            access: None,
+            timed_out_sessions: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Begin the transactions with the isolation level and the access mode,
+    /// which SeaORM issues in the syntax of the database. Deferrable
+    /// transactions are not supported by SeaORM and are ignored.
+    pub fn mode(self, mode: TransactionMode) -> Self {
+        let isolation = mode.isolation_level().map(|level| match level {
+            IsolationLevel::ReadUncommitted => sea_orm::IsolationLevel::ReadUncommitted,
+            IsolationLevel::ReadCommitted => sea_orm::IsolationLevel::ReadCommitted,
+            IsolationLevel::RepeatableRead => sea_orm::IsolationLevel::RepeatableRead,
+            IsolationLevel::Serializable => sea_orm::IsolationLevel::Serializable,
+        });
+        let access = mode.is_read_only().map(|read_only| {
+            if read_only {
+                AccessMode::ReadOnly
+            } else {
+                AccessMode::ReadWrite
+            }
+        });
+        SeaOrmPool {
+            isolation,
+            access,
+            ..self
+        }
+    }
+
+    /// The database connection
+    pub fn db(&self) -> &DatabaseConnection {
+        &self.db
+    }
+
+    // the statement limiting a transaction begun to the deadline. A timeout
+    // of 0 disables it, so at least 1ms is given.
+    fn limit_until(&self, deadline: Option<Instant>) -> Option<String> {
+        let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()).as_millis().max(1));
+        match (self.db.get_database_backend(), timeout) {
+            (DbBackend::Postgres, Some(timeout)) => Some(format!("SET LOCAL statement_timeout = {}", timeout)),
+            // the timeout of MySQL outlives the transaction on the session,
+            // so the sessions of the pool are reset once a timeout was set
+            (DbBackend::MySql, _) if timeout.is_some() || self.timed_out_sessions.load(Ordering::Relaxed) => {
+                self.timed_out_sessions.store(true, Ordering::Relaxed);
+                Some(format!(
+                    "SET SESSION max_execution_time = {}",
+                    timeout.map_or("DEFAULT".to_string(), |timeout| timeout.to_string())
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl AsyncPool for SeaOrmPool {
+    type Ctx = SeaOrmContext;
+    type Error = Error;
+
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        self.acquire_until(None)
+    }
+
+    fn acquire_until(&self, deadline: Option<Instant>) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        async move {
+            let tx = self.db.begin_with_config(self.isolation, self.access).await?;
+            if let Some(limit) = self.limit_until(deadline) {
+                tx.execute_unprepared(&limit).await?;
+            }
+            Ok(tx)
+        }.boxed()
+    }
+
+    fn release(&self, ctx: Self::Ctx, outcome: Outcome) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move {
+            match outcome {
+                Outcome::Committed => ctx.commit().await?,
+                Outcome::RolledBack => ctx.rollback().await?,
+            }
+            Ok(())
+        }.boxed()
+    }
+}
+
+/// A `Runner` of transactions on a database connection of SeaORM
+pub type SeaOrmRunner = Runner<SeaOrmPool>;
+
+/// Create a runner of transactions on the database connection
+///
+/// If a transaction panics, its `DatabaseTransaction` is rolled back and the
+/// panic is resumed.
+///
+/// # Examples
+///
+/// ```
+/// # mod user {
+/// #     use std::convert::TryInto;
+/// #
+/// #     use sea_orm::entity::prelude::*;
+/// #
+/// #     #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+/// #     #[sea_orm(table_name = "users")]
+/// #     pub struct Model {
+/// #         #[sea_orm(primary_key, auto_increment = false)]
+/// #         pub name: String,
+/// #     }
+/// #
+/// #     #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+/// #     pub enum Relation {}
+/// #
+/// #     impl ActiveModelBehavior for ActiveModel {}
+/// # }
+/// use std::panic::AssertUnwindSafe;
+///
+/// use futures::FutureExt;
+/// use sea_orm::{ActiveValue, ConnectionTrait, Database, EntityTrait};
+/// use transaction::async_tx::AsyncTransactionExt;
+/// use transaction_sea_orm::{all, insert, Error};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), Error> {
+///     let db = Database::connect("sqlite::memory:").await?;
+///     db.execute_unprepared("CREATE TABLE users (name TEXT PRIMARY KEY)").await?;
+///     let runner = transaction_sea_orm::runner(db);
+///
+///     let alice = user::ActiveModel { name: ActiveValue::Set("alice".to_string()) };
+///     let boom = insert(alice).map(|_| -> () { panic!("boom") });
+///     assert!(AssertUnwindSafe(runner.run_async(boom)).catch_unwind().await.is_err());
+///     assert!(runner.run_async(all(user::Entity::find())).await?.is_empty());
+///     Ok(())
+/// }
+/// ```
+pub fn runner(db: DatabaseConnection) -> SeaOrmRunner {
+    Runner::new(SeaOrmPool::new(db))
+}
+
+/// A `TestRunner` of transactions on a database connection of SeaORM,
+/// rolling back every transaction
+pub type SeaOrmTestRunner = TestRunner<SeaOrmPool>;
+
+/// Create a runner of transactions on the database connection rolling back
+/// every transaction, for tests
+pub fn test_runner(db: DatabaseConnection) -> SeaOrmTestRunner {
+    TestRunner::new(SeaOrmPool::new(db))
+}
+
+/// Builder of a `SeaOrmRunner` beginning the transactions with the isolation
+/// level and the access mode. See `SeaOrmPool::mode`.
+///
+/// # Examples
+///
+/// ```
+/// use sea_orm::Database;
+/// use transaction::IsolationLevel;
+/// use transaction_sea_orm::RunnerBuilder;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), sea_orm::DbErr> {
+///     let db = Database::connect("sqlite::memory:").await?;
+///     let runner = RunnerBuilder::new(db)
+///         .isolation(IsolationLevel::Serializable)
+///         .read_only()
+///         .build();
+///     # let _ = runner;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RunnerBuilder {
+    db: DatabaseConnection,
+    mode: TransactionMode,
+}
+
+impl RunnerBuilder {
+    /// Run the transactions on the database connection with the database
+    /// defaults
+    pub fn new(db: DatabaseConnection) -> Self {
+        RunnerBuilder {
+            db,
+            mode: TransactionMode::new(),
+        }
+    }
+
+    /// Set the isolation level
+    pub fn isolation(self, level: IsolationLevel) -> Self {
+        RunnerBuilder {
+            mode: self.mode.isolation(level),
+            ..self
+        }
+    }
+
+    /// Make the transactions read-only
+    pub fn read_only(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.read_only(),
+            ..self
+        }
+    }
+
+    /// Replace all the characteristics
+    pub fn mode(self, mode: TransactionMode) -> Self {
+        RunnerBuilder { mode, ..self }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> SeaOrmRunner {
+        Runner::new(SeaOrmPool::new(self.db).mode(self.mode))
+    }
+}
+
+/// Run the transaction in a nested transaction of SeaORM, i.e. a savepoint.
+/// When it fails, the changes it made are rolled back to the savepoint and
+/// the error is returned, so the enclosing transaction can recover with e.g.
+/// `or_else` and still commit.
+///
+/// The error of the savepoint statements themselves is returned instead of
+/// the error of the transaction.
+pub fn nested<A>(a: A) -> Nested<A::Tx>
+where
+    A: IntoAsyncTransaction<SeaOrmContext>,
+{
+    Nested { tx: a.into_async_transaction() }
+}
+
+/// The result of `nested`
+#[derive(Debug)]
+#[must_use]
+pub struct Nested<Tx> {
+    tx: Tx,
+}
+
+impl<Tx> AsyncTransaction for Nested<Tx>
+where
+    Tx: AsyncTransaction<Ctx = SeaOrmContext>,
+    Tx::Err: From<Error>,
+{
+    type Ctx = SeaOrmContext;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let mut savepoint = ctx.begin().await.map_err(Error::from)?;
+            match self.tx.run_async(&mut savepoint).await {
+                Ok(item) => {
+                    savepoint.commit().await.map_err(Error::from)?;
+                    Ok(item)
+                }
+                Err(e) => {
+                    savepoint.rollback().await.map_err(Error::from)?;
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Nested<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("nested"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,76 @@
+use std::error;
+use std::fmt;
+
+use sled::transaction::UnabortableTransactionError;
+use transaction::Retryable;
+
+/// The classification of the errors of sled transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The transaction conflicted with a concurrent one. The runners run it
+    /// again instead of returning it.
+    Conflict,
+    /// An error of the storage, e.g. I/O or corruption
+    Storage,
+}
+
+/// An error of sled together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: Option<sled::Error>,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of the storage, if it is not a conflict
+    pub fn get_ref(&self) -> Option<&sled::Error> {
+        self.inner.as_ref()
+    }
+}
+
+impl From<sled::Error> for Error {
+    fn from(inner: sled::Error) -> Self {
+        Error {
+            kind: ErrorKind::Storage,
+            inner: Some(inner),
+        }
+    }
+}
+
+impl From<UnabortableTransactionError> for Error {
+    fn from(e: UnabortableTransactionError) -> Self {
+        match e {
+            UnabortableTransactionError::Conflict => Error {
+                kind: ErrorKind::Conflict,
+                inner: None,
+            },
+            UnabortableTransactionError::Storage(e) => Error::from(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner {
+            Some(ref e) => fmt::Display::fmt(e, f),
+            None => f.write_str("transaction conflicted with a concurrent one"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.as_ref().and_then(|e| e.source())
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind == ErrorKind::Conflict
+    }
+}
@@ -0,0 +1,145 @@
+//! A transaction runner for sled
+//!
+//! Transactions run in a `SledContext` wrapping the transactional trees of
+//! sled. Like the closures given to `Tree::transaction`, a transaction which
+//! conflicts with a concurrent one is run again from the start, so it should
+//! not have side effects other than on the trees.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::convert::TryInto;
+//!
+//! use transaction::prelude::*;
+//! use transaction_sled::{get, insert, run};
+//!
+//! # fn main() -> Result<(), transaction_sled::Error> {
+//! let db = sled::Config::new().temporary(true).open()?;
+//! let tree = db.open_tree("counters")?;
+//!
+//! let incr = get("hits")
+//!     .map(|v| v.map_or(0, |v| u64::from_be_bytes(v.as_ref().try_into().unwrap())))
+//!     .and_then(|n| insert("hits", &(n + 1).to_be_bytes()).map(move |_| n + 1));
+//! assert_eq!(run(&tree, &incr)?, 1);
+//! assert_eq!(run(&tree, &incr)?, 2);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::slice;
+
+use sled::transaction::{
+    ConflictableTransactionError, TransactionError, TransactionalTree, Transactional,
+    UnabortableTransactionError,
+};
+use sled::Tree;
+use transaction::hooks::{self, Outcome};
+use transaction::Transaction;
+
+mod error;
+mod tree;
+
+pub use crate::error::*;
+pub use crate::tree::*;
+
+/// The context of the transactions: the transactional trees of sled.
+pub struct SledContext {
+    trees: Vec<TransactionalTree>,
+    conflicted: bool,
+}
+
+impl SledContext {
+    // never pub this function
+    fn new(trees: Vec<TransactionalTree>) -> Self {
+        SledContext {
+            trees,
+            conflicted: false,
+        }
+    }
+
+    /// The trees in the transaction, in the order given to the runner
+    pub fn trees(&self) -> &[TransactionalTree] {
+        &self.trees
+    }
+
+    // convert the result of sled, remembering the conflict so that the
+    // runner runs the transaction again even if the error is mapped
+    fn check<T>(&mut self, ret: Result<T, UnabortableTransactionError>) -> Result<T, Error> {
+        ret.map_err(|e| {
+            let e = Error::from(e);
+            if e.kind() == ErrorKind::Conflict {
+                self.conflicted = true;
+            }
+            e
+        })
+    }
+}
+
+impl fmt::Debug for SledContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SledContext")
+            .field("trees", &self.trees.len())
+            .field("conflicted", &self.conflicted)
+            .finish()
+    }
+}
+
+/// run the given function inside a transaction on the tree. Pass a reference
+/// to run the same transaction again.
+pub fn run<T, E, Tx>(tree: &Tree, tx: Tx) -> Result<T, E>
+where
+    E: From<Error>,
+    Tx: Transaction<Ctx = SledContext, Item = T, Err = E>,
+{
+    run_trees(slice::from_ref(tree), tx)
+}
+
+/// run the given function inside a transaction spanning the trees. The
+/// leaves like `get` use the first tree, and `with_trees` receives them all.
+///
+/// If the transaction panics, its writes are dropped without being applied,
+/// the locks of sled are released, and the panic is resumed.
+///
+/// # Examples
+///
+/// ```
+/// use std::panic::{self, AssertUnwindSafe};
+///
+/// use transaction::prelude::*;
+/// use transaction_sled::{get, insert, run_trees, Error};
+///
+/// # fn main() -> Result<(), Error> {
+/// let db = sled::Config::new().temporary(true).open()?;
+/// let trees = [db.open_tree("users")?, db.open_tree("emails")?];
+///
+/// let boom = insert("alice", "admin").map(|_| -> () { panic!("boom") });
+/// assert!(panic::catch_unwind(AssertUnwindSafe(|| run_trees::<_, Error, _>(&trees, boom))).is_err());
+/// assert_eq!(run_trees::<_, Error, _>(&trees, get("alice"))?, None);
+/// # Ok(())
+/// # }
+/// ```
+pub fn run_trees<T, E, Tx>(trees: &[Tree], tx: Tx) -> Result<T, E>
+where
+    E: From<Error>,
+    Tx: Transaction<Ctx = SledContext, Item = T, Err = E>,
+{
+    hooks::instrument("sled", tx.label(), Outcome::of, || {
+        let ret = trees.transaction(|views| {
+            let mut ctx = SledContext::new(views.clone());
+            match tx.run(&mut ctx) {
+                Ok(t) => Ok(t),
+                Err(_) if ctx.conflicted => {
+                    hooks::retry(tx.label());
+                    Err(ConflictableTransactionError::Conflict)
+                }
+                Err(e) => Err(ConflictableTransactionError::Abort(e)),
+            }
+        });
+        match ret {
+            Ok(t) => Ok(t),
+            Err(TransactionError::Abort(e)) => Err(e),
+            Err(TransactionError::Storage(e)) => Err(Error::from(e).into()),
+        }
+    })
+}
@@ -0,0 +1,142 @@
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
+use sled::IVec;
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+use crate::{Error, SledContext};
+
+/// Get the value of the key in the first tree.
+pub fn get<K>(key: K) -> Get
+where
+    K: Into<IVec>,
+{
+    Get { key: key.into() }
+}
+
+/// The result of `get`
+#[derive(Debug)]
+#[must_use]
+pub struct Get {
+    key: IVec,
+}
+
+impl Transaction for Get {
+    type Ctx = SledContext;
+    type Item = Option<IVec>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut SledContext) -> Result<Self::Item, Self::Err> {
+        let ret = ctx.trees()[0].get(&self.key);
+        ctx.check(ret)
+    }
+}
+
+impl Visit for Get {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get"));
+    }
+}
+
+/// Set the value of the key in the first tree, and return the previous one.
+pub fn insert<K, V>(key: K, value: V) -> Insert
+where
+    K: Into<IVec>,
+    V: Into<IVec>,
+{
+    Insert {
+        key: key.into(),
+        value: value.into(),
+    }
+}
+
+/// The result of `insert`
+#[derive(Debug)]
+#[must_use]
+pub struct Insert {
+    key: IVec,
+    value: IVec,
+}
+
+impl Transaction for Insert {
+    type Ctx = SledContext;
+    type Item = Option<IVec>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut SledContext) -> Result<Self::Item, Self::Err> {
+        let ret = ctx.trees()[0].insert(&self.key, self.value.clone());
+        ctx.check(ret)
+    }
+}
+
+impl Visit for Insert {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("insert"));
+    }
+}
+
+/// Remove the key from the first tree, and return its value.
+pub fn remove<K>(key: K) -> Remove
+where
+    K: Into<IVec>,
+{
+    Remove { key: key.into() }
+}
+
+/// The result of `remove`
+#[derive(Debug)]
+#[must_use]
+pub struct Remove {
+    key: IVec,
+}
+
+impl Transaction for Remove {
+    type Ctx = SledContext;
+    type Item = Option<IVec>;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut SledContext) -> Result<Self::Item, Self::Err> {
+        let ret = ctx.trees()[0].remove(&self.key);
+        ctx.check(ret)
+    }
+}
+
+impl Visit for Remove {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("remove"));
+    }
+}
+
+/// Receive the trees of the executing transaction, in the order given to
+/// `run_trees`, and perform computation.
+pub fn with_trees<F, T>(f: F) -> WithTrees<F>
+where
+    F: Fn(&[TransactionalTree]) -> Result<T, UnabortableTransactionError>,
+{
+    WithTrees { f }
+}
+
+/// The result of `with_trees`
+#[derive(Debug)]
+#[must_use]
+pub struct WithTrees<F> {
+    f: F,
+}
+
+impl<F, T> Transaction for WithTrees<F>
+where
+    F: Fn(&[TransactionalTree]) -> Result<T, UnabortableTransactionError>,
+{
+    type Ctx = SledContext;
+    type Item = T;
+    type Err = Error;
+
+    fn run(&self, ctx: &mut SledContext) -> Result<Self::Item, Self::Err> {
+        let ret = (self.f)(ctx.trees());
+        ctx.check(ret)
+    }
+}
+
+impl<F> Visit for WithTrees<F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_trees"));
+    }
+}
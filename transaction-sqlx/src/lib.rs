@@ -0,0 +1,514 @@
+//! Transaction backend for sqlx
+//!
+//! Asynchronous transactions run in a `sqlx::Transaction` begun on a
+//! connection of the pool. The [tokio runner](transaction_tokio::Runner)
+//! commits it when the transaction succeeds and rolls it back otherwise.
+//! `nested` runs a part of the transaction in a savepoint so that its failure
+//! can be recovered without poisoning the whole transaction.
+//!
+//! The errors of sqlx are classified by their SQLSTATE (and by the error
+//! numbers of MySQL and the result codes of SQLite when the features are
+//! enabled) so that serialization failures, deadlocks and lock timeouts are
+//! `Retryable`.
+//!
+//! # Examples
+//!
+//! ```
+//! use futures::future::FutureExt;
+//! use sqlx::sqlite::{Sqlite, SqlitePoolOptions};
+//! use transaction::async_tx::{self, AsyncTransaction, AsyncTransactionExt};
+//! use transaction_sqlx::{nested, Error, SqlxContext};
+//!
+//! fn insert(name: &'static str)
+//!     -> impl AsyncTransaction<Ctx = SqlxContext<Sqlite>, Item = (), Err = Error>
+//! {
+//!     async_tx::with_ctx(move |tx: &mut SqlxContext<Sqlite>| {
+//!         async move {
+//!             sqlx::query("INSERT INTO users (name) VALUES (?)")
+//!                 .bind(name)
+//!                 .execute(&mut **tx)
+//!                 .await?;
+//!             Ok(())
+//!         }.boxed()
+//!     })
+//! }
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), Error> {
+//!     let pool = SqlitePoolOptions::new()
+//!         .max_connections(1)
+//!         .connect("sqlite::memory:")
+//!         .await?;
+//!     sqlx::query("CREATE TABLE users (name TEXT UNIQUE)")
+//!         .execute(&pool)
+//!         .await?;
+//!     let runner = transaction_sqlx::runner(pool);
+//!
+//!     // the duplicate is rolled back to the savepoint and the rest is committed
+//!     let tx = insert("alice")
+//!         .and_then(|()| nested(insert("alice")).or_else(|_| async_tx::ok(())))
+//!         .and_then(|()| insert("bob"));
+//!     runner.run_async(tx).await?;
+//!
+//!     let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+//!         .fetch_one(runner.pool().pool())
+//!         .await?;
+//!     assert_eq!(count, 2);
+//!     Ok(())
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use futures::future::{BoxFuture, FutureExt};
+use sqlx::error::DatabaseError;
+use sqlx::{Database, Executor, Pool};
+use transaction::async_tx::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use transaction::clock::Instant;
+use transaction::hooks::Outcome;
+use transaction::{visit_node, IsolationLevel, Node, Retryable, TransactionMode, Visit, Visitor};
+use transaction_tokio::{AsyncPool, Runner, TestRunner};
+
+/// The context of the transactions: a sqlx transaction on a connection of
+/// the pool
+pub type SqlxContext<DB> = sqlx::Transaction<'static, DB>;
+
+/// An `AsyncPool` beginning a `sqlx::Transaction` on the connections of a
+/// sqlx pool
+///
+/// The transactions run with a deadline, e.g. by
+/// `Runner::run_async_with`, have their statements limited to the time
+/// remaining when they begin: by `SET LOCAL statement_timeout` on
+/// PostgreSQL, and by `SET SESSION max_execution_time` on MySQL, which only
+/// limits the `SELECT`s. SQLite has no such limit, so the transactions can
+/// only give up by themselves, checking `transaction::remaining_time`.
+#[derive(Debug)]
+pub struct SqlxPool<DB: Database> {
+    pool: Pool<DB>,
+    begin: Option<String>,
+    // whether the sessions of MySQL may have a timeout to reset
+    timed_out_sessions: AtomicBool,
+}
+
+impl<DB: Database> SqlxPool<DB> {
+    /// Use the connections of the pool
+    pub fn new(pool: Pool<DB>) -> Self {
+        SqlxPool {
+            pool,
+            begin: None,
+            timed_out_sessions: AtomicBool::new(false),
+        }
+    }
+
+    /// Begin the transactions with the characteristics. They are issued with
+    /// `BEGIN` on PostgreSQL and with `SET TRANSACTION` before
+    /// `START TRANSACTION` on MySQL. SQLite has no characteristics to set, as
+    /// its transactions are always serializable.
+    pub fn mode(self, mode: TransactionMode) -> Self {
+        let begin = mode.characteristics().and_then(|characteristics| match DB::NAME {
+            "PostgreSQL" => Some(format!("BEGIN {}", characteristics)),
+            "MySQL" => Some(format!("SET TRANSACTION {}; START TRANSACTION", characteristics)),
+            _ => None,
+        });
+        SqlxPool { begin, ..self }
+    }
+
+    /// The underlying pool
+    pub fn pool(&self) -> &Pool<DB> {
+        &self.pool
+    }
+
+    // the statements beginning a transaction to be done by the deadline. A
+    // timeout of 0 disables it, so at least 1ms is given.
+    fn begin_until(&self, deadline: Option<Instant>) -> Option<String> {
+        let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()).as_millis().max(1));
+        match (DB::NAME, timeout) {
+            ("PostgreSQL", Some(timeout)) => Some(format!(
+                "{}; SET LOCAL statement_timeout = {}",
+                self.begin.as_deref().unwrap_or("BEGIN"),
+                timeout
+            )),
+            // the timeout of MySQL outlives the transaction on the session,
+            // so the sessions of the pool are reset once a timeout was set
+            ("MySQL", _) if timeout.is_some() || self.timed_out_sessions.load(Ordering::Relaxed) => {
+                self.timed_out_sessions.store(true, Ordering::Relaxed);
+                Some(format!(
+                    "SET SESSION max_execution_time = {}; {}",
+                    timeout.map_or("DEFAULT".to_string(), |timeout| timeout.to_string()),
+                    self.begin.as_deref().unwrap_or("START TRANSACTION")
+                ))
+            }
+            _ => self.begin.clone(),
+        }
+    }
+}
+
+impl<DB: Database> AsyncPool for SqlxPool<DB> {
+    type Ctx = SqlxContext<DB>;
+    type Error = Error;
+
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        self.acquire_until(None)
+    }
+
+    fn acquire_until(&self, deadline: Option<Instant>) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        async move {
+            let tx = match self.begin_until(deadline) {
+                Some(begin) => self.pool.begin_with(begin).await?,
+                None => self.pool.begin().await?,
+            };
+            Ok(tx)
+        }.boxed()
+    }
+
+    fn release(&self, ctx: Self::Ctx, outcome: Outcome) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move {
+            match outcome {
+                Outcome::Committed => ctx.commit().await?,
+                Outcome::RolledBack => ctx.rollback().await?,
+            }
+            Ok(())
+        }.boxed()
+    }
+}
+
+/// A `Runner` of transactions on a sqlx pool
+pub type SqlxRunner<DB> = Runner<SqlxPool<DB>>;
+
+/// A `Runner` of transactions on a PostgreSQL pool
+#[cfg(feature = "postgres")]
+pub type PgRunner = SqlxRunner<sqlx::Postgres>;
+
+/// A `Runner` of transactions on a MySQL pool
+#[cfg(feature = "mysql")]
+pub type MySqlRunner = SqlxRunner<sqlx::MySql>;
+
+/// A `Runner` of transactions on a SQLite pool
+#[cfg(feature = "sqlite")]
+pub type SqliteRunner = SqlxRunner<sqlx::Sqlite>;
+
+/// Create a runner of transactions on the pool
+///
+/// If a transaction panics, its `sqlx::Transaction` is rolled back and the
+/// panic is resumed.
+///
+/// # Examples
+///
+/// ```
+/// use std::panic::AssertUnwindSafe;
+///
+/// use futures::future::FutureExt;
+/// use sqlx::sqlite::SqlitePoolOptions;
+/// use transaction::async_tx::{self, AsyncTransactionExt};
+/// use transaction_sqlx::{Error, SqlxContext};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), Error> {
+///     let pool = SqlitePoolOptions::new()
+///         .max_connections(1)
+///         .connect("sqlite::memory:")
+///         .await?;
+///     sqlx::query("CREATE TABLE users (name TEXT)").execute(&pool).await?;
+///     let runner = transaction_sqlx::runner(pool);
+///
+///     let insert = async_tx::with_ctx(|tx: &mut SqlxContext<sqlx::Sqlite>| {
+///         async move {
+///             sqlx::query("INSERT INTO users (name) VALUES ('alice')").execute(&mut **tx).await?;
+///             Ok::<_, Error>(())
+///         }.boxed()
+///     });
+///     let boom = insert.map(|()| -> () { panic!("boom") });
+///     assert!(AssertUnwindSafe(runner.run_async(boom)).catch_unwind().await.is_err());
+///
+///     let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+///         .fetch_one(runner.pool().pool())
+///         .await?;
+///     assert_eq!(count, 0);
+///     Ok(())
+/// }
+/// ```
+pub fn runner<DB: Database>(pool: Pool<DB>) -> SqlxRunner<DB> {
+    Runner::new(SqlxPool::new(pool))
+}
+
+/// A `TestRunner` of transactions on a sqlx pool, rolling back every
+/// transaction
+pub type SqlxTestRunner<DB> = TestRunner<SqlxPool<DB>>;
+
+/// Create a runner of transactions on the pool rolling back every
+/// transaction, for tests
+pub fn test_runner<DB: Database>(pool: Pool<DB>) -> SqlxTestRunner<DB> {
+    TestRunner::new(SqlxPool::new(pool))
+}
+
+/// Builder of a `SqlxRunner` issuing the isolation level and the access mode
+/// of the transactions when beginning them. See `SqlxPool::mode` for how
+/// they are issued.
+///
+/// The pools are shared by their clones, so runners with different
+/// characteristics can run the same transaction on the same pool.
+///
+/// # Examples
+///
+/// ```
+/// use sqlx::sqlite::SqlitePool;
+/// use transaction::IsolationLevel;
+/// use transaction_sqlx::RunnerBuilder;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), sqlx::Error> {
+///     let pool = SqlitePool::connect("sqlite::memory:").await?;
+///     let runner = RunnerBuilder::new(pool.clone())
+///         .isolation(IsolationLevel::Serializable)
+///         .read_only()
+///         .build();
+///     # let _ = runner;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RunnerBuilder<DB: Database> {
+    pool: Pool<DB>,
+    mode: TransactionMode,
+}
+
+impl<DB: Database> RunnerBuilder<DB> {
+    /// Run the transactions on the pool with the database defaults
+    pub fn new(pool: Pool<DB>) -> Self {
+        RunnerBuilder {
+            pool,
+            mode: TransactionMode::new(),
+        }
+    }
+
+    /// Set the isolation level
+    pub fn isolation(self, level: IsolationLevel) -> Self {
+        RunnerBuilder {
+            mode: self.mode.isolation(level),
+            ..self
+        }
+    }
+
+    /// Make the transactions read-only
+    pub fn read_only(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.read_only(),
+            ..self
+        }
+    }
+
+    /// Make the transactions deferrable (PostgreSQL only)
+    pub fn deferrable(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.deferrable(),
+            ..self
+        }
+    }
+
+    /// Replace all the characteristics
+    pub fn mode(self, mode: TransactionMode) -> Self {
+        RunnerBuilder { mode, ..self }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> SqlxRunner<DB> {
+        Runner::new(SqlxPool::new(self.pool).mode(self.mode))
+    }
+}
+
+// savepoint names are unique so that the nested savepoints don't shadow each
+// other (MySQL drops the older savepoint of the same name)
+static SAVEPOINTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Run the transaction in a savepoint. When it fails, the changes it made are
+/// rolled back to the savepoint and the error is returned, so the enclosing
+/// transaction can recover with e.g. `or_else` and still commit.
+///
+/// The error of the savepoint statements themselves is returned instead of
+/// the error of the transaction.
+pub fn nested<DB, A>(a: A) -> Nested<A::Tx>
+where
+    DB: Database,
+    A: IntoAsyncTransaction<SqlxContext<DB>>,
+{
+    Nested { tx: a.into_async_transaction() }
+}
+
+/// The result of `nested`
+#[derive(Debug)]
+#[must_use]
+pub struct Nested<Tx> {
+    tx: Tx,
+}
+
+async fn execute<DB>(ctx: &mut SqlxContext<DB>, sql: String) -> Result<(), Error>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    (&mut **ctx).execute(sql.as_str()).await?;
+    Ok(())
+}
+
+impl<DB, Tx> AsyncTransaction for Nested<Tx>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    Tx: AsyncTransaction<Ctx = SqlxContext<DB>>,
+    Tx::Err: From<Error>,
+{
+    type Ctx = SqlxContext<DB>;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let name = format!("transaction_nested_{}", SAVEPOINTS.fetch_add(1, Ordering::Relaxed));
+            execute(ctx, format!("SAVEPOINT {}", name)).await?;
+            match self.tx.run_async(ctx).await {
+                Ok(item) => {
+                    execute(ctx, format!("RELEASE SAVEPOINT {}", name)).await?;
+                    Ok(item)
+                }
+                Err(e) => {
+                    execute(ctx, format!("ROLLBACK TO SAVEPOINT {}", name)).await?;
+                    execute(ctx, format!("RELEASE SAVEPOINT {}", name)).await?;
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Nested<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("nested"), |v| self.tx.accept(v));
+    }
+}
+
+/// The classification of the errors of sqlx
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The transaction conflicted with a concurrent one (SQLSTATE 40001)
+    SerializationFailure,
+    /// The transaction was chosen as a deadlock victim
+    Deadlock,
+    /// A lock could not be acquired in time
+    LockTimeout,
+    /// The statement was cancelled by the timeout of the deadline of the run
+    /// (SQLSTATE 57014, or MySQL error 3024)
+    StatementTimeout,
+    /// No connection was available in the pool in time
+    PoolTimedOut,
+    /// Any other error
+    Other,
+}
+
+/// An error of sqlx together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: sqlx::Error,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of sqlx
+    pub fn get_ref(&self) -> &sqlx::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of sqlx
+    pub fn into_inner(self) -> sqlx::Error {
+        self.inner
+    }
+}
+
+/// Classify the error of sqlx, as `Error` does. Backends built on sqlx, e.g.
+/// ORMs wrapping its errors, can share the classification with this.
+pub fn classify(e: &sqlx::Error) -> ErrorKind {
+    match *e {
+        sqlx::Error::PoolTimedOut => ErrorKind::PoolTimedOut,
+        sqlx::Error::Database(ref e) => classify_database(&**e),
+        _ => ErrorKind::Other,
+    }
+}
+
+fn classify_database(e: &dyn DatabaseError) -> ErrorKind {
+    #[cfg(feature = "mysql")]
+    {
+        // MySQL reports deadlocks as 40001, and lock wait timeouts and
+        // statement timeouts as HY000
+        if let Some(e) = e.try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>() {
+            match e.number() {
+                1213 => return ErrorKind::Deadlock,
+                1205 => return ErrorKind::LockTimeout,
+                3024 => return ErrorKind::StatementTimeout,
+                _ => (),
+            }
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    {
+        // SQLite reports the extended result codes instead of SQLSTATE
+        if e.try_downcast_ref::<sqlx::sqlite::SqliteError>().is_some() {
+            let code = e.code().and_then(|code| code.parse::<i32>().ok()).unwrap_or(0);
+            return match code {
+                // SQLITE_BUSY_SNAPSHOT
+                517 => ErrorKind::SerializationFailure,
+                // SQLITE_BUSY, SQLITE_LOCKED and their extended codes
+                _ if code & 0xff == 5 || code & 0xff == 6 => ErrorKind::LockTimeout,
+                _ => ErrorKind::Other,
+            };
+        }
+    }
+    match e.code().as_deref() {
+        Some("40001") => ErrorKind::SerializationFailure,
+        Some("40P01") => ErrorKind::Deadlock,
+        Some("55P03") => ErrorKind::LockTimeout,
+        Some("57014") => ErrorKind::StatementTimeout,
+        _ => ErrorKind::Other,
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(inner: sqlx::Error) -> Self {
+        Error {
+            kind: classify(&inner),
+            inner,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+// the deadline of a transaction timed out is not any further when it runs
+// again
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        !matches!(self.kind, ErrorKind::StatementTimeout | ErrorKind::Other)
+    }
+}
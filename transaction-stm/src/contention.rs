@@ -0,0 +1,40 @@
+use std::error::Error;
+use std::fmt;
+use std::hint;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// delays up to this are spun away rather than slept, since sleeping takes at
+// least a scheduler tick
+const SPIN_LIMIT: Duration = Duration::from_micros(50);
+
+/// The error of `run_with` giving up on a transaction which kept conflicting
+/// with concurrent ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Contended {
+    /// How many attempts of the transaction conflicted
+    pub conflicts: u64,
+}
+
+impl fmt::Display for Contended {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the transaction gave up after {} conflicts", self.conflicts)
+    }
+}
+
+impl Error for Contended {}
+
+// wait before running a conflicting transaction again: yield the thread for
+// no delay, spin for short ones and sleep for the others
+pub(crate) fn wait(delay: Duration) {
+    if delay == Duration::from_secs(0) {
+        thread::yield_now();
+    } else if delay <= SPIN_LIMIT {
+        let start = Instant::now();
+        while start.elapsed() < delay {
+            hint::spin_loop();
+        }
+    } else {
+        thread::sleep(delay);
+    }
+}
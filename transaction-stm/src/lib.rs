@@ -6,7 +6,7 @@
 //! extern crate transaction;
 //! extern crate transaction_stm;
 //!
-//! use transaction::{Transaction, with_ctx};
+//! use transaction::{TransactionExt, with_ctx};
 //! use transaction_stm::run;
 //!
 //! fn main() {
@@ -41,19 +41,262 @@
 
 extern crate stm;
 extern crate transaction;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "log")]
+extern crate log;
 
-use transaction::Transaction;
+use transaction::{visit_leaf, IntoTransaction, Node, RetryPolicy, Transaction, Visit, Visitor};
+use transaction::hooks::{self, Outcome};
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
 use stm::Transaction as Stm;
+use stm::StmError;
+
+mod contention;
+#[cfg(feature = "model")]
+pub mod model;
+mod or_retry;
+mod par;
+mod priority;
+mod retry;
+mod stats;
+mod tvar;
+
+pub use contention::Contended;
+pub use or_retry::*;
+pub use par::par_run;
+pub use priority::*;
+pub use retry::*;
+pub use stats::Stats;
+pub use tvar::*;
 
 
 /// Run the `stm` transaction
+///
+/// # Panics
+///
+/// If the transaction panics, its writes are dropped without being
+/// committed, the hooks are notified of the rollback and the panic is
+/// resumed. The thread can run transactions again afterwards.
+///
+/// ```
+/// extern crate stm;
+/// extern crate transaction;
+/// extern crate transaction_stm;
+///
+/// use std::panic::{self, AssertUnwindSafe};
+/// use stm::TVar;
+/// use transaction::TransactionExt;
+/// use transaction_stm::{modify, read, run, write};
+///
+/// # fn main() {
+/// let x = TVar::new(0);
+/// let boom = write(&x, 1).map(|()| -> i32 { panic!("boom") });
+/// assert!(panic::catch_unwind(AssertUnwindSafe(|| run(&boom))).is_err());
+/// assert_eq!(x.read_atomic(), 0);
+/// run(&modify(&x, |xv| xv + 1));
+/// assert_eq!(run(&read(&x)), 1);
+/// # }
+/// ```
 pub fn run<T, Tx>(tx: &Tx) -> T
 where
     Tx: Transaction<Ctx = Stm, Item = T, Err = stm::StmError>,
 {
-    Stm::with(|stm| tx.run(stm))
+    run_stats(tx).0
+}
+
+/// Run the `stm` transaction like `run`, and return the statistics of the
+/// run together with its result, e.g. to find contended transactions. Every
+/// attempt but the first is also recorded by `metrics::record_retry`.
+///
+/// The variables are counted only when they are accessed by the leaves
+/// `read`, `write` and `modify`, not by `stm::Transaction` directly.
+///
+/// # Examples
+///
+/// ```
+/// extern crate stm;
+/// extern crate transaction;
+/// extern crate transaction_stm;
+///
+/// use stm::TVar;
+/// use transaction::TransactionExt;
+/// use transaction_stm::{modify, read, run_stats, write};
+///
+/// # fn main() {
+/// let x = TVar::new(1);
+/// let y = TVar::new(0);
+/// let (x2, y2) = (x.clone(), y.clone());
+/// let swap = read(&x)
+///     .join(read(&y))
+///     .and_then(move |(xv, yv)| write(&x2, yv).join(write(&y2, xv)))
+///     .and_then(|_| modify(&x, |xv| xv + 1));
+/// let ((), stats) = run_stats(&swap);
+/// assert_eq!(stats.attempts, 1);
+/// assert_eq!((stats.reads, stats.writes), (2, 2));
+/// assert_eq!((x.read_atomic(), y.read_atomic()), (1, 1));
+/// # }
+/// ```
+pub fn run_stats<T, Tx>(tx: &Tx) -> (T, Stats)
+where
+    Tx: Transaction<Ctx = Stm, Item = T, Err = stm::StmError>,
+{
+    match execute(tx, None) {
+        (Ok(t), stats) => (t, stats),
+        (Err(_), _) => unreachable!("gave up without a policy"),
+    }
 }
 
+/// Run the `stm` transaction like `run`, but wait before running it again
+/// after a conflict with a concurrent transaction as the policy says, and
+/// give up with `Contended` when the policy does. The transactions with a
+/// priority by `prioritized` then wait for those of higher priorities too. This keeps the threads
+/// from livelocking under high contention, where `run` reruns the
+/// conflicting transactions immediately and forever.
+///
+/// A delay of zero yields the thread, delays up to 50 microseconds are spun
+/// and longer ones are slept. The attempts which called `retry` wait for the
+/// variables to change, not for the policy, and are not conflicts.
+///
+/// # Examples
+///
+/// ```
+/// extern crate stm;
+/// extern crate transaction;
+/// extern crate transaction_stm;
+///
+/// use std::time::Duration;
+/// use stm::TVar;
+/// use transaction::Backoff;
+/// use transaction_stm::{modify, run_with};
+///
+/// # fn main() {
+/// let x = TVar::new(0);
+/// let policy = Backoff::exponential(Duration::from_micros(10))
+///     .max_retries(8)
+///     .max_delay(Duration::from_millis(1));
+/// run_with(&modify(&x, |xv| xv + 1), &policy).unwrap();
+/// assert_eq!(x.read_atomic(), 1);
+/// # }
+/// ```
+pub fn run_with<T, Tx, P>(tx: &Tx, policy: P) -> Result<T, Contended>
+where
+    Tx: Transaction<Ctx = Stm, Item = T, Err = stm::StmError>,
+    P: RetryPolicy,
+{
+    execute(tx, Some(&policy)).0
+}
+
+// run the transaction, giving up after the conflicts only with a policy
+fn execute<T, Tx>(tx: &Tx, policy: Option<&dyn RetryPolicy>) -> (Result<T, Contended>, Stats)
+where
+    Tx: Transaction<Ctx = Stm, Item = T, Err = stm::StmError>,
+{
+    hooks::instrument("stm", tx.label(), |(ret, _)| Outcome::of(ret), || {
+        let start = Instant::now();
+        priority::begin();
+        let attempts = Cell::new(0);
+        let blocked = Cell::new(0);
+        let conflicts = Cell::new(0);
+        // whether the last attempt called `retry` rather than conflicted
+        let waited = Cell::new(false);
+        let ret = Stm::with(|stm| {
+            if attempts.get() != 0 {
+                hooks::retry(tx.label());
+                if !waited.get() {
+                    conflicts.set(conflicts.get() + 1);
+                    if let Some(policy) = policy {
+                        match policy.next_delay(conflicts.get() as usize - 1) {
+                            Some(delay) => contention::wait(delay),
+                            // the log is empty, so this commits nothing
+                            None => return Ok(Err(Contended { conflicts: conflicts.get() })),
+                        }
+                    }
+                    priority::contend();
+                }
+            }
+            attempts.set(attempts.get() + 1);
+            #[cfg(feature = "model")]
+            model::yield_point();
+            stats::begin_attempt();
+            let ret = match panic::catch_unwind(AssertUnwindSafe(|| tx.run(stm))) {
+                Ok(ret) => ret,
+                Err(e) => {
+                    // `stm` drops the log of the attempt, so nothing is
+                    // committed, but the accesses recorded by the attempt stay
+                    stats::end();
+                    priority::end();
+                    panic::resume_unwind(e);
+                }
+            };
+            waited.set(false);
+            match ret {
+                Err(StmError::Retry) => {
+                    blocked.set(blocked.get() + 1);
+                    waited.set(true);
+                    priority::withdraw();
+                }
+                // commits right after
+                #[cfg(feature = "model")]
+                Ok(_) => model::yield_point(),
+                _ => {}
+            }
+            ret.map(Ok)
+        });
+        let (reads, writes) = stats::end();
+        priority::end();
+        let stats = Stats {
+            attempts: attempts.get(),
+            blocked: blocked.get(),
+            duration: start.elapsed(),
+            reads,
+            writes,
+        };
+        match ret {
+            Ok(_) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(attempts = stats.attempts, blocked = stats.blocked, "succeed");
+                #[cfg(feature = "log")]
+                log::debug!("transaction {:?} succeeds after {} attempts", tx.label(), stats.attempts);
+            }
+            Err(ref _e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(attempts = stats.attempts, conflicts = _e.conflicts, "give up");
+                #[cfg(feature = "log")]
+                log::debug!("give up transaction {:?} after {} conflicts", tx.label(), _e.conflicts);
+            }
+        }
+        (ret, stats)
+    })
+}
+
+/// The combinators of the transactions on `stm` in addition to those of
+/// `Transaction`
+pub trait StmTransaction: Transaction<Ctx = Stm, Err = StmError> {
+    /// Run the alternative instead when this transaction calls `retry`. See
+    /// `or_retry`.
+    fn or_retry<B>(self, b: B) -> OrRetry<Self, B::Tx>
+    where
+        B: IntoTransaction<Stm, Item = Self::Item, Err = StmError>,
+        Self: Sized,
+    {
+        or_retry(self, b)
+    }
+
+    /// Give the transaction a priority hint for the contention manager. See
+    /// `prioritized`.
+    fn priority(self, priority: u32) -> Prioritized<Self>
+    where
+        Self: Sized,
+    {
+        prioritized(priority, self)
+    }
+}
+
+impl<Tx> StmTransaction for Tx where Tx: Transaction<Ctx = Stm, Err = StmError> {}
+
 pub fn with_tx<F, T, E>(f: F) -> WithTx<F>
 where
     F: Fn(&mut Stm) -> Result<T, E>,
@@ -77,3 +320,9 @@ where
         f(ctx)
     }
 }
+
+impl<F> Visit for WithTx<F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_tx"));
+    }
+}
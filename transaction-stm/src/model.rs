@@ -0,0 +1,248 @@
+//! A model checker of STM transactions, enabled by the `model` feature for
+//! tests.
+//!
+//! `check` runs a scenario of threads running transactions once for every
+//! interleaving of them, and checks the state at the end of each one. Only
+//! one thread runs at a time, and the threads switch at the yield points:
+//! before each attempt, before each `read`, `write` and `modify` leaf, and
+//! before the commit. So a scenario explores how the combinators and the
+//! retries of STM interleave at the granularity of the leaves, e.g. that the
+//! reads of a `join` see a consistent snapshot whatever commits in between.
+//! Accesses by `stm::Transaction` directly are not yield points.
+//!
+//! The number of interleavings grows exponentially with the yield points, so
+//! keep the scenarios to a few threads of a few leaves. Transactions which
+//! block by `retry` until another one commits are not supported, since the
+//! waits are out of the control of the model. A thread blocked for 10
+//! seconds fails the check.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate stm;
+//! extern crate transaction;
+//! extern crate transaction_stm;
+//!
+//! use stm::TVar;
+//! use transaction::TransactionExt;
+//! use transaction_stm::model;
+//! use transaction_stm::{modify, read, run};
+//!
+//! # fn main() {
+//! let schedules = model::check(|threads| {
+//!     let from = TVar::new(10);
+//!     let to = TVar::new(0);
+//!     let to2 = to.clone();
+//!     let transfer = modify(&from, |v| v - 5).and_then(move |()| modify(&to2, |v| v + 5));
+//!     threads.spawn(move || run(&transfer));
+//!     // the audit never sees the money in flight
+//!     let audit = read(&from).join(read(&to));
+//!     threads.spawn(move || {
+//!         let (f, t) = run(&audit);
+//!         assert_eq!(f + t, 10);
+//!     });
+//!     move || assert_eq!((from.read_atomic(), to.read_atomic()), (5, 5))
+//! });
+//! assert!(schedules > 1);
+//! # }
+//! ```
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+// how long the scheduler waits for the running thread to yield
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// the model checks run one at a time, since the tests of a crate run
+// concurrently
+static CHECKING: Mutex<()> = Mutex::new(());
+
+thread_local!(static CURRENT: RefCell<Option<(Arc<Shared>, usize)>> = const { RefCell::new(None) });
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Parked,
+    Running,
+    Done,
+}
+
+struct State {
+    statuses: Vec<Status>,
+    panic: Option<(usize, Box<dyn Any + Send>)>,
+    // the check gave up on the interleaving, so the threads run freely
+    abandoned: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The threads of a scenario of `check`
+pub struct Threads {
+    threads: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+impl Threads {
+    /// Run `f` on a thread of the scenario
+    pub fn spawn<F>(&mut self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.threads.push(Box::new(f));
+    }
+}
+
+/// Run the scenario once for every interleaving of its threads, and return
+/// the number of the interleavings. `scenario` makes the variables, spawns
+/// the threads and returns a function checking the variables after all the
+/// threads finish. It is called again for each interleaving, so the
+/// variables must be made inside it.
+///
+/// A panic of a thread or of the check is propagated with the interleaving
+/// which led to it, given as the indices of the threads in the order they
+/// ran between the yield points.
+pub fn check<F, C>(scenario: F) -> u64
+where
+    F: Fn(&mut Threads) -> C,
+    C: FnOnce(),
+{
+    let _checking = CHECKING.lock().unwrap_or_else(|e| e.into_inner());
+    let mut prefix = Vec::new();
+    let mut schedules = 0;
+    loop {
+        let mut threads = Threads { threads: Vec::new() };
+        let verify = scenario(&mut threads);
+        let (choices, trace) = explore(threads.threads, &prefix);
+        if let Err(e) = panic::catch_unwind(AssertUnwindSafe(verify)) {
+            eprintln!("the check failed after the interleaving {:?}", trace);
+            panic::resume_unwind(e);
+        }
+        schedules += 1;
+        // the next schedule in the depth first order: advance the last
+        // choice which has an alternative left
+        prefix = choices;
+        loop {
+            match prefix.pop() {
+                Some((choice, options)) if choice + 1 < options => {
+                    prefix.push((choice + 1, options));
+                    break;
+                }
+                Some(_) => continue,
+                None => return schedules,
+            }
+        }
+    }
+}
+
+// a choice of the thread to run among the parked ones: the index of the
+// chosen one and the number of the parked ones
+type Choice = (usize, usize);
+
+// run the threads once, following the choices of the prefix and taking the
+// first option afterwards, and return all the choices made and the threads
+// in the order they ran
+fn explore(threads: Vec<Box<dyn FnOnce() + Send>>, prefix: &[Choice]) -> (Vec<Choice>, Vec<usize>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            statuses: vec![Status::Running; threads.len()],
+            panic: None,
+            abandoned: false,
+        }),
+        changed: Condvar::new(),
+    });
+    let handles = threads
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                CURRENT.with(|current| *current.borrow_mut() = Some((shared.clone(), i)));
+                yield_point();
+                let ret = panic::catch_unwind(AssertUnwindSafe(f));
+                CURRENT.with(|current| *current.borrow_mut() = None);
+                let mut state = shared.lock();
+                state.statuses[i] = Status::Done;
+                if let Err(e) = ret {
+                    state.panic.get_or_insert((i, e));
+                }
+                shared.changed.notify_all();
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut choices = Vec::new();
+    let mut trace = Vec::new();
+    let mut state = shared.lock();
+    loop {
+        // wait for all the threads to park or finish
+        while state.statuses.contains(&Status::Running) {
+            let (next, timeout) = shared
+                .changed
+                .wait_timeout(state, TIMEOUT)
+                .unwrap_or_else(|e| e.into_inner());
+            state = next;
+            if timeout.timed_out() && state.statuses.contains(&Status::Running) {
+                panic!("a thread blocked out of the yield points after the interleaving {:?}", trace);
+            }
+        }
+        if let Some((i, e)) = state.panic.take() {
+            state.abandoned = true;
+            drop(state);
+            shared.changed.notify_all();
+            eprintln!("thread {} panicked after the interleaving {:?}", i, trace);
+            panic::resume_unwind(e);
+        }
+        let parked = (0..state.statuses.len())
+            .filter(|&i| state.statuses[i] == Status::Parked)
+            .collect::<Vec<_>>();
+        if parked.is_empty() {
+            break;
+        }
+        let choice = if parked.len() == 1 {
+            0
+        } else {
+            let choice = prefix.get(choices.len()).map_or(0, |&(choice, _)| choice);
+            choices.push((choice, parked.len()));
+            choice
+        };
+        let next = parked[choice];
+        trace.push(next);
+        state.statuses[next] = Status::Running;
+        shared.changed.notify_all();
+    }
+    drop(state);
+    for handle in handles {
+        let _ = handle.join();
+    }
+    (choices, trace)
+}
+
+// let the scheduler of the model choose the thread to run next. This does
+// nothing out of `check`.
+pub(crate) fn yield_point() {
+    let current = CURRENT.with(|current| current.borrow().clone());
+    let (shared, i) = match current {
+        Some(current) => current,
+        None => return,
+    };
+    let mut state = shared.lock();
+    if state.abandoned {
+        return;
+    }
+    state.statuses[i] = Status::Parked;
+    shared.changed.notify_all();
+    while state.statuses[i] == Status::Parked && !state.abandoned {
+        state = shared.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+    }
+}
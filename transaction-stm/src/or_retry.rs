@@ -0,0 +1,87 @@
+use stm::{StmError, Transaction as Stm};
+use transaction::{visit_node, IntoTransaction, Node, Transaction, Visit, Visitor};
+
+/// Run the first transaction, and if it calls `retry`, run the second one
+/// instead, discarding the writes of the first. If both retry, the whole STM
+/// transaction blocks until a variable read by either of them changes. This is the
+/// `orElse` of STM, built on `stm::Transaction::or`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate stm;
+/// extern crate transaction;
+/// extern crate transaction_stm;
+///
+/// use stm::TVar;
+/// use transaction::with_ctx;
+/// use transaction_stm::{run, StmTransaction};
+///
+/// # fn main() {
+/// // pop the first job of the urgent queue, or of the normal one if it is
+/// // empty
+/// let pop = |queue: &TVar<Vec<u32>>| {
+///     let queue = queue.clone();
+///     with_ctx(move |ctx: &mut stm::Transaction| {
+///         let mut jobs = queue.read(ctx)?;
+///         if jobs.is_empty() {
+///             return stm::retry();
+///         }
+///         let job = jobs.remove(0);
+///         queue.write(ctx, jobs)?;
+///         Ok(job)
+///     })
+/// };
+/// let urgent = TVar::new(vec![]);
+/// let normal = TVar::new(vec![1, 2]);
+/// let next = pop(&urgent).or_retry(pop(&normal));
+/// assert_eq!(run(&next), 1);
+/// assert_eq!(normal.read_atomic(), vec![2]);
+/// # }
+/// ```
+pub fn or_retry<A, B>(a: A, b: B) -> OrRetry<A::Tx, B::Tx>
+where
+    A: IntoTransaction<Stm, Err = StmError>,
+    B: IntoTransaction<Stm, Item = A::Item, Err = StmError>,
+{
+    OrRetry {
+        tx1: a.into_transaction(),
+        tx2: b.into_transaction(),
+    }
+}
+
+/// The result of `or_retry`
+#[derive(Debug)]
+#[must_use]
+pub struct OrRetry<Tx1, Tx2> {
+    tx1: Tx1,
+    tx2: Tx2,
+}
+
+impl<Tx1, Tx2> Transaction for OrRetry<Tx1, Tx2>
+where
+    Tx1: Transaction<Ctx = Stm, Err = StmError>,
+    Tx2: Transaction<Ctx = Stm, Item = Tx1::Item, Err = StmError>,
+{
+    type Ctx = Stm;
+    type Item = Tx1::Item;
+    type Err = StmError;
+
+    fn run(&self, ctx: &mut Stm) -> Result<Self::Item, Self::Err> {
+        let OrRetry { ref tx1, ref tx2 } = *self;
+        ctx.or(|ctx| tx1.run(ctx), |ctx| tx2.run(ctx))
+    }
+}
+
+impl<Tx1, Tx2> Visit for OrRetry<Tx1, Tx2>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("or_retry"), |v| {
+            self.tx1.accept(v);
+            self.tx2.accept(v);
+        });
+    }
+}
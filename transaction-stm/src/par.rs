@@ -0,0 +1,48 @@
+use std::thread;
+
+use stm::{StmError, Transaction as Stm};
+use transaction::Transaction;
+
+use run;
+
+/// Run the `stm` transaction from `n` threads at once, and return the
+/// results in the order of the threads. This is the usual way to exercise a
+/// transaction under contention, e.g. a shared counter or queue in a test or
+/// a benchmark. Each thread runs it by `run`, and a panic of a thread is
+/// propagated after all of them finish.
+///
+/// # Examples
+///
+/// ```
+/// extern crate stm;
+/// extern crate transaction;
+/// extern crate transaction_stm;
+///
+/// use stm::TVar;
+/// use transaction::TransactionExt;
+/// use transaction_stm::{par_run, read, write};
+///
+/// # fn main() {
+/// let counter = TVar::new(0);
+/// let c = counter.clone();
+/// // take a ticket
+/// let next = read(&counter).and_then(move |n| write(&c, n + 1).map(move |()| n));
+/// let mut tickets = par_run(8, &next);
+/// tickets.sort();
+/// assert_eq!(tickets, (0..8).collect::<Vec<_>>());
+/// assert_eq!(counter.read_atomic(), 8);
+/// # }
+/// ```
+pub fn par_run<T, Tx>(n: usize, tx: &Tx) -> Vec<T>
+where
+    Tx: Transaction<Ctx = Stm, Item = T, Err = StmError> + Sync,
+    T: Send,
+{
+    thread::scope(|scope| {
+        let handles = (0..n).map(|_| scope.spawn(|| run(tx))).collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|e| ::std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
@@ -0,0 +1,198 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use stm::{StmError, Transaction as Stm};
+use transaction::{visit_node, IntoTransaction, Node, Transaction, Visit, Visitor};
+
+// a waiting transaction ages by one every interval
+const AGING_INTERVAL: Duration = Duration::from_millis(1);
+
+// the effective priorities of the prioritized runs which conflicted, by
+// their ids
+static CONTENDERS: Mutex<BTreeMap<u64, u64>> = Mutex::new(BTreeMap::new());
+static TURN: Condvar = Condvar::new();
+// the highest of `CONTENDERS`, read by the leaves without locking
+static HIGHEST: AtomicU64 = AtomicU64::new(0);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// the prioritized run on the thread. STM transactions don't nest, so a
+// thread runs one at a time.
+#[derive(Clone, Copy)]
+struct PrioritizedRun {
+    id: u64,
+    effective: u64,
+    contending: bool,
+}
+
+thread_local!(static RUN: Cell<Option<PrioritizedRun>> = const { Cell::new(None) });
+
+/// Give the STM transaction a priority hint for the contention manager of
+/// the runners: a higher number is more urgent. After a conflict, a
+/// prioritized transaction waits while one with a higher priority is
+/// contending too, and the attempts of the lower ones abort at their next
+/// `read`, `write` or `modify`, so the urgent transaction commits first
+/// instead of all of them conflicting again.
+///
+/// The priority goes up by one for every conflict and every millisecond of
+/// waiting, so a low priority transaction is delayed, not starved. The
+/// transactions without a priority are not managed. The outermost priority
+/// of a transaction is used.
+///
+/// # Examples
+///
+/// ```
+/// extern crate stm;
+/// extern crate transaction;
+/// extern crate transaction_stm;
+///
+/// use std::thread;
+/// use stm::TVar;
+/// use transaction::TransactionExt;
+/// use transaction_stm::{modify, run, StmTransaction};
+///
+/// # fn main() {
+/// let balance = TVar::new(0);
+/// let threads: Vec<_> = (0..4)
+///     .map(|i| {
+///         let balance = balance.clone();
+///         thread::spawn(move || {
+///             // the first thread is more urgent than the others
+///             let deposit = modify(&balance, |b| b + 1).priority(if i == 0 { 10 } else { 0 });
+///             for _ in 0..100 {
+///                 run(&deposit);
+///             }
+///         })
+///     })
+///     .collect();
+/// for t in threads {
+///     t.join().unwrap();
+/// }
+/// assert_eq!(balance.read_atomic(), 400);
+/// # }
+/// ```
+pub fn prioritized<A>(priority: u32, a: A) -> Prioritized<A::Tx>
+where
+    A: IntoTransaction<Stm, Err = StmError>,
+{
+    Prioritized {
+        priority,
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `prioritized`
+#[derive(Debug)]
+#[must_use]
+pub struct Prioritized<Tx> {
+    priority: u32,
+    tx: Tx,
+}
+
+impl<Tx> Transaction for Prioritized<Tx>
+where
+    Tx: Transaction<Ctx = Stm, Err = StmError>,
+{
+    type Ctx = Stm;
+    type Item = Tx::Item;
+    type Err = StmError;
+
+    fn run(&self, ctx: &mut Stm) -> Result<Self::Item, Self::Err> {
+        RUN.with(|run| {
+            if run.get().is_none() {
+                run.set(Some(PrioritizedRun {
+                    id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                    effective: u64::from(self.priority),
+                    contending: false,
+                }))
+            }
+        });
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Prioritized<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("prioritized"), |v| self.tx.accept(v));
+    }
+}
+
+// abort the attempt of the prioritized run if a run of a higher priority is
+// contending, called by the leaves
+pub(crate) fn check() -> Result<(), StmError> {
+    match RUN.with(Cell::get) {
+        Some(run) if run.effective < HIGHEST.load(Ordering::Relaxed) => Err(StmError::Failure),
+        _ => Ok(()),
+    }
+}
+
+// age the prioritized run after a conflict and wait while a run of a higher
+// priority is contending, aging meanwhile
+pub(crate) fn contend() {
+    let mut run = match RUN.with(Cell::get) {
+        Some(run) => run,
+        None => return,
+    };
+    run.effective += 1;
+    run.contending = true;
+    let mut contenders = CONTENDERS.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        contenders.insert(run.id, run.effective);
+        update_highest(&contenders);
+        let highest = contenders.values().max().copied().unwrap_or(0);
+        if run.effective >= highest {
+            break;
+        }
+        contenders = TURN
+            .wait_timeout(contenders, AGING_INTERVAL)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+        run.effective += 1;
+    }
+    RUN.with(|r| r.set(Some(run)));
+}
+
+// stop contending while the prioritized run waits for a variable to change
+// after `retry`, letting the lower ones change it
+pub(crate) fn withdraw() {
+    if let Some(mut run) = RUN.with(Cell::get) {
+        leave(&mut run);
+        RUN.with(|r| r.set(Some(run)));
+    }
+}
+
+// forget the prioritized run left on the thread by a transaction run
+// outside of the runners
+pub(crate) fn begin() {
+    RUN.with(|r| r.set(None));
+}
+
+// forget the prioritized run on the thread, letting the lower ones go
+pub(crate) fn end() {
+    if let Some(mut run) = RUN.with(|r| r.take()) {
+        leave(&mut run);
+    }
+}
+
+fn leave(run: &mut PrioritizedRun) {
+    if run.contending {
+        run.contending = false;
+        let mut contenders = CONTENDERS.lock().unwrap_or_else(|e| e.into_inner());
+        contenders.remove(&run.id);
+        update_highest(&contenders);
+        TURN.notify_all();
+    }
+}
+
+fn update_highest(contenders: &BTreeMap<u64, u64>) {
+    HIGHEST.store(contenders.values().max().copied().unwrap_or(0), Ordering::Relaxed);
+}
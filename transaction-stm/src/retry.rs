@@ -0,0 +1,75 @@
+use std::marker::PhantomData;
+
+use stm::{StmError, Transaction as Stm};
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+/// Block the STM transaction and run it again once a variable it has read
+/// changes. This is the `retry` of STM, for waiting on a condition, e.g.
+/// until a queue is non-empty. Within `or_retry`, the alternative is run
+/// instead.
+///
+/// # Examples
+///
+/// ```
+/// extern crate stm;
+/// extern crate transaction;
+/// extern crate transaction_stm;
+///
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// use stm::TVar;
+/// use transaction::prelude::*;
+/// use transaction::Branch;
+/// use transaction_stm::{retry, run};
+///
+/// # fn main() {
+/// let queue = TVar::new(Vec::new());
+/// let producer = queue.clone();
+/// let handle = thread::spawn(move || {
+///     thread::sleep(Duration::from_millis(10));
+///     stm::atomically(|ctx| producer.write(ctx, vec![42]));
+/// });
+///
+/// // take all the jobs, waiting for at least one
+/// let jobs = queue.clone();
+/// let take = with_ctx(move |ctx: &mut stm::Transaction| jobs.replace(ctx, Vec::new()))
+///     .and_then(|jobs: Vec<u32>| {
+///         if jobs.is_empty() {
+///             Branch::B1(retry())
+///         } else {
+///             Branch::B2(ok(jobs))
+///         }
+///     });
+/// assert_eq!(run(&take), vec![42]);
+/// handle.join().unwrap();
+/// # }
+/// ```
+pub fn retry<T>() -> Retry<T> {
+    Retry {
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `retry`
+#[derive(Debug)]
+#[must_use]
+pub struct Retry<T> {
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Transaction for Retry<T> {
+    type Ctx = Stm;
+    type Item = T;
+    type Err = StmError;
+
+    fn run(&self, _ctx: &mut Stm) -> Result<T, StmError> {
+        stm::retry()
+    }
+}
+
+impl<T> Visit for Retry<T> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("retry"));
+    }
+}
@@ -0,0 +1,80 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use stm::TVar;
+
+/// The statistics of a run of an STM transaction, returned by `run_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// How many times the transaction ran, 1 if the first attempt committed
+    pub attempts: u64,
+    /// How many of the attempts called `retry` and waited for a variable to
+    /// change. The other failed attempts conflicted with concurrent
+    /// transactions.
+    pub blocked: u64,
+    /// The duration of the run, including the waits
+    pub duration: Duration,
+    /// How many distinct variables the committed attempt read by `read` and
+    /// `modify`
+    pub reads: u64,
+    /// How many distinct variables the committed attempt wrote by `write`
+    /// and `modify`
+    pub writes: u64,
+}
+
+// the variables accessed by the current attempt, identified by their
+// control blocks. STM transactions don't nest, so a thread runs one attempt
+// at a time.
+#[derive(Default)]
+struct Accesses {
+    reads: HashSet<usize>,
+    writes: HashSet<usize>,
+}
+
+thread_local!(static ACCESSES: RefCell<Option<Accesses>> = const { RefCell::new(None) });
+
+fn id<T>(var: &TVar<T>) -> usize
+where
+    T: Any + Send + Sync + Clone,
+{
+    Arc::as_ptr(var.control_block()) as usize
+}
+
+pub(crate) fn record_read<T>(var: &TVar<T>)
+where
+    T: Any + Send + Sync + Clone,
+{
+    ACCESSES.with(|accesses| {
+        if let Some(ref mut accesses) = *accesses.borrow_mut() {
+            accesses.reads.insert(id(var));
+        }
+    })
+}
+
+pub(crate) fn record_write<T>(var: &TVar<T>)
+where
+    T: Any + Send + Sync + Clone,
+{
+    ACCESSES.with(|accesses| {
+        if let Some(ref mut accesses) = *accesses.borrow_mut() {
+            accesses.writes.insert(id(var));
+        }
+    })
+}
+
+// start recording the accesses of an attempt, forgetting the previous one
+pub(crate) fn begin_attempt() {
+    ACCESSES.with(|accesses| *accesses.borrow_mut() = Some(Accesses::default()))
+}
+
+// stop recording, and return the numbers of the variables read and written
+// by the last attempt
+pub(crate) fn end() -> (u64, u64) {
+    ACCESSES.with(|accesses| match accesses.borrow_mut().take() {
+        Some(accesses) => (accesses.reads.len() as u64, accesses.writes.len() as u64),
+        None => (0, 0),
+    })
+}
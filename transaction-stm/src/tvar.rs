@@ -0,0 +1,171 @@
+use std::any::Any;
+use std::fmt;
+
+use stm::{StmError, TVar, Transaction as Stm};
+use transaction::{visit_leaf, Node, Transaction, Visit, Visitor};
+
+#[cfg(feature = "model")]
+use model;
+use priority;
+use stats;
+
+/// Read the variable.
+pub fn read<T>(var: &TVar<T>) -> Read<T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    Read { var: var.clone() }
+}
+
+/// The result of `read`
+#[must_use]
+pub struct Read<T> {
+    var: TVar<T>,
+}
+
+impl<T> fmt::Debug for Read<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Read").finish_non_exhaustive()
+    }
+}
+
+impl<T> Transaction for Read<T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = Stm;
+    type Item = T;
+    type Err = StmError;
+
+    fn run(&self, ctx: &mut Stm) -> Result<T, StmError> {
+        #[cfg(feature = "model")]
+        model::yield_point();
+        priority::check()?;
+        stats::record_read(&self.var);
+        self.var.read(ctx)
+    }
+}
+
+impl<T> Visit for Read<T> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("read"));
+    }
+}
+
+/// Write the value to the variable. The value is cloned on each run, since
+/// STM may run the transaction several times.
+pub fn write<T>(var: &TVar<T>, value: T) -> Write<T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    Write {
+        var: var.clone(),
+        value,
+    }
+}
+
+/// The result of `write`
+#[must_use]
+pub struct Write<T> {
+    var: TVar<T>,
+    value: T,
+}
+
+impl<T> fmt::Debug for Write<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Write").finish_non_exhaustive()
+    }
+}
+
+impl<T> Transaction for Write<T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = Stm;
+    type Item = ();
+    type Err = StmError;
+
+    fn run(&self, ctx: &mut Stm) -> Result<(), StmError> {
+        #[cfg(feature = "model")]
+        model::yield_point();
+        priority::check()?;
+        stats::record_write(&self.var);
+        self.var.write(ctx, self.value.clone())
+    }
+}
+
+impl<T> Visit for Write<T> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("write"));
+    }
+}
+
+/// Replace the value of the variable with `f` applied to it.
+///
+/// # Examples
+///
+/// ```
+/// extern crate stm;
+/// extern crate transaction;
+/// extern crate transaction_stm;
+///
+/// use stm::TVar;
+/// use transaction::TransactionExt;
+/// use transaction_stm::{modify, read, run};
+///
+/// # fn main() {
+/// let x = TVar::new(1);
+/// let y = TVar::new(10);
+/// let (y2, x2) = (y.clone(), x.clone());
+/// let move_x_to_y = read(&x)
+///     .and_then(move |xv| modify(&y2, move |yv| yv + xv))
+///     .and_then(move |()| modify(&x2, |_| 0));
+/// run(&move_x_to_y);
+/// assert_eq!((x.read_atomic(), y.read_atomic()), (0, 11));
+/// # }
+/// ```
+pub fn modify<T, F>(var: &TVar<T>, f: F) -> Modify<T, F>
+where
+    T: Any + Send + Sync + Clone,
+    F: Fn(T) -> T,
+{
+    Modify { var: var.clone(), f }
+}
+
+/// The result of `modify`
+#[must_use]
+pub struct Modify<T, F> {
+    var: TVar<T>,
+    f: F,
+}
+
+impl<T, F> fmt::Debug for Modify<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Modify").finish_non_exhaustive()
+    }
+}
+
+impl<T, F> Transaction for Modify<T, F>
+where
+    T: Any + Send + Sync + Clone,
+    F: Fn(T) -> T,
+{
+    type Ctx = Stm;
+    type Item = ();
+    type Err = StmError;
+
+    fn run(&self, ctx: &mut Stm) -> Result<(), StmError> {
+        #[cfg(feature = "model")]
+        model::yield_point();
+        priority::check()?;
+        stats::record_read(&self.var);
+        stats::record_write(&self.var);
+        self.var.modify(ctx, &self.f)
+    }
+}
+
+impl<T, F> Visit for Modify<T, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("modify"));
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt;
+
+use transaction::{visit_node, Node, Transaction, Visit, Visitor};
+
+/// Assertions on the results of transactions, for the tests to state what
+/// they expect instead of unwrapping the results.
+///
+/// The assertions are transactions passing the results through, which panic
+/// when run if the result is not the expected one, telling the label of the
+/// transaction and the difference between the expected and the actual
+/// result.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::prelude::*;
+/// use transaction_test::Expect;
+///
+/// fn withdraw(amount: i32) -> impl Transaction<Ctx = i32, Item = i32, Err = String> {
+///     with_ctx(move |balance: &mut i32| {
+///         if *balance < amount {
+///             return Err(format!("insufficient balance {}", balance));
+///         }
+///         *balance -= amount;
+///         Ok(*balance)
+///     })
+/// }
+///
+/// let mut balance = 10;
+/// withdraw(3).expect_item(7).run(&mut balance).unwrap();
+/// withdraw(10)
+///     .named("overdraw")
+///     .expect_err_matching(|e| e.starts_with("insufficient"))
+///     .run(&mut balance)
+///     .unwrap_err();
+/// ```
+///
+/// ```should_panic
+/// # use transaction::prelude::*;
+/// # use transaction_test::Expect;
+/// // panics with
+/// // transaction `pair` returned an unexpected item:
+/// //   (
+/// // -     1,
+/// // +     2,
+/// //       3,
+/// //   )
+/// ok::<(), _, ()>((2, 3)).named("pair").expect_item((1, 3)).run(&mut ()).unwrap();
+/// ```
+pub trait Expect: Transaction + Sized {
+    /// Panic if the transaction fails
+    fn expect_ok(self) -> ExpectOk<Self>
+    where
+        Self::Err: fmt::Debug,
+    {
+        ExpectOk { tx: self }
+    }
+
+    /// Panic if the transaction succeeds
+    fn expect_err(self) -> ExpectErr<Self>
+    where
+        Self::Item: fmt::Debug,
+    {
+        ExpectErr { tx: self }
+    }
+
+    /// Panic if the transaction fails or succeeds with an item other than
+    /// `expected`
+    fn expect_item(self, expected: Self::Item) -> ExpectItem<Self, Self::Item>
+    where
+        Self::Item: PartialEq + fmt::Debug,
+        Self::Err: fmt::Debug,
+    {
+        ExpectItem { tx: self, expected }
+    }
+
+    /// Panic if the transaction succeeds or fails with an error not
+    /// satisfying the predicate
+    fn expect_err_matching<F>(self, predicate: F) -> ExpectErrMatching<Self, F>
+    where
+        Self::Item: fmt::Debug,
+        Self::Err: fmt::Debug,
+        F: Fn(&Self::Err) -> bool,
+    {
+        ExpectErrMatching { tx: self, predicate }
+    }
+}
+
+impl<Tx> Expect for Tx where Tx: Transaction {}
+
+/// The result of `expect_ok`
+#[derive(Debug)]
+#[must_use]
+pub struct ExpectOk<Tx> {
+    tx: Tx,
+}
+
+impl<Tx> Transaction for ExpectOk<Tx>
+where
+    Tx: Transaction,
+    Tx::Err: fmt::Debug,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match self.tx.run(ctx) {
+            Ok(item) => Ok(item),
+            Err(e) => panic!("{} failed unexpectedly with {:#?}", name(self.tx.label()), e),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for ExpectOk<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("expect_ok"), |v| self.tx.accept(v));
+    }
+}
+
+/// The result of `expect_err`
+#[derive(Debug)]
+#[must_use]
+pub struct ExpectErr<Tx> {
+    tx: Tx,
+}
+
+impl<Tx> Transaction for ExpectErr<Tx>
+where
+    Tx: Transaction,
+    Tx::Item: fmt::Debug,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match self.tx.run(ctx) {
+            Ok(item) => panic!("{} succeeded unexpectedly with {:#?}", name(self.tx.label()), item),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for ExpectErr<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("expect_err"), |v| self.tx.accept(v));
+    }
+}
+
+/// The result of `expect_item`
+#[derive(Debug)]
+#[must_use]
+pub struct ExpectItem<Tx, T> {
+    tx: Tx,
+    expected: T,
+}
+
+impl<Tx> Transaction for ExpectItem<Tx, Tx::Item>
+where
+    Tx: Transaction,
+    Tx::Item: PartialEq + fmt::Debug,
+    Tx::Err: fmt::Debug,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match self.tx.run(ctx) {
+            Ok(ref item) if *item != self.expected => panic!(
+                "{} returned an unexpected item:\n{}",
+                name(self.tx.label()),
+                diff(&format!("{:#?}", self.expected), &format!("{:#?}", item))
+            ),
+            Ok(item) => Ok(item),
+            Err(e) => panic!(
+                "{} failed unexpectedly with {:#?}\nexpected {:#?}",
+                name(self.tx.label()),
+                e,
+                self.expected
+            ),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, T> Visit for ExpectItem<Tx, T>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("expect_item"), |v| self.tx.accept(v));
+    }
+}
+
+/// The result of `expect_err_matching`
+#[derive(Debug)]
+#[must_use]
+pub struct ExpectErrMatching<Tx, F> {
+    tx: Tx,
+    predicate: F,
+}
+
+impl<Tx, F> Transaction for ExpectErrMatching<Tx, F>
+where
+    Tx: Transaction,
+    Tx::Item: fmt::Debug,
+    Tx::Err: fmt::Debug,
+    F: Fn(&Tx::Err) -> bool,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match self.tx.run(ctx) {
+            Ok(item) => panic!("{} succeeded unexpectedly with {:#?}", name(self.tx.label()), item),
+            Err(ref e) if !(self.predicate)(e) => {
+                panic!("{} failed with an unexpected error {:#?}", name(self.tx.label()), e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, F> Visit for ExpectErrMatching<Tx, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("expect_err_matching"), |v| self.tx.accept(v));
+    }
+}
+
+// how the panics name the transaction
+fn name(label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("transaction `{}`", label),
+        None => "transaction".to_string(),
+    }
+}
+
+// the lines of the expected and the actual texts, marking the ones only in
+// the expected with `-` and the ones only in the actual with `+`, by their
+// longest common subsequence
+fn diff(expected: &str, actual: &str) -> String {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+    // lcs[i][j]: the length of the common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out += &format!("  {}\n", old[i]);
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out += &format!("- {}\n", old[i]);
+            i += 1;
+        } else {
+            out += &format!("+ {}\n", new[j]);
+            j += 1;
+        }
+    }
+    out
+}
@@ -0,0 +1,190 @@
+//! The laws of the transaction combinators, checked on a context
+//!
+//! The combinators are expected to be lawful whatever the context, e.g.
+//! `ok(a).and_then(f)` behaves as `f(a)`. `Laws` checks each law on a
+//! context by running both sides on fresh contexts and comparing their
+//! results together with the states of the contexts after the runs, so
+//! backend crates can verify that their leaves and contexts keep the laws.
+//! Each method checks one law for the given transactions and functions;
+//! check them over many of those, e.g. generated by a property testing
+//! crate.
+//!
+//! # Examples
+//!
+//! ```
+//! use transaction::prelude::*;
+//! use transaction_test::laws::Laws;
+//!
+//! fn push(x: i32) -> impl Transaction<Ctx = Vec<i32>, Item = usize, Err = String> {
+//!     with_ctx(move |v: &mut Vec<i32>| {
+//!         if x < 0 {
+//!             return Err(format!("negative {}", x));
+//!         }
+//!         v.push(x);
+//!         Ok(v.len())
+//!     })
+//! }
+//!
+//! let laws = Laws::new(|| vec![1, 2], |v: &Vec<i32>| v.clone());
+//! for x in -2..3 {
+//!     let f = move |n: usize| push(x + n as i32);
+//!     let g = |n: usize| push(-(n as i32) + 4);
+//!     laws.left_identity(x as usize, f);
+//!     laws.right_identity(push(x));
+//!     laws.associativity(push(x), f, g);
+//!     laws.map_identity(push(x));
+//!     laws.map_fusion(push(x), |n| n * 2, |n| n + 1);
+//!     laws.or_else_left_identity(format!("{}", x), |_| push(x));
+//!     laws.or_else_right_identity(push(x));
+//!     laws.recover(push(x), |e: String| e.len());
+//! }
+//! ```
+
+use std::fmt;
+
+use transaction::prelude::*;
+use transaction::IntoTransaction;
+
+/// The checker of the laws on the contexts made by `new_ctx`, whose states
+/// are given by `observe`
+#[derive(Debug, Clone, Copy)]
+pub struct Laws<N, O> {
+    new_ctx: N,
+    observe: O,
+}
+
+impl<N, O, Ctx, S> Laws<N, O>
+where
+    N: Fn() -> Ctx,
+    O: Fn(&Ctx) -> S,
+    S: PartialEq + fmt::Debug,
+{
+    /// Check the laws on the contexts made by `new_ctx`, comparing their
+    /// states given by `observe` after the runs
+    pub fn new(new_ctx: N, observe: O) -> Self {
+        Laws { new_ctx, observe }
+    }
+
+    /// Assert that the transactions give the same results and leave the
+    /// contexts in the same states, naming the law in the panic
+    ///
+    /// # Panics
+    ///
+    /// Panics if the results or the states differ
+    pub fn assert_equivalent<A, B>(&self, law: &str, a: A, b: B)
+    where
+        A: Transaction<Ctx = Ctx>,
+        B: Transaction<Ctx = Ctx, Item = A::Item, Err = A::Err>,
+        A::Item: PartialEq + fmt::Debug,
+        A::Err: PartialEq + fmt::Debug,
+    {
+        let left = self.observe_run(a);
+        let right = self.observe_run(b);
+        if left != right {
+            panic!("the law {} is broken:\n  left: {:?}\n right: {:?}", law, left, right);
+        }
+    }
+
+    fn observe_run<Tx>(&self, tx: Tx) -> (Result<Tx::Item, Tx::Err>, S)
+    where
+        Tx: Transaction<Ctx = Ctx>,
+    {
+        let mut ctx = (self.new_ctx)();
+        let ret = tx.run(&mut ctx);
+        (ret, (self.observe)(&ctx))
+    }
+
+    /// `ok(a).and_then(f)` is `f(a)`
+    pub fn left_identity<T, F, B>(&self, a: T, f: F)
+    where
+        T: Clone,
+        F: Fn(T) -> B,
+        B: IntoTransaction<Ctx>,
+        B::Item: PartialEq + fmt::Debug,
+        B::Err: PartialEq + fmt::Debug,
+    {
+        self.assert_equivalent("left identity", ok(a.clone()).and_then(&f), f(a).into_transaction());
+    }
+
+    /// `m.and_then(ok)` is `m`
+    pub fn right_identity<M>(&self, m: M)
+    where
+        M: Transaction<Ctx = Ctx>,
+        M::Item: Clone + PartialEq + fmt::Debug,
+        M::Err: PartialEq + fmt::Debug,
+    {
+        self.assert_equivalent("right identity", (&m).and_then(ok), &m);
+    }
+
+    /// `m.and_then(f).and_then(g)` is `m.and_then(|x| f(x).and_then(g))`
+    pub fn associativity<M, F, G, B, C>(&self, m: M, f: F, g: G)
+    where
+        M: Transaction<Ctx = Ctx>,
+        F: Fn(M::Item) -> B,
+        G: Fn(B::Item) -> C,
+        B: IntoTransaction<Ctx, Err = M::Err>,
+        C: IntoTransaction<Ctx, Err = M::Err>,
+        C::Item: PartialEq + fmt::Debug,
+        M::Err: PartialEq + fmt::Debug,
+    {
+        self.assert_equivalent(
+            "associativity",
+            (&m).and_then(&f).and_then(&g),
+            (&m).and_then(|x| f(x).into_transaction().and_then(&g)),
+        );
+    }
+
+    /// `m.map(|x| x)` is `m`
+    pub fn map_identity<M>(&self, m: M)
+    where
+        M: Transaction<Ctx = Ctx>,
+        M::Item: PartialEq + fmt::Debug,
+        M::Err: PartialEq + fmt::Debug,
+    {
+        self.assert_equivalent("map identity", (&m).map(|x| x), &m);
+    }
+
+    /// `m.map(f).map(g)` is `m.map(|x| g(f(x)))`
+    pub fn map_fusion<M, F, G, U, V>(&self, m: M, f: F, g: G)
+    where
+        M: Transaction<Ctx = Ctx>,
+        F: Fn(M::Item) -> U,
+        G: Fn(U) -> V,
+        V: PartialEq + fmt::Debug,
+        M::Err: PartialEq + fmt::Debug,
+    {
+        self.assert_equivalent("map fusion", (&m).map(&f).map(&g), (&m).map(|x| g(f(x))));
+    }
+
+    /// `err(e).or_else(h)` is `h(e)`
+    pub fn or_else_left_identity<E, H, B>(&self, e: E, h: H)
+    where
+        E: Clone + PartialEq + fmt::Debug,
+        H: Fn(E) -> B,
+        B: IntoTransaction<Ctx, Err = E>,
+        B::Item: PartialEq + fmt::Debug,
+    {
+        self.assert_equivalent("or_else left identity", err(e.clone()).or_else(&h), h(e).into_transaction());
+    }
+
+    /// `m.or_else(err)` is `m`
+    pub fn or_else_right_identity<M>(&self, m: M)
+    where
+        M: Transaction<Ctx = Ctx>,
+        M::Item: PartialEq + fmt::Debug,
+        M::Err: Clone + PartialEq + fmt::Debug,
+    {
+        self.assert_equivalent("or_else right identity", (&m).or_else(err), &m);
+    }
+
+    /// `m.recover(f)` is `m.or_else(|e| ok(f(e)))`
+    pub fn recover<M, F>(&self, m: M, f: F)
+    where
+        M: Transaction<Ctx = Ctx>,
+        F: Fn(M::Err) -> M::Item,
+        M::Item: Clone + PartialEq + fmt::Debug,
+        M::Err: PartialEq + fmt::Debug,
+    {
+        self.assert_equivalent("recover", (&m).recover(&f), (&m).or_else(|e| ok(f(e))));
+    }
+}
@@ -0,0 +1,38 @@
+//! Test doubles for transactions
+//!
+//! `MockCtx` is a context for unit-testing transaction compositions without
+//! a backend. The transactions perform named operations on it by
+//! `MockCtx::call`, which are checked against a script of expected
+//! operations answering with canned responses, and can be made to fail on
+//! their Nth call. It implements the capability traits of `transaction`:
+//! the clock stands still unless set, the random numbers are scripted or
+//! seeded, and locks and savepoints are operations of the script.
+//!
+//! `RecordingCtx` wraps a context and records the operations performed
+//! against it, to compare them to a golden `Recording` or replay them to a
+//! `Verifier`, e.g. a scripted `MockCtx`.
+//!
+//! `assert_ctx_unchanged!` checks that a failed transaction left the
+//! context as it was, by the snapshots of the context.
+//!
+//! `TxTree` is a tree of combinators with known semantics, generated by
+//! quickcheck or proptest with the features of the same names, to fuzz the
+//! combinators and the backends.
+//!
+//! `Expect` adds assertions on the results of transactions, e.g.
+//! `tx.expect_item(expected)`, panicking with a diff when they fail.
+//!
+//! `laws` checks that the combinators keep their laws on a context.
+
+mod expect;
+pub mod laws;
+mod mock;
+mod recording;
+mod snapshot;
+mod tree;
+
+pub use crate::expect::*;
+pub use crate::mock::*;
+pub use crate::recording::*;
+pub use crate::snapshot::*;
+pub use crate::tree::*;
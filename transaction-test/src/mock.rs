@@ -0,0 +1,419 @@
+use std::any::{self, Any};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use transaction::{HasClock, HasConnection, HasLocking, HasRng, Retryable, Savepoints, Snapshots};
+
+/// A scriptable context for unit tests.
+///
+/// The transactions perform the operations by `call`, naming them and
+/// passing their arguments. Each call is recorded and taken against the
+/// next expectation of the script: the operation, and the arguments if
+/// given, must match, or the call panics as a bug of the transaction. The
+/// call then returns the canned response of the expectation.
+///
+/// `fail_nth` makes the Nth call of an operation fail with
+/// `MockError::Injected` instead, regardless of the script, e.g. to test
+/// the error and compensation paths of the compositions.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::prelude::*;
+/// use transaction_test::{MockCtx, MockError};
+///
+/// fn balance(account: &'static str) -> impl Transaction<Ctx = MockCtx, Item = i64, Err = MockError> {
+///     with_ctx(move |ctx: &mut MockCtx| ctx.call("balance", account))
+/// }
+///
+/// fn add(account: &'static str, amount: i64) -> impl Transaction<Ctx = MockCtx, Item = (), Err = MockError> {
+///     with_ctx(move |ctx: &mut MockCtx| ctx.call("add", (account, amount)))
+/// }
+///
+/// fn transfer(from: &'static str, to: &'static str, amount: i64) -> impl Transaction<Ctx = MockCtx, Item = (), Err = MockError> {
+///     balance(from).and_then(move |balance| {
+///         if balance < amount {
+///             err(MockError::Scripted("insufficient".to_string())).boxed()
+///         } else {
+///             add(from, -amount).and_then(move |_| add(to, amount)).boxed()
+///         }
+///     })
+/// }
+///
+/// let mut ctx = MockCtx::new();
+/// ctx.expect("balance").with("alice").returning(100_i64);
+/// ctx.expect("add").with(("alice", -30_i64));
+/// ctx.expect("add").with(("bob", 30_i64));
+/// assert_eq!(transfer("alice", "bob", 30).run(&mut ctx), Ok(()));
+/// ctx.verify();
+///
+/// // the credit fails after the debit
+/// let mut ctx = MockCtx::new();
+/// ctx.expect("balance").returning(100_i64);
+/// ctx.expect("add");
+/// ctx.fail_nth("add", 2);
+/// assert_eq!(
+///     transfer("alice", "bob", 30).run(&mut ctx),
+///     Err(MockError::Injected { op: "add".to_string(), nth: 2 })
+/// );
+/// assert_eq!(ctx.calls().iter().map(|c| c.op.as_str()).collect::<Vec<_>>(), ["balance", "add", "add"]);
+/// ```
+#[derive(Debug)]
+pub struct MockCtx {
+    script: VecDeque<Expectation>,
+    faults: Vec<(String, usize)>,
+    counts: HashMap<String, usize>,
+    calls: Vec<Call>,
+    now: SystemTime,
+    random: VecDeque<u64>,
+    seed: u64,
+}
+
+impl MockCtx {
+    /// A context with an empty script, a clock at the Unix epoch and random
+    /// numbers seeded with 0
+    pub fn new() -> Self {
+        MockCtx {
+            script: VecDeque::new(),
+            faults: Vec::new(),
+            counts: HashMap::new(),
+            calls: Vec::new(),
+            now: UNIX_EPOCH,
+            random: VecDeque::new(),
+            seed: 0,
+        }
+    }
+
+    /// Expect the operation after the ones expected so far. It answers with
+    /// `()` unless the response is given.
+    pub fn expect(&mut self, op: &str) -> &mut Expectation {
+        self.script.push_back(Expectation {
+            op: op.to_string(),
+            args: None,
+            response: None,
+        });
+        self.script.back_mut().expect("just pushed")
+    }
+
+    /// Fail the `nth` call of the operation, counted from 1, with
+    /// `MockError::Injected`. The failed call doesn't take an expectation.
+    pub fn fail_nth(&mut self, op: &str, nth: usize) -> &mut Self {
+        self.faults.push((op.to_string(), nth));
+        self
+    }
+
+    /// Perform the operation with the arguments, returning the canned
+    /// response of the next expectation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the operation or the arguments don't match the next
+    /// expectation, there is no expectation left, or the response is not a
+    /// `T`.
+    pub fn call<T, A>(&mut self, op: &str, args: A) -> Result<T, MockError>
+    where
+        T: Any,
+        A: fmt::Debug,
+    {
+        let args = format!("{:?}", args);
+        let nth = {
+            let count = self.counts.entry(op.to_string()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        self.calls.push(Call {
+            op: op.to_string(),
+            args: args.clone(),
+        });
+        if self.faults.iter().any(|&(ref o, n)| o == op && n == nth) {
+            return Err(MockError::Injected {
+                op: op.to_string(),
+                nth,
+            });
+        }
+        let expectation = match self.take(op, &args) {
+            Ok(expectation) => expectation,
+            Err(message) => panic!("{}", message),
+        };
+        match expectation.response {
+            None => match (Box::new(()) as Box<dyn Any>).downcast() {
+                Ok(t) => Ok(*t),
+                Err(_) => panic!("no response of `{}` is given, expected a {}", op, any::type_name::<T>()),
+            },
+            Some(Ok(response)) => match response.downcast() {
+                Ok(t) => Ok(*t),
+                Err(_) => panic!("the response of `{}` is not a {}", op, any::type_name::<T>()),
+            },
+            Some(Err(e)) => Err(e),
+        }
+    }
+
+    // take the next expectation if the call matches it
+    fn take(&mut self, op: &str, args: &str) -> Result<Expectation, String> {
+        let expectation = match self.script.pop_front() {
+            Some(expectation) => expectation,
+            None => return Err(format!("unexpected call `{}({})`: the script is over", op, args)),
+        };
+        if expectation.op != op {
+            return Err(format!("unexpected call `{}({})`: expected `{}`", op, args, expectation.op));
+        }
+        match expectation.args {
+            Some(ref expected) if expected != args => {
+                Err(format!("unexpected call `{}({})`: expected `{}({})`", op, args, op, expected))
+            }
+            _ => Ok(expectation),
+        }
+    }
+
+    // check the recorded call against the script
+    pub(crate) fn check(&mut self, call: &Call) -> Result<(), String> {
+        self.take(&call.op, &call.args).map(drop)
+    }
+
+    // the operations of the expectations left
+    pub(crate) fn unmet(&self) -> Vec<&str> {
+        self.script.iter().map(|e| e.op.as_str()).collect()
+    }
+
+    /// The calls performed so far, including the failed ones
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    /// Assert that all the expectations are met
+    ///
+    /// # Panics
+    ///
+    /// Panics if some expectations are left
+    pub fn verify(&self) {
+        let unmet = self.unmet();
+        if !unmet.is_empty() {
+            panic!("unmet expectations: {:?}", unmet);
+        }
+    }
+
+    /// Set the clock
+    pub fn set_now(&mut self, now: SystemTime) -> &mut Self {
+        self.now = now;
+        self
+    }
+
+    /// Advance the clock
+    pub fn advance(&mut self, duration: Duration) -> &mut Self {
+        self.now += duration;
+        self
+    }
+
+    /// Give the random numbers in order, before the seeded ones
+    pub fn random<I>(&mut self, numbers: I) -> &mut Self
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        self.random.extend(numbers);
+        self
+    }
+
+    /// Seed the random numbers given after the scripted ones
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Default for MockCtx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The snapshot is the calls performed, the clock and the random numbers.
+/// Restoring it forgets the calls performed after it, but the expectations
+/// they took are not given back, since their responses are consumed.
+impl Snapshots for MockCtx {
+    type Snapshot = MockSnapshot;
+
+    fn snapshot(&self) -> MockSnapshot {
+        MockSnapshot {
+            calls: self.calls.clone(),
+            now: self.now,
+            random: self.random.clone(),
+            seed: self.seed,
+        }
+    }
+
+    fn restore(&mut self, snapshot: MockSnapshot) {
+        self.counts.clear();
+        for call in &snapshot.calls {
+            *self.counts.entry(call.op.clone()).or_insert(0) += 1;
+        }
+        self.calls = snapshot.calls;
+        self.now = snapshot.now;
+        self.random = snapshot.random;
+        self.seed = snapshot.seed;
+    }
+}
+
+/// The state of a `MockCtx`, taken by `Snapshots::snapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockSnapshot {
+    calls: Vec<Call>,
+    now: SystemTime,
+    random: VecDeque<u64>,
+    seed: u64,
+}
+
+/// An expected operation of `MockCtx`, given by `MockCtx::expect`
+pub struct Expectation {
+    op: String,
+    args: Option<String>,
+    response: Option<Result<Box<dyn Any>, MockError>>,
+}
+
+impl Expectation {
+    /// Expect the arguments, compared by their `Debug` representations
+    pub fn with<A>(&mut self, args: A) -> &mut Self
+    where
+        A: fmt::Debug,
+    {
+        self.args = Some(format!("{:?}", args));
+        self
+    }
+
+    /// Answer with the value
+    pub fn returning<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Any,
+    {
+        self.response = Some(Ok(Box::new(value)));
+        self
+    }
+
+    /// Answer with the error
+    pub fn failing(&mut self, error: MockError) -> &mut Self {
+        self.response = Some(Err(error));
+        self
+    }
+}
+
+impl fmt::Debug for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Expectation")
+            .field("op", &self.op)
+            .field("args", &self.args)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A call performed on `MockCtx`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Call {
+    /// The name of the operation
+    pub op: String,
+    /// The `Debug` representation of the arguments
+    pub args: String,
+}
+
+/// The errors of `MockCtx`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MockError {
+    /// The call was failed by `MockCtx::fail_nth`
+    Injected {
+        /// The operation
+        op: String,
+        /// The number of the failed call of the operation
+        nth: usize,
+    },
+    /// The scripted error
+    Scripted(String),
+    /// The scripted contention for locks, which is retryable
+    Contended,
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MockError::Injected { ref op, nth } => write!(f, "injected failure of call {} of {}", nth, op),
+            MockError::Scripted(ref message) => write!(f, "{}", message),
+            MockError::Contended => write!(f, "locks are contended"),
+        }
+    }
+}
+
+impl Error for MockError {}
+
+impl Retryable for MockError {
+    fn is_retryable(&self) -> bool {
+        match *self {
+            MockError::Contended => true,
+            MockError::Injected { .. } | MockError::Scripted(_) => false,
+        }
+    }
+}
+
+/// The context is its own connection, for the transactions written with
+/// `with_connection`
+impl HasConnection<MockCtx> for MockCtx {
+    fn connection(&mut self) -> &mut MockCtx {
+        self
+    }
+}
+
+impl HasClock for MockCtx {
+    fn now(&self) -> SystemTime {
+        self.now
+    }
+}
+
+impl HasRng for MockCtx {
+    fn next_u64(&mut self) -> u64 {
+        match self.random.pop_front() {
+            Some(n) => n,
+            None => splitmix64(&mut self.seed),
+        }
+    }
+}
+
+/// Locks are the operation `lock` taking the keys
+impl<K> HasLocking<K> for MockCtx
+where
+    K: fmt::Debug,
+{
+    type Error = MockError;
+
+    fn lock(&mut self, keys: &[K]) -> Result<(), MockError> {
+        self.call("lock", keys)
+    }
+
+    fn is_contention(error: &MockError) -> bool {
+        *error == MockError::Contended
+    }
+}
+
+/// Savepoints are the operations `savepoint`, `release` and `rollback_to`
+/// taking the name
+impl Savepoints for MockCtx {
+    type Error = MockError;
+
+    fn savepoint(&mut self, name: &str) -> Result<(), MockError> {
+        self.call("savepoint", name)
+    }
+
+    fn release(&mut self, name: &str) -> Result<(), MockError> {
+        self.call("release", name)
+    }
+
+    fn rollback_to(&mut self, name: &str) -> Result<(), MockError> {
+        self.call("rollback_to", name)
+    }
+}
+
+// SplitMix64, which is enough for tests and needs no dependency
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,344 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use transaction::{HasClock, HasConnection, HasLocking, HasRng, Savepoints};
+
+use crate::mock::{Call, MockCtx};
+
+/// A context recording the operations performed against the wrapped
+/// context `C`, e.g. for golden tests asserting that a refactored
+/// composition still performs the same operations in the same order.
+///
+/// The operations of the capability traits `C` implements are recorded
+/// under the names of their methods, e.g. `now` or `lock([1, 2])`, and
+/// forwarded to `C`. The transactions record their other operations by
+/// `record`.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::SystemTime;
+///
+/// use transaction::prelude::*;
+/// use transaction::{now, random, HasClock, HasRng};
+/// use transaction_test::{MockCtx, Recording, RecordingCtx};
+///
+/// fn stamp<Ctx>() -> impl Transaction<Ctx = Ctx, Item = (SystemTime, u64), Err = ()>
+/// where
+///     Ctx: HasClock + HasRng,
+/// {
+///     now().join(random())
+/// }
+///
+/// // the refactored one draws the random number first
+/// fn stamp2<Ctx>() -> impl Transaction<Ctx = Ctx, Item = (SystemTime, u64), Err = ()>
+/// where
+///     Ctx: HasClock + HasRng,
+/// {
+///     random().and_then(|r| now().map(move |t| (t, r)))
+/// }
+///
+/// let golden = "now()\nnext_u64()\n".parse::<Recording>().unwrap();
+///
+/// let mut ctx = RecordingCtx::new(MockCtx::new());
+/// stamp().run(&mut ctx).unwrap();
+/// assert_eq!(ctx.recording(), golden);
+///
+/// let mut ctx = RecordingCtx::new(MockCtx::new());
+/// stamp2().run(&mut ctx).unwrap();
+/// let mut verifier = MockCtx::new();
+/// verifier.expect("now");
+/// verifier.expect("next_u64");
+/// let e = ctx.recording().replay(&mut verifier).unwrap_err();
+/// assert_eq!(e.index(), 0);
+/// ```
+#[derive(Debug)]
+pub struct RecordingCtx<C> {
+    inner: C,
+    calls: RefCell<Vec<Call>>,
+}
+
+impl<C> RecordingCtx<C> {
+    /// Record the operations against the context
+    pub fn new(inner: C) -> Self {
+        RecordingCtx {
+            inner,
+            calls: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Record the operation with the arguments
+    pub fn record<A>(&self, op: &str, args: A)
+    where
+        A: fmt::Debug,
+    {
+        self.calls.borrow_mut().push(Call {
+            op: op.to_string(),
+            args: format!("{:?}", args),
+        });
+    }
+
+    /// The operations recorded so far
+    pub fn recording(&self) -> Recording {
+        Recording {
+            calls: self.calls.borrow().clone(),
+        }
+    }
+
+    /// The wrapped context, e.g. to perform operations unrecorded
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// The wrapped context, e.g. to perform operations unrecorded
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Take the wrapped context out
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    // record the operation without arguments
+    fn record_bare(&self, op: &str) {
+        self.calls.borrow_mut().push(Call {
+            op: op.to_string(),
+            args: String::new(),
+        });
+    }
+}
+
+impl<C> AsMut<C> for RecordingCtx<C> {
+    fn as_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+}
+
+impl<C, Conn> HasConnection<Conn> for RecordingCtx<C>
+where
+    C: HasConnection<Conn>,
+{
+    fn connection(&mut self) -> &mut Conn {
+        self.record_bare("connection");
+        self.inner.connection()
+    }
+}
+
+impl<C> HasClock for RecordingCtx<C>
+where
+    C: HasClock,
+{
+    fn now(&self) -> SystemTime {
+        self.record_bare("now");
+        self.inner.now()
+    }
+}
+
+impl<C> HasRng for RecordingCtx<C>
+where
+    C: HasRng,
+{
+    fn next_u64(&mut self) -> u64 {
+        self.record_bare("next_u64");
+        self.inner.next_u64()
+    }
+}
+
+impl<C, K> HasLocking<K> for RecordingCtx<C>
+where
+    C: HasLocking<K>,
+    K: fmt::Debug,
+{
+    type Error = C::Error;
+
+    fn lock(&mut self, keys: &[K]) -> Result<(), C::Error> {
+        self.record("lock", keys);
+        self.inner.lock(keys)
+    }
+
+    fn is_contention(error: &C::Error) -> bool {
+        C::is_contention(error)
+    }
+}
+
+impl<C> Savepoints for RecordingCtx<C>
+where
+    C: Savepoints,
+{
+    type Error = C::Error;
+
+    fn savepoint(&mut self, name: &str) -> Result<(), C::Error> {
+        self.record("savepoint", name);
+        self.inner.savepoint(name)
+    }
+
+    fn release(&mut self, name: &str) -> Result<(), C::Error> {
+        self.record("release", name);
+        self.inner.release(name)
+    }
+
+    fn rollback_to(&mut self, name: &str) -> Result<(), C::Error> {
+        self.record("rollback_to", name);
+        self.inner.rollback_to(name)
+    }
+}
+
+/// The operations recorded by `RecordingCtx`.
+///
+/// It is written one operation per line as `op(args)`, and parsed back from
+/// it, e.g. to keep it in a golden file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Recording {
+    calls: Vec<Call>,
+}
+
+impl Recording {
+    /// The recorded operations in order
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    /// Check the operations in order by the verifier, and that it expects
+    /// no more
+    pub fn replay<V>(&self, verifier: &mut V) -> Result<(), ReplayError>
+    where
+        V: ?Sized + Verifier,
+    {
+        for (index, call) in self.calls.iter().enumerate() {
+            verifier.verify(call).map_err(|message| ReplayError { index, message })?;
+        }
+        verifier.finish().map_err(|message| ReplayError {
+            index: self.calls.len(),
+            message,
+        })
+    }
+}
+
+impl From<Vec<Call>> for Recording {
+    fn from(calls: Vec<Call>) -> Self {
+        Recording { calls }
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for call in &self.calls {
+            writeln!(f, "{}({})", call.op, call.args)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recording {
+    type Err = ParseRecordingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut calls = Vec::new();
+        for (line, text) in s.lines().enumerate() {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let call = text
+                .find('(')
+                .filter(|_| text.ends_with(')'))
+                .map(|open| Call {
+                    op: text[..open].to_string(),
+                    args: text[open + 1..text.len() - 1].to_string(),
+                });
+            match call {
+                Some(call) => calls.push(call),
+                None => return Err(ParseRecordingError { line: line + 1 }),
+            }
+        }
+        Ok(Recording { calls })
+    }
+}
+
+/// The error of parsing a `Recording`: the line is not `op(args)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRecordingError {
+    line: usize,
+}
+
+impl ParseRecordingError {
+    /// The line of the error, counted from 1
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseRecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {} is not an operation", self.line)
+    }
+}
+
+impl Error for ParseRecordingError {}
+
+/// Verifiers of the operations of a `Recording` replayed to them
+pub trait Verifier {
+    /// Check the next operation
+    fn verify(&mut self, call: &Call) -> Result<(), String>;
+
+    /// Check that no more operations are expected
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl<F> Verifier for F
+where
+    F: FnMut(&Call) -> Result<(), String>,
+{
+    fn verify(&mut self, call: &Call) -> Result<(), String> {
+        self(call)
+    }
+}
+
+/// The operations are checked against the script, ignoring the responses
+impl Verifier for MockCtx {
+    fn verify(&mut self, call: &Call) -> Result<(), String> {
+        self.check(call)
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        let unmet = self.unmet();
+        if unmet.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("unmet expectations: {:?}", unmet))
+        }
+    }
+}
+
+/// The error of replaying a `Recording`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayError {
+    index: usize,
+    message: String,
+}
+
+impl ReplayError {
+    /// The index of the operation the verifier rejected, or the number of
+    /// the operations if it expected more
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The message of the verifier
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operation {} of the recording: {}", self.index, self.message)
+    }
+}
+
+impl Error for ReplayError {}
@@ -0,0 +1,60 @@
+use transaction::Snapshots;
+
+/// Assert that running the transaction fails and leaves the context as it
+/// was, comparing the snapshots of the context taken before and after, and
+/// evaluate to the error. The context must implement `Snapshots` with a
+/// snapshot comparable and printable, e.g. `MockCtx` or the contexts of the
+/// in-memory backends.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::prelude::*;
+/// use transaction_test::{assert_ctx_unchanged, MockCtx, MockError};
+///
+/// fn charge(amount: i64) -> impl Transaction<Ctx = MockCtx, Item = (), Err = MockError> {
+///     with_ctx(move |ctx: &mut MockCtx| {
+///         if amount <= 0 {
+///             return Err(MockError::Scripted(format!("invalid amount {}", amount)));
+///         }
+///         ctx.call("charge", amount)
+///     })
+/// }
+///
+/// let mut ctx = MockCtx::new();
+/// let e = assert_ctx_unchanged!(ctx, charge(-1).run(&mut ctx));
+/// assert_eq!(e, MockError::Scripted("invalid amount -1".to_string()));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the transaction succeeds or the context changes
+#[macro_export]
+macro_rules! assert_ctx_unchanged {
+    ($ctx:expr, $run:expr) => {{
+        let before = $crate::__snapshot(&$ctx);
+        match $run {
+            Ok(_) => panic!("assertion failed: `{}` succeeded", stringify!($run)),
+            Err(e) => {
+                let after = $crate::__snapshot(&$ctx);
+                if before != after {
+                    panic!(
+                        "assertion failed: `{}` changed the context\n before: {:?}\n  after: {:?}",
+                        stringify!($run),
+                        before,
+                        after
+                    );
+                }
+                e
+            }
+        }
+    }};
+}
+
+#[doc(hidden)]
+pub fn __snapshot<C>(ctx: &C) -> C::Snapshot
+where
+    C: Snapshots,
+{
+    ctx.snapshot()
+}
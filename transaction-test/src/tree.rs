@@ -0,0 +1,278 @@
+use transaction::prelude::*;
+use transaction::{err, ok, with_ctx};
+
+/// The transactions built from a `TxTree`
+pub type TreeTx<'a, Ctx> = Box<dyn Transaction<Ctx = Ctx, Item = i32, Err = i32> + 'a>;
+
+/// A tree of combinators with known semantics, to fuzz the combinators and
+/// the contexts of the backends for panics, steps run twice or skipped, and
+/// broken laws.
+///
+/// `build` turns the tree into a transaction, taking the effectful leaves
+/// from the backend: `leaf(n)` performs the effect `n` on the context, e.g.
+/// appends it to a log kept in the store, and succeeds with `n`. `expected`
+/// tells the result of the transaction and the effects it performs in order,
+/// so running the transaction and observing the effects must agree with it.
+/// The numbers are added with wrapping.
+///
+/// With the `quickcheck` or `proptest` feature, the trees are `Arbitrary`.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::prelude::*;
+/// use transaction_test::{push_effect, Expected, TxTree};
+///
+/// // ok(1).and_then(|x| push(2).map(|y| x + y)).or_else(..) never runs the recovery
+/// let tree = TxTree::OrElse(
+///     Box::new(TxTree::AndThen(Box::new(TxTree::Ok(1)), Box::new(TxTree::Leaf(2)))),
+///     Box::new(TxTree::Leaf(3)),
+/// );
+/// let expected = tree.expected();
+/// assert_eq!(expected, Expected { result: Ok(3), effects: vec![2] });
+///
+/// let mut log = Vec::new();
+/// assert_eq!(tree.build(&push_effect).run(&mut log), expected.result);
+/// assert_eq!(log, expected.effects);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TxTree {
+    /// `ok(n)`
+    Ok(i32),
+    /// `err(n)`
+    Err(i32),
+    /// The leaf of the backend, performing the effect `n` and succeeding
+    /// with `n`
+    Leaf(i32),
+    /// Add `n` to the item by `map`
+    Map(Box<TxTree>, i32),
+    /// Add `n` to the error by `map_err`
+    MapErr(Box<TxTree>, i32),
+    /// Run the second after the first succeeds by `and_then`, adding the
+    /// items
+    AndThen(Box<TxTree>, Box<TxTree>),
+    /// Run the second after the first either way by `then`
+    Then(Box<TxTree>, Box<TxTree>),
+    /// Run the second after the first fails by `or_else`, adding the errors
+    OrElse(Box<TxTree>, Box<TxTree>),
+    /// Run both by `join`, the second even if the first fails, adding the
+    /// items or taking the first error
+    Join(Box<TxTree>, Box<TxTree>),
+    /// Turn the error into the item by `recover`
+    Recover(Box<TxTree>),
+    /// Label it by `named`, which changes nothing
+    Named(Box<TxTree>),
+}
+
+/// What running a `TxTree` results in
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Expected {
+    /// The result of the transaction
+    pub result: Result<i32, i32>,
+    /// The effects performed by the leaves, in order
+    pub effects: Vec<i32>,
+}
+
+impl TxTree {
+    /// Build the transaction of the tree with the leaves of the backend
+    pub fn build<'a, Ctx, L>(&self, leaf: &'a L) -> TreeTx<'a, Ctx>
+    where
+        Ctx: 'a,
+        L: Fn(i32) -> TreeTx<'a, Ctx>,
+    {
+        match *self {
+            TxTree::Ok(n) => ok(n).boxed(),
+            TxTree::Err(n) => err(n).boxed(),
+            TxTree::Leaf(n) => leaf(n),
+            TxTree::Map(ref t, n) => t.build(leaf).map(move |x| x.wrapping_add(n)).boxed(),
+            TxTree::MapErr(ref t, n) => t.build(leaf).map_err(move |e| e.wrapping_add(n)).boxed(),
+            TxTree::AndThen(ref a, ref b) => {
+                let b = (**b).clone();
+                a.build(leaf)
+                    .and_then(move |x| b.build(leaf).map(move |y| x.wrapping_add(y)))
+                    .boxed()
+            }
+            TxTree::Then(ref a, ref b) => {
+                let b = (**b).clone();
+                a.build(leaf).then(move |_| b.build(leaf)).boxed()
+            }
+            TxTree::OrElse(ref a, ref b) => {
+                let b = (**b).clone();
+                a.build(leaf)
+                    .or_else(move |e| b.build(leaf).map_err(move |e2| e.wrapping_add(e2)))
+                    .boxed()
+            }
+            TxTree::Join(ref a, ref b) => a
+                .build(leaf)
+                .join(b.build(leaf))
+                .map(|(x, y)| x.wrapping_add(y))
+                .boxed(),
+            TxTree::Recover(ref t) => t.build(leaf).recover(|e| e).boxed(),
+            TxTree::Named(ref t) => t.build(leaf).named("tree").boxed(),
+        }
+    }
+
+    /// The result of the transaction of the tree and the effects it performs
+    pub fn expected(&self) -> Expected {
+        let mut effects = Vec::new();
+        let result = self.eval(&mut effects);
+        Expected { result, effects }
+    }
+
+    fn eval(&self, effects: &mut Vec<i32>) -> Result<i32, i32> {
+        match *self {
+            TxTree::Ok(n) => Ok(n),
+            TxTree::Err(n) => Err(n),
+            TxTree::Leaf(n) => {
+                effects.push(n);
+                Ok(n)
+            }
+            TxTree::Map(ref t, n) => t.eval(effects).map(|x| x.wrapping_add(n)),
+            TxTree::MapErr(ref t, n) => t.eval(effects).map_err(|e| e.wrapping_add(n)),
+            TxTree::AndThen(ref a, ref b) => {
+                let x = a.eval(effects)?;
+                b.eval(effects).map(|y| x.wrapping_add(y))
+            }
+            TxTree::Then(ref a, ref b) => {
+                let _ = a.eval(effects);
+                b.eval(effects)
+            }
+            TxTree::OrElse(ref a, ref b) => match a.eval(effects) {
+                Ok(x) => Ok(x),
+                Err(e) => b.eval(effects).map_err(|e2| e.wrapping_add(e2)),
+            },
+            TxTree::Join(ref a, ref b) => match (a.eval(effects), b.eval(effects)) {
+                (Ok(x), Ok(y)) => Ok(x.wrapping_add(y)),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            },
+            TxTree::Recover(ref t) => Ok(t.eval(effects).unwrap_or_else(|e| e)),
+            TxTree::Named(ref t) => t.eval(effects),
+        }
+    }
+
+    // the subtrees, to shrink to
+    #[cfg(feature = "quickcheck")]
+    fn children(&self) -> Vec<TxTree> {
+        match *self {
+            TxTree::Ok(_) | TxTree::Err(_) | TxTree::Leaf(_) => Vec::new(),
+            TxTree::Map(ref t, _) | TxTree::MapErr(ref t, _) | TxTree::Recover(ref t) | TxTree::Named(ref t) => {
+                vec![(**t).clone()]
+            }
+            TxTree::AndThen(ref a, ref b)
+            | TxTree::Then(ref a, ref b)
+            | TxTree::OrElse(ref a, ref b)
+            | TxTree::Join(ref a, ref b) => vec![(**a).clone(), (**b).clone()],
+        }
+    }
+}
+
+/// The leaf of a `TxTree` on a log: push `n` to it
+pub fn push_effect(n: i32) -> TreeTx<'static, Vec<i32>> {
+    with_ctx(move |log: &mut Vec<i32>| {
+        log.push(n);
+        Ok(n)
+    })
+    .boxed()
+}
+
+/// The trees up to 5 combinators deep
+///
+/// # Examples
+///
+/// ```
+/// use quickcheck::QuickCheck;
+/// use transaction::prelude::*;
+/// use transaction_test::{push_effect, TxTree};
+///
+/// fn agrees(tree: TxTree) -> bool {
+///     let expected = tree.expected();
+///     let mut log = Vec::new();
+///     tree.build(&push_effect).run(&mut log) == expected.result && log == expected.effects
+/// }
+///
+/// QuickCheck::new().quickcheck(agrees as fn(TxTree) -> bool);
+/// ```
+#[cfg(feature = "quickcheck")]
+impl quickcheck::Arbitrary for TxTree {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        arbitrary_tree(g, 5)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match *self {
+            TxTree::Ok(n) => Box::new(n.shrink().map(TxTree::Ok)),
+            TxTree::Err(n) => Box::new(n.shrink().map(TxTree::Err)),
+            TxTree::Leaf(n) => Box::new(n.shrink().map(TxTree::Leaf)),
+            _ => Box::new(self.children().into_iter()),
+        }
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+fn arbitrary_tree(g: &mut quickcheck::Gen, depth: usize) -> TxTree {
+    use quickcheck::Arbitrary;
+
+    let node = if depth == 0 { 0 } else { u8::arbitrary(g) % 11 };
+    let child = |g: &mut quickcheck::Gen| Box::new(arbitrary_tree(g, depth - 1));
+    match node {
+        0..=2 => match u8::arbitrary(g) % 3 {
+            0 => TxTree::Ok(i32::arbitrary(g)),
+            1 => TxTree::Err(i32::arbitrary(g)),
+            _ => TxTree::Leaf(i32::arbitrary(g)),
+        },
+        3 => TxTree::Map(child(g), i32::arbitrary(g)),
+        4 => TxTree::MapErr(child(g), i32::arbitrary(g)),
+        5 => TxTree::AndThen(child(g), child(g)),
+        6 => TxTree::Then(child(g), child(g)),
+        7 => TxTree::OrElse(child(g), child(g)),
+        8 => TxTree::Join(child(g), child(g)),
+        9 => TxTree::Recover(child(g)),
+        _ => TxTree::Named(child(g)),
+    }
+}
+
+/// The trees up to 5 combinators deep
+///
+/// # Examples
+///
+/// ```
+/// use proptest::prelude::*;
+/// use transaction::prelude::*;
+/// use transaction_test::{push_effect, TxTree};
+///
+/// proptest!(|(tree: TxTree)| {
+///     let expected = tree.expected();
+///     let mut log = Vec::new();
+///     prop_assert_eq!(tree.build(&push_effect).run(&mut log), expected.result);
+///     prop_assert_eq!(log, expected.effects);
+/// });
+/// ```
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for TxTree {
+    type Parameters = ();
+    type Strategy = proptest::strategy::BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        use proptest::prelude::*;
+
+        let leaf = prop_oneof![
+            any::<i32>().prop_map(TxTree::Ok),
+            any::<i32>().prop_map(TxTree::Err),
+            any::<i32>().prop_map(TxTree::Leaf),
+        ];
+        leaf.prop_recursive(5, 64, 2, |inner| {
+            let pair = (inner.clone(), inner.clone());
+            prop_oneof![
+                (inner.clone(), any::<i32>()).prop_map(|(t, n)| TxTree::Map(Box::new(t), n)),
+                (inner.clone(), any::<i32>()).prop_map(|(t, n)| TxTree::MapErr(Box::new(t), n)),
+                pair.clone().prop_map(|(a, b)| TxTree::AndThen(Box::new(a), Box::new(b))),
+                pair.clone().prop_map(|(a, b)| TxTree::Then(Box::new(a), Box::new(b))),
+                pair.clone().prop_map(|(a, b)| TxTree::OrElse(Box::new(a), Box::new(b))),
+                pair.prop_map(|(a, b)| TxTree::Join(Box::new(a), Box::new(b))),
+                inner.clone().prop_map(|t| TxTree::Recover(Box::new(t))),
+                inner.prop_map(|t| TxTree::Named(Box::new(t))),
+            ]
+        })
+        .boxed()
+    }
+}
@@ -0,0 +1,95 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of TiKV
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The transaction conflicted with another one, at commit for optimistic
+    /// transactions or when locking for pessimistic ones
+    Conflict,
+    /// The pessimistic transaction was chosen as a deadlock victim
+    Deadlock,
+    /// The commit may or may not have been applied
+    Undetermined,
+    /// Any other error
+    Other,
+}
+
+/// An error of TiKV together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: tikv_client::Error,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of TiKV
+    pub fn get_ref(&self) -> &tikv_client::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of TiKV
+    pub fn into_inner(self) -> tikv_client::Error {
+        self.inner
+    }
+}
+
+fn classify(e: &tikv_client::Error) -> ErrorKind {
+    match *e {
+        tikv_client::Error::KeyError(ref e) => {
+            if e.deadlock.is_some() {
+                ErrorKind::Deadlock
+            } else if e.conflict.is_some() || !e.retryable.is_empty() {
+                ErrorKind::Conflict
+            } else {
+                ErrorKind::Other
+            }
+        }
+        tikv_client::Error::PessimisticLockError { ref inner, .. } => classify(inner),
+        // the first classified error of the keys tells the kind
+        tikv_client::Error::MultipleKeyErrors(ref errors) | tikv_client::Error::ExtractedErrors(ref errors) => errors
+            .iter()
+            .map(classify)
+            .find(|kind| *kind != ErrorKind::Other)
+            .unwrap_or(ErrorKind::Other),
+        tikv_client::Error::UndeterminedError(_) => ErrorKind::Undetermined,
+        _ => ErrorKind::Other,
+    }
+}
+
+impl From<tikv_client::Error> for Error {
+    fn from(inner: tikv_client::Error) -> Self {
+        Error {
+            kind: classify(&inner),
+            inner,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        match self.kind {
+            ErrorKind::Conflict | ErrorKind::Deadlock => true,
+            ErrorKind::Undetermined | ErrorKind::Other => false,
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Transaction backend for TiKV
+//!
+//! Asynchronous transactions run in a `TikvContext`, a transaction of TiKV
+//! begun when acquired. The mode of the transactions is a type parameter of
+//! the context, `Optimistic` or `Pessimistic`:
+//!
+//! * optimistic transactions take no locks until the commit, and fail with a
+//!   write conflict at commit if another transaction wrote the same keys.
+//! * pessimistic transactions lock the keys they read for update or lock
+//!   explicitly, waiting for the other transactions holding them, and may
+//!   fail as the victim of a deadlock.
+//!
+//! The [tokio runner](transaction_tokio::Runner) commits the transaction by
+//! the two-phase commit of TiKV when it succeeds and rolls it back otherwise.
+//! Conflicts and deadlocks are `Retryable`, so `Runner::run_async_retry` runs
+//! the whole transaction again on a new transaction.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use tikv_client::TransactionClient;
+//! use transaction::async_tx::{AsyncTransaction, AsyncTransactionExt};
+//! use transaction::Backoff;
+//! use transaction_tikv::{get_for_update, put, Pessimistic, TikvClient, TikvRunner};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), transaction_tikv::Error> {
+//!     let client = TransactionClient::new(vec!["127.0.0.1:2379"]).await?;
+//!     let runner: TikvRunner<Pessimistic> = TikvRunner::new(TikvClient::new(client));
+//!
+//!     let incr = get_for_update("hits".to_owned())
+//!         .map(|v| v.map_or(0, |v| String::from_utf8(v).unwrap().parse::<u64>().unwrap()))
+//!         .and_then(|n| put("hits".to_owned(), (n + 1).to_string()).map(move |()| n + 1));
+//!     let policy = Backoff::exponential(Duration::from_millis(10)).max_retries(5);
+//!     let hits = runner.run_async_retry(incr, policy).await?;
+//!     println!("{} hits", hits);
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+use tikv_client::{CheckLevel, Transaction, TransactionClient, TransactionOptions};
+use transaction::hooks::Outcome;
+use transaction_tokio::{AsyncPool, Runner, TestRunner};
+
+mod error;
+mod ops;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+/// The mode of the transactions of TiKV, `Optimistic` or `Pessimistic`
+pub trait Mode: 'static {
+    /// The options to begin a transaction in this mode
+    fn options() -> TransactionOptions;
+}
+
+/// The optimistic mode: the conflicts are detected at commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Optimistic;
+
+impl Mode for Optimistic {
+    fn options() -> TransactionOptions {
+        TransactionOptions::new_optimistic()
+    }
+}
+
+/// The pessimistic mode: the keys are locked when read for update
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Pessimistic;
+
+impl Mode for Pessimistic {
+    fn options() -> TransactionOptions {
+        TransactionOptions::new_pessimistic()
+    }
+}
+
+/// The context of the transactions: a transaction of TiKV in the mode `M`.
+pub struct TikvContext<M> {
+    txn: Transaction,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<M> TikvContext<M> {
+    // never pub this function
+    fn new(txn: Transaction) -> Self {
+        TikvContext {
+            txn,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The transaction of TiKV, to pass to the operations not covered by
+    /// the leaves of this crate
+    pub fn transaction_mut(&mut self) -> &mut Transaction {
+        &mut self.txn
+    }
+}
+
+impl<M> fmt::Debug for TikvContext<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TikvContext").finish_non_exhaustive()
+    }
+}
+
+/// An `AsyncPool` of the transactions of a client of TiKV in the mode `M`.
+/// Each transaction begins on acquire, so they run concurrently.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tikv_client::TransactionClient;
+/// use transaction_tikv::{Optimistic, TikvClient};
+///
+/// # async fn f() -> tikv_client::Result<()> {
+/// let client = TransactionClient::new(vec!["127.0.0.1:2379"]).await?;
+/// let client = TikvClient::<Optimistic>::new(client).async_commit(true).try_one_pc(true);
+/// # Ok(())
+/// # }
+/// ```
+pub struct TikvClient<M> {
+    client: Arc<TransactionClient>,
+    async_commit: bool,
+    try_one_pc: bool,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<M> TikvClient<M> {
+    /// Run the transactions on the client, committing them by the plain
+    /// two-phase commit
+    pub fn new(client: TransactionClient) -> Self {
+        TikvClient {
+            client: Arc::new(client),
+            async_commit: false,
+            try_one_pc: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Commit the secondary keys asynchronously, returning once the keys are
+    /// prewritten
+    pub fn async_commit(self, async_commit: bool) -> Self {
+        TikvClient { async_commit, ..self }
+    }
+
+    /// Commit in one phase when all the keys are in a region
+    pub fn try_one_pc(self, try_one_pc: bool) -> Self {
+        TikvClient { try_one_pc, ..self }
+    }
+
+    /// The client
+    pub fn client(&self) -> &TransactionClient {
+        &self.client
+    }
+}
+
+impl<M: Mode> TikvClient<M> {
+    fn options(&self) -> TransactionOptions {
+        // the runner always ends the transactions, except when the future is
+        // dropped halfway, which must not panic
+        let mut options = M::options().drop_check(CheckLevel::Warn);
+        if self.async_commit {
+            options = options.use_async_commit();
+        }
+        if self.try_one_pc {
+            options = options.try_one_pc();
+        }
+        options
+    }
+}
+
+impl<M> Clone for TikvClient<M> {
+    fn clone(&self) -> Self {
+        TikvClient {
+            client: self.client.clone(),
+            async_commit: self.async_commit,
+            try_one_pc: self.try_one_pc,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<M> fmt::Debug for TikvClient<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TikvClient")
+            .field("async_commit", &self.async_commit)
+            .field("try_one_pc", &self.try_one_pc)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M: Mode> AsyncPool for TikvClient<M> {
+    type Ctx = TikvContext<M>;
+    type Error = Error;
+
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        async move {
+            let txn = self.client.begin_with_options(self.options()).await?;
+            Ok(TikvContext::new(txn))
+        }.boxed()
+    }
+
+    fn release(&self, mut ctx: Self::Ctx, outcome: Outcome) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move {
+            match outcome {
+                Outcome::Committed => match ctx.txn.commit().await {
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        let e = Error::from(e);
+                        // clean up the prewritten locks, unless the primary
+                        // key may have been committed
+                        if e.kind() != ErrorKind::Undetermined {
+                            let _ = ctx.txn.rollback().await;
+                        }
+                        Err(e)
+                    }
+                },
+                Outcome::RolledBack => Ok(ctx.txn.rollback().await?),
+            }
+        }.boxed()
+    }
+}
+
+/// A `Runner` of transactions on a client of TiKV in the mode `M`
+///
+/// If a transaction panics, its `Transaction` of TiKV is rolled back, so its
+/// locks are released, and the panic is resumed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::panic::AssertUnwindSafe;
+///
+/// use futures::FutureExt;
+/// use tikv_client::TransactionClient;
+/// use transaction::async_tx::AsyncTransactionExt;
+/// use transaction_tikv::{get, put, Optimistic, TikvClient, TikvRunner};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), transaction_tikv::Error> {
+///     let client = TransactionClient::new(vec!["127.0.0.1:2379"]).await?;
+///     let runner: TikvRunner<Optimistic> = TikvRunner::new(TikvClient::new(client));
+///
+///     let boom = put("greeting".to_owned(), "hello").map(|()| -> () { panic!("boom") });
+///     assert!(AssertUnwindSafe(runner.run_async(boom)).catch_unwind().await.is_err());
+///     assert_eq!(runner.run_async(get("greeting".to_owned())).await?, None);
+///     Ok(())
+/// }
+/// ```
+pub type TikvRunner<M> = Runner<TikvClient<M>>;
+
+/// A `TestRunner` of transactions on a client of TiKV in the mode `M`,
+/// rolling back every transaction
+pub type TikvTestRunner<M> = TestRunner<TikvClient<M>>;
@@ -0,0 +1,188 @@
+use std::marker::PhantomData;
+
+use tikv_client::{Key, Value};
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::{Error, Mode, TikvContext};
+
+/// Get the value of the key at the start timestamp of the transaction.
+pub fn get<M, K>(key: K) -> Get<M>
+where
+    K: Into<Key>,
+{
+    Get {
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get`
+#[derive(Debug)]
+#[must_use]
+pub struct Get<M> {
+    key: Key,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<M: Mode> AsyncTransaction for Get<M> {
+    type Ctx = TikvContext<M>;
+    type Item = Option<Value>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(ctx.transaction_mut().get(self.key.clone()).await?) })
+    }
+}
+
+impl<M> Visit for Get<M> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get"));
+    }
+}
+
+/// Get the value of the key for an update. Pessimistic transactions lock
+/// the key until the end, and optimistic transactions check at commit that
+/// it was not written after the start timestamp.
+pub fn get_for_update<M, K>(key: K) -> GetForUpdate<M>
+where
+    K: Into<Key>,
+{
+    GetForUpdate {
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `get_for_update`
+#[derive(Debug)]
+#[must_use]
+pub struct GetForUpdate<M> {
+    key: Key,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<M: Mode> AsyncTransaction for GetForUpdate<M> {
+    type Ctx = TikvContext<M>;
+    type Item = Option<Value>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(ctx.transaction_mut().get_for_update(self.key.clone()).await?) })
+    }
+}
+
+impl<M> Visit for GetForUpdate<M> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get_for_update"));
+    }
+}
+
+/// Set the value of the key. The write is buffered until the commit.
+pub fn put<M, K, V>(key: K, value: V) -> Put<M>
+where
+    K: Into<Key>,
+    V: Into<Value>,
+{
+    Put {
+        key: key.into(),
+        value: value.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `put`
+#[derive(Debug)]
+#[must_use]
+pub struct Put<M> {
+    key: Key,
+    value: Value,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<M: Mode> AsyncTransaction for Put<M> {
+    type Ctx = TikvContext<M>;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(ctx.transaction_mut().put(self.key.clone(), self.value.clone()).await?) })
+    }
+}
+
+impl<M> Visit for Put<M> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("put"));
+    }
+}
+
+/// Delete the key. The write is buffered until the commit.
+pub fn delete<M, K>(key: K) -> Delete<M>
+where
+    K: Into<Key>,
+{
+    Delete {
+        key: key.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `delete`
+#[derive(Debug)]
+#[must_use]
+pub struct Delete<M> {
+    key: Key,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<M: Mode> AsyncTransaction for Delete<M> {
+    type Ctx = TikvContext<M>;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(ctx.transaction_mut().delete(self.key.clone()).await?) })
+    }
+}
+
+impl<M> Visit for Delete<M> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete"));
+    }
+}
+
+/// Lock the keys without reading them, as `get_for_update` does.
+pub fn lock_keys<M, I>(keys: I) -> LockKeys<M>
+where
+    I: IntoIterator,
+    I::Item: Into<Key>,
+{
+    LockKeys {
+        keys: keys.into_iter().map(Into::into).collect(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `lock_keys`
+#[derive(Debug)]
+#[must_use]
+pub struct LockKeys<M> {
+    keys: Vec<Key>,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<M: Mode> AsyncTransaction for LockKeys<M> {
+    type Ctx = TikvContext<M>;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { Ok(ctx.transaction_mut().lock_keys(self.keys.clone()).await?) })
+    }
+}
+
+impl<M> Visit for LockKeys<M> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("lock_keys"));
+    }
+}
@@ -0,0 +1,78 @@
+use std::error;
+use std::fmt;
+
+use tokio_postgres::error::SqlState;
+use transaction::Retryable;
+
+/// The classification of the errors of tokio-postgres by their SQLSTATE
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// `serialization_failure` (40001)
+    SerializationFailure,
+    /// `deadlock_detected` (40P01)
+    Deadlock,
+    /// `lock_not_available` (55P03)
+    LockTimeout,
+    /// `query_canceled` (57014), e.g. by the `statement_timeout` of the
+    /// deadline of the run
+    StatementTimeout,
+    /// Any other error
+    Other,
+}
+
+/// An error of tokio-postgres together with its classification
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: tokio_postgres::Error,
+}
+
+impl Error {
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The error of tokio-postgres
+    pub fn get_ref(&self) -> &tokio_postgres::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of tokio-postgres
+    pub fn into_inner(self) -> tokio_postgres::Error {
+        self.inner
+    }
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(inner: tokio_postgres::Error) -> Self {
+        let kind = match inner.code() {
+            Some(code) if *code == SqlState::T_R_SERIALIZATION_FAILURE => ErrorKind::SerializationFailure,
+            Some(code) if *code == SqlState::T_R_DEADLOCK_DETECTED => ErrorKind::Deadlock,
+            Some(code) if *code == SqlState::LOCK_NOT_AVAILABLE => ErrorKind::LockTimeout,
+            Some(code) if *code == SqlState::QUERY_CANCELED => ErrorKind::StatementTimeout,
+            _ => ErrorKind::Other,
+        };
+        Error { kind, inner }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+// the deadline of a transaction timed out is not any further when it runs
+// again
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        !matches!(self.kind, ErrorKind::StatementTimeout | ErrorKind::Other)
+    }
+}
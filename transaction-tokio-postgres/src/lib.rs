@@ -0,0 +1,308 @@
+//! Transaction backend for tokio-postgres
+//!
+//! Asynchronous transactions run in a `PgContext` on which `BEGIN` is issued
+//! when acquired. The [tokio runner](transaction_tokio::Runner) issues
+//! `COMMIT` when the transaction succeeds and `ROLLBACK` otherwise. The
+//! statements of the transactions run with a deadline, by `run_async_with`,
+//! are limited to the time remaining by `SET LOCAL statement_timeout`, so
+//! the server stops working on them too, and fail with
+//! `ErrorKind::StatementTimeout`.
+//!
+//! `query`, `query_one` and `execute` expose the statements of
+//! tokio-postgres as leaves. The independent statements joined by `pipeline`,
+//! or by `join` and `join_all` in a transaction run by `pipelined`, are sent
+//! to the server at once instead of one round trip each.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use transaction::async_tx::{AsyncTransaction, AsyncTransactionExt};
+//! use transaction_tokio_postgres::{execute, pipeline, query_one, PgClient, PgRunner};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), transaction_tokio_postgres::Error> {
+//!     let (client, connection) =
+//!         tokio_postgres::connect("host=localhost user=postgres", tokio_postgres::NoTls).await?;
+//!     tokio::spawn(connection);
+//!     let runner = PgRunner::new(PgClient::new(client));
+//!
+//!     let tx = execute("UPDATE users SET name = $1 WHERE id = $2", vec![Box::new("alice"), Box::new(1)])
+//!         .and_then(|_| {
+//!             pipeline(
+//!                 query_one("SELECT name FROM users WHERE id = $1", vec![Box::new(1)]),
+//!                 query_one("SELECT COUNT(*) FROM users", vec![]),
+//!             )
+//!         })
+//!         .map(|(user, count)| (user.get::<_, String>(0), count.get::<_, i64>(0)));
+//!     let (name, count) = runner.run_async(tx).await?;
+//!     println!("{} of {}", name, count);
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+use transaction::clock::Instant;
+use transaction::hooks::Outcome;
+use transaction::{IsolationLevel, TransactionMode};
+use transaction_tokio::{AsyncConnection, AsyncPool, Runner, TestRunner};
+
+mod error;
+mod pipeline;
+mod statement;
+
+pub use crate::error::*;
+pub use crate::pipeline::*;
+pub use crate::statement::*;
+
+trait ClientHandle: Send + Sync {
+    fn client(&self) -> &Client;
+}
+
+impl<C> ClientHandle for C
+where
+    C: Deref<Target = Client> + Send + Sync,
+{
+    fn client(&self) -> &Client {
+        self
+    }
+}
+
+/// The context of the transactions: a client of tokio-postgres in a
+/// transaction.
+///
+/// It implements `AsyncConnection`, so pools of contexts can be run by the
+/// `PooledRunner` of transaction-tokio.
+pub struct PgContext {
+    client: Box<dyn ClientHandle>,
+}
+
+impl PgContext {
+    /// Wrap a handle of a client, e.g. a guard of a lock or a connection of a
+    /// pool. No transaction is begun yet.
+    pub fn new<C>(client: C) -> Self
+    where
+        C: Deref<Target = Client> + Send + Sync + 'static,
+    {
+        PgContext { client: Box::new(client) }
+    }
+
+    /// The client
+    pub fn client(&self) -> &Client {
+        self.client.client()
+    }
+}
+
+impl fmt::Debug for PgContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PgContext").finish_non_exhaustive()
+    }
+}
+
+impl AsyncConnection for PgContext {
+    type Error = Error;
+
+    fn begin(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move { Ok(self.client().batch_execute("BEGIN").await?) }.boxed()
+    }
+
+    fn begin_until(&mut self, deadline: Option<Instant>) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move { Ok(self.client().batch_execute(&begin_until("BEGIN", deadline)).await?) }.boxed()
+    }
+
+    fn commit(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move { Ok(self.client().batch_execute("COMMIT").await?) }.boxed()
+    }
+
+    fn rollback(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move { Ok(self.client().batch_execute("ROLLBACK").await?) }.boxed()
+    }
+}
+
+// the statements beginning a transaction to be done by the deadline, sent
+// at once. A `statement_timeout` of 0 disables it, so at least 1ms is given.
+fn begin_until(begin: &str, deadline: Option<Instant>) -> String {
+    match deadline {
+        Some(deadline) => {
+            let timeout = deadline.saturating_duration_since(Instant::now()).as_millis().max(1);
+            format!("{}; SET LOCAL statement_timeout = {}", begin, timeout)
+        }
+        None => begin.to_string(),
+    }
+}
+
+/// An `AsyncPool` of a single client. The transactions run on it one at a
+/// time.
+///
+/// Cloning it shares the client, so the same client can be used by runners
+/// with different characteristics.
+#[derive(Debug, Clone)]
+pub struct PgClient {
+    client: Arc<Mutex<Client>>,
+    begin: String,
+}
+
+impl PgClient {
+    /// Run the transactions on the client
+    pub fn new(client: Client) -> Self {
+        PgClient {
+            client: Arc::new(Mutex::new(client)),
+            begin: "BEGIN".to_string(),
+        }
+    }
+
+    /// Begin the transactions with the characteristics
+    pub fn mode(self, mode: TransactionMode) -> Self {
+        let begin = match mode.characteristics() {
+            Some(characteristics) => format!("BEGIN {}", characteristics),
+            None => "BEGIN".to_string(),
+        };
+        PgClient { begin, ..self }
+    }
+}
+
+impl AsyncPool for PgClient {
+    type Ctx = PgContext;
+    type Error = Error;
+
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        self.acquire_until(None)
+    }
+
+    fn acquire_until(&self, deadline: Option<Instant>) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        async move {
+            let ctx = PgContext::new(self.client.clone().lock_owned().await);
+            ctx.client().batch_execute(&begin_until(&self.begin, deadline)).await?;
+            Ok(ctx)
+        }.boxed()
+    }
+
+    fn release(&self, mut ctx: Self::Ctx, outcome: Outcome) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move {
+            match outcome {
+                Outcome::Committed => ctx.commit().await,
+                Outcome::RolledBack => ctx.rollback().await,
+            }
+        }.boxed()
+    }
+}
+
+/// A `Runner` of transactions on a client of tokio-postgres
+///
+/// If a transaction panics, `ROLLBACK` is issued on its client, which is
+/// released for the next transaction, and the panic is resumed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::panic::AssertUnwindSafe;
+///
+/// use futures::FutureExt;
+/// use transaction::async_tx::AsyncTransactionExt;
+/// use transaction_tokio_postgres::{execute, query_one, Error, PgClient, PgRunner};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), Error> {
+///     let (client, connection) =
+///         tokio_postgres::connect("host=localhost user=postgres", tokio_postgres::NoTls).await?;
+///     tokio::spawn(connection);
+///     client.batch_execute("CREATE TEMPORARY TABLE users (name TEXT)").await?;
+///     let runner = PgRunner::new(PgClient::new(client));
+///
+///     let boom = execute("INSERT INTO users VALUES ($1)", vec![Box::new("alice")]).map(|_| -> () { panic!("boom") });
+///     assert!(AssertUnwindSafe(runner.run_async(boom)).catch_unwind().await.is_err());
+///     let users = runner.run_async(query_one("SELECT count(*) FROM users", vec![])).await?;
+///     assert_eq!(users.get::<_, i64>(0), 0);
+///     Ok(())
+/// }
+/// ```
+pub type PgRunner = Runner<PgClient>;
+
+/// A `TestRunner` of transactions on a client of tokio-postgres, rolling
+/// back every transaction
+pub type PgTestRunner = TestRunner<PgClient>;
+
+/// Builder of a `PgRunner` issuing the isolation level and the access mode
+/// of the transactions at `BEGIN`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use transaction::IsolationLevel;
+/// use transaction_tokio_postgres::{query, PgClient, RunnerBuilder};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), transaction_tokio_postgres::Error> {
+///     let (client, connection) =
+///         tokio_postgres::connect("host=localhost user=postgres", tokio_postgres::NoTls).await?;
+///     tokio::spawn(connection);
+///     let client = PgClient::new(client);
+///     let serializable = RunnerBuilder::new(client.clone())
+///         .isolation(IsolationLevel::Serializable)
+///         .build();
+///     let report = RunnerBuilder::new(client)
+///         .isolation(IsolationLevel::Serializable)
+///         .read_only()
+///         .deferrable()
+///         .build();
+///
+///     let tx = query("SELECT * FROM users", vec![]);
+///     let users = serializable.run_async(&tx).await?;
+///     assert_eq!(report.run_async(&tx).await?.len(), users.len());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RunnerBuilder {
+    client: PgClient,
+    mode: TransactionMode,
+}
+
+impl RunnerBuilder {
+    /// Run the transactions on the client with the database defaults
+    pub fn new(client: PgClient) -> Self {
+        RunnerBuilder {
+            client,
+            mode: TransactionMode::new(),
+        }
+    }
+
+    /// Set the isolation level
+    pub fn isolation(self, level: IsolationLevel) -> Self {
+        RunnerBuilder {
+            mode: self.mode.isolation(level),
+            ..self
+        }
+    }
+
+    /// Make the transactions read-only
+    pub fn read_only(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.read_only(),
+            ..self
+        }
+    }
+
+    /// Make the transactions deferrable
+    pub fn deferrable(self) -> Self {
+        RunnerBuilder {
+            mode: self.mode.deferrable(),
+            ..self
+        }
+    }
+
+    /// Replace all the characteristics
+    pub fn mode(self, mode: TransactionMode) -> Self {
+        RunnerBuilder { mode, ..self }
+    }
+
+    /// Build the runner
+    pub fn build(self) -> PgRunner {
+        Runner::new(self.client.mode(self.mode))
+    }
+}
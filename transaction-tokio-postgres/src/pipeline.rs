@@ -0,0 +1,228 @@
+use futures::future;
+use tokio_postgres::Client;
+use transaction::async_tx::{AsyncRun, AsyncTransaction, Join, JoinAll};
+use transaction::{visit_node, Node, Visit, Visitor};
+
+use crate::{Error, PgContext};
+
+/// The statements which only need shared access to the client, so that
+/// tokio-postgres can pipeline them with the other statements polled at the
+/// same time.
+pub trait Pipelinable: AsyncTransaction<Ctx = PgContext, Err = Error> {
+    /// Run the statement on the client
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error>;
+}
+
+/// Run the two independent statements at once. Both are sent to the server
+/// without waiting for the response of the other, saving a round trip.
+///
+/// Both statements are run even if one fails.
+pub fn pipeline<A, B>(a: A, b: B) -> Pipeline<A, B>
+where
+    A: Pipelinable,
+    B: Pipelinable,
+{
+    Pipeline { a, b }
+}
+
+/// The result of `pipeline`
+#[derive(Debug)]
+#[must_use]
+pub struct Pipeline<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> AsyncTransaction for Pipeline<A, B>
+where
+    A: Pipelinable,
+    B: Pipelinable,
+{
+    type Ctx = PgContext;
+    type Item = (A::Item, B::Item);
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        self.run_on(ctx.client())
+    }
+}
+
+impl<A, B> Pipelinable for Pipeline<A, B>
+where
+    A: Pipelinable,
+    B: Pipelinable,
+{
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error> {
+        Box::pin(async move {
+            let (a, b) = future::join(self.a.run_on(client), self.b.run_on(client)).await;
+            Ok((a?, b?))
+        })
+    }
+}
+
+impl<A, B> Visit for Pipeline<A, B>
+where
+    A: Visit,
+    B: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("pipeline"), |v| {
+            self.a.accept(v);
+            self.b.accept(v);
+        });
+    }
+}
+
+/// Run the independent statements at once and collect the results in order.
+pub fn pipeline_all<I>(statements: I) -> PipelineAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Pipelinable,
+{
+    PipelineAll { vec: statements.into_iter().collect() }
+}
+
+/// The result of `pipeline_all`
+#[derive(Debug)]
+#[must_use]
+pub struct PipelineAll<Tx> {
+    vec: Vec<Tx>,
+}
+
+impl<Tx> AsyncTransaction for PipelineAll<Tx>
+where
+    Tx: Pipelinable,
+{
+    type Ctx = PgContext;
+    type Item = Vec<Tx::Item>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        self.run_on(ctx.client())
+    }
+}
+
+impl<Tx> Pipelinable for PipelineAll<Tx>
+where
+    Tx: Pipelinable,
+{
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error> {
+        Box::pin(async move {
+            future::join_all(self.vec.iter().map(|tx| tx.run_on(client)))
+                .await
+                .into_iter()
+                .collect()
+        })
+    }
+}
+
+impl<Tx> Visit for PipelineAll<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("pipeline_all"), |v| for tx in &self.vec {
+            tx.accept(v);
+        });
+    }
+}
+
+/// Run the statements joined by `join` and `join_all` in the transaction at
+/// once like `pipeline`, instead of one round trip each.
+///
+/// All the joined statements are run even if one fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use transaction::async_tx::{join_all, AsyncTransaction, AsyncTransactionExt};
+/// use transaction_tokio_postgres::{pipelined, query_one, PgClient, PgRunner};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<(), transaction_tokio_postgres::Error> {
+///     let (client, connection) =
+///         tokio_postgres::connect("host=localhost user=postgres", tokio_postgres::NoTls).await?;
+///     tokio::spawn(connection);
+///     let runner = PgRunner::new(PgClient::new(client));
+///
+///     let user = |id: i32| query_one("SELECT name FROM users WHERE id = $1", vec![Box::new(id)]);
+///     let count = query_one("SELECT COUNT(*) FROM users", vec![]);
+///     // one round trip instead of four
+///     let tx = pipelined(join_all(vec![user(1), user(2), user(3)]).join(count));
+///     let (users, count) = runner.run_async(tx).await?;
+///     println!("{} of {}", users.len(), count.get::<_, i64>(0));
+///     Ok(())
+/// }
+/// ```
+pub fn pipelined<Tx>(tx: Tx) -> Pipelined<Tx>
+where
+    Tx: Pipelinable,
+{
+    Pipelined { tx }
+}
+
+/// The result of `pipelined`
+#[derive(Debug)]
+#[must_use]
+pub struct Pipelined<Tx> {
+    tx: Tx,
+}
+
+impl<Tx> AsyncTransaction for Pipelined<Tx>
+where
+    Tx: Pipelinable,
+{
+    type Ctx = PgContext;
+    type Item = Tx::Item;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        self.tx.run_on(ctx.client())
+    }
+}
+
+impl<Tx> Pipelinable for Pipelined<Tx>
+where
+    Tx: Pipelinable,
+{
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error> {
+        self.tx.run_on(client)
+    }
+}
+
+impl<Tx> Visit for Pipelined<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("pipelined"), |v| self.tx.accept(v));
+    }
+}
+
+impl<A, B> Pipelinable for Join<A, B>
+where
+    A: Pipelinable,
+    B: Pipelinable,
+{
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error> {
+        let (a, b) = self.parts();
+        Box::pin(async move {
+            let (a, b) = future::join(a.run_on(client), b.run_on(client)).await;
+            Ok((a?, b?))
+        })
+    }
+}
+
+impl<Tx> Pipelinable for JoinAll<Tx>
+where
+    Tx: Pipelinable,
+{
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error> {
+        Box::pin(async move {
+            future::join_all(self.transactions().iter().map(|tx| tx.run_on(client)))
+                .await
+                .into_iter()
+                .collect()
+        })
+    }
+}
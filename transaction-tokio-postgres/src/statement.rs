@@ -0,0 +1,132 @@
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Row};
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+
+use crate::pipeline::Pipelinable;
+use crate::{Error, PgContext};
+
+/// The parameters of a statement, owned by the transaction
+pub type Params = Vec<Box<dyn ToSql + Send + Sync>>;
+
+#[derive(Debug)]
+struct Sql {
+    statement: String,
+    params: Params,
+}
+
+impl Sql {
+    fn new(statement: impl Into<String>, params: Params) -> Self {
+        Sql {
+            statement: statement.into(),
+            params,
+        }
+    }
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params.iter().map(|p| &**p as &(dyn ToSql + Sync)).collect()
+    }
+}
+
+/// Run the statement and return the resulting rows.
+pub fn query(statement: impl Into<String>, params: Params) -> Query {
+    Query { sql: Sql::new(statement, params) }
+}
+
+/// The result of `query`
+#[derive(Debug)]
+#[must_use]
+pub struct Query {
+    sql: Sql,
+}
+
+impl AsyncTransaction for Query {
+    type Ctx = PgContext;
+    type Item = Vec<Row>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        self.run_on(ctx.client())
+    }
+}
+
+impl Pipelinable for Query {
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error> {
+        Box::pin(async move { Ok(client.query(self.sql.statement.as_str(), &self.sql.params()).await?) })
+    }
+}
+
+impl Visit for Query {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("query"));
+    }
+}
+
+/// Run the statement which returns exactly one row and return it. It fails
+/// when the statement returns no or more rows.
+pub fn query_one(statement: impl Into<String>, params: Params) -> QueryOne {
+    QueryOne { sql: Sql::new(statement, params) }
+}
+
+/// The result of `query_one`
+#[derive(Debug)]
+#[must_use]
+pub struct QueryOne {
+    sql: Sql,
+}
+
+impl AsyncTransaction for QueryOne {
+    type Ctx = PgContext;
+    type Item = Row;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        self.run_on(ctx.client())
+    }
+}
+
+impl Pipelinable for QueryOne {
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error> {
+        Box::pin(async move { Ok(client.query_one(self.sql.statement.as_str(), &self.sql.params()).await?) })
+    }
+}
+
+impl Visit for QueryOne {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("query_one"));
+    }
+}
+
+/// Run the statement and return the number of the rows modified.
+pub fn execute(statement: impl Into<String>, params: Params) -> Execute {
+    Execute { sql: Sql::new(statement, params) }
+}
+
+/// The result of `execute`
+#[derive(Debug)]
+#[must_use]
+pub struct Execute {
+    sql: Sql,
+}
+
+impl AsyncTransaction for Execute {
+    type Ctx = PgContext;
+    type Item = u64;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        self.run_on(ctx.client())
+    }
+}
+
+impl Pipelinable for Execute {
+    fn run_on<'a>(&'a self, client: &'a Client) -> AsyncRun<'a, Self::Item, Error> {
+        Box::pin(async move { Ok(client.execute(self.sql.statement.as_str(), &self.sql.params()).await?) })
+    }
+}
+
+impl Visit for Execute {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("execute"));
+    }
+}
@@ -0,0 +1,731 @@
+//! A transaction runner for tokio
+//!
+//! Synchronous transactions are run on the blocking threads of tokio
+//! (`spawn_blocking`) so that they don't block the reactor, and asynchronous
+//! transactions are run on the calling task. Contexts are acquired from an
+//! `AsyncPool` and handed back to it with the outcome of the transaction.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Mutex;
+//!
+//! use futures::future::{self, BoxFuture, FutureExt};
+//! use transaction::prelude::*;
+//! use transaction::hooks::Outcome;
+//! use transaction_tokio::{AsyncPool, Runner};
+//!
+//! // a pool of a single in-memory "database" which is replaced on commit
+//! struct MemoryPool(Mutex<Vec<i32>>);
+//!
+//! impl AsyncPool for MemoryPool {
+//!     type Ctx = Vec<i32>;
+//!     type Error = ();
+//!
+//!     fn acquire(&self) -> BoxFuture<'_, Result<Vec<i32>, ()>> {
+//!         future::ready(Ok(self.0.lock().unwrap().clone())).boxed()
+//!     }
+//!
+//!     fn release(&self, ctx: Vec<i32>, outcome: Outcome) -> BoxFuture<'_, Result<(), ()>> {
+//!         if outcome == Outcome::Committed {
+//!             *self.0.lock().unwrap() = ctx;
+//!         }
+//!         future::ready(Ok(())).boxed()
+//!     }
+//! }
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let runner = Runner::new(MemoryPool(Mutex::new(Vec::new())));
+//!     let push = |x| with_ctx(move |rows: &mut Vec<i32>| -> Result<usize, ()> {
+//!         rows.push(x);
+//!         Ok(rows.len())
+//!     });
+//!     assert_eq!(runner.run(push(1)).await, Ok(1));
+//!     assert_eq!(runner.run(push(2).and_then(|_| err::<_, usize, _>(()))).await, Err(()));
+//!     assert_eq!(runner.run(push(3)).await, Ok(2));
+//! }
+//! ```
+
+use std::convert::Infallible;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use futures::sink::SinkExt;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use transaction::{clock, Retryable, RetryPolicy, RunDeadline, Transaction};
+use transaction::async_tx::{
+    self, AsyncRunner, AsyncTransaction, BlockingPool, Interrupt, Interrupted, RunBlocking, Timer,
+};
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+
+mod pooled;
+
+pub use crate::pooled::*;
+
+/// A pool of contexts which are acquired asynchronously, e.g. a connection
+/// pool of an async driver.
+pub trait AsyncPool: Send + Sync {
+    /// The context lent to transactions
+    type Ctx: Send + 'static;
+    /// The error of the pool
+    type Error;
+
+    /// Take a context out of the pool, beginning a transaction on it if the
+    /// backend needs to
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>>;
+
+    /// Take a context out of the pool like `acquire`, for a transaction to
+    /// be done by the deadline if any. The SQL backends limit its statements
+    /// to the time remaining, e.g. by `statement_timeout`, since a timeout
+    /// of the application doesn't stop the database. By default the
+    /// deadline is ignored.
+    fn acquire_until(&self, deadline: Option<clock::Instant>) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        let _ = deadline;
+        self.acquire()
+    }
+
+    /// Give the context back to the pool, committing or rolling back its
+    /// transaction according to the outcome
+    fn release(&self, ctx: Self::Ctx, outcome: Outcome) -> BoxFuture<'_, Result<(), Self::Error>>;
+}
+
+/// The `BlockingPool` of tokio, running jobs with `spawn_blocking`.
+///
+/// It must be used within a tokio runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blocking;
+
+impl BlockingPool for Blocking {
+    fn execute(&self, job: Box<dyn FnOnce() + Send + 'static>) {
+        drop(tokio::task::spawn_blocking(job));
+    }
+}
+
+/// The `Timer` of tokio, waiting with `tokio::time::sleep`.
+///
+/// It must be used within a tokio runtime.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use futures::future::{self, FutureExt};
+/// use transaction::Backoff;
+/// use transaction::async_tx::{self, AsyncTransaction, AsyncTransactionExt};
+/// use transaction_tokio::TokioTimer;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let flaky = async_tx::with_ctx(|attempts: &mut u32| {
+///         *attempts += 1;
+///         let ret = if *attempts < 3 { Err("busy") } else { Ok(*attempts) };
+///         future::ready(ret).boxed()
+///     });
+///     let tx = flaky.retry_with(Backoff::exponential(Duration::from_millis(1)), TokioTimer);
+///     assert_eq!(tx.run_async(&mut 0).await, Ok(3));
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Run a synchronous transaction on the blocking threads of tokio. The
+/// future resolves to the context, which is given back, and the result of
+/// the transaction.
+pub fn run_blocking<Tx>(tx: Tx, ctx: Tx::Ctx) -> RunBlocking<Tx::Ctx, Tx::Item, Tx::Err>
+where
+    Tx: Transaction + Send + 'static,
+    Tx::Ctx: Send + 'static,
+    Tx::Item: Send + 'static,
+    Tx::Err: Send + 'static,
+{
+    async_tx::run_blocking(&Blocking, tx, ctx)
+}
+
+/// Runner of transactions on contexts acquired from an `AsyncPool`
+///
+/// If a transaction panics, its context is released to the pool as
+/// `RolledBack` before the panic is resumed, so the connection goes back
+/// to the pool without a dangling transaction.
+///
+/// # Examples
+///
+/// ```
+/// use std::panic::AssertUnwindSafe;
+/// use std::sync::Mutex;
+///
+/// use futures::future::{self, BoxFuture, FutureExt};
+/// use transaction::async_tx::{self, AsyncTransaction};
+/// use transaction::hooks::Outcome;
+/// use transaction::prelude::*;
+/// use transaction_tokio::{AsyncPool, Runner};
+///
+/// struct Releases(Mutex<Vec<Outcome>>);
+///
+/// impl AsyncPool for Releases {
+///     type Ctx = ();
+///     type Error = ();
+///     fn acquire(&self) -> BoxFuture<'_, Result<(), ()>> {
+///         future::ready(Ok(())).boxed()
+///     }
+///     fn release(&self, _: (), outcome: Outcome) -> BoxFuture<'_, Result<(), ()>> {
+///         self.0.lock().unwrap().push(outcome);
+///         future::ready(Ok(())).boxed()
+///     }
+/// }
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let runner = Runner::new(Releases(Mutex::new(Vec::new())));
+///     let boom = with_ctx(|_: &mut ()| -> Result<(), ()> { panic!("boom") });
+///     assert!(AssertUnwindSafe(runner.run(boom)).catch_unwind().await.is_err());
+///     let boom = async_tx::with_ctx(|_: &mut ()| -> BoxFuture<'_, Result<(), ()>> { panic!("boom") });
+///     assert!(AssertUnwindSafe(runner.run_async(boom)).catch_unwind().await.is_err());
+///     assert_eq!(*runner.pool().0.lock().unwrap(), vec![Outcome::RolledBack; 2]);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Runner<P> {
+    pool: P,
+}
+
+impl<P> Runner<P>
+where
+    P: AsyncPool,
+{
+    /// make a runner acquiring the contexts from the pool
+    pub fn new(pool: P) -> Self {
+        Runner { pool }
+    }
+
+    /// The pool of the runner
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+
+    /// Run the synchronous transaction on the blocking threads of tokio.
+    pub async fn run<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: Transaction<Ctx = P::Ctx> + Send + 'static,
+        Tx::Item: Send + 'static,
+        Tx::Err: From<P::Error> + Send + 'static,
+    {
+        let label = tx.label().map(str::to_string);
+        hooks::instrument_async("tokio", label.as_deref(), Outcome::of, async {
+            let ctx = self.pool.acquire().await?;
+            let (ctx, ret) = run_blocking(CatchUnwind(tx), ctx).await;
+            self.finish(ctx, caught(ret)).await
+        }).await
+    }
+
+    /// Run the asynchronous transaction on the current task.
+    pub async fn run_async<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = P::Ctx>,
+        Tx::Err: From<P::Error>,
+    {
+        hooks::instrument_async("tokio", tx.label(), Outcome::of, async {
+            let mut ctx = self.pool.acquire().await?;
+            let ret = run_caught(&tx, &mut ctx).await;
+            self.finish(ctx, ret).await
+        }).await
+    }
+
+    /// Run the asynchronous transaction on the current task, stopping and
+    /// rolling it back when interrupted. The deadline is waited for with
+    /// `TokioTimer`. See `AsyncRunner` for the semantics.
+    ///
+    /// The deadline is also given to the pool by `AsyncPool::acquire_until`
+    /// and entered as the `RunDeadline` of the transaction, for
+    /// `transaction::remaining_time`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures::future::{self, BoxFuture, FutureExt};
+    /// # use transaction::hooks::Outcome;
+    /// # use transaction_tokio::AsyncPool;
+    /// # struct Pool;
+    /// # impl AsyncPool for Pool {
+    /// #     type Ctx = ();
+    /// #     type Error = Interrupted;
+    /// #     fn acquire(&self) -> BoxFuture<'_, Result<(), Interrupted>> {
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// #     fn release(&self, _: (), _: Outcome) -> BoxFuture<'_, Result<(), Interrupted>> {
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// # }
+    /// use std::time::Duration;
+    ///
+    /// use transaction::async_tx::{self, Interrupt, Interrupted};
+    /// use transaction_tokio::Runner;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let runner = Runner::new(Pool);
+    ///     let slow = async_tx::with_ctx(|_: &mut ()| {
+    ///         tokio::time::sleep(Duration::from_secs(60)).map(Ok).boxed()
+    ///     });
+    ///     let interrupt = Interrupt::new().timeout(Duration::from_millis(10));
+    ///     let ret = runner.run_async_with(slow, interrupt).await;
+    ///     assert_eq!(ret, Err(Interrupted::DeadlineExceeded));
+    /// }
+    /// ```
+    pub async fn run_async_with<Tx>(&self, tx: Tx, interrupt: Interrupt) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = P::Ctx>,
+        Tx::Err: From<P::Error> + From<Interrupted>,
+    {
+        hooks::instrument_async("tokio", tx.label(), Outcome::of, async {
+            interrupt.check()?;
+            let deadline = interrupt.expires_at();
+            let mut ctx = self.pool.acquire_until(deadline).await?;
+            let ret = match interrupt.check() {
+                Ok(()) => {
+                    let run = RunDeadline::within(deadline, run_caught(&tx, &mut ctx));
+                    match future::select(run, interrupt.wait(&TokioTimer)).await {
+                        Either::Left((ret, _)) => ret,
+                        Either::Right((interrupted, _)) => Ok(Err(interrupted.into())),
+                    }
+                }
+                Err(interrupted) => Ok(Err(interrupted.into())),
+            };
+            self.finish(ctx, ret).await
+        }).await
+    }
+
+    /// Run the asynchronous transaction on the current task, running the
+    /// whole transaction again on a new context while it fails with a
+    /// retryable error (e.g. a serialization failure or a deadlock) and the
+    /// policy allows. Each retry is recorded by `metrics::record_retry`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures::future::{self, BoxFuture, FutureExt};
+    /// # use transaction::hooks::Outcome;
+    /// # use transaction_tokio::AsyncPool;
+    /// # struct Pool;
+    /// # impl AsyncPool for Pool {
+    /// #     type Ctx = ();
+    /// #     type Error = Conflict;
+    /// #     fn acquire(&self) -> BoxFuture<'_, Result<(), Conflict>> {
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// #     fn release(&self, _: (), _: Outcome) -> BoxFuture<'_, Result<(), Conflict>> {
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// # }
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::time::Duration;
+    ///
+    /// use transaction::{Backoff, Retryable};
+    /// use transaction::async_tx;
+    /// use transaction_tokio::Runner;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Conflict;
+    ///
+    /// impl Retryable for Conflict {
+    ///     fn is_retryable(&self) -> bool {
+    ///         true
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let runner = Runner::new(Pool);
+    ///     let attempts = AtomicUsize::new(0);
+    ///     let tx = async_tx::with_ctx(|_: &mut ()| {
+    ///         let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+    ///         let ret = if attempt < 3 { Err(Conflict) } else { Ok(attempt) };
+    ///         future::ready(ret).boxed()
+    ///     });
+    ///     let policy = Backoff::exponential(Duration::from_millis(1));
+    ///     assert_eq!(runner.run_async_retry(tx, policy).await, Ok(3));
+    /// }
+    /// ```
+    pub async fn run_async_retry<Tx, R>(&self, tx: Tx, policy: R) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = P::Ctx>,
+        Tx::Err: From<P::Error> + Retryable,
+        R: RetryPolicy,
+    {
+        hooks::instrument_async("tokio", tx.label(), Outcome::of, async {
+            let mut retries = 0;
+            loop {
+                let mut ctx = self.pool.acquire().await?;
+                let ret = run_caught(&tx, &mut ctx).await;
+                let e = match self.finish(ctx, ret).await {
+                    Err(e) if e.is_retryable() => e,
+                    ret => return ret,
+                };
+                let delay = match policy.next_delay(retries) {
+                    Some(delay) => delay,
+                    None => return Err(e),
+                };
+                #[cfg(feature = "log")]
+                log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+                metrics::record_retry(tx.label());
+                TokioTimer.sleep(delay).await;
+                retries += 1;
+            }
+        }).await
+    }
+
+    /// Run a synchronous transaction made by `f` for each item of the stream,
+    /// running at most `limit` transactions at a time, each on its own
+    /// context. The results are yielded in the order of completion.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures::future::{self, BoxFuture, FutureExt};
+    /// # use transaction::hooks::Outcome;
+    /// # use transaction_tokio::AsyncPool;
+    /// # struct Pool;
+    /// # impl AsyncPool for Pool {
+    /// #     type Ctx = Vec<i32>;
+    /// #     type Error = ();
+    /// #     fn acquire(&self) -> BoxFuture<'_, Result<Vec<i32>, ()>> {
+    /// #         future::ready(Ok(Vec::new())).boxed()
+    /// #     }
+    /// #     fn release(&self, _: Vec<i32>, _: Outcome) -> BoxFuture<'_, Result<(), ()>> {
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// # }
+    /// use futures::stream::{self, StreamExt};
+    /// use transaction::prelude::*;
+    /// use transaction_tokio::Runner;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let runner = Runner::new(Pool);
+    ///     let insert = |x: i32| with_ctx(move |rows: &mut Vec<i32>| -> Result<i32, ()> {
+    ///         rows.push(x);
+    ///         Ok(x * 10)
+    ///     });
+    ///     let mut results = runner
+    ///         .run_each(stream::iter(1..4), 2, insert)
+    ///         .collect::<Vec<_>>()
+    ///         .await;
+    ///     results.sort();
+    ///     assert_eq!(results, vec![Ok(10), Ok(20), Ok(30)]);
+    /// }
+    /// ```
+    pub fn run_each<'a, S, F, Tx>(
+        &'a self,
+        items: S,
+        limit: usize,
+        f: F,
+    ) -> BoxStream<'a, Result<Tx::Item, Tx::Err>>
+    where
+        S: Stream + Send + 'a,
+        F: Fn(S::Item) -> Tx + Send + 'a,
+        Tx: Transaction<Ctx = P::Ctx> + Send + 'static,
+        Tx::Item: Send + 'static,
+        Tx::Err: From<P::Error> + Send + 'static,
+    {
+        let runs = items.map(move |item| self.run(f(item)));
+        Box::pin(runs.buffer_unordered(limit.max(1)))
+    }
+
+    /// Run an asynchronous transaction made by `f` for each item of the
+    /// stream, running at most `limit` transactions at a time, each on its own
+    /// context. The results are yielded in the order of completion.
+    pub fn run_each_async<'a, S, F, Tx>(
+        &'a self,
+        items: S,
+        limit: usize,
+        f: F,
+    ) -> BoxStream<'a, Result<Tx::Item, Tx::Err>>
+    where
+        S: Stream + Send + 'a,
+        F: Fn(S::Item) -> Tx + Send + 'a,
+        Tx: AsyncTransaction<Ctx = P::Ctx> + 'a,
+        Tx::Err: From<P::Error>,
+    {
+        let runs = items.map(move |item| self.run_async(f(item)));
+        Box::pin(runs.buffer_unordered(limit.max(1)))
+    }
+
+    /// Stream the items produced by `f` from a single context. The context
+    /// is acquired when the stream is first polled and is committed after
+    /// the last item, or rolled back after the first error, which ends the
+    /// stream. At most `buffer` items are produced ahead of the consumer.
+    /// If the stream is dropped before its end, the context is dropped
+    /// without being released to the pool.
+    ///
+    /// This fits the row streams of async drivers, e.g. a large query result
+    /// read in one transaction.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures::future::{self, BoxFuture, FutureExt};
+    /// # use transaction::hooks::Outcome;
+    /// # use transaction_tokio::AsyncPool;
+    /// # struct Pool;
+    /// # impl AsyncPool for Pool {
+    /// #     type Ctx = Vec<i32>;
+    /// #     type Error = ();
+    /// #     fn acquire(&self) -> BoxFuture<'_, Result<Vec<i32>, ()>> {
+    /// #         future::ready(Ok(vec![1, 2, 3])).boxed()
+    /// #     }
+    /// #     fn release(&self, _: Vec<i32>, _: Outcome) -> BoxFuture<'_, Result<(), ()>> {
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// # }
+    /// use futures::stream::{self, StreamExt};
+    /// use transaction_tokio::Runner;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let runner = Runner::new(Pool);
+    ///     let rows = runner.stream(16, |rows: &mut Vec<i32>| {
+    ///         stream::iter(rows.iter().map(|row| Ok::<_, ()>(*row))).boxed()
+    ///     });
+    ///     assert_eq!(rows.collect::<Vec<_>>().await, vec![Ok(1), Ok(2), Ok(3)]);
+    /// }
+    /// ```
+    pub fn stream<'a, F, T, E>(&'a self, buffer: usize, f: F) -> BoxStream<'a, Result<T, E>>
+    where
+        F: for<'c> FnOnce(&'c mut P::Ctx) -> BoxStream<'c, Result<T, E>> + Send + 'a,
+        T: Send + 'a,
+        E: From<P::Error> + Send + 'a,
+    {
+        let (mut sender, receiver) = mpsc::channel(buffer);
+        let producer = async move {
+            let ret = hooks::instrument_async("tokio", None, Outcome::of, async {
+                let mut ctx = self.pool.acquire().await?;
+                let ret = AssertUnwindSafe(async {
+                    let mut items = f(&mut ctx);
+                    let mut ret = Ok(());
+                    while let Some(item) = items.next().await {
+                        match item {
+                            Ok(t) => {
+                                // the receiver lives as long as this future
+                                let _ = sender.send(Ok(t)).await;
+                            }
+                            Err(e) => {
+                                ret = Err(e);
+                                break;
+                            }
+                        }
+                    }
+                    ret
+                })
+                .catch_unwind()
+                .await;
+                self.finish(ctx, ret).await
+            }).await;
+            if let Err(e) = ret {
+                let _ = sender.send(Err(e)).await;
+            }
+        };
+        // drive the producer along with the consumer
+        let producer = producer.into_stream().filter_map(|()| future::ready(None));
+        Box::pin(stream::select(receiver, producer))
+    }
+
+    // hand the context back. A failure of the commit fails the transaction
+    // while a failure of the rollback is hidden by the original error. If
+    // the transaction panicked, the context is rolled back before the panic
+    // is resumed.
+    async fn finish<T, E>(&self, ctx: P::Ctx, ret: thread::Result<Result<T, E>>) -> Result<T, E>
+    where
+        E: From<P::Error>,
+    {
+        let ret = match ret {
+            Ok(ret) => ret,
+            Err(panic) => {
+                let _ = self.pool.release(ctx, Outcome::RolledBack).await;
+                panic::resume_unwind(panic);
+            }
+        };
+        let released = self.pool.release(ctx, Outcome::of(&ret)).await;
+        match (ret, released) {
+            (Ok(t), Ok(())) => Ok(t),
+            (Ok(_), Err(e)) => Err(e.into()),
+            (Err(e), _) => Err(e),
+        }
+    }
+}
+
+/// Runner of transactions which are always rolled back, even when they
+/// succeed, for hermetic tests against a real database. The `Item` is still
+/// returned, and the nested savepoints behave as they do in `Runner`.
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::Mutex;
+/// # use futures::future::{self, BoxFuture, FutureExt};
+/// # use transaction::hooks::Outcome;
+/// # use transaction_tokio::AsyncPool;
+/// # struct MemoryPool(Mutex<Vec<i32>>);
+/// # impl AsyncPool for MemoryPool {
+/// #     type Ctx = Vec<i32>;
+/// #     type Error = ();
+/// #     fn acquire(&self) -> BoxFuture<'_, Result<Vec<i32>, ()>> {
+/// #         future::ready(Ok(self.0.lock().unwrap().clone())).boxed()
+/// #     }
+/// #     fn release(&self, ctx: Vec<i32>, outcome: Outcome) -> BoxFuture<'_, Result<(), ()>> {
+/// #         if outcome == Outcome::Committed {
+/// #             *self.0.lock().unwrap() = ctx;
+/// #         }
+/// #         future::ready(Ok(())).boxed()
+/// #     }
+/// # }
+/// use transaction::prelude::*;
+/// use transaction_tokio::TestRunner;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let runner = TestRunner::new(MemoryPool(Mutex::new(Vec::new())));
+///     let push = |x| with_ctx(move |rows: &mut Vec<i32>| -> Result<usize, ()> {
+///         rows.push(x);
+///         Ok(rows.len())
+///     });
+///     assert_eq!(runner.run(push(1)).await, Ok(1));
+///     // nothing was committed
+///     assert_eq!(runner.run(push(2)).await, Ok(1));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TestRunner<P> {
+    pool: P,
+}
+
+impl<P> TestRunner<P>
+where
+    P: AsyncPool,
+{
+    /// make a runner acquiring the contexts from the pool
+    pub fn new(pool: P) -> Self {
+        TestRunner { pool }
+    }
+
+    /// The pool of the runner
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+
+    /// Run the synchronous transaction on the blocking threads of tokio and
+    /// roll it back.
+    pub async fn run<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: Transaction<Ctx = P::Ctx> + Send + 'static,
+        Tx::Item: Send + 'static,
+        Tx::Err: From<P::Error> + Send + 'static,
+    {
+        let label = tx.label().map(str::to_string);
+        hooks::instrument_async("tokio", label.as_deref(), |_| Outcome::RolledBack, async {
+            let ctx = self.pool.acquire().await?;
+            let (ctx, ret) = run_blocking(CatchUnwind(tx), ctx).await;
+            self.rollback(ctx, caught(ret)).await
+        }).await
+    }
+
+    /// Run the asynchronous transaction on the current task and roll it
+    /// back.
+    pub async fn run_async<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = P::Ctx>,
+        Tx::Err: From<P::Error>,
+    {
+        hooks::instrument_async("tokio", tx.label(), |_| Outcome::RolledBack, async {
+            let mut ctx = self.pool.acquire().await?;
+            let ret = run_caught(&tx, &mut ctx).await;
+            self.rollback(ctx, ret).await
+        }).await
+    }
+
+    // a failure to roll back is reported since the test is no longer hermetic
+    async fn rollback<T, E>(&self, ctx: P::Ctx, ret: thread::Result<Result<T, E>>) -> Result<T, E>
+    where
+        E: From<P::Error>,
+    {
+        let released = self.pool.release(ctx, Outcome::RolledBack).await;
+        let ret = ret.unwrap_or_else(|panic| panic::resume_unwind(panic));
+        match (ret, released) {
+            (Ok(t), Ok(())) => Ok(t),
+            (Ok(_), Err(e)) => Err(e.into()),
+            (Err(e), _) => Err(e),
+        }
+    }
+}
+
+impl<P> AsyncRunner for Runner<P>
+where
+    P: AsyncPool,
+{
+    type Ctx = P::Ctx;
+    type Error = P::Error;
+
+    fn run_async<'a, Tx>(&'a self, tx: Tx) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = Self::Ctx> + 'a,
+        Tx::Err: From<Self::Error>,
+    {
+        Box::pin(Runner::run_async(self, tx))
+    }
+
+    fn run_async_with<'a, Tx>(
+        &'a self,
+        tx: Tx,
+        interrupt: Interrupt,
+    ) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = Self::Ctx> + 'a,
+        Tx::Err: From<Self::Error> + From<Interrupted>,
+    {
+        Box::pin(Runner::run_async_with(self, tx, interrupt))
+    }
+}
+
+// a synchronous transaction catching the panics of `Tx`, so the context
+// comes back from the blocking threads to be rolled back
+struct CatchUnwind<Tx>(Tx);
+
+impl<Tx> Transaction for CatchUnwind<Tx>
+where
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = thread::Result<Result<Tx::Item, Tx::Err>>;
+    type Err = Infallible;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(panic::catch_unwind(AssertUnwindSafe(|| self.0.run(ctx))))
+    }
+}
+
+// run the asynchronous transaction catching its panics, also those of
+// `run_async` building the future
+async fn run_caught<Tx>(tx: &Tx, ctx: &mut Tx::Ctx) -> thread::Result<Result<Tx::Item, Tx::Err>>
+where
+    Tx: AsyncTransaction,
+{
+    AssertUnwindSafe(async { tx.run_async(ctx).await }).catch_unwind().await
+}
+
+// the result of `CatchUnwind`
+fn caught<T>(ret: Result<T, Infallible>) -> T {
+    match ret {
+        Ok(t) => t,
+        Err(never) => match never {},
+    }
+}
@@ -0,0 +1,602 @@
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+use futures::future::{BoxFuture, FutureExt};
+use transaction::async_tx::AsyncTransaction;
+use transaction::clock::Instant;
+use transaction::hooks::{self, Outcome};
+use transaction::pool::{AsyncConnectionProvider, Pipelining};
+use transaction::Cancelled;
+
+use crate::{AsyncPool, Runner};
+
+/// A connection of an async driver on which a transaction can be begun,
+/// committed and rolled back.
+pub trait AsyncConnection: Send {
+    /// The error of the driver
+    type Error: Send;
+
+    /// Begin a transaction
+    fn begin(&mut self) -> BoxFuture<'_, Result<(), Self::Error>>;
+
+    /// Begin a transaction to be done by the deadline if any, limiting its
+    /// statements to the time remaining if the driver can, see
+    /// `AsyncPool::acquire_until`. By default the deadline is ignored.
+    fn begin_until(&mut self, deadline: Option<Instant>) -> BoxFuture<'_, Result<(), Self::Error>> {
+        let _ = deadline;
+        self.begin()
+    }
+
+    /// Commit the transaction
+    fn commit(&mut self) -> BoxFuture<'_, Result<(), Self::Error>>;
+
+    /// Roll back the transaction
+    fn rollback(&mut self) -> BoxFuture<'_, Result<(), Self::Error>>;
+}
+
+/// When the connections are removed from the pool rather than reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Recycle {
+    /// Always give the connections back to the pool
+    Always,
+    /// Discard the connections on which beginning, committing or rolling
+    /// back failed, as their state is unknown
+    DiscardBroken,
+    /// Discard the connections also after every rollback
+    DiscardOnRollback,
+}
+
+/// An error of `Pooled`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PooledError<P, C> {
+    /// No connection was checked out within the acquisition timeout
+    Timeout,
+    /// The pool failed to give a connection
+    Pool(P),
+    /// Beginning, committing or rolling back the transaction failed
+    Connection(C),
+}
+
+impl<P, C> fmt::Display for PooledError<P, C>
+where
+    P: fmt::Display,
+    C: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PooledError::Timeout => f.write_str("timed out acquiring a connection"),
+            PooledError::Pool(ref e) => write!(f, "failed to acquire a connection: {}", e),
+            PooledError::Connection(ref e) => write!(f, "transaction control failed: {}", e),
+        }
+    }
+}
+
+impl<P, C> Error for PooledError<P, C>
+where
+    P: Error,
+    C: Error,
+{
+}
+
+/// An `AsyncPool` of connections of an `AsyncConnectionProvider`, e.g. a
+/// pool of `deadpool` or `bb8`, in transactions: a transaction is begun on
+/// the healthy connections checked out and committed or rolled back on
+/// release. `PooledRunner::run_pipelined_async` runs batches of transactions
+/// on a single connection.
+///
+/// # Examples
+///
+/// ```
+/// use futures::future::{self, BoxFuture, FutureExt};
+/// use transaction::prelude::*;
+/// use transaction::pool::AsyncConnectionProvider;
+/// use transaction_tokio::{AsyncConnection, Pooled, PooledError, PooledRunner, Recycle};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Error;
+///
+/// impl From<PooledError<(), ()>> for Error {
+///     fn from(_: PooledError<(), ()>) -> Self {
+///         Error
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Conn {
+///     log: Vec<&'static str>,
+/// }
+///
+/// impl AsyncConnection for Conn {
+///     type Error = ();
+///     fn begin(&mut self) -> BoxFuture<'_, Result<(), ()>> {
+///         self.log.push("BEGIN");
+///         future::ready(Ok(())).boxed()
+///     }
+///     fn commit(&mut self) -> BoxFuture<'_, Result<(), ()>> {
+///         self.log.push("COMMIT");
+///         future::ready(Ok(())).boxed()
+///     }
+///     fn rollback(&mut self) -> BoxFuture<'_, Result<(), ()>> {
+///         self.log.push("ROLLBACK");
+///         future::ready(Ok(())).boxed()
+///     }
+/// }
+///
+/// struct Pool;
+///
+/// impl AsyncConnectionProvider for Pool {
+///     type Connection = Conn;
+///     type Error = ();
+///     fn acquire(&self) -> BoxFuture<'_, Result<Conn, ()>> {
+///         future::ready(Ok(Conn::default())).boxed()
+///     }
+/// }
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let pooled = Pooled::new(Pool)
+///         .acquire_timeout(std::time::Duration::from_secs(1))
+///         .recycle(Recycle::DiscardOnRollback);
+///     let runner: PooledRunner<Pool> = PooledRunner::new(pooled);
+///     let log = with_ctx(|conn: &mut Conn| Ok::<_, Error>(conn.log.clone()));
+///     assert_eq!(runner.run(log).await, Ok(vec!["BEGIN"]));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Pooled<P> {
+    pool: P,
+    acquire_timeout: Option<Duration>,
+    recycle: Recycle,
+}
+
+/// A `Runner` of transactions on the connections of an
+/// `AsyncConnectionProvider`
+pub type PooledRunner<P> = Runner<Pooled<P>>;
+
+impl<P> Pooled<P>
+where
+    P: AsyncConnectionProvider,
+    P::Connection: AsyncConnection,
+{
+    /// Use the connections of the pool. There is no acquisition timeout and
+    /// the broken connections are discarded by default.
+    pub fn new(pool: P) -> Self {
+        Pooled {
+            pool,
+            acquire_timeout: None,
+            recycle: Recycle::DiscardBroken,
+        }
+    }
+
+    /// Fail with `PooledError::Timeout` if no connection is checked out
+    /// within `timeout`
+    pub fn acquire_timeout(self, timeout: Duration) -> Self {
+        Pooled {
+            acquire_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Set when the connections are discarded
+    pub fn recycle(self, recycle: Recycle) -> Self {
+        Pooled { recycle, ..self }
+    }
+
+    /// The underlying pool
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+
+    async fn get(&self) -> Result<P::Connection, PooledError<P::Error, ConnError<P>>> {
+        let get = async {
+            let mut conn = self.pool.acquire().await?;
+            if !self.pool.check_health(&mut conn).await {
+                self.pool.release(conn, true);
+                conn = self.pool.acquire().await?;
+            }
+            Ok(conn)
+        };
+        let conn = match self.acquire_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, get)
+                .await
+                .map_err(|_| PooledError::Timeout)?,
+            None => get.await,
+        };
+        conn.map_err(PooledError::Pool)
+    }
+
+    fn give_back(&self, conn: P::Connection, broken: bool, rolled_back: bool) {
+        let discard = match self.recycle {
+            Recycle::Always => false,
+            Recycle::DiscardBroken => broken,
+            Recycle::DiscardOnRollback => broken || rolled_back,
+        };
+        self.pool.release(conn, discard);
+    }
+}
+
+type ConnError<P> = <<P as AsyncConnectionProvider>::Connection as AsyncConnection>::Error;
+
+impl<P> AsyncPool for Pooled<P>
+where
+    P: AsyncConnectionProvider,
+    P::Connection: AsyncConnection,
+{
+    type Ctx = P::Connection;
+    type Error = PooledError<P::Error, ConnError<P>>;
+
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        self.acquire_until(None)
+    }
+
+    fn acquire_until(&self, deadline: Option<Instant>) -> BoxFuture<'_, Result<Self::Ctx, Self::Error>> {
+        async move {
+            let mut conn = self.get().await?;
+            match conn.begin_until(deadline).await {
+                Ok(()) => Ok(conn),
+                Err(e) => {
+                    self.give_back(conn, true, false);
+                    Err(PooledError::Connection(e))
+                }
+            }
+        }.boxed()
+    }
+
+    fn release(&self, mut ctx: Self::Ctx, outcome: Outcome) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move {
+            let ret = match outcome {
+                Outcome::Committed => ctx.commit().await,
+                Outcome::RolledBack => ctx.rollback().await,
+            };
+            self.give_back(ctx, ret.is_err(), outcome == Outcome::RolledBack);
+            ret.map_err(PooledError::Connection)
+        }.boxed()
+    }
+}
+
+impl<P> Runner<Pooled<P>>
+where
+    P: AsyncConnectionProvider,
+    P::Connection: AsyncConnection,
+{
+    /// Run a batch of independent asynchronous transactions back to back on
+    /// a single connection checked out of the pool, e.g. the jobs of a
+    /// worker, returning the result of each in order. The transactions are
+    /// committed together or one by one as told by `pipelining`.
+    ///
+    /// `setup` runs on the connection before the first transaction begins,
+    /// e.g. to set the search path of the session, and `teardown` after the
+    /// last one is finished, to reset it. The batch fails as a whole, with
+    /// nothing committed, if no connection is checked out, if `setup` fails,
+    /// or, with `Pipelining::Single`, if the transaction fails to begin or
+    /// commit. A failed `teardown` only discards the connection.
+    ///
+    /// With `Pipelining::PerItem`, once the connection fails to begin, commit
+    /// or roll back a transaction, the transactions left fail with
+    /// `Cancelled`. The hooks and metrics are told of each transaction, or of
+    /// the batch as a single unlabeled one with `Pipelining::Single`. If a
+    /// transaction panics, the connection is rolled back before the panic is
+    /// resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures::future::{self, BoxFuture, FutureExt};
+    /// # use transaction::pool::AsyncConnectionProvider;
+    /// # use transaction_tokio::AsyncConnection;
+    /// # #[derive(Default)]
+    /// # struct Conn {
+    /// #     log: Vec<&'static str>,
+    /// # }
+    /// # impl AsyncConnection for Conn {
+    /// #     type Error = ();
+    /// #     fn begin(&mut self) -> BoxFuture<'_, Result<(), ()>> {
+    /// #         self.log.push("BEGIN");
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// #     fn commit(&mut self) -> BoxFuture<'_, Result<(), ()>> {
+    /// #         self.log.push("COMMIT");
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// #     fn rollback(&mut self) -> BoxFuture<'_, Result<(), ()>> {
+    /// #         self.log.push("ROLLBACK");
+    /// #         future::ready(Ok(())).boxed()
+    /// #     }
+    /// # }
+    /// # struct Pool;
+    /// # impl AsyncConnectionProvider for Pool {
+    /// #     type Connection = Conn;
+    /// #     type Error = ();
+    /// #     fn acquire(&self) -> BoxFuture<'_, Result<Conn, ()>> {
+    /// #         future::ready(Ok(Conn::default())).boxed()
+    /// #     }
+    /// # }
+    /// use transaction::async_tx;
+    /// use transaction::pool::Pipelining;
+    /// use transaction::prelude::*;
+    /// use transaction::Cancelled;
+    /// use transaction_tokio::{Pooled, PooledError, PooledRunner};
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum Error {
+    ///     Pool,
+    ///     Failed,
+    ///     Cancelled,
+    /// }
+    ///
+    /// impl From<PooledError<(), ()>> for Error {
+    ///     fn from(_: PooledError<(), ()>) -> Self {
+    ///         Error::Pool
+    ///     }
+    /// }
+    ///
+    /// impl From<Cancelled> for Error {
+    ///     fn from(_: Cancelled) -> Self {
+    ///         Error::Cancelled
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let runner: PooledRunner<Pool> = PooledRunner::new(Pooled::new(Pool));
+    ///     let insert = |row: &'static str| {
+    ///         async_tx::from_sync(with_ctx(move |conn: &mut Conn| {
+    ///             if row == "bad" {
+    ///                 return Err(Error::Failed);
+    ///             }
+    ///             conn.log.push(row);
+    ///             Ok(row)
+    ///         }))
+    ///     };
+    ///     let set = async_tx::from_sync(with_ctx(|conn: &mut Conn| {
+    ///         conn.log.push("SET");
+    ///         Ok(())
+    ///     }));
+    ///     let check = async_tx::from_sync(with_ctx(|conn: &mut Conn| {
+    ///         assert_eq!(conn.log, ["SET", "BEGIN", "a", "ROLLBACK"]);
+    ///         Ok::<_, Error>(())
+    ///     }));
+    ///     let batch = vec![insert("a"), insert("bad"), insert("b")];
+    ///     let results = runner.run_pipelined_async(Pipelining::Single, set, batch, check).await;
+    ///     assert_eq!(results, Ok(vec![Err(Error::Cancelled), Err(Error::Failed), Err(Error::Cancelled)]));
+    /// }
+    /// ```
+    pub async fn run_pipelined_async<I, T, E, S, D>(
+        &self,
+        pipelining: Pipelining,
+        setup: S,
+        txs: I,
+        teardown: D,
+    ) -> Result<Vec<Result<T, E>>, E>
+    where
+        E: From<PooledError<P::Error, ConnError<P>>> + From<Cancelled>,
+        I: IntoIterator,
+        I::Item: AsyncTransaction<Ctx = P::Connection, Item = T, Err = E>,
+        S: AsyncTransaction<Ctx = P::Connection, Item = (), Err = E>,
+        D: AsyncTransaction<Ctx = P::Connection, Item = ()>,
+    {
+        let pooled = self.pool();
+        let mut batch = Batch::<P> {
+            conn: pooled.get().await?,
+            in_transaction: false,
+            // the session may be left half set up if the setup fails or
+            // panics
+            broken: true,
+            rolled_back: false,
+        };
+        let ret = AssertUnwindSafe(batch.run(pipelining, setup, txs, teardown))
+            .catch_unwind()
+            .await;
+        if batch.in_transaction {
+            #[cfg(feature = "log")]
+            log::warn!("rolling back the connection of a panicking transaction");
+            batch.broken |= batch.conn.rollback().await.is_err();
+            batch.rolled_back = true;
+        }
+        pooled.give_back(batch.conn, batch.broken, batch.rolled_back);
+        ret.unwrap_or_else(|panic| panic::resume_unwind(panic))
+    }
+}
+
+// the connection checked out for a batch, with whether a transaction is left
+// open on it, e.g. by a panic, and how it is given back
+struct Batch<P>
+where
+    P: AsyncConnectionProvider,
+{
+    conn: P::Connection,
+    in_transaction: bool,
+    broken: bool,
+    rolled_back: bool,
+}
+
+impl<P> Batch<P>
+where
+    P: AsyncConnectionProvider,
+    P::Connection: AsyncConnection,
+{
+    async fn run<I, T, E, S, D>(
+        &mut self,
+        pipelining: Pipelining,
+        setup: S,
+        txs: I,
+        teardown: D,
+    ) -> Result<Vec<Result<T, E>>, E>
+    where
+        E: From<PooledError<P::Error, ConnError<P>>> + From<Cancelled>,
+        I: IntoIterator,
+        I::Item: AsyncTransaction<Ctx = P::Connection, Item = T, Err = E>,
+        S: AsyncTransaction<Ctx = P::Connection, Item = (), Err = E>,
+        D: AsyncTransaction<Ctx = P::Connection, Item = ()>,
+    {
+        setup.run_async(&mut self.conn).await?;
+        self.broken = false;
+        let results = match pipelining {
+            Pipelining::Single => self.run_single(txs).await?,
+            Pipelining::PerItem => self.run_each(txs).await,
+        };
+        if !self.broken {
+            self.broken = true;
+            if teardown.run_async(&mut self.conn).await.is_ok() {
+                self.broken = false;
+            } else {
+                #[cfg(feature = "log")]
+                log::warn!("discarding the connection of a batch which failed to tear down");
+            }
+        }
+        Ok(results)
+    }
+
+    async fn begin(&mut self) -> Result<(), PooledError<P::Error, ConnError<P>>> {
+        let ret = self.conn.begin().await;
+        self.in_transaction = ret.is_ok();
+        self.broken = ret.is_err();
+        ret.map_err(PooledError::Connection)
+    }
+
+    async fn finish(&mut self, outcome: Outcome) -> Result<(), PooledError<P::Error, ConnError<P>>> {
+        self.in_transaction = false;
+        let ret = match outcome {
+            Outcome::Committed => self.conn.commit().await,
+            Outcome::RolledBack => self.conn.rollback().await,
+        };
+        self.broken = ret.is_err();
+        self.rolled_back |= outcome == Outcome::RolledBack;
+        ret.map_err(PooledError::Connection)
+    }
+
+    async fn run_single<I, T, E>(&mut self, txs: I) -> Result<Vec<Result<T, E>>, E>
+    where
+        E: From<PooledError<P::Error, ConnError<P>>> + From<Cancelled>,
+        I: IntoIterator,
+        I::Item: AsyncTransaction<Ctx = P::Connection, Item = T, Err = E>,
+    {
+        let txs: Vec<_> = txs.into_iter().collect();
+        hooks::instrument_async("tokio", None, batch_outcome, async {
+            self.begin().await?;
+            let mut items = Vec::with_capacity(txs.len());
+            for (i, tx) in txs.iter().enumerate() {
+                match tx.run_async(&mut self.conn).await {
+                    Ok(t) => items.push(t),
+                    Err(e) => {
+                        let _ = self.finish(Outcome::RolledBack).await;
+                        let mut results: Vec<Result<T, E>> = txs.iter().map(|_| Err(Cancelled.into())).collect();
+                        results[i] = Err(e);
+                        return Ok(results);
+                    }
+                }
+            }
+            self.finish(Outcome::Committed).await?;
+            Ok(items.into_iter().map(Ok).collect())
+        })
+        .await
+    }
+
+    async fn run_each<I, T, E>(&mut self, txs: I) -> Vec<Result<T, E>>
+    where
+        E: From<PooledError<P::Error, ConnError<P>>> + From<Cancelled>,
+        I: IntoIterator,
+        I::Item: AsyncTransaction<Ctx = P::Connection, Item = T, Err = E>,
+    {
+        let mut results = Vec::new();
+        for tx in txs {
+            if self.broken {
+                results.push(Err(Cancelled.into()));
+                continue;
+            }
+            let ret = hooks::instrument_async("tokio", tx.label(), Outcome::of, async {
+                self.begin().await?;
+                match tx.run_async(&mut self.conn).await {
+                    Ok(t) => {
+                        self.finish(Outcome::Committed).await?;
+                        Ok(t)
+                    }
+                    Err(e) => {
+                        let _ = self.finish(Outcome::RolledBack).await;
+                        Err(e)
+                    }
+                }
+            })
+            .await;
+            results.push(ret);
+        }
+        results
+    }
+}
+
+// the batch of `Pipelining::Single` commits only if all its transactions
+// succeed
+fn batch_outcome<T, E>(ret: &Result<Vec<Result<T, E>>, E>) -> Outcome {
+    match *ret {
+        Ok(ref results) if results.iter().all(Result::is_ok) => Outcome::Committed,
+        _ => Outcome::RolledBack,
+    }
+}
+
+#[cfg(feature = "deadpool")]
+mod deadpool_impl {
+    use deadpool::managed::{Manager, Object};
+    use futures::future::BoxFuture;
+    use transaction::clock::Instant;
+
+    use super::AsyncConnection;
+
+    impl<M> AsyncConnection for Object<M>
+    where
+        M: Manager,
+        M::Type: AsyncConnection,
+    {
+        type Error = <M::Type as AsyncConnection>::Error;
+
+        fn begin(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+            (**self).begin()
+        }
+
+        fn begin_until(&mut self, deadline: Option<Instant>) -> BoxFuture<'_, Result<(), Self::Error>> {
+            (**self).begin_until(deadline)
+        }
+
+        fn commit(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+            (**self).commit()
+        }
+
+        fn rollback(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+            (**self).rollback()
+        }
+    }
+}
+
+#[cfg(feature = "bb8")]
+mod bb8_impl {
+    use bb8::{ManageConnection, PooledConnection};
+    use futures::future::BoxFuture;
+    use transaction::clock::Instant;
+
+    use super::AsyncConnection;
+
+    impl<M> AsyncConnection for PooledConnection<'static, M>
+    where
+        M: ManageConnection,
+        M::Connection: AsyncConnection,
+    {
+        type Error = <M::Connection as AsyncConnection>::Error;
+
+        fn begin(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+            (**self).begin()
+        }
+
+        fn begin_until(&mut self, deadline: Option<Instant>) -> BoxFuture<'_, Result<(), Self::Error>> {
+            (**self).begin_until(deadline)
+        }
+
+        fn commit(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+            (**self).commit()
+        }
+
+        fn rollback(&mut self) -> BoxFuture<'_, Result<(), Self::Error>> {
+            (**self).rollback()
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Running transactions in tonic gRPC handlers
+//!
+//! `GrpcRunner` wraps an `AsyncRunner` for the unary handlers of a tonic
+//! service. `GrpcRunner::unary` builds an asynchronous transaction from the
+//! request and runs it, committing it when it results in a response and
+//! rolling it back when it results in an error `Status`. The deadline the
+//! client sent in the `grpc-timeout` header interrupts the run, so the
+//! backend transaction is rolled back instead of running on after the client
+//! gave up.
+//!
+//! The errors of the transactions are converted into `Status`, and must be
+//! convertible from the interruption, e.g. with `interrupted_status`.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Mutex;
+//! use std::time::Duration;
+//!
+//! use futures::future::{self, BoxFuture, FutureExt};
+//! use tonic::{Code, Request, Response, Status};
+//! use transaction::async_tx::{self, AsyncTransaction, Interrupted};
+//! use transaction::hooks::Outcome;
+//! use transaction_tokio::{AsyncPool, Runner};
+//! use transaction_tonic::{interrupted_status, GrpcRunner};
+//!
+//! // a pool of a single in-memory "database" which is replaced on commit
+//! struct MemoryPool(Mutex<Vec<u32>>);
+//!
+//! impl AsyncPool for MemoryPool {
+//!     type Ctx = Vec<u32>;
+//!     type Error = Status;
+//!
+//!     fn acquire(&self) -> BoxFuture<'_, Result<Vec<u32>, Status>> {
+//!         future::ready(Ok(self.0.lock().unwrap().clone())).boxed()
+//!     }
+//!
+//!     fn release(&self, ctx: Vec<u32>, outcome: Outcome) -> BoxFuture<'_, Result<(), Status>> {
+//!         if outcome == Outcome::Committed {
+//!             *self.0.lock().unwrap() = ctx;
+//!         }
+//!         future::ready(Ok(())).boxed()
+//!     }
+//! }
+//!
+//! #[derive(Debug)]
+//! struct AppError(Status);
+//!
+//! impl From<Status> for AppError {
+//!     fn from(status: Status) -> Self {
+//!         AppError(status)
+//!     }
+//! }
+//!
+//! impl From<Interrupted> for AppError {
+//!     fn from(interrupted: Interrupted) -> Self {
+//!         AppError(interrupted_status(interrupted))
+//!     }
+//! }
+//!
+//! impl From<AppError> for Status {
+//!     fn from(e: AppError) -> Status {
+//!         e.0
+//!     }
+//! }
+//!
+//! // a slow deposit, e.g. waiting for a lock
+//! fn deposit(amount: u32) -> impl AsyncTransaction<Ctx = Vec<u32>, Item = u32, Err = AppError> {
+//!     async_tx::with_ctx(move |deposits: &mut Vec<u32>| {
+//!         async move {
+//!             if amount == 0 {
+//!                 return Err(AppError(Status::invalid_argument("zero amount")));
+//!             }
+//!             deposits.push(amount);
+//!             tokio::time::sleep(Duration::from_millis(50)).await;
+//!             Ok(deposits.iter().sum())
+//!         }
+//!         .boxed()
+//!     })
+//! }
+//!
+//! // the body of the unary handler of a generated service trait
+//! async fn handle(grpc: &GrpcRunner<Runner<MemoryPool>>, request: Request<u32>) -> Result<Response<u32>, Status> {
+//!     grpc.unary(request, |request| deposit(*request.get_ref())).await
+//! }
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let grpc = GrpcRunner::new(Runner::new(MemoryPool(Mutex::new(Vec::new()))));
+//!
+//!     let response = handle(&grpc, Request::new(100)).await.unwrap();
+//!     assert_eq!(*response.get_ref(), 100);
+//!     assert_eq!(handle(&grpc, Request::new(0)).await.unwrap_err().code(), Code::InvalidArgument);
+//!
+//!     // the client gives up after 10 milliseconds
+//!     let mut request = Request::new(20);
+//!     request.metadata_mut().insert("grpc-timeout", "10m".parse().unwrap());
+//!     assert_eq!(handle(&grpc, request).await.unwrap_err().code(), Code::DeadlineExceeded);
+//!
+//!     // the interrupted deposit is rolled back
+//!     let response = handle(&grpc, Request::new(20)).await.unwrap();
+//!     assert_eq!(*response.get_ref(), 120);
+//! }
+//! ```
+
+use std::time::Duration;
+
+use tonic::{Request, Response, Status};
+use transaction::async_tx::{AsyncRunner, AsyncTransaction, Interrupt, Interrupted};
+
+/// A runner of the transactions of gRPC requests, interrupting them at the
+/// deadline of the request.
+#[derive(Debug, Clone, Default)]
+pub struct GrpcRunner<R> {
+    runner: R,
+    timeout: Option<Duration>,
+}
+
+impl<R> GrpcRunner<R>
+where
+    R: AsyncRunner,
+{
+    /// Run the transactions by the runner, interrupting them only at the
+    /// deadlines sent by the clients
+    pub fn new(runner: R) -> Self {
+        GrpcRunner {
+            runner,
+            timeout: None,
+        }
+    }
+
+    /// Interrupt the transactions after the timeout, or at the deadline of
+    /// the request if it is earlier
+    pub fn timeout(self, timeout: Duration) -> Self {
+        GrpcRunner {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// The runner
+    pub fn runner(&self) -> &R {
+        &self.runner
+    }
+
+    /// Run the transaction `make_tx` builds from the request and respond
+    /// with its item, committing it if it succeeds and rolling it back if it
+    /// fails or the deadline passes. The error is returned as the `Status`.
+    pub async fn unary<Req, Tx, F>(&self, request: Request<Req>, make_tx: F) -> Result<Response<Tx::Item>, Status>
+    where
+        F: FnOnce(Request<Req>) -> Tx,
+        Tx: AsyncTransaction<Ctx = R::Ctx>,
+        Tx::Err: From<R::Error> + From<Interrupted> + Into<Status>,
+    {
+        let timeout = match (grpc_timeout(&request), self.timeout) {
+            (Some(requested), Some(timeout)) => Some(requested.min(timeout)),
+            (requested, timeout) => requested.or(timeout),
+        };
+        let interrupt = match timeout {
+            Some(timeout) => Interrupt::new().timeout(timeout),
+            None => Interrupt::new(),
+        };
+        let tx = make_tx(request);
+        match self.runner.run_async_with(tx, interrupt).await {
+            Ok(item) => Ok(Response::new(item)),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The timeout the client sent in the `grpc-timeout` header of the request,
+/// if any and valid
+pub fn grpc_timeout<T>(request: &Request<T>) -> Option<Duration> {
+    let value = request.metadata().get("grpc-timeout")?.to_str().ok()?;
+    // at most 8 digits followed by the unit
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n = digits.parse::<u64>().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(n * 60 * 60)),
+        "M" => Some(Duration::from_secs(n * 60)),
+        "S" => Some(Duration::from_secs(n)),
+        "m" => Some(Duration::from_millis(n)),
+        "u" => Some(Duration::from_micros(n)),
+        "n" => Some(Duration::from_nanos(n)),
+        _ => None,
+    }
+}
+
+/// The status of an interrupted run: `DEADLINE_EXCEEDED` or `CANCELLED`
+pub fn interrupted_status(interrupted: Interrupted) -> Status {
+    match interrupted {
+        Interrupted::DeadlineExceeded => Status::deadline_exceeded(interrupted.to_string()),
+        Interrupted::Cancelled => Status::cancelled(interrupted.to_string()),
+    }
+}
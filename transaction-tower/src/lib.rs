@@ -0,0 +1,150 @@
+//! tower services running transactions
+//!
+//! `TxService` is a `tower::Service` mapping each request to an asynchronous
+//! transaction and running it by an `AsyncRunner`, whose item and error are
+//! the response and the error of the service. The middleware of tower then
+//! wraps transactional work like any other service, e.g. `Timeout`,
+//! `ConcurrencyLimit`, `LoadShed` or `Retry`. Synchronous transactions are
+//! run through `transaction::async_tx::from_sync` or a runner of the backend.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Mutex;
+//! use std::time::Duration;
+//!
+//! use futures::future::{self, BoxFuture, FutureExt};
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//! use transaction::async_tx::{self, AsyncTransaction};
+//! use transaction::hooks::Outcome;
+//! use transaction_tokio::{AsyncPool, Runner};
+//! use transaction_tower::TxService;
+//!
+//! // a pool of a single in-memory "database" which is replaced on commit
+//! struct MemoryPool(Mutex<Vec<u32>>);
+//!
+//! impl AsyncPool for MemoryPool {
+//!     type Ctx = Vec<u32>;
+//!     type Error = &'static str;
+//!
+//!     fn acquire(&self) -> BoxFuture<'_, Result<Vec<u32>, &'static str>> {
+//!         future::ready(Ok(self.0.lock().unwrap().clone())).boxed()
+//!     }
+//!
+//!     fn release(&self, ctx: Vec<u32>, outcome: Outcome) -> BoxFuture<'_, Result<(), &'static str>> {
+//!         if outcome == Outcome::Committed {
+//!             *self.0.lock().unwrap() = ctx;
+//!         }
+//!         future::ready(Ok(())).boxed()
+//!     }
+//! }
+//!
+//! // the requests are the amounts to deposit, and the responses the balances
+//! fn deposit(amount: u32) -> impl AsyncTransaction<Ctx = Vec<u32>, Item = u32, Err = &'static str> {
+//!     async_tx::with_ctx(move |deposits: &mut Vec<u32>| {
+//!         let ret = if amount == 0 {
+//!             Err("zero amount")
+//!         } else {
+//!             deposits.push(amount);
+//!             Ok(deposits.iter().sum())
+//!         };
+//!         future::ready(ret).boxed()
+//!     })
+//! }
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let runner = Runner::new(MemoryPool(Mutex::new(Vec::new())));
+//!     let mut service = ServiceBuilder::new()
+//!         .concurrency_limit(8)
+//!         .timeout(Duration::from_secs(1))
+//!         .service(TxService::new(runner, deposit));
+//!
+//!     assert_eq!(service.ready().await.unwrap().call(100).await.unwrap(), 100);
+//!     let e = service.ready().await.unwrap().call(0).await.unwrap_err();
+//!     assert_eq!(e.to_string(), "zero amount");
+//!     assert_eq!(service.ready().await.unwrap().call(20).await.unwrap(), 120);
+//! }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use tower::Service;
+use transaction::async_tx::{AsyncRunner, AsyncTransaction};
+
+/// A service running the transaction `make_tx` maps each request to by the
+/// runner `R`, responding with its item.
+///
+/// The service is always ready, since the runner waits for its contexts;
+/// bound the requests in flight with tower's middleware. Clones share the
+/// runner and `make_tx`.
+pub struct TxService<F, R> {
+    make_tx: Arc<F>,
+    runner: Arc<R>,
+}
+
+impl<F, R> TxService<F, R> {
+    /// A service running the transactions given by `make_tx` by the runner
+    pub fn new(runner: R, make_tx: F) -> Self {
+        Self::from_arc(Arc::new(runner), make_tx)
+    }
+
+    /// A service running the transactions given by `make_tx` by the runner
+    /// shared with others
+    pub fn from_arc(runner: Arc<R>, make_tx: F) -> Self {
+        TxService {
+            make_tx: Arc::new(make_tx),
+            runner,
+        }
+    }
+
+    /// The runner
+    pub fn runner(&self) -> &Arc<R> {
+        &self.runner
+    }
+}
+
+impl<F, R> Clone for TxService<F, R> {
+    fn clone(&self) -> Self {
+        TxService {
+            make_tx: self.make_tx.clone(),
+            runner: self.runner.clone(),
+        }
+    }
+}
+
+impl<F, R> fmt::Debug for TxService<F, R>
+where
+    R: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TxService")
+            .field("runner", &self.runner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F, R, Req, Tx> Service<Req> for TxService<F, R>
+where
+    F: Fn(Req) -> Tx,
+    R: AsyncRunner + 'static,
+    Tx: AsyncTransaction<Ctx = R::Ctx> + 'static,
+    Tx::Err: From<R::Error>,
+{
+    type Response = Tx::Item;
+    type Error = Tx::Err;
+    type Future = BoxFuture<'static, Result<Tx::Item, Tx::Err>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Tx::Err>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let tx = (self.make_tx)(req);
+        let runner = self.runner.clone();
+        Box::pin(async move { runner.run_async(tx).await })
+    }
+}
@@ -0,0 +1,296 @@
+//! warp filters running transactions on r2d2 connection pools
+//!
+//! `transactional` is a filter extracting a `Transactional` of the
+//! `PooledRunner` into the handlers. A handler builds a transaction whose
+//! item is the reply and hands it to `Transactional::run`, which runs it on
+//! a connection of the pool in the blocking threads, produces the reply, and
+//! commits the backend transaction if the reply is successful and rolls it
+//! back otherwise, e.g. on 4xx and 5xx.
+//!
+//! A failed transaction is rejected with its error as a `TxRejection`, which
+//! `recover` turns into a reply of the status given by `ToStatus`.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::fmt;
+//! use std::sync::{Arc, Mutex};
+//!
+//! use transaction::prelude::*;
+//! use transaction_r2d2::{Connection, PooledError, PooledRunner};
+//! use transaction_warp::{recover, transactional, ToStatus, Transactional};
+//! use warp::http::StatusCode;
+//! use warp::reply::{self, Response};
+//! use warp::{Filter, Rejection};
+//!
+//! // a connection logging the statements to the log shared by the pool
+//! struct Conn(Arc<Mutex<Vec<String>>>);
+//!
+//! impl Connection for Conn {
+//!     type Error = std::io::Error;
+//!     fn begin(&mut self) -> std::io::Result<()> {
+//!         Ok(self.0.lock().unwrap().push("BEGIN".to_string()))
+//!     }
+//!     fn commit(&mut self) -> std::io::Result<()> {
+//!         Ok(self.0.lock().unwrap().push("COMMIT".to_string()))
+//!     }
+//!     fn rollback(&mut self) -> std::io::Result<()> {
+//!         Ok(self.0.lock().unwrap().push("ROLLBACK".to_string()))
+//!     }
+//! }
+//!
+//! struct Manager(Arc<Mutex<Vec<String>>>);
+//! # impl r2d2::ManageConnection for Manager {
+//! #     type Connection = Conn;
+//! #     type Error = std::io::Error;
+//! #     fn connect(&self) -> std::io::Result<Conn> { Ok(Conn(self.0.clone())) }
+//! #     fn is_valid(&self, _: &mut Conn) -> std::io::Result<()> { Ok(()) }
+//! #     fn has_broken(&self, _: &mut Conn) -> bool { false }
+//! # }
+//!
+//! #[derive(Debug)]
+//! enum AppError {
+//!     NotFound,
+//!     Pool(PooledError<std::io::Error>),
+//! }
+//! # impl From<PooledError<std::io::Error>> for AppError {
+//! #     fn from(e: PooledError<std::io::Error>) -> Self { AppError::Pool(e) }
+//! # }
+//! # impl fmt::Display for AppError {
+//! #     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{:?}", self) }
+//! # }
+//!
+//! impl ToStatus for AppError {
+//!     fn to_status(&self) -> StatusCode {
+//!         match *self {
+//!             AppError::NotFound => StatusCode::NOT_FOUND,
+//!             AppError::Pool(ref e) => e.to_status(),
+//!         }
+//!     }
+//! }
+//!
+//! // the handler only builds the transaction
+//! async fn order(id: u32, tx: Transactional<Manager>) -> Result<Response, Rejection> {
+//!     tx.run(with_ctx(move |conn: &mut Conn| {
+//!         conn.0.lock().unwrap().push(format!("INSERT {}", id));
+//!         match id {
+//!             0 => Err(AppError::NotFound),
+//!             1 => Ok(reply::with_status("out of stock", StatusCode::SERVICE_UNAVAILABLE)),
+//!             _ => Ok(reply::with_status("ordered", StatusCode::CREATED)),
+//!         }
+//!     }))
+//!     .await
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let log = Arc::new(Mutex::new(Vec::new()));
+//! let runner = PooledRunner::new(r2d2::Pool::builder().max_size(1).build(Manager(log.clone()))?);
+//! let orders = warp::post()
+//!     .and(warp::path!("orders" / u32))
+//!     .and(transactional(runner))
+//!     .and_then(order)
+//!     .recover(recover::<AppError>);
+//!
+//! let rt = tokio::runtime::Builder::new_current_thread().build()?;
+//! for (id, status) in [(2, 201), (0, 404), (1, 503)] {
+//!     let req = warp::test::request().method("POST").path(&format!("/orders/{}", id));
+//!     assert_eq!(rt.block_on(req.reply(&orders)).status(), status);
+//! }
+//! assert_eq!(
+//!     *log.lock().unwrap(),
+//!     ["BEGIN", "INSERT 2", "COMMIT", "BEGIN", "INSERT 0", "ROLLBACK", "BEGIN", "INSERT 1", "ROLLBACK"]
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use std::convert::Infallible;
+use std::fmt;
+use std::panic;
+
+use r2d2::ManageConnection;
+use transaction::hooks::{self, Outcome};
+use transaction::Transaction;
+use transaction_r2d2::{Connection, PooledError, PooledRunner, PooledTransaction};
+use warp::http::StatusCode;
+use warp::reject::{self, Reject};
+use warp::reply::{self, Response};
+use warp::{Filter, Rejection, Reply};
+
+type ConnError<M> = <<M as ManageConnection>::Connection as Connection>::Error;
+
+/// A filter extracting a `Transactional` running the transactions of the
+/// request by the runner
+pub fn transactional<M>(runner: PooledRunner<M>) -> impl Filter<Extract = (Transactional<M>,), Error = Infallible> + Clone
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+{
+    warp::any().map(move || Transactional {
+        runner: runner.clone(),
+    })
+}
+
+/// The runner of the transactions of a request, extracted by `transactional`
+pub struct Transactional<M>
+where
+    M: ManageConnection,
+{
+    runner: PooledRunner<M>,
+}
+
+impl<M> Transactional<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+{
+    /// The runner
+    pub fn runner(&self) -> &PooledRunner<M> {
+        &self.runner
+    }
+
+    /// run the given transaction on a connection of the pool and turn its
+    /// item into the reply, committing the backend transaction if the reply
+    /// is successful and rolling it back otherwise.
+    ///
+    /// The error of the transaction, and of checking out a connection,
+    /// beginning or committing, is rejected as a `TxRejection`.
+    pub async fn run<T, E, Tx>(self, tx: Tx) -> Result<Response, Rejection>
+    where
+        T: Reply + 'static,
+        E: From<PooledError<ConnError<M>>> + fmt::Debug + Send + Sync + 'static,
+        Tx: Transaction<Ctx = M::Connection, Item = T, Err = E> + Send + 'static,
+    {
+        let label = tx.label().map(str::to_string);
+        let label = label.as_deref();
+        hooks::instrument_async(
+            "warp",
+            label,
+            |ret| match *ret {
+                Ok(ref res) if is_success(res.status()) => Outcome::Committed,
+                _ => Outcome::RolledBack,
+            },
+            finish(self.runner, tx),
+        )
+        .await
+        .map_err(|e| reject::custom(TxRejection(e)))
+    }
+}
+
+impl<M> fmt::Debug for Transactional<M>
+where
+    M: ManageConnection + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transactional").field("runner", &self.runner).finish()
+    }
+}
+
+fn is_success(status: StatusCode) -> bool {
+    !status.is_client_error() && !status.is_server_error()
+}
+
+// run the transaction, build the reply and commit or roll back by it
+async fn finish<M, T, E, Tx>(runner: PooledRunner<M>, tx: Tx) -> Result<Response, E>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+    T: Reply + 'static,
+    E: From<PooledError<ConnError<M>>> + Send + 'static,
+    Tx: Transaction<Ctx = M::Connection, Item = T, Err = E> + Send + 'static,
+{
+    let (conn, ret) = blocking(move || {
+        let mut conn = runner.begin()?;
+        let ret = conn.run(&tx);
+        Ok::<_, E>((conn, ret))
+    })
+    .await?;
+    let res = match ret {
+        Ok(t) => t.into_response(),
+        Err(e) => {
+            blocking(move || drop(conn.rollback())).await;
+            return Err(e);
+        }
+    };
+    if is_success(res.status()) {
+        blocking(move || commit::<M, E>(conn)).await?;
+    } else {
+        blocking(move || drop(conn.rollback())).await;
+    }
+    Ok(res)
+}
+
+fn commit<M, E>(conn: PooledTransaction<M>) -> Result<(), E>
+where
+    M: ManageConnection,
+    M::Connection: Connection,
+    E: From<PooledError<ConnError<M>>>,
+{
+    conn.commit().map_err(|e| E::from(PooledError::Connection(e)))
+}
+
+// run the job on the blocking threads, resuming its panic
+async fn blocking<F, R>(job: F) -> R
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    match tokio::task::spawn_blocking(job).await {
+        Ok(r) => r,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Errors mapped to the statuses of their replies
+pub trait ToStatus {
+    /// The status of the reply of the error
+    fn to_status(&self) -> StatusCode;
+}
+
+/// 503 Service Unavailable when no connection is available in time, and 500
+/// Internal Server Error otherwise
+impl<C> ToStatus for PooledError<C> {
+    fn to_status(&self) -> StatusCode {
+        match *self {
+            PooledError::Pool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            PooledError::Connection(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The rejection of a failed transaction
+#[derive(Debug)]
+pub struct TxRejection<E>(pub E);
+
+impl<E> Reject for TxRejection<E> where E: fmt::Debug + Send + Sync + 'static {}
+
+impl<E> TxRejection<E>
+where
+    E: ToStatus + fmt::Display,
+{
+    /// The reply of the status given by `ToStatus`, with the error message
+    /// as the body of a client error
+    pub fn to_response(&self) -> Response {
+        let status = self.0.to_status();
+        #[cfg(feature = "log")]
+        log::debug!("transaction failed with {}: {}", status, self.0);
+        // the details of server errors are not for the clients
+        if status.is_server_error() {
+            reply::with_status(reply::reply(), status).into_response()
+        } else {
+            reply::with_status(self.0.to_string(), status).into_response()
+        }
+    }
+}
+
+/// Recover the rejections of the transactions failing with `E` into their
+/// replies, passing the other rejections on. Give it to `Filter::recover`.
+pub async fn recover<E>(rejection: Rejection) -> Result<Response, Rejection>
+where
+    E: ToStatus + fmt::Debug + fmt::Display + Send + Sync + 'static,
+{
+    match rejection.find::<TxRejection<E>>() {
+        Some(e) => Ok(e.to_response()),
+        None => Err(rejection),
+    }
+}
@@ -0,0 +1,107 @@
+use std::error;
+use std::fmt;
+
+use transaction::Retryable;
+
+/// The classification of the errors of ZooKeeper transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A node read by the transaction was changed or created by another
+    /// client before the commit
+    Conflict,
+    /// The node does not exist
+    NoNode,
+    /// The node already exists
+    NodeExists,
+    /// The version of the node is not the expected one
+    BadVersion,
+    /// Any other error
+    Other,
+}
+
+/// The kind of an operation of a `multi` request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpKind {
+    /// `check_version`, or the check of a node read by the transaction
+    Check,
+    /// `create`
+    Create,
+    /// `set_data`
+    SetData,
+    /// `delete`
+    Delete,
+}
+
+/// An error of ZooKeeper, attributed to the operation of the `multi` request
+/// failing it if any
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    inner: zookeeper_client::Error,
+    op: Option<(OpKind, String)>,
+}
+
+impl Error {
+    pub(crate) fn op_failed(inner: zookeeper_client::Error, op: OpKind, path: String, conflict: bool) -> Self {
+        let mut e = Error::from(inner);
+        if conflict {
+            e.kind = ErrorKind::Conflict;
+        }
+        e.op = Some((op, path));
+        e
+    }
+
+    /// The classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The kind and the path of the operation failing the `multi` request,
+    /// if the error is of one
+    pub fn failed_op(&self) -> Option<(OpKind, &str)> {
+        self.op.as_ref().map(|(kind, path)| (*kind, path.as_str()))
+    }
+
+    /// The error of ZooKeeper
+    pub fn get_ref(&self) -> &zookeeper_client::Error {
+        &self.inner
+    }
+
+    /// Unwrap the error of ZooKeeper
+    pub fn into_inner(self) -> zookeeper_client::Error {
+        self.inner
+    }
+}
+
+impl From<zookeeper_client::Error> for Error {
+    fn from(inner: zookeeper_client::Error) -> Self {
+        let kind = match inner {
+            zookeeper_client::Error::NoNode => ErrorKind::NoNode,
+            zookeeper_client::Error::NodeExists => ErrorKind::NodeExists,
+            zookeeper_client::Error::BadVersion => ErrorKind::BadVersion,
+            _ => ErrorKind::Other,
+        };
+        Error { kind, inner, op: None }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.op {
+            Some((op, ref path)) => write!(f, "{:?} of {} failed: {}", op, path, self.inner),
+            None => fmt::Display::fmt(&self.inner, f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind == ErrorKind::Conflict
+    }
+}
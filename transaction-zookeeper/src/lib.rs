@@ -0,0 +1,285 @@
+//! Transaction backend for ZooKeeper
+//!
+//! Asynchronous transactions running in a `ZkContext` batch the operations
+//! of the leaves `create`, `set_data`, `delete` and `check_version` into a
+//! `multi` request of ZooKeeper, which the `Runner` sends at the end. The
+//! operations are applied all together or not at all.
+//!
+//! The nodes read by `get_data` are read at once, and their versions are
+//! checked by the `multi` request before the other operations. When one of
+//! them was changed by another client, or a node read as absent was created
+//! by another client, the request fails with `ErrorKind::Conflict` and the
+//! runner runs the transaction again. The reads don't see the operations of
+//! the transaction, which are applied at commit.
+//!
+//! When an operation fails the request, the error tells which one by
+//! `Error::failed_op`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use transaction::async_tx::AsyncTransactionExt;
+//! use transaction_zookeeper::{get_data, set_data, Runner};
+//! use zookeeper_client::Client;
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), transaction_zookeeper::Error> {
+//!     let client = Client::connect("localhost:2181").await?;
+//!     let runner = Runner::new(client).max_retries(10);
+//!
+//!     let incr = get_data("/hits")
+//!         .map(|v| v.map_or(0, |(v, _)| String::from_utf8(v).unwrap().parse::<u64>().unwrap()))
+//!         .and_then(|n| set_data("/hits", (n + 1).to_string(), None).map(move |()| n + 1));
+//!     let hits = runner.run(&incr).await?;
+//!     println!("{} hits", hits);
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use transaction::async_tx::AsyncTransaction;
+use transaction::hooks::{self, Outcome};
+use transaction::metrics;
+use zookeeper_client::{Client, CreateOptions, MultiWriteError, Stat};
+
+mod error;
+mod ops;
+
+pub use crate::error::*;
+pub use crate::ops::*;
+
+// an operation of the `multi` request
+#[derive(Debug)]
+enum Op {
+    Check {
+        path: String,
+        version: i32,
+    },
+    Create {
+        path: String,
+        data: Vec<u8>,
+        options: CreateOptions<'static>,
+    },
+    SetData {
+        path: String,
+        data: Vec<u8>,
+        expected_version: Option<i32>,
+    },
+    Delete {
+        path: String,
+        expected_version: Option<i32>,
+    },
+}
+
+impl Op {
+    fn kind(&self) -> OpKind {
+        match *self {
+            Op::Check { .. } => OpKind::Check,
+            Op::Create { .. } => OpKind::Create,
+            Op::SetData { .. } => OpKind::SetData,
+            Op::Delete { .. } => OpKind::Delete,
+        }
+    }
+
+    fn path(&self) -> &str {
+        match *self {
+            Op::Check { ref path, .. }
+            | Op::Create { ref path, .. }
+            | Op::SetData { ref path, .. }
+            | Op::Delete { ref path, .. } => path,
+        }
+    }
+}
+
+/// The context of the transactions: the `multi` request being built.
+pub struct ZkContext {
+    client: Client,
+    // the nodes read, `None` for the absent ones
+    reads: BTreeMap<String, Option<(Vec<u8>, Stat)>>,
+    ops: Vec<Op>,
+}
+
+impl ZkContext {
+    // never pub this function
+    fn new(client: Client) -> Self {
+        ZkContext {
+            client,
+            reads: BTreeMap::new(),
+            ops: Vec::new(),
+        }
+    }
+
+    /// The client
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    async fn get_data(&mut self, path: &str) -> Result<Option<(Vec<u8>, Stat)>, Error> {
+        if let Some(read) = self.reads.get(path) {
+            return Ok(read.clone());
+        }
+        let read = match self.client.get_data(path).await {
+            Ok(read) => Some(read),
+            Err(zookeeper_client::Error::NoNode) => None,
+            Err(e) => return Err(e.into()),
+        };
+        self.reads.insert(path.to_owned(), read.clone());
+        Ok(read)
+    }
+
+    // send the `multi` request, checking the versions read first
+    async fn commit(self) -> Result<(), Error> {
+        let mut ops = self
+            .reads
+            .iter()
+            .filter_map(|(path, read)| {
+                read.as_ref().map(|(_, stat)| Op::Check {
+                    path: path.clone(),
+                    version: stat.version,
+                })
+            })
+            .collect::<Vec<_>>();
+        let checks = ops.len();
+        ops.extend(self.ops);
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        let mut writer = self.client.new_multi_writer();
+        for op in &ops {
+            match *op {
+                Op::Check { ref path, version } => writer.add_check_version(path, version)?,
+                Op::Create {
+                    ref path,
+                    ref data,
+                    ref options,
+                } => writer.add_create(path, data, options)?,
+                Op::SetData {
+                    ref path,
+                    ref data,
+                    expected_version,
+                } => writer.add_set_data(path, data, expected_version)?,
+                Op::Delete {
+                    ref path,
+                    expected_version,
+                } => writer.add_delete(path, expected_version)?,
+            }
+        }
+        match writer.commit().await {
+            Ok(_) => Ok(()),
+            Err(MultiWriteError::RequestFailed { source }) => Err(source.into()),
+            Err(MultiWriteError::OperationFailed { index, source }) => {
+                let op = &ops[index];
+                // a node read was changed or deleted, or a node read as
+                // absent was created
+                let conflict = match source {
+                    zookeeper_client::Error::BadVersion | zookeeper_client::Error::NoNode => index < checks,
+                    zookeeper_client::Error::NodeExists => {
+                        matches!(self.reads.get(op.path()), Some(None)) && op.kind() == OpKind::Create
+                    }
+                    _ => false,
+                };
+                Err(Error::op_failed(source, op.kind(), op.path().to_owned(), conflict))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ZkContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ZkContext")
+            .field("reads", &self.reads.len())
+            .field("ops", &self.ops.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runner of transactions batched into `multi` requests of ZooKeeper
+#[derive(Debug, Clone)]
+pub struct Runner {
+    client: Client,
+    max_retries: Option<u32>,
+}
+
+impl Runner {
+    /// Run the transactions on the client, retrying them as long as the
+    /// nodes they read conflict
+    pub fn new(client: Client) -> Self {
+        Runner {
+            client,
+            max_retries: None,
+        }
+    }
+
+    /// Limit the number of the retries of a transaction
+    pub fn max_retries(self, max_retries: u32) -> Self {
+        Runner {
+            max_retries: Some(max_retries),
+            ..self
+        }
+    }
+
+    /// The client
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// run the given transaction and send the `multi` request built by it.
+    /// When a node read conflicts, it is run again, reading the nodes again,
+    /// and fails with `ErrorKind::Conflict` once the retries run out. Each
+    /// retry is recorded by `metrics::record_retry`.
+    ///
+    /// If the transaction panics, the operations batched are dropped without
+    /// being sent, and the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::panic::AssertUnwindSafe;
+    ///
+    /// use futures::FutureExt;
+    /// use transaction::async_tx::AsyncTransactionExt;
+    /// use transaction_zookeeper::{get_data, set_data, Runner};
+    /// use zookeeper_client::Client;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() -> Result<(), transaction_zookeeper::Error> {
+    ///     let client = Client::connect("localhost:2181").await?;
+    ///     let runner = Runner::new(client);
+    ///
+    ///     let hits = || get_data("/hits").map(|v| v.map(|(data, _)| data));
+    ///     let before = runner.run(hits()).await?;
+    ///     let boom = set_data("/hits", "0", None).map(|()| -> () { panic!("boom") });
+    ///     assert!(AssertUnwindSafe(runner.run(boom)).catch_unwind().await.is_err());
+    ///     assert_eq!(runner.run(hits()).await?, before);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn run<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: AsyncTransaction<Ctx = ZkContext>,
+        Tx::Err: From<Error>,
+    {
+        hooks::instrument_async("zookeeper", tx.label(), Outcome::of, async {
+            let mut retries = 0;
+            loop {
+                let mut ctx = ZkContext::new(self.client.clone());
+                let item = tx.run_async(&mut ctx).await?;
+                match ctx.commit().await {
+                    Ok(()) => return Ok(item),
+                    Err(e) => {
+                        if e.kind() != ErrorKind::Conflict || self.max_retries.is_some_and(|max| retries >= max) {
+                            return Err(e.into());
+                        }
+                        #[cfg(feature = "log")]
+                        log::debug!("transaction conflicted, retrying: {}", e);
+                    }
+                }
+                retries += 1;
+                metrics::record_retry(tx.label());
+            }
+        }).await
+    }
+}
@@ -0,0 +1,202 @@
+use transaction::async_tx::{AsyncRun, AsyncTransaction};
+use transaction::{visit_leaf, Node, Visit, Visitor};
+use zookeeper_client::{CreateOptions, Stat};
+
+use crate::{Error, Op, ZkContext};
+
+/// Get the data and the stat of the node, or `None` if it does not exist.
+/// The version read is checked at commit.
+pub fn get_data<P>(path: P) -> GetData
+where
+    P: Into<String>,
+{
+    GetData { path: path.into() }
+}
+
+/// The result of `get_data`
+#[derive(Debug)]
+#[must_use]
+pub struct GetData {
+    path: String,
+}
+
+impl AsyncTransaction for GetData {
+    type Ctx = ZkContext;
+    type Item = Option<(Vec<u8>, Stat)>;
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(ctx.get_data(&self.path))
+    }
+}
+
+impl Visit for GetData {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get_data"));
+    }
+}
+
+/// Create the node with the data, e.g.
+/// `create("/app/leader", "me", CreateMode::Ephemeral.with_acls(Acls::anyone_all()))`.
+pub fn create<P, D>(path: P, data: D, options: CreateOptions<'static>) -> Create
+where
+    P: Into<String>,
+    D: Into<Vec<u8>>,
+{
+    Create {
+        path: path.into(),
+        data: data.into(),
+        options,
+    }
+}
+
+/// The result of `create`
+#[derive(Debug)]
+#[must_use]
+pub struct Create {
+    path: String,
+    data: Vec<u8>,
+    options: CreateOptions<'static>,
+}
+
+impl AsyncTransaction for Create {
+    type Ctx = ZkContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.ops.push(Op::Create {
+            path: self.path.clone(),
+            data: self.data.clone(),
+            options: self.options.clone(),
+        });
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for Create {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("create"));
+    }
+}
+
+/// Set the data of the node, if its version is the expected one when given.
+pub fn set_data<P, D>(path: P, data: D, expected_version: Option<i32>) -> SetData
+where
+    P: Into<String>,
+    D: Into<Vec<u8>>,
+{
+    SetData {
+        path: path.into(),
+        data: data.into(),
+        expected_version,
+    }
+}
+
+/// The result of `set_data`
+#[derive(Debug)]
+#[must_use]
+pub struct SetData {
+    path: String,
+    data: Vec<u8>,
+    expected_version: Option<i32>,
+}
+
+impl AsyncTransaction for SetData {
+    type Ctx = ZkContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.ops.push(Op::SetData {
+            path: self.path.clone(),
+            data: self.data.clone(),
+            expected_version: self.expected_version,
+        });
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for SetData {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("set_data"));
+    }
+}
+
+/// Delete the node, if its version is the expected one when given.
+pub fn delete<P>(path: P, expected_version: Option<i32>) -> Delete
+where
+    P: Into<String>,
+{
+    Delete {
+        path: path.into(),
+        expected_version,
+    }
+}
+
+/// The result of `delete`
+#[derive(Debug)]
+#[must_use]
+pub struct Delete {
+    path: String,
+    expected_version: Option<i32>,
+}
+
+impl AsyncTransaction for Delete {
+    type Ctx = ZkContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.ops.push(Op::Delete {
+            path: self.path.clone(),
+            expected_version: self.expected_version,
+        });
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for Delete {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("delete"));
+    }
+}
+
+/// Check at commit that the version of the node is the given one.
+pub fn check_version<P>(path: P, version: i32) -> CheckVersion
+where
+    P: Into<String>,
+{
+    CheckVersion {
+        path: path.into(),
+        version,
+    }
+}
+
+/// The result of `check_version`
+#[derive(Debug)]
+#[must_use]
+pub struct CheckVersion {
+    path: String,
+    version: i32,
+}
+
+impl AsyncTransaction for CheckVersion {
+    type Ctx = ZkContext;
+    type Item = ();
+    type Err = Error;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        ctx.ops.push(Op::Check {
+            path: self.path.clone(),
+            version: self.version,
+        });
+        Box::pin(futures::future::ok(()))
+    }
+}
+
+impl Visit for CheckVersion {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("check_version"));
+    }
+}
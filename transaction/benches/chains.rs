@@ -0,0 +1,45 @@
+//! Measure the chains of combinators in `tests/codegen/chains.rs` against
+//! the code written by hand next to them, which `tests/codegen.rs` checks
+//! compile to the same assembly.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[path = "../tests/codegen/chains.rs"]
+mod chains;
+
+fn bench_chains(c: &mut Criterion) {
+    let mut group = c.benchmark_group("map_and_then");
+    group.bench_function("composed", |b| b.iter(|| chains::composed_map_and_then(black_box(&mut 21))));
+    group.bench_function("handwritten", |b| b.iter(|| chains::handwritten_map_and_then(black_box(&mut 21))));
+    group.finish();
+
+    let mut group = c.benchmark_group("join");
+    group.bench_function("composed", |b| b.iter(|| chains::composed_join(black_box(&mut 21))));
+    group.bench_function("handwritten", |b| b.iter(|| chains::handwritten_join(black_box(&mut 21))));
+    group.finish();
+
+    let mut group = c.benchmark_group("branch");
+    for &flag in &[true, false] {
+        group.bench_function(format!("composed/{}", flag), |b| {
+            b.iter(|| chains::composed_branch(black_box(flag), black_box(&mut 21)))
+        });
+        group.bench_function(format!("handwritten/{}", flag), |b| {
+            b.iter(|| chains::handwritten_branch(black_box(flag), black_box(&mut 21)))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("recover");
+    for &amount in &[10, 30] {
+        group.bench_function(format!("composed/{}", amount), |b| {
+            b.iter(|| chains::composed_recover(black_box(amount), black_box(&mut 21)))
+        });
+        group.bench_function(format!("handwritten/{}", amount), |b| {
+            b.iter(|| chains::handwritten_recover(black_box(amount), black_box(&mut 21)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_chains);
+criterion_main!(benches);
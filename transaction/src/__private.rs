@@ -0,0 +1,37 @@
+//! Support of the code generated by the `#[transaction]` attribute of
+//! `transaction-macros`. Not public API.
+
+use crate::Transaction;
+
+/// What `step?` does in the body of a `#[transaction]` function: a
+/// transaction is run on the context, a `Result` is passed through.
+pub trait Step<Ctx> {
+    /// The item
+    type Item;
+    /// The error
+    type Err;
+
+    /// Run the step on the context
+    fn step(self, ctx: &mut Ctx) -> Result<Self::Item, Self::Err>;
+}
+
+impl<Tx> Step<Tx::Ctx> for Tx
+where
+    Tx: Transaction,
+{
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn step(self, ctx: &mut Tx::Ctx) -> Result<Self::Item, Self::Err> {
+        self.run(ctx)
+    }
+}
+
+impl<Ctx, T, E> Step<Ctx> for Result<T, E> {
+    type Item = T;
+    type Err = E;
+
+    fn step(self, _ctx: &mut Ctx) -> Result<T, E> {
+        self
+    }
+}
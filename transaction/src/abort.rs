@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 /// Take the previous successfull value of computation and abort the
 /// transaction.
@@ -43,3 +44,12 @@ where
         }
     }
 }
+
+impl<Tx, T, F> Visit for Abort<Tx, T, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("abort"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,82 @@
+use std::marker::PhantomData;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Run the transaction in any context which can be borrowed as the context of
+/// the transaction via `AsMut`. This is `zoom` for wrapper contexts.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// struct Conn(i32);
+/// struct Traced {
+///     conn: Conn,
+///     trace_id: u64,
+/// }
+///
+/// impl AsMut<Conn> for Traced {
+///     fn as_mut(&mut self) -> &mut Conn {
+///         &mut self.conn
+///     }
+/// }
+///
+/// # fn main() {
+/// let incr = with_ctx(|conn: &mut Conn| -> Result<i32, ()> {
+///     conn.0 += 1;
+///     Ok(conn.0)
+/// });
+/// let mut ctx = Traced { conn: Conn(0), trace_id: 42 };
+/// assert_eq!(incr.adapt_ctx().run(&mut ctx), Ok(1));
+/// # let _ = ctx.trace_id;
+/// # }
+/// ```
+pub fn adapt_ctx<Ctx, A, Big>(a: A) -> AdaptCtx<Big, A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+    Big: AsMut<Ctx>,
+{
+    AdaptCtx {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `adapt_ctx`
+#[derive(Debug)]
+#[must_use]
+pub struct AdaptCtx<Big, Tx> {
+    tx: Tx,
+    _phantom: PhantomData<Big>,
+}
+
+impl<Big, Tx> Transaction for AdaptCtx<Big, Tx>
+where
+    Tx: Transaction,
+    Big: AsMut<Tx::Ctx>,
+{
+    type Ctx = Big;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.tx.run(ctx.as_mut())
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Big, Tx> Visit for AdaptCtx<Big, Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("adapt_ctx"), |v| self.tx.accept(v));
+    }
+}
@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn and_then<Ctx, A, F, B>(a: A, f: F) -> AndThen<A::Tx, F, B>
 where
@@ -43,3 +44,12 @@ where
         )
     }
 }
+
+impl<Tx1, F, Tx2> Visit for AndThen<Tx1, F, Tx2>
+where
+    Tx1: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("and_then"), |v| self.tx.accept(v));
+    }
+}
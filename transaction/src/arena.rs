@@ -0,0 +1,155 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+
+use bumpalo::Bump;
+
+use crate::{IntoTransaction, Transaction, Visit, Visitor};
+
+/// A bump region into which `boxed_in` allocates transactions, e.g. the
+/// graph built for each request, so that they are allocated together and
+/// freed at once by `reset` or when the arena is dropped.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{Arena, ArenaTx};
+///
+/// # fn main() {
+/// fn plan<'a>(arena: &'a Arena, ids: &[i32]) -> ArenaTx<'a, Vec<i32>, usize, ()> {
+///     let mut tx = ok(0).boxed_in(arena);
+///     for &id in ids {
+///         let push = with_ctx(move |v: &mut Vec<i32>| {
+///             v.push(id);
+///             Ok(v.len())
+///         });
+///         tx = tx.join(push).map(|(_, len)| len).boxed_in(arena);
+///     }
+///     tx
+/// }
+///
+/// let mut arena = Arena::new();
+/// for request in 1..4 {
+///     let ids = (0..request).collect::<Vec<_>>();
+///     let mut v = vec![];
+///     assert_eq!(plan(&arena, &ids).run(&mut v), Ok(ids.len()));
+///     assert_eq!(v, ids);
+///     // free the whole graph at once
+///     arena.reset();
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct Arena {
+    bump: Bump,
+}
+
+impl Arena {
+    /// Make an empty arena
+    pub fn new() -> Self {
+        Arena { bump: Bump::new() }
+    }
+
+    /// Make an empty arena with room for `capacity` bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Arena {
+            bump: Bump::with_capacity(capacity),
+        }
+    }
+
+    /// Move the value into the arena
+    pub fn alloc<T>(&self, value: T) -> ArenaBox<'_, T> {
+        ArenaBox {
+            value: self.bump.alloc(value),
+        }
+    }
+
+    /// The number of bytes allocated by the arena, including those not
+    /// used yet
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+
+    /// Free all the values at once, keeping the largest chunk for reuse
+    pub fn reset(&mut self) {
+        self.bump.reset()
+    }
+}
+
+/// A value owned in an `Arena`, dropped in place like a `Box` but whose
+/// memory is freed with the arena
+pub struct ArenaBox<'a, T: ?Sized> {
+    value: &'a mut T,
+}
+
+/// A transaction boxed in an `Arena` by `boxed_in`
+pub type ArenaTx<'a, Ctx, T, E> = ArenaBox<'a, dyn Transaction<Ctx = Ctx, Item = T, Err = E> + 'a>;
+
+/// Box the transaction in the arena, like `boxed` but without allocating
+/// on the heap. See `Arena`.
+pub fn boxed_in<'a, Ctx, A>(a: A, arena: &'a Arena) -> ArenaTx<'a, Ctx, A::Item, A::Err>
+where
+    A: IntoTransaction<Ctx>,
+    A::Tx: 'a,
+{
+    ArenaBox {
+        value: arena.bump.alloc(a.into_transaction()),
+    }
+}
+
+impl<'a, T: ?Sized> Deref for ArenaBox<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for ArenaBox<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for ArenaBox<'a, T> {
+    fn drop(&mut self) {
+        // SAFETY: the value is owned by the box, and the arena keeps its
+        // memory until it is reset, which needs the box to be gone
+        unsafe { ptr::drop_in_place(self.value as *mut T) }
+    }
+}
+
+impl<'a, T> Transaction for ArenaBox<'a, T>
+where
+    T: ?Sized + Transaction,
+{
+    type Ctx = T::Ctx;
+    type Item = T::Item;
+    type Err = T::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        (**self).run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        (**self).label()
+    }
+}
+
+impl<'a, T> Visit for ArenaBox<'a, T>
+where
+    T: ?Sized + Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        (**self).accept(visitor)
+    }
+}
+
+impl<'a, T: ?Sized> fmt::Debug for ArenaBox<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArenaBox").finish_non_exhaustive()
+    }
+}
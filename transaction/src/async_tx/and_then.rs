@@ -0,0 +1,53 @@
+use std::marker::PhantomData;
+
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+pub fn and_then<Ctx, A, F, B>(a: A, f: F) -> AndThen<A::Tx, F, B>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    B: IntoAsyncTransaction<Ctx, Err = A::Err>,
+    F: Fn(A::Item) -> B + Send + Sync,
+{
+    AndThen {
+        tx: a.into_async_transaction(),
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `and_then`
+#[derive(Debug)]
+#[must_use]
+pub struct AndThen<Tx1, F, Tx2> {
+    tx: Tx1,
+    f: F,
+    _phantom: PhantomData<fn() -> Tx2>,
+}
+
+impl<Tx, Tx2, F> AsyncTransaction for AndThen<Tx, F, Tx2>
+where
+    Tx2: IntoAsyncTransaction<Tx::Ctx, Err = Tx::Err>,
+    Tx: AsyncTransaction,
+    F: Fn(Tx::Item) -> Tx2 + Send + Sync,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx2::Item;
+    type Err = Tx2::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let item = self.tx.run_async(ctx).await?;
+            (self.f)(item).into_async_transaction().run_async(ctx).await
+        })
+    }
+}
+
+impl<Tx1, F, Tx2> Visit for AndThen<Tx1, F, Tx2>
+where
+    Tx1: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("and_then"), |v| self.tx.accept(v));
+    }
+}
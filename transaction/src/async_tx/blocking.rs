@@ -0,0 +1,114 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+
+use futures::channel::oneshot;
+
+use crate::{platform, Transaction};
+
+/// A pool of threads which may block, like `tokio::task::spawn_blocking`.
+pub trait BlockingPool {
+    /// Run the job on a thread of the pool
+    fn execute(&self, job: Box<dyn FnOnce() + Send + 'static>);
+}
+
+/// A `BlockingPool` spawning a new thread for each job. Runtimes usually
+/// provide a better pool. On the targets without threads, e.g.
+/// `wasm32-unknown-unknown`, the job is run by the caller.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadPerJob;
+
+impl BlockingPool for ThreadPerJob {
+    fn execute(&self, job: Box<dyn FnOnce() + Send + 'static>) {
+        if platform::THREADS {
+            thread::spawn(job);
+        } else {
+            job();
+        }
+    }
+}
+
+impl<P> BlockingPool for &P
+where
+    P: ?Sized + BlockingPool,
+{
+    fn execute(&self, job: Box<dyn FnOnce() + Send + 'static>) {
+        (**self).execute(job)
+    }
+}
+
+impl<P> BlockingPool for ::std::sync::Arc<P>
+where
+    P: ?Sized + BlockingPool,
+{
+    fn execute(&self, job: Box<dyn FnOnce() + Send + 'static>) {
+        (**self).execute(job)
+    }
+}
+
+/// Run a synchronous transaction on the blocking pool without blocking the
+/// caller. The future resolves to the context, which is given back, and the
+/// result of the transaction.
+///
+/// # Panics
+///
+/// The future panics if the transaction panics or the pool drops the job.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::async_tx::{run_blocking, ThreadPerJob};
+///
+/// # fn main() {
+/// let tx = with_ctx(|rows: &mut Vec<i32>| -> Result<usize, ()> {
+///     rows.push(1);
+///     Ok(rows.len())
+/// });
+/// let (rows, ret) = futures::executor::block_on(run_blocking(&ThreadPerJob, tx, Vec::new()));
+/// assert_eq!(ret, Ok(1));
+/// assert_eq!(rows, vec![1]);
+/// # }
+/// ```
+pub fn run_blocking<P, Tx>(pool: &P, tx: Tx, ctx: Tx::Ctx) -> RunBlocking<Tx::Ctx, Tx::Item, Tx::Err>
+where
+    P: BlockingPool,
+    Tx: Transaction + Send + 'static,
+    Tx::Ctx: Send + 'static,
+    Tx::Item: Send + 'static,
+    Tx::Err: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    pool.execute(Box::new(move || {
+        let mut ctx = ctx;
+        let ret = tx.run(&mut ctx);
+        // the caller may have lost interest
+        let _ = sender.send((ctx, ret));
+    }));
+    RunBlocking { receiver }
+}
+
+/// The result of `run_blocking`
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct RunBlocking<Ctx, T, E> {
+    receiver: oneshot::Receiver<(Ctx, Result<T, E>)>,
+}
+
+impl<Ctx, T, E> Future for RunBlocking<Ctx, T, E> {
+    type Output = (Ctx, Result<T, E>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match Pin::new(&mut self.receiver).poll(cx) {
+            Poll::Ready(Ok(output)) => Poll::Ready(output),
+            Poll::Ready(Err(oneshot::Canceled)) => {
+                panic!("the blocking transaction panicked or was dropped by the pool")
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
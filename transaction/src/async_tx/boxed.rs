@@ -0,0 +1,13 @@
+use super::{AsyncTransaction, IntoAsyncTransaction};
+
+/// Box the asynchronous transaction, e.g. to keep transactions of different
+/// types together.
+pub fn boxed<'a, Ctx, A>(
+    a: A,
+) -> Box<dyn AsyncTransaction<Ctx = Ctx, Item = A::Item, Err = A::Err> + 'a>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    A::Tx: 'a,
+{
+    Box::new(a.into_async_transaction())
+}
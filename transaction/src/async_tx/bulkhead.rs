@@ -0,0 +1,307 @@
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use futures::future::{self, Either, FutureExt};
+
+use super::{AsyncRun, AsyncRunner, AsyncTransaction, BoxFuture, Interrupt, Interrupted, Timer};
+use crate::bulkhead::Bulkhead;
+use crate::retry_policy::Retryable;
+
+/// A runner limiting the runs of the runner `R` running at once to the
+/// permits of the bulkhead, see `bulkhead`. The runs wait for a permit at
+/// most the wait timeout of the bulkhead, on the `Timer`, and then fail
+/// with `BulkheadError::Full` without acquiring a context of `R`, so a slow
+/// backend doesn't exhaust the connections of the pool.
+///
+/// An interrupted run stops waiting for a permit. Once it has one, it is
+/// interrupted like by `ChaosRunner`.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Mutex;
+/// use std::time::Duration;
+///
+/// use futures::executor::block_on;
+/// use futures::future::{self, BoxFuture, FutureExt};
+/// use transaction::async_tx::{self, AsyncRunner, AsyncTransaction, BulkheadError, BulkheadRunner, Interrupt, Interrupted, Timer};
+/// use transaction::bulkhead::Bulkhead;
+///
+/// // a runner committing the transactions on a copy of the store
+/// struct Store(Mutex<Vec<i32>>);
+///
+/// impl AsyncRunner for Store {
+///     type Ctx = Vec<i32>;
+///     type Error = ();
+///
+///     fn run_async<'a, Tx>(&'a self, tx: Tx) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+///     where
+///         Tx: AsyncTransaction<Ctx = Vec<i32>> + 'a,
+///         Tx::Err: From<()>,
+///     {
+///         async move {
+///             let mut ctx = self.0.lock().unwrap().clone();
+///             let item = tx.run_async(&mut ctx).await?;
+///             *self.0.lock().unwrap() = ctx;
+///             Ok(item)
+///         }
+///         .boxed()
+///     }
+///
+///     fn run_async_with<'a, Tx>(&'a self, tx: Tx, _: Interrupt) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+///     where
+///         Tx: AsyncTransaction<Ctx = Vec<i32>> + 'a,
+///         Tx::Err: From<()> + From<Interrupted>,
+///     {
+///         self.run_async(tx)
+///     }
+/// }
+///
+/// struct NoWait;
+///
+/// impl Timer for NoWait {
+///     fn sleep(&self, _: Duration) -> BoxFuture<'static, ()> {
+///         future::ready(()).boxed()
+///     }
+/// }
+///
+/// let bulkhead = Bulkhead::new(1, Duration::from_secs(1));
+/// let runner = BulkheadRunner::new(Store(Mutex::new(vec![])), bulkhead.clone(), NoWait);
+/// let push = |x| {
+///     async_tx::from_sync(transaction::with_ctx(move |v: &mut Vec<i32>| {
+///         v.push(x);
+///         Ok::<_, BulkheadError<()>>(())
+///     }))
+/// };
+///
+/// // the only permit is taken, e.g. by a sync runner sharing the bulkhead
+/// let permit = bulkhead.try_acquire().unwrap();
+/// assert_eq!(block_on(runner.run_async(push(1))), Err(BulkheadError::Full));
+/// drop(permit);
+/// assert_eq!(block_on(runner.run_async(push(2))), Ok(()));
+/// assert_eq!(*runner.runner().0.lock().unwrap(), vec![2]);
+/// ```
+#[derive(Debug)]
+pub struct BulkheadRunner<R, T> {
+    runner: R,
+    bulkhead: Bulkhead,
+    timer: T,
+}
+
+// the interrupt of a run, with the conversion of its error
+type Interruption<E> = (Interrupt, fn(Interrupted) -> E);
+
+impl<R, T> BulkheadRunner<R, T> {
+    /// Limit the runs of the runner by the bulkhead, waiting for the
+    /// permits on the timer
+    pub fn new(runner: R, bulkhead: Bulkhead, timer: T) -> Self {
+        BulkheadRunner { runner, bulkhead, timer }
+    }
+
+    /// The runner limited
+    pub fn runner(&self) -> &R {
+        &self.runner
+    }
+
+    /// The bulkhead lending the permits
+    pub fn bulkhead(&self) -> &Bulkhead {
+        &self.bulkhead
+    }
+}
+
+impl<R, T> BulkheadRunner<R, T>
+where
+    R: AsyncRunner,
+    R::Error: Send,
+    T: Timer,
+{
+    fn run_limited<'a, Tx>(
+        &'a self,
+        tx: Tx,
+        interrupt: Option<Interruption<Tx::Err>>,
+    ) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = R::Ctx> + 'a,
+        Tx::Err: From<BulkheadError<R::Error>>,
+    {
+        async move {
+            let (interrupt, interrupted) = match interrupt {
+                Some((interrupt, interrupted)) => (Some(interrupt), Some(interrupted)),
+                None => (None, None),
+            };
+            let _permit = match self.bulkhead.try_acquire() {
+                Some(permit) => permit,
+                None if self.bulkhead.max_wait().is_zero() => return Err(BulkheadError::Full.into()),
+                None => {
+                    let acquire = future::poll_fn(|cx| self.bulkhead.poll_acquire(cx));
+                    let timeout = self.timer.sleep(self.bulkhead.max_wait()).map(|()| None);
+                    let stop = match interrupt {
+                        Some(ref interrupt) => future::select(timeout, interrupt.wait(&self.timer).map(Some))
+                            .map(|either| either.factor_first().0)
+                            .boxed(),
+                        None => timeout.boxed(),
+                    };
+                    match future::select(acquire, stop).await {
+                        Either::Left((permit, _)) => permit,
+                        Either::Right((None, _)) => {
+                            #[cfg(feature = "log")]
+                            log::warn!("bulkhead full with {} transactions running", self.bulkhead.running());
+                            return Err(BulkheadError::Full.into());
+                        }
+                        Either::Right((Some(i), _)) => {
+                            return Err(interrupted.expect("interrupted without an interrupt")(i))
+                        }
+                    }
+                }
+            };
+            let limited = Limited {
+                tx,
+                timer: &self.timer,
+                interrupt,
+                _phantom: PhantomData,
+            };
+            match self.runner.run_async(limited).await {
+                Ok(item) => Ok(item),
+                Err(Caught::Tx(e)) => Err(e),
+                Err(Caught::Runner(e)) => Err(BulkheadError::Runner(e).into()),
+                Err(Caught::Interrupted(i)) => Err(interrupted.expect("interrupted without an interrupt")(i)),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Interrupting a run holding a permit rolls back the transaction by
+/// failing it, so the runner `R` runs it uninterrupted.
+impl<R, T> AsyncRunner for BulkheadRunner<R, T>
+where
+    R: AsyncRunner,
+    R::Error: Send,
+    T: Timer,
+{
+    type Ctx = R::Ctx;
+    type Error = BulkheadError<R::Error>;
+
+    fn run_async<'a, Tx>(&'a self, tx: Tx) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = Self::Ctx> + 'a,
+        Tx::Err: From<Self::Error>,
+    {
+        self.run_limited(tx, None)
+    }
+
+    fn run_async_with<'a, Tx>(
+        &'a self,
+        tx: Tx,
+        interrupt: Interrupt,
+    ) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = Self::Ctx> + 'a,
+        Tx::Err: From<Self::Error> + From<Interrupted>,
+    {
+        if let Err(i) = interrupt.check() {
+            return future::ready(Err(i.into())).boxed();
+        }
+        self.run_limited(tx, Some((interrupt, Tx::Err::from)))
+    }
+}
+
+/// The error of the runs of `BulkheadRunner`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkheadError<E> {
+    /// The error of the runner
+    Runner(E),
+    /// No permit was available in time
+    Full,
+}
+
+impl<E> fmt::Display for BulkheadError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BulkheadError::Runner(ref e) => e.fmt(f),
+            BulkheadError::Full => f.write_str("too many transactions running concurrently"),
+        }
+    }
+}
+
+impl<E> Error for BulkheadError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            BulkheadError::Runner(ref e) => Some(e),
+            BulkheadError::Full => None,
+        }
+    }
+}
+
+impl<E> Retryable for BulkheadError<E>
+where
+    E: Retryable,
+{
+    fn is_retryable(&self) -> bool {
+        match *self {
+            BulkheadError::Runner(ref e) => e.is_retryable(),
+            BulkheadError::Full => true,
+        }
+    }
+}
+
+// the error of the transaction run by the runner, telling the errors of
+// the runner from the ones of the transaction
+enum Caught<E, R> {
+    Tx(E),
+    Runner(R),
+    Interrupted(Interrupted),
+}
+
+impl<E, R> From<R> for Caught<E, R> {
+    fn from(e: R) -> Self {
+        Caught::Runner(e)
+    }
+}
+
+// the transaction interrupted by failing it
+struct Limited<'t, Tx, T, E> {
+    tx: Tx,
+    timer: &'t T,
+    interrupt: Option<Interrupt>,
+    _phantom: PhantomData<fn() -> E>,
+}
+
+impl<'t, Tx, T, E> AsyncTransaction for Limited<'t, Tx, T, E>
+where
+    Tx: AsyncTransaction,
+    T: Timer,
+    E: Send,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Caught<Tx::Err, E>;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        async move {
+            let run = self.tx.run_async(ctx).map(|ret| ret.map_err(Caught::Tx));
+            match self.interrupt {
+                None => run.await,
+                Some(ref interrupt) => {
+                    interrupt.check().map_err(Caught::Interrupted)?;
+                    match future::select(run, interrupt.wait(self.timer)).await {
+                        Either::Left((ret, _)) => ret,
+                        Either::Right((i, _)) => Err(Caught::Interrupted(i)),
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
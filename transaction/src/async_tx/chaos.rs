@@ -0,0 +1,394 @@
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::{self, Either, FutureExt};
+
+use super::{AsyncRun, AsyncRunner, AsyncTransaction, BoxFuture, Interrupt, Interrupted, Timer};
+use crate::fault::{splitmix64, unit};
+use crate::retry_policy::Retryable;
+use crate::Trigger;
+
+/// A fault injected into a run by `ChaosRunner`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chaos {
+    /// Fail the run with `ChaosError::ConnectionDropped` before acquiring
+    /// the context
+    DropConnection,
+    /// Roll the transaction back after it succeeds, failing the run with
+    /// `ChaosError::CommitFailed`
+    FailCommit,
+    /// Commit the transaction but fail the run with
+    /// `ChaosError::CommitFailed` anyway, as if the acknowledgement of the
+    /// commit were lost
+    LoseCommit,
+    /// Wait between the success of the transaction and its commit
+    DelayCommit(Duration),
+}
+
+/// A runner injecting faults into the runs of the runner `R` at the
+/// boundary of the backend, so applications can test how they handle
+/// dropped connections, failed commits and slow commits.
+///
+/// A failed commit is reported the same whether the transaction is rolled
+/// back (`Chaos::FailCommit`) or committed (`Chaos::LoseCommit`), just like
+/// a connection lost during a commit leaves its outcome unknown, so the
+/// application has to find out by itself, e.g. by reading again or by
+/// making the transaction idempotent.
+///
+/// The first rule triggered by a run wins. The runs are counted and the
+/// probabilities drawn from a generator seeded with 0 unless `seed` is
+/// given, so the faults are reproducible. The delays are waited on the
+/// `Timer`.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Mutex;
+/// use std::time::Duration;
+///
+/// use futures::executor::block_on;
+/// use futures::future::{self, BoxFuture, FutureExt};
+/// use transaction::async_tx::{self, AsyncRunner, AsyncTransaction, ChaosError, ChaosRunner, Interrupt, Interrupted, Timer};
+/// use transaction::Trigger;
+///
+/// // a runner committing the transactions on a copy of the store
+/// struct Store(Mutex<Vec<i32>>);
+///
+/// impl AsyncRunner for Store {
+///     type Ctx = Vec<i32>;
+///     type Error = ();
+///
+///     fn run_async<'a, Tx>(&'a self, tx: Tx) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+///     where
+///         Tx: AsyncTransaction<Ctx = Vec<i32>> + 'a,
+///         Tx::Err: From<()>,
+///     {
+///         async move {
+///             let mut ctx = self.0.lock().unwrap().clone();
+///             let item = tx.run_async(&mut ctx).await?;
+///             *self.0.lock().unwrap() = ctx;
+///             Ok(item)
+///         }
+///         .boxed()
+///     }
+///
+///     fn run_async_with<'a, Tx>(&'a self, tx: Tx, _: Interrupt) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+///     where
+///         Tx: AsyncTransaction<Ctx = Vec<i32>> + 'a,
+///         Tx::Err: From<()> + From<Interrupted>,
+///     {
+///         self.run_async(tx)
+///     }
+/// }
+///
+/// struct NoWait;
+///
+/// impl Timer for NoWait {
+///     fn sleep(&self, _: Duration) -> BoxFuture<'static, ()> {
+///         future::ready(()).boxed()
+///     }
+/// }
+///
+/// let runner = ChaosRunner::new(Store(Mutex::new(vec![])), NoWait)
+///     .fail_commit(Trigger::Nth(1))
+///     .lose_commit(Trigger::Nth(2));
+/// let push = |x| {
+///     async_tx::from_sync(transaction::with_ctx(move |v: &mut Vec<i32>| {
+///         v.push(x);
+///         Ok::<_, ChaosError<()>>(())
+///     }))
+/// };
+///
+/// // the same error whether it committed or not
+/// assert_eq!(block_on(runner.run_async(push(1))), Err(ChaosError::CommitFailed));
+/// assert_eq!(block_on(runner.run_async(push(2))), Err(ChaosError::CommitFailed));
+/// assert_eq!(block_on(runner.run_async(push(3))), Ok(()));
+/// assert_eq!(*runner.runner().0.lock().unwrap(), vec![2, 3]);
+/// ```
+#[derive(Debug)]
+pub struct ChaosRunner<R, T> {
+    runner: R,
+    timer: T,
+    rules: Vec<(Chaos, Trigger)>,
+    state: Mutex<State>,
+}
+
+// the interrupt of a run, with the conversion of its error
+type Interruption<E> = (Interrupt, fn(Interrupted) -> E);
+
+#[derive(Debug, Default)]
+struct State {
+    runs: usize,
+    rng: u64,
+}
+
+impl<R, T> ChaosRunner<R, T> {
+    /// Inject no faults into the runs of the runner, waiting the delays on
+    /// the timer
+    pub fn new(runner: R, timer: T) -> Self {
+        ChaosRunner {
+            runner,
+            timer,
+            rules: Vec::new(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Seed the generator of the probabilities
+    pub fn seed(self, seed: u64) -> Self {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).rng = seed;
+        self
+    }
+
+    /// Inject the fault into the runs when triggered
+    pub fn inject(mut self, chaos: Chaos, trigger: Trigger) -> Self {
+        self.rules.push((chaos, trigger));
+        self
+    }
+
+    /// Drop the connection of the runs when triggered
+    pub fn drop_connection(self, trigger: Trigger) -> Self {
+        self.inject(Chaos::DropConnection, trigger)
+    }
+
+    /// Fail the commits of the runs when triggered, rolling them back
+    pub fn fail_commit(self, trigger: Trigger) -> Self {
+        self.inject(Chaos::FailCommit, trigger)
+    }
+
+    /// Lose the acknowledgements of the commits of the runs when triggered
+    pub fn lose_commit(self, trigger: Trigger) -> Self {
+        self.inject(Chaos::LoseCommit, trigger)
+    }
+
+    /// Delay the commits of the runs when triggered
+    pub fn delay_commit(self, delay: Duration, trigger: Trigger) -> Self {
+        self.inject(Chaos::DelayCommit(delay), trigger)
+    }
+
+    /// The runner the faults are injected into
+    pub fn runner(&self) -> &R {
+        &self.runner
+    }
+
+    // the fault of the next run, if any
+    fn draw(&self) -> Option<Chaos> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.runs += 1;
+        let run = state.runs;
+        for &(chaos, trigger) in &self.rules {
+            let triggered = match trigger {
+                Trigger::Always => true,
+                Trigger::Nth(n) => run == n,
+                Trigger::Probability(p) => unit(splitmix64(&mut state.rng)) < p,
+            };
+            if triggered {
+                #[cfg(feature = "log")]
+                log::debug!("injecting {:?} into run {}", chaos, run);
+                return Some(chaos);
+            }
+        }
+        None
+    }
+}
+
+impl<R, T> ChaosRunner<R, T>
+where
+    R: AsyncRunner,
+    R::Error: Send,
+    T: Timer,
+{
+    fn run_chaos<'a, Tx>(
+        &'a self,
+        tx: Tx,
+        interrupt: Option<Interruption<Tx::Err>>,
+    ) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = R::Ctx> + 'a,
+        Tx::Err: From<ChaosError<R::Error>>,
+    {
+        let chaos = self.draw();
+        async move {
+            if chaos == Some(Chaos::DropConnection) {
+                return Err(ChaosError::ConnectionDropped.into());
+            }
+            let (interrupt, interrupted) = match interrupt {
+                Some((interrupt, interrupted)) => (Some(interrupt), Some(interrupted)),
+                None => (None, None),
+            };
+            let chaotic = Chaotic {
+                tx,
+                chaos,
+                timer: &self.timer,
+                interrupt,
+                _phantom: PhantomData,
+            };
+            match self.runner.run_async(chaotic).await {
+                Ok(_) if chaos == Some(Chaos::LoseCommit) => Err(ChaosError::CommitFailed.into()),
+                Ok(item) => Ok(item),
+                Err(Caught::Tx(e)) => Err(e),
+                Err(Caught::Runner(e)) => Err(ChaosError::Runner(e).into()),
+                Err(Caught::CommitFailed) => Err(ChaosError::CommitFailed.into()),
+                Err(Caught::Interrupted(i)) => Err(interrupted.expect("interrupted without an interrupt")(i)),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Interrupting a run rolls back the transaction by failing it, so the
+/// runner `R` runs it uninterrupted.
+impl<R, T> AsyncRunner for ChaosRunner<R, T>
+where
+    R: AsyncRunner,
+    R::Error: Send,
+    T: Timer,
+{
+    type Ctx = R::Ctx;
+    type Error = ChaosError<R::Error>;
+
+    fn run_async<'a, Tx>(&'a self, tx: Tx) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = Self::Ctx> + 'a,
+        Tx::Err: From<Self::Error>,
+    {
+        self.run_chaos(tx, None)
+    }
+
+    fn run_async_with<'a, Tx>(
+        &'a self,
+        tx: Tx,
+        interrupt: Interrupt,
+    ) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = Self::Ctx> + 'a,
+        Tx::Err: From<Self::Error> + From<Interrupted>,
+    {
+        if let Err(i) = interrupt.check() {
+            return future::ready(Err(i.into())).boxed();
+        }
+        self.run_chaos(tx, Some((interrupt, Tx::Err::from)))
+    }
+}
+
+/// The error of the runs of `ChaosRunner`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChaosError<E> {
+    /// The error of the runner
+    Runner(E),
+    /// The connection was dropped before the run
+    ConnectionDropped,
+    /// The commit failed. The transaction may or may not be committed.
+    CommitFailed,
+}
+
+impl<E> fmt::Display for ChaosError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChaosError::Runner(ref e) => e.fmt(f),
+            ChaosError::ConnectionDropped => f.write_str("connection dropped"),
+            ChaosError::CommitFailed => f.write_str("commit failed, the transaction may be committed"),
+        }
+    }
+}
+
+impl<E> Error for ChaosError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ChaosError::Runner(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A dropped connection is retryable, but a failed commit is not, since the
+/// transaction may be committed
+impl<E> Retryable for ChaosError<E>
+where
+    E: Retryable,
+{
+    fn is_retryable(&self) -> bool {
+        match *self {
+            ChaosError::Runner(ref e) => e.is_retryable(),
+            ChaosError::ConnectionDropped => true,
+            ChaosError::CommitFailed => false,
+        }
+    }
+}
+
+// the error of the transaction run by the runner, telling the injected
+// failures from the ones of the transaction
+enum Caught<E, R> {
+    Tx(E),
+    Runner(R),
+    CommitFailed,
+    Interrupted(Interrupted),
+}
+
+impl<E, R> From<R> for Caught<E, R> {
+    fn from(e: R) -> Self {
+        Caught::Runner(e)
+    }
+}
+
+// the transaction with the faults injected between its success and the
+// commit
+struct Chaotic<'t, Tx, T, E> {
+    tx: Tx,
+    chaos: Option<Chaos>,
+    timer: &'t T,
+    interrupt: Option<Interrupt>,
+    _phantom: PhantomData<fn() -> E>,
+}
+
+impl<'t, Tx, T, E> AsyncTransaction for Chaotic<'t, Tx, T, E>
+where
+    Tx: AsyncTransaction,
+    T: Timer,
+    E: Send,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Caught<Tx::Err, E>;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        async move {
+            let run = self.tx.run_async(ctx).map(|ret| ret.map_err(Caught::Tx));
+            let item = match self.interrupt {
+                None => run.await?,
+                Some(ref interrupt) => {
+                    interrupt.check().map_err(Caught::Interrupted)?;
+                    match future::select(run, interrupt.wait(self.timer)).await {
+                        Either::Left((ret, _)) => ret?,
+                        Either::Right((i, _)) => return Err(Caught::Interrupted(i)),
+                    }
+                }
+            };
+            match self.chaos {
+                Some(Chaos::FailCommit) => Err(Caught::CommitFailed),
+                Some(Chaos::DelayCommit(delay)) => {
+                    self.timer.sleep(delay).await;
+                    Ok(item)
+                }
+                _ => Ok(item),
+            }
+        }
+        .boxed()
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
@@ -0,0 +1,41 @@
+use std::marker::PhantomData;
+
+use super::{AsyncRun, AsyncTransaction};
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// make a error transaction value.
+pub fn err<Ctx, T, E>(e: E) -> TxErr<Ctx, T, E> {
+    TxErr {
+        err: e,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `err`
+#[derive(Debug)]
+#[must_use]
+pub struct TxErr<Ctx, T, E> {
+    err: E,
+    _phantom: PhantomData<fn() -> (Ctx, T)>,
+}
+
+impl<Ctx, T, E> AsyncTransaction for TxErr<Ctx, T, E>
+where
+    Ctx: Send,
+    T: Send,
+    E: Clone + Send + Sync,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run_async<'a>(&'a self, _ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(::futures::future::ready(Err(self.err.clone())))
+    }
+}
+
+impl<Ctx, T, E> Visit for TxErr<Ctx, T, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("err"));
+    }
+}
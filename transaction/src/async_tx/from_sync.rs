@@ -0,0 +1,71 @@
+use futures::future;
+
+use super::{AsyncRun, AsyncTransaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Use a synchronous transaction as an asynchronous one. The transaction is
+/// run inline when the future is polled, so it must not block for long; use
+/// `run_blocking` for blocking backends.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::async_tx::{self, AsyncTransaction, AsyncTransactionExt};
+///
+/// # fn main() {
+/// let incr = with_ctx(|n: &mut i32| -> Result<i32, ()> {
+///     *n += 1;
+///     Ok(*n)
+/// });
+/// let tx = async_tx::from_sync(incr).and_then(|n| async_tx::ok(n * 10));
+/// let mut n = 0;
+/// assert_eq!(futures::executor::block_on(tx.run_async(&mut n)), Ok(10));
+/// # }
+/// ```
+pub fn from_sync<Ctx, A>(a: A) -> FromSync<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    FromSync { tx: a.into_transaction() }
+}
+
+/// The result of `from_sync`
+#[derive(Debug)]
+#[must_use]
+pub struct FromSync<Tx> {
+    tx: Tx,
+}
+
+impl<Tx> AsyncTransaction for FromSync<Tx>
+where
+    Tx: Transaction + Send + Sync,
+    Tx::Ctx: Send,
+    Tx::Item: Send,
+    Tx::Err: Send,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(future::lazy(move |_| self.tx.run(ctx)))
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for FromSync<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("from_sync"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,186 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+use futures::future::{self, FutureExt};
+
+use super::{BoxFuture, Timer};
+use crate::clock::Instant;
+
+/// A token to cancel runs from the outside. The clones share the state, so
+/// cancelling one cancels them all.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl CancellationToken {
+    /// make a token which is not cancelled yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the runs given the token
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        let wakers = ::std::mem::take(&mut *self.inner.wakers.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Whether the token is cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// A future completing when the token is cancelled
+    pub fn cancelled(&self) -> BoxFuture<'static, ()> {
+        let token = self.clone();
+        future::poll_fn(move |cx| {
+            if token.is_cancelled() {
+                return Poll::Ready(());
+            }
+            {
+                let mut wakers = token.inner.wakers.lock().unwrap();
+                if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+            }
+            // cancelled while registering
+            if token.is_cancelled() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }).boxed()
+    }
+}
+
+/// When a run is interrupted: a deadline, a cancellation token or both.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use futures::future::{self, BoxFuture, FutureExt};
+/// use transaction::async_tx::{CancellationToken, Interrupt, Interrupted, Timer};
+///
+/// struct Never;
+///
+/// impl Timer for Never {
+///     fn sleep(&self, _: Duration) -> BoxFuture<'static, ()> {
+///         future::pending().boxed()
+///     }
+/// }
+///
+/// let token = CancellationToken::new();
+/// let interrupt = Interrupt::new().timeout(Duration::from_secs(60)).token(token.clone());
+/// assert_eq!(interrupt.check(), Ok(()));
+/// token.cancel();
+/// assert_eq!(interrupt.check(), Err(Interrupted::Cancelled));
+/// assert_eq!(futures::executor::block_on(interrupt.wait(&Never)), Interrupted::Cancelled);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    deadline: Option<Instant>,
+    token: Option<CancellationToken>,
+}
+
+impl Interrupt {
+    /// Never interrupt
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interrupt the run at the deadline
+    pub fn deadline(self, deadline: Instant) -> Self {
+        Interrupt {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Interrupt the run after the duration from now
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    /// Interrupt the run when the token is cancelled
+    pub fn token(self, token: CancellationToken) -> Self {
+        Interrupt {
+            token: Some(token),
+            ..self
+        }
+    }
+
+    /// The deadline of the run, if any, e.g. for the runners to limit the
+    /// statements of the transaction to it
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Whether the run is already interrupted. Cancellation takes precedence
+    /// over the deadline.
+    pub fn check(&self) -> Result<(), Interrupted> {
+        if self.token.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            return Err(Interrupted::Cancelled);
+        }
+        match self.deadline {
+            Some(deadline) if deadline <= Instant::now() => Err(Interrupted::DeadlineExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// A future completing when the run is interrupted, waiting for the
+    /// deadline on the timer. It never completes if neither is set.
+    pub fn wait<T>(&self, timer: &T) -> BoxFuture<'static, Interrupted>
+    where
+        T: ?Sized + Timer,
+    {
+        let cancelled = match self.token {
+            Some(ref token) => token.cancelled().map(|()| Interrupted::Cancelled).boxed(),
+            None => future::pending().boxed(),
+        };
+        let expired = match self.deadline {
+            Some(deadline) => timer
+                .sleep(deadline.saturating_duration_since(Instant::now()))
+                .map(|()| Interrupted::DeadlineExceeded)
+                .boxed(),
+            None => future::pending().boxed(),
+        };
+        future::select(cancelled, expired)
+            .map(|either| either.factor_first().0)
+            .boxed()
+    }
+}
+
+/// The error of the runs stopped by an `Interrupt`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interrupted {
+    /// The cancellation token is cancelled
+    Cancelled,
+    /// The deadline has passed
+    DeadlineExceeded,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Interrupted::Cancelled => f.write_str("transaction cancelled"),
+            Interrupted::DeadlineExceeded => f.write_str("transaction deadline exceeded"),
+        }
+    }
+}
+
+impl Error for Interrupted {}
@@ -0,0 +1,61 @@
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// join 2 indepndant transactions. As they share the context, they are run
+/// one after another.
+pub fn join<Ctx, A, B>(a: A, b: B) -> Join<A::Tx, B::Tx>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    B: IntoAsyncTransaction<Ctx, Err = A::Err>,
+{
+    Join {
+        tx1: a.into_async_transaction(),
+        tx2: b.into_async_transaction(),
+    }
+}
+
+/// The result of `join`
+#[derive(Debug)]
+#[must_use]
+pub struct Join<Tx1, Tx2> {
+    tx1: Tx1,
+    tx2: Tx2,
+}
+
+impl<Tx1, Tx2> Join<Tx1, Tx2> {
+    /// The transactions joined, e.g. for backends running them at once
+    pub fn parts(&self) -> (&Tx1, &Tx2) {
+        (&self.tx1, &self.tx2)
+    }
+}
+
+impl<Tx1, Tx2> AsyncTransaction for Join<Tx1, Tx2>
+where
+    Tx1: AsyncTransaction,
+    Tx2: AsyncTransaction<Ctx = Tx1::Ctx, Err = Tx1::Err>,
+{
+    type Ctx = Tx1::Ctx;
+    type Item = (Tx1::Item, Tx2::Item);
+    type Err = Tx1::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let r1 = self.tx1.run_async(ctx).await?;
+            let r2 = self.tx2.run_async(ctx).await?;
+            Ok((r1, r2))
+        })
+    }
+}
+
+impl<Tx1, Tx2> Visit for Join<Tx1, Tx2>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("join"), |v| {
+            self.tx1.accept(v);
+            self.tx2.accept(v);
+        });
+    }
+}
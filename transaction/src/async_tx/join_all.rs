@@ -0,0 +1,59 @@
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// join a vec of transaction
+pub fn join_all<Ctx, I, B>(i: I) -> JoinAll<B::Tx>
+where
+    I: IntoIterator<Item = B>,
+    B: IntoAsyncTransaction<Ctx>,
+{
+    JoinAll {
+        vec: i.into_iter()
+            .map(IntoAsyncTransaction::into_async_transaction)
+            .collect(),
+    }
+}
+
+/// The result of `join_all`
+#[derive(Debug)]
+#[must_use]
+pub struct JoinAll<Tx> {
+    vec: Vec<Tx>,
+}
+
+impl<Tx> JoinAll<Tx> {
+    /// The transactions joined, e.g. for backends running them at once
+    pub fn transactions(&self) -> &[Tx] {
+        &self.vec
+    }
+}
+
+impl<Tx> AsyncTransaction for JoinAll<Tx>
+where
+    Tx: AsyncTransaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Vec<Tx::Item>;
+    type Err = Tx::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let mut items = Vec::with_capacity(self.vec.len());
+            for tx in &self.vec {
+                items.push(tx.run_async(ctx).await?);
+            }
+            Ok(items)
+        })
+    }
+}
+
+impl<Tx> Visit for JoinAll<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("join_all"), |v| for tx in &self.vec {
+            tx.accept(v);
+        });
+    }
+}
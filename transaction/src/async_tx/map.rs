@@ -0,0 +1,46 @@
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+pub fn map<Ctx, A, F, B>(a: A, f: F) -> Map<A::Tx, F>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    F: Fn(A::Item) -> B + Send + Sync,
+    B: Send,
+{
+    Map {
+        tx: a.into_async_transaction(),
+        f,
+    }
+}
+
+/// The result of `map`
+#[derive(Debug)]
+#[must_use]
+pub struct Map<Tx, F> {
+    tx: Tx,
+    f: F,
+}
+
+impl<Tx, U, F> AsyncTransaction for Map<Tx, F>
+where
+    Tx: AsyncTransaction,
+    F: Fn(Tx::Item) -> U + Send + Sync,
+    U: Send,
+{
+    type Ctx = Tx::Ctx;
+    type Item = U;
+    type Err = Tx::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { self.tx.run_async(ctx).await.map(&self.f) })
+    }
+}
+
+impl<Tx, F> Visit for Map<Tx, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("map"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,46 @@
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+pub fn map_err<Ctx, A, F, B>(a: A, f: F) -> MapErr<A::Tx, F>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    F: Fn(A::Err) -> B + Send + Sync,
+    B: Send,
+{
+    MapErr {
+        tx: a.into_async_transaction(),
+        f,
+    }
+}
+
+/// The result of `map_err`
+#[derive(Debug)]
+#[must_use]
+pub struct MapErr<Tx, F> {
+    tx: Tx,
+    f: F,
+}
+
+impl<Tx, E, F> AsyncTransaction for MapErr<Tx, F>
+where
+    Tx: AsyncTransaction,
+    F: Fn(Tx::Err) -> E + Send + Sync,
+    E: Send,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = E;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move { self.tx.run_async(ctx).await.map_err(&self.f) })
+    }
+}
+
+impl<Tx, F> Visit for MapErr<Tx, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("map_err"), |v| self.tx.accept(v));
+    }
+}
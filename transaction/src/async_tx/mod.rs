@@ -0,0 +1,276 @@
+//! Asynchronous transactions, enabled by the `async` feature.
+//!
+//! `AsyncTransaction` mirrors `Transaction` for the backends whose drivers
+//! return futures. The context is borrowed for the whole run, so the steps of
+//! a transaction are run one after another, just like the synchronous ones.
+//! Each combinator boxes the future of its run, and is also a free function
+//! taking the transaction first, like the synchronous ones.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate futures;
+//! extern crate transaction;
+//!
+//! use transaction::async_tx::{self, AsyncTransaction, AsyncTransactionExt};
+//!
+//! # fn main() {
+//! let tx = async_tx::ok::<Vec<i32>, _, ()>(1)
+//!     .join(async_tx::ok(2))
+//!     .and_then(|(a, b)| async_tx::ok(a + b))
+//!     .or_else(|()| async_tx::ok::<_, _, ()>(0));
+//! let mut ctx = Vec::new();
+//! assert_eq!(futures::executor::block_on(tx.run_async(&mut ctx)), Ok(3));
+//! # }
+//! ```
+
+pub use futures::future::BoxFuture;
+
+mod then;
+mod boxed;
+mod map;
+mod and_then;
+mod map_err;
+mod or_else;
+mod join;
+mod join_all;
+mod retry;
+mod retry_with;
+mod race;
+mod select_ok;
+mod result;
+mod ok;
+mod err;
+mod named;
+mod with_ctx;
+mod from_sync;
+mod blocking;
+mod interrupt;
+mod runner;
+mod chaos;
+mod bulkhead;
+
+pub use self::and_then::*;
+pub use self::blocking::*;
+pub use self::boxed::*;
+pub use self::bulkhead::*;
+pub use self::chaos::*;
+pub use self::err::*;
+pub use self::from_sync::*;
+pub use self::interrupt::*;
+pub use self::join::*;
+pub use self::join_all::*;
+pub use self::map::*;
+pub use self::map_err::*;
+pub use self::named::*;
+pub use self::ok::*;
+pub use self::or_else::*;
+pub use self::race::*;
+pub use self::result::*;
+pub use self::retry::*;
+pub use self::retry_with::*;
+pub use self::runner::*;
+pub use self::select_ok::*;
+pub use self::then::*;
+pub use self::with_ctx::*;
+
+/// The future of the run of an asynchronous transaction
+pub type AsyncRun<'a, T, E> = BoxFuture<'a, Result<T, E>>;
+
+/// An abstract asynchronous transaction, the counterpart of `Transaction` for
+/// the backends whose operations are futures. Note that this transaction is
+/// not executed until the future returned by `run_async` is polled.
+#[must_use]
+pub trait AsyncTransaction: Send + Sync {
+    /// The contxt type (i.e. transaction type) of the transaction
+    type Ctx: Send;
+    /// The return type of the transaction
+    type Item: Send;
+    /// The error type of the transaction
+    type Err: Send;
+
+    /// Run the transaction. This will called by transaction runner rather than
+    /// user by hand.
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err>;
+
+    /// The label of the transaction given by `named`, if any.
+    fn label(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// The combinators of the asynchronous transactions, implemented for all of
+/// them like `TransactionExt` for the synchronous ones.
+pub trait AsyncTransactionExt: AsyncTransaction {
+    /// Box the transaction
+    fn boxed<'a>(
+        self,
+    ) -> Box<dyn AsyncTransaction<Ctx = Self::Ctx, Item = Self::Item, Err = Self::Err> + 'a>
+    where
+        Self: Sized + 'a,
+    {
+        boxed(self)
+    }
+
+    /// Take the previous result of computation and do another computation
+    fn then<F, Tx2>(self, f: F) -> Then<Self, F, Tx2>
+    where
+        Tx2: IntoAsyncTransaction<Self::Ctx, Err = Self::Err>,
+        F: Fn(Result<Self::Item, Self::Err>) -> Tx2 + Send + Sync,
+        Self: Sized,
+    {
+        then(self, f)
+    }
+
+    /// Attach a label to the transaction
+    fn named<L>(self, label: L) -> Named<Self>
+    where
+        L: Into<::std::borrow::Cow<'static, str>>,
+        Self: Sized,
+    {
+        named(self, label)
+    }
+
+    /// Transform the previous successful value
+    fn map<F, B>(self, f: F) -> Map<Self, F>
+    where
+        F: Fn(Self::Item) -> B + Send + Sync,
+        B: Send,
+        Self: Sized,
+    {
+        map(self, f)
+    }
+
+    /// Take the previous successful value of computation and do another
+    /// computation
+    fn and_then<F, B>(self, f: F) -> AndThen<Self, F, B>
+    where
+        B: IntoAsyncTransaction<Self::Ctx, Err = Self::Err>,
+        F: Fn(Self::Item) -> B + Send + Sync,
+        Self: Sized,
+    {
+        and_then(self, f)
+    }
+
+    /// Transform the previous error value
+    fn map_err<F, B>(self, f: F) -> MapErr<Self, F>
+    where
+        F: Fn(Self::Err) -> B + Send + Sync,
+        B: Send,
+        Self: Sized,
+    {
+        map_err(self, f)
+    }
+
+    /// Take the previous error value of computation and do another computation.
+    /// This may be used falling back
+    fn or_else<F, B>(self, f: F) -> OrElse<Self, F, B>
+    where
+        B: IntoAsyncTransaction<Self::Ctx, Item = Self::Item>,
+        F: Fn(Self::Err) -> B + Send + Sync,
+        Self: Sized,
+    {
+        or_else(self, f)
+    }
+
+    /// Run the alternative concurrently and take the first success
+    fn race<B>(self, b: B) -> Race<Self, B::Tx>
+    where
+        B: IntoAsyncTransaction<Self::Ctx, Item = Self::Item, Err = Self::Err>,
+        Self: Sized,
+    {
+        race(self, b)
+    }
+
+    /// Run the transaction again with the delays of the policy while it fails
+    fn retry_with<P, T>(self, policy: P, timer: T) -> RetryWith<Self, P, T>
+    where
+        P: crate::RetryPolicy + Send + Sync,
+        T: Timer,
+        Self: Sized,
+    {
+        retry_with(self, policy, timer)
+    }
+
+    /// join 2 indepndant transactions
+    fn join<B>(self, b: B) -> Join<Self, B::Tx>
+    where
+        B: IntoAsyncTransaction<Self::Ctx, Err = Self::Err>,
+        Self: Sized,
+    {
+        join(self, b)
+    }
+}
+
+impl<Tx> AsyncTransactionExt for Tx where Tx: AsyncTransaction + ?Sized {}
+
+/// types than can be converted into asynchronous transaction
+pub trait IntoAsyncTransaction<Ctx> {
+    type Tx: AsyncTransaction<Ctx = Ctx, Item = Self::Item, Err = Self::Err>;
+    type Err: Send;
+    type Item: Send;
+
+    fn into_async_transaction(self) -> Self::Tx;
+}
+
+impl<Tx, Ctx> IntoAsyncTransaction<Ctx> for Tx
+where
+    Tx: AsyncTransaction<Ctx = Ctx>,
+{
+    type Tx = Tx;
+    type Err = Tx::Err;
+    type Item = Tx::Item;
+
+    fn into_async_transaction(self) -> Self::Tx {
+        self
+    }
+}
+
+impl<Ctx, T, E> IntoAsyncTransaction<Ctx> for Result<T, E>
+where
+    Ctx: Send,
+    T: Clone + Send + Sync,
+    E: Clone + Send + Sync,
+{
+    type Tx = TxResult<Ctx, T, E>;
+    type Err = E;
+    type Item = T;
+
+    fn into_async_transaction(self) -> Self::Tx {
+        result(self)
+    }
+}
+
+impl<T> AsyncTransaction for Box<T>
+where
+    T: ?Sized + AsyncTransaction,
+{
+    type Ctx = T::Ctx;
+    type Item = T::Item;
+    type Err = T::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        (**self).run_async(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        (**self).label()
+    }
+}
+
+impl<T> AsyncTransaction for &T
+where
+    T: ?Sized + AsyncTransaction,
+{
+    type Ctx = T::Ctx;
+    type Item = T::Item;
+    type Err = T::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        (**self).run_async(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        (**self).label()
+    }
+}
@@ -0,0 +1,57 @@
+use std::borrow::Cow;
+
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Attach a label to the transaction. The label doesn't change the
+/// computation but is visible to runners and tooling walking the transaction.
+pub fn named<Ctx, A, L>(a: A, label: L) -> Named<A::Tx>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    L: Into<Cow<'static, str>>,
+{
+    Named {
+        tx: a.into_async_transaction(),
+        label: label.into(),
+    }
+}
+
+/// The result of `named`
+#[derive(Debug)]
+#[must_use]
+pub struct Named<Tx> {
+    tx: Tx,
+    label: Cow<'static, str>,
+}
+
+impl<Tx> AsyncTransaction for Named<Tx>
+where
+    Tx: AsyncTransaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        #[cfg(feature = "log")]
+        log::trace!("running step {:?}", self.label);
+        self.tx.run_async(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        Some(&self.label)
+    }
+}
+
+impl<Tx> Visit for Named<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        let node = Node {
+            kind: "named",
+            label: Some(&self.label),
+        };
+        visit_node(visitor, node, |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,41 @@
+use std::marker::PhantomData;
+
+use super::{AsyncRun, AsyncTransaction};
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// make a successful transaction value.
+pub fn ok<Ctx, T, E>(t: T) -> TxOk<Ctx, T, E> {
+    TxOk {
+        ok: t,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `ok`
+#[derive(Debug)]
+#[must_use]
+pub struct TxOk<Ctx, T, E> {
+    ok: T,
+    _phantom: PhantomData<fn() -> (Ctx, E)>,
+}
+
+impl<Ctx, T, E> AsyncTransaction for TxOk<Ctx, T, E>
+where
+    Ctx: Send,
+    T: Clone + Send + Sync,
+    E: Send,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run_async<'a>(&'a self, _ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(::futures::future::ready(Ok(self.ok.clone())))
+    }
+}
+
+impl<Ctx, T, E> Visit for TxOk<Ctx, T, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("ok"));
+    }
+}
@@ -0,0 +1,55 @@
+use std::marker::PhantomData;
+
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+pub fn or_else<Ctx, A, F, B>(a: A, f: F) -> OrElse<A::Tx, F, B>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    B: IntoAsyncTransaction<Ctx, Item = A::Item>,
+    F: Fn(A::Err) -> B + Send + Sync,
+{
+    OrElse {
+        tx: a.into_async_transaction(),
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `or_else`
+#[derive(Debug)]
+#[must_use]
+pub struct OrElse<Tx1, F, Tx2> {
+    tx: Tx1,
+    f: F,
+    _phantom: PhantomData<fn() -> Tx2>,
+}
+
+impl<Tx, Tx2, F> AsyncTransaction for OrElse<Tx, F, Tx2>
+where
+    Tx2: IntoAsyncTransaction<Tx::Ctx, Item = Tx::Item>,
+    Tx: AsyncTransaction,
+    F: Fn(Tx::Err) -> Tx2 + Send + Sync,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx2::Item;
+    type Err = Tx2::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            match self.tx.run_async(ctx).await {
+                Ok(item) => Ok(item),
+                Err(e) => (self.f)(e).into_async_transaction().run_async(ctx).await,
+            }
+        })
+    }
+}
+
+impl<Tx1, F, Tx2> Visit for OrElse<Tx1, F, Tx2>
+where
+    Tx1: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("or_else"), |v| self.tx.accept(v));
+    }
+}
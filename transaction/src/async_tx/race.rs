@@ -0,0 +1,93 @@
+use futures::future;
+
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Run 2 alternative transactions concurrently and take the first success,
+/// cancelling the other. If both fail, the error of the one failing last is
+/// returned.
+///
+/// Each alternative runs on its own clone of the context and the context of
+/// the successful one replaces the original, so the context should be a
+/// cheaply clonable handle, e.g. a client of a replicated store.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate transaction;
+///
+/// use transaction::async_tx::{self, AsyncTransaction, AsyncTransactionExt};
+///
+/// # fn main() {
+/// let primary = async_tx::err::<(), &str, _>("timeout");
+/// let replica = async_tx::ok("row");
+/// let tx = primary.race(replica);
+/// assert_eq!(futures::executor::block_on(tx.run_async(&mut ())), Ok("row"));
+/// # }
+/// ```
+pub fn race<Ctx, A, B>(a: A, b: B) -> Race<A::Tx, B::Tx>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    B: IntoAsyncTransaction<Ctx, Item = A::Item, Err = A::Err>,
+{
+    Race {
+        tx1: a.into_async_transaction(),
+        tx2: b.into_async_transaction(),
+    }
+}
+
+/// The result of `race`
+#[derive(Debug)]
+#[must_use]
+pub struct Race<Tx1, Tx2> {
+    tx1: Tx1,
+    tx2: Tx2,
+}
+
+impl<Tx1, Tx2> AsyncTransaction for Race<Tx1, Tx2>
+where
+    Tx1: AsyncTransaction,
+    Tx1::Ctx: Clone,
+    Tx2: AsyncTransaction<Ctx = Tx1::Ctx, Item = Tx1::Item, Err = Tx1::Err>,
+{
+    type Ctx = Tx1::Ctx;
+    type Item = Tx1::Item;
+    type Err = Tx1::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let alternatives = vec![
+                run_on_clone(&self.tx1, ctx.clone()),
+                run_on_clone(&self.tx2, ctx.clone()),
+            ];
+            let ((item, winner), _cancelled) = future::select_ok(alternatives).await?;
+            *ctx = winner;
+            Ok(item)
+        })
+    }
+}
+
+impl<Tx1, Tx2> Visit for Race<Tx1, Tx2>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("race"), |v| {
+            self.tx1.accept(v);
+            self.tx2.accept(v);
+        });
+    }
+}
+
+/// Run the transaction on its own context, giving the context back on success
+pub(crate) fn run_on_clone<Tx>(tx: &Tx, mut ctx: Tx::Ctx) -> AsyncRun<'_, (Tx::Item, Tx::Ctx), Tx::Err>
+where
+    Tx: AsyncTransaction,
+{
+    Box::pin(async move {
+        let item = tx.run_async(&mut ctx).await?;
+        Ok((item, ctx))
+    })
+}
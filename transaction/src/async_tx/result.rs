@@ -0,0 +1,41 @@
+use std::marker::PhantomData;
+
+use super::{AsyncRun, AsyncTransaction};
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// The result of `result`
+#[derive(Debug)]
+#[must_use]
+pub struct TxResult<Ctx, T, E> {
+    r: Result<T, E>,
+    _phantom: PhantomData<fn() -> Ctx>,
+}
+
+/// Take a result and make a leaf transaction value.
+pub fn result<Ctx, T, E>(r: Result<T, E>) -> TxResult<Ctx, T, E> {
+    TxResult {
+        r,
+        _phantom: PhantomData,
+    }
+}
+
+impl<Ctx, T, E> AsyncTransaction for TxResult<Ctx, T, E>
+where
+    Ctx: Send,
+    T: Clone + Send + Sync,
+    E: Clone + Send + Sync,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run_async<'a>(&'a self, _ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(::futures::future::ready(self.r.clone()))
+    }
+}
+
+impl<Ctx, T, E> Visit for TxResult<Ctx, T, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("result"));
+    }
+}
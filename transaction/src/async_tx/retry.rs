@@ -0,0 +1,65 @@
+use std::marker::PhantomData;
+
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::metrics;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// Run the transaction made by `f` up to `n` times until it succeeds. The
+/// attempt number starting from 0 is passed to `f`. The errors of all the
+/// attempts are returned if none succeeds.
+pub fn retry<Ctx, F, Tx>(n: usize, f: F) -> Retry<Ctx, F, Tx>
+where
+    Tx: IntoAsyncTransaction<Ctx>,
+    F: Fn(usize) -> Tx + Send + Sync,
+{
+    Retry {
+        n,
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `retry`
+#[derive(Debug)]
+#[must_use]
+pub struct Retry<Ctx, F, Tx> {
+    n: usize,
+    f: F,
+    _phantom: PhantomData<fn() -> (Tx, Ctx)>,
+}
+
+impl<Ctx, F, Tx> AsyncTransaction for Retry<Ctx, F, Tx>
+where
+    Ctx: Send,
+    F: Fn(usize) -> Tx + Send + Sync,
+    Tx: IntoAsyncTransaction<Ctx>,
+{
+    type Ctx = Ctx;
+    type Item = Tx::Item;
+    type Err = Vec<Tx::Err>;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let mut errors = Vec::new();
+            for i in 0..self.n {
+                let tx = (self.f)(i).into_async_transaction();
+                if i != 0 {
+                    metrics::record_retry(tx.label());
+                }
+                match tx.run_async(ctx).await {
+                    Ok(t) => return Ok(t),
+                    Err(e) => errors.push(e),
+                }
+                #[cfg(feature = "log")]
+                log::debug!("attempt {} of {} failed", i + 1, self.n);
+            }
+            Err(errors)
+        })
+    }
+}
+
+impl<Ctx, F, Tx> Visit for Retry<Ctx, F, Tx> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("retry"));
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use super::{AsyncRun, AsyncTransaction, BoxFuture, IntoAsyncTransaction};
+use crate::metrics;
+use crate::retry_policy::RetryPolicy;
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// The timer of an async runtime, e.g. `tokio::time::sleep`.
+pub trait Timer: Send + Sync {
+    /// A future completing after `duration`
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+impl<T> Timer for &T
+where
+    T: ?Sized + Timer,
+{
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        (**self).sleep(duration)
+    }
+}
+
+/// Run the transaction again while it fails and the policy allows, waiting
+/// for the delays given by the policy on the timer. The error of the last
+/// attempt is returned when the policy gives up.
+///
+/// The future can be dropped safely while waiting between attempts: no
+/// attempt is in progress then, so the context is left as the last failed
+/// attempt left it and the remaining attempts are not made.
+pub fn retry_with<Ctx, A, P, T>(a: A, policy: P, timer: T) -> RetryWith<A::Tx, P, T>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    P: RetryPolicy + Send + Sync,
+    T: Timer,
+{
+    RetryWith {
+        tx: a.into_async_transaction(),
+        policy,
+        timer,
+    }
+}
+
+/// The result of `retry_with`
+#[derive(Debug)]
+#[must_use]
+pub struct RetryWith<Tx, P, T> {
+    tx: Tx,
+    policy: P,
+    timer: T,
+}
+
+impl<Tx, P, T> AsyncTransaction for RetryWith<Tx, P, T>
+where
+    Tx: AsyncTransaction,
+    P: RetryPolicy + Send + Sync,
+    T: Timer,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let mut retries = 0;
+            loop {
+                let e = match self.tx.run_async(ctx).await {
+                    Ok(t) => return Ok(t),
+                    Err(e) => e,
+                };
+                let delay = match self.policy.next_delay(retries) {
+                    Some(delay) => delay,
+                    None => return Err(e),
+                };
+                #[cfg(feature = "log")]
+                log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+                self.timer.sleep(delay).await;
+                metrics::record_retry(self.tx.label());
+                retries += 1;
+            }
+        })
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, P, T> Visit for RetryWith<Tx, P, T>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("retry_with"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,45 @@
+use super::{AsyncTransaction, BoxFuture, Interrupt, Interrupted};
+
+/// A runner of asynchronous transactions, acquiring a backend context,
+/// running the transaction on it and committing or rolling it back.
+///
+/// # Interruption
+///
+/// `run_async_with` stops the run when the `Interrupt` fires, and all the
+/// runners follow the same rules:
+///
+/// * When the run is already interrupted, nothing is acquired.
+/// * Acquiring the context is not interrupted, since a half-begun backend
+///   transaction can't be abandoned safely. The interruption is checked
+///   again once the context is acquired.
+/// * Running the transaction is interrupted: its future is dropped and the
+///   backend transaction is rolled back before the context is given back,
+///   so it is never left dangling on a pooled connection.
+/// * Committing and rolling back are not interrupted.
+///
+/// An interrupted run fails with `Interrupted` converted into the error of
+/// the transaction.
+pub trait AsyncRunner: Send + Sync {
+    /// The context lent to the transactions
+    type Ctx: Send;
+    /// The error of acquiring, committing and rolling back the context
+    type Error;
+
+    /// Run the transaction, committing it if it succeeds and rolling it back
+    /// otherwise.
+    fn run_async<'a, Tx>(&'a self, tx: Tx) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = Self::Ctx> + 'a,
+        Tx::Err: From<Self::Error>;
+
+    /// Run the transaction like `run_async`, stopping and rolling it back
+    /// when interrupted.
+    fn run_async_with<'a, Tx>(
+        &'a self,
+        tx: Tx,
+        interrupt: Interrupt,
+    ) -> BoxFuture<'a, Result<Tx::Item, Tx::Err>>
+    where
+        Tx: AsyncTransaction<Ctx = Self::Ctx> + 'a,
+        Tx::Err: From<Self::Error> + From<Interrupted>;
+}
@@ -0,0 +1,86 @@
+use futures::future;
+
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use super::race::run_on_clone;
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Run alternative transactions concurrently and take the first success,
+/// cancelling the rest. If all fail, the error of the one failing last is
+/// returned.
+///
+/// Like `race`, each alternative runs on its own clone of the context and
+/// the context of the successful one replaces the original.
+///
+/// # Panics
+///
+/// Panics if no transaction is given.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate transaction;
+///
+/// use transaction::async_tx::{self, AsyncTransaction};
+///
+/// # fn main() {
+/// // read the row from whichever replica answers first
+/// let read = |replica: usize| async_tx::with_ctx(move |_: &mut ()| {
+///     let ret = if replica == 0 { Err("down") } else { Ok(replica) };
+///     Box::pin(futures::future::ready(ret))
+/// });
+/// let tx = async_tx::select_ok(vec![read(0), read(1)]);
+/// assert_eq!(futures::executor::block_on(tx.run_async(&mut ())), Ok(1));
+/// # }
+/// ```
+pub fn select_ok<Ctx, I, B>(i: I) -> SelectOk<B::Tx>
+where
+    I: IntoIterator<Item = B>,
+    B: IntoAsyncTransaction<Ctx>,
+{
+    let vec = i.into_iter()
+        .map(IntoAsyncTransaction::into_async_transaction)
+        .collect::<Vec<_>>();
+    assert!(!vec.is_empty(), "select_ok needs at least one transaction");
+    SelectOk { vec }
+}
+
+/// The result of `select_ok`
+#[derive(Debug)]
+#[must_use]
+pub struct SelectOk<Tx> {
+    vec: Vec<Tx>,
+}
+
+impl<Tx> AsyncTransaction for SelectOk<Tx>
+where
+    Tx: AsyncTransaction,
+    Tx::Ctx: Clone,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let alternatives = self.vec
+                .iter()
+                .map(|tx| run_on_clone(tx, ctx.clone()))
+                .collect::<Vec<_>>();
+            let ((item, winner), _cancelled) = future::select_ok(alternatives).await?;
+            *ctx = winner;
+            Ok(item)
+        })
+    }
+}
+
+impl<Tx> Visit for SelectOk<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("select_ok"), |v| for tx in &self.vec {
+            tx.accept(v);
+        });
+    }
+}
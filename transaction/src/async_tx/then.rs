@@ -0,0 +1,53 @@
+use std::marker::PhantomData;
+
+use super::{AsyncRun, AsyncTransaction, IntoAsyncTransaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+pub fn then<Ctx, A, F, Tx2>(a: A, f: F) -> Then<A::Tx, F, Tx2>
+where
+    A: IntoAsyncTransaction<Ctx>,
+    Tx2: IntoAsyncTransaction<Ctx, Err = A::Err>,
+    F: Fn(Result<A::Item, A::Err>) -> Tx2 + Send + Sync,
+{
+    Then {
+        tx: a.into_async_transaction(),
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `then`
+#[derive(Debug)]
+#[must_use]
+pub struct Then<Tx1, F, Tx2> {
+    tx: Tx1,
+    f: F,
+    _phantom: PhantomData<fn() -> Tx2>,
+}
+
+impl<Tx, Tx2, F> AsyncTransaction for Then<Tx, F, Tx2>
+where
+    Tx2: IntoAsyncTransaction<Tx::Ctx, Err = Tx::Err>,
+    Tx: AsyncTransaction,
+    F: Fn(Result<Tx::Item, Tx::Err>) -> Tx2 + Send + Sync,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx2::Item;
+    type Err = Tx2::Err;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        Box::pin(async move {
+            let ret = self.tx.run_async(ctx).await;
+            (self.f)(ret).into_async_transaction().run_async(ctx).await
+        })
+    }
+}
+
+impl<Tx1, F, Tx2> Visit for Then<Tx1, F, Tx2>
+where
+    Tx1: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("then"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,75 @@
+use std::marker::PhantomData;
+
+use super::{AsyncRun, AsyncTransaction};
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// Receive the context from the executing transaction and perform
+/// asynchronous computation. This wraps the future-returning operations of
+/// async drivers as transactions.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate transaction;
+///
+/// use futures::future::{BoxFuture, FutureExt};
+/// use transaction::async_tx::{self, AsyncTransaction, AsyncTransactionExt};
+///
+/// struct Conn {
+///     rows: Vec<i32>,
+/// }
+///
+/// impl Conn {
+///     fn insert(&mut self, row: i32) -> BoxFuture<'_, Result<usize, ()>> {
+///         self.rows.push(row);
+///         futures::future::ready(Ok(self.rows.len())).boxed()
+///     }
+/// }
+///
+/// # fn main() {
+/// let tx = async_tx::with_ctx(|conn: &mut Conn| conn.insert(1))
+///     .and_then(|_| async_tx::with_ctx(|conn: &mut Conn| conn.insert(2)));
+/// let mut conn = Conn { rows: Vec::new() };
+/// assert_eq!(futures::executor::block_on(tx.run_async(&mut conn)), Ok(2));
+/// # }
+/// ```
+pub fn with_ctx<Ctx, F, T, E>(f: F) -> WithCtx<Ctx, F>
+where
+    F: for<'c> Fn(&'c mut Ctx) -> AsyncRun<'c, T, E> + Send + Sync,
+{
+    WithCtx {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_ctx`
+#[derive(Debug)]
+#[must_use]
+pub struct WithCtx<Ctx, F> {
+    f: F,
+    _phantom: PhantomData<fn() -> Ctx>,
+}
+
+impl<Ctx, T, E, F> AsyncTransaction for WithCtx<Ctx, F>
+where
+    Ctx: Send,
+    T: Send,
+    E: Send,
+    F: for<'c> Fn(&'c mut Ctx) -> AsyncRun<'c, T, E> + Send + Sync,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run_async<'a>(&'a self, ctx: &'a mut Self::Ctx) -> AsyncRun<'a, Self::Item, Self::Err> {
+        (self.f)(ctx)
+    }
+}
+
+impl<Ctx, F> Visit for WithCtx<Ctx, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_ctx"));
+    }
+}
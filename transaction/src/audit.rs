@@ -0,0 +1,160 @@
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::{clock, IntoTransaction, Transaction};
+use crate::hooks::Outcome;
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Record an audit entry to the sink after each run of the transaction.
+/// The redactor decides what part of the result, if any, is recorded as
+/// the payload.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::sync::{Arc, Mutex};
+/// use transaction::prelude::*;
+/// use transaction::{AuditEntry, NoPayload};
+/// use transaction::hooks::Outcome;
+///
+/// # fn main() {
+/// let sink = Mutex::new(Vec::<AuditEntry>::new());
+/// let tx = err::<(), (), &str>("insufficient funds")
+///     .named("transfer")
+///     .audited(&sink, NoPayload);
+/// assert!(tx.run(&mut ()).is_err());
+///
+/// let entries = sink.lock().unwrap();
+/// assert_eq!(entries[0].label, Some("transfer".to_string()));
+/// assert_eq!(entries[0].outcome, Outcome::RolledBack);
+/// assert_eq!(entries[0].payload, None);
+/// # }
+/// ```
+pub fn audited<Ctx, A, S, R>(a: A, sink: S, redactor: R) -> Audited<A::Tx, S, R>
+where
+    A: IntoTransaction<Ctx>,
+    S: AuditSink,
+    R: Redactor<A::Item, A::Err>,
+{
+    Audited {
+        tx: a.into_transaction(),
+        sink,
+        redactor,
+    }
+}
+
+/// The result of `audited`
+#[derive(Debug)]
+#[must_use]
+pub struct Audited<Tx, S, R> {
+    tx: Tx,
+    sink: S,
+    redactor: R,
+}
+
+/// An entry of the audit log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// The label of the transaction given by `named`
+    pub label: Option<String>,
+    /// When the transaction started
+    pub timestamp: SystemTime,
+    /// Whether the transaction succeeded
+    pub outcome: Outcome,
+    /// The redacted result of the transaction
+    pub payload: Option<String>,
+}
+
+/// Destination of audit entries
+pub trait AuditSink {
+    /// Record the entry
+    fn record(&self, entry: AuditEntry);
+}
+
+impl AuditSink for Mutex<Vec<AuditEntry>> {
+    fn record(&self, entry: AuditEntry) {
+        self.lock().unwrap_or_else(|e| e.into_inner()).push(entry)
+    }
+}
+
+impl<S> AuditSink for &S
+where
+    S: ?Sized + AuditSink,
+{
+    fn record(&self, entry: AuditEntry) {
+        (**self).record(entry)
+    }
+}
+
+impl<S> AuditSink for Arc<S>
+where
+    S: ?Sized + AuditSink,
+{
+    fn record(&self, entry: AuditEntry) {
+        (**self).record(entry)
+    }
+}
+
+/// Converter of the result of a transaction into the payload of the audit
+/// entry. Implementations should strip anything that must not be logged.
+pub trait Redactor<T, E> {
+    /// The payload to record, if any
+    fn redact(&self, result: &Result<T, E>) -> Option<String>;
+}
+
+impl<T, E, F> Redactor<T, E> for F
+where
+    F: Fn(&Result<T, E>) -> Option<String>,
+{
+    fn redact(&self, result: &Result<T, E>) -> Option<String> {
+        self(result)
+    }
+}
+
+/// Redactor recording no payload
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoPayload;
+
+impl<T, E> Redactor<T, E> for NoPayload {
+    fn redact(&self, _result: &Result<T, E>) -> Option<String> {
+        None
+    }
+}
+
+impl<Tx, S, R> Transaction for Audited<Tx, S, R>
+where
+    Tx: Transaction,
+    S: AuditSink,
+    R: Redactor<Tx::Item, Tx::Err>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let timestamp = clock::system_time();
+        let ret = self.tx.run(ctx);
+        self.sink.record(AuditEntry {
+            label: self.tx.label().map(str::to_string),
+            timestamp,
+            outcome: Outcome::of(&ret),
+            payload: self.redactor.redact(&ret),
+        });
+        ret
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, S, R> Visit for Audited<Tx, S, R>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("audited"), |v| self.tx.accept(v));
+    }
+}
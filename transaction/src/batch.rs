@@ -0,0 +1,251 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Contexts loading values of type `V` by keys of type `K` many at once,
+/// e.g. by a single `SELECT .. WHERE id IN (..)`, for `batched_get`
+pub trait Batcher<K, V> {
+    /// The error of loading
+    type Err;
+
+    /// Load the values of the keys in one round trip. The keys without a
+    /// value are left out of the map.
+    fn load_many(&mut self, keys: &[K]) -> Result<HashMap<K, V>, Self::Err>;
+
+    /// The keys to load and the values loaded, kept by the context
+    fn batch(&mut self) -> &mut Batch<K, V>;
+}
+
+/// The keys waiting to be loaded by a `Batcher` and the values it loaded
+#[derive(Debug, Clone)]
+pub struct Batch<K, V> {
+    pending: Vec<K>,
+    loaded: HashMap<K, Option<V>>,
+    loads: usize,
+}
+
+impl<K, V> Default for Batch<K, V> {
+    fn default() -> Self {
+        Batch {
+            pending: Vec::new(),
+            loaded: HashMap::new(),
+            loads: 0,
+        }
+    }
+}
+
+impl<K, V> Batch<K, V>
+where
+    K: Hash + Eq,
+{
+    /// Make an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the key with the next load, unless it is loaded already
+    pub fn prefetch(&mut self, key: K) {
+        if !self.loaded.contains_key(&key) && !self.pending.contains(&key) {
+            self.pending.push(key)
+        }
+    }
+
+    /// Forget the value loaded for the key, e.g. after writing it
+    pub fn forget(&mut self, key: &K) {
+        self.loaded.remove(key);
+    }
+
+    /// Forget the keys to load and the values loaded
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.loaded.clear();
+    }
+
+    /// The number of round trips made by the `Batcher`
+    pub fn loads(&self) -> usize {
+        self.loads
+    }
+}
+
+/// Get the value of the key from a `Batcher` context, loading it together
+/// with the keys of the other reads known so far.
+///
+/// Reads of a key already loaded are answered without a round trip, and
+/// the first read loads the keys of all the `batched_get`s statically
+/// known in the transaction run by `batched`, e.g. of those joined
+/// together, at once. Reads by keys made at run time, e.g. in the closure
+/// of `and_then`, are loaded when they run.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::collections::HashMap;
+/// use transaction::prelude::*;
+/// use transaction::{batched_get, Batch, Batcher};
+///
+/// struct Db {
+///     users: HashMap<u32, String>,
+///     batch: Batch<u32, String>,
+///     queries: Vec<Vec<u32>>,
+/// }
+///
+/// impl Batcher<u32, String> for Db {
+///     type Err = ();
+///
+///     fn load_many(&mut self, ids: &[u32]) -> Result<HashMap<u32, String>, ()> {
+///         self.queries.push(ids.to_vec());
+///         let users = &self.users;
+///         Ok(ids.iter().filter_map(|id| users.get(id).map(|name| (*id, name.clone()))).collect())
+///     }
+///
+///     fn batch(&mut self) -> &mut Batch<u32, String> {
+///         &mut self.batch
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut db = Db {
+///     users: vec![(1, "alice".to_string()), (2, "bob".to_string())].into_iter().collect(),
+///     batch: Batch::new(),
+///     queries: vec![],
+/// };
+///
+/// let names = join_all(vec![batched_get(1), batched_get(2), batched_get(1), batched_get(3)]).batched();
+/// assert_eq!(
+///     names.run(&mut db),
+///     Ok::<_, ()>(vec![Some("alice".to_string()), Some("bob".to_string()), Some("alice".to_string()), None])
+/// );
+/// // one query instead of one per read
+/// assert_eq!(db.queries, vec![vec![1, 2, 3]]);
+///
+/// // a key made at run time is loaded when read
+/// let friend = batched_get(1).join(batched_get(2)).and_then(|_| batched_get(4)).batched();
+/// assert_eq!(friend.run(&mut db), Ok::<_, ()>(None));
+/// assert_eq!(&db.queries[1..], &[vec![1, 2], vec![4]]);
+/// # }
+/// ```
+pub fn batched_get<Ctx, K, V, E>(key: K) -> BatchedGet<Ctx, K, V, E>
+where
+    Ctx: Batcher<K, V>,
+{
+    BatchedGet {
+        key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `batched_get`
+#[derive(Debug)]
+#[must_use]
+pub struct BatchedGet<Ctx, K, V, E> {
+    key: K,
+    _phantom: PhantomData<(Ctx, V, E)>,
+}
+
+impl<Ctx, K, V, E> Transaction for BatchedGet<Ctx, K, V, E>
+where
+    Ctx: Batcher<K, V>,
+    K: Hash + Eq + Clone,
+    V: Clone,
+    E: From<Ctx::Err>,
+{
+    type Ctx = Ctx;
+    type Item = Option<V>;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        if let Some(value) = ctx.batch().loaded.get(&self.key) {
+            return Ok(value.clone());
+        }
+        ctx.batch().prefetch(self.key.clone());
+        let keys = mem::take(&mut ctx.batch().pending);
+        let mut values = ctx.load_many(&keys)?;
+        let batch = ctx.batch();
+        batch.loads += 1;
+        for key in keys {
+            let value = values.remove(&key);
+            batch.loaded.insert(key, value);
+        }
+        Ok(batch.loaded[&self.key].clone())
+    }
+}
+
+impl<Ctx, K, V, E> Visit for BatchedGet<Ctx, K, V, E>
+where
+    K: Any,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("batched_get"), |visitor| {
+            visitor.read_key(&self.key)
+        });
+    }
+}
+
+/// Run the transaction loading the keys of all its statically known
+/// `batched_get`s together on the first of them, and forget the values
+/// loaded once it finishes. See `batched_get`.
+pub fn batched<Ctx, A, K, V>(a: A) -> Batched<A::Tx, K, V>
+where
+    A: IntoTransaction<Ctx>,
+    Ctx: Batcher<K, V>,
+{
+    Batched {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `batched`
+#[derive(Debug)]
+#[must_use]
+pub struct Batched<Tx, K, V> {
+    tx: Tx,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<Tx, K, V> Transaction for Batched<Tx, K, V>
+where
+    Tx: Transaction + Visit,
+    Tx::Ctx: Batcher<K, V>,
+    K: Hash + Eq + Clone + Any,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        struct Keys<'a, K, V>(&'a mut Batch<K, V>);
+        impl<'a, K, V> Visitor for Keys<'a, K, V>
+        where
+            K: Hash + Eq + Clone + Any,
+        {
+            fn enter(&mut self, _node: &Node) {}
+
+            fn read_key(&mut self, key: &dyn Any) {
+                if let Some(key) = key.downcast_ref::<K>() {
+                    self.0.prefetch(key.clone())
+                }
+            }
+        }
+        self.tx.accept(&mut Keys(ctx.batch()));
+        let ret = self.tx.run(ctx);
+        ctx.batch().clear();
+        ret
+    }
+}
+
+impl<Tx, K, V> Visit for Batched<Tx, K, V>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("batched"), |visitor| self.tx.accept(visitor));
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{IntoTransaction, Transaction};
+
+/// Box the transaction, e.g. to keep transactions of different types
+/// together.
+///
+/// A transaction nesting a boxed one in each step of a loop recurses once
+/// per step when run; use `chain` for long ones.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::boxed;
+///
+/// # fn main() {
+/// let txs = vec![boxed(ok(1)), boxed(with_ctx(|n: &mut i32| Ok(*n)))];
+/// let items = txs.iter().map(|tx| tx.run(&mut 2)).collect::<Result<Vec<i32>, ()>>();
+/// assert_eq!(items, Ok(vec![1, 2]));
+///
+/// // it can be passed as a function
+/// let txs = (0..3).map(ok).map(boxed).collect::<Vec<_>>();
+/// assert_eq!(join_all(txs).run(&mut ()), Ok::<_, ()>(vec![0, 1, 2]));
+/// # }
+/// ```
+pub fn boxed<'a, Ctx, A>(a: A) -> Box<dyn Transaction<Ctx = Ctx, Item = A::Item, Err = A::Err> + 'a>
+where
+    A: IntoTransaction<Ctx>,
+    A::Tx: 'a,
+{
+    Box::new(a.into_transaction())
+}
@@ -1,4 +1,14 @@
-use Transaction;
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Start a branch with the transaction, to be put in one of the arms
+/// by the builder
+pub fn branch<Ctx, A>(a: A) -> BranchBuilder<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    BranchBuilder::new(a.into_transaction())
+}
 
 /// BranchBuilder
 #[derive(Debug)]
@@ -47,3 +57,16 @@ where
         }
     }
 }
+
+impl<Tx1, Tx2> Visit for Branch<Tx1, Tx2>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("branch"), |v| match *self {
+            Branch::B1(ref tx) => tx.accept(v),
+            Branch::B2(ref tx) => tx.accept(v),
+        });
+    }
+}
@@ -1,4 +1,14 @@
-use Transaction;
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Start a 3 branch with the transaction, to be put in one of the arms
+/// by the builder
+pub fn branch3<Ctx, A>(a: A) -> Branch3Builder<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    Branch3Builder::new(a.into_transaction())
+}
 
 /// Branch3Builder
 #[derive(Debug)]
@@ -57,3 +67,18 @@ where
         }
     }
 }
+
+impl<Tx1, Tx2, Tx3> Visit for Branch3<Tx1, Tx2, Tx3>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+    Tx3: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("branch3"), |v| match *self {
+            Branch3::B1(ref tx) => tx.accept(v),
+            Branch3::B2(ref tx) => tx.accept(v),
+            Branch3::B3(ref tx) => tx.accept(v),
+        });
+    }
+}
@@ -1,4 +1,14 @@
-use Transaction;
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Start a 4 branch with the transaction, to be put in one of the arms
+/// by the builder
+pub fn branch4<Ctx, A>(a: A) -> Branch4Builder<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    Branch4Builder::new(a.into_transaction())
+}
 
 /// Branch4Builder
 #[derive(Debug)]
@@ -69,3 +79,20 @@ where
         }
     }
 }
+
+impl<Tx1, Tx2, Tx3, Tx4> Visit for Branch4<Tx1, Tx2, Tx3, Tx4>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+    Tx3: Visit,
+    Tx4: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("branch4"), |v| match *self {
+            Branch4::B1(ref tx) => tx.accept(v),
+            Branch4::B2(ref tx) => tx.accept(v),
+            Branch4::B3(ref tx) => tx.accept(v),
+            Branch4::B4(ref tx) => tx.accept(v),
+        });
+    }
+}
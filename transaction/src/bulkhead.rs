@@ -0,0 +1,230 @@
+//! Limits of the transactions running concurrently, so that a slow backend
+//! doesn't tie up all the worker threads or the connections of the pool.
+//!
+//! A `Bulkhead` lends a permit to each run, up to its maximum. The runs
+//! finding it full wait for a permit, at most its wait timeout, and
+//! then fail with `BulkheadFull`, which is retryable. The clones of a
+//! bulkhead share the permits, so a bulkhead can limit several runners
+//! together, e.g. the sync and the async runners of a same database.
+//!
+//! The sync runners are limited by `RunnerBuilder::bulkhead`, and the async
+//! ones by `async_tx::BulkheadRunner`. On the targets without threads, e.g.
+//! `wasm32-unknown-unknown`, the sync runs can't wait for the others, so a
+//! full bulkhead fails them at once.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use std::time::Duration;
+//! use transaction::bulkhead::{Bulkhead, BulkheadFull};
+//! use transaction::prelude::*;
+//! use transaction::runner::{Backend, RunOptions, RunnerBuilder};
+//!
+//! // a backend running the transactions on a fresh counter
+//! struct Counter;
+//!
+//! impl<T, E> Backend<T, E> for Counter {
+//!     type Ctx = i32;
+//!
+//!     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+//!     where
+//!         Tx: Transaction<Ctx = i32, Item = T, Err = E> + ?Sized,
+//!     {
+//!         tx.run(&mut 0)
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let bulkhead = Bulkhead::new(2, Duration::from_millis(10));
+//! let runner = RunnerBuilder::new(Counter).bulkhead(bulkhead.clone()).build();
+//! let incr = with_ctx(|n: &mut i32| -> Result<i32, BulkheadFull> {
+//!     *n += 1;
+//!     Ok(*n)
+//! });
+//! assert_eq!(runner.run(&incr), Ok(1));
+//!
+//! // the permits are taken by other runs
+//! let first = bulkhead.acquire(None).unwrap();
+//! let _second = bulkhead.try_acquire().unwrap();
+//! assert_eq!(bulkhead.running(), 2);
+//! assert_eq!(runner.run(&incr), Err(BulkheadFull));
+//!
+//! drop(first);
+//! assert_eq!(runner.run(&incr), Ok(1));
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::task::Waker;
+use std::time::Duration;
+
+use crate::clock::Instant;
+use crate::platform;
+use crate::retry_policy::Retryable;
+
+/// The error of the runs which found the bulkhead full for its wait timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulkheadFull;
+
+impl fmt::Display for BulkheadFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("too many transactions running concurrently")
+    }
+}
+
+impl Error for BulkheadFull {}
+
+// the running transactions are likely done when it runs again
+impl Retryable for BulkheadFull {
+    fn is_retryable(&self) -> bool {
+        true
+    }
+}
+
+/// A semaphore limiting the transactions running concurrently. The clones
+/// share the permits.
+#[derive(Debug, Clone)]
+pub struct Bulkhead {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    max_concurrent: usize,
+    max_wait: Duration,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    running: usize,
+    // the async runs waiting for a permit
+    wakers: Vec<Waker>,
+}
+
+impl Bulkhead {
+    /// Let at most `max_concurrent` transactions run at once, the others
+    /// waiting at most `max_wait` for one of them to finish
+    pub fn new(max_concurrent: usize, max_wait: Duration) -> Self {
+        Bulkhead {
+            inner: Arc::new(Inner {
+                max_concurrent,
+                max_wait,
+                state: Mutex::new(State::default()),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    /// The maximum of transactions running at once
+    pub fn max_concurrent(&self) -> usize {
+        self.inner.max_concurrent
+    }
+
+    /// How long the transactions wait for a permit
+    pub fn max_wait(&self) -> Duration {
+        self.inner.max_wait
+    }
+
+    /// The number of permits lent
+    pub fn running(&self) -> usize {
+        self.state().running
+    }
+
+    /// Take a permit if one is available, without waiting
+    pub fn try_acquire(&self) -> Option<Permit> {
+        let mut state = self.state();
+        self.take(&mut state)
+    }
+
+    /// Take a permit, waiting for one at most the wait timeout and not past
+    /// the deadline if any, blocking the thread
+    pub fn acquire(&self, deadline: Option<Instant>) -> Result<Permit, BulkheadFull> {
+        let mut state = self.state();
+        if let Some(permit) = self.take(&mut state) {
+            return Ok(permit);
+        }
+        let wait = match deadline {
+            Some(deadline) => self
+                .inner
+                .max_wait
+                .min(deadline.saturating_duration_since(Instant::now())),
+            None => self.inner.max_wait,
+        };
+        if platform::THREADS && !wait.is_zero() {
+            let max = self.inner.max_concurrent;
+            state = self
+                .inner
+                .released
+                .wait_timeout_while(state, wait, |state| state.running >= max)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        match self.take(&mut state) {
+            Some(permit) => Ok(permit),
+            None => {
+                #[cfg(feature = "log")]
+                log::warn!("bulkhead full with {} transactions running", state.running);
+                Err(BulkheadFull)
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    // take a permit for an async run, or wake it when one is given back
+    pub(crate) fn poll_acquire(&self, cx: &mut Context) -> Poll<Permit> {
+        let mut state = self.state();
+        match self.take(&mut state) {
+            Some(permit) => Poll::Ready(permit),
+            None => {
+                if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    state.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn take(&self, state: &mut State) -> Option<Permit> {
+        if state.running >= self.inner.max_concurrent {
+            return None;
+        }
+        state.running += 1;
+        Some(Permit { bulkhead: self.clone() })
+    }
+}
+
+/// A permit of a `Bulkhead`, given back when dropped, even if the
+/// transaction panicked
+#[derive(Debug)]
+#[must_use]
+pub struct Permit {
+    bulkhead: Bulkhead,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let wakers = {
+            let mut state = self.bulkhead.state();
+            state.running -= 1;
+            mem::take(&mut state.wakers)
+        };
+        // the waiting runs race for the permit, and those losing wait again
+        self.bulkhead.inner.released.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::clock::Instant;
+use crate::runner::after_commit;
+use crate::visit::{visit_node, Node, Visit, Visitor};
+use crate::{IntoTransaction, Transaction};
+
+/// Caches of the items of transactions by keys, for `cached`.
+///
+/// The caches are shared handles, cloned into the actions filling them
+/// after commit, which may be sent from the thread of a branch of
+/// `par_join` or `scope` to that of the run. A cache failing to answer, e.g. a remote one, is to treat
+/// it as a miss, and to ignore the failures to write.
+pub trait Cache<K, V> {
+    /// The value cached for the key, unless it expired
+    fn get(&self, key: &K) -> Option<V>;
+
+    /// Cache the value for the key for `ttl`
+    fn insert(&self, key: K, value: V, ttl: Duration);
+
+    /// Forget the value cached for the key
+    fn remove(&self, key: &K);
+}
+
+/// An in-memory `Cache` keeping at most `capacity` values, evicting the
+/// least recently used one first. Cloning it shares the values.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    lru: Arc<Mutex<Lru<K, V>>>,
+}
+
+#[derive(Debug)]
+struct Lru<K, V> {
+    capacity: usize,
+    entries: HashMap<K, Entry<V>>,
+    // the keys by the tick of their last use
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    expires: Instant,
+    tick: u64,
+}
+
+impl<K, V> Clone for LruCache<K, V> {
+    fn clone(&self) -> Self {
+        LruCache { lru: self.lru.clone() }
+    }
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Make an empty cache of `capacity` values. Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity of a cache must be positive");
+        LruCache {
+            lru: Arc::new(Mutex::new(Lru {
+                capacity,
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+            })),
+        }
+    }
+
+    /// The number of values cached, including those expired but not evicted
+    /// yet
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether no value is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget all the values
+    pub fn clear(&self) {
+        let mut lru = self.lock();
+        lru.entries.clear();
+        lru.recency.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lru<K, V>> {
+        // the entries are always left consistent, so poisoning can be ignored
+        self.lru.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<K, V> Lru<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.tick);
+        Some(entry)
+    }
+}
+
+impl<K, V> Cache<K, V> for LruCache<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    fn get(&self, key: &K) -> Option<V> {
+        let mut lru = self.lock();
+        let mut entry = lru.remove(key)?;
+        if entry.expires <= Instant::now() {
+            return None;
+        }
+        let value = entry.value.clone();
+        lru.tick += 1;
+        entry.tick = lru.tick;
+        lru.recency.insert(entry.tick, key.clone());
+        lru.entries.insert(key.clone(), entry);
+        Some(value)
+    }
+
+    fn insert(&self, key: K, value: V, ttl: Duration) {
+        let mut lru = self.lock();
+        lru.remove(&key);
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.recency.insert(tick, key.clone());
+        lru.entries.insert(
+            key,
+            Entry {
+                value,
+                expires: Instant::now() + ttl,
+                tick,
+            },
+        );
+        while lru.entries.len() > lru.capacity {
+            let (_, oldest) = lru.recency.pop_first().expect("an entry has a tick");
+            lru.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&self, key: &K) {
+        self.lock().remove(key);
+    }
+}
+
+/// Return the item cached for the key of the context given by `key`
+/// without running the transaction, or run it and cache its item for `ttl`.
+///
+/// The item is cached only once the transaction commits, which is told by
+/// the runners of the backends, e.g. `MemStore::run` of `transaction-mem`,
+/// and by the layer of `runner::RunnerBuilder::after_commit` for the
+/// backends run by a `Runner`, like below. The transactions run otherwise,
+/// e.g. on a context directly or by the runners of async transactions,
+/// never fill the cache. Use `invalidates` on the transactions writing what
+/// is cached.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::cell::{Cell, RefCell};
+/// use std::collections::HashMap;
+/// use std::rc::Rc;
+/// use std::time::Duration;
+/// use transaction::prelude::*;
+/// use transaction::runner::{Backend, RunOptions, RunnerBuilder};
+/// use transaction::LruCache;
+///
+/// type Users = HashMap<u32, String>;
+///
+/// // a backend committing the users changed by a successful transaction
+/// struct Store(Rc<RefCell<Users>>);
+///
+/// impl<T, E> Backend<T, E> for Store {
+///     type Ctx = Users;
+///
+///     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+///     where
+///         Tx: Transaction<Ctx = Users, Item = T, Err = E> + ?Sized,
+///     {
+///         let mut users = self.0.borrow().clone();
+///         let t = tx.run(&mut users)?;
+///         *self.0.borrow_mut() = users;
+///         Ok(t)
+///     }
+/// }
+///
+/// # fn main() {
+/// let store = Store(Rc::new(RefCell::new(vec![(1, "alice".to_string())].into_iter().collect())));
+/// let runner = RunnerBuilder::new(store).after_commit().build();
+/// let cache = LruCache::new(100);
+///
+/// let reads = Cell::new(0);
+/// let name = |id: u32| {
+///     let reads = &reads;
+///     with_ctx(move |users: &mut Users| {
+///         reads.set(reads.get() + 1);
+///         Ok(users.get(&id).cloned())
+///     })
+///     .cached(&cache, move |_: &Users| id, Duration::from_secs(60))
+/// };
+///
+/// assert_eq!(runner.run(name(1)), Ok::<_, String>(Some("alice".to_string())));
+/// assert_eq!(runner.run(name(1)), Ok::<_, String>(Some("alice".to_string())));
+/// assert_eq!(reads.get(), 1);
+///
+/// let rename = with_ctx(|users: &mut Users| {
+///     users.insert(1, "bob".to_string());
+///     Ok::<_, String>(())
+/// })
+/// .invalidates(&cache, |_: &Users| 1);
+/// runner.run(rename).unwrap();
+/// assert_eq!(runner.run(name(1)), Ok::<_, String>(Some("bob".to_string())));
+/// assert_eq!(reads.get(), 2);
+///
+/// // a transaction rolled back caches nothing
+/// let failed = name(2).and_then(|_| err("rolled back".to_string()));
+/// assert_eq!(runner.run(failed), Err::<(), _>("rolled back".to_string()));
+/// assert_eq!(cache.len(), 1);
+/// # }
+/// ```
+pub fn cached<Ctx, A, C, F, K>(a: A, cache: &C, key: F, ttl: Duration) -> Cached<A::Tx, C, F>
+where
+    A: IntoTransaction<Ctx>,
+    C: Cache<K, A::Item> + Clone,
+    F: Fn(&Ctx) -> K,
+{
+    Cached {
+        tx: a.into_transaction(),
+        cache: cache.clone(),
+        key,
+        ttl,
+    }
+}
+
+/// The result of `cached`
+#[derive(Debug)]
+#[must_use]
+pub struct Cached<Tx, C, F> {
+    tx: Tx,
+    cache: C,
+    key: F,
+    ttl: Duration,
+}
+
+impl<Tx, C, F, K> Transaction for Cached<Tx, C, F>
+where
+    Tx: Transaction,
+    Tx::Item: Clone + Send + 'static,
+    C: Cache<K, Tx::Item> + Clone + Send + 'static,
+    F: Fn(&Tx::Ctx) -> K,
+    K: Send + 'static,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let key = (self.key)(ctx);
+        if let Some(item) = self.cache.get(&key) {
+            return Ok(item);
+        }
+        let item = self.tx.run(ctx)?;
+        let (cache, value, ttl) = (self.cache.clone(), item.clone(), self.ttl);
+        after_commit(Box::new(move || cache.insert(key, value, ttl)));
+        Ok(item)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, C, F> Visit for Cached<Tx, C, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("cached"), |v| self.tx.accept(v));
+    }
+}
+
+/// Run the transaction and forget the value cached for the key of the
+/// context given by `key`, e.g. after writing what is cached. The value is
+/// forgotten right away, and again once the transaction commits in case a
+/// concurrent `cached` filled it meanwhile. See `cached`.
+pub fn invalidates<Ctx, A, C, F, K, V>(a: A, cache: &C, key: F) -> Invalidates<A::Tx, C, F, V>
+where
+    A: IntoTransaction<Ctx>,
+    C: Cache<K, V> + Clone,
+    F: Fn(&Ctx) -> K,
+{
+    Invalidates {
+        tx: a.into_transaction(),
+        cache: cache.clone(),
+        key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `invalidates`
+#[derive(Debug)]
+#[must_use]
+pub struct Invalidates<Tx, C, F, V> {
+    tx: Tx,
+    cache: C,
+    key: F,
+    _phantom: PhantomData<V>,
+}
+
+impl<Tx, C, F, K, V> Transaction for Invalidates<Tx, C, F, V>
+where
+    Tx: Transaction,
+    C: Cache<K, V> + Clone + Send + 'static,
+    F: Fn(&Tx::Ctx) -> K,
+    K: Send + 'static,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let item = self.tx.run(ctx)?;
+        let (cache, key) = (self.cache.clone(), (self.key)(ctx));
+        cache.remove(&key);
+        after_commit(Box::new(move || cache.remove(&key)));
+        Ok(item)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, C, F, V> Visit for Invalidates<Tx, C, F, V>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("invalidates"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,191 @@
+use std::marker::PhantomData;
+use std::time::SystemTime;
+
+use crate::{clock, Transaction};
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// Contexts providing a connection of type `C`
+pub trait HasConnection<C> {
+    /// borrow the connection
+    fn connection(&mut self) -> &mut C;
+}
+
+/// Contexts providing the current time
+pub trait HasClock {
+    /// The current time
+    fn now(&self) -> SystemTime;
+}
+
+/// Contexts providing random numbers
+pub trait HasRng {
+    /// The next random number
+    fn next_u64(&mut self) -> u64;
+}
+
+/// Contexts whose state can be taken and put back, e.g. in-memory stores
+/// and test doubles, to check or undo what transactions did to them
+pub trait Snapshots {
+    /// The state of the context
+    type Snapshot;
+
+    /// Take the state of the context
+    fn snapshot(&self) -> Self::Snapshot;
+
+    /// Put the context back to the state
+    fn restore(&mut self, snapshot: Self::Snapshot);
+}
+
+/// The clock of the system, for contexts that don't need a fake clock.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl HasClock for SystemClock {
+    fn now(&self) -> SystemTime {
+        clock::system_time()
+    }
+}
+
+/// Receive the connection from any context providing it and perform
+/// computation.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{with_connection, HasConnection};
+///
+/// struct Conn(Vec<String>);
+/// struct AppCtx {
+///     conn: Conn,
+/// }
+///
+/// impl HasConnection<Conn> for AppCtx {
+///     fn connection(&mut self) -> &mut Conn {
+///         &mut self.conn
+///     }
+/// }
+///
+/// // reusable: runs in any context providing `Conn`
+/// fn insert<Ctx: HasConnection<Conn>>(
+///     s: &'static str,
+/// ) -> impl Transaction<Ctx = Ctx, Item = (), Err = ()> {
+///     with_connection(move |conn: &mut Conn| {
+///         conn.0.push(s.to_string());
+///         Ok(())
+///     })
+/// }
+///
+/// # fn main() {
+/// let mut ctx = AppCtx { conn: Conn(vec![]) };
+/// insert("keen").run(&mut ctx).unwrap();
+/// assert_eq!(ctx.conn.0, vec!["keen".to_string()]);
+/// # }
+/// ```
+pub fn with_connection<Ctx, C, F, T, E>(f: F) -> WithConnection<Ctx, C, F>
+where
+    Ctx: HasConnection<C>,
+    F: Fn(&mut C) -> Result<T, E>,
+{
+    WithConnection {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_connection`
+#[derive(Debug)]
+#[must_use]
+pub struct WithConnection<Ctx, C, F> {
+    f: F,
+    _phantom: PhantomData<(Ctx, C)>,
+}
+
+impl<Ctx, C, F, T, E> Transaction for WithConnection<Ctx, C, F>
+where
+    Ctx: HasConnection<C>,
+    F: Fn(&mut C) -> Result<T, E>,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        (self.f)(ctx.connection())
+    }
+}
+
+impl<Ctx, C, F> Visit for WithConnection<Ctx, C, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_connection"));
+    }
+}
+
+/// Get the current time from the context
+pub fn now<Ctx, E>() -> Now<Ctx, E>
+where
+    Ctx: HasClock,
+{
+    Now { _phantom: PhantomData }
+}
+
+/// The result of `now`
+#[derive(Debug)]
+#[must_use]
+pub struct Now<Ctx, E> {
+    _phantom: PhantomData<(Ctx, E)>,
+}
+
+impl<Ctx, E> Transaction for Now<Ctx, E>
+where
+    Ctx: HasClock,
+{
+    type Ctx = Ctx;
+    type Item = SystemTime;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.now())
+    }
+}
+
+impl<Ctx, E> Visit for Now<Ctx, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("now"));
+    }
+}
+
+/// Get a random number from the context
+pub fn random<Ctx, E>() -> Random<Ctx, E>
+where
+    Ctx: HasRng,
+{
+    Random { _phantom: PhantomData }
+}
+
+/// The result of `random`
+#[derive(Debug)]
+#[must_use]
+pub struct Random<Ctx, E> {
+    _phantom: PhantomData<(Ctx, E)>,
+}
+
+impl<Ctx, E> Transaction for Random<Ctx, E>
+where
+    Ctx: HasRng,
+{
+    type Ctx = Ctx;
+    type Item = u64;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.next_u64())
+    }
+}
+
+impl<Ctx, E> Visit for Random<Ctx, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("random"));
+    }
+}
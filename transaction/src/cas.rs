@@ -0,0 +1,304 @@
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::{clock, metrics};
+use crate::retry_policy::{RetryPolicy, Retryable};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+use crate::{IntoTransaction, Transaction};
+
+/// A value with the version it was read at, e.g. a row with its version
+/// column, a key of a store supporting compare-and-swap or an HTTP resource
+/// with its ETag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Versioned<T, V = u64> {
+    /// The value
+    pub value: T,
+    /// The version of the value
+    pub version: V,
+}
+
+impl<T, V> Versioned<T, V> {
+    /// The value at the version
+    pub fn new(value: T, version: V) -> Self {
+        Versioned { value, version }
+    }
+
+    /// Transform the value keeping the version
+    pub fn map<U, F>(self, f: F) -> Versioned<U, V>
+    where
+        F: FnOnce(T) -> U,
+    {
+        Versioned {
+            value: f(self.value),
+            version: self.version,
+        }
+    }
+}
+
+/// The error of a write expecting a version which is no longer the current
+/// one, since another client has written meanwhile. It is retryable: run
+/// again, the transaction reads the current version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionMismatch<V> {
+    /// The version the write expected
+    pub expected: V,
+    /// The current version, if the backend tells it
+    pub found: Option<V>,
+}
+
+impl<V> fmt::Display for VersionMismatch<V>
+where
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.found {
+            Some(ref found) => write!(f, "expected version {:?} but found {:?}", self.expected, found),
+            None => write!(f, "version {:?} is no longer the current one", self.expected),
+        }
+    }
+}
+
+impl<V> Error for VersionMismatch<V> where V: fmt::Debug {}
+
+impl<V> Retryable for VersionMismatch<V> {
+    fn is_retryable(&self) -> bool {
+        true
+    }
+}
+
+/// Read the versioned value and write by the closure if it is still at the
+/// expected version, e.g. the version a form was rendered with, or fail with
+/// `VersionMismatch` otherwise.
+///
+/// The check narrows but doesn't close the window for a concurrent write
+/// between the read and the write: when the backend supports conditional
+/// writes, e.g. `If-Match` of HTTP or `UPDATE ... WHERE version = ?`, the
+/// write should also pass the version it is given.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::prelude::*;
+/// use transaction::{cas, VersionMismatch, Versioned};
+///
+/// struct Doc {
+///     text: String,
+///     version: u64,
+/// }
+///
+/// let read = with_ctx(|d: &mut Doc| -> Result<_, VersionMismatch<u64>> {
+///     Ok(Versioned::new(d.text.clone(), d.version))
+/// });
+/// let edit = cas(read, 1, |current: Versioned<String>| {
+///     with_ctx(move |d: &mut Doc| {
+///         d.text = format!("{}!", current.value);
+///         d.version = current.version + 1;
+///         Ok(())
+///     })
+/// });
+///
+/// let mut doc = Doc { text: "hello".to_string(), version: 1 };
+/// assert_eq!(edit.run(&mut doc), Ok(()));
+/// assert_eq!(doc.text, "hello!");
+/// // the edit was made on version 1
+/// assert_eq!(
+///     edit.run(&mut doc),
+///     Err(VersionMismatch { expected: 1, found: Some(2) })
+/// );
+/// ```
+pub fn cas<Ctx, A, T, V, F, B>(read: A, expected: V, write: F) -> Cas<A::Tx, V, F, B>
+where
+    A: IntoTransaction<Ctx, Item = Versioned<T, V>>,
+    A::Err: From<VersionMismatch<V>>,
+    V: PartialEq + Clone,
+    F: Fn(Versioned<T, V>) -> B,
+    B: IntoTransaction<Ctx, Err = A::Err>,
+{
+    Cas {
+        tx: read.into_transaction(),
+        expected,
+        write,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `cas`
+#[derive(Debug)]
+#[must_use]
+pub struct Cas<Tx, V, F, B> {
+    tx: Tx,
+    expected: V,
+    write: F,
+    _phantom: PhantomData<B>,
+}
+
+impl<Tx, T, V, F, B> Transaction for Cas<Tx, V, F, B>
+where
+    Tx: Transaction<Item = Versioned<T, V>>,
+    Tx::Err: From<VersionMismatch<V>>,
+    V: PartialEq + Clone,
+    F: Fn(Versioned<T, V>) -> B,
+    B: IntoTransaction<Tx::Ctx, Err = Tx::Err>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = B::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let current = self.tx.run(ctx)?;
+        if current.version != self.expected {
+            return Err(VersionMismatch {
+                expected: self.expected.clone(),
+                found: Some(current.version),
+            }
+            .into());
+        }
+        (self.write)(current).into_transaction().run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, V, F, B> Visit for Cas<Tx, V, F, B>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("cas"), |v| self.tx.accept(v));
+    }
+}
+
+/// Read the versioned value and write by the closure, reading and writing
+/// again with the delays of the policy while it fails with a retryable
+/// error, e.g. `VersionMismatch`. The write should be conditional on the
+/// version it is given, failing with `VersionMismatch` if another client
+/// has written meanwhile.
+///
+/// This is optimistic concurrency for backends without transactions, e.g.
+/// HTTP APIs or key-value stores with compare-and-swap.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::prelude::*;
+/// use transaction::{optimistic, Backoff, VersionMismatch, Versioned};
+///
+/// struct Counter {
+///     value: u32,
+///     version: u64,
+///     // writes by another client, made after our reads
+///     racing: u32,
+/// }
+///
+/// let read = with_ctx(|c: &mut Counter| -> Result<_, VersionMismatch<u64>> {
+///     let read = Versioned::new(c.value, c.version);
+///     if c.racing > 0 {
+///         c.racing -= 1;
+///         c.value += 10;
+///         c.version += 1;
+///     }
+///     Ok(read)
+/// });
+/// // compare-and-swap of the store
+/// let increment = optimistic(read, |read: Versioned<u32>| {
+///     with_ctx(move |c: &mut Counter| {
+///         if c.version != read.version {
+///             return Err(VersionMismatch { expected: read.version, found: Some(c.version) });
+///         }
+///         c.value = read.value + 1;
+///         c.version += 1;
+///         Ok(c.value)
+///     })
+/// }, Backoff::immediate());
+///
+/// let mut counter = Counter { value: 0, version: 0, racing: 2 };
+/// assert_eq!(increment.run(&mut counter), Ok(21));
+///
+/// counter.racing = 4;
+/// assert!(increment.run(&mut counter).is_err());
+/// ```
+pub fn optimistic<Ctx, A, T, V, F, B, P>(read: A, write: F, policy: P) -> Optimistic<A::Tx, F, B, P>
+where
+    A: IntoTransaction<Ctx, Item = Versioned<T, V>>,
+    A::Err: Retryable,
+    F: Fn(Versioned<T, V>) -> B,
+    B: IntoTransaction<Ctx, Err = A::Err>,
+    P: RetryPolicy,
+{
+    Optimistic {
+        tx: read.into_transaction(),
+        write,
+        policy,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `optimistic`
+#[derive(Debug)]
+#[must_use]
+pub struct Optimistic<Tx, F, B, P> {
+    tx: Tx,
+    write: F,
+    policy: P,
+    _phantom: PhantomData<B>,
+}
+
+impl<Tx, T, V, F, B, P> Optimistic<Tx, F, B, P>
+where
+    Tx: Transaction<Item = Versioned<T, V>>,
+    F: Fn(Versioned<T, V>) -> B,
+    B: IntoTransaction<Tx::Ctx, Err = Tx::Err>,
+{
+    fn attempt(&self, ctx: &mut Tx::Ctx) -> Result<B::Item, Tx::Err> {
+        let current = self.tx.run(ctx)?;
+        (self.write)(current).into_transaction().run(ctx)
+    }
+}
+
+impl<Tx, T, V, F, B, P> Transaction for Optimistic<Tx, F, B, P>
+where
+    Tx: Transaction<Item = Versioned<T, V>>,
+    Tx::Err: Retryable,
+    F: Fn(Versioned<T, V>) -> B,
+    B: IntoTransaction<Tx::Ctx, Err = Tx::Err>,
+    P: RetryPolicy,
+{
+    type Ctx = Tx::Ctx;
+    type Item = B::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let mut retries = 0;
+        loop {
+            let e = match self.attempt(ctx) {
+                Ok(t) => return Ok(t),
+                Err(e) => if e.is_retryable() { e } else { return Err(e) },
+            };
+            let delay = match self.policy.next_delay(retries) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            #[cfg(feature = "log")]
+            log::debug!("attempt {} conflicted, retrying in {:?}", retries + 1, delay);
+            clock::sleep(delay);
+            metrics::record_retry(self.tx.label());
+            retries += 1;
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, F, B, P> Visit for Optimistic<Tx, F, B, P>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("optimistic"), |v| self.tx.accept(v));
+    }
+}
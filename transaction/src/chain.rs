@@ -0,0 +1,182 @@
+use std::fmt;
+
+use crate::visit::{visit_leaf, visit_node, Node, Visit, Visitor};
+use crate::{IntoTransaction, Transaction};
+
+/// Start a chain of steps run one after another in a loop rather than by
+/// nesting, so that its length is limited only by the heap.
+///
+/// A chain built in a loop by `and_then` and `boxed` nests a transaction in
+/// each step, which runs, and drops, by recursing once per step and
+/// overflows the stack at some depth. The `and_then`, `then` and `map` of
+/// the chain instead append the step to the chain, keeping the type of the
+/// item and the error along it.
+///
+/// The chain is walked by `Visit` as a `chain` node with the first
+/// transaction followed by a leaf for each step, as the transactions of the
+/// steps are made by closures at run time.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{chain, Visit};
+///
+/// # fn main() {
+/// fn deposit(amount: i64) -> impl Transaction<Ctx = i64, Item = i64, Err = String> + Visit {
+///     with_ctx(move |balance: &mut i64| {
+///         *balance += amount;
+///         Ok(*balance)
+///     })
+/// }
+///
+/// let mut tx = chain(deposit(0).named("open"));
+/// for _ in 0..1_000_000 {
+///     tx = tx.and_then(|_| deposit(1));
+/// }
+/// let tx = tx
+///     .map(|balance| balance * 2)
+///     .then(|ret| ret.and_then(|n| if n > 0 { Ok(n) } else { Err("empty".to_string()) }));
+///
+/// assert_eq!(tx.label(), Some("open"));
+/// assert_eq!(tx.labels(), vec!["open"]);
+/// // chain -> named -> with_ctx, and a leaf for each step
+/// assert_eq!(tx.node_count(), 3 + 1_000_002);
+///
+/// let mut balance = 0;
+/// assert_eq!(tx.run(&mut balance), Ok(2_000_000));
+/// assert_eq!(balance, 1_000_000);
+/// # }
+/// ```
+pub fn chain<'a, Ctx, A>(a: A) -> Chain<'a, Ctx, A::Item, A::Err>
+where
+    A: IntoTransaction<Ctx>,
+    A::Tx: Visit + 'a,
+{
+    Chain {
+        first: Box::new(a.into_transaction()),
+        steps: Vec::new(),
+    }
+}
+
+/// The result of `chain`
+#[must_use]
+pub struct Chain<'a, Ctx, T, E> {
+    first: Box<dyn Head<Ctx = Ctx, Item = T, Err = E> + 'a>,
+    steps: Vec<Step<'a, Ctx, T, E>>,
+}
+
+// the first transaction of a chain, boxed with its `Visit`
+trait Head: Transaction + Visit {}
+
+impl<Tx> Head for Tx where Tx: Transaction + Visit {}
+
+// a step run with the result of the previous one, only if it succeeded for
+// `AndThen` and `Map`
+enum Step<'a, Ctx, T, E> {
+    AndThen(StepFn<'a, Ctx, T, T, E>),
+    Then(StepFn<'a, Ctx, Result<T, E>, T, E>),
+    Map(StepFn<'a, Ctx, T, T, E>),
+}
+
+impl<'a, Ctx, T, E> Step<'a, Ctx, T, E> {
+    fn kind(&self) -> &'static str {
+        match *self {
+            Step::AndThen(_) => "and_then",
+            Step::Then(_) => "then",
+            Step::Map(_) => "map",
+        }
+    }
+}
+
+type StepFn<'a, Ctx, A, T, E> = Box<dyn Fn(A, &mut Ctx) -> Result<T, E> + 'a>;
+
+impl<'a, Ctx, T, E> Chain<'a, Ctx, T, E> {
+    /// Append the transaction made by the closure from the item
+    pub fn and_then<F, B>(mut self, f: F) -> Self
+    where
+        F: Fn(T) -> B + 'a,
+        B: IntoTransaction<Ctx, Item = T, Err = E>,
+    {
+        self.steps.push(Step::AndThen(Box::new(move |item, ctx| {
+            f(item).into_transaction().run(ctx)
+        })));
+        self
+    }
+
+    /// Append the transaction made by the closure from the result
+    pub fn then<F, B>(mut self, f: F) -> Self
+    where
+        F: Fn(Result<T, E>) -> B + 'a,
+        B: IntoTransaction<Ctx, Item = T, Err = E>,
+    {
+        self.steps.push(Step::Then(Box::new(move |ret, ctx| {
+            f(ret).into_transaction().run(ctx)
+        })));
+        self
+    }
+
+    /// Append the mapping of the item
+    pub fn map<F>(mut self, f: F) -> Self
+    where
+        F: Fn(T) -> T + 'a,
+    {
+        self.steps.push(Step::Map(Box::new(move |item, _| Ok(f(item)))));
+        self
+    }
+
+    /// The number of steps appended to the first transaction
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether no step is appended to the first transaction
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl<'a, Ctx, T, E> Transaction for Chain<'a, Ctx, T, E> {
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let mut ret = self.first.run(ctx);
+        for step in &self.steps {
+            ret = match step {
+                Step::AndThen(f) | Step::Map(f) => match ret {
+                    Ok(item) => f(item, ctx),
+                    Err(e) => Err(e),
+                },
+                Step::Then(f) => f(ret, ctx),
+            };
+        }
+        ret
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.first.label()
+    }
+}
+
+impl<'a, Ctx, T, E> Visit for Chain<'a, Ctx, T, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("chain"), |v| {
+            self.first.accept(v);
+            for step in &self.steps {
+                visit_leaf(v, Node::new(step.kind()));
+            }
+        });
+    }
+}
+
+impl<'a, Ctx, T, E> fmt::Debug for Chain<'a, Ctx, T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Chain")
+            .field("steps", &self.steps.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,167 @@
+//! The clock read by the runners and the combinators: the deadlines, the
+//! timings of the hooks and the profiles, the TTLs of the caches, the
+//! timestamps of the audit logs, and the sleeps between retries.
+//!
+//! Like the hooks, a clock is set once at start up and then used by all the
+//! runners. By default it is `StdClock`, on `std::time` and
+//! `std::thread::sleep`. These are not available on
+//! `wasm32-unknown-unknown`, where the time of `StdClock` doesn't pass and
+//! its sleeps return at once, so browser and edge runtimes set a clock of
+//! their own, e.g. reading `performance.now()`. The asynchronous
+//! transactions sleep on an `async_tx::Timer` instead.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::time::{Duration, SystemTime};
+//! use transaction::clock::{self, Clock, Instant};
+//! use transaction::prelude::*;
+//! use transaction::Backoff;
+//!
+//! // a clock whose time passes only by sleeping, e.g. for tests
+//! struct Manual(AtomicU64);
+//!
+//! impl Clock for Manual {
+//!     fn now(&self) -> Duration {
+//!         Duration::from_millis(self.0.load(Ordering::SeqCst))
+//!     }
+//!     fn system_time(&self) -> SystemTime {
+//!         SystemTime::UNIX_EPOCH + self.now()
+//!     }
+//!     fn sleep(&self, duration: Duration) {
+//!         self.0.fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+//!     }
+//! }
+//!
+//! # fn main() {
+//! clock::set(Box::new(Manual(AtomicU64::new(0))));
+//! let start = Instant::now();
+//! let tx = with_ctx(|attempts: &mut u32| {
+//!     *attempts += 1;
+//!     if *attempts < 3 { Err(()) } else { Ok(*attempts) }
+//! });
+//! let mut attempts = 0;
+//! assert_eq!(tx.retry_with(Backoff::fixed(Duration::from_secs(1))).run(&mut attempts), Ok(3));
+//! assert_eq!(start.elapsed(), Duration::from_secs(2));
+//! # }
+//! ```
+
+use std::ops::Add;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::thread;
+use std::time::{self, Duration, SystemTime};
+
+use crate::platform;
+
+static SET: AtomicBool = AtomicBool::new(false);
+static CLOCK: RwLock<Option<Arc<dyn Clock>>> = RwLock::new(None);
+
+/// The source of time of the runners and the combinators
+pub trait Clock: Send + Sync {
+    /// The time elapsed since an arbitrary origin, never decreasing
+    fn now(&self) -> Duration;
+
+    /// The time of the system, e.g. for the timestamps of the audit logs
+    fn system_time(&self) -> SystemTime;
+
+    /// Block the thread for the duration, e.g. between retries
+    fn sleep(&self, duration: Duration);
+}
+
+/// The clock of `std`, used unless another clock is set. On
+/// `wasm32-unknown-unknown`, lacking the time and the threads, the time
+/// stays at its origin, the system time at `UNIX_EPOCH`, and the sleeps
+/// return at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StdClock;
+
+impl Clock for StdClock {
+    fn now(&self) -> Duration {
+        static ORIGIN: OnceLock<time::Instant> = OnceLock::new();
+        if !platform::TIME {
+            return Duration::ZERO;
+        }
+        ORIGIN.get_or_init(time::Instant::now).elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        if !platform::TIME {
+            return SystemTime::UNIX_EPOCH;
+        }
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if platform::THREADS {
+            thread::sleep(duration);
+        }
+    }
+}
+
+/// Set the clock of the runners and the combinators, replacing the previous
+/// one. The `Instant`s taken from the previous clock are not comparable
+/// with those of the new one, so it should be set at start up.
+pub fn set(clock: Box<dyn Clock>) {
+    *CLOCK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::from(clock));
+    SET.store(true, Ordering::Release);
+}
+
+fn with_clock<F, R>(f: F) -> R
+where
+    F: FnOnce(&dyn Clock) -> R,
+{
+    if !SET.load(Ordering::Acquire) {
+        return f(&StdClock);
+    }
+    // not to hold the lock while sleeping
+    let clock = CLOCK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match clock {
+        Some(clock) => f(&*clock),
+        None => f(&StdClock),
+    }
+}
+
+/// The time of the system on the clock
+pub fn system_time() -> SystemTime {
+    with_clock(|clock| clock.system_time())
+}
+
+/// Block the thread for the duration on the clock
+pub fn sleep(duration: Duration) {
+    with_clock(|clock| clock.sleep(duration))
+}
+
+/// A point in time on the clock, like `std::time::Instant` which is not
+/// available on all the targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(Duration);
+
+impl Instant {
+    /// The current time on the clock
+    pub fn now() -> Self {
+        Instant(with_clock(|clock| clock.now()))
+    }
+
+    /// The time elapsed since this instant
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().saturating_duration_since(*self)
+    }
+
+    /// The time elapsed from `earlier` to this instant, or zero if
+    /// `earlier` is later
+    pub fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(self.0 + duration)
+    }
+}
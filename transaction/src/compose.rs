@@ -0,0 +1,92 @@
+/// Run any number of transactions in order, resulting in the tuple of their
+/// items, like `join3` and `join4` for the other arities. The arguments are
+/// `IntoTransaction`, and it expands to nested `join`s mapped to a flat
+/// tuple.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// # fn main() {
+/// fn push(n: i32) -> impl Transaction<Ctx = Vec<i32>, Item = usize, Err = ()> {
+///     with_ctx(move |v: &mut Vec<i32>| {
+///         v.push(n);
+///         Ok(v.len())
+///     })
+/// }
+///
+/// let tx = join!(push(1), push(2), ok("three"), push(4), push(5), push(6));
+/// let mut v = vec![];
+/// assert_eq!(tx.run(&mut v), Ok((1, 2, "three", 3, 4, 5)));
+/// assert_eq!(v, vec![1, 2, 4, 5, 6]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! join {
+    // collect the transactions, naming each item `x`, which are distinct
+    // by hygiene as each is introduced by its own expansion
+    (@collect [$($x:ident)*] [$($tx:expr,)*]) => {
+        $crate::map($crate::join!(@nest $($tx),*), |$crate::join!(@pat $($x)*)| ($($x),*))
+    };
+    (@collect [$($x:ident)*] [$($tx:expr,)*] $head:expr $(, $rest:expr)*) => {
+        $crate::join!(@collect [$($x)* x] [$($tx,)* $head,] $($rest),*)
+    };
+    (@nest $tx:expr) => { $tx };
+    (@nest $tx:expr, $($rest:expr),+) => { $crate::join($tx, $crate::join!(@nest $($rest),+)) };
+    (@pat $x:ident) => { $x };
+    (@pat $x:ident $($rest:ident)+) => { ($x, $crate::join!(@pat $($rest)+)) };
+    ($($tx:expr),+ $(,)?) => {
+        $crate::join!(@collect [] [] $($tx),+)
+    };
+}
+
+/// Chain transactions with `and_then`: `pipeline!(a => f => g)` runs `a`,
+/// then the transaction made by `f` from its item, then that made by `g`
+/// from the item of the second, without nesting the closures.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// # fn main() {
+/// fn read() -> impl Transaction<Ctx = i32, Item = i32, Err = String> {
+///     with_ctx(|n: &mut i32| Ok(*n))
+/// }
+///
+/// fn write(n: i32) -> impl Transaction<Ctx = i32, Item = i32, Err = String> {
+///     with_ctx(move |ctx: &mut i32| {
+///         *ctx = n;
+///         Ok(n)
+///     })
+/// }
+///
+/// fn check(n: i32) -> Result<i32, String> {
+///     if n < 100 { Ok(n) } else { Err(format!("{} is too large", n)) }
+/// }
+///
+/// let tx = pipeline!(read() => |n| write(n * 2) => check => |n| ok(n + 1));
+/// let mut n = 21;
+/// assert_eq!(tx.run(&mut n), Ok(43));
+/// assert_eq!(n, 42);
+/// assert_eq!(tx.run(&mut n), Ok(85));
+/// assert_eq!(tx.run(&mut n), Err("168 is too large".to_string()));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! pipeline {
+    (@chain $tx:expr) => { $tx };
+    (@chain $tx:expr => $f:expr $(=> $rest:expr)*) => {
+        $crate::pipeline!(@chain $crate::and_then($tx, $f) $(=> $rest)*)
+    };
+    ($tx:expr $(=> $rest:expr)* $(,)?) => {
+        $crate::pipeline!(@chain $tx $(=> $rest)*)
+    };
+}
@@ -0,0 +1,336 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+thread_local!(static STATE: RefCell<State> = const {
+    RefCell::new(State {
+        active: Vec::new(),
+        scope: Vec::new(),
+        recovering: 0,
+    })
+});
+
+// the coverages recording on this thread, the labels of the steps running
+// and how many `or_else` recoveries are running
+struct State {
+    active: Vec<Coverage>,
+    scope: Vec<String>,
+    recovering: usize,
+}
+
+/// Run the transaction recording into the coverage which of its labeled
+/// steps run on the success path and which while recovering from an error
+/// by `or_else`, and whether the recoveries of its `or_else`s run.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::Coverage;
+///
+/// # fn main() {
+/// fn transfer(amount: i32) -> impl Transaction<Ctx = i32, Item = (), Err = ()> + Visit {
+///     with_ctx(move |balance: &mut i32| if *balance >= amount { Ok(*balance -= amount) } else { Err(()) })
+///         .named("debit")
+///         .or_else(|()| ok(()).named("refund"))
+///         .named("transfer")
+/// }
+///
+/// let coverage = Coverage::new();
+/// coverage.expect(&transfer(0));
+/// transfer(5).covered(&coverage).run(&mut 10).unwrap();
+///
+/// let report = coverage.report();
+/// assert_eq!(report.step("transfer/debit").unwrap().success, 1);
+/// assert_eq!(report.uncovered(), vec!["the recovery of or_else in `transfer`"]);
+///
+/// transfer(50).covered(&coverage).run(&mut 10).unwrap();
+/// let report = coverage.report();
+/// assert_eq!(report.step("transfer/refund").unwrap().recovery, 1);
+/// assert!(report.uncovered().is_empty());
+/// # }
+/// ```
+pub fn covered<Ctx, A>(a: A, coverage: &Coverage) -> Covered<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    Covered {
+        tx: a.into_transaction(),
+        coverage: coverage.clone(),
+    }
+}
+
+/// The result of `covered`
+#[derive(Debug)]
+#[must_use]
+pub struct Covered<Tx> {
+    tx: Tx,
+    coverage: Coverage,
+}
+
+impl<Tx> Transaction for Covered<Tx>
+where
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        STATE.with(|state| state.borrow_mut().active.push(self.coverage.clone()));
+        let _guard = PopGuard;
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Covered<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("covered"), |v| self.tx.accept(v));
+    }
+}
+
+// pops the coverage even if the transaction panics
+struct PopGuard;
+
+impl Drop for PopGuard {
+    fn drop(&mut self) {
+        STATE.with(|state| state.borrow_mut().active.pop());
+    }
+}
+
+/// How many times a labeled step ran on each path
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepCoverage {
+    /// The runs outside of any `or_else` recovery
+    pub success: u64,
+    /// The runs inside the recovery of an `or_else`
+    pub recovery: u64,
+}
+
+/// How many times an `or_else` ran and recovered from an error
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCoverage {
+    /// How many times the `or_else` ran
+    pub runs: u64,
+    /// How many of the runs failed and ran the recovery
+    pub recoveries: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    steps: BTreeMap<String, StepCoverage>,
+    branches: BTreeMap<String, BranchCoverage>,
+}
+
+/// Collector of the paths taken by labeled transactions, e.g. shared by a
+/// test suite to find the error handling it never exercises. Clones share
+/// the same counts.
+///
+/// The steps are identified by the labels given by `named` enclosing them,
+/// joined by `/`, e.g. `transfer/debit`. The `or_else`s are identified by
+/// the labels enclosing them, so label the steps to tell the `or_else`s of a
+/// transaction apart.
+///
+/// Only the runs on the thread running the `covered` transaction are
+/// recorded.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl Coverage {
+    /// make an empty coverage
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    /// Expect the labeled steps and the `or_else`s of the transaction to be
+    /// covered, so they are reported if they never run. Only the
+    /// statically known part of the transaction is walked, see `Visit`.
+    pub fn expect<T>(&self, tx: &T)
+    where
+        T: Visit + ?Sized,
+    {
+        struct Expect<'a> {
+            counts: &'a mut Counts,
+            scope: Vec<String>,
+        }
+        impl<'a> Visitor for Expect<'a> {
+            fn enter(&mut self, node: &Node) {
+                if node.kind == "or_else" {
+                    self.counts.branches.entry(self.scope.join("/")).or_default();
+                }
+                if let Some(label) = node.label {
+                    self.scope.push(label.to_string());
+                    self.counts.steps.entry(self.scope.join("/")).or_default();
+                }
+            }
+            fn leave(&mut self, node: &Node) {
+                if node.label.is_some() {
+                    self.scope.pop();
+                }
+            }
+        }
+        let mut counts = self.lock();
+        tx.accept(&mut Expect {
+            counts: &mut counts,
+            scope: Vec::new(),
+        });
+    }
+
+    /// The counts recorded so far
+    pub fn report(&self) -> CoverageReport {
+        let counts = self.lock();
+        CoverageReport {
+            steps: counts.steps.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+            branches: counts.branches.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+        }
+    }
+
+    /// Forget all the recorded counts and expectations
+    pub fn reset(&self) {
+        *self.lock() = Counts::default();
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, Counts> {
+        // the counts are always left consistent, so poisoning can be ignored
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The counts of a `Coverage`, sorted by the paths of the steps and the
+/// `or_else`s
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// The labeled steps
+    pub steps: Vec<(String, StepCoverage)>,
+    /// The `or_else`s, by the labels enclosing them
+    pub branches: Vec<(String, BranchCoverage)>,
+}
+
+impl CoverageReport {
+    /// The counts of the step
+    pub fn step(&self, path: &str) -> Option<StepCoverage> {
+        self.steps.iter().find(|s| s.0 == path).map(|s| s.1)
+    }
+
+    /// The counts of the `or_else` enclosed by the labels
+    pub fn branch(&self, path: &str) -> Option<BranchCoverage> {
+        self.branches.iter().find(|b| b.0 == path).map(|b| b.1)
+    }
+
+    /// The steps which never ran and the `or_else`s which never recovered
+    pub fn uncovered(&self) -> Vec<String> {
+        let steps = self.steps
+            .iter()
+            .filter(|s| s.1 == StepCoverage::default())
+            .map(|s| format!("the step `{}`", s.0));
+        let branches = self.branches
+            .iter()
+            .filter(|b| b.1.recoveries == 0)
+            .map(|b| format!("the recovery of or_else {}", scope(&b.0)));
+        steps.chain(branches).collect()
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (path, s) in &self.steps {
+            writeln!(f, "step {}: {} success, {} recovery", path, s.success, s.recovery)?;
+        }
+        for (path, b) in &self.branches {
+            writeln!(f, "or_else {}: {} runs, {} recoveries", scope(path), b.runs, b.recoveries)?;
+        }
+        for uncovered in self.uncovered() {
+            writeln!(f, "uncovered: {}", uncovered)?;
+        }
+        Ok(())
+    }
+}
+
+fn scope(path: &str) -> String {
+    if path.is_empty() {
+        "at the top level".to_string()
+    } else {
+        format!("in `{}`", path)
+    }
+}
+
+/// Whether any coverage is recording on this thread
+pub(crate) fn is_active() -> bool {
+    STATE.with(|state| !state.borrow().active.is_empty())
+}
+
+/// Record the run of a labeled step to the coverages active on this thread
+/// and enter its scope until the guard is dropped
+pub(crate) fn enter_step(label: &str) -> ScopeGuard {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.scope.push(label.to_string());
+        let path = state.scope.join("/");
+        let recovering = state.recovering > 0;
+        for coverage in &state.active {
+            let mut counts = coverage.lock();
+            let step = counts.steps.entry(path.clone()).or_default();
+            if recovering {
+                step.recovery += 1;
+            } else {
+                step.success += 1;
+            }
+        }
+    });
+    ScopeGuard
+}
+
+/// Record the run of an `or_else` to the coverages active on this thread
+pub(crate) fn run_branch(recovered: bool) {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let path = state.scope.join("/");
+        for coverage in &state.active {
+            let mut counts = coverage.lock();
+            let branch = counts.branches.entry(path.clone()).or_default();
+            branch.runs += 1;
+            if recovered {
+                branch.recoveries += 1;
+            }
+        }
+    })
+}
+
+/// Enter the recovery of an `or_else` until the guard is dropped
+pub(crate) fn enter_recovery() -> RecoveryGuard {
+    STATE.with(|state| state.borrow_mut().recovering += 1);
+    RecoveryGuard
+}
+
+/// Leaves the scope of a labeled step
+pub(crate) struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        STATE.with(|state| state.borrow_mut().scope.pop());
+    }
+}
+
+/// Leaves the recovery of an `or_else`
+pub(crate) struct RecoveryGuard;
+
+impl Drop for RecoveryGuard {
+    fn drop(&mut self) {
+        STATE.with(|state| state.borrow_mut().recovering -= 1);
+    }
+}
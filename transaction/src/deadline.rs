@@ -0,0 +1,157 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::clock::Instant;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+use crate::Transaction;
+
+thread_local!(static DEADLINE: RefCell<Vec<Option<Instant>>> = const { RefCell::new(Vec::new()) });
+
+/// The deadline of the transaction run by a runner, read by
+/// `remaining_time`. The runners given a deadline, e.g. by
+/// `RunnerBuilder::deadline` or by the `Interrupt` of an async run, enter it
+/// around the run, and the SQL runners also limit the statements of the
+/// transaction to it, e.g. by `statement_timeout`.
+#[derive(Debug)]
+pub struct RunDeadline {
+    _private: (),
+}
+
+impl RunDeadline {
+    /// Run the transactions until the deadline, or `None` for no deadline,
+    /// until dropped. The deadline entered around, if any, still holds when
+    /// it is earlier.
+    pub fn enter(deadline: Option<Instant>) -> Self {
+        DEADLINE.with(|deadlines| {
+            let mut deadlines = deadlines.borrow_mut();
+            let outer = deadlines.last().copied().flatten();
+            let deadline = match (outer, deadline) {
+                (Some(outer), Some(deadline)) => Some(outer.min(deadline)),
+                (outer, deadline) => outer.or(deadline),
+            };
+            deadlines.push(deadline);
+        });
+        RunDeadline { _private: () }
+    }
+
+    /// The deadline of the transaction running on this thread, if any
+    pub fn current() -> Option<Instant> {
+        DEADLINE.with(|deadlines| deadlines.borrow().last().copied().flatten())
+    }
+
+    /// Poll the future with the deadline entered, for the async runners,
+    /// since their runs move between the threads
+    pub fn within<F>(deadline: Option<Instant>, future: F) -> WithinDeadline<F>
+    where
+        F: Future,
+    {
+        WithinDeadline {
+            deadline,
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl Drop for RunDeadline {
+    fn drop(&mut self) {
+        DEADLINE.with(|deadlines| deadlines.borrow_mut().pop());
+    }
+}
+
+// the deadlines entered on the thread, innermost last
+pub(crate) fn current() -> Vec<Option<Instant>> {
+    DEADLINE.with(|deadlines| deadlines.borrow().clone())
+}
+
+// replace the deadlines entered on the thread, returning those replaced
+pub(crate) fn replace(deadlines: Vec<Option<Instant>>) -> Vec<Option<Instant>> {
+    DEADLINE.with(|current| mem::replace(&mut *current.borrow_mut(), deadlines))
+}
+
+/// The result of `RunDeadline::within`
+#[derive(Debug)]
+#[must_use]
+pub struct WithinDeadline<F> {
+    deadline: Option<Instant>,
+    future: Pin<Box<F>>,
+}
+
+impl<F> Future for WithinDeadline<F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let _deadline = RunDeadline::enter(self.deadline);
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// Get the time remaining until the deadline of the run, see `RunDeadline`,
+/// or `None` if it has none, so that long transactions can give up before
+/// the database cancels their statements. Zero once the deadline passed.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::time::Duration;
+/// use transaction::clock::Instant;
+/// use transaction::prelude::*;
+/// use transaction::{remaining_time, RunDeadline};
+///
+/// // archive the rows one by one while there is time left
+/// fn archive(rows: usize) -> impl Transaction<Ctx = Vec<usize>, Item = usize, Err = ()> {
+///     remaining_time().and_then(move |remaining: Option<Duration>| {
+///         with_ctx(move |archived: &mut Vec<usize>| {
+///             let rows = match remaining {
+///                 Some(remaining) if remaining < Duration::from_secs(1) => 0,
+///                 _ => rows,
+///             };
+///             archived.extend(0..rows);
+///             Ok(rows)
+///         })
+///     })
+/// }
+///
+/// # fn main() {
+/// assert_eq!(archive(3).run(&mut vec![]), Ok(3));
+///
+/// // a runner past its deadline
+/// let _deadline = RunDeadline::enter(Some(Instant::now()));
+/// assert_eq!(archive(3).run(&mut vec![]), Ok(0));
+/// # }
+/// ```
+pub fn remaining_time<Ctx, E>() -> RemainingTime<Ctx, E> {
+    RemainingTime { _phantom: PhantomData }
+}
+
+/// The result of `remaining_time`
+#[derive(Debug)]
+#[must_use]
+pub struct RemainingTime<Ctx, E> {
+    _phantom: PhantomData<(Ctx, E)>,
+}
+
+impl<Ctx, E> Transaction for RemainingTime<Ctx, E> {
+    type Ctx = Ctx;
+    type Item = Option<Duration>;
+    type Err = E;
+
+    fn run(&self, _ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(RunDeadline::current().map(|deadline| deadline.saturating_duration_since(Instant::now())))
+    }
+}
+
+impl<Ctx, E> Visit for RemainingTime<Ctx, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("remaining_time"));
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+
+use crate::visit::{Node, Visit, Visitor};
+
+/// Describe the steps of the transaction without running it.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// # fn main() {
+/// let tx = ok::<(), i32, ()>(1)
+///     .named("load")
+///     .and_then(|x| ok(x + 1))
+///     .named("increment");
+/// assert_eq!(
+///     tx.describe().to_string(),
+///     "named \"increment\"\n  and_then\n    named \"load\"\n      ok\n"
+/// );
+/// # }
+/// ```
+pub fn describe<Tx>(tx: &Tx) -> PlanDescription
+where
+    Tx: ?Sized + Visit,
+{
+    let mut builder = Builder { stack: vec![Vec::new()] };
+    tx.accept(&mut builder);
+    let mut roots = builder.stack.pop().unwrap_or_default();
+    if roots.len() == 1 {
+        roots.pop().unwrap()
+    } else {
+        PlanDescription {
+            kind: "plan",
+            label: None,
+            children: roots,
+        }
+    }
+}
+
+/// A tree of the steps of a transaction, made by `describe`.
+///
+/// It is rendered by `Display` as an indented tree, one step per line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlanDescription {
+    /// The name of the combinator or leaf
+    pub kind: &'static str,
+    /// The label attached by `named`, if any
+    pub label: Option<String>,
+    /// The steps this step consists of
+    pub children: Vec<PlanDescription>,
+}
+
+impl PlanDescription {
+    fn fmt_indent(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        for _ in 0..depth {
+            f.write_str("  ")?;
+        }
+        match self.label {
+            Some(ref label) => writeln!(f, "{} {:?}", self.kind, label)?,
+            None => writeln!(f, "{}", self.kind)?,
+        }
+        for child in &self.children {
+            child.fmt_indent(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PlanDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indent(f, 0)
+    }
+}
+
+struct Builder {
+    stack: Vec<Vec<PlanDescription>>,
+}
+
+impl Visitor for Builder {
+    fn enter(&mut self, _node: &Node) {
+        self.stack.push(Vec::new());
+    }
+
+    fn leave(&mut self, node: &Node) {
+        let children = self.stack.pop().unwrap_or_default();
+        let plan = PlanDescription {
+            kind: node.kind,
+            label: node.label.map(str::to_string),
+            children,
+        };
+        if let Some(parent) = self.stack.last_mut() {
+            parent.push(plan);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::slice;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Contexts which can run transactions in preview mode, where the write
+/// leaves record what they would do instead of doing it, while the reads
+/// still run. The write leaves of the backend crates consult it, and
+/// `write_effect` makes any transaction such a leaf.
+pub trait DryRun {
+    /// The effects recorded instead of the writes, if the context runs in
+    /// preview mode
+    fn dry_run(&mut self) -> Option<&mut Effects>;
+}
+
+/// The writes a transaction run in preview mode would have done, in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Effects {
+    effects: Vec<String>,
+}
+
+impl Effects {
+    /// Make an empty record
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the description of a write
+    pub fn record<S>(&mut self, effect: S)
+    where
+        S: Into<String>,
+    {
+        self.effects.push(effect.into())
+    }
+
+    /// The descriptions of the writes
+    pub fn as_slice(&self) -> &[String] {
+        &self.effects
+    }
+
+    /// Iterate over the descriptions of the writes
+    pub fn iter(&self) -> slice::Iter<'_, String> {
+        self.effects.iter()
+    }
+
+    /// The number of writes
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    /// Whether no write was recorded
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Unwrap the descriptions of the writes
+    pub fn into_vec(self) -> Vec<String> {
+        self.effects
+    }
+}
+
+impl<'a> IntoIterator for &'a Effects {
+    type Item = &'a String;
+    type IntoIter = slice::Iter<'a, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Run the transaction as a write described by `effect`: in preview mode,
+/// it is not run, the description is recorded and the default item is
+/// returned.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{write_effect, DryRun, Effects};
+///
+/// struct Ledger {
+///     balance: i64,
+///     preview: Option<Effects>,
+/// }
+///
+/// impl DryRun for Ledger {
+///     fn dry_run(&mut self) -> Option<&mut Effects> {
+///         self.preview.as_mut()
+///     }
+/// }
+///
+/// fn withdraw(amount: i64) -> impl Transaction<Ctx = Ledger, Item = i64, Err = String> {
+///     with_ctx(|ledger: &mut Ledger| Ok(ledger.balance)).and_then(move |balance| {
+///         if balance < amount {
+///             return err(format!("{} is short of {}", balance, amount)).boxed();
+///         }
+///         with_ctx(move |ledger: &mut Ledger| {
+///             ledger.balance -= amount;
+///             Ok(())
+///         })
+///         .write_effect(format!("debit {}", amount))
+///         .map(move |_| balance - amount)
+///         .boxed()
+///     })
+/// }
+///
+/// # fn main() {
+/// let mut ledger = Ledger { balance: 100, preview: Some(Effects::new()) };
+/// assert_eq!(withdraw(30).run(&mut ledger), Ok(70));
+/// assert_eq!(withdraw(300).run(&mut ledger), Err("100 is short of 300".to_string()));
+/// // nothing was written
+/// assert_eq!(ledger.balance, 100);
+/// assert_eq!(ledger.preview.unwrap().into_vec(), vec!["debit 30"]);
+/// # }
+/// ```
+pub fn write_effect<Ctx, A, S>(effect: S, a: A) -> WriteEffect<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+    S: Into<String>,
+    Ctx: DryRun,
+{
+    WriteEffect {
+        effect: effect.into(),
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `write_effect`
+#[derive(Debug)]
+#[must_use]
+pub struct WriteEffect<Tx> {
+    effect: String,
+    tx: Tx,
+}
+
+impl<Tx> Transaction for WriteEffect<Tx>
+where
+    Tx: Transaction,
+    Tx::Ctx: DryRun,
+    Tx::Item: Default,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match ctx.dry_run() {
+            Some(effects) => {
+                effects.record(self.effect.clone());
+                Ok(Tx::Item::default())
+            }
+            None => self.tx.run(ctx),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for WriteEffect<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("write_effect"), |v| self.tx.accept(v));
+    }
+}
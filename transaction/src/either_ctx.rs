@@ -0,0 +1,151 @@
+use std::time::SystemTime;
+
+use crate::{IntoTransaction, Transaction};
+use crate::capability::{HasClock, HasConnection, HasRng};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// A context which is one of two backends, chosen at run time.
+///
+/// The capabilities both of the backends provide are provided by this context
+/// too, so transactions generic over the capabilities run against it as is.
+/// For the other transactions, use `either`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EitherCtx<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B, C> HasConnection<C> for EitherCtx<A, B>
+where
+    A: HasConnection<C>,
+    B: HasConnection<C>,
+{
+    fn connection(&mut self) -> &mut C {
+        match *self {
+            EitherCtx::Left(ref mut a) => a.connection(),
+            EitherCtx::Right(ref mut b) => b.connection(),
+        }
+    }
+}
+
+impl<A, B> HasClock for EitherCtx<A, B>
+where
+    A: HasClock,
+    B: HasClock,
+{
+    fn now(&self) -> SystemTime {
+        match *self {
+            EitherCtx::Left(ref a) => a.now(),
+            EitherCtx::Right(ref b) => b.now(),
+        }
+    }
+}
+
+impl<A, B> HasRng for EitherCtx<A, B>
+where
+    A: HasRng,
+    B: HasRng,
+{
+    fn next_u64(&mut self) -> u64 {
+        match *self {
+            EitherCtx::Left(ref mut a) => a.next_u64(),
+            EitherCtx::Right(ref mut b) => b.next_u64(),
+        }
+    }
+}
+
+/// Run `left` if the context is `EitherCtx::Left` and `right` otherwise.
+/// Usually both are the same generic transaction instantiated for each
+/// backend.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{either, EitherCtx};
+///
+/// trait Kv {
+///     fn put(&mut self, k: &str, v: i32);
+/// }
+///
+/// struct Sqlite(Vec<(String, i32)>);
+/// struct Postgres(Vec<(String, i32)>);
+///
+/// impl Kv for Sqlite {
+///     fn put(&mut self, k: &str, v: i32) { self.0.push((k.to_string(), v)) }
+/// }
+/// impl Kv for Postgres {
+///     fn put(&mut self, k: &str, v: i32) { self.0.push((k.to_string(), v)) }
+/// }
+///
+/// fn put<Ctx: Kv>(k: &'static str, v: i32) -> impl Transaction<Ctx = Ctx, Item = (), Err = ()> {
+///     with_ctx(move |ctx: &mut Ctx| {
+///         ctx.put(k, v);
+///         Ok(())
+///     })
+/// }
+///
+/// # fn main() {
+/// let tx = either(put::<Sqlite>("a", 1), put::<Postgres>("a", 1));
+/// let mut ctx = EitherCtx::Left(Sqlite(vec![]));
+/// tx.run(&mut ctx).unwrap();
+/// match ctx {
+///     EitherCtx::Left(db) => assert_eq!(db.0.len(), 1),
+///     EitherCtx::Right(_) => unreachable!(),
+/// }
+/// # }
+/// ```
+pub fn either<CtxA, CtxB, A, B>(left: A, right: B) -> Either<A::Tx, B::Tx>
+where
+    A: IntoTransaction<CtxA>,
+    B: IntoTransaction<CtxB, Item = A::Item, Err = A::Err>,
+{
+    Either {
+        left: left.into_transaction(),
+        right: right.into_transaction(),
+    }
+}
+
+/// The result of `either`
+#[derive(Debug)]
+#[must_use]
+pub struct Either<TxA, TxB> {
+    left: TxA,
+    right: TxB,
+}
+
+impl<TxA, TxB> Transaction for Either<TxA, TxB>
+where
+    TxA: Transaction,
+    TxB: Transaction<Item = TxA::Item, Err = TxA::Err>,
+{
+    type Ctx = EitherCtx<TxA::Ctx, TxB::Ctx>;
+    type Item = TxA::Item;
+    type Err = TxA::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match *ctx {
+            EitherCtx::Left(ref mut a) => self.left.run(a),
+            EitherCtx::Right(ref mut b) => self.right.run(b),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.left.label().or_else(|| self.right.label())
+    }
+}
+
+impl<TxA, TxB> Visit for Either<TxA, TxB>
+where
+    TxA: Visit,
+    TxB: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("either"), |v| {
+            self.left.accept(v);
+            self.right.accept(v);
+        });
+    }
+}
@@ -0,0 +1,165 @@
+use std::any::{self, Any};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Arc;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_leaf, visit_node, Node, Visit, Visitor};
+
+thread_local!(static ENVS: RefCell<Vec<SharedEnv>> = const { RefCell::new(Vec::new()) });
+
+// an environment provided, shared with the threads of `par_join` and `scope`
+pub(crate) type SharedEnv = Arc<dyn Any + Send + Sync>;
+
+/// Make the read-only environment `env` available to the `with_env` leaves
+/// of the transaction. Unlike the context, the environment is not a part of
+/// the transaction type, so configurations, feature flags or request metadata
+/// can be injected without wrapping the backend context.
+///
+/// Environments are looked up by their type. When `provide` is nested, the
+/// innermost environment of the type wins. They are shared with the
+/// branches of `par_join` and `scope` running on other threads, so they are
+/// `Send` and `Sync`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{provide, with_env};
+///
+/// struct Config {
+///     page_size: usize,
+/// }
+///
+/// # fn main() {
+/// let page = with_env(|config: &Config, rows: &mut Vec<i32>| -> Result<Vec<i32>, ()> {
+///     Ok(rows.iter().cloned().take(config.page_size).collect())
+/// });
+/// let tx = provide(Config { page_size: 2 }, page);
+/// assert_eq!(tx.run(&mut vec![1, 2, 3]), Ok(vec![1, 2]));
+/// # }
+/// ```
+pub fn provide<Ctx, Env, A>(env: Env, a: A) -> Provide<Env, A::Tx>
+where
+    Env: Send + Sync + 'static,
+    A: IntoTransaction<Ctx>,
+{
+    Provide {
+        env: Arc::new(env),
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `provide`
+#[derive(Debug)]
+#[must_use]
+pub struct Provide<Env, Tx> {
+    env: Arc<Env>,
+    tx: Tx,
+}
+
+impl<Env, Tx> Transaction for Provide<Env, Tx>
+where
+    Env: Send + Sync + 'static,
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ENVS.with(|envs| envs.borrow_mut().push(self.env.clone()));
+        let _guard = PopGuard;
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Env, Tx> Visit for Provide<Env, Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("provide"), |v| self.tx.accept(v));
+    }
+}
+
+// the environments provided on the thread, innermost last
+pub(crate) fn current() -> Vec<SharedEnv> {
+    ENVS.with(|envs| envs.borrow().clone())
+}
+
+// replace the environments provided on the thread, returning those replaced
+pub(crate) fn replace(envs: Vec<SharedEnv>) -> Vec<SharedEnv> {
+    ENVS.with(|current| mem::replace(&mut *current.borrow_mut(), envs))
+}
+
+// pops the environment even if the transaction panics
+struct PopGuard;
+
+impl Drop for PopGuard {
+    fn drop(&mut self) {
+        ENVS.with(|envs| envs.borrow_mut().pop());
+    }
+}
+
+/// Receive the environment given by `provide` together with the context and
+/// perform computation.
+///
+/// # Panics
+///
+/// Panics when run outside of a `provide` of the environment type.
+pub fn with_env<Env, Ctx, F, T, E>(f: F) -> WithEnv<Env, Ctx, F>
+where
+    Env: Send + Sync + 'static,
+    F: Fn(&Env, &mut Ctx) -> Result<T, E>,
+{
+    WithEnv {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_env`
+#[derive(Debug)]
+#[must_use]
+pub struct WithEnv<Env, Ctx, F> {
+    f: F,
+    _phantom: PhantomData<(Env, Ctx)>,
+}
+
+impl<Env, Ctx, F, T, E> Transaction for WithEnv<Env, Ctx, F>
+where
+    Env: Send + Sync + 'static,
+    F: Fn(&Env, &mut Ctx) -> Result<T, E>,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        // clone the handle so that the computation can `provide` again
+        let env = ENVS.with(|envs| {
+            envs.borrow()
+                .iter()
+                .rev()
+                .find_map(|env| env.clone().downcast::<Env>().ok())
+        });
+        match env {
+            Some(env) => (self.f)(&env, ctx),
+            None => panic!("no environment of type {} is provided", any::type_name::<Env>()),
+        }
+    }
+}
+
+impl<Env, Ctx, F> Visit for WithEnv<Env, Ctx, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_env"));
+    }
+}
@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use crate::Transaction;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
 
 /// make a error transaction value.
 pub fn err<Ctx, T, E>(e: E) -> TxErr<Ctx, T, E> {
@@ -30,3 +31,9 @@ where
         Err(self.err.clone())
     }
 }
+
+impl<Ctx, T, E> Visit for TxErr<Ctx, T, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("err"));
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{clock, IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// A fault injected into a run of a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// Fail the step with `InjectedFault` without running it
+    Fail,
+    /// Sleep before running the step
+    Delay(Duration),
+}
+
+/// Policies deciding the faults injected into the steps by their labels
+pub trait FaultPolicy {
+    /// The fault to inject into this run of the step, if any
+    fn fault(&self, label: Option<&str>) -> Option<Fault>;
+}
+
+impl<P> FaultPolicy for &P
+where
+    P: ?Sized + FaultPolicy,
+{
+    fn fault(&self, label: Option<&str>) -> Option<Fault> {
+        (**self).fault(label)
+    }
+}
+
+/// When a rule of `Faults` injects its fault
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// Every run of the step
+    Always,
+    /// The Nth run of the step, counted from 1
+    Nth(usize),
+    /// Each run of the step with the probability, drawn from the seeded
+    /// generator of the `Faults`
+    Probability(f64),
+}
+
+/// A `FaultPolicy` of rules injecting faults into the steps of the labels.
+/// The first rule of the label triggered by the run wins. The runs are
+/// counted and the probabilities drawn across all the transactions given
+/// the policy, so share it by reference.
+///
+/// With no rules, which is the default, it injects nothing.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{Faults, InjectedFault, Trigger};
+///
+/// # fn main() {
+/// let faults = Faults::new().fail("credit", Trigger::Nth(2));
+///
+/// let add = |label: &'static str, amount: i32| {
+///     with_ctx(move |balances: &mut Vec<i32>| -> Result<(), InjectedFault> {
+///         balances.push(amount);
+///         Ok(())
+///     })
+///     .named(label)
+///     .inject_fault(&faults)
+/// };
+/// let transfer = add("debit", -10).and_then(|_| add("credit", 10));
+///
+/// let mut balances = Vec::new();
+/// assert_eq!(transfer.run(&mut balances), Ok(()));
+/// let e = transfer.run(&mut balances).unwrap_err();
+/// assert_eq!(e.label(), Some("credit"));
+/// assert_eq!(balances, vec![-10, 10, -10]);
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct Faults {
+    rules: Vec<Rule>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    label: String,
+    trigger: Trigger,
+    fault: Fault,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    runs: HashMap<String, usize>,
+    rng: u64,
+}
+
+impl Faults {
+    /// A policy injecting nothing, with the generator seeded with 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seed the generator of the probabilities
+    pub fn seed(self, seed: u64) -> Self {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).rng = seed;
+        self
+    }
+
+    /// Fail the runs of the steps labeled `label` when triggered
+    pub fn fail<L>(self, label: L, trigger: Trigger) -> Self
+    where
+        L: Into<String>,
+    {
+        self.rule(label.into(), trigger, Fault::Fail)
+    }
+
+    /// Delay the runs of the steps labeled `label` when triggered
+    pub fn delay<L>(self, label: L, delay: Duration, trigger: Trigger) -> Self
+    where
+        L: Into<String>,
+    {
+        self.rule(label.into(), trigger, Fault::Delay(delay))
+    }
+
+    fn rule(mut self, label: String, trigger: Trigger, fault: Fault) -> Self {
+        self.rules.push(Rule { label, trigger, fault });
+        self
+    }
+}
+
+impl FaultPolicy for Faults {
+    fn fault(&self, label: Option<&str>) -> Option<Fault> {
+        let label = label?;
+        if !self.rules.iter().any(|rule| rule.label == label) {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let run = {
+            let runs = state.runs.entry(label.to_string()).or_insert(0);
+            *runs += 1;
+            *runs
+        };
+        for rule in self.rules.iter().filter(|rule| rule.label == label) {
+            let triggered = match rule.trigger {
+                Trigger::Always => true,
+                Trigger::Nth(n) => run == n,
+                Trigger::Probability(p) => unit(splitmix64(&mut state.rng)) < p,
+            };
+            if triggered {
+                return Some(rule.fault);
+            }
+        }
+        None
+    }
+}
+
+/// The error of a step failed by `inject_fault`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InjectedFault {
+    label: Option<String>,
+}
+
+impl InjectedFault {
+    /// The label of the failed step
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.label {
+            Some(ref label) => write!(f, "fault injected into step {}", label),
+            None => write!(f, "fault injected into an unlabeled step"),
+        }
+    }
+}
+
+impl Error for InjectedFault {}
+
+/// Inject the faults the policy decides by the label of the transaction
+/// into its runs: fail it with `InjectedFault` without running it, or sleep
+/// before running it. It is meant for the labeled steps of a composition,
+/// e.g. to exercise its rollback and compensation paths in tests and chaos
+/// environments, and is a no-op with an empty `Faults`.
+pub fn inject_fault<Ctx, A, P>(a: A, policy: P) -> InjectFault<A::Tx, P>
+where
+    A: IntoTransaction<Ctx>,
+    A::Err: From<InjectedFault>,
+    P: FaultPolicy,
+{
+    InjectFault {
+        tx: a.into_transaction(),
+        policy,
+    }
+}
+
+/// The result of `inject_fault`
+#[derive(Debug)]
+#[must_use]
+pub struct InjectFault<Tx, P> {
+    tx: Tx,
+    policy: P,
+}
+
+impl<Tx, P> Transaction for InjectFault<Tx, P>
+where
+    Tx: Transaction,
+    Tx::Err: From<InjectedFault>,
+    P: FaultPolicy,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let label = self.tx.label();
+        match self.policy.fault(label) {
+            None => {}
+            Some(Fault::Fail) => {
+                #[cfg(feature = "log")]
+                log::debug!("injecting failure into step {:?}", label);
+                return Err(InjectedFault {
+                    label: label.map(str::to_string),
+                }
+                .into());
+            }
+            Some(Fault::Delay(delay)) => {
+                #[cfg(feature = "log")]
+                log::debug!("injecting delay of {:?} into step {:?}", delay, label);
+                clock::sleep(delay);
+            }
+        }
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, P> Visit for InjectFault<Tx, P>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("inject_fault"), |v| self.tx.accept(v));
+    }
+}
+
+// SplitMix64, which is enough to draw the faults
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// a number in [0, 1) from the 53 high bits
+pub(crate) fn unit(n: u64) -> f64 {
+    (n >> 11) as f64 / (1u64 << 53) as f64
+}
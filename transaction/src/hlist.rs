@@ -0,0 +1,153 @@
+use std::marker::PhantomData;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// The empty context list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct HNil;
+
+/// A context list with `head` followed by the contexts in `tail`.
+/// Use `hlist!` to build one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct HCons<H, T> {
+    pub head: H,
+    pub tail: T,
+}
+
+/// Index of the context at the head of the list
+#[derive(Debug)]
+pub enum Here {}
+
+/// Index of the context at the `I` in the tail of the list
+#[derive(Debug)]
+pub struct There<I>(PhantomData<I>);
+
+/// Context lists containing the context `T` at the index `I`.
+/// The index is inferred, so users don't need to write it.
+pub trait Contains<T, I> {
+    /// borrow the context
+    fn get(&self) -> &T;
+
+    /// mutably borrow the context
+    fn get_mut(&mut self) -> &mut T;
+}
+
+impl<T, Tail> Contains<T, Here> for HCons<T, Tail> {
+    fn get(&self) -> &T {
+        &self.head
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        &mut self.head
+    }
+}
+
+impl<H, T, Tail, I> Contains<T, There<I>> for HCons<H, Tail>
+where
+    Tail: Contains<T, I>,
+{
+    fn get(&self) -> &T {
+        self.tail.get()
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        self.tail.get_mut()
+    }
+}
+
+/// Build a context list.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// struct Db(Vec<i32>);
+/// struct Cache(Option<i32>);
+/// struct Clock(u64);
+///
+/// # fn main() {
+/// let save = with_ctx(|db: &mut Db| -> Result<(), ()> {
+///     db.0.push(1);
+///     Ok(())
+/// });
+/// let invalidate = with_ctx(|cache: &mut Cache| -> Result<(), ()> {
+///     cache.0 = None;
+///     Ok(())
+/// });
+/// let tx = save.lift().join(invalidate.lift());
+///
+/// let mut ctx = hlist![Db(vec![]), Cache(Some(0)), Clock(0)];
+/// tx.run(&mut ctx).unwrap();
+/// assert_eq!(ctx.head.0, vec![1]);
+/// assert_eq!(ctx.tail.head.0, None);
+/// # }
+/// ```
+///
+/// The macro can also be called by its path, without `#[macro_use]`:
+///
+/// ```edition2021
+/// let ctx = transaction::hlist![1u8, 2u16, 3u32];
+/// assert_eq!((ctx.head, ctx.tail.head, ctx.tail.tail.head), (1, 2, 3));
+/// ```
+#[macro_export]
+macro_rules! hlist {
+    () => { $crate::HNil };
+    ($head: expr $(, $tail: expr)* $(,)*) => {
+        $crate::HCons {
+            head: $head,
+            tail: $crate::hlist!($($tail),*),
+        }
+    };
+}
+
+/// Run the transaction on the context of its type found in a context list.
+pub fn lift<Ctx, A, L, I>(a: A) -> Lift<L, I, A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+    L: Contains<Ctx, I>,
+{
+    Lift {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `lift`
+#[derive(Debug)]
+#[must_use]
+pub struct Lift<L, I, Tx> {
+    tx: Tx,
+    _phantom: PhantomData<(L, I)>,
+}
+
+impl<L, I, Tx> Transaction for Lift<L, I, Tx>
+where
+    Tx: Transaction,
+    L: Contains<Tx::Ctx, I>,
+{
+    type Ctx = L;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.tx.run(ctx.get_mut())
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<L, I, Tx> Visit for Lift<L, I, Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("lift"), |v| self.tx.accept(v));
+    }
+}
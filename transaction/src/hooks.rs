@@ -0,0 +1,229 @@
+//! Application-wide hooks invoked by runners around every transaction
+//! execution.
+//!
+//! Like the global logger of `log`, hooks are registered once at start up
+//! and then notified by all the runners, so auditing doesn't need any
+//! decorators at the call sites.
+//!
+//! Runners are panic safe: if a transaction panics, the runner rolls back
+//! the transaction of the backend and gives the connection back in a clean
+//! state, then notifies the hooks that the transaction was rolled back and
+//! resumes the panic. The synchronous runners roll back from a guard
+//! dropped while unwinding, or leave it to the transaction of the driver
+//! when it rolls back on drop, and the asynchronous ones catch the panic
+//! and roll back before resuming it. Each backend documents how on its
+//! runner.
+//!
+//! The runners notify the hooks and the metrics by `instrument`, or
+//! `instrument_async` for the async ones, so that they all report the runs
+//! alike.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use transaction::hooks::{self, Outcome, RunHooks};
+//!
+//! struct CountCommits(AtomicUsize);
+//!
+//! impl RunHooks for CountCommits {
+//!     fn before_run(&self, _label: Option<&str>) {}
+//!     fn after_run(&self, _label: Option<&str>, outcome: Outcome) {
+//!         if outcome == Outcome::Committed {
+//!             self.0.fetch_add(1, Ordering::SeqCst);
+//!         }
+//!     }
+//! }
+//!
+//! # fn main() {
+//! hooks::register(Box::new(CountCommits(AtomicUsize::new(0))));
+//! # }
+//! ```
+
+use std::future::Future;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::clock::Instant;
+use crate::metrics;
+use crate::runner::{discard_deferred, CommitFrame};
+
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+static HOOKS: RwLock<Vec<Box<dyn RunHooks>>> = RwLock::new(Vec::new());
+
+/// How the execution of a transaction ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The transaction succeeded and its effects are committed
+    Committed,
+    /// The transaction failed or was not committed
+    RolledBack,
+}
+
+impl Outcome {
+    /// `Committed` for `Ok`, `RolledBack` for `Err`
+    pub fn of<T, E>(result: &Result<T, E>) -> Self {
+        match *result {
+            Ok(_) => Outcome::Committed,
+            Err(_) => Outcome::RolledBack,
+        }
+    }
+}
+
+/// Hooks invoked by runners before and after every transaction execution.
+/// `label` is the label of the outermost transaction given by `named`.
+pub trait RunHooks: Send + Sync {
+    /// Called before the runner starts the transaction
+    fn before_run(&self, label: Option<&str>);
+
+    /// Called after the runner committed or rolled back the transaction
+    fn after_run(&self, label: Option<&str>, outcome: Outcome);
+}
+
+/// Register hooks. All the registered hooks are invoked in the registration
+/// order.
+pub fn register(hooks: Box<dyn RunHooks>) {
+    HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(hooks);
+    REGISTERED.store(true, Ordering::Release);
+}
+
+/// Notify the hooks that a transaction is about to run.
+/// This is called by transaction runners rather than users.
+pub fn before_run(label: Option<&str>) {
+    if !REGISTERED.load(Ordering::Acquire) {
+        return;
+    }
+    for hooks in HOOKS.read().unwrap_or_else(|e| e.into_inner()).iter() {
+        hooks.before_run(label);
+    }
+}
+
+/// Notify the hooks that a transaction has finished.
+/// This is called by transaction runners rather than users.
+pub fn after_run(label: Option<&str>, outcome: Outcome) {
+    if !REGISTERED.load(Ordering::Acquire) {
+        return;
+    }
+    for hooks in HOOKS.read().unwrap_or_else(|e| e.into_inner()).iter() {
+        hooks.after_run(label, outcome);
+    }
+}
+
+/// Notify the hooks and the metrics that the transaction was rolled back
+/// when dropped while the thread is panicking, so a transaction panicking
+/// between `before_run` and `after_run` is still reported. Runners create it
+/// right after `before_run` and keep it for the whole run.
+#[derive(Debug)]
+pub struct PanicGuard<'a> {
+    label: Option<&'a str>,
+    start: Instant,
+}
+
+impl<'a> PanicGuard<'a> {
+    /// Guard the run of the transaction
+    pub fn new(label: Option<&'a str>) -> Self {
+        PanicGuard {
+            label,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl<'a> Drop for PanicGuard<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            metrics::record_run(self.label, Outcome::RolledBack, self.start.elapsed());
+            after_run(self.label, Outcome::RolledBack);
+        }
+    }
+}
+
+/// Notify the hooks and the metrics around the run of a transaction by `f`,
+/// which begins, runs and ends the transaction of the backend. The outcome
+/// is given by `outcome_of`, e.g. `Outcome::of` for the runners committing
+/// the transactions which succeed, and a run which panics is reported rolled
+/// back. With the `log` and `tracing` features, the run is also logged, and
+/// traced in a span with the name of the backend, e.g. `"postgres"`.
+///
+/// The actions deferred by the transaction until it commits, e.g. filling
+/// the caches of `cached`, are run if the outcome is `Committed`, and
+/// dropped otherwise.
+/// This is called by transaction runners rather than users.
+#[cfg_attr(not(any(feature = "log", feature = "tracing")), allow(unused_variables))]
+pub fn instrument<R, F>(backend: &str, label: Option<&str>, outcome_of: fn(&R) -> Outcome, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    before_run(label);
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!("transaction", backend = backend, label = label).entered();
+    #[cfg(feature = "log")]
+    log::debug!("start {} transaction {:?}", backend, label);
+    let start = Instant::now();
+    let _guard = PanicGuard::new(label);
+    let mut frame = CommitFrame::enter();
+    let ret = f();
+    let outcome = outcome_of(&ret);
+    frame.committed = outcome == Outcome::Committed;
+    drop(frame);
+    finish_run(backend, label, outcome, start);
+    ret
+}
+
+/// Record a retry of the transaction run by `instrument` on the thread, and
+/// drop the actions it deferred until commit, since the failed attempt
+/// doesn't commit. This is called by the runners which run the transaction
+/// again inside `instrument`, e.g. on conflicts, rather than users.
+pub fn retry(label: Option<&str>) {
+    metrics::record_retry(label);
+    discard_deferred();
+}
+
+/// Same as `instrument`, for the runners of async transactions. The span is
+/// entered whenever the future is polled. The actions deferred until commit
+/// are not run, since the future may be polled on any thread.
+#[cfg_attr(not(any(feature = "log", feature = "tracing")), allow(unused_variables))]
+pub async fn instrument_async<R, Fut>(backend: &str, label: Option<&str>, outcome_of: fn(&R) -> Outcome, fut: Fut) -> R
+where
+    Fut: Future<Output = R>,
+{
+    before_run(label);
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!("transaction", backend = backend, label = label);
+    #[cfg(feature = "tracing")]
+    let fut = tracing::Instrument::instrument(fut, span.clone());
+    #[cfg(feature = "log")]
+    log::debug!("start {} transaction {:?}", backend, label);
+    let start = Instant::now();
+    let _guard = PanicGuard::new(label);
+    let ret = fut.await;
+    let outcome = outcome_of(&ret);
+    #[cfg(feature = "tracing")]
+    span.in_scope(|| finish_run(backend, label, outcome, start));
+    #[cfg(not(feature = "tracing"))]
+    finish_run(backend, label, outcome, start);
+    ret
+}
+
+// report the end of a run by `instrument`
+#[cfg_attr(not(feature = "log"), allow(unused_variables))]
+fn finish_run(backend: &str, label: Option<&str>, outcome: Outcome, start: Instant) {
+    metrics::record_run(label, outcome, start.elapsed());
+    #[cfg(feature = "tracing")]
+    match outcome {
+        Outcome::Committed => tracing::debug!("commit"),
+        Outcome::RolledBack => tracing::debug!("rollback"),
+    }
+    #[cfg(feature = "log")]
+    match outcome {
+        Outcome::Committed => log::debug!("commit {} transaction {:?}", backend, label),
+        Outcome::RolledBack => log::debug!("rollback {} transaction {:?}", backend, label),
+    }
+    after_run(label, outcome);
+}
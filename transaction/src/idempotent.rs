@@ -0,0 +1,121 @@
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Backend contexts storing the items of the transactions by their
+/// idempotency keys, e.g. in a table, within the transaction.
+pub trait IdempotencyStore<T> {
+    /// The error of the store
+    type Error;
+
+    /// The item recorded for the key, if any
+    fn lookup(&mut self, key: &str) -> Result<Option<T>, Self::Error>;
+
+    /// Record the item for the key
+    fn record(&mut self, key: &str, item: &T) -> Result<(), Self::Error>;
+}
+
+/// Run the transaction once per idempotency key: return the item recorded
+/// for the key if there is one, or run the transaction and record its item
+/// in the same transaction. A failed transaction records nothing, so it runs
+/// again with the same key.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::collections::HashMap;
+/// use std::convert::Infallible;
+/// use transaction::prelude::*;
+/// use transaction::IdempotencyStore;
+///
+/// #[derive(Default)]
+/// struct Payments {
+///     charged: u32,
+///     keys: HashMap<String, u32>,
+/// }
+///
+/// impl IdempotencyStore<u32> for Payments {
+///     type Error = Infallible;
+///     fn lookup(&mut self, key: &str) -> Result<Option<u32>, Infallible> {
+///         Ok(self.keys.get(key).cloned())
+///     }
+///     fn record(&mut self, key: &str, item: &u32) -> Result<(), Infallible> {
+///         self.keys.insert(key.to_string(), *item);
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() {
+/// let charge = with_ctx(|p: &mut Payments| -> Result<u32, Infallible> {
+///     p.charged += 100;
+///     Ok(p.charged)
+/// })
+/// .idempotent("payment-1");
+///
+/// let mut payments = Payments::default();
+/// assert_eq!(charge.run(&mut payments), Ok(100));
+/// // the webhook is delivered twice
+/// assert_eq!(charge.run(&mut payments), Ok(100));
+/// assert_eq!(payments.charged, 100);
+/// # }
+/// ```
+pub fn idempotent<Ctx, A>(key: impl Into<String>, a: A) -> Idempotent<A::Tx>
+where
+    Ctx: IdempotencyStore<A::Item>,
+    A: IntoTransaction<Ctx>,
+    A::Err: From<Ctx::Error>,
+{
+    Idempotent {
+        key: key.into(),
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `idempotent`
+#[derive(Debug)]
+#[must_use]
+pub struct Idempotent<Tx> {
+    key: String,
+    tx: Tx,
+}
+
+impl<Tx> Idempotent<Tx> {
+    /// The idempotency key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl<Tx> Transaction for Idempotent<Tx>
+where
+    Tx: Transaction,
+    Tx::Ctx: IdempotencyStore<Tx::Item>,
+    Tx::Err: From<<Tx::Ctx as IdempotencyStore<Tx::Item>>::Error>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        if let Some(item) = ctx.lookup(&self.key)? {
+            return Ok(item);
+        }
+        let item = self.tx.run(ctx)?;
+        ctx.record(&self.key, &item)?;
+        Ok(item)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Idempotent<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("idempotent"), |v| self.tx.accept(v));
+    }
+}
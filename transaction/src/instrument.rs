@@ -0,0 +1,57 @@
+use tracing::Span;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Enter the span while the transaction is running.
+pub fn instrument<Ctx, A>(a: A, span: Span) -> Instrumented<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    Instrumented {
+        tx: a.into_transaction(),
+        span,
+    }
+}
+
+/// The result of `instrument`
+#[derive(Debug)]
+#[must_use]
+pub struct Instrumented<Tx> {
+    tx: Tx,
+    span: Span,
+}
+
+impl<Tx> Instrumented<Tx> {
+    /// The span entered while running
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+impl<Tx> Transaction for Instrumented<Tx>
+where
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let _enter = self.span.enter();
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Instrumented<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("instrument"), |v| self.tx.accept(v));
+    }
+}
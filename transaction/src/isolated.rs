@@ -0,0 +1,327 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::level::{AtLeast, Level};
+use crate::{
+    Abort, AndThen, Branch, Branch3, Branch4, IntoTransaction, IsolationLevel, Join, Join3, Join4, JoinAll, Lazy,
+    LoopFn, Map, MapErr, Named, OrElse, ReadCtx, Recover, Repeat, Retry, Then, Transaction, TryAbort, TryRecover,
+    TxErr, TxOk, TxResult, WithCtx, Zoom,
+};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+thread_local!(static ISOLATION: RefCell<Vec<Option<IsolationLevel>>> = const { RefCell::new(Vec::new()) });
+
+/// Transactions safe to run at the isolation level `L`, so that the runners
+/// taking the level as a type, e.g. `Runner::run_at` of
+/// transaction-postgres, reject at compile time those requiring a stronger
+/// level.
+///
+/// The leaves implement it for all the levels, except `requires_isolation`,
+/// which implements it for the levels at least as strong as its own, and
+/// the combinators do when all the transactions they are made of do.
+///
+/// # Examples
+///
+/// ```compile_fail
+/// extern crate transaction;
+///
+/// use transaction::level::{Level, ReadCommitted, Serializable};
+/// use transaction::prelude::*;
+/// use transaction::IsolatedAt;
+///
+/// fn run_at<L: Level, Tx: IsolatedAt<L, Ctx = Vec<i32>>>(tx: Tx, ctx: &mut Vec<i32>) -> Result<Tx::Item, Tx::Err> {
+///     tx.run(ctx)
+/// }
+///
+/// # fn main() {
+/// let push = with_ctx(|v: &mut Vec<i32>| Ok::<_, transaction::IsolationTooWeak>(v.push(1)));
+/// run_at::<ReadCommitted, _>(push.requires_isolation::<Serializable>(), &mut vec![]);
+/// # }
+/// ```
+pub trait IsolatedAt<L: Level>: Transaction {}
+
+/// The error of a transaction run at an isolation level weaker than the one
+/// it requires, see `requires_isolation`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsolationTooWeak {
+    /// The isolation level the transaction requires
+    pub required: IsolationLevel,
+    /// The isolation level of the run, or `None` for the database default
+    pub actual: Option<IsolationLevel>,
+}
+
+impl fmt::Display for IsolationTooWeak {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.actual {
+            Some(actual) => write!(f, "a transaction requiring {} ran at {}", self.required, actual),
+            None => write!(
+                f,
+                "a transaction requiring {} ran at the default isolation level",
+                self.required
+            ),
+        }
+    }
+}
+
+impl Error for IsolationTooWeak {}
+
+/// The isolation level of the transaction run by a runner, checked by the
+/// `requires_isolation`s of the transaction. The runners of the backend
+/// crates enter it around the run with the configured level, or `None` for
+/// the database default, which satisfies no requirement.
+#[derive(Debug)]
+pub struct RunIsolation {
+    _private: (),
+}
+
+impl RunIsolation {
+    /// Run the transactions at the level until dropped
+    pub fn enter(level: Option<IsolationLevel>) -> Self {
+        ISOLATION.with(|levels| levels.borrow_mut().push(level));
+        RunIsolation { _private: () }
+    }
+}
+
+impl Drop for RunIsolation {
+    fn drop(&mut self) {
+        ISOLATION.with(|levels| levels.borrow_mut().pop());
+    }
+}
+
+// the levels entered on the thread, innermost last
+pub(crate) fn current() -> Vec<Option<IsolationLevel>> {
+    ISOLATION.with(|levels| levels.borrow().clone())
+}
+
+// replace the levels entered on the thread, returning those replaced
+pub(crate) fn replace(levels: Vec<Option<IsolationLevel>>) -> Vec<Option<IsolationLevel>> {
+    ISOLATION.with(|current| mem::replace(&mut *current.borrow_mut(), levels))
+}
+
+/// Declare that the transaction requires the isolation level `L` at least,
+/// e.g. a check-then-insert which is only safe at `SERIALIZABLE`.
+///
+/// The requirement is checked at compile time by the runners taking the
+/// level as a type, see `IsolatedAt`. The runners configured at run time
+/// enter their level by `RunIsolation`, and the transaction fails with
+/// `IsolationTooWeak` before it runs if the level is weaker. Outside of
+/// such runners, e.g. on a context in memory, nothing is checked.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::level::{Level, Serializable};
+/// use transaction::prelude::*;
+/// use transaction::{IsolatedAt, IsolationLevel, IsolationTooWeak, RunIsolation};
+///
+/// // reserve a seat if any is left, a write skew below serializable
+/// fn reserve() -> impl IsolatedAt<Serializable, Ctx = Vec<u32>, Item = usize, Err = IsolationTooWeak> {
+///     with_ctx(|seats: &mut Vec<u32>| {
+///         if seats.len() < 2 {
+///             seats.push(1);
+///         }
+///         Ok(seats.len())
+///     })
+///     .requires_isolation::<Serializable>()
+/// }
+///
+/// // a runner running at the level `L`
+/// fn run_at<L: Level, Tx: IsolatedAt<L, Ctx = Vec<u32>>>(tx: Tx, seats: &mut Vec<u32>) -> Result<Tx::Item, Tx::Err> {
+///     let _isolation = RunIsolation::enter(Some(L::LEVEL));
+///     tx.run(seats)
+/// }
+///
+/// # fn main() {
+/// let mut seats = vec![];
+/// assert_eq!(run_at::<Serializable, _>(reserve(), &mut seats), Ok(1));
+/// // run_at::<ReadCommitted, _>(reserve(), &mut seats) doesn't compile
+///
+/// // checked at run time by the runners configured at run time
+/// let _isolation = RunIsolation::enter(Some(IsolationLevel::ReadCommitted));
+/// assert_eq!(
+///     reserve().run(&mut seats),
+///     Err(IsolationTooWeak { required: IsolationLevel::Serializable, actual: Some(IsolationLevel::ReadCommitted) })
+/// );
+/// assert_eq!(seats, vec![1]);
+/// # }
+/// ```
+pub fn requires_isolation<L, Ctx, A>(a: A) -> RequiresIsolation<L, A::Tx>
+where
+    L: Level,
+    A: IntoTransaction<Ctx>,
+{
+    RequiresIsolation {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `requires_isolation`
+#[derive(Debug)]
+#[must_use]
+pub struct RequiresIsolation<L, Tx> {
+    tx: Tx,
+    _phantom: PhantomData<L>,
+}
+
+impl<L, Tx> Transaction for RequiresIsolation<L, Tx>
+where
+    L: Level,
+    Tx: Transaction,
+    Tx::Err: From<IsolationTooWeak>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let actual = ISOLATION.with(|levels| levels.borrow().last().copied());
+        match actual {
+            // the database default, `None`, is weaker than any level
+            Some(actual) if actual < Some(L::LEVEL) => Err(IsolationTooWeak {
+                required: L::LEVEL,
+                actual,
+            }
+            .into()),
+            _ => self.tx.run(ctx),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<L, Tx> Visit for RequiresIsolation<L, Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("requires_isolation"), |v| self.tx.accept(v));
+    }
+}
+
+impl<M, L, Tx> IsolatedAt<M> for RequiresIsolation<L, Tx>
+where
+    Self: Transaction,
+    M: AtLeast<L>,
+    L: Level,
+    Tx: IsolatedAt<M>,
+{
+}
+
+// the leaves
+impl<L: Level, Ctx, T, E, F> IsolatedAt<L> for WithCtx<Ctx, F> where F: Fn(&mut Ctx) -> Result<T, E> {}
+impl<L: Level, Ctx, T, E, F> IsolatedAt<L> for ReadCtx<Ctx, F> where F: Fn(&Ctx) -> Result<T, E> {}
+impl<L: Level, Ctx, T, E> IsolatedAt<L> for TxOk<Ctx, T, E> where Self: Transaction {}
+impl<L: Level, Ctx, T, E> IsolatedAt<L> for TxErr<Ctx, T, E> where Self: Transaction {}
+impl<L: Level, Ctx, T, E> IsolatedAt<L> for TxResult<Ctx, T, E> where Self: Transaction {}
+impl<L: Level, Ctx, T, E, F> IsolatedAt<L> for Lazy<Ctx, F> where F: Fn() -> Result<T, E> {}
+
+// the combinators
+impl<L: Level, T> IsolatedAt<L> for Box<T> where T: IsolatedAt<L> + ?Sized {}
+impl<L: Level, T> IsolatedAt<L> for &T where T: IsolatedAt<L> + ?Sized {}
+impl<L: Level, Tx> IsolatedAt<L> for Named<Tx> where Tx: IsolatedAt<L> {}
+impl<L: Level, Tx, F> IsolatedAt<L> for Map<Tx, F> where Self: Transaction, Tx: IsolatedAt<L> {}
+impl<L: Level, Tx, F> IsolatedAt<L> for MapErr<Tx, F> where Self: Transaction, Tx: IsolatedAt<L> {}
+impl<L: Level, Tx1, F, Tx2> IsolatedAt<L> for AndThen<Tx1, F, Tx2>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+{
+}
+impl<L: Level, Tx1, F, Tx2> IsolatedAt<L> for OrElse<Tx1, F, Tx2>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+{
+}
+impl<L: Level, Tx1, F, Tx2> IsolatedAt<L> for Then<Tx1, F, Tx2>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+{
+}
+impl<L: Level, Tx, T, F> IsolatedAt<L> for Recover<Tx, T, F> where Self: Transaction, Tx: IsolatedAt<L> {}
+impl<L: Level, Tx, F, B> IsolatedAt<L> for TryRecover<Tx, F, B> where Self: Transaction, Tx: IsolatedAt<L> {}
+impl<L: Level, Tx, T, F> IsolatedAt<L> for Abort<Tx, T, F> where Self: Transaction, Tx: IsolatedAt<L> {}
+impl<L: Level, Tx, F, B> IsolatedAt<L> for TryAbort<Tx, F, B> where Self: Transaction, Tx: IsolatedAt<L> {}
+impl<L: Level, Tx1, Tx2> IsolatedAt<L> for Join<Tx1, Tx2>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+{
+}
+impl<L: Level, Tx1, Tx2, Tx3> IsolatedAt<L> for Join3<Tx1, Tx2, Tx3>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+    Tx3: IsolatedAt<L>,
+{
+}
+impl<L: Level, Tx1, Tx2, Tx3, Tx4> IsolatedAt<L> for Join4<Tx1, Tx2, Tx3, Tx4>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+    Tx3: IsolatedAt<L>,
+    Tx4: IsolatedAt<L>,
+{
+}
+impl<L: Level, Tx> IsolatedAt<L> for JoinAll<Tx> where Self: Transaction, Tx: IsolatedAt<L> {}
+impl<L: Level, Tx1, Tx2> IsolatedAt<L> for Branch<Tx1, Tx2>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+{
+}
+impl<L: Level, Tx1, Tx2, Tx3> IsolatedAt<L> for Branch3<Tx1, Tx2, Tx3>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+    Tx3: IsolatedAt<L>,
+{
+}
+impl<L: Level, Tx1, Tx2, Tx3, Tx4> IsolatedAt<L> for Branch4<Tx1, Tx2, Tx3, Tx4>
+where
+    Self: Transaction,
+    Tx1: IsolatedAt<L>,
+    Tx2: IsolatedAt<L>,
+    Tx3: IsolatedAt<L>,
+    Tx4: IsolatedAt<L>,
+{
+}
+impl<L: Level, Big, Tx, F> IsolatedAt<L> for Zoom<Big, Tx, F> where Self: Transaction, Tx: IsolatedAt<L> {}
+impl<L: Level, Ctx, F, Tx> IsolatedAt<L> for Retry<Ctx, F, Tx>
+where
+    Self: Transaction,
+    Tx: IntoTransaction<Ctx>,
+    Tx::Tx: IsolatedAt<L>,
+{
+}
+impl<L: Level, Ctx, F, Tx> IsolatedAt<L> for Repeat<Ctx, F, Tx>
+where
+    Self: Transaction,
+    Tx: IntoTransaction<Ctx>,
+    Tx::Tx: IsolatedAt<L>,
+{
+}
+impl<L: Level, Ctx, F, A> IsolatedAt<L> for LoopFn<Ctx, F, A>
+where
+    Self: Transaction,
+    A: IntoTransaction<Ctx>,
+    A::Tx: IsolatedAt<L>,
+{
+}
@@ -0,0 +1,194 @@
+use std::fmt;
+
+/// The isolation level of SQL transactions, ordered from the weakest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IsolationLevel {
+    /// `READ UNCOMMITTED`
+    ReadUncommitted,
+    /// `READ COMMITTED`
+    ReadCommitted,
+    /// `REPEATABLE READ`
+    RepeatableRead,
+    /// `SERIALIZABLE`
+    Serializable,
+}
+
+impl IsolationLevel {
+    /// The SQL of the level
+    pub fn as_sql(&self) -> &'static str {
+        match *self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+impl fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_sql())
+    }
+}
+
+/// The isolation levels as types, for the requirements checked at compile
+/// time, see `IsolatedAt`
+pub mod level {
+    use super::IsolationLevel;
+
+    /// An isolation level as a type
+    pub trait Level {
+        /// The isolation level
+        const LEVEL: IsolationLevel;
+    }
+
+    /// The levels at least as strong as `L`
+    pub trait AtLeast<L: Level>: Level {}
+
+    /// `READ UNCOMMITTED`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct ReadUncommitted;
+
+    /// `READ COMMITTED`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct ReadCommitted;
+
+    /// `REPEATABLE READ`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct RepeatableRead;
+
+    /// `SERIALIZABLE`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Serializable;
+
+    impl Level for ReadUncommitted {
+        const LEVEL: IsolationLevel = IsolationLevel::ReadUncommitted;
+    }
+
+    impl Level for ReadCommitted {
+        const LEVEL: IsolationLevel = IsolationLevel::ReadCommitted;
+    }
+
+    impl Level for RepeatableRead {
+        const LEVEL: IsolationLevel = IsolationLevel::RepeatableRead;
+    }
+
+    impl Level for Serializable {
+        const LEVEL: IsolationLevel = IsolationLevel::Serializable;
+    }
+
+    impl AtLeast<ReadUncommitted> for ReadUncommitted {}
+    impl AtLeast<ReadUncommitted> for ReadCommitted {}
+    impl AtLeast<ReadUncommitted> for RepeatableRead {}
+    impl AtLeast<ReadUncommitted> for Serializable {}
+    impl AtLeast<ReadCommitted> for ReadCommitted {}
+    impl AtLeast<ReadCommitted> for RepeatableRead {}
+    impl AtLeast<ReadCommitted> for Serializable {}
+    impl AtLeast<RepeatableRead> for RepeatableRead {}
+    impl AtLeast<RepeatableRead> for Serializable {}
+    impl AtLeast<Serializable> for Serializable {}
+}
+
+/// The characteristics of SQL transactions issued by the runners when
+/// beginning them. Unset characteristics are left to the database defaults.
+///
+/// # Examples
+///
+/// ```
+/// use transaction::{IsolationLevel, TransactionMode};
+///
+/// let mode = TransactionMode::new()
+///     .isolation(IsolationLevel::Serializable)
+///     .read_only()
+///     .deferrable();
+/// assert_eq!(
+///     mode.characteristics().as_deref(),
+///     Some("ISOLATION LEVEL SERIALIZABLE, READ ONLY, DEFERRABLE")
+/// );
+/// assert_eq!(TransactionMode::new().characteristics(), None);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TransactionMode {
+    isolation: Option<IsolationLevel>,
+    read_only: Option<bool>,
+    deferrable: Option<bool>,
+}
+
+impl TransactionMode {
+    /// The database defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the isolation level
+    pub fn isolation(self, level: IsolationLevel) -> Self {
+        TransactionMode {
+            isolation: Some(level),
+            ..self
+        }
+    }
+
+    /// Make the transactions read-only
+    pub fn read_only(self) -> Self {
+        TransactionMode {
+            read_only: Some(true),
+            ..self
+        }
+    }
+
+    /// Make the transactions read-write
+    pub fn read_write(self) -> Self {
+        TransactionMode {
+            read_only: Some(false),
+            ..self
+        }
+    }
+
+    /// Make the transactions deferrable. Only PostgreSQL supports it, and
+    /// only serializable read-only transactions are affected.
+    pub fn deferrable(self) -> Self {
+        TransactionMode {
+            deferrable: Some(true),
+            ..self
+        }
+    }
+
+    /// The isolation level, if set
+    pub fn isolation_level(&self) -> Option<IsolationLevel> {
+        self.isolation
+    }
+
+    /// Whether the transactions are read-only, if set
+    pub fn is_read_only(&self) -> Option<bool> {
+        self.read_only
+    }
+
+    /// Whether the transactions are deferrable, if set
+    pub fn is_deferrable(&self) -> Option<bool> {
+        self.deferrable
+    }
+
+    /// The characteristics in the syntax of `SET TRANSACTION` and `BEGIN`,
+    /// or `None` if nothing is set
+    pub fn characteristics(&self) -> Option<String> {
+        let mut modes = Vec::new();
+        if let Some(level) = self.isolation {
+            modes.push(format!("ISOLATION LEVEL {}", level));
+        }
+        match self.read_only {
+            Some(true) => modes.push("READ ONLY".to_string()),
+            Some(false) => modes.push("READ WRITE".to_string()),
+            None => (),
+        }
+        match self.deferrable {
+            Some(true) => modes.push("DEFERRABLE".to_string()),
+            Some(false) => modes.push("NOT DEFERRABLE".to_string()),
+            None => (),
+        }
+        if modes.is_empty() {
+            None
+        } else {
+            Some(modes.join(", "))
+        }
+    }
+}
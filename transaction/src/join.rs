@@ -1,4 +1,5 @@
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn join<Ctx, A: IntoTransaction<Ctx>, B: IntoTransaction<Ctx, Err = A::Err>>(
     a: A,
@@ -37,3 +38,16 @@ where
         }
     }
 }
+
+impl<Tx1, Tx2> Visit for Join<Tx1, Tx2>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("join"), |v| {
+            self.tx1.accept(v);
+            self.tx2.accept(v);
+        });
+    }
+}
@@ -1,4 +1,5 @@
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn join3<
     Ctx,
@@ -55,3 +56,18 @@ where
         }
     }
 }
+
+impl<Tx1, Tx2, Tx3> Visit for Join3<Tx1, Tx2, Tx3>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+    Tx3: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("join3"), |v| {
+            self.tx1.accept(v);
+            self.tx2.accept(v);
+            self.tx3.accept(v);
+        });
+    }
+}
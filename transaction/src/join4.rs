@@ -1,4 +1,5 @@
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn join4<
     Ctx,
@@ -67,3 +68,20 @@ where
         }
     }
 }
+
+impl<Tx1, Tx2, Tx3, Tx4> Visit for Join4<Tx1, Tx2, Tx3, Tx4>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+    Tx3: Visit,
+    Tx4: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("join4"), |v| {
+            self.tx1.accept(v);
+            self.tx2.accept(v);
+            self.tx3.accept(v);
+            self.tx4.accept(v);
+        });
+    }
+}
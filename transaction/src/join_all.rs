@@ -1,6 +1,8 @@
-use {IntoTransaction, Transaction};
+use crate::{progress, IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
-/// join a vec of transaction
+/// join a vec of transaction. The number of the transactions done is
+/// reported to `progress` as the step `join_all`.
 pub fn join_all<Ctx, I, B>(i: I) -> JoinAll<B::Tx>
 where
     I: IntoIterator<Item = B>,
@@ -32,7 +34,23 @@ where
         let vec = &self.vec;
 
         vec.iter()
-            .map(|tx| tx.run(ctx))
+            .enumerate()
+            .map(|(i, tx)| {
+                let item = tx.run(ctx)?;
+                progress::report("join_all", i + 1, Some(vec.len()));
+                Ok(item)
+            })
             .collect::<Result<Vec<_>, _>>()
     }
 }
+
+impl<Tx> Visit for JoinAll<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("join_all"), |v| for tx in &self.vec {
+            tx.accept(v);
+        });
+    }
+}
@@ -0,0 +1,409 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Mutations of contexts of type `C` without a rollback of their own, e.g.
+/// in-memory state, made undoable by journaling them in a `Journaled`
+pub trait Mutation<C>: Sized {
+    /// The mutation undoing this one on the context as it is before this
+    /// one, e.g. setting the old value back. It may be applied even if this
+    /// one was not, after a crash, so it is to be harmless then.
+    fn inverse(&self, ctx: &C) -> Self;
+
+    /// Apply the mutation to the context
+    fn apply(&self, ctx: &mut C);
+}
+
+/// A mutation journaled before it is applied, with its inverse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry<M> {
+    /// The mutation
+    pub redo: M,
+    /// The mutation undoing it
+    pub undo: M,
+}
+
+/// Durable journals of the mutations of a `Journaled` context
+pub trait Journal<M> {
+    /// The error of the journal
+    type Err;
+
+    /// Append the entry, durably once this returns
+    fn append(&mut self, entry: &JournalEntry<M>) -> Result<(), Self::Err>;
+
+    /// The entries appended since the journal was last cleared
+    fn entries(&mut self) -> Result<Vec<JournalEntry<M>>, Self::Err>;
+
+    /// Forget the entries, once they are committed or rolled back
+    fn clear(&mut self) -> Result<(), Self::Err>;
+}
+
+/// A context of type `C` whose mutations are written to a journal before
+/// they are applied, giving it the rollback it lacks: the transactions run
+/// by `write_ahead` are undone from the entries if they fail, and those cut
+/// short by a crash are undone when the context is opened again.
+///
+/// The context is read by `inner` and mutated by `apply` only, so that
+/// every mutation is journaled.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::collections::BTreeMap;
+/// use std::io;
+/// use transaction::prelude::*;
+/// use transaction::{Journaled, MemoryJournal, Mutation};
+///
+/// type Accounts = BTreeMap<&'static str, i64>;
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// struct SetBalance(&'static str, Option<i64>);
+///
+/// impl Mutation<Accounts> for SetBalance {
+///     fn inverse(&self, accounts: &Accounts) -> Self {
+///         SetBalance(self.0, accounts.get(self.0).cloned())
+///     }
+///
+///     fn apply(&self, accounts: &mut Accounts) {
+///         match self.1 {
+///             Some(balance) => accounts.insert(self.0, balance),
+///             None => accounts.remove(self.0),
+///         };
+///     }
+/// }
+///
+/// type Ctx = Journaled<Accounts, MemoryJournal<SetBalance>, SetBalance>;
+///
+/// fn transfer(amount: i64) -> impl Transaction<Ctx = Ctx, Item = (), Err = io::Error> {
+///     with_ctx(move |ctx: &mut Ctx| {
+///         let alice = ctx.inner()["alice"];
+///         let bob = ctx.inner()["bob"];
+///         ctx.apply(SetBalance("bob", Some(bob + amount)))?;
+///         if alice < amount {
+///             return Err(io::Error::new(io::ErrorKind::Other, "insufficient funds"));
+///         }
+///         ctx.apply(SetBalance("alice", Some(alice - amount)))?;
+///         Ok(())
+///     })
+///     .write_ahead()
+/// }
+///
+/// # fn main() {
+/// let journal = MemoryJournal::new();
+/// let accounts = vec![("alice", 100), ("bob", 0)].into_iter().collect();
+/// let mut ctx = Journaled::open(accounts, journal.clone()).unwrap();
+///
+/// transfer(30).run(&mut ctx).unwrap();
+/// assert_eq!(transfer(300).run(&mut ctx).unwrap_err().to_string(), "insufficient funds");
+/// assert_eq!(ctx.inner()["bob"], 30);
+/// assert!(journal.is_empty());
+///
+/// // a crash before the end of a run leaves its entries in the journal,
+/// // which are undone on opening
+/// ctx.apply(SetBalance("bob", None)).unwrap();
+/// let (accounts, journal) = ctx.into_parts();
+/// assert_eq!(journal.len(), 1);
+/// let ctx = Journaled::open(accounts, journal).unwrap();
+/// assert_eq!(ctx.inner()["bob"], 30);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Journaled<C, J, M> {
+    inner: C,
+    journal: J,
+    // the inverses of the mutations of the runs in progress
+    undo: Vec<M>,
+    // the number of nested `write_ahead`s running
+    depth: usize,
+}
+
+impl<C, J, M> Journaled<C, J, M>
+where
+    J: Journal<M>,
+    M: Mutation<C>,
+{
+    /// Journal the mutations of the context, undoing first the entries left
+    /// in the journal by a run cut short
+    pub fn open(inner: C, journal: J) -> Result<Self, J::Err> {
+        let mut ctx = Journaled {
+            inner,
+            journal,
+            undo: Vec::new(),
+            depth: 0,
+        };
+        ctx.undo = ctx.journal.entries()?.into_iter().map(|entry| entry.undo).collect();
+        ctx.undo_to(0);
+        ctx.journal.clear()?;
+        Ok(ctx)
+    }
+
+    /// The context
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// The journal
+    pub fn journal(&self) -> &J {
+        &self.journal
+    }
+
+    /// Unwrap the context and the journal, leaving the entries of the runs
+    /// in progress in the journal
+    pub fn into_parts(self) -> (C, J) {
+        (self.inner, self.journal)
+    }
+
+    /// Journal the mutation, and apply it once it is journaled
+    pub fn apply(&mut self, mutation: M) -> Result<(), J::Err> {
+        let entry = JournalEntry {
+            undo: mutation.inverse(&self.inner),
+            redo: mutation,
+        };
+        self.journal.append(&entry)?;
+        entry.redo.apply(&mut self.inner);
+        self.undo.push(entry.undo);
+        Ok(())
+    }
+
+    // undo the mutations after the first `mark` ones, latest first
+    fn undo_to(&mut self, mark: usize) {
+        while self.undo.len() > mark {
+            if let Some(undo) = self.undo.pop() {
+                undo.apply(&mut self.inner);
+            }
+        }
+    }
+}
+
+/// Run the transaction on a `Journaled` context, undoing its mutations if
+/// it fails. The journal is cleared once the outermost one finishes, and
+/// nested ones undo only their own mutations, like `atomic`. See
+/// `Journaled`.
+pub fn write_ahead<C, J, M, A>(a: A) -> WriteAhead<A::Tx>
+where
+    A: IntoTransaction<Journaled<C, J, M>>,
+{
+    WriteAhead { tx: a.into_transaction() }
+}
+
+/// The result of `write_ahead`
+#[derive(Debug)]
+#[must_use]
+pub struct WriteAhead<Tx> {
+    tx: Tx,
+}
+
+impl<Tx, C, J, M> Transaction for WriteAhead<Tx>
+where
+    Tx: Transaction<Ctx = Journaled<C, J, M>>,
+    J: Journal<M>,
+    M: Mutation<C>,
+    Tx::Err: From<J::Err>,
+{
+    type Ctx = Journaled<C, J, M>;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let mark = ctx.undo.len();
+        ctx.depth += 1;
+        let ret = self.tx.run(ctx);
+        ctx.depth -= 1;
+        if ret.is_err() {
+            ctx.undo_to(mark);
+        }
+        if ctx.depth == 0 {
+            ctx.undo.clear();
+            ctx.journal.clear()?;
+        }
+        ret
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for WriteAhead<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("write_ahead"), |v| self.tx.accept(v));
+    }
+}
+
+/// A `Journal` in memory, which is not durable, for tests. Cloning it
+/// shares the entries, so a clone outlives a context dropped as if crashed.
+#[derive(Debug)]
+pub struct MemoryJournal<M> {
+    entries: Arc<Mutex<Vec<JournalEntry<M>>>>,
+}
+
+impl<M> Clone for MemoryJournal<M> {
+    fn clone(&self) -> Self {
+        MemoryJournal {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<M> Default for MemoryJournal<M> {
+    fn default() -> Self {
+        MemoryJournal {
+            entries: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<M> MemoryJournal<M> {
+    /// Make an empty journal
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether there is no entry
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<JournalEntry<M>>> {
+        // the entries are always left consistent, so poisoning can be ignored
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<M> Journal<M> for MemoryJournal<M>
+where
+    M: Clone,
+{
+    type Err = io::Error;
+
+    fn append(&mut self, entry: &JournalEntry<M>) -> Result<(), Self::Err> {
+        self.lock().push(entry.clone());
+        Ok(())
+    }
+
+    fn entries(&mut self) -> Result<Vec<JournalEntry<M>>, Self::Err> {
+        Ok(self.lock().clone())
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Err> {
+        self.lock().clear();
+        Ok(())
+    }
+}
+
+/// A `Journal` in a file, which is synced on every entry. The mutation and
+/// its inverse are written on a line each by `Display` and read back by
+/// `FromStr`, so they are not to contain newlines. An entry cut short by a
+/// crash is ignored, since its mutation was not applied.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::{FileJournal, Journal, JournalEntry};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join(format!("journal-doctest-{}", std::process::id()));
+/// let mut journal = FileJournal::<i64>::new(&path);
+/// journal.append(&JournalEntry { redo: 1, undo: -1 })?;
+///
+/// let mut reopened = FileJournal::<i64>::new(&path);
+/// assert_eq!(reopened.entries()?, vec![JournalEntry { redo: 1, undo: -1 }]);
+/// reopened.clear()?;
+/// assert_eq!(journal.entries()?, vec![]);
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+pub struct FileJournal<M> {
+    path: PathBuf,
+    file: Option<File>,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<M> fmt::Debug for FileJournal<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FileJournal").field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+impl<M> FileJournal<M> {
+    /// Journal in the file at the path, which is created on the first entry
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        FileJournal {
+            path: path.as_ref().to_path_buf(),
+            file: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<M> Journal<M> for FileJournal<M>
+where
+    M: fmt::Display + FromStr,
+{
+    type Err = io::Error;
+
+    fn append(&mut self, entry: &JournalEntry<M>) -> Result<(), Self::Err> {
+        if self.file.is_none() {
+            self.file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        }
+        let file = self.file.as_mut().expect("the file is open");
+        file.write_all(format!("{}\n{}\n", entry.redo, entry.undo).as_bytes())?;
+        file.sync_data()
+    }
+
+    fn entries(&mut self) -> Result<Vec<JournalEntry<M>>, Self::Err> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        // only the lines ended by a newline are complete
+        let lines = text.split_terminator('\n').take(text.matches('\n').count()).collect::<Vec<_>>();
+        lines
+            .chunks_exact(2)
+            .map(|entry| {
+                let parse = |line: &str| {
+                    line.parse()
+                        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("bad journal entry: {}", line)))
+                };
+                Ok(JournalEntry {
+                    redo: parse(entry[0])?,
+                    undo: parse(entry[1])?,
+                })
+            })
+            .collect()
+    }
+
+    fn clear(&mut self) -> Result<(), Self::Err> {
+        self.file = None;
+        let file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        file.sync_all()
+    }
+}
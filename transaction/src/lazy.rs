@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use crate::Transaction;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
 
 /// lazy evaluated transaction value.
 /// Note that inner function can be called many times.
@@ -33,3 +34,9 @@ where
         (self.f)()
     }
 }
+
+impl<Ctx, F> Visit for Lazy<Ctx, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("lazy"));
+    }
+}
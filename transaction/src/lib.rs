@@ -13,6 +13,11 @@
 //! of) `if` and so on. As all the combinators have its own result type, no
 //! dispatches are done at execution time thus it is zero-cost.
 //!
+//! Each combinator is also a free function taking the transaction first,
+//! e.g. `and_then(tx, f)` for `tx.and_then(f)`, to be passed as a function
+//! value or to be called without the method resolution, e.g. on the trait
+//! objects.
+//!
 //! Another feature is it does DI of transaction. For database transaction, it
 //! means it injects DB connection from the context.
 //!
@@ -81,25 +86,65 @@
 
 #[cfg(feature = "mdo")]
 pub mod mdo;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "metrics")]
+extern crate metrics as metrics_crate;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "arena")]
+extern crate bumpalo;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "r2d2")]
+extern crate r2d2;
+#[cfg(feature = "deadpool")]
+extern crate deadpool;
+#[cfg(feature = "bb8")]
+extern crate bb8;
+pub mod bulkhead;
+pub mod clock;
+pub mod hooks;
+pub mod metrics;
+pub mod outbox;
+pub mod pool;
+pub mod progress;
+pub mod unit_of_work;
+pub mod runner;
+#[cfg(feature = "async")]
+pub mod async_tx;
+#[doc(hidden)]
+pub mod __private;
 
 pub mod prelude {
-    pub use super::Transaction;
-    pub use err::err;
-    pub use join_all::join_all;
-    pub use lazy::lazy;
-    pub use loop_fn::loop_fn;
-    pub use ok::ok;
-    pub use repeat::repeat;
-    pub use result::result;
-    pub use retry::retry;
-    pub use with_ctx::with_ctx;
+    pub use super::{Transaction, TransactionExt, Visit};
+    pub use crate::err::err;
+    pub use crate::join_all::join_all;
+    pub use crate::lazy::lazy;
+    pub use crate::loop_fn::loop_fn;
+    pub use crate::ok::ok;
+    pub use crate::repeat::repeat;
+    pub use crate::result::result;
+    pub use crate::retry::retry;
+    pub use crate::tx_try::ErrInto;
+    pub use crate::with_ctx::with_ctx;
 }
 
 mod then;
+mod platform;
+#[cfg(feature = "arena")]
+mod arena;
+mod batch;
+mod cache;
+mod boxed;
+mod small_box;
 mod map;
 mod and_then;
 mod map_err;
 mod or_else;
+mod overlay;
 mod abort;
 mod try_abort;
 mod recover;
@@ -115,35 +160,128 @@ mod repeat;
 mod retry;
 mod result;
 mod ok;
+#[cfg(feature = "rayon")]
+mod par_join;
 mod err;
 mod lazy;
 mod join_all;
+mod journal;
 mod with_ctx;
+mod named;
+mod visit;
+mod describe;
+mod dry_run;
+mod coverage;
+mod profile;
+mod audit;
+mod zoom;
+mod product;
+mod read_only;
+mod hlist;
+mod tx_try;
+mod compose;
+mod capability;
+mod deadline;
+mod chain;
+mod registry;
+mod either_ctx;
+mod adapt_ctx;
+mod scope;
+mod thread_state;
+mod scoped;
+mod local;
+mod env;
+mod with_log;
+mod state;
+mod tenant;
+mod retry_policy;
+mod retry_with;
+mod isolation;
+mod isolated;
+mod undo;
+mod idempotent;
+mod lock;
+mod cas;
+mod row_lock;
+mod fault;
+mod tx_hash_map;
+mod tx_vec;
+#[cfg(feature = "tracing")]
+mod instrument;
 
 pub use abort::*;
+pub use adapt_ctx::*;
 pub use and_then::*;
+#[cfg(feature = "arena")]
+pub use arena::*;
+pub use audit::*;
+pub use batch::*;
+pub use cache::*;
+pub use boxed::*;
 pub use branch::*;
 pub use branch3::*;
 pub use branch4::*;
+pub use capability::*;
+pub use deadline::*;
+pub use chain::*;
+pub use cas::*;
+pub use coverage::*;
+pub use describe::*;
+pub use dry_run::*;
+pub use either_ctx::*;
+pub use env::*;
 pub use err::*;
+pub use fault::*;
+pub use hlist::*;
+pub use idempotent::*;
+#[cfg(feature = "tracing")]
+pub use instrument::*;
+pub use isolation::*;
+pub use isolated::*;
 pub use join::*;
 pub use join3::*;
 pub use join4::*;
 pub use join_all::*;
+pub use journal::*;
+pub use lock::*;
 pub use lazy::*;
+pub use local::*;
 pub use loop_fn::*;
 pub use map::*;
 pub use map_err::*;
+pub use named::*;
 pub use ok::*;
+#[cfg(feature = "rayon")]
+pub use par_join::*;
 pub use or_else::*;
+pub use overlay::*;
+pub use product::*;
+pub use read_only::*;
+pub use profile::*;
 pub use recover::*;
+pub use registry::*;
 pub use repeat::*;
 pub use result::*;
 pub use retry::*;
+pub use retry_policy::*;
+pub use retry_with::*;
+pub use row_lock::*;
+pub use scope::*;
+pub use scoped::*;
+pub use small_box::*;
+pub use state::*;
+pub use tenant::*;
 pub use then::*;
 pub use try_abort::*;
 pub use try_recover::*;
+pub use tx_hash_map::*;
+pub use tx_try::*;
+pub use tx_vec::*;
+pub use undo::*;
+pub use visit::*;
 pub use with_ctx::*;
+pub use with_log::*;
+pub use zoom::*;
 
 /// An abstract transaction. Transactions sharing the same `Ctx` can be
 /// composed with combinators. When the transaction return an error, it means
@@ -151,6 +289,9 @@ pub use with_ctx::*;
 /// other may retry the computation. Thus all the computation should be
 /// idempotent (of cause, except operations using context). Note that this
 /// transaction is not executed until it is `run`.
+///
+/// The combinators are the methods of `TransactionExt`, which is in the
+/// prelude together with this trait.
 #[must_use]
 pub trait Transaction {
     /// The contxt type (i.e. transaction type) of the transaction
@@ -164,12 +305,50 @@ pub trait Transaction {
     /// user by hand.
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err>;
 
+    /// The label of the transaction given by `named`, if any.
+    fn label(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// The combinators of the transactions, implemented for all of them and
+/// re-exported from the prelude. `Transaction` itself is kept to what the
+/// runners and the implementors need, so new combinators are added here
+/// without touching the implementations.
+pub trait TransactionExt: Transaction {
     /// Box the transaction
     fn boxed<'a>(self) -> Box<Transaction<Ctx = Self::Ctx, Item = Self::Item, Err = Self::Err> + 'a>
     where
         Self: Sized + 'a,
     {
-        Box::new(self)
+        boxed(self)
+    }
+
+    /// Box the transaction without allocating if it fits in `N` words. See
+    /// `small_boxed`.
+    fn small_boxed<'a, const N: usize>(self) -> SmallBoxTx<'a, Self::Ctx, Self::Item, Self::Err, N>
+    where
+        Self: Sized + 'a,
+    {
+        small_boxed(self)
+    }
+
+    /// Box the transaction in the arena. See `Arena`.
+    #[cfg(feature = "arena")]
+    fn boxed_in<'a>(self, arena: &'a Arena) -> ArenaTx<'a, Self::Ctx, Self::Item, Self::Err>
+    where
+        Self: Sized + 'a,
+    {
+        boxed_in(self, arena)
+    }
+
+    /// Start a chain of steps run in a loop rather than by nesting. See
+    /// `chain`.
+    fn chain<'a>(self) -> Chain<'a, Self::Ctx, Self::Item, Self::Err>
+    where
+        Self: Sized + Visit + 'a,
+    {
+        chain(self)
     }
 
     /// Take the previous result of computation and do another computation
@@ -182,6 +361,312 @@ pub trait Transaction {
         then(self, f)
     }
 
+    /// Attach a label to the transaction
+    fn named<L>(self, label: L) -> Named<Self>
+    where
+        L: Into<::std::borrow::Cow<'static, str>>,
+        Self: Sized,
+    {
+        named(self, label)
+    }
+
+    /// Record the run counts and durations of the labeled sub-transactions
+    fn profiled(self, profiler: &Profiler) -> Profiled<Self>
+    where
+        Self: Sized,
+    {
+        profiled(self, profiler)
+    }
+
+    /// Record the paths taken by the labeled sub-transactions and the
+    /// `or_else`s
+    fn covered(self, coverage: &Coverage) -> Covered<Self>
+    where
+        Self: Sized,
+    {
+        covered(self, coverage)
+    }
+
+    /// Enter the `tracing` span for the duration of the run
+    #[cfg(feature = "tracing")]
+    fn instrument(self, span: tracing::Span) -> Instrumented<Self>
+    where
+        Self: Sized,
+    {
+        instrument(self, span)
+    }
+
+    /// Use the transaction as an asynchronous one run inline
+    #[cfg(feature = "async")]
+    fn into_async(self) -> async_tx::FromSync<Self>
+    where
+        Self: Sized,
+    {
+        async_tx::from_sync(self)
+    }
+
+    /// Record an audit entry to the sink after each run
+    fn audited<S, R>(self, sink: S, redactor: R) -> Audited<Self, S, R>
+    where
+        S: AuditSink,
+        R: Redactor<Self::Item, Self::Err>,
+        Self: Sized,
+    {
+        audited(self, sink, redactor)
+    }
+
+    /// Run the transaction on the part of a larger context focused by `f`
+    fn zoom<Big, F>(self, f: F) -> Zoom<Big, Self, F>
+    where
+        F: Fn(&mut Big) -> &mut Self::Ctx,
+        Self: Sized,
+    {
+        zoom(self, f)
+    }
+
+    /// Run the transaction in any context borrowable as its context via `AsMut`
+    fn adapt_ctx<Big>(self) -> AdaptCtx<Big, Self>
+    where
+        Big: AsMut<Self::Ctx>,
+        Self: Sized,
+    {
+        adapt_ctx(self)
+    }
+
+    /// Run the transaction on the left context of a pair of contexts
+    fn in_left<R>(self) -> InLeft<Self, R>
+    where
+        Self: Sized,
+    {
+        in_left(self)
+    }
+
+    /// Run the transaction on the right context of a pair of contexts
+    fn in_right<L>(self) -> InRight<L, Self>
+    where
+        Self: Sized,
+    {
+        in_right(self)
+    }
+
+    /// Run the transaction on the context of its type in a context list
+    fn lift<L, I>(self) -> Lift<L, I, Self>
+    where
+        L: Contains<Self::Ctx, I>,
+        Self: Sized,
+    {
+        lift(self)
+    }
+
+    /// Run the transaction in a nested scope backed by a savepoint
+    fn scoped<C, D>(self) -> Scoped<Self>
+    where
+        Self: Transaction<Ctx = ScopedCtx<C, InScope<D>>> + Sized,
+    {
+        scoped(self)
+    }
+
+    /// Undo the mutations of the context recording an undo log if the
+    /// transaction fails
+    fn atomic(self) -> Atomic<Self>
+    where
+        Self::Ctx: Undo,
+        Self: Sized,
+    {
+        atomic(self)
+    }
+
+    /// Undo the mutations journaled by the transaction if it fails. See
+    /// `Journaled`.
+    fn write_ahead<C, J, M>(self) -> WriteAhead<Self>
+    where
+        Self: Transaction<Ctx = Journaled<C, J, M>> + Sized,
+    {
+        write_ahead(self)
+    }
+
+    /// Load the keys of the `batched_get`s in the transaction together. See
+    /// `batched_get`.
+    fn batched<K, V>(self) -> Batched<Self, K, V>
+    where
+        Self::Ctx: Batcher<K, V>,
+        Self: Sized,
+    {
+        batched(self)
+    }
+
+    /// Answer the transaction from the cache, filling it once the
+    /// transaction commits. See `cached`.
+    fn cached<C, F, K>(self, cache: &C, key: F, ttl: ::std::time::Duration) -> Cached<Self, C, F>
+    where
+        C: Cache<K, Self::Item> + Clone,
+        F: Fn(&Self::Ctx) -> K,
+        Self: Sized,
+    {
+        cached(self, cache, key, ttl)
+    }
+
+    /// Forget the value cached for the key after the transaction. See
+    /// `invalidates`.
+    fn invalidates<C, F, K, V>(self, cache: &C, key: F) -> Invalidates<Self, C, F, V>
+    where
+        C: Cache<K, V> + Clone,
+        F: Fn(&Self::Ctx) -> K,
+        Self: Sized,
+    {
+        invalidates(self, cache, key)
+    }
+
+    /// Start the `overlay_get`s of the transaction from an empty overlay.
+    /// See `overlay_get`.
+    fn read_your_writes<K, V>(self) -> ReadYourWrites<Self, K, V>
+    where
+        Self::Ctx: OverlayStore<K, V>,
+        Self: Sized,
+    {
+        read_your_writes(self)
+    }
+
+    /// Declare that the transaction requires the isolation level `L` at
+    /// least. See `requires_isolation`.
+    fn requires_isolation<L>(self) -> RequiresIsolation<L, Self>
+    where
+        L: level::Level,
+        Self: Sized,
+    {
+        requires_isolation::<L, Self::Ctx, Self>(self)
+    }
+
+    /// Skip the transaction in preview mode, recording the effect instead.
+    /// See `write_effect`.
+    fn write_effect<S>(self, effect: S) -> WriteEffect<Self>
+    where
+        S: Into<String>,
+        Self::Ctx: DryRun,
+        Self: Sized,
+    {
+        write_effect(effect, self)
+    }
+
+    /// Insert the event into the outbox of the context after the transaction
+    /// succeeds, so that it is published if and only if the transaction
+    /// commits. See `outbox`.
+    fn publish(self, event: outbox::Event) -> outbox::Publish<Self>
+    where
+        Self::Ctx: outbox::Outbox,
+        Self::Err: From<<Self::Ctx as outbox::Outbox>::Error>,
+        Self: Sized,
+    {
+        outbox::publish(self, event)
+    }
+
+    /// Run the transaction once per idempotency key, returning the item
+    /// recorded for the key by an earlier run
+    fn idempotent(self, key: impl Into<String>) -> Idempotent<Self>
+    where
+        Self::Ctx: IdempotencyStore<Self::Item>,
+        Self::Err: From<<Self::Ctx as IdempotencyStore<Self::Item>>::Error>,
+        Self: Sized,
+    {
+        idempotent(key, self)
+    }
+
+    /// Write by the closure if the versioned value read by the transaction
+    /// is still at the expected version, or fail with `VersionMismatch`
+    fn cas<T, V, F, B>(self, expected: V, write: F) -> Cas<Self, V, F, B>
+    where
+        Self: Transaction<Item = Versioned<T, V>> + Sized,
+        Self::Err: From<VersionMismatch<V>>,
+        V: PartialEq + Clone,
+        F: Fn(Versioned<T, V>) -> B,
+        B: IntoTransaction<Self::Ctx, Err = Self::Err>,
+    {
+        cas(self, expected, write)
+    }
+
+    /// Write the versioned value read by the transaction by the closure,
+    /// reading and writing again with the delays of the policy while it fails
+    /// with a retryable error, e.g. `VersionMismatch`
+    fn optimistic<T, V, F, B, P>(self, write: F, policy: P) -> Optimistic<Self, F, B, P>
+    where
+        Self: Transaction<Item = Versioned<T, V>> + Sized,
+        Self::Err: Retryable,
+        F: Fn(Versioned<T, V>) -> B,
+        B: IntoTransaction<Self::Ctx, Err = Self::Err>,
+        P: RetryPolicy,
+    {
+        optimistic(self, write, policy)
+    }
+
+    /// Modify the context for the run of the transaction and restore it after
+    fn local<F, G, S>(self, modify: F, restore: G) -> Local<Self, F, G>
+    where
+        F: Fn(&mut Self::Ctx) -> S,
+        G: Fn(&mut Self::Ctx, S),
+        Self: Sized,
+    {
+        local(self, modify, restore)
+    }
+
+    /// Make the read-only environment available to the `with_env` leaves
+    fn provide<Env>(self, env: Env) -> Provide<Env, Self>
+    where
+        Env: Send + Sync + 'static,
+        Self: Sized,
+    {
+        provide(env, self)
+    }
+
+    /// Run the transaction on the context of a tenant, scoped to it first.
+    /// See `in_tenant`.
+    fn in_tenant(self) -> InTenant<Self>
+    where
+        Self::Ctx: TenantScope,
+        Self: Sized,
+    {
+        in_tenant(self)
+    }
+
+    /// Run the transaction in the tenant, scoping the context to it first.
+    /// See `scoped_to_tenant`.
+    fn scoped_to_tenant<S>(self, tenant: S) -> ScopedToTenant<Self>
+    where
+        S: Into<String>,
+        Self::Ctx: TenantScope,
+        Self: Sized,
+    {
+        scoped_to_tenant(tenant, self)
+    }
+
+    /// Fail the transaction unless it runs in the tenant. See `for_tenant`.
+    fn for_tenant<S>(self, tenant: S) -> ForTenant<Self>
+    where
+        S: Into<String>,
+        Self: Sized,
+    {
+        for_tenant(tenant, self)
+    }
+
+    /// Run the transaction again with the delays of the policy while it fails
+    fn retry_with<P>(self, policy: P) -> RetryWith<Self, P>
+    where
+        P: RetryPolicy,
+        Self: Sized,
+    {
+        retry_with(self, policy)
+    }
+
+    /// Inject the faults the policy decides by the label of the transaction
+    /// into its runs, e.g. to exercise the rollback paths in tests
+    fn inject_fault<P>(self, policy: P) -> InjectFault<Self, P>
+    where
+        P: FaultPolicy,
+        Self::Err: From<InjectedFault>,
+        Self: Sized,
+    {
+        inject_fault(self, policy)
+    }
+
     /// Transform the previous successful value
     fn map<F, B>(self, f: F) -> Map<Self, F>
     where
@@ -245,9 +730,9 @@ pub trait Transaction {
     }
 
     /// Recover from an error
-    fn recover<T, F>(self, f: F) -> Recover<Self, T, F>
+    fn recover<F>(self, f: F) -> Recover<Self, Self::Item, F>
     where
-        F: Fn(Self::Item) -> Self::Err,
+        F: Fn(Self::Err) -> Self::Item,
         Self: Sized,
     {
         recover(self, f)
@@ -256,7 +741,7 @@ pub trait Transaction {
     /// Try to recover from an error
     fn try_recover<F, B>(self, f: F) -> TryRecover<Self, F, B>
     where
-        F: Fn(Self::Item) -> Result<B, Self::Err>,
+        F: Fn(Self::Err) -> Result<Self::Item, B>,
         Self: Sized,
     {
         try_recover(self, f)
@@ -271,6 +756,16 @@ pub trait Transaction {
         join(self, b)
     }
 
+    /// join 2 independent transactions run in parallel, see `par_join`
+    #[cfg(feature = "rayon")]
+    fn par_join<B>(self, b: B) -> ParJoin<Self, B::Tx>
+    where
+        B: IntoTransaction<Self::Ctx, Err = Self::Err>,
+        Self: Sized,
+    {
+        par_join(self, b)
+    }
+
     /// join 3 indepndant transactions
     fn join3<B, C>(self, b: B, c: C) -> Join3<Self, B::Tx, C::Tx>
     where
@@ -281,7 +776,7 @@ pub trait Transaction {
         join3(self, b, c)
     }
 
-    /// join 4 indepndant transactions
+    /// join 4 indepndant transactions. `join!` joins any number of them.
     fn join4<B, C, D>(self, b: B, c: C, d: D) -> Join4<Self, B::Tx, C::Tx, D::Tx>
     where
         B: IntoTransaction<Self::Ctx, Err = Self::Err>,
@@ -297,7 +792,7 @@ pub trait Transaction {
     where
         Self: Sized,
     {
-        BranchBuilder::new(self)
+        branch(self)
     }
 
     /// 3 branch builder
@@ -305,7 +800,7 @@ pub trait Transaction {
     where
         Self: Sized,
     {
-        Branch3Builder::new(self)
+        branch3(self)
     }
 
     /// 4 branch builder
@@ -313,10 +808,12 @@ pub trait Transaction {
     where
         Self: Sized,
     {
-        Branch4Builder::new(self)
+        branch4(self)
     }
 }
 
+impl<Tx> TransactionExt for Tx where Tx: Transaction + ?Sized {}
+
 /// types than can be converted into transaction
 pub trait IntoTransaction<Ctx> {
     type Tx: Transaction<Ctx = Ctx, Item = Self::Item, Err = Self::Err>;
@@ -440,6 +937,10 @@ where
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         (**self).run(ctx)
     }
+
+    fn label(&self) -> Option<&str> {
+        (**self).label()
+    }
 }
 
 impl<'a, T> Transaction for &'a T
@@ -452,4 +953,8 @@ where
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         (**self).run(ctx)
     }
+
+    fn label(&self) -> Option<&str> {
+        (**self).label()
+    }
 }
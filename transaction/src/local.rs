@@ -0,0 +1,84 @@
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Temporarily modify the context for the run of the transaction.
+/// `modify` is called before the run and its return value is passed to
+/// `restore`, which is called after the run whether the transaction
+/// succeeded or not.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// struct Conn {
+///     timeout_ms: u64,
+/// }
+///
+/// # fn main() {
+/// let step = with_ctx(|_: &mut Conn| -> Result<u64, ()> { Err(()) })
+///     .or_else(|()| with_ctx(|conn: &mut Conn| Ok(conn.timeout_ms)));
+/// // tighten the timeout only for this step
+/// let tx = step.local(
+///     |conn| ::std::mem::replace(&mut conn.timeout_ms, 100),
+///     |conn, saved| conn.timeout_ms = saved,
+/// );
+/// let mut conn = Conn { timeout_ms: 5000 };
+/// assert_eq!(tx.run(&mut conn), Ok(100));
+/// assert_eq!(conn.timeout_ms, 5000);
+/// # }
+/// ```
+pub fn local<Ctx, A, F, G, S>(a: A, modify: F, restore: G) -> Local<A::Tx, F, G>
+where
+    A: IntoTransaction<Ctx>,
+    F: Fn(&mut Ctx) -> S,
+    G: Fn(&mut Ctx, S),
+{
+    Local {
+        tx: a.into_transaction(),
+        modify,
+        restore,
+    }
+}
+
+/// The result of `local`
+#[derive(Debug)]
+#[must_use]
+pub struct Local<Tx, F, G> {
+    tx: Tx,
+    modify: F,
+    restore: G,
+}
+
+impl<Tx, F, G, S> Transaction for Local<Tx, F, G>
+where
+    Tx: Transaction,
+    F: Fn(&mut Tx::Ctx) -> S,
+    G: Fn(&mut Tx::Ctx, S),
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let saved = (self.modify)(ctx);
+        let ret = self.tx.run(ctx);
+        (self.restore)(ctx, saved);
+        ret
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, F, G> Visit for Local<Tx, F, G>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("local"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,247 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::retry_policy::Retryable;
+use crate::visit::{visit_node, Node, Visit, Visitor};
+use crate::{platform, IntoTransaction, Transaction};
+
+/// Managers of distributed locks with a time to live, e.g. Redlock on
+/// redis. A lock not extended expires after its TTL, so that a crashed
+/// holder doesn't keep it forever.
+pub trait LockManager {
+    /// The proof of holding a lock
+    type Guard;
+    /// The error of the manager
+    type Error;
+
+    /// Acquire the lock of the key for `ttl`, or return `None` if another
+    /// holder has it
+    fn acquire(&self, key: &str, ttl: Duration) -> Result<Option<Self::Guard>, Self::Error>;
+
+    /// Extend the lock to expire `ttl` from now, or return `false` if it has
+    /// already expired
+    fn extend(&self, guard: &Self::Guard, ttl: Duration) -> Result<bool, Self::Error>;
+
+    /// Release the lock, unless it has expired
+    fn release(&self, guard: Self::Guard) -> Result<(), Self::Error>;
+}
+
+impl<M> LockManager for &M
+where
+    M: ?Sized + LockManager,
+{
+    type Guard = M::Guard;
+    type Error = M::Error;
+
+    fn acquire(&self, key: &str, ttl: Duration) -> Result<Option<Self::Guard>, Self::Error> {
+        (**self).acquire(key, ttl)
+    }
+
+    fn extend(&self, guard: &Self::Guard, ttl: Duration) -> Result<bool, Self::Error> {
+        (**self).extend(guard, ttl)
+    }
+
+    fn release(&self, guard: Self::Guard) -> Result<(), Self::Error> {
+        (**self).release(guard)
+    }
+}
+
+/// The error of `with_lock`
+#[derive(Debug)]
+pub enum LockError<E> {
+    /// Another holder has the lock
+    Contended {
+        /// The key of the lock
+        key: String,
+    },
+    /// The lock expired while the transaction ran, so another holder may
+    /// have run concurrently
+    Lost {
+        /// The key of the lock
+        key: String,
+    },
+    /// The lock manager failed
+    Manager(E),
+}
+
+impl<E> fmt::Display for LockError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockError::Contended { ref key } => write!(f, "lock `{}` is held by another holder", key),
+            LockError::Lost { ref key } => write!(f, "lock `{}` expired while the transaction ran", key),
+            LockError::Manager(ref e) => write!(f, "failed to lock: {}", e),
+        }
+    }
+}
+
+impl<E> Error for LockError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LockError::Manager(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// the lock is likely to be free when the transaction runs again
+impl<E> Retryable for LockError<E>
+where
+    E: Retryable,
+{
+    fn is_retryable(&self) -> bool {
+        match *self {
+            LockError::Contended { .. } | LockError::Lost { .. } => true,
+            LockError::Manager(ref e) => e.is_retryable(),
+        }
+    }
+}
+
+/// Run the transaction holding the distributed lock of the key, acquired
+/// for `ttl` before it runs and released after. The lock is extended every
+/// third of `ttl` while the transaction runs, and the transaction fails with
+/// `LockError::Lost` if it expires nevertheless, so that the runner rolls it
+/// back. A lock held by another holder fails it with the retryable
+/// `LockError::Contended`, e.g. for `retry_with`.
+///
+/// The lock is extended on a thread of its own, so on the targets without
+/// threads, e.g. `wasm32-unknown-unknown`, it is not extended and the
+/// transaction has to finish within `ttl`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::collections::HashSet;
+/// use std::convert::Infallible;
+/// use std::sync::Mutex;
+/// use std::time::Duration;
+/// use transaction::prelude::*;
+/// use transaction::{with_lock, LockError, LockManager};
+///
+/// #[derive(Default)]
+/// struct Locks(Mutex<HashSet<String>>);
+///
+/// impl LockManager for Locks {
+///     type Guard = String;
+///     type Error = Infallible;
+///     fn acquire(&self, key: &str, _ttl: Duration) -> Result<Option<String>, Infallible> {
+///         let fresh = self.0.lock().unwrap().insert(key.to_string());
+///         Ok(if fresh { Some(key.to_string()) } else { None })
+///     }
+///     fn extend(&self, _guard: &String, _ttl: Duration) -> Result<bool, Infallible> {
+///         Ok(true)
+///     }
+///     fn release(&self, guard: String) -> Result<(), Infallible> {
+///         self.0.lock().unwrap().remove(&guard);
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() {
+/// let locks = Locks::default();
+/// let bump = with_ctx(|n: &mut i32| -> Result<i32, LockError<Infallible>> {
+///     *n += 1;
+///     Ok(*n)
+/// });
+/// let tx = with_lock(&locks, "counter", Duration::from_secs(10), bump);
+/// assert_eq!(tx.run(&mut 0).unwrap(), 1);
+///
+/// let held = locks.acquire("counter", Duration::from_secs(10)).unwrap();
+/// assert!(matches!(tx.run(&mut 0), Err(LockError::Contended { .. })));
+/// # let _ = held;
+/// # }
+/// ```
+pub fn with_lock<Ctx, M, A>(manager: M, key: impl Into<String>, ttl: Duration, a: A) -> WithLock<M, A::Tx>
+where
+    M: LockManager + Sync,
+    M::Guard: Sync,
+    A: IntoTransaction<Ctx>,
+    A::Err: From<LockError<M::Error>>,
+{
+    WithLock {
+        manager,
+        key: key.into(),
+        ttl,
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `with_lock`
+#[derive(Debug)]
+#[must_use]
+pub struct WithLock<M, Tx> {
+    manager: M,
+    key: String,
+    ttl: Duration,
+    tx: Tx,
+}
+
+impl<M, Tx> Transaction for WithLock<M, Tx>
+where
+    M: LockManager + Sync,
+    M::Guard: Sync,
+    Tx: Transaction,
+    Tx::Err: From<LockError<M::Error>>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let guard = match self.manager.acquire(&self.key, self.ttl).map_err(LockError::Manager)? {
+            Some(guard) => guard,
+            None => return Err(LockError::Contended { key: self.key.clone() }.into()),
+        };
+        let lost = AtomicBool::new(false);
+        let ret = if !platform::THREADS {
+            self.tx.run(ctx)
+        } else {
+            thread::scope(|scope| {
+                let (done, finished) = mpsc::channel::<()>();
+                let (manager, ttl, guard, lost) = (&self.manager, self.ttl, &guard, &lost);
+                scope.spawn(move || {
+                    while let Err(mpsc::RecvTimeoutError::Timeout) = finished.recv_timeout(ttl / 3) {
+                        // a failure to extend may have let the lock expire
+                        if !manager.extend(guard, ttl).unwrap_or(false) {
+                            lost.store(true, Ordering::SeqCst);
+                            return;
+                        }
+                    }
+                });
+                let ret = self.tx.run(ctx);
+                drop(done);
+                ret
+            })
+        };
+        // a lock failed to release expires after the TTL anyway
+        let _ = self.manager.release(guard);
+        match ret {
+            Ok(_) if lost.load(Ordering::SeqCst) => Err(LockError::Lost { key: self.key.clone() }.into()),
+            ret => ret,
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<M, Tx> Visit for WithLock<M, Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("with_lock"), |v| self.tx.accept(v));
+    }
+}
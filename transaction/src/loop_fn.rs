@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn loop_fn<Ctx, S, T, F, A>(initial_state: S, f: F) -> LoopFn<Ctx, F, A>
 where
@@ -52,3 +53,13 @@ where
         }
     }
 }
+
+impl<Ctx, F, A> Visit for LoopFn<Ctx, F, A>
+where
+    A: IntoTransaction<Ctx>,
+    A::Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("loop_fn"), |v| self.tx.accept(v));
+    }
+}
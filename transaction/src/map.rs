@@ -1,4 +1,5 @@
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn map<Ctx, A, F, B>(a: A, f: F) -> Map<A::Tx, F>
 where
@@ -34,3 +35,12 @@ where
         tx.run(ctx).map(f)
     }
 }
+
+impl<Tx, F> Visit for Map<Tx, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("map"), |v| self.tx.accept(v));
+    }
+}
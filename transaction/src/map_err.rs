@@ -1,4 +1,5 @@
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn map_err<Ctx, A, F, B>(a: A, f: F) -> MapErr<A::Tx, F>
 where
@@ -34,3 +35,12 @@ where
         tx.run(ctx).map_err(f)
     }
 }
+
+impl<Tx, F> Visit for MapErr<Tx, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("map_err"), |v| self.tx.accept(v));
+    }
+}
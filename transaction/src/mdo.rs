@@ -1,7 +1,103 @@
+//! Do-notation for transactions
+//!
+//! `mdo!` writes a chain of `and_then`s as a sequence of statements:
+//!
+//! * `pattern =<< tx;` runs `tx` and binds its item to the irrefutable
+//!   pattern, e.g. `(x, y) =<< read_x().join(read_y());`. `x: T =<< tx;`
+//!   gives the type of the item.
+//! * `ign tx;` runs `tx` and ignores its item.
+//! * `let pattern = expr;` binds a plain value like `let`.
+//! * `if cond { .. } else { .. }` runs one of the branches, each of which is
+//!   a sequence of statements in turn. When the `if` is not the last
+//!   statement, it is followed by `;` and its item can be bound by
+//!   `pattern =<< if cond { .. } else { .. };`. The `else` can be left out
+//!   when the branch results in `()`, and `else if` chains are allowed.
+//! * `abort!(e)` fails with the error `e` like `err(e)`, e.g.
+//!   `if x < 0 { abort!(e) };`.
+//! * the sequence ends with `ret tx`, whose result is that of the whole
+//!   sequence, e.g. `ret ok(x + y)`, or with an `if` or `abort!`.
+//!
+//! The statements after a bind run in a `move` closure which is called every
+//! time the transaction runs, as with `and_then`, so the values used by the
+//! later statements are to be copied or cloned.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use transaction::prelude::*;
+//! use transaction::mdo;
+//!
+//! # fn main() {
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Error {
+//!     NoAccount(usize),
+//!     Insufficient(i64),
+//! }
+//!
+//! type Accounts = Vec<i64>;
+//!
+//! fn balance(account: usize) -> impl Transaction<Ctx = Accounts, Item = i64, Err = Error> {
+//!     with_ctx(move |accounts: &mut Accounts| accounts.get(account).cloned().ok_or(Error::NoAccount(account)))
+//! }
+//!
+//! fn set(account: usize, amount: i64) -> impl Transaction<Ctx = Accounts, Item = (), Err = Error> {
+//!     with_ctx(move |accounts: &mut Accounts| Ok(accounts[account] = amount))
+//! }
+//!
+//! fn transfer(from: usize, to: usize, amount: i64) -> impl Transaction<Ctx = Accounts, Item = i64, Err = Error> {
+//!     mdo! {
+//!         (available, received) =<< balance(from).join(balance(to));
+//!         if available < amount { abort!(Error::Insufficient(available)) };
+//!         let left = available - amount;
+//!         ign set(from, left);
+//!         ign set(to, received + amount);
+//!         ret ok(left)
+//!     }
+//! }
+//!
+//! let mut accounts = vec![100, 0];
+//! assert_eq!(transfer(0, 1, 30).run(&mut accounts), Ok(70));
+//! assert_eq!(transfer(0, 1, 100).run(&mut accounts), Err(Error::Insufficient(70)));
+//! assert_eq!(transfer(0, 2, 10).run(&mut accounts), Err(Error::NoAccount(2)));
+//! assert_eq!(accounts, vec![70, 30]);
+//! # }
+//! ```
+//!
+//! The branches may be transactions of different types:
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use transaction::prelude::*;
+//! use transaction::mdo;
+//!
+//! # fn main() {
+//! let clamp = mdo! {
+//!     x: i32 =<< with_ctx(|n: &mut i32| Ok::<_, ()>(*n));
+//!     y =<< if x < 0 {
+//!         ret ok(0)
+//!     } else if x > 10 {
+//!         ign with_ctx(|n: &mut i32| Ok(*n = 10));
+//!         ret ok(10)
+//!     } else {
+//!         ret with_ctx(|n: &mut i32| Ok(*n))
+//!     };
+//!     ret ok(y * 2)
+//! };
+//! assert_eq!(clamp.run(&mut -5), Ok(0));
+//! assert_eq!(clamp.run(&mut 5), Ok(10));
+//! let mut n = 50;
+//! assert_eq!(clamp.run(&mut n), Ok(20));
+//! assert_eq!(n, 10);
+//! # }
+//! ```
+
 use super::prelude::*;
 
 /// bind for Transaction>, equivalent to `tx.and_then(f)
-pub fn bind<Tx, F, B>(tx: Tx, f: F) -> ::AndThen<Tx, F, B>
+pub fn bind<Tx, F, B>(tx: Tx, f: F) -> crate::AndThen<Tx, F, B>
 where
     B: Transaction<Ctx = Tx::Ctx, Err = Tx::Err>,
     F: Fn(Tx::Item) -> B,
@@ -11,6 +107,76 @@ where
 }
 
 /// return for Transaction<Ctx = Ctx, Item = T, Err = E>, equivalent to `ok(x)`
-pub fn ret<Ctx, T, E>(x: T) -> ::TxOk<Ctx, T, E> {
+pub fn ret<Ctx, T, E>(x: T) -> crate::TxOk<Ctx, T, E> {
     ok(x)
 }
+
+/// Do-notation for transactions. See the `mdo` module.
+#[macro_export]
+macro_rules! mdo {
+    (@if $p:tt [$($c:tt)*] { $($a:tt)* } else if $($t:tt)*) => (
+        $crate::mdo!(@elif $p [$($c)*] { $($a)* } [] $($t)*)
+    );
+    (@if $p:tt [$($c:tt)*] { $($a:tt)* } else { $($b:tt)* } $($t:tt)*) => (
+        $crate::mdo!(@then $p (
+            if $($c)* {
+                $crate::Branch::B1($crate::mdo! { $($a)* })
+            } else {
+                $crate::Branch::B2($crate::mdo! { $($b)* })
+            }
+        ) $($t)*)
+    );
+    (@if $p:tt [$($c:tt)*] { $($a:tt)* } ; $($t:tt)*) => (
+        $crate::mdo!(@if $p [$($c)*] { $($a)* } else { ret $crate::ok(()) } ; $($t)*)
+    );
+    (@if $p:tt [$($c:tt)*] $x:tt $($t:tt)*) => ($crate::mdo!(@if $p [$($c)* $x] $($t)*));
+
+    // `else if` chains run up to the end of the `if`, which ends the
+    // sequence or is followed by `;`
+    (@elif $p:tt [$($c:tt)*] { $($a:tt)* } [$($e:tt)*] ; $($t:tt)*) => (
+        $crate::mdo!(@if $p [$($c)*] { $($a)* } else { if $($e)* } ; $($t)*)
+    );
+    (@elif $p:tt [$($c:tt)*] { $($a:tt)* } [$($e:tt)*]) => (
+        $crate::mdo!(@if $p [$($c)*] { $($a)* } else { if $($e)* })
+    );
+    (@elif $p:tt [$($c:tt)*] { $($a:tt)* } [$($e:tt)*] $x:tt $($t:tt)*) => (
+        $crate::mdo!(@elif $p [$($c)*] { $($a)* } [$($e)* $x] $($t)*)
+    );
+
+    // what comes after an `if`
+    (@then (_) ($e:expr)) => ($e);
+    (@then ($p:ident : $ty:ty) ($e:expr) ; $($t:tt)*) => (
+        $crate::mdo::bind($e, move |$p: $ty| $crate::mdo! { $($t)* })
+    );
+    (@then ($p:pat) ($e:expr) ; $($t:tt)*) => (
+        $crate::mdo::bind($e, move |$p| $crate::mdo! { $($t)* })
+    );
+
+    // the end of a sequence
+    (ret $e:expr) => ($e);
+    (abort!($e:expr)) => ($crate::err($e));
+
+    (let $p:ident : $ty:ty = $e:expr ; $($t:tt)*) => ({
+        let $p: $ty = $e;
+        $crate::mdo! { $($t)* }
+    });
+    (let $p:pat = $e:expr ; $($t:tt)*) => ({
+        let $p = $e;
+        $crate::mdo! { $($t)* }
+    });
+    (ign $e:expr ; $($t:tt)*) => (
+        $crate::mdo::bind($e, move |_| $crate::mdo! { $($t)* })
+    );
+
+    // `if` collects its condition up to the first block
+    (if $($t:tt)*) => ($crate::mdo!(@if (_) [] $($t)*));
+    ($p:ident : $ty:ty =<< if $($t:tt)*) => ($crate::mdo!(@if ($p: $ty) [] $($t)*));
+    ($p:pat =<< if $($t:tt)*) => ($crate::mdo!(@if ($p) [] $($t)*));
+
+    ($p:ident : $ty:ty =<< $e:expr ; $($t:tt)*) => (
+        $crate::mdo::bind($e, move |$p: $ty| $crate::mdo! { $($t)* })
+    );
+    ($p:pat =<< $e:expr ; $($t:tt)*) => (
+        $crate::mdo::bind($e, move |$p| $crate::mdo! { $($t)* })
+    );
+}
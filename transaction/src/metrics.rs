@@ -0,0 +1,97 @@
+//! Metrics reported by runners and retrying combinators.
+//!
+//! Register a `Metrics` implementation once at start up to export commit
+//! rate, rollback rate, retry counts and latency per named transaction.
+//! With the `metrics` feature, `MetricsFacade` forwards them to the
+//! [metrics](https://docs.rs/metrics) crate.
+
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::hooks::Outcome;
+
+/// Counter incremented for each committed transaction
+pub const COMMITS: &str = "transaction_commits_total";
+/// Counter incremented for each rolled back transaction
+pub const ROLLBACKS: &str = "transaction_rollbacks_total";
+/// Counter incremented for each retry of a failed attempt
+pub const RETRIES: &str = "transaction_retries_total";
+/// Histogram of the duration of the runs in seconds
+pub const DURATION: &str = "transaction_duration_seconds";
+
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+static METRICS: RwLock<Vec<Box<dyn Metrics>>> = RwLock::new(Vec::new());
+
+/// Receiver of counters and histograms. `label` is the label of the
+/// transaction given by `named`.
+pub trait Metrics: Send + Sync {
+    /// Increment the counter `name`
+    fn increment_counter(&self, name: &'static str, label: Option<&str>);
+
+    /// Record `value` to the histogram `name`
+    fn record_histogram(&self, name: &'static str, label: Option<&str>, value: f64);
+}
+
+/// Register metrics. All the registered metrics receive all the values.
+pub fn register(metrics: Box<dyn Metrics>) {
+    METRICS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(metrics);
+    REGISTERED.store(true, Ordering::Release);
+}
+
+fn each<F>(f: F)
+where
+    F: Fn(&dyn Metrics),
+{
+    if !REGISTERED.load(Ordering::Acquire) {
+        return;
+    }
+    for metrics in METRICS.read().unwrap_or_else(|e| e.into_inner()).iter() {
+        f(&**metrics);
+    }
+}
+
+/// Record a finished run of a transaction.
+/// This is called by transaction runners rather than users.
+pub fn record_run(label: Option<&str>, outcome: Outcome, elapsed: Duration) {
+    each(|metrics| {
+        let counter = match outcome {
+            Outcome::Committed => COMMITS,
+            Outcome::RolledBack => ROLLBACKS,
+        };
+        metrics.increment_counter(counter, label);
+        metrics.record_histogram(DURATION, label, elapsed.as_secs_f64());
+    })
+}
+
+/// Record a retry of a failed attempt.
+/// This is called by transaction runners and retrying combinators.
+pub fn record_retry(label: Option<&str>) {
+    each(|metrics| metrics.increment_counter(RETRIES, label))
+}
+
+/// Forwarder to the [metrics](https://docs.rs/metrics) crate.
+/// The label of the transaction is attached as the `label` label.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsFacade;
+
+#[cfg(feature = "metrics")]
+impl Metrics for MetricsFacade {
+    fn increment_counter(&self, name: &'static str, label: Option<&str>) {
+        match label {
+            Some(label) => metrics_crate::counter!(name, "label" => label.to_string()).increment(1),
+            None => metrics_crate::counter!(name).increment(1),
+        }
+    }
+
+    fn record_histogram(&self, name: &'static str, label: Option<&str>, value: f64) {
+        match label {
+            Some(label) => metrics_crate::histogram!(name, "label" => label.to_string()).record(value),
+            None => metrics_crate::histogram!(name).record(value),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::borrow::Cow;
+
+use crate::{IntoTransaction, Transaction};
+use crate::clock::Instant;
+use crate::{coverage, profile};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Attach a label to the transaction. The label doesn't change the
+/// computation but is visible to tooling walking the transaction.
+pub fn named<Ctx, A, L>(a: A, label: L) -> Named<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+    L: Into<Cow<'static, str>>,
+{
+    Named {
+        tx: a.into_transaction(),
+        label: label.into(),
+    }
+}
+
+/// The result of `named`
+#[derive(Debug)]
+#[must_use]
+pub struct Named<Tx> {
+    tx: Tx,
+    label: Cow<'static, str>,
+}
+
+impl<Tx> Named<Tx> {
+    /// The label of the transaction
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+impl<Tx> Transaction for Named<Tx>
+where
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        #[cfg(feature = "log")]
+        log::trace!("running step {:?}", self.label);
+        let _scope = if coverage::is_active() {
+            Some(coverage::enter_step(&self.label))
+        } else {
+            None
+        };
+        let ret = if !profile::is_active() {
+            self.tx.run(ctx)
+        } else {
+            let start = Instant::now();
+            let ret = self.tx.run(ctx);
+            profile::record(&self.label, start.elapsed(), ret.is_ok());
+            ret
+        };
+        #[cfg(feature = "log")]
+        match ret {
+            Ok(_) => log::trace!("step {:?} succeeded", self.label),
+            Err(_) => log::debug!("step {:?} failed", self.label),
+        }
+        ret
+    }
+
+    fn label(&self) -> Option<&str> {
+        Some(&self.label)
+    }
+}
+
+impl<Tx> Visit for Named<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        let node = Node {
+            kind: "named",
+            label: Some(&self.label),
+        };
+        visit_node(visitor, node, |v| self.tx.accept(v));
+    }
+}
@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use crate::Transaction;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
 
 /// make a successful transaction value.
 pub fn ok<Ctx, T, E>(t: T) -> TxOk<Ctx, T, E> {
@@ -29,3 +30,9 @@ where
         Ok(self.ok.clone())
     }
 }
+
+impl<Ctx, T, E> Visit for TxOk<Ctx, T, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("ok"));
+    }
+}
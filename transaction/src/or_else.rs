@@ -1,6 +1,8 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::coverage;
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 
 pub fn or_else<Ctx, A, F, B>(a: A, f: F) -> OrElse<A::Tx, F, B>
@@ -42,8 +44,25 @@ where
 
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         let &OrElse { ref tx, ref f, .. } = self;
-        tx.run(ctx).or_else(
-            |item| f(item).into_transaction().run(ctx),
-        )
+        if !coverage::is_active() {
+            return tx.run(ctx).or_else(
+                |item| f(item).into_transaction().run(ctx),
+            );
+        }
+        let ret = tx.run(ctx);
+        coverage::run_branch(ret.is_err());
+        ret.or_else(|item| {
+            let _recovery = coverage::enter_recovery();
+            f(item).into_transaction().run(ctx)
+        })
+    }
+}
+
+impl<Tx1, F, Tx2> Visit for OrElse<Tx1, F, Tx2>
+where
+    Tx1: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("or_else"), |v| self.tx.accept(v));
     }
 }
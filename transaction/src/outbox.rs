@@ -0,0 +1,317 @@
+//! The transactional outbox: events published by a transaction are inserted
+//! into an outbox in the same backend transaction, so that they are
+//! published if and only if it commits.
+//!
+//! `publish` inserts an event into the outbox of a context implementing
+//! `Outbox`, e.g. a table of a SQL database. A `Relay` then drains the
+//! outbox to a message `Broker` in the order of the insertion, removing the
+//! events once they are sent. An event may be sent again if the relay fails
+//! in between, so the consumers should be idempotent.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use std::convert::Infallible;
+//! use transaction::prelude::*;
+//! use transaction::outbox::{Broker, Event, Outbox, Pending, Relay, Source};
+//!
+//! #[derive(Default)]
+//! struct Db {
+//!     orders: Vec<u32>,
+//!     outbox: Vec<Pending<usize>>,
+//!     next: usize,
+//! }
+//!
+//! impl Outbox for Db {
+//!     type Error = Infallible;
+//!     fn insert(&mut self, event: &Event) -> Result<(), Infallible> {
+//!         self.outbox.push(Pending { id: self.next, event: event.clone() });
+//!         self.next += 1;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! impl<'a> Source for &'a mut Db {
+//!     type Id = usize;
+//!     type Error = Infallible;
+//!     fn fetch(&mut self, limit: usize) -> Result<Vec<Pending<usize>>, Infallible> {
+//!         Ok(self.outbox.iter().take(limit).cloned().collect())
+//!     }
+//!     fn remove(&mut self, ids: &[usize]) -> Result<(), Infallible> {
+//!         self.outbox.retain(|p| !ids.contains(&p.id));
+//!         Ok(())
+//!     }
+//! }
+//!
+//! struct Printer(Vec<String>);
+//!
+//! impl Broker for Printer {
+//!     type Error = Infallible;
+//!     fn send(&mut self, event: &Event) -> Result<(), Infallible> {
+//!         self.0.push(format!("{}: {}", event.topic, String::from_utf8_lossy(&event.payload)));
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let place = with_ctx(|db: &mut Db| -> Result<u32, Infallible> {
+//!     db.orders.push(42);
+//!     Ok(42)
+//! })
+//! .publish(Event::new("orders", "placed 42"));
+//!
+//! let mut db = Db::default();
+//! assert_eq!(place.run(&mut db), Ok(42));
+//!
+//! let mut relay = Relay::new(&mut db, Printer(vec![]));
+//! assert_eq!(relay.drain().unwrap(), 1);
+//! assert_eq!(relay.broker().0, vec!["orders: placed 42".to_string()]);
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::visit::{visit_node, Node, Visit, Visitor};
+use crate::{clock, IntoTransaction, Transaction};
+
+/// An event to publish
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Event {
+    /// The topic, queue or subject the event is sent to
+    pub topic: String,
+    /// The encoded event
+    pub payload: Vec<u8>,
+}
+
+impl Event {
+    /// An event of `payload` sent to `topic`
+    pub fn new(topic: impl Into<String>, payload: impl Into<Vec<u8>>) -> Self {
+        Event {
+            topic: topic.into(),
+            payload: payload.into(),
+        }
+    }
+}
+
+/// Backend contexts with an outbox the events can be inserted into within
+/// the transaction
+pub trait Outbox {
+    /// The error of the insertion
+    type Error;
+
+    /// Insert the event into the outbox
+    fn insert(&mut self, event: &Event) -> Result<(), Self::Error>;
+}
+
+/// Insert the event into the outbox of the context after the transaction
+/// succeeds, keeping its item. The event is published only if the whole
+/// transaction commits.
+pub fn publish<Ctx, A>(a: A, event: Event) -> Publish<A::Tx>
+where
+    Ctx: Outbox,
+    A: IntoTransaction<Ctx>,
+    A::Err: From<Ctx::Error>,
+{
+    Publish {
+        tx: a.into_transaction(),
+        event,
+    }
+}
+
+/// The result of `publish`
+#[derive(Debug)]
+#[must_use]
+pub struct Publish<Tx> {
+    tx: Tx,
+    event: Event,
+}
+
+impl<Tx> Transaction for Publish<Tx>
+where
+    Tx: Transaction,
+    Tx::Ctx: Outbox,
+    Tx::Err: From<<Tx::Ctx as Outbox>::Error>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let item = self.tx.run(ctx)?;
+        ctx.insert(&self.event)?;
+        Ok(item)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Publish<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("publish"), |v| self.tx.accept(v));
+    }
+}
+
+/// An event in the outbox waiting to be sent
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pending<Id> {
+    /// The id of the event in the outbox
+    pub id: Id,
+    /// The event
+    pub event: Event,
+}
+
+/// The outbox drained by a `Relay`, read outside of the transactions
+/// inserting into it
+pub trait Source {
+    /// The id of an event in the outbox
+    type Id;
+    /// The error of the outbox
+    type Error;
+
+    /// The oldest events in the outbox, at most `limit` of them, in the
+    /// order of the insertion
+    fn fetch(&mut self, limit: usize) -> Result<Vec<Pending<Self::Id>>, Self::Error>;
+
+    /// Remove the events sent
+    fn remove(&mut self, ids: &[Self::Id]) -> Result<(), Self::Error>;
+}
+
+/// A message broker the events are sent to
+pub trait Broker {
+    /// The error of sending
+    type Error;
+
+    /// Send the event. It must be accepted by the broker once this
+    /// succeeds.
+    fn send(&mut self, event: &Event) -> Result<(), Self::Error>;
+}
+
+/// The error of a `Relay`
+#[derive(Debug)]
+pub enum RelayError<S, B> {
+    /// The outbox failed
+    Source(S),
+    /// The broker failed to send an event, which is sent again next time
+    Broker(B),
+}
+
+impl<S, B> fmt::Display for RelayError<S, B>
+where
+    S: fmt::Display,
+    B: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RelayError::Source(ref e) => write!(f, "failed to read the outbox: {}", e),
+            RelayError::Broker(ref e) => write!(f, "failed to send an event: {}", e),
+        }
+    }
+}
+
+impl<S, B> Error for RelayError<S, B>
+where
+    S: Error + 'static,
+    B: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RelayError::Source(ref e) => Some(e),
+            RelayError::Broker(ref e) => Some(e),
+        }
+    }
+}
+
+/// A worker sending the events of an outbox to a broker in the order of the
+/// insertion, at least once each
+#[derive(Debug)]
+pub struct Relay<S, B> {
+    source: S,
+    broker: B,
+    batch: usize,
+}
+
+impl<S, B> Relay<S, B>
+where
+    S: Source,
+    B: Broker,
+{
+    /// A relay from the outbox to the broker, fetching 100 events at a time
+    pub fn new(source: S, broker: B) -> Self {
+        Relay {
+            source,
+            broker,
+            batch: 100,
+        }
+    }
+
+    /// Set how many events are fetched at a time
+    pub fn batch(self, batch: usize) -> Self {
+        Relay { batch, ..self }
+    }
+
+    /// The outbox
+    pub fn source(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// The broker
+    pub fn broker(&mut self) -> &mut B {
+        &mut self.broker
+    }
+
+    /// Send a batch of events and remove them from the outbox, and return
+    /// how many are sent. When sending one fails, those sent before it are
+    /// still removed.
+    pub fn relay_once(&mut self) -> Result<usize, RelayError<S::Error, B::Error>> {
+        let pending = self.source.fetch(self.batch).map_err(RelayError::Source)?;
+        let mut sent = Vec::with_capacity(pending.len());
+        let mut failed = None;
+        for Pending { id, event } in pending {
+            if let Err(e) = self.broker.send(&event) {
+                failed = Some(e);
+                break;
+            }
+            sent.push(id);
+        }
+        if !sent.is_empty() {
+            self.source.remove(&sent).map_err(RelayError::Source)?;
+        }
+        match failed {
+            Some(e) => Err(RelayError::Broker(e)),
+            None => Ok(sent.len()),
+        }
+    }
+
+    /// Send the events until the outbox is empty, and return how many are
+    /// sent
+    pub fn drain(&mut self) -> Result<usize, RelayError<S::Error, B::Error>> {
+        let mut total = 0;
+        loop {
+            match self.relay_once()? {
+                0 => return Ok(total),
+                sent => total += sent,
+            }
+        }
+    }
+
+    /// Drain the outbox repeatedly, sleeping `interval` whenever it is
+    /// empty, until `stop` is set or an error occurs
+    pub fn run(&mut self, interval: Duration, stop: &AtomicBool) -> Result<(), RelayError<S::Error, B::Error>> {
+        while !stop.load(Ordering::Relaxed) {
+            if self.drain()? == 0 {
+                clock::sleep(interval);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_leaf, visit_node, Node, Visit, Visitor};
+
+/// Key-value contexts whose reads don't see the writes of the same run,
+/// e.g. redis queuing the writes until `EXEC`, for `overlay_get`,
+/// `overlay_set` and `overlay_del`
+pub trait OverlayStore<K, V> {
+    /// The error of the store
+    type Err;
+
+    /// Read the value of the key from the store
+    fn load(&mut self, key: &K) -> Result<Option<V>, Self::Err>;
+
+    /// Write the value of the key to the store, deleting it if `None`
+    fn store(&mut self, key: &K, value: Option<&V>) -> Result<(), Self::Err>;
+
+    /// The writes of the run, kept by the context
+    fn overlay(&mut self) -> &mut Overlay<K, V>;
+}
+
+/// The values written by the `overlay_set`s and `overlay_del`s of a run,
+/// answering the `overlay_get`s of the same keys
+#[derive(Debug, Clone)]
+pub struct Overlay<K, V> {
+    writes: HashMap<K, Option<V>>,
+}
+
+impl<K, V> Default for Overlay<K, V> {
+    fn default() -> Self {
+        Overlay { writes: HashMap::new() }
+    }
+}
+
+impl<K, V> Overlay<K, V>
+where
+    K: Hash + Eq,
+{
+    /// Make an empty overlay
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value written for the key, `Some(None)` if it was deleted, or
+    /// `None` if it wasn't written
+    pub fn get(&self, key: &K) -> Option<Option<&V>> {
+        self.writes.get(key).map(Option::as_ref)
+    }
+
+    /// The number of keys written
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Whether no key was written
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Forget the writes
+    pub fn clear(&mut self) {
+        self.writes.clear();
+    }
+}
+
+/// Get the value of the key, as last written by `overlay_set` or
+/// `overlay_del` in the run if it was, or else from the store.
+///
+/// The writes of the other leaves of the store are not seen. Run the
+/// transaction by `read_your_writes` to start from an empty overlay.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::collections::HashMap;
+/// use transaction::prelude::*;
+/// use transaction::{overlay_del, overlay_get, overlay_set, Overlay, OverlayStore};
+///
+/// // a store applying the writes only at commit, like `MULTI` of redis
+/// struct Store {
+///     rows: HashMap<&'static str, i64>,
+///     queued: Vec<(&'static str, Option<i64>)>,
+///     overlay: Overlay<&'static str, i64>,
+/// }
+///
+/// impl OverlayStore<&'static str, i64> for Store {
+///     type Err = ();
+///
+///     fn load(&mut self, key: &&'static str) -> Result<Option<i64>, ()> {
+///         Ok(self.rows.get(key).cloned())
+///     }
+///
+///     fn store(&mut self, key: &&'static str, value: Option<&i64>) -> Result<(), ()> {
+///         self.queued.push((*key, value.cloned()));
+///         Ok(())
+///     }
+///
+///     fn overlay(&mut self) -> &mut Overlay<&'static str, i64> {
+///         &mut self.overlay
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut store = Store {
+///     rows: vec![("alice", 10)].into_iter().collect(),
+///     queued: vec![],
+///     overlay: Overlay::new(),
+/// };
+///
+/// let tx = overlay_set("bob", 5)
+///     .and_then(|_| overlay_get("bob"))
+///     .join(overlay_del("alice").and_then(|_| overlay_get("alice")))
+///     .read_your_writes();
+/// assert_eq!(tx.run(&mut store), Ok::<_, ()>((Some(5), None)));
+/// assert_eq!(store.queued, vec![("bob", Some(5)), ("alice", None)]);
+/// // the store itself is not written before commit
+/// assert_eq!(store.rows.get("alice"), Some(&10));
+/// assert!(store.overlay.is_empty());
+/// # }
+/// ```
+pub fn overlay_get<Ctx, K, V, E>(key: K) -> OverlayGet<Ctx, K, V, E>
+where
+    Ctx: OverlayStore<K, V>,
+{
+    OverlayGet {
+        key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `overlay_get`
+#[derive(Debug)]
+#[must_use]
+pub struct OverlayGet<Ctx, K, V, E> {
+    key: K,
+    _phantom: PhantomData<(Ctx, V, E)>,
+}
+
+impl<Ctx, K, V, E> Transaction for OverlayGet<Ctx, K, V, E>
+where
+    Ctx: OverlayStore<K, V>,
+    K: Hash + Eq,
+    V: Clone,
+    E: From<Ctx::Err>,
+{
+    type Ctx = Ctx;
+    type Item = Option<V>;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        if let Some(value) = ctx.overlay().get(&self.key) {
+            return Ok(value.cloned());
+        }
+        Ok(ctx.load(&self.key)?)
+    }
+}
+
+impl<Ctx, K, V, E> Visit for OverlayGet<Ctx, K, V, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("overlay_get"));
+    }
+}
+
+/// Write the value of the key to the store, and record it so that the
+/// `overlay_get`s of the run read it. See `overlay_get`.
+pub fn overlay_set<Ctx, K, V, E>(key: K, value: V) -> OverlaySet<Ctx, K, V, E>
+where
+    Ctx: OverlayStore<K, V>,
+{
+    OverlaySet {
+        key,
+        value: Some(value),
+        _phantom: PhantomData,
+    }
+}
+
+/// Delete the key from the store, and record it so that the `overlay_get`s
+/// of the run read nothing. See `overlay_get`.
+pub fn overlay_del<Ctx, K, V, E>(key: K) -> OverlaySet<Ctx, K, V, E>
+where
+    Ctx: OverlayStore<K, V>,
+{
+    OverlaySet {
+        key,
+        value: None,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `overlay_set` and `overlay_del`
+#[derive(Debug)]
+#[must_use]
+pub struct OverlaySet<Ctx, K, V, E> {
+    key: K,
+    value: Option<V>,
+    _phantom: PhantomData<(Ctx, E)>,
+}
+
+impl<Ctx, K, V, E> Transaction for OverlaySet<Ctx, K, V, E>
+where
+    Ctx: OverlayStore<K, V>,
+    K: Hash + Eq + Clone,
+    V: Clone,
+    E: From<Ctx::Err>,
+{
+    type Ctx = Ctx;
+    type Item = ();
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.store(&self.key, self.value.as_ref())?;
+        ctx.overlay().writes.insert(self.key.clone(), self.value.clone());
+        Ok(())
+    }
+}
+
+impl<Ctx, K, V, E> Visit for OverlaySet<Ctx, K, V, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        let kind = if self.value.is_some() { "overlay_set" } else { "overlay_del" };
+        visit_leaf(visitor, Node::new(kind));
+    }
+}
+
+/// Run the transaction from an empty overlay, and forget its writes once it
+/// finishes, so that the contexts kept across runs don't answer from the
+/// writes of another run. See `overlay_get`.
+pub fn read_your_writes<Ctx, A, K, V>(a: A) -> ReadYourWrites<A::Tx, K, V>
+where
+    A: IntoTransaction<Ctx>,
+    Ctx: OverlayStore<K, V>,
+{
+    ReadYourWrites {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `read_your_writes`
+#[derive(Debug)]
+#[must_use]
+pub struct ReadYourWrites<Tx, K, V> {
+    tx: Tx,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<Tx, K, V> Transaction for ReadYourWrites<Tx, K, V>
+where
+    Tx: Transaction,
+    Tx::Ctx: OverlayStore<K, V>,
+    K: Hash + Eq,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.overlay().clear();
+        let ret = self.tx.run(ctx);
+        ctx.overlay().clear();
+        ret
+    }
+}
+
+impl<Tx, K, V> Visit for ReadYourWrites<Tx, K, V>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("read_your_writes"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,242 @@
+use rayon::prelude::*;
+
+use crate::thread_state::{self, ThreadState};
+use crate::{IntoTransaction, SplittableCtx, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Run the two transactions in parallel on the thread pool of rayon, each in
+/// a context split from the context. Both are run, and the error of the
+/// first one is returned if both fail, like `join`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::ops::Range;
+/// use std::sync::Arc;
+/// use transaction::prelude::*;
+/// use transaction::{par_join, par_join_all, SplittableCtx};
+///
+/// // a snapshot of the rows counting its reads
+/// struct Snapshot {
+///     rows: Arc<Vec<i64>>,
+///     reads: usize,
+/// }
+///
+/// impl SplittableCtx for Snapshot {
+///     fn split(&mut self) -> Self {
+///         Snapshot { rows: self.rows.clone(), reads: 0 }
+///     }
+///
+///     fn merge(&mut self, branch: Self) {
+///         self.reads += branch.reads;
+///     }
+/// }
+///
+/// fn sum(range: Range<usize>) -> impl Transaction<Ctx = Snapshot, Item = i64, Err = String> + Sync {
+///     with_ctx(move |snapshot: &mut Snapshot| {
+///         let rows = snapshot.rows.get(range.clone()).ok_or(format!("no rows {:?}", range))?;
+///         snapshot.reads += rows.len();
+///         Ok(rows.iter().sum())
+///     })
+/// }
+///
+/// # fn main() {
+/// let mut snapshot = Snapshot { rows: Arc::new((1..=1000).collect()), reads: 0 };
+///
+/// let halves = par_join(sum(0..500), sum(500..1000)).map(|(a, b)| a + b);
+/// assert_eq!(halves.run(&mut snapshot), Ok(500500));
+/// assert_eq!(snapshot.reads, 1000);
+///
+/// let quarters = par_join_all((0..4).map(|i| sum(i * 250..(i + 1) * 250)));
+/// assert_eq!(quarters.run(&mut snapshot), Ok(vec![31375, 93875, 156375, 218875]));
+///
+/// let out_of_range = par_join_all(vec![sum(0..10), sum(990..1010), sum(2000..2001)]);
+/// assert_eq!(out_of_range.run(&mut snapshot), Err("no rows 990..1010".to_string()));
+/// # }
+/// ```
+///
+/// The branches run with the environments provided, the tenant, the
+/// deadline, the isolation level and the progress subscribers of the run on
+/// the thread calling `run`, and the actions they defer until the commit,
+/// e.g. filling the cache of `cached`, wait for the run to commit.
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::time::Duration;
+/// use transaction::prelude::*;
+/// use transaction::runner::{Backend, RunOptions, RunnerBuilder};
+/// use transaction::{for_tenant, par_join, provide, with_env, Cache, CrossTenant, LruCache, SplittableCtx, TenantScope};
+///
+/// struct Db;
+///
+/// impl SplittableCtx for Db {
+///     fn split(&mut self) -> Self {
+///         Db
+///     }
+/// }
+///
+/// impl TenantScope for Db {
+///     type Err = CrossTenant;
+///
+///     fn scope_to(&mut self, _tenant: &str) -> Result<(), CrossTenant> {
+///         Ok(())
+///     }
+/// }
+///
+/// // a backend committing every run
+/// struct Store;
+///
+/// impl<T, E> Backend<T, E> for Store {
+///     type Ctx = Db;
+///
+///     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+///     where
+///         Tx: Transaction<Ctx = Db, Item = T, Err = E> + ?Sized,
+///     {
+///         tx.run(&mut Db)
+///     }
+/// }
+///
+/// struct PageSize(usize);
+///
+/// # fn main() {
+/// let runner = RunnerBuilder::new(Store).after_commit().build();
+/// let cache = LruCache::new(10);
+///
+/// let page_size = with_env(|size: &PageSize, _: &mut Db| Ok(size.0));
+/// let count = for_tenant("acme", with_ctx(|_: &mut Db| Ok(42)))
+///     .cached(&cache, |_: &Db| "count", Duration::from_secs(60));
+/// let tx = provide(PageSize(20), par_join(page_size, count)).scoped_to_tenant("acme");
+/// assert_eq!(runner.run(tx), Ok::<_, CrossTenant>((20, 42)));
+/// assert_eq!(cache.get(&"count"), Some(42));
+/// # }
+/// ```
+pub fn par_join<Ctx, A, B>(a: A, b: B) -> ParJoin<A::Tx, B::Tx>
+where
+    A: IntoTransaction<Ctx>,
+    B: IntoTransaction<Ctx, Err = A::Err>,
+{
+    ParJoin {
+        tx1: a.into_transaction(),
+        tx2: b.into_transaction(),
+    }
+}
+
+/// The result of `par_join`
+#[derive(Debug)]
+#[must_use]
+pub struct ParJoin<Tx1, Tx2> {
+    tx1: Tx1,
+    tx2: Tx2,
+}
+
+impl<Tx1, Tx2> Transaction for ParJoin<Tx1, Tx2>
+where
+    Tx1: Transaction + Sync,
+    Tx2: Transaction<Ctx = Tx1::Ctx, Err = Tx1::Err> + Sync,
+    Tx1::Ctx: SplittableCtx + Send,
+    Tx1::Item: Send,
+    Tx2::Item: Send,
+    Tx1::Err: Send,
+{
+    type Ctx = Tx1::Ctx;
+    type Item = (Tx1::Item, Tx2::Item);
+    type Err = Tx1::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let (mut ctx1, mut ctx2) = (ctx.split(), ctx.split());
+        let state = ThreadState::capture();
+        let ((r1, deferred1), (r2, deferred2)) = rayon::join(
+            || state.enter(|| self.tx1.run(&mut ctx1)),
+            || state.enter(|| self.tx2.run(&mut ctx2)),
+        );
+        ctx.merge(ctx1);
+        ctx.merge(ctx2);
+        thread_state::defer(deferred1);
+        thread_state::defer(deferred2);
+        match (r1, r2) {
+            (Ok(r1), Ok(r2)) => Ok((r1, r2)),
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+    }
+}
+
+impl<Tx1, Tx2> Visit for ParJoin<Tx1, Tx2>
+where
+    Tx1: Visit,
+    Tx2: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("par_join"), |v| {
+            self.tx1.accept(v);
+            self.tx2.accept(v);
+        });
+    }
+}
+
+/// Run the transactions in parallel on the thread pool of rayon, each in a
+/// context split from the context, and collect the items in order. All are
+/// run, and the error of the first one failing in order is returned. See
+/// `par_join`.
+pub fn par_join_all<Ctx, I, B>(i: I) -> ParJoinAll<B::Tx>
+where
+    I: IntoIterator<Item = B>,
+    B: IntoTransaction<Ctx>,
+{
+    ParJoinAll {
+        vec: i.into_iter().map(IntoTransaction::into_transaction).collect(),
+    }
+}
+
+/// The result of `par_join_all`
+#[derive(Debug)]
+#[must_use]
+pub struct ParJoinAll<Tx> {
+    vec: Vec<Tx>,
+}
+
+impl<Tx> Transaction for ParJoinAll<Tx>
+where
+    Tx: Transaction + Sync,
+    Tx::Ctx: SplittableCtx + Send,
+    Tx::Item: Send,
+    Tx::Err: Send,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Vec<Tx::Item>;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let mut ctxs = self.vec.iter().map(|_| ctx.split()).collect::<Vec<_>>();
+        let state = ThreadState::capture();
+        let rets = self
+            .vec
+            .par_iter()
+            .zip(ctxs.par_iter_mut())
+            .map(|(tx, ctx)| state.enter(|| tx.run(ctx)))
+            .collect::<Vec<_>>();
+        for branch in ctxs {
+            ctx.merge(branch);
+        }
+        let mut items = Vec::with_capacity(rets.len());
+        for (ret, deferred) in rets {
+            thread_state::defer(deferred);
+            items.push(ret);
+        }
+        items.into_iter().collect()
+    }
+}
+
+impl<Tx> Visit for ParJoinAll<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("par_join_all"), |v| for tx in &self.vec {
+            tx.accept(v);
+        });
+    }
+}
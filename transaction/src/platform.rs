@@ -0,0 +1,11 @@
+// What the target provides, in the code rather than the build scripts. On
+// `wasm32-unknown-unknown`, `std::thread::spawn`, `std::thread::sleep`,
+// `std::time::Instant::now` and `std::time::SystemTime::now` panic, so the
+// clock stands still and the work of the threads is run by the caller.
+const BARE_WASM: bool = cfg!(all(target_family = "wasm", target_os = "unknown"));
+
+// whether threads can be spawned and slept
+pub(crate) const THREADS: bool = !BARE_WASM;
+
+// whether the time can be read
+pub(crate) const TIME: bool = !BARE_WASM;
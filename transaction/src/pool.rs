@@ -0,0 +1,253 @@
+//! Connection pools the pooled runners are built against, so that the
+//! runners and the middleware around them work with any pool.
+//!
+//! A `ConnectionProvider` lends the connections of a pool of a sync driver,
+//! and an `AsyncConnectionProvider` those of an async driver. The runners
+//! check the health of the connections they acquire, and give them back
+//! with whether they are broken, e.g. after a failed commit, so that the
+//! pool doesn't lend them again.
+//!
+//! The pools of `r2d2`, `deadpool` and `bb8` are providers with the features
+//! of the same names.
+//!
+//! The pooled runners also run batches of independent transactions on a
+//! single connection, committed together or one by one as told by
+//! `Pipelining`, so that job workers don't check a connection out for each.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use std::time::Duration;
+//! use transaction::pool::ConnectionProvider;
+//!
+//! // a middleware counting the connections given back broken
+//! struct CountBroken<P> {
+//!     inner: P,
+//!     broken: std::cell::Cell<usize>,
+//! }
+//!
+//! impl<P: ConnectionProvider> ConnectionProvider for CountBroken<P> {
+//!     type Connection = P::Connection;
+//!     type Error = P::Error;
+//!     fn acquire(&self, timeout: Option<Duration>) -> Result<P::Connection, P::Error> {
+//!         self.inner.acquire(timeout)
+//!     }
+//!     fn check_health(&self, conn: &mut P::Connection) -> bool {
+//!         self.inner.check_health(conn)
+//!     }
+//!     fn release(&self, conn: P::Connection, broken: bool) {
+//!         self.broken.set(self.broken.get() + broken as usize);
+//!         self.inner.release(conn, broken)
+//!     }
+//! }
+//!
+//! // a pool opening a connection for each acquisition
+//! struct Connect;
+//!
+//! impl ConnectionProvider for Connect {
+//!     type Connection = Vec<&'static str>;
+//!     type Error = ();
+//!     fn acquire(&self, _timeout: Option<Duration>) -> Result<Self::Connection, ()> {
+//!         Ok(Vec::new())
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let pool = CountBroken { inner: Connect, broken: Default::default() };
+//! let conn = pool.acquire(Some(Duration::from_secs(1))).unwrap();
+//! pool.release(conn, true);
+//! assert_eq!(pool.broken.get(), 1);
+//! # }
+//! ```
+
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+use crate::async_tx::BoxFuture;
+
+/// How the pooled runners commit the transactions of a batch run on one
+/// connection by their `run_pipelined`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pipelining {
+    /// Run the batch in a single transaction, committed if all the
+    /// transactions succeed. Once one fails, the batch is rolled back and
+    /// the others fail with `Cancelled`.
+    Single,
+    /// Run each transaction of the batch in a transaction of its own,
+    /// committed or rolled back by itself
+    PerItem,
+}
+
+/// A pool lending the connections of a sync driver
+pub trait ConnectionProvider {
+    /// The connection checked out of the pool, which gets back to the pool
+    /// when dropped
+    type Connection;
+    /// The error of the pool, e.g. when no connection is available in time
+    type Error;
+
+    /// Check a connection out of the pool, waiting at most `timeout`, or the
+    /// timeout of the pool if `None`
+    fn acquire(&self, timeout: Option<Duration>) -> Result<Self::Connection, Self::Error>;
+
+    /// Whether the connection checked out is usable, e.g. by a ping. The
+    /// runners give back the unhealthy connections as broken and acquire
+    /// another. Healthy by default, as most pools test the connections
+    /// themselves.
+    fn check_health(&self, _conn: &mut Self::Connection) -> bool {
+        true
+    }
+
+    /// Give the connection back to the pool, or remove it from the pool if
+    /// it is broken. By default the connection is just dropped.
+    fn release(&self, conn: Self::Connection, _broken: bool) {
+        drop(conn)
+    }
+}
+
+impl<P> ConnectionProvider for &P
+where
+    P: ?Sized + ConnectionProvider,
+{
+    type Connection = P::Connection;
+    type Error = P::Error;
+
+    fn acquire(&self, timeout: Option<Duration>) -> Result<Self::Connection, Self::Error> {
+        (**self).acquire(timeout)
+    }
+
+    fn check_health(&self, conn: &mut Self::Connection) -> bool {
+        (**self).check_health(conn)
+    }
+
+    fn release(&self, conn: Self::Connection, broken: bool) {
+        (**self).release(conn, broken)
+    }
+}
+
+/// A pool lending the connections of an async driver. Unlike
+/// `ConnectionProvider`, the acquisition is given no timeout: the runners
+/// time it out on their timers.
+#[cfg(feature = "async")]
+pub trait AsyncConnectionProvider: Send + Sync {
+    /// The connection checked out of the pool, which gets back to the pool
+    /// when dropped
+    type Connection: Send + 'static;
+    /// The error of the pool
+    type Error: Send;
+
+    /// Check a connection out of the pool
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Connection, Self::Error>>;
+
+    /// Whether the connection checked out is usable, e.g. by a ping. The
+    /// runners give back the unhealthy connections as broken and acquire
+    /// another. Healthy by default.
+    fn check_health<'a>(&'a self, _conn: &'a mut Self::Connection) -> BoxFuture<'a, bool> {
+        Box::pin(async { true })
+    }
+
+    /// Give the connection back to the pool, or remove it from the pool if
+    /// it is broken. By default the connection is just dropped.
+    fn release(&self, conn: Self::Connection, _broken: bool) {
+        drop(conn)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<P> AsyncConnectionProvider for &P
+where
+    P: ?Sized + AsyncConnectionProvider,
+{
+    type Connection = P::Connection;
+    type Error = P::Error;
+
+    fn acquire(&self) -> BoxFuture<'_, Result<Self::Connection, Self::Error>> {
+        (**self).acquire()
+    }
+
+    fn check_health<'a>(&'a self, conn: &'a mut Self::Connection) -> BoxFuture<'a, bool> {
+        (**self).check_health(conn)
+    }
+
+    fn release(&self, conn: Self::Connection, broken: bool) {
+        (**self).release(conn, broken)
+    }
+}
+
+// r2d2 tests the connections on check out and drops the broken ones on
+// return by `ManageConnection::has_broken`
+#[cfg(feature = "r2d2")]
+mod r2d2_impl {
+    use std::time::Duration;
+
+    use r2d2::{ManageConnection, Pool, PooledConnection};
+
+    use super::ConnectionProvider;
+
+    impl<M> ConnectionProvider for Pool<M>
+    where
+        M: ManageConnection,
+    {
+        type Connection = PooledConnection<M>;
+        type Error = r2d2::Error;
+
+        fn acquire(&self, timeout: Option<Duration>) -> Result<Self::Connection, Self::Error> {
+            match timeout {
+                Some(timeout) => self.get_timeout(timeout),
+                None => self.get(),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "deadpool")]
+mod deadpool_impl {
+    use deadpool::managed::{Manager, Object, Pool, PoolError};
+    use futures::future::FutureExt;
+
+    use super::AsyncConnectionProvider;
+    use crate::async_tx::BoxFuture;
+
+    impl<M> AsyncConnectionProvider for Pool<M>
+    where
+        M: Manager + 'static,
+    {
+        type Connection = Object<M>;
+        type Error = PoolError<M::Error>;
+
+        fn acquire(&self) -> BoxFuture<'_, Result<Self::Connection, Self::Error>> {
+            Pool::get(self).boxed()
+        }
+
+        fn release(&self, conn: Self::Connection, broken: bool) {
+            if broken {
+                drop(Object::take(conn))
+            }
+        }
+    }
+}
+
+// bb8 can't detach a connection; a broken one is dropped by the pool when
+// `ManageConnection::has_broken` says so
+#[cfg(feature = "bb8")]
+mod bb8_impl {
+    use bb8::{ManageConnection, Pool, PooledConnection, RunError};
+    use futures::future::FutureExt;
+
+    use super::AsyncConnectionProvider;
+    use crate::async_tx::BoxFuture;
+
+    impl<M> AsyncConnectionProvider for Pool<M>
+    where
+        M: ManageConnection,
+    {
+        type Connection = PooledConnection<'static, M>;
+        type Error = RunError<M::Error>;
+
+        fn acquire(&self) -> BoxFuture<'_, Result<Self::Connection, Self::Error>> {
+            self.get_owned().boxed()
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::marker::PhantomData;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Run the transaction on the left context of a pair of contexts.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// struct Users(Vec<&'static str>);
+/// struct Logs(Vec<String>);
+///
+/// # fn main() {
+/// let add_user = with_ctx(|users: &mut Users| -> Result<(), ()> {
+///     users.0.push("keen");
+///     Ok(())
+/// });
+/// let add_log = with_ctx(|logs: &mut Logs| -> Result<(), ()> {
+///     logs.0.push("added keen".to_string());
+///     Ok(())
+/// });
+/// let tx = add_user.in_left().join(add_log.in_right());
+///
+/// let mut ctx = (Users(vec![]), Logs(vec![]));
+/// tx.run(&mut ctx).unwrap();
+/// assert_eq!((ctx.0).0, vec!["keen"]);
+/// assert_eq!((ctx.1).0, vec!["added keen".to_string()]);
+/// # }
+/// ```
+pub fn in_left<Ctx, A, R>(a: A) -> InLeft<A::Tx, R>
+where
+    A: IntoTransaction<Ctx>,
+{
+    InLeft {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+/// Run the transaction on the right context of a pair of contexts.
+pub fn in_right<Ctx, L, A>(a: A) -> InRight<L, A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    InRight {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `in_left`
+#[derive(Debug)]
+#[must_use]
+pub struct InLeft<Tx, R> {
+    tx: Tx,
+    _phantom: PhantomData<R>,
+}
+
+/// The result of `in_right`
+#[derive(Debug)]
+#[must_use]
+pub struct InRight<L, Tx> {
+    tx: Tx,
+    _phantom: PhantomData<L>,
+}
+
+impl<Tx, R> Transaction for InLeft<Tx, R>
+where
+    Tx: Transaction,
+{
+    type Ctx = (Tx::Ctx, R);
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.tx.run(&mut ctx.0)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<L, Tx> Transaction for InRight<L, Tx>
+where
+    Tx: Transaction,
+{
+    type Ctx = (L, Tx::Ctx);
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.tx.run(&mut ctx.1)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, R> Visit for InLeft<Tx, R>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("in_left"), |v| self.tx.accept(v));
+    }
+}
+
+impl<L, Tx> Visit for InRight<L, Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("in_right"), |v| self.tx.accept(v));
+    }
+}
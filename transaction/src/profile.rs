@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+thread_local!(static ACTIVE: RefCell<Vec<Profiler>> = const { RefCell::new(Vec::new()) });
+
+/// Run the transaction recording the run counts and durations of its labeled
+/// sub-transactions into the profiler.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::Profiler;
+///
+/// # fn main() {
+/// let profiler = Profiler::new();
+/// let tx = repeat(3, |i| ok::<(), usize, ()>(i).named("step"))
+///     .named("all")
+///     .profiled(&profiler);
+/// tx.run(&mut ()).unwrap();
+/// assert_eq!(profiler.stats("all").unwrap().runs, 1);
+/// assert_eq!(profiler.stats("step").unwrap().runs, 3);
+/// # }
+/// ```
+pub fn profiled<Ctx, A>(a: A, profiler: &Profiler) -> Profiled<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    Profiled {
+        tx: a.into_transaction(),
+        profiler: profiler.clone(),
+    }
+}
+
+/// The result of `profiled`
+#[derive(Debug)]
+#[must_use]
+pub struct Profiled<Tx> {
+    tx: Tx,
+    profiler: Profiler,
+}
+
+impl<Tx> Transaction for Profiled<Tx>
+where
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ACTIVE.with(|active| active.borrow_mut().push(self.profiler.clone()));
+        let _guard = PopGuard;
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Profiled<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("profiled"), |v| self.tx.accept(v));
+    }
+}
+
+// pops the profiler even if the transaction panics
+struct PopGuard;
+
+impl Drop for PopGuard {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| active.borrow_mut().pop());
+    }
+}
+
+/// Run counts and durations of a labeled transaction.
+/// Durations include the time spent in the nested transactions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeStats {
+    /// How many times the transaction ran
+    pub runs: u64,
+    /// How many of the runs returned an error
+    pub errors: u64,
+    /// The sum of the durations of all the runs
+    pub total: Duration,
+    /// The longest duration of the runs
+    pub max: Duration,
+}
+
+/// Collector of the statistics of labeled transactions.
+/// Clones share the same statistics.
+///
+/// Only the runs on the thread running the `profiled` transaction are
+/// recorded.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    stats: Arc<Mutex<HashMap<String, NodeStats>>>,
+}
+
+impl Profiler {
+    /// make an empty profiler
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// The statistics of the transactions labeled `label`
+    pub fn stats(&self, label: &str) -> Option<NodeStats> {
+        self.lock().get(label).cloned()
+    }
+
+    /// The statistics of all the labeled transactions, sorted by label
+    pub fn report(&self) -> Vec<(String, NodeStats)> {
+        let mut report = self.lock()
+            .iter()
+            .map(|(label, stats)| (label.clone(), *stats))
+            .collect::<Vec<_>>();
+        report.sort_by(|a, b| a.0.cmp(&b.0));
+        report
+    }
+
+    /// Forget all the recorded statistics
+    pub fn reset(&self) {
+        self.lock().clear()
+    }
+
+    fn record(&self, label: &str, elapsed: Duration, ok: bool) {
+        let mut stats = self.lock();
+        let stats = stats.entry(label.to_string()).or_default();
+        stats.runs += 1;
+        if !ok {
+            stats.errors += 1;
+        }
+        stats.total += elapsed;
+        if stats.max < elapsed {
+            stats.max = elapsed;
+        }
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, HashMap<String, NodeStats>> {
+        // the map is always left consistent, so poisoning can be ignored
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether any profiler is recording on this thread
+pub(crate) fn is_active() -> bool {
+    ACTIVE.with(|active| !active.borrow().is_empty())
+}
+
+/// Record a run of a labeled transaction to the profilers active on this thread
+pub(crate) fn record(label: &str, elapsed: Duration, ok: bool) {
+    ACTIVE.with(|active| for profiler in active.borrow().iter() {
+        profiler.record(label, elapsed, ok);
+    })
+}
@@ -0,0 +1,118 @@
+//! Progress of long-running transactions, e.g. migrations, reported to the
+//! subscribers of the runner running them.
+//!
+//! The leaves report their progress by `report`, and so do `join_all`,
+//! after each of its transactions, and `runner::ChunkedRunner`, after each
+//! item. The reports go to the subscribers of the runners running on the
+//! thread, given by `runner::RunnerBuilder::progress`, or by
+//! `with_subscriber` for any runner, so the closures don't need a channel
+//! of their own. Without a subscriber, `report` does nothing.
+//!
+//! A transaction run again, e.g. retried, reports its progress from the
+//! start again.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use std::sync::{Arc, Mutex};
+//! use transaction::prelude::*;
+//! use transaction::progress::{self, Progress};
+//!
+//! # fn main() {
+//! let reports = Arc::new(Mutex::new(Vec::new()));
+//! let log = reports.clone();
+//! let subscriber: Arc<dyn Progress> = Arc::new(move |step: &str, done: usize, total: Option<usize>| {
+//!     log.lock().unwrap().push(format!("{} {}/{:?}", step, done, total));
+//! });
+//!
+//! let migrate = |id: u32| {
+//!     with_ctx(move |rows: &mut Vec<u32>| {
+//!         rows.push(id);
+//!         Ok::<_, ()>(())
+//!     })
+//! };
+//! let tx = join_all((1..=3).map(migrate)).and_then(|_| {
+//!     with_ctx(|rows: &mut Vec<u32>| {
+//!         progress::report("reindex", 0, None);
+//!         rows.sort();
+//!         Ok(())
+//!     })
+//! });
+//!
+//! let mut rows = vec![];
+//! progress::with_subscriber(subscriber, || tx.run(&mut rows)).unwrap();
+//! assert_eq!(
+//!     *reports.lock().unwrap(),
+//!     vec!["join_all 1/Some(3)", "join_all 2/Some(3)", "join_all 3/Some(3)", "reindex 0/None"]
+//! );
+//! // no subscriber out of `with_subscriber`
+//! progress::report("reindex", 1, None);
+//! assert_eq!(reports.lock().unwrap().len(), 4);
+//! # }
+//! ```
+
+use std::cell::RefCell;
+use std::mem;
+use std::sync::Arc;
+
+// the subscribers of the runners running on the thread, outermost first
+thread_local!(static SUBSCRIBERS: RefCell<Vec<Arc<dyn Progress>>> = const { RefCell::new(Vec::new()) });
+
+/// Subscriber to the progress of the transactions
+pub trait Progress: Send + Sync {
+    /// Called with the name of the step, e.g. `join_all` or the name given
+    /// by a leaf, the units of work done, and the total if known
+    fn report(&self, step: &str, done: usize, total: Option<usize>);
+}
+
+impl<F> Progress for F
+where
+    F: Fn(&str, usize, Option<usize>) + Send + Sync,
+{
+    fn report(&self, step: &str, done: usize, total: Option<usize>) {
+        self(step, done, total)
+    }
+}
+
+/// Report the progress of the step to the subscribers of the runners
+/// running on the thread, outermost first.
+/// This is called by the leaves and the combinators rather than runners.
+pub fn report(step: &str, done: usize, total: Option<usize>) {
+    // the subscribers may run transactions too
+    let subscribers = SUBSCRIBERS.with(|subscribers| subscribers.borrow().clone());
+    for subscriber in &subscribers {
+        subscriber.report(step, done, total);
+    }
+}
+
+/// Run `f`, e.g. a runner, with the subscriber receiving the progress
+/// reported on the thread meanwhile.
+pub fn with_subscriber<F, R>(subscriber: Arc<dyn Progress>, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    SUBSCRIBERS.with(|subscribers| subscribers.borrow_mut().push(subscriber));
+    let _guard = PopGuard;
+    f()
+}
+
+// the subscribers on the thread, outermost first
+pub(crate) fn current() -> Vec<Arc<dyn Progress>> {
+    SUBSCRIBERS.with(|subscribers| subscribers.borrow().clone())
+}
+
+// replace the subscribers on the thread, returning those replaced
+pub(crate) fn replace(subscribers: Vec<Arc<dyn Progress>>) -> Vec<Arc<dyn Progress>> {
+    SUBSCRIBERS.with(|current| mem::replace(&mut *current.borrow_mut(), subscribers))
+}
+
+// pops the subscriber even if `f` panics
+struct PopGuard;
+
+impl Drop for PopGuard {
+    fn drop(&mut self) {
+        SUBSCRIBERS.with(|subscribers| subscribers.borrow_mut().pop());
+    }
+}
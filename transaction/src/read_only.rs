@@ -0,0 +1,176 @@
+use std::marker::PhantomData;
+
+use crate::{
+    Abort, AndThen, Branch, Branch3, Branch4, IntoTransaction, Join, Join3, Join4, JoinAll, Lazy, LoopFn, Map, MapErr,
+    Named, OrElse, Recover, Repeat, Retry, Then, Transaction, TryAbort, TryRecover, TxErr, TxOk, TxResult, Zoom,
+};
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// Transactions known at compile time not to write, so that the runners
+/// can run them on replicas or in read-only snapshots, e.g.
+/// `runner::ReplicatedRunner::read`.
+///
+/// The read leaves, e.g. `read_ctx` and the queries of the backend crates,
+/// implement it, and the combinators do when all the transactions they are
+/// made of do. A write leaf, e.g. `with_ctx`, in a transaction passed where
+/// `ReadOnly` is required is a compile error. The transactions made at run
+/// time, e.g. by the function given to `and_then`, are checked by their
+/// types too.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{read_ctx, ReadOnly};
+///
+/// fn on_replica<Tx: ReadOnly<Ctx = Vec<i32>>>(tx: Tx, replica: &mut Vec<i32>) -> Result<Tx::Item, Tx::Err> {
+///     tx.run(replica)
+/// }
+///
+/// # fn main() {
+/// let len = read_ctx(|v: &Vec<i32>| Ok::<_, ()>(v.len()));
+/// let sum = read_ctx(|v: &Vec<i32>| Ok(v.iter().sum::<i32>()));
+/// let mean = len.join(sum).map(|(len, sum)| sum / len as i32);
+/// assert_eq!(on_replica(mean, &mut vec![1, 2, 3]), Ok(2));
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{read_ctx, ReadOnly};
+///
+/// fn on_replica<Tx: ReadOnly<Ctx = Vec<i32>>>(tx: Tx, replica: &mut Vec<i32>) -> Result<Tx::Item, Tx::Err> {
+///     tx.run(replica)
+/// }
+///
+/// # fn main() {
+/// let len = read_ctx(|v: &Vec<i32>| Ok::<_, ()>(v.len()));
+/// let push = with_ctx(|v: &mut Vec<i32>| Ok(v.push(4)));
+/// on_replica(len.join(push), &mut vec![1, 2, 3]);
+/// # }
+/// ```
+pub trait ReadOnly: Transaction {}
+
+/// Receive the context by a shared reference and perform computation. Unlike
+/// `with_ctx`, it is `ReadOnly`.
+pub fn read_ctx<Ctx, F, T, E>(f: F) -> ReadCtx<Ctx, F>
+where
+    F: Fn(&Ctx) -> Result<T, E>,
+{
+    ReadCtx {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `read_ctx`
+#[derive(Debug)]
+#[must_use]
+pub struct ReadCtx<Ctx, F> {
+    f: F,
+    _phantom: PhantomData<Ctx>,
+}
+
+impl<Ctx, T, E, F> Transaction for ReadCtx<Ctx, F>
+where
+    F: Fn(&Ctx) -> Result<T, E>,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        (self.f)(ctx)
+    }
+}
+
+impl<Ctx, F> Visit for ReadCtx<Ctx, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("read_ctx"));
+    }
+}
+
+impl<Ctx, T, E, F> ReadOnly for ReadCtx<Ctx, F> where F: Fn(&Ctx) -> Result<T, E> {}
+
+// the leaves not touching the context
+impl<Ctx, T, E> ReadOnly for TxOk<Ctx, T, E> where Self: Transaction {}
+impl<Ctx, T, E> ReadOnly for TxErr<Ctx, T, E> where Self: Transaction {}
+impl<Ctx, T, E> ReadOnly for TxResult<Ctx, T, E> where Self: Transaction {}
+impl<Ctx, T, E, F> ReadOnly for Lazy<Ctx, F> where F: Fn() -> Result<T, E> {}
+
+// the combinators of read-only transactions
+impl<T> ReadOnly for Box<T> where T: ReadOnly + ?Sized {}
+impl<T> ReadOnly for &T where T: ReadOnly + ?Sized {}
+impl<Tx> ReadOnly for Named<Tx> where Tx: ReadOnly {}
+impl<Tx, F> ReadOnly for Map<Tx, F> where Self: Transaction, Tx: ReadOnly {}
+impl<Tx, F> ReadOnly for MapErr<Tx, F> where Self: Transaction, Tx: ReadOnly {}
+impl<Tx1, F, Tx2> ReadOnly for AndThen<Tx1, F, Tx2> where Self: Transaction, Tx1: ReadOnly, Tx2: ReadOnly {}
+impl<Tx1, F, Tx2> ReadOnly for OrElse<Tx1, F, Tx2> where Self: Transaction, Tx1: ReadOnly, Tx2: ReadOnly {}
+impl<Tx1, F, Tx2> ReadOnly for Then<Tx1, F, Tx2> where Self: Transaction, Tx1: ReadOnly, Tx2: ReadOnly {}
+impl<Tx, T, F> ReadOnly for Recover<Tx, T, F> where Self: Transaction, Tx: ReadOnly {}
+impl<Tx, F, B> ReadOnly for TryRecover<Tx, F, B> where Self: Transaction, Tx: ReadOnly {}
+impl<Tx, T, F> ReadOnly for Abort<Tx, T, F> where Self: Transaction, Tx: ReadOnly {}
+impl<Tx, F, B> ReadOnly for TryAbort<Tx, F, B> where Self: Transaction, Tx: ReadOnly {}
+impl<Tx1, Tx2> ReadOnly for Join<Tx1, Tx2> where Self: Transaction, Tx1: ReadOnly, Tx2: ReadOnly {}
+impl<Tx1, Tx2, Tx3> ReadOnly for Join3<Tx1, Tx2, Tx3>
+where
+    Self: Transaction,
+    Tx1: ReadOnly,
+    Tx2: ReadOnly,
+    Tx3: ReadOnly,
+{
+}
+impl<Tx1, Tx2, Tx3, Tx4> ReadOnly for Join4<Tx1, Tx2, Tx3, Tx4>
+where
+    Self: Transaction,
+    Tx1: ReadOnly,
+    Tx2: ReadOnly,
+    Tx3: ReadOnly,
+    Tx4: ReadOnly,
+{
+}
+impl<Tx> ReadOnly for JoinAll<Tx> where Self: Transaction, Tx: ReadOnly {}
+impl<Tx1, Tx2> ReadOnly for Branch<Tx1, Tx2> where Self: Transaction, Tx1: ReadOnly, Tx2: ReadOnly {}
+impl<Tx1, Tx2, Tx3> ReadOnly for Branch3<Tx1, Tx2, Tx3>
+where
+    Self: Transaction,
+    Tx1: ReadOnly,
+    Tx2: ReadOnly,
+    Tx3: ReadOnly,
+{
+}
+impl<Tx1, Tx2, Tx3, Tx4> ReadOnly for Branch4<Tx1, Tx2, Tx3, Tx4>
+where
+    Self: Transaction,
+    Tx1: ReadOnly,
+    Tx2: ReadOnly,
+    Tx3: ReadOnly,
+    Tx4: ReadOnly,
+{
+}
+impl<Big, Tx, F> ReadOnly for Zoom<Big, Tx, F> where Self: Transaction, Tx: ReadOnly {}
+impl<Ctx, F, Tx> ReadOnly for Retry<Ctx, F, Tx>
+where
+    Self: Transaction,
+    Tx: IntoTransaction<Ctx>,
+    Tx::Tx: ReadOnly,
+{
+}
+impl<Ctx, F, Tx> ReadOnly for Repeat<Ctx, F, Tx>
+where
+    Self: Transaction,
+    Tx: IntoTransaction<Ctx>,
+    Tx::Tx: ReadOnly,
+{
+}
+impl<Ctx, F, A> ReadOnly for LoopFn<Ctx, F, A>
+where
+    Self: Transaction,
+    A: IntoTransaction<Ctx>,
+    A::Tx: ReadOnly,
+{
+}
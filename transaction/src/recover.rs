@@ -1,11 +1,14 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
-pub fn recover<Ctx, A, T, F>(a: A, f: F) -> Recover<A::Tx, T, F>
+/// Recover from the error of the transaction by the value the closure
+/// makes of it.
+pub fn recover<Ctx, A, F>(a: A, f: F) -> Recover<A::Tx, A::Item, F>
 where
     A: IntoTransaction<Ctx>,
-    F: Fn(A::Item) -> A::Err,
+    F: Fn(A::Err) -> A::Item,
 {
     Recover {
         tx: a.into_transaction(),
@@ -40,3 +43,12 @@ where
         }
     }
 }
+
+impl<Tx, T, F> Visit for Recover<Tx, T, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("recover"), |v| self.tx.accept(v));
+    }
+}
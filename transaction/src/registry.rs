@@ -0,0 +1,223 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_leaf, visit_node, Node, Visit, Visitor};
+
+/// A context holding resources of any types, at most one per type.
+///
+/// Transactions get resources with `with_resource`, so new resources can be
+/// added to the context without changing the type of existing transactions.
+/// The existence of resources is checked at run time. If it should be checked
+/// at compile time, use the context lists (`hlist!`) instead.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{with_resource, MissingResource, Registry};
+///
+/// struct Db(Vec<String>);
+///
+/// #[derive(Debug)]
+/// enum Error {
+///     Missing(MissingResource),
+/// }
+///
+/// impl From<MissingResource> for Error {
+///     fn from(e: MissingResource) -> Self {
+///         Error::Missing(e)
+///     }
+/// }
+///
+/// # fn main() {
+/// let tx = with_resource(|db: &mut Db| -> Result<usize, Error> {
+///     db.0.push("keen".to_string());
+///     Ok(db.0.len())
+/// });
+///
+/// let mut registry = Registry::new();
+/// assert!(tx.run(&mut registry).is_err());
+/// registry.insert(Db(vec![]));
+/// assert_eq!(tx.run(&mut registry).unwrap(), 1);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Registry {
+    resources: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Registry {
+    /// make an empty registry
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Add a resource, returning the previous one of the same type
+    pub fn insert<T: Any>(&mut self, resource: T) -> Option<T> {
+        self.resources
+            .insert(TypeId::of::<T>(), Box::new(resource))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    /// Remove the resource of the type
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    /// Whether the registry has a resource of the type
+    pub fn contains<T: Any>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    /// borrow the resource of the type
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .and_then(|r| r.downcast_ref())
+    }
+
+    /// mutably borrow the resource of the type
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.resources
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|r| r.downcast_mut())
+    }
+
+    /// borrow the resource of the type or report which one is missing
+    pub fn resource<T: Any>(&mut self) -> Result<&mut T, MissingResource> {
+        self.get_mut().ok_or_else(MissingResource::of::<T>)
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("len", &self.resources.len())
+            .finish()
+    }
+}
+
+/// The error of a required resource not being in the registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MissingResource {
+    /// The name of the type of the missing resource
+    pub type_name: &'static str,
+}
+
+impl MissingResource {
+    fn of<T>() -> Self {
+        MissingResource { type_name: ::std::any::type_name::<T>() }
+    }
+}
+
+impl fmt::Display for MissingResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "resource `{}` is not registered", self.type_name)
+    }
+}
+
+impl Error for MissingResource {}
+
+/// Receive the resource of the type from the registry and perform
+/// computation. Fails with `MissingResource` if there is no such resource.
+pub fn with_resource<R, F, T, E>(f: F) -> WithResource<R, F>
+where
+    R: Any,
+    F: Fn(&mut R) -> Result<T, E>,
+    E: From<MissingResource>,
+{
+    WithResource {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_resource`
+#[derive(Debug)]
+#[must_use]
+pub struct WithResource<R, F> {
+    f: F,
+    _phantom: PhantomData<R>,
+}
+
+impl<R, F, T, E> Transaction for WithResource<R, F>
+where
+    R: Any,
+    F: Fn(&mut R) -> Result<T, E>,
+    E: From<MissingResource>,
+{
+    type Ctx = Registry;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        (self.f)(ctx.resource()?)
+    }
+}
+
+impl<R, F> Visit for WithResource<R, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_resource"));
+    }
+}
+
+/// Check that the registry has the resource of the type before running any
+/// step of the transaction, so it doesn't fail half way.
+pub fn require<R, A>(a: A) -> Require<R, A::Tx>
+where
+    R: Any,
+    A: IntoTransaction<Registry>,
+    A::Err: From<MissingResource>,
+{
+    Require {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `require`
+#[derive(Debug)]
+#[must_use]
+pub struct Require<R, Tx> {
+    tx: Tx,
+    _phantom: PhantomData<R>,
+}
+
+impl<R, Tx> Transaction for Require<R, Tx>
+where
+    R: Any,
+    Tx: Transaction<Ctx = Registry>,
+    Tx::Err: From<MissingResource>,
+{
+    type Ctx = Registry;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.resource::<R>()?;
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<R, Tx> Visit for Require<R, Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("require"), |v| self.tx.accept(v));
+    }
+}
@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
 
 pub fn repeat<Ctx, F, Tx>(n: usize, f: F) -> Repeat<Ctx, F, Tx>
 where
@@ -41,3 +42,9 @@ where
         Ok(ret)
     }
 }
+
+impl<Ctx, F, Tx> Visit for Repeat<Ctx, F, Tx> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("repeat"));
+    }
+}
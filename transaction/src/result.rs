@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use crate::Transaction;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
 
 /// The result of `result`
 #[derive(Debug)]
@@ -30,3 +31,9 @@ where
         self.r.clone()
     }
 }
+
+impl<Ctx, T, E> Visit for TxResult<Ctx, T, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("result"));
+    }
+}
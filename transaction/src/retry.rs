@@ -1,6 +1,8 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::metrics;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
 
 
 
@@ -37,12 +39,24 @@ where
         let Retry { ref n, ref f, .. } = *self;
         let mut ret = Vec::new();
         for i in 0..*n {
-            let t = match f(i).into_transaction().run(ctx) {
+            let tx = f(i).into_transaction();
+            if i != 0 {
+                metrics::record_retry(tx.label());
+            }
+            let t = match tx.run(ctx) {
                 Ok(t) => return Ok(t),
                 Err(e) => e,
             };
+            #[cfg(feature = "log")]
+            log::debug!("attempt {} of {} failed", i + 1, n);
             ret.push(t);
         }
         Err(ret)
     }
 }
+
+impl<Ctx, F, Tx> Visit for Retry<Ctx, F, Tx> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("retry"));
+    }
+}
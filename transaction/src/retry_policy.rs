@@ -0,0 +1,112 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// When and how long to wait before running a failed transaction again.
+pub trait RetryPolicy {
+    /// The delay before the next attempt after `retries` retries have been
+    /// made, or `None` to give up.
+    fn next_delay(&self, retries: usize) -> Option<Duration>;
+}
+
+impl<P> RetryPolicy for &P
+where
+    P: ?Sized + RetryPolicy,
+{
+    fn next_delay(&self, retries: usize) -> Option<Duration> {
+        (**self).next_delay(retries)
+    }
+}
+
+/// A `RetryPolicy` making a bounded number of retries with exponentially
+/// growing delays.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use transaction::{Backoff, RetryPolicy};
+///
+/// let policy = Backoff::exponential(Duration::from_millis(10))
+///     .max_retries(3)
+///     .max_delay(Duration::from_millis(25));
+/// assert_eq!(policy.next_delay(0), Some(Duration::from_millis(10)));
+/// assert_eq!(policy.next_delay(1), Some(Duration::from_millis(20)));
+/// assert_eq!(policy.next_delay(2), Some(Duration::from_millis(25)));
+/// assert_eq!(policy.next_delay(3), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    max_retries: usize,
+    initial: Duration,
+    multiplier: u32,
+    max_delay: Duration,
+}
+
+impl Backoff {
+    /// Wait `delay` before each retry. 3 retries are made by default.
+    pub fn fixed(delay: Duration) -> Self {
+        Backoff {
+            max_retries: 3,
+            initial: delay,
+            multiplier: 1,
+            max_delay: Duration::MAX,
+        }
+    }
+
+    /// Wait `initial` before the first retry and double the delay for each
+    /// retry. 3 retries are made by default.
+    pub fn exponential(initial: Duration) -> Self {
+        Backoff {
+            multiplier: 2,
+            ..Backoff::fixed(initial)
+        }
+    }
+
+    /// Retry immediately. 3 retries are made by default.
+    pub fn immediate() -> Self {
+        Backoff::fixed(Duration::from_secs(0))
+    }
+
+    /// Give up after `max_retries` retries
+    pub fn max_retries(self, max_retries: usize) -> Self {
+        Backoff { max_retries, ..self }
+    }
+
+    /// Cap the delays at `max_delay`
+    pub fn max_delay(self, max_delay: Duration) -> Self {
+        Backoff { max_delay, ..self }
+    }
+}
+
+impl RetryPolicy for Backoff {
+    fn next_delay(&self, retries: usize) -> Option<Duration> {
+        if self.max_retries <= retries {
+            return None;
+        }
+        let delay = u32::try_from(retries)
+            .ok()
+            .and_then(|retries| self.multiplier.checked_pow(retries))
+            .and_then(|factor| self.initial.checked_mul(factor))
+            .unwrap_or(self.max_delay);
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// The classification of errors which may go away by running the whole
+/// transaction again, e.g. serialization failures, deadlocks or lock
+/// timeouts. Backends implement it for their errors so that the retries can
+/// be limited to such errors.
+pub trait Retryable {
+    /// Whether the transaction failed with this error may succeed when run
+    /// again
+    fn is_retryable(&self) -> bool;
+}
+
+impl<E> Retryable for Box<E>
+where
+    E: ?Sized + Retryable,
+{
+    fn is_retryable(&self) -> bool {
+        (**self).is_retryable()
+    }
+}
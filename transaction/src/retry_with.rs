@@ -0,0 +1,83 @@
+use crate::{IntoTransaction, Transaction};
+use crate::{clock, metrics};
+use crate::retry_policy::RetryPolicy;
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Run the transaction again while it fails and the policy allows, sleeping
+/// the thread for the delays given by the policy. The error of the last
+/// attempt is returned when the policy gives up.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use transaction::prelude::*;
+/// use transaction::Backoff;
+///
+/// let flaky = with_ctx(|attempts: &mut u32| {
+///     *attempts += 1;
+///     if *attempts < 3 { Err("busy") } else { Ok(*attempts) }
+/// });
+/// let tx = flaky.retry_with(Backoff::fixed(Duration::from_millis(1)));
+/// assert_eq!(tx.run(&mut 0), Ok(3));
+/// ```
+pub fn retry_with<Ctx, A, P>(a: A, policy: P) -> RetryWith<A::Tx, P>
+where
+    A: IntoTransaction<Ctx>,
+    P: RetryPolicy,
+{
+    RetryWith {
+        tx: a.into_transaction(),
+        policy,
+    }
+}
+
+/// The result of `retry_with`
+#[derive(Debug)]
+#[must_use]
+pub struct RetryWith<Tx, P> {
+    tx: Tx,
+    policy: P,
+}
+
+impl<Tx, P> Transaction for RetryWith<Tx, P>
+where
+    Tx: Transaction,
+    P: RetryPolicy,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let mut retries = 0;
+        loop {
+            let e = match self.tx.run(ctx) {
+                Ok(t) => return Ok(t),
+                Err(e) => e,
+            };
+            let delay = match self.policy.next_delay(retries) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            #[cfg(feature = "log")]
+            log::debug!("attempt {} failed, retrying in {:?}", retries + 1, delay);
+            clock::sleep(delay);
+            metrics::record_retry(self.tx.label());
+            retries += 1;
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx, P> Visit for RetryWith<Tx, P>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("retry_with"), |v| self.tx.accept(v));
+    }
+}
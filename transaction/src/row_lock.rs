@@ -0,0 +1,184 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::retry_policy::Retryable;
+use crate::visit::{visit_node, Node, Visit, Visitor};
+use crate::{IntoTransaction, Transaction};
+
+/// Contexts locking keys of type `K` pessimistically until the end of the
+/// transaction, e.g. rows by `SELECT ... FOR UPDATE` or advisory locks.
+pub trait HasLocking<K> {
+    /// The error of the backend
+    type Error;
+
+    /// Lock the keys in their order, waiting while other transactions hold
+    /// them
+    fn lock(&mut self, keys: &[K]) -> Result<(), Self::Error>;
+
+    /// Whether the error is from the contention for the locks, e.g. a lock
+    /// timeout or a deadlock, which may go away by running the transaction
+    /// again
+    fn is_contention(error: &Self::Error) -> bool;
+}
+
+/// The error of `with_row_lock`
+#[derive(Debug)]
+pub enum RowLockError<E> {
+    /// The locks were contended, e.g. timed out or deadlocked
+    Contended(E),
+    /// The backend failed otherwise
+    Failed(E),
+}
+
+impl<E> RowLockError<E> {
+    /// The error of the backend
+    pub fn into_inner(self) -> E {
+        match self {
+            RowLockError::Contended(e) | RowLockError::Failed(e) => e,
+        }
+    }
+}
+
+impl<E> fmt::Display for RowLockError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RowLockError::Contended(ref e) => write!(f, "locks are contended: {}", e),
+            RowLockError::Failed(ref e) => write!(f, "failed to lock: {}", e),
+        }
+    }
+}
+
+impl<E> Error for RowLockError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RowLockError::Contended(ref e) | RowLockError::Failed(ref e) => Some(e),
+        }
+    }
+}
+
+impl<E> Retryable for RowLockError<E> {
+    fn is_retryable(&self) -> bool {
+        match *self {
+            RowLockError::Contended(_) => true,
+            RowLockError::Failed(_) => false,
+        }
+    }
+}
+
+/// Lock the keys before running the transaction, holding them until it
+/// ends. The keys are sorted and deduplicated, so that transactions locking
+/// overlapping keys lock them in the same order and don't deadlock on each
+/// other.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::BTreeSet;
+/// use transaction::prelude::*;
+/// use transaction::{with_row_lock, HasLocking, Retryable, RowLockError};
+///
+/// struct Accounts {
+///     // the rows locked by other transactions
+///     held: BTreeSet<u32>,
+///     locked: Vec<u32>,
+/// }
+///
+/// impl HasLocking<u32> for Accounts {
+///     type Error = String;
+///
+///     fn lock(&mut self, keys: &[u32]) -> Result<(), String> {
+///         for key in keys {
+///             if self.held.contains(key) {
+///                 return Err(format!("lock timeout on {}", key));
+///             }
+///             self.locked.push(*key);
+///         }
+///         Ok(())
+///     }
+///
+///     fn is_contention(error: &String) -> bool {
+///         error.starts_with("lock timeout")
+///     }
+/// }
+///
+/// let transfer = with_row_lock(vec![2, 1], with_ctx(|a: &mut Accounts| -> Result<_, RowLockError<String>> {
+///     Ok(a.locked.clone())
+/// }));
+///
+/// let mut accounts = Accounts { held: BTreeSet::new(), locked: vec![] };
+/// assert_eq!(transfer.run(&mut accounts).unwrap(), vec![1, 2]);
+///
+/// let mut accounts = Accounts { held: vec![2].into_iter().collect(), locked: vec![] };
+/// assert!(transfer.run(&mut accounts).unwrap_err().is_retryable());
+/// ```
+pub fn with_row_lock<Ctx, K, A>(keys: impl IntoIterator<Item = K>, a: A) -> WithRowLock<K, A::Tx>
+where
+    Ctx: HasLocking<K>,
+    K: Ord,
+    A: IntoTransaction<Ctx>,
+    A::Err: From<RowLockError<Ctx::Error>>,
+{
+    let mut keys = keys.into_iter().collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    WithRowLock {
+        keys,
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `with_row_lock`
+#[derive(Debug)]
+#[must_use]
+pub struct WithRowLock<K, Tx> {
+    keys: Vec<K>,
+    tx: Tx,
+}
+
+impl<K, Tx> WithRowLock<K, Tx> {
+    /// The keys to lock, in the order they are locked
+    pub fn keys(&self) -> &[K] {
+        &self.keys
+    }
+}
+
+impl<K, Tx> Transaction for WithRowLock<K, Tx>
+where
+    Tx: Transaction,
+    Tx::Ctx: HasLocking<K>,
+    Tx::Err: From<RowLockError<<Tx::Ctx as HasLocking<K>>::Error>>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.lock(&self.keys).map_err(|e| {
+            if <Tx::Ctx as HasLocking<K>>::is_contention(&e) {
+                RowLockError::Contended(e)
+            } else {
+                RowLockError::Failed(e)
+            }
+        })?;
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<K, Tx> Visit for WithRowLock<K, Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("with_row_lock"), |v| self.tx.accept(v));
+    }
+}
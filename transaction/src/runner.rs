@@ -0,0 +1,1108 @@
+//! Runners assembled from a backend and layers.
+//!
+//! A `Backend` runs a transaction once in a transaction of the backend, e.g.
+//! a store together with its connection. `RunnerBuilder` wraps it in the
+//! layers an application wants for all its transactions, such as retries, a
+//! deadline and metrics, and builds a `Runner` to be shared by the call
+//! sites, so they only pass the transactions.
+//!
+//! `ChunkedRunner` runs batch jobs too large for one transaction on the
+//! backend and the layers, committing every chunk of items. `ShadowRunner`
+//! runs the transactions on a shadow backend too, without committing them,
+//! to compare the results during a migration. `ReplicatedRunner` runs the
+//! `ReadOnly` transactions on a replica. The progress of the transactions
+//! and of the chunks is reported to the subscribers given by
+//! `RunnerBuilder::progress`.
+//!
+//! Each layer wraps the backend and the layers configured before it, so the
+//! first one configured is the closest to the backend. For example, a
+//! deadline configured after the retries bounds all the attempts, and
+//! metrics configured before the retries record every attempt.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use std::cell::Cell;
+//! use std::rc::Rc;
+//! use std::time::Duration;
+//! use transaction::prelude::*;
+//! use transaction::runner::{Backend, DeadlineExceeded, RunOptions, RunnerBuilder};
+//! use transaction::{Backoff, Retryable};
+//!
+//! // a backend running the transactions on a counter
+//! struct Counter(Rc<Cell<i32>>);
+//!
+//! impl<T, E> Backend<T, E> for Counter {
+//!     type Ctx = i32;
+//!
+//!     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+//!     where
+//!         Tx: Transaction<Ctx = i32, Item = T, Err = E> + ?Sized,
+//!     {
+//!         let mut n = self.0.get();
+//!         let t = tx.run(&mut n)?;
+//!         self.0.set(n);
+//!         Ok(t)
+//!     }
+//! }
+//!
+//! #[derive(Debug, PartialEq)]
+//! enum Error {
+//!     Busy,
+//!     TimedOut,
+//! }
+//!
+//! impl Retryable for Error {
+//!     fn is_retryable(&self) -> bool {
+//!         *self == Error::Busy
+//!     }
+//! }
+//!
+//! impl From<DeadlineExceeded> for Error {
+//!     fn from(_: DeadlineExceeded) -> Self {
+//!         Error::TimedOut
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let count = Rc::new(Cell::new(0));
+//! let runner = RunnerBuilder::new(Counter(count.clone()))
+//!     .retry(Backoff::immediate().max_retries(3))
+//!     .deadline(Duration::from_secs(1))
+//!     .build();
+//!
+//! // busy for the first two attempts
+//! let attempts = Cell::new(0);
+//! let incr = with_ctx(|n: &mut i32| {
+//!     attempts.set(attempts.get() + 1);
+//!     if attempts.get() < 3 {
+//!         return Err(Error::Busy);
+//!     }
+//!     *n += 1;
+//!     Ok(*n)
+//! });
+//! assert_eq!(runner.run(&incr), Ok(1));
+//! assert_eq!(attempts.get(), 3);
+//! assert_eq!(count.get(), 1);
+//! # }
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::bulkhead::{Bulkhead, BulkheadFull};
+use crate::clock::{self, Instant};
+use crate::hooks::Outcome;
+use crate::metrics::{self, Metrics};
+use crate::progress::{self, Progress};
+use crate::{
+    idempotent, with_ctx, IdempotencyStore, IntoTransaction, ReadOnly, Retryable, RetryPolicy, RunDeadline,
+    Transaction,
+};
+
+/// The options of a run passed down the layers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunOptions<'a> {
+    /// When the run is to be given up, set by `RunnerBuilder::deadline`
+    pub deadline: Option<Instant>,
+    /// The idempotency key given by `Runner::run_keyed`
+    pub key: Option<&'a str>,
+}
+
+/// Backends running transactions with the items `T` and the errors `E`, and
+/// the layers wrapping them. The backends begin a transaction of the
+/// backend, run the transaction on it and commit it if it succeeds, like
+/// the `run` functions of the backend crates.
+pub trait Backend<T, E> {
+    /// The context of the transactions
+    type Ctx;
+
+    /// Run the transaction once
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized;
+}
+
+/// The error of the runs started past the deadline of `RunnerBuilder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("transaction deadline exceeded")
+    }
+}
+
+impl Error for DeadlineExceeded {}
+
+/// Builder of a `Runner` from a backend and layers
+#[derive(Debug)]
+pub struct RunnerBuilder<B> {
+    backend: B,
+}
+
+impl<B> RunnerBuilder<B> {
+    /// Start from the backend without layers
+    pub fn new(backend: B) -> Self {
+        RunnerBuilder { backend }
+    }
+
+    /// Run the transactions again while they fail with a retryable error
+    /// and the policy allows, but not past the deadline if any. Each retry
+    /// is recorded by `metrics::record_retry`. The errors of the
+    /// transactions are to implement `Retryable`.
+    pub fn retry<P>(self, policy: P) -> RunnerBuilder<RetryLayer<B, P>>
+    where
+        P: RetryPolicy,
+    {
+        RunnerBuilder::new(RetryLayer {
+            inner: self.backend,
+            policy,
+        })
+    }
+
+    /// Give up the runs, failing with `DeadlineExceeded`, when they are not
+    /// done in the duration. The transactions running synchronously can't
+    /// be interrupted, so the deadline is checked before running them and
+    /// bounds the retries. It is entered as the `RunDeadline` of the runs,
+    /// for `remaining_time` and the SQL backends. The errors of the
+    /// transactions are to implement `From<DeadlineExceeded>`.
+    pub fn deadline(self, timeout: Duration) -> RunnerBuilder<DeadlineLayer<B>> {
+        RunnerBuilder::new(DeadlineLayer {
+            inner: self.backend,
+            timeout,
+        })
+    }
+
+    /// Limit the transactions running at once to the permits of the
+    /// bulkhead, see `bulkhead`. The runs waiting for a permit don't wait
+    /// past the deadline, and fail with `BulkheadFull` when they get none,
+    /// so the errors of the transactions are to implement
+    /// `From<BulkheadFull>`. Configure it after the retries, so that the
+    /// retries don't hold a permit while waiting between attempts.
+    pub fn bulkhead(self, bulkhead: Bulkhead) -> RunnerBuilder<BulkheadLayer<B>> {
+        RunnerBuilder::new(BulkheadLayer {
+            inner: self.backend,
+            bulkhead,
+        })
+    }
+
+    /// Run the transactions in a `runner` span with the name of the runner
+    #[cfg(feature = "tracing")]
+    pub fn traced(self, name: &'static str) -> RunnerBuilder<TraceLayer<B>> {
+        RunnerBuilder::new(TraceLayer {
+            inner: self.backend,
+            name,
+        })
+    }
+
+    /// Record the runs to the metrics, in addition to the metrics
+    /// registered by `metrics::register`, e.g. to tell the runners apart
+    pub fn metrics(self, metrics: Arc<dyn Metrics>) -> RunnerBuilder<MetricsLayer<B>> {
+        RunnerBuilder::new(MetricsLayer {
+            inner: self.backend,
+            metrics,
+        })
+    }
+
+    /// Report the progress of the transactions to the subscriber, see
+    /// `progress`
+    pub fn progress(self, subscriber: Arc<dyn Progress>) -> RunnerBuilder<ProgressLayer<B>> {
+        RunnerBuilder::new(ProgressLayer {
+            inner: self.backend,
+            subscriber,
+        })
+    }
+
+    /// Run the transactions given an idempotency key by `Runner::run_keyed`
+    /// once per key, see `idempotent`. The contexts are to implement
+    /// `IdempotencyStore`.
+    pub fn idempotent(self) -> RunnerBuilder<IdempotencyLayer<B>> {
+        RunnerBuilder::new(IdempotencyLayer { inner: self.backend })
+    }
+
+    /// Run the actions deferred by the transactions until they commit, e.g.
+    /// filling the caches of `cached`, once the backend commits them, and
+    /// drop them otherwise. Configure it first, closest to the backend, so
+    /// that the actions of the attempts retried are dropped too.
+    pub fn after_commit(self) -> RunnerBuilder<AfterCommitLayer<B>> {
+        RunnerBuilder::new(AfterCommitLayer { inner: self.backend })
+    }
+
+    /// Build the runner
+    pub fn build(self) -> Runner<B> {
+        Runner {
+            backend: self.backend,
+        }
+    }
+
+    /// Build a runner of the transactions on the backend and the layers,
+    /// verifying them on the shadow backend, see `ShadowRunner`
+    pub fn build_shadow<S, R>(self, shadow: S, on_divergence: R) -> ShadowRunner<B, S, R>
+    where
+        R: Fn(&Divergence),
+    {
+        ShadowRunner::new(self.backend, shadow, on_divergence)
+    }
+
+    /// Build a runner of the transactions on the backend and the layers,
+    /// running the `ReadOnly` ones on the replica, see `ReplicatedRunner`
+    pub fn build_replicated<R>(self, replica: R) -> ReplicatedRunner<B, R> {
+        ReplicatedRunner {
+            primary: self.backend,
+            replica,
+        }
+    }
+
+    /// Build a runner of batch jobs committing every `chunk_size` items,
+    /// see `ChunkedRunner`
+    pub fn build_chunked(self, chunk_size: usize) -> ChunkedRunner<B> {
+        ChunkedRunner::new(self.backend, chunk_size)
+    }
+}
+
+/// Runner of transactions configured by `RunnerBuilder`
+#[derive(Debug, Clone)]
+pub struct Runner<B> {
+    backend: B,
+}
+
+impl<B> Runner<B> {
+    /// The backend wrapped in the layers
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Run the transaction through the layers. Pass a reference to run the
+    /// same transaction again.
+    pub fn run<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: Transaction,
+        B: Backend<Tx::Item, Tx::Err, Ctx = Tx::Ctx>,
+    {
+        self.backend.run(&tx, RunOptions::default())
+    }
+
+    /// Run the transaction like `run` with the idempotency key, which is
+    /// used by the layer of `RunnerBuilder::idempotent`
+    pub fn run_keyed<Tx>(&self, key: &str, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: Transaction,
+        B: Backend<Tx::Item, Tx::Err, Ctx = Tx::Ctx>,
+    {
+        let options = RunOptions {
+            key: Some(key),
+            ..RunOptions::default()
+        };
+        self.backend.run(&tx, options)
+    }
+}
+
+/// The layer of `RunnerBuilder::retry`
+#[derive(Debug, Clone)]
+pub struct RetryLayer<B, P> {
+    inner: B,
+    policy: P,
+}
+
+impl<B, P, T, E> Backend<T, E> for RetryLayer<B, P>
+where
+    B: Backend<T, E>,
+    P: RetryPolicy,
+    E: Retryable,
+{
+    type Ctx = B::Ctx;
+
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        let mut retries = 0;
+        loop {
+            let e = match self.inner.run(tx, options) {
+                Err(e) => if e.is_retryable() { e } else { return Err(e) },
+                Ok(t) => return Ok(t),
+            };
+            let delay = match self.policy.next_delay(retries) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            // the error of the transaction tells more than that of the
+            // deadline
+            if options.deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                return Err(e);
+            }
+            metrics::record_retry(tx.label());
+            clock::sleep(delay);
+            retries += 1;
+        }
+    }
+}
+
+/// The layer of `RunnerBuilder::deadline`
+#[derive(Debug, Clone)]
+pub struct DeadlineLayer<B> {
+    inner: B,
+    timeout: Duration,
+}
+
+impl<B, T, E> Backend<T, E> for DeadlineLayer<B>
+where
+    B: Backend<T, E>,
+    E: From<DeadlineExceeded>,
+{
+    type Ctx = B::Ctx;
+
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        let now = Instant::now();
+        if options.deadline.is_some_and(|deadline| deadline <= now) {
+            return Err(DeadlineExceeded.into());
+        }
+        let deadline = now + self.timeout;
+        let options = RunOptions {
+            deadline: Some(options.deadline.map_or(deadline, |outer| outer.min(deadline))),
+            ..options
+        };
+        let _deadline = RunDeadline::enter(options.deadline);
+        self.inner.run(tx, options)
+    }
+}
+
+/// The layer of `RunnerBuilder::bulkhead`
+#[derive(Debug, Clone)]
+pub struct BulkheadLayer<B> {
+    inner: B,
+    bulkhead: Bulkhead,
+}
+
+impl<B, T, E> Backend<T, E> for BulkheadLayer<B>
+where
+    B: Backend<T, E>,
+    E: From<BulkheadFull>,
+{
+    type Ctx = B::Ctx;
+
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        let _permit = self.bulkhead.acquire(options.deadline)?;
+        self.inner.run(tx, options)
+    }
+}
+
+/// The layer of `RunnerBuilder::traced`
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone)]
+pub struct TraceLayer<B> {
+    inner: B,
+    name: &'static str,
+}
+
+#[cfg(feature = "tracing")]
+impl<B, T, E> Backend<T, E> for TraceLayer<B>
+where
+    B: Backend<T, E>,
+{
+    type Ctx = B::Ctx;
+
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        let _span = tracing::info_span!("runner", name = self.name, label = tx.label()).entered();
+        let ret = self.inner.run(tx, options);
+        tracing::debug!(outcome = ?Outcome::of(&ret), "run");
+        ret
+    }
+}
+
+/// The layer of `RunnerBuilder::metrics`
+#[derive(Clone)]
+pub struct MetricsLayer<B> {
+    inner: B,
+    metrics: Arc<dyn Metrics>,
+}
+
+impl<B> fmt::Debug for MetricsLayer<B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MetricsLayer")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B, T, E> Backend<T, E> for MetricsLayer<B>
+where
+    B: Backend<T, E>,
+{
+    type Ctx = B::Ctx;
+
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        let start = Instant::now();
+        let ret = self.inner.run(tx, options);
+        let counter = match Outcome::of(&ret) {
+            Outcome::Committed => metrics::COMMITS,
+            Outcome::RolledBack => metrics::ROLLBACKS,
+        };
+        self.metrics.increment_counter(counter, tx.label());
+        self.metrics
+            .record_histogram(metrics::DURATION, tx.label(), start.elapsed().as_secs_f64());
+        ret
+    }
+}
+
+/// The layer of `RunnerBuilder::progress`
+#[derive(Clone)]
+pub struct ProgressLayer<B> {
+    inner: B,
+    subscriber: Arc<dyn Progress>,
+}
+
+impl<B> fmt::Debug for ProgressLayer<B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProgressLayer")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B, T, E> Backend<T, E> for ProgressLayer<B>
+where
+    B: Backend<T, E>,
+{
+    type Ctx = B::Ctx;
+
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        progress::with_subscriber(self.subscriber.clone(), || self.inner.run(tx, options))
+    }
+}
+
+/// The layer of `RunnerBuilder::idempotent`
+#[derive(Debug, Clone)]
+pub struct IdempotencyLayer<B> {
+    inner: B,
+}
+
+impl<B, T, E> Backend<T, E> for IdempotencyLayer<B>
+where
+    B: Backend<T, E>,
+    B::Ctx: IdempotencyStore<T>,
+    E: From<<B::Ctx as IdempotencyStore<T>>::Error>,
+{
+    type Ctx = B::Ctx;
+
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        match options.key {
+            Some(key) => self.inner.run(&idempotent(key, tx), options),
+            None => self.inner.run(tx, options),
+        }
+    }
+}
+
+/// The layer of `RunnerBuilder::after_commit`
+#[derive(Debug, Clone)]
+pub struct AfterCommitLayer<B> {
+    inner: B,
+}
+
+impl<B, T, E> Backend<T, E> for AfterCommitLayer<B>
+where
+    B: Backend<T, E>,
+{
+    type Ctx = B::Ctx;
+
+    fn run<Tx>(&self, tx: &Tx, options: RunOptions) -> Result<T, E>
+    where
+        Tx: Transaction<Ctx = Self::Ctx, Item = T, Err = E> + ?Sized,
+    {
+        let mut frame = CommitFrame::enter();
+        let ret = self.inner.run(tx, options);
+        frame.committed = ret.is_ok();
+        drop(frame);
+        ret
+    }
+}
+
+// the actions deferred by the runs of `AfterCommitLayer` on the thread,
+// innermost last
+thread_local!(static COMMIT_FRAMES: RefCell<Vec<Vec<Deferred>>> = const { RefCell::new(Vec::new()) });
+
+// sent back to the thread of the run by the branches running on other
+// threads, see `ThreadState`
+pub(crate) type Deferred = Box<dyn FnOnce() + Send>;
+
+// the actions deferred during a run, run if it commits and dropped
+// otherwise, even when the run panics
+pub(crate) struct CommitFrame {
+    pub(crate) committed: bool,
+}
+
+impl CommitFrame {
+    pub(crate) fn enter() -> Self {
+        COMMIT_FRAMES.with(|frames| frames.borrow_mut().push(Vec::new()));
+        CommitFrame { committed: false }
+    }
+}
+
+impl Drop for CommitFrame {
+    fn drop(&mut self) {
+        let actions = COMMIT_FRAMES.with(|frames| frames.borrow_mut().pop()).unwrap_or_default();
+        if self.committed {
+            for action in actions {
+                action()
+            }
+        }
+    }
+}
+
+// Defer the action until the run of `AfterCommitLayer` or
+// `hooks::instrument` in progress on the thread commits. Outside of such a
+// run the action is dropped, since nothing tells that the transaction
+// commits.
+pub(crate) fn after_commit(action: Deferred) {
+    COMMIT_FRAMES.with(|frames| {
+        if let Some(frame) = frames.borrow_mut().last_mut() {
+            frame.push(action)
+        }
+    })
+}
+
+// Replace the frames of the runs in progress on the thread, returning those
+// replaced
+pub(crate) fn replace_frames(frames: Vec<Vec<Deferred>>) -> Vec<Vec<Deferred>> {
+    COMMIT_FRAMES.with(|current| mem::replace(&mut *current.borrow_mut(), frames))
+}
+
+// Whether a run in progress on the thread defers actions until it commits
+pub(crate) fn deferring() -> bool {
+    COMMIT_FRAMES.with(|frames| !frames.borrow().is_empty())
+}
+
+// Drop the actions deferred by the failed attempt of the run in progress on
+// the thread, which runs the transaction again
+pub(crate) fn discard_deferred() {
+    let actions = COMMIT_FRAMES.with(|frames| frames.borrow_mut().last_mut().map(mem::take));
+    drop(actions);
+}
+
+/// Runner of batch jobs too large for one transaction, e.g. imports,
+/// running a transaction per item and committing every chunk of items by a
+/// run of the backend.
+///
+/// A chunk is committed once it has `chunk_size` items, or once it has run
+/// for the interval if any. The items of a chunk are kept until it is
+/// committed, so the layers of the backend retry the chunk with the same
+/// items from the last commit. If a chunk fails, the error tells how many
+/// items were committed, and `resume` continues from there.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::cell::{Cell, RefCell};
+/// use std::rc::Rc;
+/// use transaction::prelude::*;
+/// use transaction::runner::{Backend, ChunkError, RunOptions, RunnerBuilder};
+///
+/// // a backend committing to a table of rows
+/// struct Table(Rc<RefCell<Vec<i32>>>);
+///
+/// impl<T, E> Backend<T, E> for Table {
+///     type Ctx = Vec<i32>;
+///
+///     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+///     where
+///         Tx: Transaction<Ctx = Vec<i32>, Item = T, Err = E> + ?Sized,
+///     {
+///         let mut rows = vec![];
+///         let t = tx.run(&mut rows)?;
+///         self.0.borrow_mut().append(&mut rows);
+///         Ok(t)
+///     }
+/// }
+///
+/// # fn main() {
+/// let table = Rc::new(RefCell::new(vec![]));
+/// let runner = RunnerBuilder::new(Table(table.clone())).build_chunked(3);
+///
+/// // the 7th row fails once
+/// let failed = Cell::new(false);
+/// let insert = |n: &i32| {
+///     let n = *n;
+///     let fail = n == 7 && !failed.replace(true);
+///     with_ctx(move |rows: &mut Vec<i32>| {
+///         if fail {
+///             return Err("connection lost");
+///         }
+///         rows.push(n);
+///         Ok(())
+///     })
+/// };
+///
+/// let err = runner.run(1..=10, &insert).unwrap_err();
+/// assert_eq!(err, ChunkError { committed: 6, error: "connection lost" });
+/// assert_eq!(*table.borrow(), vec![1, 2, 3, 4, 5, 6]);
+///
+/// assert_eq!(runner.resume(1..=10, err.committed, &insert), Ok(10));
+/// assert_eq!(*table.borrow(), (1..=10).collect::<Vec<_>>());
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChunkedRunner<B> {
+    backend: B,
+    chunk_size: usize,
+    interval: Option<Duration>,
+}
+
+impl<B> ChunkedRunner<B> {
+    /// Run the items on the backend committing every `chunk_size` items.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero
+    pub fn new(backend: B, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "the chunks are to have an item at least");
+        ChunkedRunner {
+            backend,
+            chunk_size,
+            interval: None,
+        }
+    }
+
+    /// Commit the chunks also once they have run for the interval, even
+    /// with fewer items
+    pub fn interval(self, interval: Duration) -> Self {
+        ChunkedRunner {
+            interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// The backend wrapped in the layers
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Run the transaction made by `f` for each item, committing the
+    /// chunks. Returns the number of items committed. The number of the
+    /// items run, including those of the chunk not committed yet, is
+    /// reported to `progress` as the step `chunked`.
+    pub fn run<I, F, Ctx, A>(&self, items: I, f: F) -> Result<usize, ChunkError<A::Err>>
+    where
+        I: IntoIterator,
+        F: Fn(&I::Item) -> A,
+        A: IntoTransaction<Ctx>,
+        B: Backend<usize, A::Err, Ctx = Ctx>,
+    {
+        self.resume(items, 0, f)
+    }
+
+    /// Run the items like `run`, skipping the first `committed` ones, which
+    /// were committed by a run which failed. Returns the number of items
+    /// committed including them.
+    pub fn resume<I, F, Ctx, A>(
+        &self,
+        items: I,
+        committed: usize,
+        f: F,
+    ) -> Result<usize, ChunkError<A::Err>>
+    where
+        I: IntoIterator,
+        F: Fn(&I::Item) -> A,
+        A: IntoTransaction<Ctx>,
+        B: Backend<usize, A::Err, Ctx = Ctx>,
+    {
+        let items = items.into_iter().skip(committed);
+        let total = match items.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(committed + lower),
+            _ => None,
+        };
+        let items = RefCell::new(items);
+        let committed = Cell::new(committed);
+        // the items of the chunk running, kept until it is committed
+        let buffer = RefCell::new(VecDeque::new());
+        let chunk = with_ctx(|ctx: &mut Ctx| {
+            let start = Instant::now();
+            let timed_out = |n| n > 0 && self.interval.is_some_and(|interval| start.elapsed() >= interval);
+            let mut n = 0;
+            while n < self.chunk_size && !timed_out(n) {
+                if n == buffer.borrow().len() {
+                    match items.borrow_mut().next() {
+                        Some(item) => buffer.borrow_mut().push_back(item),
+                        None => break,
+                    }
+                }
+                f(&buffer.borrow()[n]).into_transaction().run(ctx)?;
+                n += 1;
+                progress::report("chunked", committed.get() + n, total);
+            }
+            Ok(n)
+        });
+        loop {
+            if buffer.borrow().is_empty() {
+                match items.borrow_mut().next() {
+                    Some(item) => buffer.borrow_mut().push_back(item),
+                    None => return Ok(committed.get()),
+                }
+            }
+            match self.backend.run(&chunk, RunOptions::default()) {
+                Ok(n) => {
+                    buffer.borrow_mut().drain(..n);
+                    committed.set(committed.get() + n);
+                }
+                Err(error) => {
+                    return Err(ChunkError {
+                        committed: committed.get(),
+                        error,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// The error of `ChunkedRunner`: the error of the chunk which failed and
+/// the number of items committed before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkError<E> {
+    /// The number of items committed, from which to `resume`
+    pub committed: usize,
+    /// The error of the chunk
+    pub error: E,
+}
+
+impl<E> fmt::Display for ChunkError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "chunk failed after committing {} items: {}", self.committed, self.error)
+    }
+}
+
+impl<E> Error for ChunkError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Runner of transactions on a primary backend and, to verify a migration,
+/// on a shadow backend too, e.g. the old and the new database. The
+/// transaction is committed on the primary, and its result is returned. It
+/// is then run on the shadow, which always rolls it back, and the results
+/// differing are reported to `on_divergence`.
+///
+/// The shadow backend runs the transaction with the item `Infallible` and
+/// the error `Shadowed`, which carries the result to compare, so that it
+/// rolls back even when the transaction succeeds.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::cell::{Cell, RefCell};
+/// use std::rc::Rc;
+/// use transaction::prelude::*;
+/// use transaction::runner::{Backend, Divergence, RunOptions, RunnerBuilder};
+///
+/// // a backend running the transactions on a counter
+/// struct Counter(Rc<Cell<i32>>);
+///
+/// impl<T, E> Backend<T, E> for Counter {
+///     type Ctx = i32;
+///
+///     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+///     where
+///         Tx: Transaction<Ctx = i32, Item = T, Err = E> + ?Sized,
+///     {
+///         let mut n = self.0.get();
+///         let t = tx.run(&mut n)?;
+///         self.0.set(n);
+///         Ok(t)
+///     }
+/// }
+///
+/// # fn main() {
+/// let (old, new) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(10)));
+/// let divergences = RefCell::new(vec![]);
+/// let runner = RunnerBuilder::new(Counter(old.clone()))
+///     .build_shadow(Counter(new.clone()), |d: &Divergence| divergences.borrow_mut().push(d.clone()));
+///
+/// let incr = || {
+///     with_ctx(|n: &mut i32| -> Result<i32, ()> {
+///         *n += 1;
+///         Ok(*n)
+///     })
+/// };
+/// assert_eq!(runner.run(incr().named("incr")), Ok(1));
+/// // committed on the primary only
+/// assert_eq!((old.get(), new.get()), (1, 10));
+/// assert_eq!(
+///     *divergences.borrow(),
+///     vec![Divergence { label: Some("incr".to_string()), primary: "Ok(1)".to_string(), shadow: "Ok(11)".to_string() }]
+/// );
+///
+/// // compare only whether both succeed
+/// let both_ok = |a: &Result<i32, ()>, b: &Result<i32, ()>| a.is_ok() == b.is_ok();
+/// assert_eq!(runner.run_compared(incr(), both_ok), Ok(2));
+/// assert_eq!(divergences.borrow().len(), 1);
+/// # }
+/// ```
+pub struct ShadowRunner<P, S, R> {
+    primary: P,
+    shadow: S,
+    on_divergence: R,
+}
+
+impl<P, S, R> fmt::Debug for ShadowRunner<P, S, R>
+where
+    P: fmt::Debug,
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShadowRunner")
+            .field("primary", &self.primary)
+            .field("shadow", &self.shadow)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P, S, R> ShadowRunner<P, S, R>
+where
+    R: Fn(&Divergence),
+{
+    /// Run the transactions on the primary and the shadow, reporting the
+    /// divergences to `on_divergence`
+    pub fn new(primary: P, shadow: S, on_divergence: R) -> Self {
+        ShadowRunner {
+            primary,
+            shadow,
+            on_divergence,
+        }
+    }
+
+    /// The primary backend wrapped in the layers
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// The shadow backend
+    pub fn shadow(&self) -> &S {
+        &self.shadow
+    }
+
+    /// Run the transaction on the primary and then on the shadow, and
+    /// report the results which are not equal. Returns the result of the
+    /// primary.
+    pub fn run<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: Transaction,
+        Tx::Item: PartialEq + fmt::Debug,
+        Tx::Err: PartialEq + fmt::Debug,
+        P: Backend<Tx::Item, Tx::Err, Ctx = Tx::Ctx>,
+        S: Backend<Infallible, Shadowed<Tx::Item, Tx::Err>, Ctx = Tx::Ctx>,
+    {
+        self.run_compared(tx, |primary, shadow| primary == shadow)
+    }
+
+    /// Run the transaction like `run`, reporting the results for which
+    /// `same` returns false, e.g. to ignore the errors of the backends
+    /// worded differently
+    pub fn run_compared<Tx, F>(&self, tx: Tx, same: F) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: Transaction,
+        Tx::Item: fmt::Debug,
+        Tx::Err: fmt::Debug,
+        F: Fn(&Result<Tx::Item, Tx::Err>, &Result<Tx::Item, Tx::Err>) -> bool,
+        P: Backend<Tx::Item, Tx::Err, Ctx = Tx::Ctx>,
+        S: Backend<Infallible, Shadowed<Tx::Item, Tx::Err>, Ctx = Tx::Ctx>,
+    {
+        let primary = self.primary.run(&tx, RunOptions::default());
+        let shadow = match self.shadow.run(&RollBack(&tx), RunOptions::default()) {
+            Ok(never) => match never {},
+            Err(Shadowed(shadow)) => shadow,
+        };
+        if !same(&primary, &shadow) {
+            (self.on_divergence)(&Divergence {
+                label: tx.label().map(String::from),
+                primary: format!("{:?}", primary),
+                shadow: format!("{:?}", shadow),
+            });
+        }
+        primary
+    }
+}
+
+/// The results of a transaction differing between the primary and the
+/// shadow of a `ShadowRunner`, formatted by `Debug`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The label of the transaction, if any
+    pub label: Option<String>,
+    /// The result of the primary
+    pub primary: String,
+    /// The result of the shadow
+    pub shadow: String,
+}
+
+/// The error making the shadow backend of a `ShadowRunner` roll back, with
+/// the result of the transaction. It is retryable if the error of the
+/// transaction is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadowed<T, E>(pub Result<T, E>);
+
+impl<T, E> Retryable for Shadowed<T, E>
+where
+    E: Retryable,
+{
+    fn is_retryable(&self) -> bool {
+        match self.0 {
+            Ok(_) => false,
+            Err(ref e) => e.is_retryable(),
+        }
+    }
+}
+
+// the transaction failing with its result, run on the shadow
+struct RollBack<'a, Tx>(&'a Tx);
+
+impl<'a, Tx> Transaction for RollBack<'a, Tx>
+where
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Infallible;
+    type Err = Shadowed<Tx::Item, Tx::Err>;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Err(Shadowed(self.0.run(ctx)))
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.0.label()
+    }
+}
+
+/// Runner of the transactions on a primary backend and of the `ReadOnly`
+/// transactions on a replica, e.g. a read replica of the database or a
+/// backend running read-only snapshots, built by
+/// `RunnerBuilder::build_replicated`. The layers wrap the primary only; wrap
+/// the replica in its own layers before.
+///
+/// The transactions are routed by the call sites, `read` accepting only the
+/// transactions made of read leaves, so that a write can't reach the
+/// replica.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+/// use transaction::prelude::*;
+/// use transaction::read_ctx;
+/// use transaction::runner::{Backend, RunOptions, RunnerBuilder};
+///
+/// // a backend running the transactions on a counter
+/// struct Counter(Rc<Cell<i32>>);
+///
+/// impl<T, E> Backend<T, E> for Counter {
+///     type Ctx = i32;
+///
+///     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+///     where
+///         Tx: Transaction<Ctx = i32, Item = T, Err = E> + ?Sized,
+///     {
+///         let mut n = self.0.get();
+///         let t = tx.run(&mut n)?;
+///         self.0.set(n);
+///         Ok(t)
+///     }
+/// }
+///
+/// # fn main() {
+/// // the replica lags behind
+/// let (primary, replica) = (Rc::new(Cell::new(1)), Rc::new(Cell::new(0)));
+/// let runner = RunnerBuilder::new(Counter(primary.clone())).build_replicated(Counter(replica.clone()));
+///
+/// let get = read_ctx(|n: &i32| Ok::<_, ()>(*n));
+/// assert_eq!(runner.read(&get), Ok(0));
+/// assert_eq!(runner.run(&get), Ok(1));
+///
+/// let incr = with_ctx(|n: &mut i32| -> Result<i32, ()> {
+///     *n += 1;
+///     Ok(*n)
+/// });
+/// assert_eq!(runner.run(incr), Ok(2));
+/// // runner.read(incr) doesn't compile
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReplicatedRunner<P, R> {
+    primary: P,
+    replica: R,
+}
+
+impl<P, R> ReplicatedRunner<P, R> {
+    /// The primary backend wrapped in the layers
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// The replica backend
+    pub fn replica(&self) -> &R {
+        &self.replica
+    }
+
+    /// Run the transaction on the primary, like `Runner::run`, e.g. the
+    /// writes and the reads which must see them
+    pub fn run<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: Transaction,
+        P: Backend<Tx::Item, Tx::Err, Ctx = Tx::Ctx>,
+    {
+        self.primary.run(&tx, RunOptions::default())
+    }
+
+    /// Run the read-only transaction on the replica
+    pub fn read<Tx>(&self, tx: Tx) -> Result<Tx::Item, Tx::Err>
+    where
+        Tx: ReadOnly,
+        R: Backend<Tx::Item, Tx::Err, Ctx = Tx::Ctx>,
+    {
+        self.replica.run(&tx, RunOptions::default())
+    }
+}
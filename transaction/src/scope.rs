@@ -0,0 +1,382 @@
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::runner::Deferred;
+use crate::thread_state::{self, ThreadState};
+use crate::{platform, Transaction};
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// Contexts which can be split into contexts for transactions run in
+/// parallel by `par_join`, `par_join_all` and `scope`, e.g. read-only
+/// snapshots or a connection for each branch
+pub trait SplittableCtx: Sized {
+    /// Make a context for a branch
+    fn split(&mut self) -> Self;
+
+    /// Take back the context of a branch once it is done, in the order of
+    /// the branches. Does nothing by default.
+    fn merge(&mut self, _branch: Self) {}
+}
+
+/// The error of a sub-transaction of `scope` not run since another one
+/// failed before it was spawned, or of a transaction of a batch of
+/// `pool::Pipelining::Single` rolled back with another one which failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the transaction was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// Run the function spawning sub-transactions, each on a thread of its own
+/// and in a context split from the context, e.g. a connection for each
+/// branch. The scope returns only once all the sub-transactions are done,
+/// and merges their contexts in the order they were spawned, so the outer
+/// transaction can't commit while some are still running.
+///
+/// The sub-transactions are joined by their handles to get their items.
+/// The error of a sub-transaction not joined fails the scope, and once a
+/// sub-transaction failed, those spawned afterwards are cancelled: they
+/// don't run and fail with `Cancelled`. The sub-transactions already
+/// running can't be interrupted and complete.
+///
+/// The sub-transactions run with the environments provided, the tenant,
+/// the deadline, the isolation level and the progress subscribers of the
+/// run on the thread calling `run`, and the actions they defer until the
+/// commit, e.g. filling the cache of `cached`, wait for the run to commit.
+///
+/// On the targets without threads, e.g. `wasm32-unknown-unknown`, the
+/// sub-transactions are run one by one when spawned.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{scope, Cancelled, SplittableCtx};
+///
+/// // a branch of the database, holding its writes until merged
+/// #[derive(Default)]
+/// struct Db {
+///     rows: Vec<String>,
+/// }
+///
+/// impl SplittableCtx for Db {
+///     fn split(&mut self) -> Self {
+///         Db::default()
+///     }
+///
+///     fn merge(&mut self, branch: Self) {
+///         self.rows.extend(branch.rows);
+///     }
+/// }
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Error {
+///     Cancelled,
+///     Duplicate(&'static str),
+/// }
+///
+/// impl From<Cancelled> for Error {
+///     fn from(_: Cancelled) -> Self {
+///         Error::Cancelled
+///     }
+/// }
+///
+/// fn insert(row: &'static str) -> impl Transaction<Ctx = Db, Item = usize, Err = Error> + Send {
+///     with_ctx(move |db: &mut Db| {
+///         if row == "alice" {
+///             return Err(Error::Duplicate(row));
+///         }
+///         db.rows.push(row.to_string());
+///         Ok(row.len())
+///     })
+/// }
+///
+/// # fn main() {
+/// let mut db = Db::default();
+/// let both = scope(|s| {
+///     let bob = s.spawn(insert("bob"));
+///     let carol = s.spawn(insert("carol"));
+///     Ok(bob.join()? + carol.join()?)
+/// });
+/// assert_eq!(both.run(&mut db), Ok(8));
+/// assert_eq!(db.rows, vec!["bob", "carol"]);
+///
+/// // the error of a sub-transaction not joined fails the scope
+/// let failing = scope(|s| {
+///     s.spawn(insert("alice"));
+///     s.spawn(insert("dave"));
+///     Ok(())
+/// });
+/// assert_eq!(failing.run(&mut db), Err(Error::Duplicate("alice")));
+///
+/// // and cancels those spawned afterwards
+/// let cancelled = scope(|s| {
+///     let alice = s.spawn(insert("alice")).join();
+///     Ok((alice, s.spawn(insert("erin")).join()))
+/// });
+/// assert_eq!(cancelled.run(&mut db), Ok((Err(Error::Duplicate("alice")), Err(Error::Cancelled))));
+/// # }
+/// ```
+///
+/// The environments, the tenant and the commit of the run reach the
+/// sub-transactions:
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::time::Duration;
+/// use transaction::prelude::*;
+/// use transaction::runner::{Backend, RunOptions, RunnerBuilder};
+/// use transaction::{for_tenant, provide, scope, with_env, Cache, Cancelled, CrossTenant, LruCache, SplittableCtx, TenantScope};
+///
+/// struct Db;
+///
+/// impl SplittableCtx for Db {
+///     fn split(&mut self) -> Self {
+///         Db
+///     }
+/// }
+///
+/// impl TenantScope for Db {
+///     type Err = CrossTenant;
+///
+///     fn scope_to(&mut self, _tenant: &str) -> Result<(), CrossTenant> {
+///         Ok(())
+///     }
+/// }
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Error {
+///     Cancelled,
+///     CrossTenant(CrossTenant),
+/// }
+///
+/// impl From<Cancelled> for Error {
+///     fn from(_: Cancelled) -> Self {
+///         Error::Cancelled
+///     }
+/// }
+///
+/// impl From<CrossTenant> for Error {
+///     fn from(e: CrossTenant) -> Self {
+///         Error::CrossTenant(e)
+///     }
+/// }
+///
+/// // a backend committing every run
+/// struct Store;
+///
+/// impl<T, E> Backend<T, E> for Store {
+///     type Ctx = Db;
+///
+///     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+///     where
+///         Tx: Transaction<Ctx = Db, Item = T, Err = E> + ?Sized,
+///     {
+///         tx.run(&mut Db)
+///     }
+/// }
+///
+/// struct PageSize(usize);
+///
+/// # fn main() {
+/// let runner = RunnerBuilder::new(Store).after_commit().build();
+/// let cache = LruCache::new(10);
+///
+/// let tx = scope(|s| {
+///     let page_size = s.spawn(with_env(|size: &PageSize, _: &mut Db| Ok(size.0)));
+///     let count = for_tenant("acme", with_ctx(|_: &mut Db| Ok::<_, Error>(42)));
+///     let count = s.spawn(count.cached(&cache, |_: &Db| "count", Duration::from_secs(60)));
+///     Ok((page_size.join()?, count.join()?))
+/// });
+/// let tx = provide(PageSize(20), tx).scoped_to_tenant("acme");
+/// assert_eq!(runner.run(tx), Ok((20, 42)));
+/// assert_eq!(cache.get(&"count"), Some(42));
+/// # }
+/// ```
+pub fn scope<Ctx, E, F, T>(f: F) -> SpawnScope<Ctx, E, F>
+where
+    F: for<'s, 'env> Fn(&Scope<'s, 'env, Ctx, E>) -> Result<T, E>,
+{
+    SpawnScope {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `scope`
+#[derive(Debug)]
+#[must_use]
+pub struct SpawnScope<Ctx, E, F> {
+    f: F,
+    _phantom: PhantomData<(Ctx, E)>,
+}
+
+impl<Ctx, E, F, T> Transaction for SpawnScope<Ctx, E, F>
+where
+    Ctx: SplittableCtx + Send,
+    E: From<Cancelled> + Send,
+    F: for<'s, 'env> Fn(&Scope<'s, 'env, Ctx, E>) -> Result<T, E>,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let branches = Mutex::new(Vec::new());
+        let cancelled = AtomicBool::new(false);
+        let state = ThreadState::capture();
+        let (ret, unjoined) = thread::scope(|s| {
+            let scope = Scope {
+                scope: s,
+                ctx: RefCell::new(&mut *ctx),
+                state: &state,
+                branches: &branches,
+                cancelled: &cancelled,
+                spawned: Cell::new(0),
+                unjoined: RefCell::new(Vec::new()),
+            };
+            let ret = (self.f)(&scope);
+            (ret, scope.unjoined.into_inner())
+        });
+        // all the sub-transactions are done
+        let unjoined = unjoined.into_iter().find_map(|error| error());
+        let mut branches = branches.into_inner().unwrap_or_else(|e| e.into_inner());
+        branches.sort_by_key(|&(i, _, _)| i);
+        for (_, branch, deferred) in branches {
+            ctx.merge(branch);
+            thread_state::defer(deferred);
+        }
+        let item = ret?;
+        match unjoined {
+            Some(e) => Err(e),
+            None => Ok(item),
+        }
+    }
+}
+
+impl<Ctx, E, F> Visit for SpawnScope<Ctx, E, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("scope"));
+    }
+}
+
+// the error of a sub-transaction, if it failed and was not joined
+type Unjoined<'env, E> = Box<dyn FnOnce() -> Option<E> + 'env>;
+
+/// The scope given to the function of `scope` to spawn sub-transactions
+pub struct Scope<'s, 'env, Ctx, E> {
+    scope: &'s thread::Scope<'s, 'env>,
+    ctx: RefCell<&'env mut Ctx>,
+    state: &'env ThreadState,
+    // the contexts of the sub-transactions done, with their indices and the
+    // actions they deferred
+    branches: &'env Mutex<Vec<(usize, Ctx, Vec<Deferred>)>>,
+    cancelled: &'env AtomicBool,
+    spawned: Cell<usize>,
+    unjoined: RefCell<Vec<Unjoined<'env, E>>>,
+}
+
+impl<'s, 'env, Ctx, E> fmt::Debug for Scope<'s, 'env, Ctx, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Scope")
+            .field("spawned", &self.spawned.get())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'s, 'env, Ctx, E> Scope<'s, 'env, Ctx, E>
+where
+    Ctx: SplittableCtx + Send,
+    E: From<Cancelled> + Send,
+{
+    /// Run the sub-transaction on a thread of its own, in a context split
+    /// from the context of the scope, unless the scope is cancelled
+    pub fn spawn<Tx>(&self, tx: Tx) -> Spawned<'s, Tx::Item, E>
+    where
+        Tx: Transaction<Ctx = Ctx, Err = E> + Send + 's,
+        Tx::Item: Send + 'env,
+        E: 'env,
+    {
+        let index = self.spawned.get();
+        self.spawned.set(index + 1);
+        let slot = Arc::new(Mutex::new(None));
+        let unjoined = slot.clone();
+        self.unjoined.borrow_mut().push(Box::new(move || {
+            match unjoined.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                Some(Err(e)) => Some(e),
+                _ => None,
+            }
+        }));
+        if self.cancelled.load(Ordering::SeqCst) {
+            *slot.lock().unwrap_or_else(|e| e.into_inner()) = Some(Err(Cancelled.into()));
+            return Spawned { thread: None, slot };
+        }
+        let mut branch = self.ctx.borrow_mut().split();
+        let (result, state, branches, cancelled) = (slot.clone(), self.state, self.branches, self.cancelled);
+        let run = move || {
+            let (ret, deferred) = state.enter(|| tx.run(&mut branch));
+            if ret.is_err() {
+                cancelled.store(true, Ordering::SeqCst);
+            }
+            *result.lock().unwrap_or_else(|e| e.into_inner()) = Some(ret);
+            branches.lock().unwrap_or_else(|e| e.into_inner()).push((index, branch, deferred));
+        };
+        if !platform::THREADS {
+            run();
+            return Spawned { thread: None, slot };
+        }
+        Spawned {
+            thread: Some(self.scope.spawn(run)),
+            slot,
+        }
+    }
+
+    /// Whether a sub-transaction failed, cancelling those spawned afterwards
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// The handle of a sub-transaction spawned by `Scope::spawn`
+#[must_use]
+pub struct Spawned<'s, T, E> {
+    thread: Option<thread::ScopedJoinHandle<'s, ()>>,
+    slot: Arc<Mutex<Option<Result<T, E>>>>,
+}
+
+impl<'s, T, E> fmt::Debug for Spawned<'s, T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Spawned").finish_non_exhaustive()
+    }
+}
+
+impl<'s, T, E> Spawned<'s, T, E> {
+    /// Wait for the sub-transaction and return its result. If it panicked,
+    /// the panic is resumed.
+    pub fn join(self) -> Result<T, E> {
+        if let Some(thread) = self.thread {
+            if let Err(panic) = thread.join() {
+                panic::resume_unwind(panic);
+            }
+        }
+        self.slot
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .expect("the sub-transaction is done")
+    }
+}
@@ -0,0 +1,293 @@
+use std::marker::PhantomData;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_leaf, visit_node, Node, Visit, Visitor};
+
+/// Backend contexts which can emulate nested transactions with savepoints.
+///
+/// All the methods do nothing by default, so backends without savepoints
+/// can still be used in a `ScopedCtx` by an empty `impl`: the scopes are
+/// tracked but an error of a scope is not rolled back separately.
+pub trait Savepoints {
+    /// The error of the savepoint operations
+    type Error;
+
+    /// Create a savepoint named `name`
+    fn savepoint(&mut self, _name: &str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Forget the savepoint named `name`, keeping the changes made after it
+    fn release(&mut self, _name: &str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Undo the changes made after the savepoint named `name`
+    fn rollback_to(&mut self, _name: &str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The depth of a `ScopedCtx` outside of any scope
+#[derive(Debug)]
+pub struct Top;
+
+/// The depth of a `ScopedCtx` in a scope entered at the depth `D`
+#[derive(Debug)]
+pub struct InScope<D>(PhantomData<D>);
+
+/// A context maintaining a stack of the scopes entered by `scoped`
+/// transactions on top of the backend context `C`.
+///
+/// The depth is tracked by the type too: `D` is `Top` outside of any scope,
+/// `InScope<Top>` in a scope, and so on. The transactions which are illegal
+/// in a savepoint, e.g. `with_top_ctx`, only run on `ScopedCtx<C, Top>`, so
+/// nesting them in a `scoped` doesn't compile. The transactions which run at
+/// any depth are generic over `D`.
+///
+/// Transactions written for `C` can be run in it with `adapt_ctx`.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct ScopedCtx<C, D = Top> {
+    scopes: Scopes<C>,
+    _depth: PhantomData<D>,
+}
+
+#[derive(Debug)]
+struct Scopes<C> {
+    ctx: C,
+    names: Vec<String>,
+}
+
+impl<C> ScopedCtx<C> {
+    /// wrap the context with an empty scope stack
+    pub fn new(ctx: C) -> Self {
+        ScopedCtx {
+            scopes: Scopes {
+                ctx,
+                names: Vec::new(),
+            },
+            _depth: PhantomData,
+        }
+    }
+}
+
+impl<C, D> ScopedCtx<C, D> {
+    /// The number of the scopes currently entered
+    pub fn depth(&self) -> usize {
+        self.scopes.names.len()
+    }
+
+    /// The savepoint names of the scopes currently entered, outermost first
+    pub fn scopes(&self) -> &[String] {
+        &self.scopes.names
+    }
+
+    /// The backend context
+    pub fn inner(&self) -> &C {
+        &self.scopes.ctx
+    }
+
+    /// The backend context
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.scopes.ctx
+    }
+
+    /// Unwrap the backend context
+    pub fn into_inner(self) -> C {
+        self.scopes.ctx
+    }
+
+    // the same context one scope deeper
+    fn nested(&mut self) -> &mut ScopedCtx<C, InScope<D>> {
+        // SAFETY: `ScopedCtx` is `repr(transparent)` over `Scopes<C>`, so the
+        // contexts of all the depths have the same layout
+        unsafe { &mut *(self as *mut Self as *mut ScopedCtx<C, InScope<D>>) }
+    }
+}
+
+impl<C, D> AsMut<C> for ScopedCtx<C, D> {
+    fn as_mut(&mut self) -> &mut C {
+        &mut self.scopes.ctx
+    }
+}
+
+/// Run the transaction in a nested scope. A savepoint is created before the
+/// run; it is released if the transaction succeeds and rolled back to if it
+/// fails, so only the changes of the failed scope are undone.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{ScopedCtx, Savepoints};
+///
+/// #[derive(Default)]
+/// struct Journal(Vec<String>);
+///
+/// impl Savepoints for Journal {
+///     type Error = ();
+///     fn savepoint(&mut self, name: &str) -> Result<(), ()> {
+///         self.0.push(format!("SAVEPOINT {}", name));
+///         Ok(())
+///     }
+///     fn rollback_to(&mut self, name: &str) -> Result<(), ()> {
+///         self.0.push(format!("ROLLBACK TO {}", name));
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() {
+/// let failing = err::<Journal, (), ()>(()).adapt_ctx().scoped();
+/// let tx = failing.or_else(|()| ok(()));
+/// let mut ctx = ScopedCtx::new(Journal::default());
+/// assert_eq!(tx.run(&mut ctx), Ok(()));
+/// assert_eq!(ctx.depth(), 0);
+/// assert_eq!(
+///     ctx.into_inner().0,
+///     vec!["SAVEPOINT transaction_scope_0", "ROLLBACK TO transaction_scope_0"]
+/// );
+/// # }
+/// ```
+pub fn scoped<C, D, A>(a: A) -> Scoped<A::Tx>
+where
+    A: IntoTransaction<ScopedCtx<C, InScope<D>>>,
+{
+    Scoped { tx: a.into_transaction() }
+}
+
+/// The result of `scoped`
+#[derive(Debug)]
+#[must_use]
+pub struct Scoped<Tx> {
+    tx: Tx,
+}
+
+impl<C, D, Tx> Transaction for Scoped<Tx>
+where
+    C: Savepoints,
+    Tx: Transaction<Ctx = ScopedCtx<C, InScope<D>>>,
+    Tx::Err: From<C::Error>,
+{
+    type Ctx = ScopedCtx<C, D>;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let name = format!("transaction_scope_{}", ctx.depth());
+        ctx.scopes.ctx.savepoint(&name)?;
+        ctx.scopes.names.push(name);
+        let ret = self.tx.run(ctx.nested());
+        let scopes = &mut ctx.scopes;
+        let name = scopes.names.pop().expect("scope stack is broken");
+        match ret {
+            Ok(item) => {
+                scopes.ctx.release(&name)?;
+                Ok(item)
+            }
+            Err(e) => {
+                scopes.ctx.rollback_to(&name)?;
+                scopes.ctx.release(&name)?;
+                Err(e)
+            }
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Scoped<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("scoped"), |v| self.tx.accept(v));
+    }
+}
+
+/// Receive the backend context outside of any scope and perform
+/// computation, for the operations which are illegal in a savepoint, e.g.
+/// changing the isolation level or `COMMIT PREPARED`. It runs on
+/// `ScopedCtx<C, Top>` only, so it doesn't compile in a `scoped`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{with_top_ctx, Savepoints, ScopedCtx};
+///
+/// #[derive(Default)]
+/// struct Conn(Vec<&'static str>);
+///
+/// impl Savepoints for Conn {
+///     type Error = ();
+/// }
+///
+/// # fn main() {
+/// let set = with_top_ctx(|conn: &mut Conn| Ok::<_, ()>(conn.0.push("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")));
+/// let insert = with_ctx(|conn: &mut Conn| Ok(conn.0.push("INSERT"))).adapt_ctx().scoped();
+/// let mut ctx = ScopedCtx::new(Conn::default());
+/// assert_eq!(set.join(insert).run(&mut ctx), Ok(((), ())));
+/// assert_eq!(ctx.into_inner().0, vec!["SET TRANSACTION ISOLATION LEVEL SERIALIZABLE", "INSERT"]);
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{with_top_ctx, Savepoints, ScopedCtx};
+///
+/// struct Conn;
+///
+/// impl Savepoints for Conn {
+///     type Error = ();
+/// }
+///
+/// # fn main() {
+/// let set = with_top_ctx(|_: &mut Conn| Ok::<_, ()>("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE"));
+/// set.scoped().run(&mut ScopedCtx::new(Conn));
+/// # }
+/// ```
+pub fn with_top_ctx<C, F, T, E>(f: F) -> WithTopCtx<C, F>
+where
+    F: Fn(&mut C) -> Result<T, E>,
+{
+    WithTopCtx {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_top_ctx`
+#[derive(Debug)]
+#[must_use]
+pub struct WithTopCtx<C, F> {
+    f: F,
+    _phantom: PhantomData<C>,
+}
+
+impl<C, F, T, E> Transaction for WithTopCtx<C, F>
+where
+    F: Fn(&mut C) -> Result<T, E>,
+{
+    type Ctx = ScopedCtx<C, Top>;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        (self.f)(ctx.inner_mut())
+    }
+}
+
+impl<C, F> Visit for WithTopCtx<C, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_top_ctx"));
+    }
+}
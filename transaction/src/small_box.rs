@@ -0,0 +1,168 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+
+use crate::{IntoTransaction, Transaction};
+
+/// Box the transaction like `boxed`, but store it inline without allocating
+/// when it fits in `N` words, e.g. the leaves and the small combinators
+/// built by a DI container in a hot path. Larger transactions, and those
+/// aligned more strictly than `usize`, are stored on the heap.
+///
+/// Transactions built on the stack can also be composed as `&dyn
+/// Transaction`, which is a transaction too, without allocating at all.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{small_boxed, SmallBoxTx};
+///
+/// # fn main() {
+/// fn lookup<'a>(users: &'a [&'a str], id: usize) -> SmallBoxTx<'a, (), &'a str, ()> {
+///     if id < users.len() {
+///         ok(users[id]).small_boxed()
+///     } else {
+///         with_ctx(move |_: &mut ()| users.last().cloned().ok_or(())).small_boxed()
+///     }
+/// }
+///
+/// let users = ["alice", "bob"];
+/// let tx = lookup(&users, 0);
+/// assert!(tx.is_inline());
+/// assert_eq!(tx.run(&mut ()), Ok("alice"));
+/// assert_eq!(lookup(&users, 5).run(&mut ()), Ok("bob"));
+///
+/// // too large for a single word
+/// let big = small_boxed::<_, _, 1>(ok::<(), _, ()>([0u64; 4]));
+/// assert!(!big.is_inline());
+/// assert_eq!(big.run(&mut ()), Ok([0; 4]));
+///
+/// // no allocation with references
+/// let hello = ok::<(), _, ()>("hello");
+/// let steps: [&dyn Transaction<Ctx = (), Item = &str, Err = ()>; 2] = [&hello, &ok("world")];
+/// let words = steps[0].join(steps[1]).map(|(a, b)| format!("{} {}", a, b));
+/// assert_eq!(words.run(&mut ()), Ok("hello world".to_string()));
+/// # }
+/// ```
+pub fn small_boxed<'a, Ctx, A, const N: usize>(a: A) -> SmallBoxTx<'a, Ctx, A::Item, A::Err, N>
+where
+    A: IntoTransaction<Ctx>,
+    A::Tx: 'a,
+{
+    SmallBoxTx::new(a.into_transaction())
+}
+
+/// The result of `small_boxed`: a transaction of any type, stored inline
+/// when it fits in `N` words
+#[must_use]
+pub struct SmallBoxTx<'a, Ctx, T, E, const N: usize = 4> {
+    // the transaction may mutate itself through a shared reference
+    inline: UnsafeCell<[MaybeUninit<usize>; N]>,
+    // null when stored inline
+    heap: *mut (),
+    run: unsafe fn(*const (), &mut Ctx) -> Result<T, E>,
+    label: unsafe fn(*const ()) -> Option<*const str>,
+    drop: unsafe fn(*mut ()),
+    // owns a transaction of a type borrowing for 'a
+    _phantom: PhantomData<Box<dyn Transaction<Ctx = Ctx, Item = T, Err = E> + 'a>>,
+}
+
+impl<'a, Ctx, T, E, const N: usize> SmallBoxTx<'a, Ctx, T, E, N> {
+    /// Box the transaction, inline if it fits
+    pub fn new<Tx>(tx: Tx) -> Self
+    where
+        Tx: Transaction<Ctx = Ctx, Item = T, Err = E> + 'a,
+    {
+        let mut inline = [MaybeUninit::<usize>::uninit(); N];
+        let fits = mem::size_of::<Tx>() <= mem::size_of_val(&inline)
+            && mem::align_of::<Tx>() <= mem::align_of::<usize>();
+        let heap = if fits {
+            // SAFETY: the storage is large and aligned enough for `Tx`
+            unsafe { ptr::write(inline.as_mut_ptr() as *mut Tx, tx) };
+            ptr::null_mut()
+        } else {
+            Box::into_raw(Box::new(tx)) as *mut ()
+        };
+        SmallBoxTx {
+            inline: UnsafeCell::new(inline),
+            heap,
+            run: run::<Tx>,
+            label: label::<Tx>,
+            drop: if fits { drop_inline::<Tx> } else { drop_heap::<Tx> },
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Whether the transaction is stored inline rather than on the heap
+    pub fn is_inline(&self) -> bool {
+        self.heap.is_null()
+    }
+
+    fn tx(&self) -> *mut () {
+        if self.is_inline() {
+            self.inline.get() as *mut ()
+        } else {
+            self.heap
+        }
+    }
+}
+
+unsafe fn run<Tx>(tx: *const (), ctx: &mut Tx::Ctx) -> Result<Tx::Item, Tx::Err>
+where
+    Tx: Transaction,
+{
+    (*(tx as *const Tx)).run(ctx)
+}
+
+unsafe fn label<Tx>(tx: *const ()) -> Option<*const str>
+where
+    Tx: Transaction,
+{
+    (*(tx as *const Tx)).label().map(|label| label as *const str)
+}
+
+unsafe fn drop_inline<Tx>(tx: *mut ()) {
+    ptr::drop_in_place(tx as *mut Tx)
+}
+
+unsafe fn drop_heap<Tx>(tx: *mut ()) {
+    drop(Box::from_raw(tx as *mut Tx))
+}
+
+impl<'a, Ctx, T, E, const N: usize> Transaction for SmallBoxTx<'a, Ctx, T, E, N> {
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        // SAFETY: `run` is of the type of the transaction at `tx`
+        unsafe { (self.run)(self.tx(), ctx) }
+    }
+
+    fn label(&self) -> Option<&str> {
+        // SAFETY: the label borrows the transaction, which lives as long as
+        // `self`
+        unsafe { (self.label)(self.tx()).map(|label| &*label) }
+    }
+}
+
+impl<'a, Ctx, T, E, const N: usize> Drop for SmallBoxTx<'a, Ctx, T, E, N> {
+    fn drop(&mut self) {
+        // SAFETY: `drop` is of the type and the storage of the transaction
+        // at `tx`, which is dropped only once
+        unsafe { (self.drop)(self.tx()) }
+    }
+}
+
+impl<'a, Ctx, T, E, const N: usize> fmt::Debug for SmallBoxTx<'a, Ctx, T, E, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SmallBoxTx")
+            .field("inline", &self.is_inline())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,204 @@
+use std::marker::PhantomData;
+
+use crate::Transaction;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// A context threading the user state `S` through the transaction on top of
+/// the backend context `C`.
+///
+/// Transactions written for `C` can be run in it with `adapt_ctx`.
+#[derive(Debug)]
+pub struct StateCtx<C, S> {
+    ctx: C,
+    state: S,
+}
+
+impl<C, S> StateCtx<C, S> {
+    /// wrap the context with the initial state
+    pub fn new(ctx: C, state: S) -> Self {
+        StateCtx { ctx, state }
+    }
+
+    /// The current state
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// The current state
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// The backend context
+    pub fn inner(&self) -> &C {
+        &self.ctx
+    }
+
+    /// The backend context
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+
+    /// Unwrap the backend context and the state
+    pub fn into_parts(self) -> (C, S) {
+        (self.ctx, self.state)
+    }
+}
+
+impl<C, S> AsMut<C> for StateCtx<C, S> {
+    fn as_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+}
+
+/// Get a copy of the current state.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{get_state, set_state, StateCtx};
+///
+/// # fn main() {
+/// let bump = || get_state::<(), u32, ()>().and_then(|n| set_state(n + 1));
+/// let tx = bump().and_then(move |()| bump()).and_then(|()| get_state());
+/// let mut ctx = StateCtx::new((), 0);
+/// assert_eq!(tx.run(&mut ctx), Ok(2));
+/// # }
+/// ```
+pub fn get_state<C, S, E>() -> GetState<C, S, E>
+where
+    S: Clone,
+{
+    GetState { _phantom: PhantomData }
+}
+
+/// The result of `get_state`
+#[derive(Debug)]
+#[must_use]
+pub struct GetState<C, S, E> {
+    _phantom: PhantomData<(C, S, E)>,
+}
+
+impl<C, S, E> Transaction for GetState<C, S, E>
+where
+    S: Clone,
+{
+    type Ctx = StateCtx<C, S>;
+    type Item = S;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Ok(ctx.state.clone())
+    }
+}
+
+impl<C, S, E> Visit for GetState<C, S, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("get_state"));
+    }
+}
+
+/// Replace the current state.
+pub fn set_state<C, S, E>(state: S) -> SetState<C, S, E>
+where
+    S: Clone,
+{
+    SetState {
+        state,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `set_state`
+#[derive(Debug)]
+#[must_use]
+pub struct SetState<C, S, E> {
+    state: S,
+    _phantom: PhantomData<(C, E)>,
+}
+
+impl<C, S, E> Transaction for SetState<C, S, E>
+where
+    S: Clone,
+{
+    type Ctx = StateCtx<C, S>;
+    type Item = ();
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.state = self.state.clone();
+        Ok(())
+    }
+}
+
+impl<C, S, E> Visit for SetState<C, S, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("set_state"));
+    }
+}
+
+/// Receive the state together with the backend context and perform
+/// computation.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{with_state, StateCtx};
+///
+/// # fn main() {
+/// // count the rows pushed across the steps
+/// let push = |row: i32| {
+///     with_state(move |count: &mut usize, rows: &mut Vec<i32>| -> Result<(), ()> {
+///         rows.push(row);
+///         *count += 1;
+///         Ok(())
+///     })
+/// };
+/// let tx = push(1).and_then(move |()| push(2));
+/// let mut ctx = StateCtx::new(Vec::new(), 0);
+/// tx.run(&mut ctx).unwrap();
+/// assert_eq!(ctx.into_parts(), (vec![1, 2], 2));
+/// # }
+/// ```
+pub fn with_state<C, S, F, T, E>(f: F) -> WithState<C, S, F>
+where
+    F: Fn(&mut S, &mut C) -> Result<T, E>,
+{
+    WithState {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_state`
+#[derive(Debug)]
+#[must_use]
+pub struct WithState<C, S, F> {
+    f: F,
+    _phantom: PhantomData<(C, S)>,
+}
+
+impl<C, S, F, T, E> Transaction for WithState<C, S, F>
+where
+    F: Fn(&mut S, &mut C) -> Result<T, E>,
+{
+    type Ctx = StateCtx<C, S>;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        (self.f)(&mut ctx.state, &mut ctx.ctx)
+    }
+}
+
+impl<C, S, F> Visit for WithState<C, S, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_state"));
+    }
+}
@@ -0,0 +1,377 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_leaf, visit_node, Node, Visit, Visitor};
+
+thread_local!(static TENANTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) });
+
+/// Contexts which can be scoped to a tenant, e.g. by switching the schema,
+/// setting the tenant of the row-level security policies, or choosing the
+/// prefix of the keys, for `in_tenant`
+pub trait TenantScope {
+    /// The error of scoping
+    type Err;
+
+    /// Scope the following statements to the tenant
+    fn scope_to(&mut self, tenant: &str) -> Result<(), Self::Err>;
+}
+
+/// A context of type `C` for a tenant. The transactions of `C` run on it by
+/// `in_tenant`, which scopes it to the tenant first.
+#[derive(Debug)]
+pub struct TenantCtx<C> {
+    tenant: String,
+    inner: C,
+}
+
+impl<C> TenantCtx<C> {
+    /// Wrap the context for the tenant
+    pub fn new<S>(tenant: S, inner: C) -> Self
+    where
+        S: Into<String>,
+    {
+        TenantCtx {
+            tenant: tenant.into(),
+            inner,
+        }
+    }
+
+    /// The tenant
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// The context, not scoped yet
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Unwrap the context
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+/// The error of a transaction for a tenant, by `for_tenant`, run in another
+/// tenant, or of a `with_tenant` run outside of `in_tenant`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossTenant {
+    /// The tenant the run is scoped to, if any
+    pub current: Option<String>,
+    /// The tenant the transaction is for, if known
+    pub expected: Option<String>,
+}
+
+impl fmt::Display for CrossTenant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.expected, &self.current) {
+            (Some(expected), Some(current)) => {
+                write!(f, "a transaction of tenant {} ran in tenant {}", expected, current)
+            }
+            (Some(expected), None) => write!(f, "a transaction of tenant {} ran outside of a tenant", expected),
+            (None, _) => write!(f, "a transaction needing a tenant ran outside of a tenant"),
+        }
+    }
+}
+
+impl Error for CrossTenant {}
+
+// the tenant of the innermost `in_tenant` or `scoped_to_tenant` running on
+// the thread
+fn current_tenant() -> Option<String> {
+    TENANTS.with(|tenants| tenants.borrow().last().cloned())
+}
+
+/// Run the transaction on the context of the tenant, scoping it to the
+/// tenant by `TenantScope` first. Use `scoped_to_tenant` for the contexts
+/// made by the runners. The `for_tenant`s and `with_tenant`s of
+/// the transaction, even those built at run time, check the tenant when
+/// they run, so a transaction built for another tenant fails with
+/// `CrossTenant` instead of reaching its data.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::collections::HashMap;
+/// use transaction::prelude::*;
+/// use transaction::{for_tenant, with_tenant, CrossTenant, TenantCtx, TenantScope};
+///
+/// #[derive(Default)]
+/// struct Db {
+///     statements: Vec<String>,
+///     rows: HashMap<String, i64>,
+/// }
+///
+/// impl TenantScope for Db {
+///     type Err = CrossTenant;
+///
+///     fn scope_to(&mut self, tenant: &str) -> Result<(), CrossTenant> {
+///         self.statements.push(format!("SET search_path TO tenant_{}", tenant));
+///         Ok(())
+///     }
+/// }
+///
+/// // prefix the keys by the tenant
+/// fn incr(key: &'static str) -> impl Transaction<Ctx = Db, Item = i64, Err = CrossTenant> {
+///     with_tenant(move |tenant: &str, db: &mut Db| {
+///         let n = db.rows.entry(format!("{}:{}", tenant, key)).or_insert(0);
+///         *n += 1;
+///         Ok(*n)
+///     })
+/// }
+///
+/// # fn main() {
+/// let mut acme = TenantCtx::new("acme", Db::default());
+/// assert_eq!(incr("visits").join(incr("visits")).in_tenant().run(&mut acme), Ok((1, 2)));
+/// assert_eq!(acme.inner().statements, vec!["SET search_path TO tenant_acme"]);
+/// assert_eq!(acme.inner().rows.get("acme:visits"), Some(&2));
+///
+/// // a transaction built for another tenant doesn't run
+/// let globex = for_tenant("globex", incr("visits"));
+/// assert_eq!(
+///     globex.in_tenant().run(&mut acme),
+///     Err(CrossTenant { current: Some("acme".to_string()), expected: Some("globex".to_string()) })
+/// );
+///
+/// // or on a context which is not wrapped
+/// let mut db = Db::default();
+/// assert_eq!(incr("visits").scoped_to_tenant("globex").run(&mut db), Ok(1));
+/// assert_eq!(db.rows.get("globex:visits"), Some(&1));
+/// # }
+/// ```
+pub fn in_tenant<C, A>(a: A) -> InTenant<A::Tx>
+where
+    A: IntoTransaction<C>,
+    C: TenantScope,
+{
+    InTenant { tx: a.into_transaction() }
+}
+
+/// The result of `in_tenant`
+#[derive(Debug)]
+#[must_use]
+pub struct InTenant<Tx> {
+    tx: Tx,
+}
+
+impl<Tx> Transaction for InTenant<Tx>
+where
+    Tx: Transaction,
+    Tx::Ctx: TenantScope,
+    Tx::Err: From<<Tx::Ctx as TenantScope>::Err>,
+{
+    type Ctx = TenantCtx<Tx::Ctx>;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        run_in_tenant(&ctx.tenant, &self.tx, &mut ctx.inner)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for InTenant<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("in_tenant"), |v| self.tx.accept(v));
+    }
+}
+
+/// Run the transaction in the tenant like `in_tenant`, on the context of a
+/// runner scoped to the tenant first, e.g. the context of a database
+/// runner, which can't be wrapped in a `TenantCtx`.
+pub fn scoped_to_tenant<C, S, A>(tenant: S, a: A) -> ScopedToTenant<A::Tx>
+where
+    S: Into<String>,
+    A: IntoTransaction<C>,
+    C: TenantScope,
+{
+    ScopedToTenant {
+        tenant: tenant.into(),
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `scoped_to_tenant`
+#[derive(Debug)]
+#[must_use]
+pub struct ScopedToTenant<Tx> {
+    tenant: String,
+    tx: Tx,
+}
+
+impl<Tx> Transaction for ScopedToTenant<Tx>
+where
+    Tx: Transaction,
+    Tx::Ctx: TenantScope,
+    Tx::Err: From<<Tx::Ctx as TenantScope>::Err>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        run_in_tenant(&self.tenant, &self.tx, ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for ScopedToTenant<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("scoped_to_tenant"), |v| self.tx.accept(v));
+    }
+}
+
+// scope the context to the tenant and run the transaction in it
+fn run_in_tenant<Tx>(tenant: &str, tx: &Tx, ctx: &mut Tx::Ctx) -> Result<Tx::Item, Tx::Err>
+where
+    Tx: Transaction,
+    Tx::Ctx: TenantScope,
+    Tx::Err: From<<Tx::Ctx as TenantScope>::Err>,
+{
+    ctx.scope_to(tenant)?;
+    TENANTS.with(|tenants| tenants.borrow_mut().push(tenant.to_string()));
+    let _guard = PopGuard;
+    tx.run(ctx)
+}
+
+// the tenants entered on the thread, innermost last
+pub(crate) fn current() -> Vec<String> {
+    TENANTS.with(|tenants| tenants.borrow().clone())
+}
+
+// replace the tenants entered on the thread, returning those replaced
+pub(crate) fn replace(tenants: Vec<String>) -> Vec<String> {
+    TENANTS.with(|current| mem::replace(&mut *current.borrow_mut(), tenants))
+}
+
+// pops the tenant even if the transaction panics
+struct PopGuard;
+
+impl Drop for PopGuard {
+    fn drop(&mut self) {
+        TENANTS.with(|tenants| tenants.borrow_mut().pop());
+    }
+}
+
+/// Mark the transaction as built for the tenant, e.g. from the tenant of a
+/// request, so that it fails with `CrossTenant` unless it runs in the
+/// `in_tenant` of the same tenant. See `in_tenant`.
+pub fn for_tenant<Ctx, S, A>(tenant: S, a: A) -> ForTenant<A::Tx>
+where
+    S: Into<String>,
+    A: IntoTransaction<Ctx>,
+{
+    ForTenant {
+        tenant: tenant.into(),
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `for_tenant`
+#[derive(Debug)]
+#[must_use]
+pub struct ForTenant<Tx> {
+    tenant: String,
+    tx: Tx,
+}
+
+impl<Tx> Transaction for ForTenant<Tx>
+where
+    Tx: Transaction,
+    Tx::Err: From<CrossTenant>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let current = current_tenant();
+        if current.as_deref() != Some(self.tenant.as_str()) {
+            return Err(CrossTenant {
+                current,
+                expected: Some(self.tenant.clone()),
+            }
+            .into());
+        }
+        self.tx.run(ctx)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for ForTenant<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("for_tenant"), |v| self.tx.accept(v));
+    }
+}
+
+/// Receive the tenant of the `in_tenant` running the transaction together
+/// with the context and perform computation, e.g. prefixing the keys by the
+/// tenant. Fails with `CrossTenant` outside of `in_tenant`.
+pub fn with_tenant<Ctx, F, T, E>(f: F) -> WithTenant<Ctx, F>
+where
+    F: Fn(&str, &mut Ctx) -> Result<T, E>,
+{
+    WithTenant {
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_tenant`
+#[derive(Debug)]
+#[must_use]
+pub struct WithTenant<Ctx, F> {
+    f: F,
+    _phantom: PhantomData<Ctx>,
+}
+
+impl<Ctx, F, T, E> Transaction for WithTenant<Ctx, F>
+where
+    F: Fn(&str, &mut Ctx) -> Result<T, E>,
+    E: From<CrossTenant>,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match current_tenant() {
+            Some(tenant) => (self.f)(&tenant, ctx),
+            None => Err(CrossTenant {
+                current: None,
+                expected: None,
+            }
+            .into()),
+        }
+    }
+}
+
+impl<Ctx, F> Visit for WithTenant<Ctx, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_tenant"));
+    }
+}
@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn then<Ctx, A, F, B, Tx2>(a: A, f: F) -> Then<A::Tx, F, Tx2>
 where
@@ -39,3 +40,12 @@ where
         f(tx.run(ctx)).into_transaction().run(ctx)
     }
 }
+
+impl<Tx1, F, Tx2> Visit for Then<Tx1, F, Tx2>
+where
+    Tx1: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("then"), |v| self.tx.accept(v));
+    }
+}
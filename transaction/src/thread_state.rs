@@ -0,0 +1,98 @@
+use std::mem;
+use std::sync::Arc;
+
+use crate::clock::Instant;
+use crate::env::SharedEnv;
+use crate::progress::Progress;
+use crate::runner::{self, Deferred};
+use crate::{deadline, env, isolated, progress, tenant, IsolationLevel};
+
+// The state kept on the thread around a run: the environments provided, the
+// tenants, the deadline, the isolation level and the progress subscribers.
+// The combinators running branches on other threads, `par_join` and
+// `scope`, capture it on the thread of the run and enter it on those of the
+// branches, so that the branches run as they would on the thread of the
+// run. The actions the branches defer until the commit are handed back to
+// the run by `defer`.
+pub(crate) struct ThreadState {
+    envs: Vec<SharedEnv>,
+    tenants: Vec<String>,
+    deadlines: Vec<Option<Instant>>,
+    levels: Vec<Option<IsolationLevel>>,
+    subscribers: Vec<Arc<dyn Progress>>,
+    // whether the run defers actions until it commits
+    deferring: bool,
+}
+
+impl ThreadState {
+    pub(crate) fn capture() -> Self {
+        ThreadState {
+            envs: env::current(),
+            tenants: tenant::current(),
+            deadlines: deadline::current(),
+            levels: isolated::current(),
+            subscribers: progress::current(),
+            deferring: runner::deferring(),
+        }
+    }
+
+    // Run `f` in the state, on the thread of a branch, and return the
+    // actions it deferred until the commit
+    pub(crate) fn enter<R, F>(&self, f: F) -> (R, Vec<Deferred>)
+    where
+        F: FnOnce() -> R,
+    {
+        let mut entered = Entered {
+            envs: env::replace(self.envs.clone()),
+            tenants: tenant::replace(self.tenants.clone()),
+            deadlines: deadline::replace(self.deadlines.clone()),
+            levels: isolated::replace(self.levels.clone()),
+            subscribers: progress::replace(self.subscribers.clone()),
+            frames: runner::replace_frames(if self.deferring { vec![Vec::new()] } else { Vec::new() }),
+            exited: false,
+        };
+        let ret = f();
+        (ret, entered.exit())
+    }
+}
+
+// Defer the actions deferred by a branch until the run on this thread
+// commits
+pub(crate) fn defer(actions: Vec<Deferred>) {
+    for action in actions {
+        runner::after_commit(action);
+    }
+}
+
+// the state of the thread replaced by `ThreadState::enter`, restored even if
+// the branch panics
+struct Entered {
+    envs: Vec<SharedEnv>,
+    tenants: Vec<String>,
+    deadlines: Vec<Option<Instant>>,
+    levels: Vec<Option<IsolationLevel>>,
+    subscribers: Vec<Arc<dyn Progress>>,
+    frames: Vec<Vec<Deferred>>,
+    exited: bool,
+}
+
+impl Entered {
+    fn exit(&mut self) -> Vec<Deferred> {
+        self.exited = true;
+        env::replace(mem::take(&mut self.envs));
+        tenant::replace(mem::take(&mut self.tenants));
+        deadline::replace(mem::take(&mut self.deadlines));
+        isolated::replace(mem::take(&mut self.levels));
+        progress::replace(mem::take(&mut self.subscribers));
+        let mut frames = runner::replace_frames(mem::take(&mut self.frames));
+        frames.pop().unwrap_or_default()
+    }
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        if !self.exited {
+            self.exit();
+        }
+    }
+}
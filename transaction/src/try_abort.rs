@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
 pub fn try_abort<Ctx, A, F, B>(a: A, f: F) -> TryAbort<A::Tx, F, B>
 where
@@ -40,3 +41,12 @@ where
         }
     }
 }
+
+impl<Tx, F, B> Visit for TryAbort<Tx, F, B>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("try_abort"), |v| self.tx.accept(v));
+    }
+}
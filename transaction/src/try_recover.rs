@@ -1,11 +1,14 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
 
+/// Recover from the error of the transaction by the closure, which may
+/// fail with another error.
 pub fn try_recover<Ctx, A, F, B>(a: A, f: F) -> TryRecover<A::Tx, F, B>
 where
     A: IntoTransaction<Ctx>,
-    F: Fn(A::Item) -> Result<B, A::Err>,
+    F: Fn(A::Err) -> Result<A::Item, B>,
 {
     TryRecover {
         tx: a.into_transaction(),
@@ -41,3 +44,12 @@ where
         }
     }
 }
+
+impl<Tx, F, B> Visit for TryRecover<Tx, F, B>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("try_recover"), |v| self.tx.accept(v));
+    }
+}
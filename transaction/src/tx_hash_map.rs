@@ -0,0 +1,151 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+use std::ops::Deref;
+
+use crate::undo::Undo;
+
+#[derive(Debug)]
+enum MapUndo<K, V> {
+    // restore the entry of the key, removing it if `None`
+    Entry(K, Option<V>),
+    Restore(HashMap<K, V>),
+}
+
+/// A `HashMap` recording an undo log of its mutations, so that `atomic`
+/// transactions undo them when they fail. It dereferences to the `HashMap`
+/// for reading.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::{TxHashMap, Undo};
+///
+/// # fn main() {
+/// let mut map = TxHashMap::new();
+/// map.insert("a", 1);
+/// let mark = map.mark();
+/// map.insert("a", 2);
+/// map.remove(&"a");
+/// map.insert("b", 3);
+/// map.undo_to(mark);
+/// assert_eq!(map.get(&"a"), Some(&1));
+/// assert_eq!(map.len(), 1);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TxHashMap<K, V> {
+    map: HashMap<K, V>,
+    log: Vec<MapUndo<K, V>>,
+}
+
+impl<K, V> TxHashMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Create an empty map
+    pub fn new() -> Self {
+        TxHashMap::from(HashMap::new())
+    }
+
+    /// Insert the value, and return the previous one
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let prev = self.map.insert(k.clone(), v);
+        self.log.push(MapUndo::Entry(k, prev.clone()));
+        prev
+    }
+
+    /// Remove the key, and return its value
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (k, v) = self.map.remove_entry(k)?;
+        self.log.push(MapUndo::Entry(k, Some(v.clone())));
+        Some(v)
+    }
+
+    /// Borrow the value mutably. The current value is recorded in the undo
+    /// log whether it is mutated or not.
+    pub fn get_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, v) = self.map.get_key_value(k)?;
+        let undo = MapUndo::Entry(key.clone(), Some(v.clone()));
+        self.log.push(undo);
+        self.map.get_mut(k)
+    }
+
+    /// Remove all the entries
+    pub fn clear(&mut self) {
+        let map = mem::take(&mut self.map);
+        self.log.push(MapUndo::Restore(map));
+    }
+
+    /// Unwrap the map, forgetting the undo log
+    pub fn into_inner(self) -> HashMap<K, V> {
+        self.map
+    }
+}
+
+impl<K, V> Undo for TxHashMap<K, V>
+where
+    K: Eq + Hash,
+{
+    type Mark = usize;
+
+    fn mark(&self) -> usize {
+        self.log.len()
+    }
+
+    fn undo_to(&mut self, mark: usize) {
+        while self.log.len() > mark {
+            match self.log.pop().expect("the undo log is longer than the mark") {
+                MapUndo::Entry(k, Some(v)) => {
+                    self.map.insert(k, v);
+                }
+                MapUndo::Entry(k, None) => {
+                    self.map.remove(&k);
+                }
+                MapUndo::Restore(map) => self.map = map,
+            }
+        }
+    }
+
+    fn commit(&mut self) {
+        self.log.clear();
+    }
+}
+
+impl<K, V> Default for TxHashMap<K, V> {
+    fn default() -> Self {
+        TxHashMap {
+            map: HashMap::new(),
+            log: Vec::new(),
+        }
+    }
+}
+
+impl<K, V> From<HashMap<K, V>> for TxHashMap<K, V> {
+    fn from(map: HashMap<K, V>) -> Self {
+        TxHashMap {
+            map,
+            log: Vec::new(),
+        }
+    }
+}
+
+impl<K, V> Deref for TxHashMap<K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &HashMap<K, V> {
+        &self.map
+    }
+}
@@ -0,0 +1,147 @@
+/// Early return of the error of a `Result` from the closure of `with_ctx`,
+/// converting it into the error of the transaction by `Into`, like `?` does
+/// by `From`. Evaluates to the success value otherwise.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate transaction;
+///
+/// use std::num::ParseIntError;
+/// use transaction::prelude::*;
+///
+/// # fn main() {
+/// #[derive(Debug, PartialEq)]
+/// enum Error {
+///     Parse,
+///     Empty,
+/// }
+///
+/// // a conversion which `?` can't use
+/// struct Foreign(ParseIntError);
+///
+/// impl Into<Error> for Foreign {
+///     fn into(self) -> Error {
+///         Error::Parse
+///     }
+/// }
+///
+/// let pop = with_ctx(|lines: &mut Vec<String>| -> Result<i32, Error> {
+///     let line = tx_try!(lines.pop().ok_or(Error::Empty));
+///     Ok(tx_try!(line.parse().map_err(Foreign)))
+/// });
+///
+/// let mut lines = vec!["x".to_string(), "42".to_string()];
+/// assert_eq!(pop.run(&mut lines), Ok(42));
+/// assert_eq!(pop.run(&mut lines), Err(Error::Parse));
+/// assert_eq!(pop.run(&mut lines), Err(Error::Empty));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tx_try {
+    ($e:expr) => {
+        match $e {
+            ::std::result::Result::Ok(t) => t,
+            ::std::result::Result::Err(e) => {
+                return ::std::result::Result::Err(::std::convert::Into::into(e))
+            }
+        }
+    };
+    ($e:expr,) => {
+        $crate::tx_try!($e)
+    };
+}
+
+/// Early return of the error from the closure of `with_ctx`, converting it
+/// into the error of the transaction by `Into`. `abort!(e)` is
+/// `return Err(e.into())`, and `abort!(cond, e)` returns only if `cond`
+/// holds.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// # fn main() {
+/// let withdraw = |amount: i64| {
+///     with_ctx(move |balance: &mut i64| -> Result<i64, String> {
+///         abort!(amount < 0, "negative amount");
+///         if *balance < amount {
+///             abort!(format!("insufficient balance {}", balance));
+///         }
+///         *balance -= amount;
+///         Ok(*balance)
+///     })
+/// };
+///
+/// let mut balance = 10;
+/// assert_eq!(withdraw(3).run(&mut balance), Ok(7));
+/// assert_eq!(withdraw(-1).run(&mut balance), Err("negative amount".to_string()));
+/// assert_eq!(withdraw(8).run(&mut balance), Err("insufficient balance 7".to_string()));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! abort {
+    ($e:expr) => {
+        return ::std::result::Result::Err(::std::convert::Into::into($e))
+    };
+    ($cond:expr, $e:expr) => {
+        if $cond {
+            $crate::abort!($e);
+        }
+    };
+    ($cond:expr, $e:expr,) => {
+        $crate::abort!($cond, $e)
+    };
+}
+
+/// Conversion of the error of a `Result` by `Into`, e.g. to give the error
+/// of a transaction in the closure of `with_ctx` without naming the
+/// conversion, or to use `?` on errors only convertible by `Into`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::num::ParseIntError;
+/// use transaction::prelude::*;
+///
+/// # fn main() {
+/// #[derive(Debug, PartialEq)]
+/// struct Error(String);
+///
+/// impl From<ParseIntError> for Error {
+///     fn from(e: ParseIntError) -> Self {
+///         Error(e.to_string())
+///     }
+/// }
+///
+/// let parse = with_ctx(|input: &mut String| input.trim().parse::<i32>().err_into::<Error>());
+/// let double = parse.and_then(|n| with_ctx(move |input: &mut String| {
+///     let m = input.parse::<i32>().err_into::<Error>()?;
+///     Ok(n + m)
+/// }));
+/// assert_eq!(double.run(&mut "21".to_string()), Ok(42));
+/// assert_eq!(double.run(&mut " 1".to_string()), Err(Error("invalid digit found in string".to_string())));
+/// # }
+/// ```
+pub trait ErrInto<T, E> {
+    /// Convert the error into `E2`
+    fn err_into<E2>(self) -> Result<T, E2>
+    where
+        E: Into<E2>;
+}
+
+impl<T, E> ErrInto<T, E> for Result<T, E> {
+    fn err_into<E2>(self) -> Result<T, E2>
+    where
+        E: Into<E2>,
+    {
+        self.map_err(Into::into)
+    }
+}
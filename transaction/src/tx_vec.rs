@@ -0,0 +1,168 @@
+use std::ops::Deref;
+
+use crate::undo::Undo;
+
+#[derive(Debug)]
+enum VecUndo<T> {
+    Pop,
+    Push(T),
+    Set(usize, T),
+    Insert(usize, T),
+    Remove(usize),
+    Swap(usize, usize),
+    Extend(Vec<T>),
+}
+
+/// A `Vec` recording an undo log of its mutations, so that `atomic`
+/// transactions undo them when they fail. It dereferences to the `Vec` for
+/// reading.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::{TxVec, Undo};
+///
+/// # fn main() {
+/// let mut v = TxVec::from(vec![1, 2, 3]);
+/// let mark = v.mark();
+/// v.push(4);
+/// v.remove(0);
+/// *v.get_mut(0).unwrap() = 20;
+/// v.truncate(1);
+/// assert_eq!(&v[..], &[20]);
+/// v.undo_to(mark);
+/// assert_eq!(&v[..], &[1, 2, 3]);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TxVec<T> {
+    vec: Vec<T>,
+    log: Vec<VecUndo<T>>,
+}
+
+impl<T> TxVec<T>
+where
+    T: Clone,
+{
+    /// Create an empty vector
+    pub fn new() -> Self {
+        TxVec::from(Vec::new())
+    }
+
+    /// Append the element
+    pub fn push(&mut self, value: T) {
+        self.vec.push(value);
+        self.log.push(VecUndo::Pop);
+    }
+
+    /// Remove the last element and return it
+    pub fn pop(&mut self) -> Option<T> {
+        let value = self.vec.pop()?;
+        self.log.push(VecUndo::Push(value.clone()));
+        Some(value)
+    }
+
+    /// Insert the element at the index, shifting the following ones. Panics
+    /// if the index is out of bounds like `Vec::insert`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.vec.insert(index, value);
+        self.log.push(VecUndo::Remove(index));
+    }
+
+    /// Remove the element at the index and return it, shifting the following
+    /// ones. Panics if the index is out of bounds like `Vec::remove`.
+    pub fn remove(&mut self, index: usize) -> T {
+        let value = self.vec.remove(index);
+        self.log.push(VecUndo::Insert(index, value.clone()));
+        value
+    }
+
+    /// Borrow the element mutably. The current element is recorded in the
+    /// undo log whether it is mutated or not.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let value = self.vec.get(index)?.clone();
+        self.log.push(VecUndo::Set(index, value));
+        self.vec.get_mut(index)
+    }
+
+    /// Swap the elements. Panics if either index is out of bounds like
+    /// `slice::swap`.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.vec.swap(a, b);
+        self.log.push(VecUndo::Swap(a, b));
+    }
+
+    /// Shorten the vector, dropping the rest. Does nothing if the vector is
+    /// not longer than `len`.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.vec.len() {
+            let rest = self.vec.split_off(len);
+            self.log.push(VecUndo::Extend(rest));
+        }
+    }
+
+    /// Remove all the elements
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Unwrap the vector, forgetting the undo log
+    pub fn into_inner(self) -> Vec<T> {
+        self.vec
+    }
+}
+
+impl<T> Undo for TxVec<T> {
+    type Mark = usize;
+
+    fn mark(&self) -> usize {
+        self.log.len()
+    }
+
+    fn undo_to(&mut self, mark: usize) {
+        while self.log.len() > mark {
+            match self.log.pop().expect("the undo log is longer than the mark") {
+                VecUndo::Pop => {
+                    self.vec.pop();
+                }
+                VecUndo::Push(value) => self.vec.push(value),
+                VecUndo::Set(index, value) => self.vec[index] = value,
+                VecUndo::Insert(index, value) => self.vec.insert(index, value),
+                VecUndo::Remove(index) => {
+                    self.vec.remove(index);
+                }
+                VecUndo::Swap(a, b) => self.vec.swap(a, b),
+                VecUndo::Extend(rest) => self.vec.extend(rest),
+            }
+        }
+    }
+
+    fn commit(&mut self) {
+        self.log.clear();
+    }
+}
+
+impl<T> Default for TxVec<T> {
+    fn default() -> Self {
+        TxVec::from(Vec::new())
+    }
+}
+
+impl<T> From<Vec<T>> for TxVec<T> {
+    fn from(vec: Vec<T>) -> Self {
+        TxVec {
+            vec,
+            log: Vec::new(),
+        }
+    }
+}
+
+impl<T> Deref for TxVec<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.vec
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Contexts recording an undo log of their mutations, like `TxHashMap` and
+/// `TxVec`. Tuples of them record one too, so they can be combined into a
+/// context.
+pub trait Undo {
+    /// A position in the undo log
+    type Mark;
+
+    /// The current position in the undo log
+    fn mark(&self) -> Self::Mark;
+
+    /// Undo the mutations made after the mark, latest first
+    fn undo_to(&mut self, mark: Self::Mark);
+
+    /// Forget the undo log, keeping the mutations. Call this once the
+    /// outermost transaction succeeded, or the log keeps growing.
+    fn commit(&mut self);
+}
+
+impl<A, B> Undo for (A, B)
+where
+    A: Undo,
+    B: Undo,
+{
+    type Mark = (A::Mark, B::Mark);
+
+    fn mark(&self) -> Self::Mark {
+        (self.0.mark(), self.1.mark())
+    }
+
+    fn undo_to(&mut self, mark: Self::Mark) {
+        self.0.undo_to(mark.0);
+        self.1.undo_to(mark.1);
+    }
+
+    fn commit(&mut self) {
+        self.0.commit();
+        self.1.commit();
+    }
+}
+
+impl<A, B, C> Undo for (A, B, C)
+where
+    A: Undo,
+    B: Undo,
+    C: Undo,
+{
+    type Mark = (A::Mark, B::Mark, C::Mark);
+
+    fn mark(&self) -> Self::Mark {
+        (self.0.mark(), self.1.mark(), self.2.mark())
+    }
+
+    fn undo_to(&mut self, mark: Self::Mark) {
+        self.0.undo_to(mark.0);
+        self.1.undo_to(mark.1);
+        self.2.undo_to(mark.2);
+    }
+
+    fn commit(&mut self) {
+        self.0.commit();
+        self.1.commit();
+        self.2.commit();
+    }
+}
+
+/// Run the transaction on a context recording an undo log, undoing its
+/// mutations if it fails. Nested ones undo only their own mutations.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{TxHashMap, TxVec, Undo};
+///
+/// type Ctx = (TxHashMap<&'static str, u32>, TxVec<String>);
+///
+/// fn withdraw(amount: u32) -> impl Transaction<Ctx = Ctx, Item = (), Err = String> {
+///     with_ctx(move |(balances, log): &mut Ctx| {
+///         log.push(format!("withdraw {}", amount));
+///         let balance = balances.get_mut(&"alice").unwrap();
+///         *balance = balance.checked_sub(amount).ok_or("insufficient funds")?;
+///         Ok(())
+///     })
+///     .atomic()
+/// }
+///
+/// # fn main() {
+/// let mut ctx: Ctx = (TxHashMap::new(), TxVec::new());
+/// ctx.0.insert("alice", 100);
+/// ctx.commit();
+///
+/// assert_eq!(withdraw(30).run(&mut ctx), Ok(()));
+/// assert_eq!(withdraw(80).run(&mut ctx), Err("insufficient funds".to_string()));
+/// ctx.commit();
+/// assert_eq!(ctx.0[&"alice"], 70);
+/// assert_eq!(&ctx.1[..], &["withdraw 30".to_string()]);
+/// # }
+/// ```
+pub fn atomic<Ctx, A>(a: A) -> Atomic<A::Tx>
+where
+    Ctx: Undo,
+    A: IntoTransaction<Ctx>,
+{
+    Atomic { tx: a.into_transaction() }
+}
+
+/// The result of `atomic`
+#[derive(Debug)]
+#[must_use]
+pub struct Atomic<Tx> {
+    tx: Tx,
+}
+
+impl<Tx> Transaction for Atomic<Tx>
+where
+    Tx: Transaction,
+    Tx::Ctx: Undo,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let mark = ctx.mark();
+        let ret = self.tx.run(ctx);
+        if ret.is_err() {
+            ctx.undo_to(mark);
+        }
+        ret
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Atomic<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("atomic"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,871 @@
+//! The unit of work: the entities a transaction loads, creates, modifies and
+//! removes are tracked in a `UnitOfWork` wrapping the backend context, and
+//! written back together just before the commit.
+//!
+//! The entities are loaded through an identity map, so that the same entity
+//! is loaded once per unit of work and reflects the changes registered so
+//! far. `flush` writes the new and dirty entities in the order of the
+//! dependencies between the entity types, e.g. customers before their
+//! orders, and of the references between the entities of a type, e.g.
+//! managers before their employees, and deletes the removed ones in the
+//! reverse order. The backend maps the entities by implementing `Mapper`
+//! for each type.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate transaction;
+//!
+//! use std::any::TypeId;
+//! use std::convert::Infallible;
+//! use std::error::Error;
+//! use transaction::prelude::*;
+//! use transaction::unit_of_work::{flushed, Entity, Mapper, Persistence, UnitOfWork};
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! struct Customer {
+//!     id: u32,
+//!     name: String,
+//! }
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! struct Order {
+//!     id: u32,
+//!     customer: u32,
+//! }
+//!
+//! impl Entity for Customer {
+//!     type Id = u32;
+//!     fn id(&self) -> u32 {
+//!         self.id
+//!     }
+//! }
+//!
+//! impl Entity for Order {
+//!     type Id = u32;
+//!     fn id(&self) -> u32 {
+//!         self.id
+//!     }
+//!     // an order refers to its customer
+//!     fn dependencies() -> Vec<TypeId> {
+//!         vec![TypeId::of::<Customer>()]
+//!     }
+//! }
+//!
+//! // a database logging its statements
+//! #[derive(Default)]
+//! struct Db(Vec<String>);
+//!
+//! impl Persistence for Db {
+//!     type Error = Infallible;
+//! }
+//!
+//! impl Mapper<Customer> for Db {
+//!     fn find(&mut self, id: &u32) -> Result<Option<Customer>, Infallible> {
+//!         self.0.push(format!("SELECT customer {}", id));
+//!         Ok(Some(Customer { id: *id, name: "alice".to_string() }))
+//!     }
+//!     fn insert(&mut self, c: &Customer) -> Result<(), Infallible> {
+//!         Ok(self.0.push(format!("INSERT customer {}", c.id)))
+//!     }
+//!     fn update(&mut self, c: &Customer) -> Result<(), Infallible> {
+//!         Ok(self.0.push(format!("UPDATE customer {} {}", c.id, c.name)))
+//!     }
+//!     fn delete(&mut self, id: &u32) -> Result<(), Infallible> {
+//!         Ok(self.0.push(format!("DELETE customer {}", id)))
+//!     }
+//! }
+//! # impl Mapper<Order> for Db {
+//! #     fn find(&mut self, _: &u32) -> Result<Option<Order>, Infallible> { Ok(None) }
+//! #     fn insert(&mut self, o: &Order) -> Result<(), Infallible> {
+//! #         Ok(self.0.push(format!("INSERT order {}", o.id)))
+//! #     }
+//! #     fn update(&mut self, o: &Order) -> Result<(), Infallible> {
+//! #         Ok(self.0.push(format!("UPDATE order {}", o.id)))
+//! #     }
+//! #     fn delete(&mut self, id: &u32) -> Result<(), Infallible> {
+//! #         Ok(self.0.push(format!("DELETE order {}", id)))
+//! #     }
+//! # }
+//!
+//! # fn main() {
+//! let tx = with_ctx(|uow: &mut UnitOfWork<Db>| -> Result<(), Box<dyn Error>> {
+//!     uow.register_new(Order { id: 10, customer: 2 });
+//!     uow.register_new(Customer { id: 2, name: "bob".to_string() });
+//!     let mut alice = uow.load::<Customer>(&1)?.unwrap();
+//!     alice.name = "alicia".to_string();
+//!     uow.register_dirty(alice);
+//!     // loaded from the identity map
+//!     assert_eq!(uow.load::<Customer>(&1)?.unwrap().name, "alicia");
+//!     Ok(())
+//! });
+//!
+//! let mut uow = UnitOfWork::new(Db::default());
+//! flushed(tx).run(&mut uow).unwrap();
+//! assert_eq!(
+//!     uow.into_inner().0,
+//!     ["SELECT customer 1", "INSERT customer 2", "UPDATE customer 1 alicia", "INSERT order 10"]
+//! );
+//! # }
+//! ```
+
+use std::any::{self, Any, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use crate::runner;
+use crate::visit::{visit_node, Node, Visit, Visitor};
+use crate::{IntoTransaction, Retryable, Transaction};
+
+/// Entities tracked by a `UnitOfWork`
+pub trait Entity: Clone + 'static {
+    /// The identity of the entities
+    type Id: Clone + Eq + Hash + 'static;
+
+    /// The identity of the entity
+    fn id(&self) -> Self::Id;
+
+    /// The entity types this type refers to, which are written before and
+    /// deleted after it. A type may refer to itself, see `references`.
+    fn dependencies() -> Vec<TypeId> {
+        Vec::new()
+    }
+
+    /// The identities of the entities of the same type this entity refers
+    /// to, e.g. the manager of an employee, which are written before and
+    /// deleted after it
+    fn references(&self) -> Vec<Self::Id> {
+        Vec::new()
+    }
+}
+
+/// Backend contexts storing entities, with the error of the storage
+pub trait Persistence {
+    /// The error of the storage
+    type Error;
+}
+
+/// Backend contexts storing the entities of type `E`, e.g. in a table
+pub trait Mapper<E>: Persistence
+where
+    E: Entity,
+{
+    /// The entity of the identity, if any
+    fn find(&mut self, id: &E::Id) -> Result<Option<E>, Self::Error>;
+
+    /// Store the new entity
+    fn insert(&mut self, entity: &E) -> Result<(), Self::Error>;
+
+    /// Store the modified entity
+    fn update(&mut self, entity: &E) -> Result<(), Self::Error>;
+
+    /// Delete the entity of the identity
+    fn delete(&mut self, id: &E::Id) -> Result<(), Self::Error>;
+}
+
+/// The error of `flush`
+#[derive(Debug)]
+pub enum FlushError<E> {
+    /// The entity types, or the entities of a type, refer to each other in a
+    /// cycle, so none of them can be written first
+    Cyclic {
+        /// The names of the entity types of the cycle
+        types: Vec<&'static str>,
+    },
+    /// The backend failed
+    Backend(E),
+}
+
+impl<E> fmt::Display for FlushError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FlushError::Cyclic { ref types } => write!(f, "cyclic references between {}", types.join(", ")),
+            FlushError::Backend(ref e) => write!(f, "failed to flush: {}", e),
+        }
+    }
+}
+
+impl<E> Error for FlushError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            FlushError::Backend(ref e) => Some(e),
+            FlushError::Cyclic { .. } => None,
+        }
+    }
+}
+
+// the references stay cyclic however many times the transaction runs
+impl<E> Retryable for FlushError<E>
+where
+    E: Retryable,
+{
+    fn is_retryable(&self) -> bool {
+        match *self {
+            FlushError::Cyclic { .. } => false,
+            FlushError::Backend(ref e) => e.is_retryable(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Clean,
+    New,
+    Dirty,
+    // written by a flush whose transaction is not known to be committed,
+    // New or Dirty again if it is rolled back
+    Written { new: bool },
+}
+
+impl State {
+    fn is_pending(self) -> bool {
+        self == State::New || self == State::Dirty
+    }
+}
+
+// the outcome of the transaction of a flush, told by the action it defers
+// until the commit: run once it commits, or dropped if it is rolled back
+const PENDING: u8 = 0;
+const COMMITTED: u8 = 1;
+const ROLLED_BACK: u8 = 2;
+
+struct Commit(Arc<AtomicU8>);
+
+impl Drop for Commit {
+    fn drop(&mut self) {
+        let _ = self.0.compare_exchange(PENDING, ROLLED_BACK, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
+
+// the entities of a type loaded and registered in a unit of work
+struct Table<E>
+where
+    E: Entity,
+{
+    // the identity map, with None for the removed entities
+    entities: HashMap<E::Id, (Option<E>, State)>,
+    // the identities in the order of their registrations
+    order: Vec<E::Id>,
+    // the references of the removed entities which were loaded
+    removed: HashMap<E::Id, Vec<E::Id>>,
+    // the entities written by the flushes of the transaction in progress
+    written: Vec<E::Id>,
+}
+
+// the tables of the entity types, erased to be kept together
+trait AnyTable<C>
+where
+    C: Persistence,
+{
+    fn type_name(&self) -> &'static str;
+    fn dependencies(&self) -> Vec<TypeId>;
+    fn is_acyclic(&self) -> bool;
+    fn write(&mut self, ctx: &mut C, track: bool) -> Result<bool, C::Error>;
+    fn delete(&mut self, ctx: &mut C, track: bool) -> Result<bool, C::Error>;
+    fn settle(&mut self, committed: bool);
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+impl<E> Table<E>
+where
+    E: Entity,
+{
+    fn new() -> Self {
+        Table {
+            entities: HashMap::new(),
+            order: Vec::new(),
+            removed: HashMap::new(),
+            written: Vec::new(),
+        }
+    }
+
+    fn register(&mut self, id: E::Id, entity: Option<E>, state: State) {
+        if !self.entities.contains_key(&id) {
+            self.order.push(id.clone());
+        }
+        self.entities.insert(id, (entity, state));
+    }
+
+    fn references(&self, id: &E::Id) -> Vec<E::Id> {
+        match self.entities.get(id) {
+            Some(&(Some(ref entity), _)) => entity.references(),
+            _ => self.removed.get(id).cloned().unwrap_or_default(),
+        }
+    }
+
+    // the pending entities to write, or removed if `removed`, in the order
+    // of their registrations after those they refer to, or None if they
+    // refer to each other in a cycle
+    fn sorted(&self, removed: bool) -> Option<Vec<E::Id>> {
+        fn visit<E: Entity>(
+            table: &Table<E>,
+            id: &E::Id,
+            removed: bool,
+            visiting: &mut Vec<E::Id>,
+            sorted: &mut Vec<E::Id>,
+            done: &mut HashSet<E::Id>,
+        ) -> bool {
+            if done.contains(id) {
+                return true;
+            }
+            if visiting.contains(id) {
+                return false;
+            }
+            visiting.push(id.clone());
+            for reference in table.references(id) {
+                // an entity referring to itself is written at once
+                if reference != *id
+                    && table.is_pending(&reference, removed)
+                    && !visit(table, &reference, removed, visiting, sorted, done)
+                {
+                    return false;
+                }
+            }
+            visiting.pop();
+            done.insert(id.clone());
+            sorted.push(id.clone());
+            true
+        }
+
+        let mut sorted = Vec::new();
+        let mut done = HashSet::new();
+        for id in &self.order {
+            if self.is_pending(id, removed) && !visit(self, id, removed, &mut Vec::new(), &mut sorted, &mut done) {
+                return None;
+            }
+        }
+        Some(sorted)
+    }
+
+    fn is_pending(&self, id: &E::Id, removed: bool) -> bool {
+        match self.entities.get(id) {
+            Some(&(ref entity, state)) => state.is_pending() && entity.is_none() == removed,
+            None => false,
+        }
+    }
+}
+
+impl<C, E> AnyTable<C> for Table<E>
+where
+    C: Mapper<E>,
+    E: Entity,
+{
+    fn type_name(&self) -> &'static str {
+        any::type_name::<E>()
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        E::dependencies()
+            .into_iter()
+            .filter(|&dependency| dependency != TypeId::of::<E>())
+            .collect()
+    }
+
+    fn is_acyclic(&self) -> bool {
+        self.sorted(false).is_some() && self.sorted(true).is_some()
+    }
+
+    fn write(&mut self, ctx: &mut C, track: bool) -> Result<bool, C::Error> {
+        let mut written = false;
+        for id in self.sorted(false).unwrap_or_default() {
+            if let Some(&mut (Some(ref entity), ref mut state)) = self.entities.get_mut(&id) {
+                let new = *state == State::New;
+                if new {
+                    ctx.insert(entity)?;
+                } else {
+                    ctx.update(entity)?;
+                }
+                *state = State::Written { new };
+                written = true;
+                if track {
+                    self.written.push(id);
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    fn delete(&mut self, ctx: &mut C, track: bool) -> Result<bool, C::Error> {
+        let mut deleted = false;
+        // those referring to an entity are deleted before it
+        for id in self.sorted(true).unwrap_or_default().iter().rev() {
+            if let Some(&mut (None, ref mut state)) = self.entities.get_mut(id) {
+                // a new entity removed before the flush was never stored
+                if *state == State::Dirty {
+                    ctx.delete(id)?;
+                    *state = State::Written { new: false };
+                    deleted = true;
+                    if track {
+                        self.written.push(id.clone());
+                    }
+                } else {
+                    *state = State::Clean;
+                }
+            }
+        }
+        Ok(deleted)
+    }
+
+    fn settle(&mut self, committed: bool) {
+        for id in self.written.drain(..) {
+            if let Some(&mut (_, ref mut state)) = self.entities.get_mut(&id) {
+                if let State::Written { new } = *state {
+                    *state = match (committed, new) {
+                        (true, _) => State::Clean,
+                        (false, true) => State::New,
+                        (false, false) => State::Dirty,
+                    };
+                }
+            }
+        }
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A context tracking the entities loaded and registered by the
+/// transactions on top of the backend context `C`, and writing the changes
+/// back by `flush`.
+///
+/// Transactions written for `C` can be run in it with `adapt_ctx`.
+pub struct UnitOfWork<C>
+where
+    C: Persistence,
+{
+    ctx: C,
+    // in the order of the first registrations, to flush in a stable order
+    tables: Vec<(TypeId, Box<dyn AnyTable<C>>)>,
+    // the outcome of the transaction of the entities written by `flush`
+    written: Option<Arc<AtomicU8>>,
+}
+
+impl<C> UnitOfWork<C>
+where
+    C: Persistence,
+{
+    /// wrap the context with no entities tracked
+    pub fn new(ctx: C) -> Self {
+        UnitOfWork {
+            ctx,
+            tables: Vec::new(),
+            written: None,
+        }
+    }
+
+    /// The backend context
+    pub fn inner(&self) -> &C {
+        &self.ctx
+    }
+
+    /// The backend context
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+
+    /// Unwrap the backend context, dropping the changes not flushed
+    pub fn into_inner(self) -> C {
+        self.ctx
+    }
+
+    fn table<E>(&mut self) -> &mut Table<E>
+    where
+        C: Mapper<E>,
+        E: Entity,
+    {
+        self.settle();
+        let type_id = TypeId::of::<E>();
+        let i = match self.tables.iter().position(|&(t, _)| t == type_id) {
+            Some(i) => i,
+            None => {
+                self.tables.push((type_id, Box::new(Table::<E>::new())));
+                self.tables.len() - 1
+            }
+        };
+        self.tables[i]
+            .1
+            .as_any()
+            .downcast_mut()
+            .expect("table of another entity type")
+    }
+
+    // mark the entities written clean once their transaction is committed,
+    // or to be written again once it is rolled back
+    fn settle(&mut self) {
+        let committed = match self.written.as_ref().map(|written| written.load(Ordering::SeqCst)) {
+            Some(COMMITTED) => true,
+            Some(ROLLED_BACK) => false,
+            _ => return,
+        };
+        self.written = None;
+        for (_, table) in &mut self.tables {
+            table.settle(committed);
+        }
+    }
+
+    /// Load the entity of the identity: from the identity map if it is
+    /// loaded or registered, or from the backend otherwise. A removed entity
+    /// is `None`.
+    pub fn load<E>(&mut self, id: &E::Id) -> Result<Option<E>, C::Error>
+    where
+        C: Mapper<E>,
+        E: Entity,
+    {
+        if let Some((entity, _)) = self.table::<E>().entities.get(id) {
+            return Ok(entity.clone());
+        }
+        let entity = Mapper::<E>::find(&mut self.ctx, id)?;
+        if let Some(ref entity) = entity {
+            self.table::<E>().register(id.clone(), Some(entity.clone()), State::Clean);
+        }
+        Ok(entity)
+    }
+
+    /// Register the entity to insert
+    pub fn register_new<E>(&mut self, entity: E)
+    where
+        C: Mapper<E>,
+        E: Entity,
+    {
+        self.table::<E>().register(entity.id(), Some(entity), State::New);
+    }
+
+    /// Register the entity to update. A new entity stays to be inserted.
+    pub fn register_dirty<E>(&mut self, entity: E)
+    where
+        C: Mapper<E>,
+        E: Entity,
+    {
+        let table = self.table::<E>();
+        let state = match table.entities.get(&entity.id()) {
+            Some(&(Some(_), State::New)) => State::New,
+            _ => State::Dirty,
+        };
+        table.register(entity.id(), Some(entity), state);
+    }
+
+    /// Register the entity of the identity to delete. A new entity is just
+    /// forgotten.
+    pub fn register_removed<E>(&mut self, id: E::Id)
+    where
+        C: Mapper<E>,
+        E: Entity,
+    {
+        let table = self.table::<E>();
+        let state = match table.entities.get(&id) {
+            Some(&(Some(_), State::New)) => State::New,
+            _ => State::Dirty,
+        };
+        if let Some(&(Some(ref entity), _)) = table.entities.get(&id) {
+            let references = entity.references();
+            table.removed.insert(id.clone(), references);
+        }
+        table.register(id, None, state);
+    }
+
+    /// Write the registered changes to the backend: insert and update the
+    /// entities of each type after those of the types it depends on, then
+    /// delete them in the reverse order. The entities of a type referring
+    /// to each other are written after, and deleted before, those they
+    /// refer to, as far as the removed ones were loaded. If the types, or
+    /// the entities of a type, refer to each other in a cycle, nothing is
+    /// written and `FlushError::Cyclic` is returned.
+    ///
+    /// The entities stay in the identity map, and are not written again
+    /// unless they are registered again. They are clean once the run of the
+    /// transaction commits, and to be written again if it is rolled back,
+    /// as told by the actions deferred until the commit, e.g. by
+    /// `RunnerBuilder::after_commit`. Outside of such a run nothing tells
+    /// whether it commits, and they are not written again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate transaction;
+    ///
+    /// use std::convert::Infallible;
+    /// use transaction::unit_of_work::{Entity, FlushError, Mapper, Persistence, UnitOfWork};
+    ///
+    /// #[derive(Debug, Clone)]
+    /// struct Employee {
+    ///     id: u32,
+    ///     manager: Option<u32>,
+    /// }
+    ///
+    /// impl Entity for Employee {
+    ///     type Id = u32;
+    ///     fn id(&self) -> u32 {
+    ///         self.id
+    ///     }
+    ///     fn references(&self) -> Vec<u32> {
+    ///         self.manager.into_iter().collect()
+    ///     }
+    /// }
+    ///
+    /// #[derive(Default)]
+    /// struct Db(Vec<String>);
+    ///
+    /// impl Persistence for Db {
+    ///     type Error = Infallible;
+    /// }
+    ///
+    /// impl Mapper<Employee> for Db {
+    ///     fn find(&mut self, _: &u32) -> Result<Option<Employee>, Infallible> {
+    ///         Ok(None)
+    ///     }
+    ///     fn insert(&mut self, e: &Employee) -> Result<(), Infallible> {
+    ///         Ok(self.0.push(format!("INSERT employee {}", e.id)))
+    ///     }
+    ///     fn update(&mut self, e: &Employee) -> Result<(), Infallible> {
+    ///         Ok(self.0.push(format!("UPDATE employee {}", e.id)))
+    ///     }
+    ///     fn delete(&mut self, id: &u32) -> Result<(), Infallible> {
+    ///         Ok(self.0.push(format!("DELETE employee {}", id)))
+    ///     }
+    /// }
+    ///
+    /// # fn main() {
+    /// let mut uow = UnitOfWork::new(Db::default());
+    /// uow.register_new(Employee { id: 2, manager: Some(1) });
+    /// uow.register_new(Employee { id: 1, manager: Some(1) });
+    /// uow.flush().unwrap();
+    /// assert_eq!(uow.inner().0, ["INSERT employee 1", "INSERT employee 2"]);
+    ///
+    /// // nobody can be inserted first
+    /// uow.register_new(Employee { id: 3, manager: Some(4) });
+    /// uow.register_new(Employee { id: 4, manager: Some(3) });
+    /// match uow.flush() {
+    ///     Err(FlushError::Cyclic { types }) => assert_eq!(types.len(), 1),
+    ///     ret => panic!("{:?}", ret),
+    /// }
+    /// assert_eq!(uow.inner().0.len(), 2);
+    /// # }
+    /// ```
+    pub fn flush(&mut self) -> Result<(), FlushError<C::Error>> {
+        self.settle();
+        let order = self.dependency_order()?;
+        if let Some((_, table)) = self.tables.iter().find(|(_, table)| !table.is_acyclic()) {
+            return Err(FlushError::Cyclic {
+                types: vec![table.type_name()],
+            });
+        }
+        let track = runner::deferring();
+        let mut written = false;
+        for &i in &order {
+            written |= self.tables[i].1.write(&mut self.ctx, track).map_err(FlushError::Backend)?;
+        }
+        for &i in order.iter().rev() {
+            written |= self.tables[i].1.delete(&mut self.ctx, track).map_err(FlushError::Backend)?;
+        }
+        // a later flush of the same transaction shares the outcome
+        if written && track && self.written.is_none() {
+            let outcome = Arc::new(AtomicU8::new(PENDING));
+            let commit = Commit(outcome.clone());
+            runner::after_commit(Box::new(move || commit.0.store(COMMITTED, Ordering::SeqCst)));
+            self.written = Some(outcome);
+        }
+        Ok(())
+    }
+
+    // the indices of the tables, those of the dependencies first, or the
+    // types of a cycle
+    fn dependency_order<E>(&self) -> Result<Vec<usize>, FlushError<E>> {
+        fn visit<C: Persistence>(
+            tables: &[(TypeId, Box<dyn AnyTable<C>>)],
+            i: usize,
+            visiting: &mut Vec<usize>,
+            order: &mut Vec<usize>,
+        ) -> Result<(), Vec<&'static str>> {
+            if order.contains(&i) {
+                return Ok(());
+            }
+            if let Some(start) = visiting.iter().position(|&j| j == i) {
+                return Err(visiting[start..].iter().map(|&j| tables[j].1.type_name()).collect());
+            }
+            visiting.push(i);
+            for dependency in tables[i].1.dependencies() {
+                if let Some(j) = tables.iter().position(|&(t, _)| t == dependency) {
+                    visit(tables, j, visiting, order)?;
+                }
+            }
+            visiting.pop();
+            order.push(i);
+            Ok(())
+        }
+
+        let mut order = Vec::with_capacity(self.tables.len());
+        for i in 0..self.tables.len() {
+            visit(&self.tables, i, &mut Vec::new(), &mut order).map_err(|types| FlushError::Cyclic { types })?;
+        }
+        Ok(order)
+    }
+}
+
+impl<C> AsMut<C> for UnitOfWork<C>
+where
+    C: Persistence,
+{
+    fn as_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+}
+
+impl<C> fmt::Debug for UnitOfWork<C>
+where
+    C: Persistence + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UnitOfWork")
+            .field("ctx", &self.ctx)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Run the transaction and flush the unit of work after it succeeds, as the
+/// last step before the commit.
+///
+/// The entities flushed by a run rolled back are written again by the next
+/// flush, as long as the run defers actions until it commits, e.g. by
+/// `RunnerBuilder::after_commit`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use std::cell::RefCell;
+/// use std::convert::Infallible;
+/// use std::rc::Rc;
+/// use transaction::prelude::*;
+/// use transaction::runner::{Backend, RunOptions, RunnerBuilder};
+/// use transaction::unit_of_work::{flushed, Entity, FlushError, Mapper, Persistence, UnitOfWork};
+///
+/// #[derive(Debug, Clone)]
+/// struct Customer(u32);
+///
+/// impl Entity for Customer {
+///     type Id = u32;
+///     fn id(&self) -> u32 {
+///         self.0
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct Db(Vec<String>);
+///
+/// impl Persistence for Db {
+///     type Error = Infallible;
+/// }
+///
+/// impl Mapper<Customer> for Db {
+///     fn find(&mut self, _: &u32) -> Result<Option<Customer>, Infallible> {
+///         Ok(None)
+///     }
+///     fn insert(&mut self, c: &Customer) -> Result<(), Infallible> {
+///         Ok(self.0.push(format!("INSERT customer {}", c.0)))
+///     }
+///     fn update(&mut self, c: &Customer) -> Result<(), Infallible> {
+///         Ok(self.0.push(format!("UPDATE customer {}", c.0)))
+///     }
+///     fn delete(&mut self, id: &u32) -> Result<(), Infallible> {
+///         Ok(self.0.push(format!("DELETE customer {}", id)))
+///     }
+/// }
+///
+/// // a backend keeping the unit of work across the runs, committing those
+/// // succeeding
+/// struct Store(Rc<RefCell<UnitOfWork<Db>>>);
+///
+/// impl<T, E> Backend<T, E> for Store {
+///     type Ctx = UnitOfWork<Db>;
+///
+///     fn run<Tx>(&self, tx: &Tx, _: RunOptions) -> Result<T, E>
+///     where
+///         Tx: Transaction<Ctx = UnitOfWork<Db>, Item = T, Err = E> + ?Sized,
+///     {
+///         tx.run(&mut self.0.borrow_mut())
+///     }
+/// }
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// enum Error {
+///     Conflict,
+///     Flush,
+/// }
+///
+/// impl From<FlushError<Infallible>> for Error {
+///     fn from(_: FlushError<Infallible>) -> Self {
+///         Error::Flush
+///     }
+/// }
+///
+/// # fn main() {
+/// let uow = Rc::new(RefCell::new(UnitOfWork::new(Db::default())));
+/// let runner = RunnerBuilder::new(Store(uow.clone())).after_commit().build();
+///
+/// let register = with_ctx(|uow: &mut UnitOfWork<Db>| Ok(uow.register_new(Customer(1))));
+/// let conflict = flushed(register).and_then(|()| err::<_, (), _>(Error::Conflict));
+/// assert_eq!(runner.run(conflict), Err(Error::Conflict));
+/// // inserted again, as the run was rolled back
+/// assert_eq!(runner.run(flushed(ok::<_, _, Error>(()))), Ok(()));
+/// // and clean once committed
+/// assert_eq!(runner.run(flushed(ok::<_, _, Error>(()))), Ok(()));
+/// assert_eq!(uow.borrow().inner().0, ["INSERT customer 1", "INSERT customer 1"]);
+/// # }
+/// ```
+pub fn flushed<C, A>(a: A) -> Flushed<A::Tx>
+where
+    C: Persistence,
+    A: IntoTransaction<UnitOfWork<C>>,
+    A::Err: From<FlushError<C::Error>>,
+{
+    Flushed { tx: a.into_transaction() }
+}
+
+/// The result of `flushed`
+#[derive(Debug)]
+#[must_use]
+pub struct Flushed<Tx> {
+    tx: Tx,
+}
+
+impl<C, Tx> Transaction for Flushed<Tx>
+where
+    C: Persistence,
+    Tx: Transaction<Ctx = UnitOfWork<C>>,
+    Tx::Err: From<FlushError<C::Error>>,
+{
+    type Ctx = UnitOfWork<C>;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let item = self.tx.run(ctx)?;
+        ctx.flush()?;
+        Ok(item)
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Tx> Visit for Flushed<Tx>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("flushed"), |v| self.tx.accept(v));
+    }
+}
@@ -0,0 +1,129 @@
+use std::any::Any;
+
+use crate::describe::{describe, PlanDescription};
+
+/// A node of a composed transaction as seen by a `Visitor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Node<'a> {
+    /// The name of the combinator or leaf, e.g. `"and_then"` or `"ok"`
+    pub kind: &'static str,
+    /// The label attached by `named`, if any
+    pub label: Option<&'a str>,
+}
+
+impl<'a> Node<'a> {
+    /// make an unlabeled node
+    pub fn new(kind: &'static str) -> Self {
+        Node { kind, label: None }
+    }
+}
+
+/// Receiver of the nodes of a transaction walked by `Visit::accept`.
+/// Nodes are reported in pre-order: `enter` is called for a node, then for
+/// its children, then `leave` for the node.
+pub trait Visitor {
+    /// Called when walking into a node
+    fn enter(&mut self, node: &Node);
+
+    /// Called when all the children of the node are walked
+    fn leave(&mut self, _node: &Node) {}
+
+    /// Called by the leaves reading by a key known before they run, e.g.
+    /// `batched_get`, between `enter` and `leave` of the leaf
+    fn read_key(&mut self, _key: &dyn Any) {}
+}
+
+/// Transactions whose structure can be walked without running them.
+///
+/// Only the statically known part of the transaction is visible. The
+/// transactions produced by closures (e.g. the one returned from the function
+/// given to `and_then`) are built at run time, so they are not walked.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// # fn main() {
+/// let tx = ok::<(), i32, ()>(1)
+///     .named("one")
+///     .join(ok(2).named("two"))
+///     .map(|(a, b)| a + b);
+/// // map -> join -> (named -> ok, named -> ok)
+/// assert_eq!(tx.node_count(), 6);
+/// assert_eq!(tx.labels(), vec!["one", "two"]);
+/// # }
+/// ```
+pub trait Visit {
+    /// Walk the transaction tree with the visitor
+    fn accept(&self, visitor: &mut dyn Visitor);
+
+    /// Count the nodes of the transaction tree
+    fn node_count(&self) -> usize {
+        struct Count(usize);
+        impl Visitor for Count {
+            fn enter(&mut self, _node: &Node) {
+                self.0 += 1;
+            }
+        }
+        let mut count = Count(0);
+        self.accept(&mut count);
+        count.0
+    }
+
+    /// Collect the labels attached by `named`, in pre-order
+    fn labels(&self) -> Vec<String> {
+        struct Labels(Vec<String>);
+        impl Visitor for Labels {
+            fn enter(&mut self, node: &Node) {
+                if let Some(label) = node.label {
+                    self.0.push(label.to_string());
+                }
+            }
+        }
+        let mut labels = Labels(Vec::new());
+        self.accept(&mut labels);
+        labels.0
+    }
+
+    /// Describe the steps of the transaction without running it
+    fn describe(&self) -> PlanDescription {
+        describe(self)
+    }
+}
+
+/// Walk a leaf node, which has no children.
+pub fn visit_leaf(visitor: &mut dyn Visitor, node: Node) {
+    visitor.enter(&node);
+    visitor.leave(&node);
+}
+
+/// Walk a node and then its children walked by `children`.
+pub fn visit_node<F>(visitor: &mut dyn Visitor, node: Node, children: F)
+where
+    F: FnOnce(&mut dyn Visitor),
+{
+    visitor.enter(&node);
+    children(visitor);
+    visitor.leave(&node);
+}
+
+impl<T> Visit for Box<T>
+where
+    T: ?Sized + Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        (**self).accept(visitor)
+    }
+}
+
+impl<T> Visit for &T
+where
+    T: ?Sized + Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        (**self).accept(visitor)
+    }
+}
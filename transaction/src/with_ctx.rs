@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use crate::Transaction;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
 
 
 /// Receive the context from the executing transaction and perform computation.
@@ -33,3 +34,9 @@ where
         (self.f)(ctx)
     }
 }
+
+impl<Ctx, F> Visit for WithCtx<Ctx, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("with_ctx"));
+    }
+}
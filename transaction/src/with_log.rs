@@ -0,0 +1,126 @@
+use std::marker::PhantomData;
+
+use crate::Transaction;
+use crate::visit::{visit_leaf, Node, Visit, Visitor};
+
+/// A context accumulating log entries (e.g. domain events) told by the
+/// transaction on top of the backend context `C`.
+///
+/// Runners supporting it hand the entries over only when the transaction
+/// commits, so the events are never published for rolled back changes.
+/// Transactions written for `C` can be run in it with `adapt_ctx`.
+#[derive(Debug)]
+pub struct WithLog<C, L> {
+    ctx: C,
+    log: Vec<L>,
+}
+
+impl<C, L> WithLog<C, L> {
+    /// wrap the context with an empty log
+    pub fn new(ctx: C) -> Self {
+        WithLog {
+            ctx,
+            log: Vec::new(),
+        }
+    }
+
+    /// The entries told so far, oldest first
+    pub fn log(&self) -> &[L] {
+        &self.log
+    }
+
+    /// Take the entries told so far, leaving the log empty
+    pub fn take_log(&mut self) -> Vec<L> {
+        ::std::mem::take(&mut self.log)
+    }
+
+    /// The backend context
+    pub fn inner(&self) -> &C {
+        &self.ctx
+    }
+
+    /// The backend context
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+
+    /// Unwrap the backend context and the log
+    pub fn into_parts(self) -> (C, Vec<L>) {
+        (self.ctx, self.log)
+    }
+}
+
+impl<C, L> AsMut<C> for WithLog<C, L> {
+    fn as_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+}
+
+/// Append an entry to the log of the context.
+///
+/// The entries told by the failed steps recovered by e.g. `or_else` are kept.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+/// use transaction::{tell, WithLog};
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// enum Event {
+///     Deposited(i32),
+/// }
+///
+/// # fn main() {
+/// let deposit = |amount: i32| {
+///     with_ctx(move |balance: &mut i32| -> Result<(), ()> {
+///         *balance += amount;
+///         Ok(())
+///     }).adapt_ctx()
+///         .and_then(move |()| tell(Event::Deposited(amount)))
+/// };
+/// let mut ctx = WithLog::new(0);
+/// deposit(10).run(&mut ctx).unwrap();
+/// assert_eq!(*ctx.inner(), 10);
+/// assert_eq!(ctx.take_log(), vec![Event::Deposited(10)]);
+/// # }
+/// ```
+pub fn tell<C, L, E>(entry: L) -> Tell<C, L, E>
+where
+    L: Clone,
+{
+    Tell {
+        entry,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `tell`
+#[derive(Debug)]
+#[must_use]
+pub struct Tell<C, L, E> {
+    entry: L,
+    _phantom: PhantomData<(C, E)>,
+}
+
+impl<C, L, E> Transaction for Tell<C, L, E>
+where
+    L: Clone,
+{
+    type Ctx = WithLog<C, L>;
+    type Item = ();
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.log.push(self.entry.clone());
+        Ok(())
+    }
+}
+
+impl<C, L, E> Visit for Tell<C, L, E> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_leaf(visitor, Node::new("tell"));
+    }
+}
@@ -0,0 +1,80 @@
+use std::marker::PhantomData;
+
+use crate::{IntoTransaction, Transaction};
+use crate::visit::{visit_node, Node, Visit, Visitor};
+
+/// Run the transaction on a part of a larger context. `f` focuses the larger
+/// context on the part the transaction needs.
+///
+/// # Examples
+///
+/// ```
+/// extern crate transaction;
+///
+/// use transaction::prelude::*;
+///
+/// struct Counter(i32);
+/// struct App {
+///     counter: Counter,
+///     name: String,
+/// }
+///
+/// # fn main() {
+/// let incr = with_ctx(|c: &mut Counter| -> Result<i32, ()> {
+///     c.0 += 1;
+///     Ok(c.0)
+/// });
+/// let tx = incr.zoom(|app: &mut App| &mut app.counter);
+/// let mut app = App { counter: Counter(0), name: "app".to_string() };
+/// assert_eq!(tx.run(&mut app), Ok(1));
+/// # let _ = app.name;
+/// # }
+/// ```
+pub fn zoom<Ctx, A, Big, F>(a: A, f: F) -> Zoom<Big, A::Tx, F>
+where
+    A: IntoTransaction<Ctx>,
+    F: Fn(&mut Big) -> &mut Ctx,
+{
+    Zoom {
+        tx: a.into_transaction(),
+        f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `zoom`
+#[derive(Debug)]
+#[must_use]
+pub struct Zoom<Big, Tx, F> {
+    tx: Tx,
+    f: F,
+    _phantom: PhantomData<Big>,
+}
+
+impl<Big, Tx, F> Transaction for Zoom<Big, Tx, F>
+where
+    Tx: Transaction,
+    F: Fn(&mut Big) -> &mut Tx::Ctx,
+{
+    type Ctx = Big;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let Zoom { ref tx, ref f, .. } = *self;
+        tx.run(f(ctx))
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.tx.label()
+    }
+}
+
+impl<Big, Tx, F> Visit for Zoom<Big, Tx, F>
+where
+    Tx: Visit,
+{
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visit_node(visitor, Node::new("zoom"), |v| self.tx.accept(v));
+    }
+}
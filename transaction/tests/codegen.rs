@@ -0,0 +1,125 @@
+//! Check that the chains of combinators in `codegen/chains.rs` compile down
+//! to the same assembly as the code written by hand next to them, so that
+//! the abstraction stays zero-cost as the combinators grow.
+//!
+//! The chains are compiled with optimizations by the `rustc` which built
+//! this test, against `transaction` built with optimizations by cargo in a
+//! target directory of the test, whose rlib is the only one there.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+const CHAINS: &[&str] = &["map_and_then", "join", "branch", "recover"];
+
+// build `transaction` with its default features, returning the directory
+// of the artifacts, where its rlib is uplifted to `libtransaction.rlib`
+fn build() -> PathBuf {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("codegen");
+    let output = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["build", "--lib", "--release", "--manifest-path"])
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .output()
+        .expect("run cargo");
+    assert!(
+        output.status.success(),
+        "failed to build transaction:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    target_dir.join("release")
+}
+
+fn assembly() -> &'static str {
+    static ASSEMBLY: OnceLock<String> = OnceLock::new();
+    ASSEMBLY.get_or_init(|| {
+        let release = build();
+        let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("chains.s");
+        let chains = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/codegen/chains.rs");
+        let output = Command::new(env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()))
+            .args(["--edition", "2018", "--crate-type", "lib", "--crate-name", "chains"])
+            .args(["-C", "opt-level=3", "-C", "debuginfo=0", "--emit", "asm", "-o"])
+            .arg(&out)
+            .arg("-L")
+            .arg(format!("dependency={}", release.join("deps").display()))
+            .arg("--extern")
+            .arg(format!("transaction={}", release.join("libtransaction.rlib").display()))
+            .arg(&chains)
+            .output()
+            .expect("run rustc");
+        assert!(
+            output.status.success(),
+            "failed to compile the chains:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        fs::read_to_string(&out).expect("read the assembly")
+    })
+}
+
+// The instructions of the function, with the local labels numbered in the
+// order they appear and the comments and directives left out
+fn instructions(asm: &str, name: &str) -> Vec<String> {
+    let start = format!("{}:", name);
+    let lines = asm
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != start && line.trim_start_matches('_') != start)
+        .skip(1)
+        .take_while(|line| !line.contains("func_end") && !line.contains("endproc"));
+    let mut labels = HashMap::new();
+    let mut instructions = vec![];
+    for line in lines {
+        let line = line.split(&['#', ';'][..]).next().unwrap().trim();
+        let line = line.split("//").next().unwrap().trim();
+        if line.is_empty() || (line.starts_with('.') && !line.ends_with(':')) {
+            continue;
+        }
+        instructions.push(rename_labels(line, &mut labels));
+    }
+    assert!(!instructions.is_empty(), "`{}` is not in the assembly", name);
+    instructions
+}
+
+fn rename_labels(line: &str, labels: &mut HashMap<String, usize>) -> String {
+    let mut renamed = String::new();
+    let mut rest = line;
+    while let Some(i) = rest.find("LBB") {
+        let end = rest[i + 3..]
+            .find(|c: char| !c.is_ascii_digit() && c != '_')
+            .map_or(rest.len(), |j| i + 3 + j);
+        let next = labels.len();
+        let n = *labels.entry(rest[i..end].to_string()).or_insert(next);
+        renamed.push_str(&rest[..i]);
+        renamed.push_str(&format!("L{}", n));
+        rest = &rest[end..];
+    }
+    renamed.push_str(rest);
+    renamed
+}
+
+#[test]
+fn chains_compile_to_handwritten_code() {
+    let asm = assembly();
+    let mismatches = CHAINS
+        .iter()
+        .filter_map(|chain| {
+            let composed = instructions(asm, &format!("composed_{}", chain));
+            let handwritten = instructions(asm, &format!("handwritten_{}", chain));
+            if composed == handwritten {
+                None
+            } else {
+                Some(format!(
+                    "`{}`:\n  composed:\n    {}\n  handwritten:\n    {}",
+                    chain,
+                    composed.join("\n    "),
+                    handwritten.join("\n    ")
+                ))
+            }
+        })
+        .collect::<Vec<_>>();
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
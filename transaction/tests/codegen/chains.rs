@@ -0,0 +1,95 @@
+// Representative chains of combinators, each paired with the code one
+// would write by hand. `tests/codegen.rs` checks that each pair compiles to
+// the same assembly, and `benches/chains.rs` measures them.
+//
+// `or_else` and `named` check whether a `Coverage` is recording on each
+// run, so they are not on the list.
+
+use transaction::prelude::*;
+
+fn read() -> impl Transaction<Ctx = i64, Item = i64, Err = ()> {
+    with_ctx(|n: &mut i64| Ok(*n))
+}
+
+fn write(n: i64) -> impl Transaction<Ctx = i64, Item = i64, Err = ()> {
+    with_ctx(move |ctx: &mut i64| {
+        *ctx = n;
+        Ok(n)
+    })
+}
+
+fn withdraw(amount: i64) -> impl Transaction<Ctx = i64, Item = i64, Err = i64> {
+    with_ctx(move |balance: &mut i64| {
+        if *balance < amount {
+            return Err(*balance);
+        }
+        *balance -= amount;
+        Ok(*balance)
+    })
+}
+
+#[no_mangle]
+#[inline(never)]
+pub fn composed_map_and_then(ctx: &mut i64) -> Result<i64, ()> {
+    read().map(|n| n * 2).and_then(write).run(ctx)
+}
+
+#[no_mangle]
+#[inline(never)]
+pub fn handwritten_map_and_then(ctx: &mut i64) -> Result<i64, ()> {
+    let n = *ctx * 2;
+    *ctx = n;
+    Ok(n)
+}
+
+#[no_mangle]
+#[inline(never)]
+pub fn composed_join(ctx: &mut i64) -> Result<(i64, i64, i64), ()> {
+    read().join3(write(1).map(|n| n + 1), read()).run(ctx)
+}
+
+#[no_mangle]
+#[inline(never)]
+pub fn handwritten_join(ctx: &mut i64) -> Result<(i64, i64, i64), ()> {
+    let a = *ctx;
+    *ctx = 1;
+    Ok((a, 2, *ctx))
+}
+
+#[no_mangle]
+#[inline(never)]
+pub fn composed_branch(flag: bool, ctx: &mut i64) -> Result<i64, ()> {
+    let tx = if flag {
+        read().map(|n| n + 1).branch().first()
+    } else {
+        write(0).branch().second()
+    };
+    tx.run(ctx)
+}
+
+#[no_mangle]
+#[inline(never)]
+pub fn handwritten_branch(flag: bool, ctx: &mut i64) -> Result<i64, ()> {
+    if flag {
+        Ok(*ctx + 1)
+    } else {
+        *ctx = 0;
+        Ok(0)
+    }
+}
+
+#[no_mangle]
+#[inline(never)]
+pub fn composed_recover(amount: i64, ctx: &mut i64) -> Result<i64, i64> {
+    withdraw(amount).map_err(|balance| balance - amount).recover(|short| short * 2).run(ctx)
+}
+
+#[no_mangle]
+#[inline(never)]
+pub fn handwritten_recover(amount: i64, ctx: &mut i64) -> Result<i64, i64> {
+    if *ctx < amount {
+        return Ok((*ctx - amount) * 2);
+    }
+    *ctx -= amount;
+    Ok(*ctx)
+}